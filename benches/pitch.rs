@@ -0,0 +1,59 @@
+//! Benchmarks of the pitch detection hot path
+//!
+//! Run with `cargo bench`; criterion compares each run against the last.
+
+use chroma_tuner::pitch::{WindowKind, FFT_SIZES};
+use chroma_tuner::{frequency_to_note, AudioData, PitchDetector};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use std::hint::black_box;
+
+#[path = "../src/pitch/test_signals.rs"]
+#[allow(dead_code)]
+mod test_signals;
+
+const SAMPLE_RATE: f32 = 48000.0;
+/// Samples per audio callback at 48 kHz with a 10 ms buffer.
+const CALLBACK_CHUNK: usize = 480;
+
+fn detect_pitch(c: &mut Criterion) {
+    let mut group = c.benchmark_group("detect_pitch");
+    for size in FFT_SIZES {
+        let mut detector = PitchDetector::new(size, SAMPLE_RATE, WindowKind::Hann);
+        let signals = [
+            ("sine", test_signals::sine(440.0, SAMPLE_RATE, size)),
+            ("sawtooth", test_signals::sawtooth(82.41, SAMPLE_RATE, size)),
+            (
+                "sine+noise",
+                test_signals::sine_with_noise(440.0, SAMPLE_RATE, size, 0.2),
+            ),
+        ];
+        for (name, samples) in &signals {
+            group.bench_with_input(BenchmarkId::new(*name, size), samples, |b, samples| {
+                b.iter(|| detector.detect_pitch(black_box(samples)))
+            });
+        }
+    }
+    group.finish();
+}
+
+fn note_conversion(c: &mut Criterion) {
+    c.bench_function("frequency_to_note", |b| {
+        b.iter(|| frequency_to_note(black_box(329.63), black_box(440.0)))
+    });
+}
+
+fn push_samples(c: &mut Criterion) {
+    let signal = test_signals::sine(440.0, SAMPLE_RATE, CALLBACK_CHUNK * 100);
+    let chunks: Vec<&[f32]> = signal.chunks(CALLBACK_CHUNK).collect();
+    let mut audio_data = AudioData::new();
+    let mut next = 0;
+    c.bench_function("push_samples/480", |b| {
+        b.iter(|| {
+            audio_data.push_samples(black_box(chunks[next]));
+            next = (next + 1) % chunks.len();
+        })
+    });
+}
+
+criterion_group!(benches, detect_pitch, note_conversion, push_samples);
+criterion_main!(benches);
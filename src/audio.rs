@@ -1,146 +1,1535 @@
-//! Audio input capture and device management
+//! Audio input capture, reference tone output and device management
 //!
-//! Handles real-time audio capture from input devices, maintains a rolling
-//! buffer of samples for pitch analysis, and provides device selection functionality.
+//! Handles real-time audio capture from input devices or WAV files, maintains a
+//! rolling buffer of samples for pitch analysis, records the input to WAV,
+//! synthesizes a reference tone or drone on the default output device, and provides
+//! device selection functionality. Everything that talks to audio hardware
+//! lives in the `capture` submodule, behind the `capture` feature.
 
-use anyhow::Result;
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use cpal::{Device, Sample, SampleFormat, Stream, StreamConfig};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender};
 use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
-const SAMPLE_RATE: u32 = 44100;
-const BUFFER_SIZE: usize = 8192;
+use crate::pitch::DEFAULT_FFT_SIZE;
 
+#[cfg(feature = "capture")]
+mod capture;
+mod resample;
+#[cfg(feature = "capture")]
+pub use capture::*;
+pub use resample::{Resampler, RESAMPLER_TAPS};
+
+/// The rate every input is resampled to before analysis, in Hz.
+pub const ANALYSIS_SAMPLE_RATE: u32 = 48000;
+const CLIP_LEVEL: f32 = 0.999;
+const MONITOR_SHUTDOWN_POLL: Duration = Duration::from_millis(50);
+const TONE_AMPLITUDE: f32 = 0.2;
+const TONE_ENVELOPE_SECONDS: f32 = 0.02;
+/// Relative levels of the fundamental and first harmonics of the organ tone.
+const ORGAN_HARMONICS: [f32; 4] = [1.0, 0.5, 0.25, 0.125];
+const FILE_CHUNK_FRAMES: usize = 1024;
+/// Blocks the recorder may fall behind by before new ones are dropped.
+const RECORDER_QUEUE_BLOCKS: usize = 256;
+/// Lowest software input gain, in dB.
+pub const MIN_INPUT_GAIN_DB: f32 = -12.0;
+/// Highest software input gain, in dB, enough to lift a passive piezo pickup.
+pub const MAX_INPUT_GAIN_DB: f32 = 30.0;
+/// Sample rates asked for in order of preference, before settling for the
+/// supported rate nearest the first.
+pub const PREFERRED_SAMPLE_RATES: [u32; 2] = [48000, 44100];
+/// Frames per callback offered in settings, 0 leaving it to the device.
+pub const BUFFER_FRAME_OPTIONS: [u32; 6] = [0, 128, 256, 512, 1024, 2048];
+
+/// Which channel of a multi-channel input is analysed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChannelSelection {
+    /// The average of all channels.
+    Mix,
+    /// A single channel, counted from 0.
+    Channel(usize),
+}
+
+/// The sample rates and callback sizes one of a device's input formats
+/// supports.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SupportedFormat {
+    /// Lowest sample rate in Hz.
+    pub min_sample_rate: u32,
+    /// Highest sample rate in Hz.
+    pub max_sample_rate: u32,
+    /// Fewest and most frames per callback, if the host says.
+    pub buffer_frames: Option<(u32, u32)>,
+}
+
+/// The input format to open, picked by [`choose_format`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FormatChoice {
+    /// Index of the format in the supported list.
+    pub index: usize,
+    /// Sample rate in Hz.
+    pub sample_rate: u32,
+    /// Frames per callback to ask for, or `None` for the device default.
+    pub buffer_frames: Option<u32>,
+}
+
+/// Picks the first supported format offering one of the
+/// [`PREFERRED_SAMPLE_RATES`], in order, or failing that the rate nearest the
+/// first of them. `buffer_frames` is clamped into the format's range; 0 leaves
+/// the callback size to the device. `None` if nothing is supported.
+pub fn choose_format(supported: &[SupportedFormat], buffer_frames: u32) -> Option<FormatChoice> {
+    let offers = |format: &SupportedFormat, rate: u32| {
+        (format.min_sample_rate..=format.max_sample_rate).contains(&rate)
+    };
+    let preferred = PREFERRED_SAMPLE_RATES.iter().find_map(|&rate| {
+        let index = supported.iter().position(|format| offers(format, rate))?;
+        Some((index, rate))
+    });
+    let (index, sample_rate) = preferred.or_else(|| {
+        let target = PREFERRED_SAMPLE_RATES[0];
+        supported
+            .iter()
+            .enumerate()
+            .map(|(index, format)| {
+                (
+                    index,
+                    target.clamp(format.min_sample_rate, format.max_sample_rate),
+                )
+            })
+            .min_by_key(|&(_, rate)| rate.abs_diff(target))
+    })?;
+
+    let buffer_frames = (buffer_frames > 0).then(|| match supported[index].buffer_frames {
+        Some((min, max)) => buffer_frames.clamp(min, max),
+        None => buffer_frames,
+    });
+    Some(FormatChoice {
+        index,
+        sample_rate,
+        buffer_frames,
+    })
+}
+
+/// Level of the most recent block of input.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct InputLevel {
+    /// RMS level, 1.0 being full scale.
+    pub rms: f32,
+    /// Largest absolute sample.
+    pub peak: f32,
+    /// Number of callback blocks so far that contained a clipped sample.
+    pub clip_count: u64,
+}
+
+/// Rolling buffer of the latest mono samples, shared between an audio source
+/// and the analysis.
 #[derive(Clone)]
 pub struct AudioData {
-    pub samples: Vec<f32>,
+    buffer: Box<[f32]>,
+    write_pos: usize,
+    len: usize,
+    /// Sample rate of the buffered samples in Hz, [`ANALYSIS_SAMPLE_RATE`]
+    /// once an input is configured.
     pub sample_rate: f32,
-    pub updated: bool,
+    /// Sample rate the current input delivers before resampling, in Hz.
+    pub input_sample_rate: f32,
+    /// Samples pushed since the buffer was created; consumers keep their own
+    /// cursor into this count to tell how much arrived since they last read.
+    total_written: u64,
+    /// Channel count of the current input.
+    pub channels: usize,
+    /// Which channel is mixed down into the buffer.
+    pub channel_selection: ChannelSelection,
+    /// Linear gain sources apply before pushing, so the level and analysis
+    /// both see the boosted signal.
+    pub gain: f32,
+    /// Level of the latest block pushed.
+    pub level: InputLevel,
+    /// Frames per callback the input asked for, or `None` for the default.
+    pub buffer_frames: Option<u32>,
+    /// Mono samples in the latest block pushed.
+    pub block_len: usize,
+    stream_error: Option<String>,
+    last_push: Option<Instant>,
+    recorder: Option<SyncSender<Vec<f32>>>,
+    resampler: Resampler,
+    resampled: Vec<f32>,
 }
 
 impl AudioData {
+    /// An empty buffer sized for the default analysis window.
     pub fn new() -> Self {
         Self {
-            samples: Vec::with_capacity(BUFFER_SIZE),
-            sample_rate: SAMPLE_RATE as f32,
-            updated: false,
+            buffer: vec![0.0; DEFAULT_FFT_SIZE].into_boxed_slice(),
+            write_pos: 0,
+            len: 0,
+            sample_rate: ANALYSIS_SAMPLE_RATE as f32,
+            input_sample_rate: ANALYSIS_SAMPLE_RATE as f32,
+            total_written: 0,
+            channels: 1,
+            channel_selection: ChannelSelection::Mix,
+            gain: 1.0,
+            level: InputLevel::default(),
+            buffer_frames: None,
+            block_len: 0,
+            stream_error: None,
+            last_push: None,
+            recorder: None,
+            resampler: Resampler::new(ANALYSIS_SAMPLE_RATE, ANALYSIS_SAMPLE_RATE),
+            resampled: Vec::new(),
+        }
+    }
+
+    /// Resizes the ring buffer to hold one analysis window, discarding the
+    /// samples collected so far if the size changes.
+    pub fn set_buffer_size(&mut self, buffer_size: usize) {
+        if buffer_size == self.buffer.len() {
+            return;
         }
+
+        self.buffer = vec![0.0; buffer_size].into_boxed_slice();
+        self.clear();
     }
 
+    /// Discards the buffered samples, e.g. those of a previous input at a
+    /// different sample rate. The written count carries on, so cursors held
+    /// by consumers stay valid.
+    pub fn clear(&mut self) {
+        self.write_pos = 0;
+        self.len = 0;
+    }
+
+    /// Records the format of a newly opened input, falling back to a mix if the
+    /// selected channel no longer exists, and starts a fresh resampler from its
+    /// rate to the analysis rate.
+    pub(crate) fn configure_input(
+        &mut self,
+        sample_rate: f32,
+        channels: usize,
+        buffer_frames: Option<u32>,
+    ) {
+        self.sample_rate = ANALYSIS_SAMPLE_RATE as f32;
+        self.input_sample_rate = sample_rate;
+        self.resampler = Resampler::new(sample_rate as u32, ANALYSIS_SAMPLE_RATE);
+        self.channels = channels;
+        self.buffer_frames = buffer_frames;
+        self.block_len = 0;
+        if let ChannelSelection::Channel(index) = self.channel_selection {
+            if index >= channels {
+                self.channel_selection = ChannelSelection::Mix;
+            }
+        }
+    }
+
+    /// Resamples a mono block from the input's rate to the analysis rate and
+    /// appends it; blocks already at the analysis rate go straight in.
+    pub fn push_input(&mut self, samples: &[f32]) {
+        if self.resampler.is_bypassed() {
+            self.push_samples(samples);
+            return;
+        }
+        let mut resampled = std::mem::take(&mut self.resampled);
+        self.resampler.process(samples, &mut resampled);
+        self.push_samples(&resampled);
+        self.resampled = resampled;
+    }
+
+    /// Appends mono samples, overwriting the oldest once the buffer is full.
     pub fn push_samples(&mut self, new_samples: &[f32]) {
-        if self.samples.len() + new_samples.len() > BUFFER_SIZE {
-            let overflow = (self.samples.len() + new_samples.len()) - BUFFER_SIZE;
-            self.samples.drain(0..overflow);
+        self.update_level(new_samples);
+        self.block_len = new_samples.len();
+        self.total_written += new_samples.len() as u64;
+        if let Some(recorder) = &self.recorder {
+            // Copying the block is the only allocation on this path, and only
+            // while recording; a full queue drops the block rather than waiting.
+            let _ = recorder.try_send(new_samples.to_vec());
+        }
+
+        let capacity = self.buffer.len();
+        let new_samples = &new_samples[new_samples.len().saturating_sub(capacity)..];
+
+        let first_len = new_samples.len().min(capacity - self.write_pos);
+        let (first, second) = new_samples.split_at(first_len);
+        self.buffer[self.write_pos..self.write_pos + first_len].copy_from_slice(first);
+        self.buffer[..second.len()].copy_from_slice(second);
+
+        self.write_pos = (self.write_pos + new_samples.len()) % capacity;
+        self.len = (self.len + new_samples.len()).min(capacity);
+        self.last_push = Some(Instant::now());
+    }
+
+    /// Copies the most recent samples, oldest first, into `out` and returns how
+    /// many were written.
+    pub fn get_samples(&self, out: &mut [f32]) -> usize {
+        let capacity = self.buffer.len();
+        let count = out.len().min(self.len);
+        let start = (self.write_pos + capacity - count) % capacity;
+
+        let first_len = count.min(capacity - start);
+        out[..first_len].copy_from_slice(&self.buffer[start..start + first_len]);
+        out[first_len..count].copy_from_slice(&self.buffer[..count - first_len]);
+
+        count
+    }
+
+    fn update_level(&mut self, samples: &[f32]) {
+        if samples.is_empty() {
+            return;
+        }
+
+        let mut sum_squares = 0.0;
+        let mut peak = 0.0f32;
+        for &sample in samples {
+            sum_squares += sample * sample;
+            peak = peak.max(sample.abs());
+        }
+
+        self.level.rms = (sum_squares / samples.len() as f32).sqrt();
+        self.level.peak = peak;
+        if peak >= CLIP_LEVEL {
+            self.level.clip_count += 1;
         }
+    }
+
+    /// Records an error from the source's stream for the UI to show.
+    pub fn report_stream_error(&mut self, error: String) {
+        self.stream_error = Some(error);
+    }
+
+    /// Returns the last stream error, if any, and clears it.
+    pub fn take_stream_error(&mut self) -> Option<String> {
+        self.stream_error.take()
+    }
 
-        self.samples.extend_from_slice(new_samples);
-        self.updated = true;
+    /// When the capture callback last delivered samples, used as a stream watchdog.
+    pub fn last_push(&self) -> Option<Instant> {
+        self.last_push
     }
 
-    pub fn get_samples(&mut self) -> Vec<f32> {
-        self.updated = false;
-        self.samples.clone()
+    /// Samples pushed since the buffer was created, including those the ring
+    /// buffer has since overwritten.
+    pub fn total_samples_written(&self) -> u64 {
+        self.total_written
     }
 
-    pub fn has_new_data(&self) -> bool {
-        self.updated && self.samples.len() >= BUFFER_SIZE / 2
+    /// Samples pushed since `cursor`, a value of `total_samples_written` the
+    /// consumer took when it last read.
+    pub fn samples_since(&self, cursor: u64) -> u64 {
+        self.total_written.saturating_sub(cursor)
+    }
+
+    /// Whether at least `hop` samples arrived since `cursor` and at least
+    /// half a window is buffered.
+    pub fn has_new_data(&self, cursor: u64, hop: usize) -> bool {
+        self.samples_since(cursor) >= hop.max(1) as u64 && self.len >= self.buffer.len() / 2
     }
 }
 
-pub struct AudioCapture {
-    _stream: Stream,
+impl Default for AudioData {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-impl AudioCapture {
-    pub fn new(audio_data: Arc<Mutex<AudioData>>) -> Result<Self> {
-        let host = cpal::default_host();
-        let device = host
-            .default_input_device()
-            .ok_or_else(|| anyhow::anyhow!("No input device available"))?;
+/// Something that feeds samples into an `AudioData` for as long as it lives,
+/// such as an input device or a WAV file. Dropping it stops the samples.
+pub trait AudioSource {
+    /// The device or file name shown to the user.
+    fn name(&self) -> &str;
 
-        Self::new_with_device(audio_data, device)
+    /// Whether the samples come from hardware that can disappear, as opposed
+    /// to a file.
+    fn is_live(&self) -> bool {
+        true
     }
 
-    pub fn new_with_device(
-        audio_data: Arc<Mutex<AudioData>>,
-        device: cpal::Device,
-    ) -> Result<Self> {
-        let config = device.default_input_config()?;
-        let actual_sample_rate = config.sample_rate().0 as f32;
+    /// Stops delivering samples and releases the hardware where the platform
+    /// allows, e.g. while the window is minimized. Files keep playing.
+    fn pause(&mut self) -> Result<()> {
+        Ok(())
+    }
 
-        println!("Input device: {}", device.name()?);
-        println!("Default input config: {:?}", config);
-        println!("Actual sample rate: {} Hz", actual_sample_rate);
+    /// Delivers samples again after [`pause`](AudioSource::pause).
+    fn resume(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Streams a WAV file into `AudioData` at real-time pace (or faster), standing in
+/// for a capture device when analysing recordings. Once the file ends it keeps
+/// delivering silence, like a device that went quiet. Samples already in
+/// memory, such as a synthesized test signal, can be streamed the same way.
+pub struct FileSource {
+    name: String,
+    running: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl FileSource {
+    /// `speed` scales the pace, e.g. 4.0 streams four times faster than real time.
+    pub fn open(path: &Path, audio_data: Arc<Mutex<AudioData>>, speed: f32) -> Result<Self> {
+        let (samples, spec) = read_wav(path)
+            .with_context(|| format!("Failed to read WAV file {}", path.display()))?;
+        let channels = spec.channels.max(1) as usize;
+        let sample_rate = spec.sample_rate as f32;
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.display().to_string());
 
+        log::info!(
+            "Input file: {} ({} Hz, {} channels, {:.1} s)",
+            name,
+            spec.sample_rate,
+            channels,
+            samples.len() as f32 / channels as f32 / sample_rate
+        );
+
+        Ok(Self::stream(
+            name,
+            samples,
+            channels,
+            sample_rate,
+            audio_data,
+            speed,
+        ))
+    }
+
+    /// Streams mono `samples` at `sample_rate` under `name`, paced as `open` does.
+    pub fn from_samples(
+        name: &str,
+        samples: Vec<f32>,
+        sample_rate: f32,
+        audio_data: Arc<Mutex<AudioData>>,
+        speed: f32,
+    ) -> Self {
+        Self::stream(name.to_string(), samples, 1, sample_rate, audio_data, speed)
+    }
+
+    fn stream(
+        name: String,
+        samples: Vec<f32>,
+        channels: usize,
+        sample_rate: f32,
+        audio_data: Arc<Mutex<AudioData>>,
+        speed: f32,
+    ) -> Self {
         if let Ok(mut audio_data) = audio_data.lock() {
-            audio_data.sample_rate = actual_sample_rate;
-        }
-
-        let stream = match config.sample_format() {
-            SampleFormat::I8 => Self::create_stream::<i8>(&device, &config.into(), audio_data)?,
-            SampleFormat::I16 => Self::create_stream::<i16>(&device, &config.into(), audio_data)?,
-            SampleFormat::I32 => Self::create_stream::<i32>(&device, &config.into(), audio_data)?,
-            SampleFormat::I64 => Self::create_stream::<i64>(&device, &config.into(), audio_data)?,
-            SampleFormat::U8 => Self::create_stream::<u8>(&device, &config.into(), audio_data)?,
-            SampleFormat::U16 => Self::create_stream::<u16>(&device, &config.into(), audio_data)?,
-            SampleFormat::U32 => Self::create_stream::<u32>(&device, &config.into(), audio_data)?,
-            SampleFormat::U64 => Self::create_stream::<u64>(&device, &config.into(), audio_data)?,
-            SampleFormat::F32 => Self::create_stream::<f32>(&device, &config.into(), audio_data)?,
-            SampleFormat::F64 => Self::create_stream::<f64>(&device, &config.into(), audio_data)?,
-            _ => return Err(anyhow::anyhow!("Unsupported sample format")),
+            audio_data.configure_input(sample_rate, channels, None);
+        }
+
+        let chunk_duration = Duration::from_secs_f32(
+            FILE_CHUNK_FRAMES as f32 / sample_rate / speed.max(f32::EPSILON),
+        );
+        let running = Arc::new(AtomicBool::new(true));
+
+        let handle = {
+            let running = running.clone();
+            std::thread::Builder::new()
+                .name("file-source".to_string())
+                .spawn(move || {
+                    let silence = vec![0.0; FILE_CHUNK_FRAMES * channels];
+                    let mut chunks = samples.chunks(FILE_CHUNK_FRAMES * channels);
+                    let mut mono = Vec::new();
+                    let mut next_chunk = Instant::now();
+
+                    while running.load(Ordering::Relaxed) {
+                        let chunk = chunks.next().unwrap_or(&silence);
+                        if let Ok(mut audio_data) = audio_data.lock() {
+                            let selection = audio_data.channel_selection;
+                            extract_channel(chunk, channels, selection, &mut mono);
+                            apply_gain(&mut mono, audio_data.gain);
+                            audio_data.push_input(&mono);
+                        }
+
+                        next_chunk += chunk_duration;
+                        std::thread::sleep(next_chunk.saturating_duration_since(Instant::now()));
+                    }
+                })
+                .ok()
         };
 
-        stream.play()?;
+        Self {
+            name,
+            running,
+            handle,
+        }
+    }
 
-        Ok(Self { _stream: stream })
+    /// Stops streaming and waits for the thread to finish.
+    pub fn stop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
     }
+}
 
-    fn create_stream<T>(
-        device: &Device,
-        config: &StreamConfig,
-        audio_data: Arc<Mutex<AudioData>>,
-    ) -> Result<Stream>
-    where
-        T: Sample + cpal::SizedSample + Send + 'static,
-        f32: cpal::FromSample<T>,
-    {
-        let stream = device.build_input_stream(
-            config,
-            move |data: &[T], _: &cpal::InputCallbackInfo| {
-                let samples: Vec<f32> = data
-                    .iter()
-                    .map(|&sample| f32::from_sample(sample))
-                    .collect();
+impl AudioSource for FileSource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn is_live(&self) -> bool {
+        false
+    }
+}
+
+impl Drop for FileSource {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Appends everything pushed into `AudioData` to a mono WAV file. Blocks are
+/// handed over a channel and written on a background thread, so the capture
+/// callback never waits on the disk.
+pub struct Recorder {
+    path: PathBuf,
+    started: Instant,
+    handle: Option<JoinHandle<Result<()>>>,
+}
+
+impl Recorder {
+    /// Creates the file and starts appending everything pushed into `audio_data`.
+    pub fn start(path: &Path, audio_data: &Mutex<AudioData>) -> Result<Self> {
+        let mut audio_data = audio_data
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Audio buffer is unavailable"))?;
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: audio_data.sample_rate as u32,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let mut writer = hound::WavWriter::create(path, spec)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
 
-                if let Ok(mut audio_data) = audio_data.lock() {
-                    audio_data.push_samples(&samples);
+        let (sender, receiver) = mpsc::sync_channel::<Vec<f32>>(RECORDER_QUEUE_BLOCKS);
+        let display_path = path.display().to_string();
+        let handle = std::thread::Builder::new()
+            .name("recorder".to_string())
+            .spawn(move || {
+                for block in receiver {
+                    for sample in block {
+                        writer
+                            .write_sample(sample)
+                            .with_context(|| format!("Failed to write {}", display_path))?;
+                    }
                 }
-            },
-            |err| eprintln!("Audio stream error: {}", err),
-            None,
-        )?;
+                writer
+                    .finalize()
+                    .with_context(|| format!("Failed to finalize {}", display_path))
+            })?;
+
+        audio_data.recorder = Some(sender);
+        log::info!("Recording input to {}", path.display());
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            started: Instant::now(),
+            handle: Some(handle),
+        })
+    }
+
+    /// The file being written.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// How long the recording has been running.
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// True once the writer thread has exited on its own, which only happens
+    /// when writing failed.
+    pub fn failed(&self) -> bool {
+        self.handle
+            .as_ref()
+            .is_some_and(|handle| handle.is_finished())
+    }
+
+    /// Detaches from the input, flushes the remaining blocks and finalizes the
+    /// file.
+    pub fn stop(mut self, audio_data: &Mutex<AudioData>) -> Result<()> {
+        if let Ok(mut audio_data) = audio_data.lock() {
+            audio_data.recorder = None;
+        }
+        match self.handle.take().map(JoinHandle::join) {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => Err(anyhow::anyhow!("Recorder thread panicked")),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Reads every sample of a WAV file as interleaved floats in [-1, 1].
+pub fn read_wav(path: &Path) -> Result<(Vec<f32>, hound::WavSpec)> {
+    let reader = hound::WavReader::open(path)?;
+    let spec = reader.spec();
+    let samples = match spec.sample_format {
+        hound::SampleFormat::Float => reader.into_samples::<f32>().collect::<Result<_, _>>()?,
+        hound::SampleFormat::Int => {
+            let scale = 1.0 / (1i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .into_samples::<i32>()
+                .map(|sample| sample.map(|sample| sample as f32 * scale))
+                .collect::<Result<_, _>>()?
+        }
+    };
+    Ok((samples, spec))
+}
+
+/// A mono signal an output stream pulls one sample at a time.
+pub trait MonoGenerator: Send + 'static {
+    /// The next mono sample.
+    fn next_sample(&mut self) -> f32;
+}
+
+/// Shape of a generated tone.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Waveform {
+    /// A pure sine.
+    #[default]
+    Sine,
+    /// A sine with a few quieter harmonics, like an organ flue pipe.
+    Organ,
+}
+
+impl Waveform {
+    /// Every option, in menu order.
+    pub const ALL: [Waveform; 2] = [Waveform::Sine, Waveform::Organ];
+
+    /// Name shown in the settings panel.
+    pub fn label(self) -> &'static str {
+        match self {
+            Waveform::Sine => "Sine",
+            Waveform::Organ => "Organ",
+        }
+    }
+
+    /// One sample at `phase` through the cycle (0–1), peaking at about 1.
+    fn sample(self, phase: f32) -> f32 {
+        let sine = |harmonic: f32| (phase * harmonic * std::f32::consts::TAU).sin();
+        match self {
+            Waveform::Sine => sine(1.0),
+            Waveform::Organ => {
+                let total: f32 = ORGAN_HARMONICS.iter().sum();
+                ORGAN_HARMONICS
+                    .iter()
+                    .enumerate()
+                    .map(|(index, level)| level * sine((index + 1) as f32))
+                    .sum::<f32>()
+                    / total
+            }
+        }
+    }
+}
+
+/// Oscillator with a linear attack/release envelope so starting and stopping
+/// the tone, or changing its volume, doesn't click.
+pub struct ToneGenerator {
+    sample_rate: f32,
+    frequency: f32,
+    waveform: Waveform,
+    volume: f32,
+    phase: f32,
+    gain: f32,
+    gain_step: f32,
+    playing: bool,
+    /// Samples left before a beep starts its release.
+    beep_remaining: Option<usize>,
+}
+
+impl ToneGenerator {
+    /// A silent generator at `frequency`; call `set_playing` to fade it in.
+    pub fn new(frequency: f32, sample_rate: f32) -> Self {
+        Self {
+            sample_rate,
+            frequency,
+            waveform: Waveform::Sine,
+            volume: 1.0,
+            phase: 0.0,
+            gain: 0.0,
+            gain_step: 1.0 / (TONE_ENVELOPE_SECONDS * sample_rate).max(1.0),
+            playing: false,
+            beep_remaining: None,
+        }
+    }
+
+    /// Retunes the oscillator without a click.
+    pub fn set_frequency(&mut self, frequency: f32) {
+        self.frequency = frequency;
+    }
 
-        Ok(stream)
+    /// Changes the waveform from the next sample.
+    pub fn set_waveform(&mut self, waveform: Waveform) {
+        self.waveform = waveform;
+    }
+
+    /// Ramps to `volume`, from 0 to 1, over the envelope time.
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume.clamp(0.0, 1.0);
+    }
+
+    /// Starts the attack or the release ramp, cancelling any beep.
+    pub fn set_playing(&mut self, playing: bool) {
+        self.playing = playing;
+        self.beep_remaining = None;
+    }
+
+    /// Plays `frequency` for `duration`, then releases on its own.
+    pub fn beep(&mut self, frequency: f32, duration: Duration) {
+        self.frequency = frequency;
+        self.playing = true;
+        self.beep_remaining = Some((duration.as_secs_f32() * self.sample_rate) as usize);
     }
 }
 
-pub fn get_input_devices() -> Result<Vec<(String, cpal::Device)>> {
-    let host = cpal::default_host();
-    let mut devices = Vec::new();
+impl MonoGenerator for ToneGenerator {
+    fn next_sample(&mut self) -> f32 {
+        if let Some(remaining) = &mut self.beep_remaining {
+            if *remaining == 0 {
+                self.playing = false;
+                self.beep_remaining = None;
+            } else {
+                *remaining -= 1;
+            }
+        }
+        let target = if self.playing { self.volume } else { 0.0 };
+        if self.gain < target {
+            self.gain = (self.gain + self.gain_step).min(target);
+        } else if self.gain > target {
+            self.gain = (self.gain - self.gain_step).max(target);
+        }
 
-    for device in host.input_devices()? {
-        if let Ok(name) = device.name() {
-            devices.push((name, device));
+        if self.gain == 0.0 {
+            self.phase = 0.0;
+            return 0.0;
         }
+
+        let sample = self.waveform.sample(self.phase) * self.gain * TONE_AMPLITUDE;
+        self.phase = (self.phase + self.frequency / self.sample_rate).fract();
+        sample
     }
+}
 
-    Ok(devices)
+/// Reduces interleaved frames to mono according to `selection`.
+pub fn extract_channel(
+    interleaved: &[f32],
+    channels: usize,
+    selection: ChannelSelection,
+    mono: &mut Vec<f32>,
+) {
+    mono.clear();
+
+    if channels <= 1 {
+        mono.extend_from_slice(interleaved);
+        return;
+    }
+
+    let frames = interleaved.chunks_exact(channels);
+    match selection {
+        ChannelSelection::Mix => {
+            mono.extend(frames.map(|frame| frame.iter().sum::<f32>() / channels as f32));
+        }
+        ChannelSelection::Channel(index) => {
+            let index = index.min(channels - 1);
+            mono.extend(frames.map(|frame| frame[index]));
+        }
+    }
+}
+
+/// The linear factor for a gain of `db` decibels.
+pub fn db_to_gain(db: f32) -> f32 {
+    10.0f32.powf(db / 20.0)
 }
 
-pub fn get_default_input_device_name() -> Result<String> {
-    let host = cpal::default_host();
-    let device = host
-        .default_input_device()
-        .ok_or_else(|| anyhow::anyhow!("No default input device"))?;
-    Ok(device.name()?)
+/// Scales `samples` by `gain` in place; unity gain leaves them untouched.
+pub fn apply_gain(samples: &mut [f32], gain: f32) {
+    if gain == 1.0 {
+        return;
+    }
+    for sample in samples {
+        *sample *= gain;
+    }
+}
+
+/// Whether a device scan differs from the previous one, ignoring order.
+pub fn device_list_changed(previous: &[String], current: &[String]) -> bool {
+    previous.len() != current.len() || !current.iter().all(|name| previous.contains(name))
+}
+
+/// The result of one device scan.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DeviceScan {
+    /// Names of every input device.
+    pub devices: Vec<String>,
+    /// Name of the system default input, if there is one.
+    pub default: Option<String>,
+}
+
+/// Scans input devices on a background thread, since enumeration can block for
+/// long enough to stutter the UI. Only names are sent; callers look the device
+/// up again when they actually open it.
+pub struct DeviceMonitor {
+    receiver: Receiver<DeviceScan>,
+    running: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl DeviceMonitor {
+    /// Calls `scan` every `interval` and sends the result whenever the device
+    /// list or the default device changes.
+    pub fn start<F>(interval: Duration, mut scan: F) -> Self
+    where
+        F: FnMut() -> Result<DeviceScan> + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        let running = Arc::new(AtomicBool::new(true));
+
+        let handle = {
+            let running = running.clone();
+            std::thread::Builder::new()
+                .name("device-monitor".to_string())
+                .spawn(move || {
+                    let mut known: Option<DeviceScan> = None;
+
+                    while running.load(Ordering::Relaxed) {
+                        match scan() {
+                            Ok(scan) => {
+                                let changed = match &known {
+                                    Some(known) => {
+                                        device_list_changed(&known.devices, &scan.devices)
+                                            || known.default != scan.default
+                                    }
+                                    None => true,
+                                };
+                                if changed {
+                                    known = Some(scan.clone());
+                                    if sender.send(scan).is_err() {
+                                        break;
+                                    }
+                                }
+                            }
+                            Err(err) => log::warn!("Failed to enumerate input devices: {}", err),
+                        }
+
+                        let scanned_at = Instant::now();
+                        while running.load(Ordering::Relaxed) && scanned_at.elapsed() < interval {
+                            std::thread::sleep(MONITOR_SHUTDOWN_POLL.min(interval));
+                        }
+                    }
+                })
+                .ok()
+        };
+
+        Self {
+            receiver,
+            running,
+            handle,
+        }
+    }
+
+    /// Returns the most recent scan if it changed since the last call.
+    pub fn try_recv(&self) -> Option<DeviceScan> {
+        self.receiver.try_iter().last()
+    }
+
+    /// Stops scanning and waits for the thread to finish.
+    pub fn stop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for DeviceMonitor {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// The device list entry that captures what the computer is playing, by
+/// loopback from the default output device where the host supports it.
+pub const SYSTEM_AUDIO_DEVICE: &str = "System audio";
+
+/// Whether `name` is a PulseAudio or PipeWire monitor source, which carries
+/// what an output device is playing rather than a microphone.
+pub fn is_monitor_source(name: &str) -> bool {
+    name.ends_with(".monitor") || name.starts_with("Monitor of ")
+}
+
+/// How a device is shown in the device list: monitor sources are labelled as
+/// system audio from their output device, other names are left as they are.
+pub fn device_label(name: &str) -> String {
+    let output = name
+        .strip_suffix(".monitor")
+        .or_else(|| name.strip_prefix("Monitor of "));
+    match output {
+        Some(output) => format!("{} ({})", SYSTEM_AUDIO_DEVICE, output),
+        None => name.to_string(),
+    }
+}
+
+/// Resolves a device named on the command line: an exact name wins, then a
+/// unique case-insensitive prefix.
+pub fn match_device_name(names: &[String], query: &str) -> Result<usize> {
+    if let Some(index) = names.iter().position(|name| name == query) {
+        return Ok(index);
+    }
+
+    let prefix = query.to_lowercase();
+    let matches: Vec<usize> = names
+        .iter()
+        .enumerate()
+        .filter(|(_, name)| name.to_lowercase().starts_with(&prefix))
+        .map(|(index, _)| index)
+        .collect();
+    match matches.as_slice() {
+        [index] => Ok(*index),
+        [] => Err(anyhow::anyhow!(
+            "No input device matches '{}' (available: {})",
+            query,
+            names.join(", ")
+        )),
+        _ => Err(anyhow::anyhow!(
+            "'{}' matches several input devices: {}",
+            query,
+            matches
+                .iter()
+                .map(|&index| names[index].as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alloc_counter::count_allocations;
+
+    const BUFFER_SIZE: usize = DEFAULT_FFT_SIZE;
+
+    #[test]
+    fn test_ring_buffer_returns_latest_samples_in_order() {
+        let mut audio_data = AudioData::new();
+        let mut out = vec![0.0; BUFFER_SIZE];
+
+        audio_data.push_samples(&[1.0, 2.0, 3.0]);
+        assert_eq!(audio_data.get_samples(&mut out), 3);
+        assert_eq!(&out[..3], &[1.0, 2.0, 3.0]);
+
+        let ramp: Vec<f32> = (0..BUFFER_SIZE + 100).map(|i| i as f32).collect();
+        for chunk in ramp.chunks(300) {
+            audio_data.push_samples(chunk);
+        }
+
+        assert_eq!(audio_data.get_samples(&mut out), BUFFER_SIZE);
+        assert_eq!(out, &ramp[100..]);
+
+        let mut latest = [0.0; 4];
+        assert_eq!(audio_data.get_samples(&mut latest), 4);
+        assert_eq!(&latest, &ramp[ramp.len() - 4..]);
+    }
+
+    #[test]
+    fn test_has_new_data_requires_half_buffer() {
+        let mut audio_data = AudioData::new();
+
+        audio_data.push_samples(&vec![0.0; BUFFER_SIZE / 2 - 1]);
+        assert!(!audio_data.has_new_data(0, 1));
+
+        audio_data.push_samples(&[0.0]);
+        assert!(audio_data.has_new_data(0, 1));
+        let cursor = audio_data.total_samples_written();
+        assert!(!audio_data.has_new_data(cursor, 1));
+
+        audio_data.set_buffer_size(16384);
+        audio_data.push_samples(&vec![0.0; BUFFER_SIZE]);
+        assert!(audio_data.has_new_data(cursor, 1));
+        assert_eq!(audio_data.get_samples(&mut vec![0.0; 16384]), BUFFER_SIZE);
+
+        audio_data.set_buffer_size(4096);
+        let cursor = audio_data.total_samples_written();
+        audio_data.push_samples(&vec![0.0; 2047]);
+        assert!(!audio_data.has_new_data(cursor, 1));
+        audio_data.push_samples(&[0.0]);
+        assert!(audio_data.has_new_data(cursor, 1));
+    }
+
+    #[test]
+    fn test_has_new_data_waits_for_a_hop() {
+        let mut audio_data = AudioData::new();
+        audio_data.push_samples(&vec![0.0; BUFFER_SIZE]);
+        let cursor = audio_data.total_samples_written();
+
+        for _ in 0..3 {
+            audio_data.push_samples(&[0.0; 256]);
+            assert!(!audio_data.has_new_data(cursor, 1024));
+        }
+        audio_data.push_samples(&[0.0; 256]);
+        assert_eq!(audio_data.samples_since(cursor), 1024);
+        assert!(audio_data.has_new_data(cursor, 1024));
+        assert!(!audio_data.has_new_data(cursor, 2048));
+    }
+
+    #[test]
+    fn test_written_count_survives_ring_buffer_overflow() {
+        let mut audio_data = AudioData::new();
+        let mut out = vec![0.0; BUFFER_SIZE];
+        audio_data.push_samples(&[1.0; 100]);
+        let cursor = audio_data.total_samples_written();
+        assert_eq!(cursor, 100);
+
+        // Three windows' worth wraps the ring buffer several times over, in
+        // blocks both smaller and larger than it.
+        audio_data.push_samples(&vec![2.0; BUFFER_SIZE / 3]);
+        audio_data.push_samples(&vec![3.0; 2 * BUFFER_SIZE]);
+        audio_data.push_samples(&vec![4.0; BUFFER_SIZE - BUFFER_SIZE / 3]);
+        assert_eq!(audio_data.samples_since(cursor), 3 * BUFFER_SIZE as u64);
+        assert_eq!(
+            audio_data.total_samples_written(),
+            100 + 3 * BUFFER_SIZE as u64
+        );
+
+        // Reading doesn't move anyone's cursor, and a cursor from the future
+        // sees nothing new rather than wrapping around.
+        assert_eq!(audio_data.get_samples(&mut out), BUFFER_SIZE);
+        assert_eq!(audio_data.samples_since(cursor), 3 * BUFFER_SIZE as u64);
+        assert_eq!(audio_data.samples_since(u64::MAX), 0);
+    }
+
+    #[test]
+    fn test_clear_discards_samples_but_keeps_counting() {
+        let mut audio_data = AudioData::new();
+        let mut out = vec![0.0; BUFFER_SIZE];
+        audio_data.push_samples(&vec![1.0; BUFFER_SIZE]);
+        let cursor = audio_data.total_samples_written();
+
+        audio_data.clear();
+        assert_eq!(audio_data.get_samples(&mut out), 0);
+        assert_eq!(audio_data.total_samples_written(), cursor);
+        assert!(!audio_data.has_new_data(0, 1));
+
+        audio_data.push_samples(&vec![2.0; BUFFER_SIZE / 2]);
+        assert!(audio_data.has_new_data(cursor, 1024));
+        assert_eq!(audio_data.get_samples(&mut out), BUFFER_SIZE / 2);
+        assert!(out[..BUFFER_SIZE / 2].iter().all(|&sample| sample == 2.0));
+    }
+
+    #[test]
+    fn test_push_samples_tracks_level_and_clipping() {
+        let mut audio_data = AudioData::new();
+        assert!(audio_data.last_push().is_none());
+
+        audio_data.push_samples(&[0.5, -0.5, 0.5, -0.5]);
+        assert!(audio_data.last_push().is_some());
+        assert!((audio_data.level.rms - 0.5).abs() < 1e-6);
+        assert!((audio_data.level.peak - 0.5).abs() < 1e-6);
+        assert_eq!(audio_data.level.clip_count, 0);
+
+        audio_data.push_samples(&[0.0, 1.0, -1.0, 0.0]);
+        assert_eq!(audio_data.level.peak, 1.0);
+        assert_eq!(audio_data.level.clip_count, 1);
+
+        audio_data.push_samples(&[0.01; 4]);
+        assert!((audio_data.level.rms - 0.01).abs() < 1e-6);
+        assert_eq!(audio_data.level.clip_count, 1);
+    }
+
+    #[test]
+    fn test_gain_converts_decibels_and_scales_samples() {
+        assert_eq!(db_to_gain(0.0), 1.0);
+        assert!((db_to_gain(20.0) - 10.0).abs() < 1e-5);
+        assert!((db_to_gain(-12.0) - 0.251_188_6).abs() < 1e-6);
+        assert!((db_to_gain(6.0) - 1.995_262_3).abs() < 1e-6);
+        assert!((db_to_gain(MAX_INPUT_GAIN_DB) - 31.622_776).abs() < 1e-4);
+
+        // A quiet pickup boosted by 30 dB clips on its peaks.
+        let mut samples = [0.001, -0.02, 0.05];
+        apply_gain(&mut samples, db_to_gain(MAX_INPUT_GAIN_DB));
+        let mut audio_data = AudioData::new();
+        audio_data.push_samples(&samples);
+        assert!((samples[0] - 0.031_622_78).abs() < 1e-6);
+        assert!(audio_data.level.peak > 1.0);
+        assert_eq!(audio_data.level.clip_count, 1);
+    }
+
+    #[test]
+    fn test_callback_path_does_not_allocate() {
+        let mut audio_data = AudioData::new();
+        audio_data.configure_input(44100.0, 2, None);
+        let interleaved = vec![0.25; 512 * 2];
+        let mut mono = Vec::with_capacity(512);
+        let mut scratch = vec![0.0; BUFFER_SIZE];
+        // The resampler's buffers grow to the block size once.
+        extract_channel(&interleaved, 2, ChannelSelection::Mix, &mut mono);
+        audio_data.push_input(&mono);
+
+        let (_, allocations) = count_allocations(|| {
+            for _ in 0..64 {
+                extract_channel(&interleaved, 2, ChannelSelection::Mix, &mut mono);
+                audio_data.push_input(&mono);
+                audio_data.get_samples(&mut scratch);
+            }
+        });
+
+        assert_eq!(allocations, 0);
+    }
+
+    #[test]
+    fn test_a4_reads_in_tune_from_any_input_rate() {
+        use crate::pitch::{frequency_to_note, test_signals, PitchDetector, WindowKind};
+
+        for input_rate in [44100, 48000, 96000] {
+            let mut audio_data = AudioData::new();
+            audio_data.configure_input(input_rate as f32, 1, None);
+            let tone = test_signals::sine(440.0, input_rate as f32, input_rate as usize);
+            for block in tone.chunks(480) {
+                audio_data.push_input(block);
+            }
+
+            let mut window = vec![0.0; BUFFER_SIZE];
+            assert_eq!(audio_data.get_samples(&mut window), BUFFER_SIZE);
+            let mut detector =
+                PitchDetector::new(BUFFER_SIZE, audio_data.sample_rate, WindowKind::Hann);
+            let frequency = detector.detect_pitch(&window).unwrap().frequency;
+            let note = frequency_to_note(frequency, 440.0).unwrap();
+            assert_eq!(note.to_string(), "A4", "{} Hz", input_rate);
+            assert!(
+                note.cents_off.abs() < 1.0,
+                "{} Hz: {} cents",
+                input_rate,
+                note.cents_off
+            );
+        }
+    }
+
+    #[test]
+    fn test_extract_channel_from_interleaved_buffer() {
+        let channels = 8;
+        let frames = 256;
+        let sine: Vec<f32> = (0..frames)
+            .map(|i| (2.0 * std::f32::consts::PI * 440.0 * i as f32 / 48000.0).sin())
+            .collect();
+
+        let mut interleaved = vec![0.0; frames * channels];
+        for (frame, &value) in sine.iter().enumerate() {
+            interleaved[frame * channels + 2] = value;
+        }
+
+        let mut mono = Vec::new();
+
+        extract_channel(
+            &interleaved,
+            channels,
+            ChannelSelection::Channel(2),
+            &mut mono,
+        );
+        assert_eq!(mono, sine);
+
+        extract_channel(
+            &interleaved,
+            channels,
+            ChannelSelection::Channel(0),
+            &mut mono,
+        );
+        assert_eq!(mono.len(), frames);
+        assert!(mono.iter().all(|&sample| sample == 0.0));
+
+        extract_channel(&interleaved, channels, ChannelSelection::Mix, &mut mono);
+        assert_eq!(mono.len(), frames);
+        for (mixed, &value) in mono.iter().zip(sine.iter()) {
+            assert!((mixed - value / channels as f32).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_tone_generator_ramps_in_and_out() {
+        let sample_rate = 48000.0;
+        let envelope_samples = (TONE_ENVELOPE_SECONDS * sample_rate) as usize;
+        let mut generator = ToneGenerator::new(440.0, sample_rate);
+
+        assert_eq!(generator.next_sample(), 0.0);
+
+        generator.set_playing(true);
+        let attack: Vec<f32> = (0..envelope_samples)
+            .map(|_| generator.next_sample())
+            .collect();
+        assert!(attack[0].abs() < 1e-3);
+        for pair in attack.windows(2) {
+            assert!((pair[1] - pair[0]).abs() < TONE_AMPLITUDE * 0.1);
+        }
+
+        let sustain: Vec<f32> = (0..sample_rate as usize)
+            .map(|_| generator.next_sample())
+            .collect();
+        let peak = sustain.iter().fold(0.0f32, |a, &b| a.max(b.abs()));
+        assert!((peak - TONE_AMPLITUDE).abs() < 1e-3);
+
+        let rising_crossings = sustain
+            .windows(2)
+            .filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0)
+            .count();
+        assert!((439..=441).contains(&rising_crossings));
+
+        generator.set_playing(false);
+        let release: Vec<f32> = (0..envelope_samples + 1)
+            .map(|_| generator.next_sample())
+            .collect();
+        for pair in release.windows(2) {
+            assert!((pair[1] - pair[0]).abs() < TONE_AMPLITUDE * 0.1);
+        }
+        assert_eq!(*release.last().unwrap(), 0.0);
+    }
+
+    #[test]
+    fn test_tone_generator_volume_and_organ_waveform() {
+        let sample_rate = 48000.0;
+        let mut generator = ToneGenerator::new(200.0, sample_rate);
+        generator.set_volume(0.5);
+        generator.set_playing(true);
+        let quiet: Vec<f32> = (0..4800).map(|_| generator.next_sample()).collect();
+        let peak = quiet[2400..].iter().fold(0.0f32, |a, &b| a.max(b.abs()));
+        assert!((peak - 0.5 * TONE_AMPLITUDE).abs() < 1e-3);
+
+        // Turning it up ramps rather than jumps.
+        generator.set_volume(1.0);
+        let louder: Vec<f32> = (0..4800).map(|_| generator.next_sample()).collect();
+        for pair in louder.windows(2) {
+            assert!((pair[1] - pair[0]).abs() < TONE_AMPLITUDE * 0.1);
+        }
+
+        // The organ tone carries the octave and twelfth as well.
+        generator.set_waveform(Waveform::Organ);
+        let organ: Vec<f32> = (0..4800).map(|_| generator.next_sample()).collect();
+        let level = |frequency: f32| {
+            let (re, im) = organ
+                .iter()
+                .enumerate()
+                .fold((0.0, 0.0), |(re, im), (i, &x)| {
+                    let angle = std::f32::consts::TAU * frequency * i as f32 / sample_rate;
+                    (re + x * angle.cos(), im + x * angle.sin())
+                });
+            (re * re + im * im).sqrt() / organ.len() as f32
+        };
+        assert!(level(400.0) > level(200.0) * 0.4);
+        assert!(level(600.0) > level(200.0) * 0.2);
+        assert!(level(300.0) < level(200.0) * 0.01);
+        let peak = organ.iter().fold(0.0f32, |a, &b| a.max(b.abs()));
+        assert!(peak <= TONE_AMPLITUDE);
+    }
+
+    #[test]
+    fn test_tone_generator_beeps_and_releases() {
+        let sample_rate = 48000.0;
+        let envelope_samples = (TONE_ENVELOPE_SECONDS * sample_rate) as usize;
+        let mut generator = ToneGenerator::new(440.0, sample_rate);
+
+        generator.beep(880.0, Duration::from_millis(100));
+        let beep: Vec<f32> = (0..4800 + envelope_samples + 1)
+            .map(|_| generator.next_sample())
+            .collect();
+        let rising_crossings = beep[..4800]
+            .windows(2)
+            .filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0)
+            .count();
+        assert!((87..=89).contains(&rising_crossings));
+        for pair in beep.windows(2) {
+            assert!((pair[1] - pair[0]).abs() < TONE_AMPLITUDE * 0.3);
+        }
+        assert_eq!(*beep.last().unwrap(), 0.0);
+        assert_eq!(generator.next_sample(), 0.0);
+
+        // Starting the reference tone mid-beep keeps it playing.
+        generator.beep(880.0, Duration::from_millis(10));
+        generator.set_playing(true);
+        let tone: Vec<f32> = (0..4800).map(|_| generator.next_sample()).collect();
+        assert!(tone[4000..]
+            .iter()
+            .any(|sample| sample.abs() > TONE_AMPLITUDE * 0.9));
+    }
+
+    #[test]
+    fn test_device_monitor_sends_changes_and_stops() {
+        use std::sync::atomic::AtomicUsize;
+
+        let scans = Arc::new(AtomicUsize::new(0));
+        let monitor = {
+            let scans = scans.clone();
+            DeviceMonitor::start(Duration::from_millis(5), move || {
+                let scan = scans.fetch_add(1, Ordering::Relaxed);
+                let mut devices = vec!["Built-in Microphone".to_string()];
+                if scan >= 3 {
+                    devices.push("Scarlett 2i2 USB".to_string());
+                }
+                // Plugging in a headset makes it the default without adding
+                // anything to this list.
+                let default = if scan >= 6 {
+                    "Scarlett 2i2 USB"
+                } else {
+                    "Built-in Microphone"
+                };
+                Ok(DeviceScan {
+                    devices,
+                    default: Some(default.to_string()),
+                })
+            })
+        };
+
+        let mut received = Vec::new();
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while received.len() < 3 && Instant::now() < deadline {
+            if let Some(scan) = monitor.try_recv() {
+                received.push(scan);
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        assert_eq!(received.len(), 3, "unchanged scans are not re-sent");
+        assert_eq!(received[0].devices, vec!["Built-in Microphone".to_string()]);
+        assert_eq!(received[1].devices.len(), 2);
+        assert_eq!(received[1].default.as_deref(), Some("Built-in Microphone"));
+        assert_eq!(received[2].devices, received[1].devices);
+        assert_eq!(received[2].default.as_deref(), Some("Scarlett 2i2 USB"));
+
+        let mut monitor = monitor;
+        let stopping = Instant::now();
+        monitor.stop();
+        assert!(stopping.elapsed() < Duration::from_secs(1));
+        let scans_after_stop = scans.load(Ordering::Relaxed);
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(scans.load(Ordering::Relaxed), scans_after_stop);
+    }
+
+    #[test]
+    fn test_device_list_changed_ignores_order() {
+        let names = |list: &[&str]| list.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+
+        assert!(!device_list_changed(
+            &names(&["A", "B"]),
+            &names(&["B", "A"])
+        ));
+        assert!(device_list_changed(&names(&["A", "B"]), &names(&["A"])));
+        assert!(device_list_changed(
+            &names(&["A", "B"]),
+            &names(&["A", "C"])
+        ));
+    }
+
+    #[test]
+    fn test_file_source_downmixes_and_propagates_sample_rate() {
+        let path = std::env::temp_dir().join(format!(
+            "chroma-tuner-file-source-{}.wav",
+            std::process::id()
+        ));
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 22050,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for _ in 0..BUFFER_SIZE {
+            writer.write_sample(i16::MAX / 2).unwrap();
+            writer.write_sample(0i16).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let audio_data = Arc::new(Mutex::new(AudioData::new()));
+        audio_data.lock().unwrap().channel_selection = ChannelSelection::Channel(3);
+        let mut source = FileSource::open(&path, audio_data.clone(), 2.0).unwrap();
+        assert_eq!(
+            source.name(),
+            path.file_name().unwrap().to_string_lossy().as_ref()
+        );
+
+        let deadline = Instant::now() + Duration::from_secs(2);
+        // Stop as soon as the whole file is in, well before the trailing silence.
+        while audio_data.lock().unwrap().len < BUFFER_SIZE && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        source.stop();
+        let _ = std::fs::remove_file(&path);
+
+        let audio_data = audio_data.lock().unwrap();
+        assert_eq!(audio_data.input_sample_rate, 22050.0);
+        assert_eq!(audio_data.sample_rate, ANALYSIS_SAMPLE_RATE as f32);
+        assert_eq!(audio_data.channels, 2);
+        assert_eq!(audio_data.channel_selection, ChannelSelection::Mix);
+
+        let mut out = vec![0.0; BUFFER_SIZE];
+        assert_eq!(audio_data.get_samples(&mut out), out.len());
+        // The resampler starts from silence, so the first few ramp up.
+        let settled = &out[RESAMPLER_TAPS * 3..];
+        assert!(settled.iter().all(|&sample| (sample - 0.25).abs() < 1e-3));
+    }
+
+    #[test]
+    fn test_file_source_reports_unreadable_files() {
+        let missing = std::env::temp_dir().join("chroma-tuner-missing.wav");
+        let audio_data = Arc::new(Mutex::new(AudioData::new()));
+        let error = FileSource::open(&missing, audio_data, 1.0).err().unwrap();
+        assert!(format!("{:#}", error).starts_with("Failed to read WAV file"));
+    }
+
+    #[test]
+    fn test_recorder_writes_pushed_blocks() {
+        let path = std::env::temp_dir()
+            .join(format!("chroma-tuner-recorder-{}", std::process::id()))
+            .join("take.wav");
+        let audio_data = Mutex::new(AudioData::new());
+        audio_data.lock().unwrap().sample_rate = 48000.0;
+
+        audio_data.lock().unwrap().push_samples(&[9.0; 16]);
+        let recorder = Recorder::start(&path, &audio_data).unwrap();
+        assert_eq!(recorder.path(), path);
+        for block in 0..4 {
+            audio_data
+                .lock()
+                .unwrap()
+                .push_samples(&[block as f32 * 0.1; 256]);
+        }
+        assert!(!recorder.failed());
+        recorder.stop(&audio_data).unwrap();
+        audio_data.lock().unwrap().push_samples(&[9.0; 16]);
+
+        let reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.spec().sample_rate, 48000);
+        assert_eq!(reader.spec().channels, 1);
+        let samples: Vec<f32> = reader.into_samples().map(Result::unwrap).collect();
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+
+        assert_eq!(samples.len(), 4 * 256);
+        assert_eq!(samples[0], 0.0);
+        assert_eq!(samples[3 * 256], 0.3);
+    }
+
+    #[test]
+    fn test_recorder_reports_unwritable_path() {
+        let blocker = std::env::temp_dir().join(format!(
+            "chroma-tuner-recorder-blocker-{}",
+            std::process::id()
+        ));
+        std::fs::write(&blocker, b"not a directory").unwrap();
+        let audio_data = Mutex::new(AudioData::new());
+
+        let error = Recorder::start(&blocker.join("take.wav"), &audio_data)
+            .err()
+            .unwrap();
+        let _ = std::fs::remove_file(&blocker);
+
+        assert!(format!("{:#}", error).starts_with("Failed to create"));
+        assert!(audio_data.lock().unwrap().recorder.is_none());
+    }
+
+    #[test]
+    fn test_match_device_name() {
+        let names: Vec<String> = [
+            "MacBook Pro Microphone",
+            "Scarlett 2i2 USB",
+            "Scarlett 4i4 USB",
+            "scarlett",
+        ]
+        .map(String::from)
+        .to_vec();
+
+        assert_eq!(match_device_name(&names, "Scarlett 2i2 USB").unwrap(), 1);
+        assert_eq!(match_device_name(&names, "macbook").unwrap(), 0);
+        assert_eq!(match_device_name(&names, "SCARLETT 4").unwrap(), 2);
+        assert_eq!(match_device_name(&names, "scarlett").unwrap(), 3);
+
+        let ambiguous = match_device_name(&names, "Scarlett ").unwrap_err();
+        assert_eq!(
+            ambiguous.to_string(),
+            "'Scarlett ' matches several input devices: Scarlett 2i2 USB, Scarlett 4i4 USB"
+        );
+        let missing = match_device_name(&names, "Focusrite").unwrap_err();
+        assert!(missing
+            .to_string()
+            .starts_with("No input device matches 'Focusrite'"));
+        assert!(match_device_name(&[], "anything").is_err());
+    }
+
+    fn format(min: u32, max: u32, buffer_frames: Option<(u32, u32)>) -> SupportedFormat {
+        SupportedFormat {
+            min_sample_rate: min,
+            max_sample_rate: max,
+            buffer_frames,
+        }
+    }
+
+    #[test]
+    fn test_choose_format_prefers_48_then_44_1_khz() {
+        let choice = |supported: &[SupportedFormat]| {
+            choose_format(supported, 0).map(|choice| (choice.index, choice.sample_rate))
+        };
+        let wide = format(8000, 192000, None);
+        assert_eq!(choice(&[wide]), Some((0, 48000)));
+
+        let cd = format(44100, 44100, None);
+        let hi_res = format(96000, 192000, None);
+        assert_eq!(choice(&[hi_res, cd]), Some((1, 44100)));
+        assert_eq!(
+            choice(&[cd, format(48000, 48000, None)]),
+            Some((1, 48000)),
+            "48 kHz wins even when listed later"
+        );
+        assert_eq!(choice(&[]), None);
+    }
+
+    #[test]
+    fn test_choose_format_falls_back_to_the_nearest_rate() {
+        let hi_res = format(88200, 192000, None);
+        let low = format(8000, 32000, None);
+        let choice = choose_format(&[hi_res, low], 0).unwrap();
+        assert_eq!((choice.index, choice.sample_rate), (1, 32000));
+
+        let choice = choose_format(&[format(96000, 96000, None), hi_res], 0).unwrap();
+        assert_eq!((choice.index, choice.sample_rate), (1, 88200));
+    }
+
+    #[test]
+    fn test_choose_format_clamps_the_buffer_size() {
+        let buffer = |range, frames| {
+            choose_format(&[format(48000, 48000, range)], frames)
+                .unwrap()
+                .buffer_frames
+        };
+        assert_eq!(buffer(Some((64, 4096)), 512), Some(512));
+        assert_eq!(buffer(Some((1024, 4096)), 512), Some(1024));
+        assert_eq!(buffer(Some((16, 256)), 512), Some(256));
+        assert_eq!(buffer(None, 512), Some(512), "unknown ranges are tried");
+        assert_eq!(buffer(Some((64, 4096)), 0), None);
+    }
+
+    #[test]
+    fn test_monitor_sources_are_labelled_as_system_audio() {
+        let monitor = "alsa_output.pci-0000_00_1f.3.analog-stereo.monitor";
+        assert!(is_monitor_source(monitor));
+        assert_eq!(
+            device_label(monitor),
+            "System audio (alsa_output.pci-0000_00_1f.3.analog-stereo)"
+        );
+        assert_eq!(
+            device_label("Monitor of Built-in Audio Analog Stereo"),
+            "System audio (Built-in Audio Analog Stereo)"
+        );
+
+        assert!(!is_monitor_source("Scarlett 2i2 USB"));
+        assert_eq!(device_label("Scarlett 2i2 USB"), "Scarlett 2i2 USB");
+    }
 }
@@ -0,0 +1,495 @@
+//! Capture from input devices and playback of the reference tone, in-tune
+//! beep and metronome via cpal
+
+use anyhow::Result;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{
+    BufferSize, Device, Sample, SampleFormat, SampleRate, Stream, StreamConfig,
+    SupportedBufferSize, SupportedStreamConfig,
+};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::{
+    apply_gain, choose_format, extract_channel, is_monitor_source, AudioData, AudioSource,
+    DeviceScan, MonoGenerator, SupportedFormat, ToneGenerator, Waveform, SYSTEM_AUDIO_DEVICE,
+};
+use crate::metronome::{Beat, ClickGenerator};
+
+/// Streams an input device into `AudioData` for as long as it is alive.
+pub struct AudioCapture {
+    stream: Stream,
+    device_name: String,
+}
+
+impl AudioCapture {
+    /// Opens the system default input device, asking for `buffer_frames`
+    /// frames per callback as [`AudioCapture::new_with_device`] does.
+    pub fn new(audio_data: Arc<Mutex<AudioData>>, buffer_frames: u32) -> Result<Self> {
+        let host = cpal::default_host();
+        let device = host
+            .default_input_device()
+            .ok_or_else(|| anyhow::anyhow!("No input device available"))?;
+
+        Self::new_with_device(audio_data, device, buffer_frames)
+    }
+
+    /// Opens `device` at the rate [`negotiate_config`] picks, asking for
+    /// `buffer_frames` frames per callback, or the device default for 0 or if
+    /// the device refuses them.
+    pub fn new_with_device(
+        audio_data: Arc<Mutex<AudioData>>,
+        device: cpal::Device,
+        buffer_frames: u32,
+    ) -> Result<Self> {
+        let (config, mut buffer_frames) = negotiate_config(&device, buffer_frames)?;
+        let actual_sample_rate = config.sample_rate().0 as f32;
+        let channels = config.channels() as usize;
+        // Only an output device opened for loopback gets this far without an
+        // input format.
+        let device_name = if device.default_input_config().is_err() {
+            SYSTEM_AUDIO_DEVICE.to_string()
+        } else {
+            device.name()?
+        };
+
+        log::info!("Input device: {}", device_name);
+        log::debug!("Input config: {:?}, buffer {:?}", config, buffer_frames);
+        log::info!("Actual sample rate: {} Hz", actual_sample_rate);
+
+        if let Ok(mut audio_data) = audio_data.lock() {
+            audio_data.configure_input(actual_sample_rate, channels, buffer_frames);
+            audio_data.clear();
+        }
+
+        let sample_format = config.sample_format();
+        let mut stream_config: StreamConfig = config.into();
+        if let Some(frames) = buffer_frames {
+            stream_config.buffer_size = BufferSize::Fixed(frames);
+        }
+        let stream =
+            match Self::build_stream(&device, &stream_config, sample_format, audio_data.clone()) {
+                Err(err) if buffer_frames.is_some() => {
+                    log::warn!(
+                        "'{}' refused {:?} frames per callback ({}), using its default",
+                        device_name,
+                        buffer_frames,
+                        err
+                    );
+                    stream_config.buffer_size = BufferSize::Default;
+                    buffer_frames = None;
+                    if let Ok(mut audio_data) = audio_data.lock() {
+                        audio_data.buffer_frames = buffer_frames;
+                    }
+                    Self::build_stream(&device, &stream_config, sample_format, audio_data)?
+                }
+                result => result?,
+            };
+
+        stream.play()?;
+
+        Ok(Self {
+            stream,
+            device_name,
+        })
+    }
+
+    fn build_stream(
+        device: &Device,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        audio_data: Arc<Mutex<AudioData>>,
+    ) -> Result<Stream> {
+        match sample_format {
+            SampleFormat::I8 => Self::create_stream::<i8>(device, config, audio_data),
+            SampleFormat::I16 => Self::create_stream::<i16>(device, config, audio_data),
+            SampleFormat::I32 => Self::create_stream::<i32>(device, config, audio_data),
+            SampleFormat::I64 => Self::create_stream::<i64>(device, config, audio_data),
+            SampleFormat::U8 => Self::create_stream::<u8>(device, config, audio_data),
+            SampleFormat::U16 => Self::create_stream::<u16>(device, config, audio_data),
+            SampleFormat::U32 => Self::create_stream::<u32>(device, config, audio_data),
+            SampleFormat::U64 => Self::create_stream::<u64>(device, config, audio_data),
+            SampleFormat::F32 => Self::create_stream::<f32>(device, config, audio_data),
+            SampleFormat::F64 => Self::create_stream::<f64>(device, config, audio_data),
+            _ => Err(anyhow::anyhow!("Unsupported sample format")),
+        }
+    }
+
+    fn create_stream<T>(
+        device: &Device,
+        config: &StreamConfig,
+        audio_data: Arc<Mutex<AudioData>>,
+    ) -> Result<Stream>
+    where
+        T: Sample + cpal::SizedSample + Send + 'static,
+        f32: cpal::FromSample<T>,
+    {
+        let channels = config.channels as usize;
+        let mut samples = Vec::new();
+        let mut mono = Vec::new();
+        let error_data = audio_data.clone();
+
+        let stream = device.build_input_stream(
+            config,
+            move |data: &[T], _: &cpal::InputCallbackInfo| {
+                samples.clear();
+                samples.extend(data.iter().map(|&sample| f32::from_sample(sample)));
+
+                if let Ok(mut audio_data) = audio_data.lock() {
+                    let selection = audio_data.channel_selection;
+                    extract_channel(&samples, channels, selection, &mut mono);
+                    apply_gain(&mut mono, audio_data.gain);
+                    audio_data.push_input(&mono);
+                }
+            },
+            move |err| {
+                log::error!("Audio stream error: {}", err);
+                if let Ok(mut audio_data) = error_data.lock() {
+                    audio_data.report_stream_error(format!("Audio stream error: {}", err));
+                }
+            },
+            None,
+        )?;
+
+        Ok(stream)
+    }
+}
+
+impl AudioSource for AudioCapture {
+    fn name(&self) -> &str {
+        &self.device_name
+    }
+
+    fn pause(&mut self) -> Result<()> {
+        self.stream.pause()?;
+        Ok(())
+    }
+
+    fn resume(&mut self) -> Result<()> {
+        self.stream.play()?;
+        Ok(())
+    }
+}
+
+/// Plays the reference tone, the drone or the in-tune beep on the default
+/// output device.
+pub struct TonePlayer {
+    _stream: Stream,
+    generator: Arc<Mutex<ToneGenerator>>,
+    playing: bool,
+}
+
+impl TonePlayer {
+    /// Opens the default output device, silent until `play` is called.
+    pub fn new(frequency: f32) -> Result<Self> {
+        let (stream, generator) =
+            open_output(|sample_rate| ToneGenerator::new(frequency, sample_rate))?;
+
+        Ok(Self {
+            _stream: stream,
+            generator,
+            playing: false,
+        })
+    }
+
+    /// Retunes the tone without restarting it.
+    pub fn set_frequency(&self, frequency: f32) {
+        if let Ok(mut generator) = self.generator.lock() {
+            generator.set_frequency(frequency);
+        }
+    }
+
+    /// Changes the tone's waveform.
+    pub fn set_waveform(&self, waveform: Waveform) {
+        if let Ok(mut generator) = self.generator.lock() {
+            generator.set_waveform(waveform);
+        }
+    }
+
+    /// Ramps the tone to `volume`, from 0 to 1.
+    pub fn set_volume(&self, volume: f32) {
+        if let Ok(mut generator) = self.generator.lock() {
+            generator.set_volume(volume);
+        }
+    }
+
+    /// Plays a short beep at `frequency`, unless the tone is playing.
+    pub fn beep(&self, frequency: f32, duration: Duration) {
+        if self.playing {
+            return;
+        }
+        if let Ok(mut generator) = self.generator.lock() {
+            generator.beep(frequency, duration);
+        }
+    }
+
+    /// Fades the tone in.
+    pub fn play(&mut self) {
+        self.set_playing(true);
+    }
+
+    /// Fades the tone out.
+    pub fn stop(&mut self) {
+        self.set_playing(false);
+    }
+
+    /// Whether the tone is playing or fading in.
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    fn set_playing(&mut self, playing: bool) {
+        self.playing = playing;
+        if let Ok(mut generator) = self.generator.lock() {
+            generator.set_playing(playing);
+        }
+    }
+}
+
+/// Plays metronome clicks on the default output device.
+pub struct MetronomePlayer {
+    _stream: Stream,
+    generator: Arc<Mutex<ClickGenerator>>,
+}
+
+impl MetronomePlayer {
+    /// Opens the default output device, silent until `start` is called.
+    pub fn new() -> Result<Self> {
+        let (stream, generator) = open_output(ClickGenerator::new)?;
+        Ok(Self {
+            _stream: stream,
+            generator,
+        })
+    }
+
+    /// Starts counting from a downbeat.
+    pub fn start(&self, bpm: u32, beats_per_bar: u32) {
+        if let Ok(mut generator) = self.generator.lock() {
+            generator.set_tempo(bpm as f32, beats_per_bar);
+            generator.start();
+        }
+    }
+
+    /// Stops after the click that is sounding.
+    pub fn stop(&self) {
+        if let Ok(mut generator) = self.generator.lock() {
+            generator.stop();
+        }
+    }
+
+    /// Changes the tempo or bar length without restarting.
+    pub fn set_tempo(&self, bpm: u32, beats_per_bar: u32) {
+        if let Ok(mut generator) = self.generator.lock() {
+            generator.set_tempo(bpm as f32, beats_per_bar);
+        }
+    }
+
+    /// Whether the metronome is counting.
+    pub fn is_running(&self) -> bool {
+        self.generator
+            .lock()
+            .is_ok_and(|generator| generator.is_running())
+    }
+
+    /// The beat that sounded last.
+    pub fn last_beat(&self) -> Option<Beat> {
+        self.generator
+            .lock()
+            .ok()
+            .and_then(|generator| generator.last_beat())
+    }
+}
+
+/// Opens the default output device and plays the generator `make` builds for
+/// its sample rate through every channel.
+fn open_output<G: MonoGenerator>(make: impl FnOnce(f32) -> G) -> Result<(Stream, Arc<Mutex<G>>)> {
+    let host = cpal::default_host();
+    let device = host
+        .default_output_device()
+        .ok_or_else(|| anyhow::anyhow!("No output device available"))?;
+    let config = device.default_output_config()?;
+
+    log::info!("Output device: {}", device.name()?);
+
+    let generator = Arc::new(Mutex::new(make(config.sample_rate().0 as f32)));
+
+    let stream = match config.sample_format() {
+        SampleFormat::I8 => {
+            build_output_stream::<i8, G>(&device, &config.into(), generator.clone())?
+        }
+        SampleFormat::I16 => {
+            build_output_stream::<i16, G>(&device, &config.into(), generator.clone())?
+        }
+        SampleFormat::I32 => {
+            build_output_stream::<i32, G>(&device, &config.into(), generator.clone())?
+        }
+        SampleFormat::I64 => {
+            build_output_stream::<i64, G>(&device, &config.into(), generator.clone())?
+        }
+        SampleFormat::U8 => {
+            build_output_stream::<u8, G>(&device, &config.into(), generator.clone())?
+        }
+        SampleFormat::U16 => {
+            build_output_stream::<u16, G>(&device, &config.into(), generator.clone())?
+        }
+        SampleFormat::U32 => {
+            build_output_stream::<u32, G>(&device, &config.into(), generator.clone())?
+        }
+        SampleFormat::U64 => {
+            build_output_stream::<u64, G>(&device, &config.into(), generator.clone())?
+        }
+        SampleFormat::F32 => {
+            build_output_stream::<f32, G>(&device, &config.into(), generator.clone())?
+        }
+        SampleFormat::F64 => {
+            build_output_stream::<f64, G>(&device, &config.into(), generator.clone())?
+        }
+        _ => return Err(anyhow::anyhow!("Unsupported sample format")),
+    };
+
+    stream.play()?;
+    Ok((stream, generator))
+}
+
+fn build_output_stream<T, G>(
+    device: &Device,
+    config: &StreamConfig,
+    generator: Arc<Mutex<G>>,
+) -> Result<Stream>
+where
+    T: Sample + cpal::SizedSample + cpal::FromSample<f32> + Send + 'static,
+    G: MonoGenerator,
+{
+    let channels = config.channels as usize;
+
+    let stream = device.build_output_stream(
+        config,
+        move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+            let Ok(mut generator) = generator.lock() else {
+                data.fill(T::EQUILIBRIUM);
+                return;
+            };
+
+            for frame in data.chunks_mut(channels) {
+                let sample = T::from_sample(generator.next_sample());
+                frame.fill(sample);
+            }
+        },
+        |err| log::error!("Audio output stream error: {}", err),
+        None,
+    )?;
+
+    Ok(stream)
+}
+
+/// The format `device` is captured in: its default input format or, for an
+/// output device on Windows, its default output format, captured by WASAPI
+/// loopback.
+pub fn capture_config(device: &Device) -> Result<SupportedStreamConfig> {
+    match device.default_input_config() {
+        Ok(config) => Ok(config),
+        #[cfg(target_os = "windows")]
+        Err(_) if device.default_output_config().is_ok() => Ok(device.default_output_config()?),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// The format to capture `device` in: the channels and sample type of its
+/// [`capture_config`], at the rate [`choose_format`] picks among those it
+/// supports, with the callback size to ask for. Falls back to the default
+/// format where the device doesn't list what it supports, as for loopback.
+pub fn negotiate_config(
+    device: &Device,
+    buffer_frames: u32,
+) -> Result<(SupportedStreamConfig, Option<u32>)> {
+    let default = capture_config(device)?;
+    let ranges: Vec<_> = match device.supported_input_configs() {
+        Ok(configs) => configs
+            .filter(|range| {
+                range.channels() == default.channels()
+                    && range.sample_format() == default.sample_format()
+            })
+            .collect(),
+        Err(err) => {
+            log::debug!("No supported input configs listed: {}", err);
+            Vec::new()
+        }
+    };
+    let supported: Vec<SupportedFormat> = ranges
+        .iter()
+        .map(|range| SupportedFormat {
+            min_sample_rate: range.min_sample_rate().0,
+            max_sample_rate: range.max_sample_rate().0,
+            buffer_frames: match range.buffer_size() {
+                SupportedBufferSize::Range { min, max } => Some((*min, *max)),
+                SupportedBufferSize::Unknown => None,
+            },
+        })
+        .collect();
+
+    Ok(match choose_format(&supported, buffer_frames) {
+        Some(choice) => (
+            ranges[choice.index].with_sample_rate(SampleRate(choice.sample_rate)),
+            choice.buffer_frames,
+        ),
+        None => (default, (buffer_frames > 0).then_some(buffer_frames)),
+    })
+}
+
+/// Every input device the default host offers, with its name. Monitor
+/// sources come after the microphones and line inputs, and on Windows the
+/// default output device is offered for loopback as [`SYSTEM_AUDIO_DEVICE`].
+pub fn get_input_devices() -> Result<Vec<(String, cpal::Device)>> {
+    let host = cpal::default_host();
+    let mut devices = Vec::new();
+
+    for device in host.input_devices()? {
+        if let Ok(name) = device.name() {
+            devices.push((name, device));
+        }
+    }
+    devices.sort_by_key(|(name, _)| is_monitor_source(name));
+
+    #[cfg(target_os = "windows")]
+    if host.id() == cpal::HostId::Wasapi {
+        if let Some(output) = host.default_output_device() {
+            devices.push((SYSTEM_AUDIO_DEVICE.to_string(), output));
+        }
+    }
+
+    Ok(devices)
+}
+
+/// Names of every input device the default host offers.
+pub fn get_input_device_names() -> Result<Vec<String>> {
+    Ok(get_input_devices()?
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect())
+}
+
+/// Input device names along with the current default, for [`DeviceMonitor`].
+pub fn scan_input_devices() -> Result<DeviceScan> {
+    Ok(DeviceScan {
+        devices: get_input_device_names()?,
+        default: get_default_input_device_name().ok(),
+    })
+}
+
+/// Looks up an input device by its exact name.
+pub fn find_input_device(name: &str) -> Option<cpal::Device> {
+    get_input_devices()
+        .ok()?
+        .into_iter()
+        .find(|(device_name, _)| device_name == name)
+        .map(|(_, device)| device)
+}
+
+/// Name of the system default input device.
+pub fn get_default_input_device_name() -> Result<String> {
+    let host = cpal::default_host();
+    let device = host
+        .default_input_device()
+        .ok_or_else(|| anyhow::anyhow!("No default input device"))?;
+    Ok(device.name()?)
+}
@@ -0,0 +1,197 @@
+//! Sample rate conversion from the input device to the analysis rate
+//!
+//! A windowed-sinc interpolator with a precomputed polyphase table: each
+//! output sample is a short dot product, cheap enough for the capture
+//! callback, and the delay is only half the filter length.
+
+/// Input samples each output sample is interpolated from.
+pub const RESAMPLER_TAPS: usize = 32;
+/// Fractional positions between input samples with their own coefficients.
+const PHASES: usize = 256;
+/// Passband as a fraction of the lower of the two Nyquist frequencies,
+/// leaving room for the filter to roll off before aliasing.
+const PASSBAND: f64 = 0.9;
+
+/// Converts a stream of mono blocks from one sample rate to another, keeping
+/// the tail of each block for the next so the seams are seamless. Passes
+/// samples straight through when the rates match.
+#[derive(Clone, Debug)]
+pub struct Resampler {
+    /// Input samples advanced per output sample.
+    step: f64,
+    /// One row of [`RESAMPLER_TAPS`] coefficients per phase, plus a last row
+    /// for a whole sample so rounding up needs no wrap.
+    table: Vec<f32>,
+    /// Input not yet fully used, starting with the history the next output
+    /// still reaches back into.
+    pending: Vec<f32>,
+    /// Position of the next output sample in `pending`.
+    position: f64,
+}
+
+impl Resampler {
+    /// A resampler from `input_rate` to `output_rate`, both in Hz.
+    pub fn new(input_rate: u32, output_rate: u32) -> Self {
+        let step = input_rate as f64 / output_rate.max(1) as f64;
+        let table = if input_rate == output_rate {
+            Vec::new()
+        } else {
+            coefficient_table(PASSBAND * 0.5 * (1.0 / step).min(1.0))
+        };
+        let mut resampler = Self {
+            step,
+            table,
+            pending: Vec::new(),
+            position: 0.0,
+        };
+        resampler.reset();
+        resampler
+    }
+
+    /// Whether samples pass through untouched.
+    pub fn is_bypassed(&self) -> bool {
+        self.table.is_empty()
+    }
+
+    /// Forgets the history, as at the start of a new stream.
+    pub fn reset(&mut self) {
+        self.pending.clear();
+        self.pending.resize(RESAMPLER_TAPS, 0.0);
+        self.position = (RESAMPLER_TAPS / 2 - 1) as f64;
+    }
+
+    /// Replaces the contents of `out` with `input` at the output rate. Output
+    /// lags input by half the filter length, [`RESAMPLER_TAPS`] / 2 input
+    /// samples.
+    pub fn process(&mut self, input: &[f32], out: &mut Vec<f32>) {
+        out.clear();
+        if self.is_bypassed() {
+            out.extend_from_slice(input);
+            return;
+        }
+
+        self.pending.extend_from_slice(input);
+        let half = RESAMPLER_TAPS / 2;
+        while (self.position as usize) + half < self.pending.len() {
+            let base = self.position as usize;
+            let phase = ((self.position - base as f64) * PHASES as f64).round() as usize;
+            let coefficients = &self.table[phase * RESAMPLER_TAPS..(phase + 1) * RESAMPLER_TAPS];
+            let window = &self.pending[base + 1 - half..=base + half];
+            out.push(
+                window
+                    .iter()
+                    .zip(coefficients)
+                    .map(|(sample, coefficient)| sample * coefficient)
+                    .sum(),
+            );
+            self.position += self.step;
+        }
+
+        let consumed = (self.position as usize + 1).saturating_sub(half);
+        self.pending.drain(..consumed.min(self.pending.len()));
+        self.position -= consumed as f64;
+    }
+}
+
+/// Blackman-windowed sinc coefficients for every phase, each row summing to
+/// one so a constant signal keeps its level. `cutoff` is in cycles per input
+/// sample.
+fn coefficient_table(cutoff: f64) -> Vec<f32> {
+    let half = (RESAMPLER_TAPS / 2) as f64;
+    let mut table = Vec::with_capacity((PHASES + 1) * RESAMPLER_TAPS);
+    for phase in 0..=PHASES {
+        let fraction = phase as f64 / PHASES as f64;
+        let row: Vec<f64> = (0..RESAMPLER_TAPS)
+            .map(|tap| {
+                let distance = fraction + half - 1.0 - tap as f64;
+                let x = 2.0 * cutoff * distance;
+                let sinc = if x == 0.0 {
+                    1.0
+                } else {
+                    (std::f64::consts::PI * x).sin() / (std::f64::consts::PI * x)
+                };
+                let w = std::f64::consts::PI * distance / half;
+                let window = 0.42 + 0.5 * w.cos() + 0.08 * (2.0 * w).cos();
+                sinc * window
+            })
+            .collect();
+        let sum: f64 = row.iter().sum();
+        table.extend(row.iter().map(|coefficient| (coefficient / sum) as f32));
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(frequency: f32, sample_rate: u32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| (std::f32::consts::TAU * frequency * i as f32 / sample_rate as f32).sin())
+            .collect()
+    }
+
+    #[test]
+    fn test_matching_rates_pass_through() {
+        let mut resampler = Resampler::new(48000, 48000);
+        assert!(resampler.is_bypassed());
+        let input = sine(440.0, 48000, 100);
+        let mut out = Vec::new();
+        resampler.process(&input, &mut out);
+        assert_eq!(out, input);
+    }
+
+    #[test]
+    fn test_output_length_follows_the_rate_ratio_across_blocks() {
+        for (input_rate, output_rate) in [(44100, 48000), (96000, 48000), (22050, 48000)] {
+            let mut resampler = Resampler::new(input_rate, output_rate);
+            let mut out = Vec::new();
+            let mut total = 0;
+            for _ in 0..100 {
+                resampler.process(&[0.5; 441], &mut out);
+                total += out.len();
+            }
+            let expected = 44100.0 * output_rate as f64 / input_rate as f64;
+            assert!(
+                (total as f64 - expected).abs() <= RESAMPLER_TAPS as f64,
+                "{} Hz: {} of {}",
+                input_rate,
+                total,
+                expected
+            );
+            assert!(out.iter().all(|&sample| (sample - 0.5).abs() < 1e-3));
+        }
+    }
+
+    #[test]
+    fn test_blocks_join_seamlessly() {
+        let input = sine(1000.0, 44100, 4410);
+        let mut whole = Resampler::new(44100, 48000);
+        let mut expected = Vec::new();
+        whole.process(&input, &mut expected);
+
+        let mut split = Resampler::new(44100, 48000);
+        let mut joined = Vec::new();
+        let mut out = Vec::new();
+        for block in input.chunks(37) {
+            split.process(block, &mut out);
+            joined.extend_from_slice(&out);
+        }
+        assert_eq!(joined.len(), expected.len());
+        assert!(joined
+            .iter()
+            .zip(&expected)
+            .all(|(a, b)| (a - b).abs() < 1e-6));
+    }
+
+    #[test]
+    fn test_downsampling_rejects_what_would_alias() {
+        let mut resampler = Resampler::new(96000, 48000);
+        let mut out = Vec::new();
+        resampler.process(&sine(40000.0, 96000, 9600), &mut out);
+        let peak = out[RESAMPLER_TAPS..]
+            .iter()
+            .fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+        assert!(peak < 0.02, "peak {}", peak);
+    }
+}
@@ -0,0 +1,923 @@
+//! Persistent user configuration
+//!
+//! Defines the TunerConfig struct holding all user-adjustable tuning parameters and
+//! handles loading and saving it as TOML in the platform config directory.
+//! Recordings default to the platform data directory.
+
+use anyhow::Result;
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::audio::Waveform;
+use crate::detection_log::ExportFormat;
+use crate::midi_file::MidiExportOptions;
+use crate::pitch::{
+    midi_to_frequency, HumRejection, Key, KeyMode, LowRange, NoteNaming, PitchClassSet, Precision,
+    Temperament, WindowKind, CHROMATIC_RANGE, DEFAULT_A4_FREQUENCY, DEFAULT_CROSSOVER_FREQUENCY,
+    DEFAULT_FFT_SIZE, DEFAULT_HOP_SIZE, DEFAULT_MIN_TONALITY, EDO_RANGE, FFT_SIZES, HOP_SIZES,
+};
+
+const CONFIG_FILE_NAME: &str = "config.toml";
+const RECORDINGS_DIR_NAME: &str = "recordings";
+
+/// How quickly the needle follows the reading, like the damping switch of a
+/// hardware tuner.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NeedleResponse {
+    /// Settles in about 0.1 s.
+    Fast,
+    /// Settles in about 0.25 s.
+    #[default]
+    Medium,
+    /// Settles in about half a second.
+    Slow,
+}
+
+impl NeedleResponse {
+    /// Every option, in menu order.
+    pub const ALL: [NeedleResponse; 3] = [
+        NeedleResponse::Fast,
+        NeedleResponse::Medium,
+        NeedleResponse::Slow,
+    ];
+
+    /// Name shown in the settings panel.
+    pub fn label(self) -> &'static str {
+        match self {
+            NeedleResponse::Fast => "Fast",
+            NeedleResponse::Medium => "Medium",
+            NeedleResponse::Slow => "Slow",
+        }
+    }
+
+    /// Roughly how long the needle takes to reach a new reading.
+    pub fn response_time(self) -> Duration {
+        match self {
+            NeedleResponse::Fast => Duration::from_millis(100),
+            NeedleResponse::Medium => Duration::from_millis(250),
+            NeedleResponse::Slow => Duration::from_millis(500),
+        }
+    }
+}
+
+/// How the needle is drawn.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NeedleStyle {
+    /// A dot sliding along a straight bar.
+    #[default]
+    Bar,
+    /// A needle pivoting across an arc, like an analog meter.
+    Gauge,
+}
+
+impl NeedleStyle {
+    /// Every option, in menu order.
+    pub const ALL: [NeedleStyle; 2] = [NeedleStyle::Bar, NeedleStyle::Gauge];
+
+    /// Name shown in the settings panel.
+    pub fn label(self) -> &'static str {
+        match self {
+            NeedleStyle::Bar => "Bar",
+            NeedleStyle::Gauge => "Gauge",
+        }
+    }
+}
+
+/// How many cents either end of the needle's scale stands for.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NeedleRange {
+    /// ±50 cents, half a semitone.
+    #[default]
+    Cents50,
+    /// ±25 cents.
+    Cents25,
+    /// ±10 cents, for fine adjustment.
+    Cents10,
+    /// ±50 cents, zooming in while the note stays close to in tune.
+    Auto,
+}
+
+impl NeedleRange {
+    /// Every option, in menu order.
+    pub const ALL: [NeedleRange; 4] = [
+        NeedleRange::Cents50,
+        NeedleRange::Cents25,
+        NeedleRange::Cents10,
+        NeedleRange::Auto,
+    ];
+
+    /// Name shown in the settings panel.
+    pub fn label(self) -> &'static str {
+        match self {
+            NeedleRange::Cents50 => "±50 ¢",
+            NeedleRange::Cents25 => "±25 ¢",
+            NeedleRange::Cents10 => "±10 ¢",
+            NeedleRange::Auto => "Auto zoom",
+        }
+    }
+
+    /// Cents at either end of the scale, or where auto zoom starts out.
+    pub fn cents(self) -> f32 {
+        match self {
+            NeedleRange::Cents50 | NeedleRange::Auto => 50.0,
+            NeedleRange::Cents25 => 25.0,
+            NeedleRange::Cents10 => 10.0,
+        }
+    }
+}
+
+/// How the deviation from the target is shown under the note.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DeviationDisplay {
+    /// Cents only.
+    #[default]
+    Cents,
+    /// Hz only.
+    Hz,
+    /// Both cents and Hz.
+    Both,
+}
+
+impl DeviationDisplay {
+    /// Every option, in menu order.
+    pub const ALL: [DeviationDisplay; 3] = [
+        DeviationDisplay::Cents,
+        DeviationDisplay::Hz,
+        DeviationDisplay::Both,
+    ];
+
+    /// Name shown in the settings panel.
+    pub fn label(self) -> &'static str {
+        match self {
+            DeviationDisplay::Cents => "Cents",
+            DeviationDisplay::Hz => "Hz",
+            DeviationDisplay::Both => "Cents and Hz",
+        }
+    }
+
+    /// Whether the cents deviation is shown.
+    pub fn shows_cents(self) -> bool {
+        self != DeviationDisplay::Hz
+    }
+
+    /// Whether the Hz deviation is shown.
+    pub fn shows_hz(self) -> bool {
+        self != DeviationDisplay::Cents
+    }
+}
+
+/// What the pitch history graph plots on its vertical axis.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HistoryScale {
+    /// Cents from the target note.
+    #[default]
+    Cents,
+    /// Absolute frequency.
+    Hz,
+}
+
+impl HistoryScale {
+    /// Every option, in menu order.
+    pub const ALL: [HistoryScale; 2] = [HistoryScale::Cents, HistoryScale::Hz];
+
+    /// Name shown in the settings panel.
+    pub fn label(self) -> &'static str {
+        match self {
+            HistoryScale::Cents => "Cents",
+            HistoryScale::Hz => "Hz",
+        }
+    }
+}
+
+/// Colors used to show how far off the pitch is.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ColorScheme {
+    /// Green, amber and red.
+    #[default]
+    Standard,
+    /// Blue, yellow and orange, told apart with red-green color blindness.
+    ColorBlind,
+    /// Shades of grey only.
+    HighContrast,
+}
+
+impl ColorScheme {
+    /// Every option, in menu order.
+    pub const ALL: [ColorScheme; 3] = [
+        ColorScheme::Standard,
+        ColorScheme::ColorBlind,
+        ColorScheme::HighContrast,
+    ];
+
+    /// Name shown in the settings panel.
+    pub fn label(self) -> &'static str {
+        match self {
+            ColorScheme::Standard => "Standard",
+            ColorScheme::ColorBlind => "Color-blind safe",
+            ColorScheme::HighContrast => "High contrast",
+        }
+    }
+}
+
+/// Light or dark interface.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UiTheme {
+    /// Whatever the operating system is set to.
+    System,
+    /// Light text on a dark background.
+    #[default]
+    Dark,
+    /// Dark text on a light background.
+    Light,
+}
+
+impl UiTheme {
+    /// Every option, in menu order.
+    pub const ALL: [UiTheme; 3] = [UiTheme::System, UiTheme::Dark, UiTheme::Light];
+
+    /// Name shown in the settings panel.
+    pub fn label(self) -> &'static str {
+        match self {
+            UiTheme::System => "Follow system",
+            UiTheme::Dark => "Dark",
+            UiTheme::Light => "Light",
+        }
+    }
+}
+
+/// Which layout the window shows.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DisplayMode {
+    /// The tuner with all its controls and panels.
+    #[default]
+    Standard,
+    /// A small strip with only the note and a deviation bar.
+    Mini,
+    /// The note and needle filling a large window, to read from a distance.
+    Performance,
+}
+
+impl DisplayMode {
+    /// Every option, in the order the shortcut cycles through them.
+    pub const ALL: [DisplayMode; 3] = [
+        DisplayMode::Standard,
+        DisplayMode::Mini,
+        DisplayMode::Performance,
+    ];
+
+    /// Name shown in the settings panel.
+    pub fn label(self) -> &'static str {
+        match self {
+            DisplayMode::Standard => "Standard",
+            DisplayMode::Mini => "Mini",
+            DisplayMode::Performance => "Performance",
+        }
+    }
+
+    /// The mode after this one, wrapping around.
+    pub fn next(self) -> Self {
+        match self {
+            DisplayMode::Standard => DisplayMode::Mini,
+            DisplayMode::Mini => DisplayMode::Performance,
+            DisplayMode::Performance => DisplayMode::Standard,
+        }
+    }
+}
+
+/// What shows behind the reading in overlay mode.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OverlayBackground {
+    /// A solid color for streaming software to key out.
+    #[default]
+    ChromaKey,
+    /// Nothing, where the window system supports transparent windows.
+    Transparent,
+}
+
+impl OverlayBackground {
+    /// Every option, in menu order.
+    pub const ALL: [OverlayBackground; 2] =
+        [OverlayBackground::ChromaKey, OverlayBackground::Transparent];
+
+    /// Name shown in the settings panel.
+    pub fn label(self) -> &'static str {
+        match self {
+            OverlayBackground::ChromaKey => "Chroma key",
+            OverlayBackground::Transparent => "Transparent",
+        }
+    }
+}
+
+/// Every user setting, saved as TOML between launches.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TunerConfig {
+    /// Frequency of A4 in Hz.
+    pub a4_reference: f32,
+    /// Detections quieter than this cannot acquire a note.
+    pub min_magnitude_threshold: f32,
+    /// Detections less periodic than this (0–1) are ignored.
+    pub min_confidence: f32,
+    /// Share of spectral energy (0–1) a note's harmonics must hold for it to
+    /// be detected at all; 0 disables the check.
+    pub min_tonality: f32,
+    /// Largest frequency spread in Hz the pitch window may hold.
+    pub stability_threshold: f32,
+    /// How long detections must agree before a pitch is accepted.
+    pub pitch_window_ms: u64,
+    /// How much cents history is averaged for the needle.
+    pub cents_window_ms: u64,
+    /// Time constant of the needle's exponential smoothing.
+    pub smoothing_ms: u64,
+    /// Once a note is locked it keeps updating down to this fraction of the
+    /// magnitude gate, so a decaying string isn't dropped early.
+    pub sustain_fraction: f32,
+    /// How long a note is held while detections fall below the gate.
+    pub quiet_hold_ms: u64,
+    /// How long a note is held once detections stop entirely.
+    pub silence_hold_ms: u64,
+    /// How long after a note drops out it resumes straight away, with its old
+    /// deviation, if it is heard again; 0 disables it.
+    pub relock_grace_ms: u64,
+    /// How long detections are ignored after a note's onset, long enough for
+    /// the attack to clear most of the analysis block; 0 disables it.
+    pub attack_skip_ms: u64,
+    /// Deviation in cents below which a note is in tune.
+    pub in_tune_cents: f32,
+    /// Deviation in cents below which an out-of-tune note is shown as close
+    /// rather than off; kept above `in_tune_cents`.
+    pub close_cents: f32,
+    /// Whether the stability meter and cents sparkline are shown under the
+    /// needle.
+    pub show_stability: bool,
+    /// Standard deviation in cents below which a note counts as held steady.
+    pub stability_target_cents: f32,
+    /// MIDI note the drone sustains, tuned in the configured temperament.
+    pub drone_note: i32,
+    /// Waveform of the drone.
+    pub drone_waveform: Waveform,
+    /// Drone volume from 0 to 1.
+    pub drone_volume: f32,
+    /// Whether notes are measured against a piano's stretched tuning, and
+    /// their partials shown.
+    pub piano_mode: bool,
+    /// How much piano mode stretches the tuning, in cents per octave, as a
+    /// scale on a curve that is gentler near A4 and steeper towards the ends.
+    pub piano_stretch: f32,
+    /// Metronome tempo in beats per minute.
+    pub metronome_bpm: u32,
+    /// Beats in each metronome bar, the first of them accented.
+    pub metronome_beats_per_bar: u32,
+    /// Whether the window flashes once a note has settled in tune.
+    pub in_tune_flash: bool,
+    /// Whether a short beep plays once a note has settled in tune.
+    pub in_tune_beep: bool,
+    /// How long a note has to stay in tune before the flash or beep.
+    pub in_tune_dwell_ms: u64,
+    /// Input device to open on launch.
+    pub preferred_device: Option<String>,
+    /// Whether capture moves to whatever the system default input is.
+    pub follow_default_device: bool,
+    /// Frames per capture callback to ask devices for; 0 takes each
+    /// device's default.
+    pub buffer_frames: u32,
+    /// Name of the active instrument preset.
+    pub instrument_preset: Option<String>,
+    /// Capo or retuning of each instrument preset in semitones, keyed by
+    /// preset name; presets not listed are at open pitch.
+    pub preset_transpose: BTreeMap<String, i32>,
+    /// Cents every preset target is shifted by, for tuning slightly off on
+    /// purpose.
+    pub tuning_offset_cents: f32,
+    /// First octave of the intonation keyboard in chromatic mode.
+    pub keyboard_octave: i32,
+    /// First octave of the intonation keyboard for each instrument preset,
+    /// keyed by preset name; presets not listed start at the octave of their
+    /// lowest string.
+    pub preset_keyboard_octaves: BTreeMap<String, i32>,
+    /// Whether the spectrum is drawn behind the needle.
+    pub show_spectrum: bool,
+    /// Whether the diagnostics overlay is shown.
+    pub show_diagnostics: bool,
+    /// Whether the spectrum uses a log frequency axis.
+    pub spectrum_log_scale: bool,
+    /// Whether two or three notes sounding together are named with the
+    /// interval between them.
+    pub identify_intervals: bool,
+    /// Whether the beat rate against the target is shown under the needle.
+    pub show_beats: bool,
+    /// Analysis size in samples; see `analysis_size`.
+    pub fft_size: usize,
+    /// New samples between analyses; see `analysis_hop`.
+    pub hop_size: usize,
+    /// Window applied before the FFT.
+    pub window_kind: WindowKind,
+    /// How finely the FFT peak is located, against CPU.
+    pub precision: Precision,
+    /// Whether bass notes are analysed on a decimated signal.
+    pub low_range: LowRange,
+    /// Mains hum notch applied before detection.
+    pub hum_rejection: HumRejection,
+    /// High-pass cutoff in Hz applied alongside hum rejection; 0 disables it.
+    pub high_pass_cutoff: f32,
+    /// Whether `min_frequency`–`max_frequency` replace the range the
+    /// selected preset implies.
+    pub custom_frequency_range: bool,
+    /// Lowest fundamental searched for with a custom range, in Hz.
+    pub min_frequency: f32,
+    /// Highest fundamental searched for with a custom range, in Hz.
+    pub max_frequency: f32,
+    /// Detections below this are re-estimated in the time domain, in Hz.
+    pub crossover_frequency: f32,
+    /// How note names are spelled.
+    pub note_naming: NoteNaming,
+    /// How the deviation is shown.
+    pub deviation_display: DeviationDisplay,
+    /// How the needle is drawn.
+    pub needle_style: NeedleStyle,
+    /// Cents at either end of the needle's scale.
+    pub needle_range: NeedleRange,
+    /// How quickly the needle follows the reading.
+    pub needle_response: NeedleResponse,
+    /// Seconds of pitch shown by the history graph.
+    pub history_seconds: u64,
+    /// What the history graph plots.
+    pub history_scale: HistoryScale,
+    /// Temperament notes are tuned to.
+    pub temperament: Temperament,
+    /// Pitch class (0 = C) the temperament is built on.
+    pub temperament_tonic: u8,
+    /// Equal divisions of the octave notes snap to; 12 uses the temperament.
+    pub edo: u32,
+    /// Whether notes outside the key are skipped when naming a note.
+    pub key_filter: bool,
+    /// Pitch class (0 = C) of the key's tonic.
+    pub key_tonic: u8,
+    /// Scale of the key.
+    pub key_mode: KeyMode,
+    /// Pitch classes of a custom key.
+    pub key_custom_notes: Vec<u8>,
+    /// Scala scale to tune to instead of the temperament.
+    pub scala_file: Option<PathBuf>,
+    /// Frequency of degree 0 of the Scala scale.
+    pub scala_reference: f32,
+    /// Calibrated magnitude gate for each input device, keyed by device name.
+    pub device_thresholds: BTreeMap<String, f32>,
+    /// Software input gain in dB for each input device, keyed by device name;
+    /// devices without one are left at 0 dB.
+    pub device_gains: BTreeMap<String, f32>,
+    /// Lowest MIDI note the challenge asks for.
+    pub challenge_low_note: i32,
+    /// Highest MIDI note the challenge asks for.
+    pub challenge_high_note: i32,
+    /// Tonic pitch class of the major key challenge targets are limited to;
+    /// `None` allows every note.
+    pub challenge_key: Option<u8>,
+    /// Best challenge round scores, best first.
+    pub challenge_high_scores: Vec<u32>,
+    /// Where recordings are written; defaults to the data directory.
+    pub recordings_dir: Option<PathBuf>,
+    /// Whether detected notes are sent as MIDI.
+    pub midi_enabled: bool,
+    /// MIDI output port to send to.
+    pub midi_port: Option<String>,
+    /// Pitch-bend range of the receiving synth, in semitones either way.
+    pub midi_bend_range: u8,
+    /// Whether detections are sent as OSC.
+    pub osc_enabled: bool,
+    /// `host:port` OSC messages are sent to.
+    pub osc_target: String,
+    /// Whether live readings are served to WebSocket clients.
+    pub websocket_enabled: bool,
+    /// Port the WebSocket server listens on, on every network interface.
+    pub websocket_port: u16,
+    /// Most stable detections kept in the log for export.
+    pub log_capacity: usize,
+    /// Format the detection log is exported in.
+    pub export_format: ExportFormat,
+    /// Ticks per quarter note of an exported MIDI file.
+    pub midi_export_ppq: u16,
+    /// Whether an exported MIDI file bends each note to the logged deviation.
+    pub midi_export_bend: bool,
+    /// Shortest note kept in an exported MIDI file, in milliseconds.
+    pub midi_export_min_note_ms: u64,
+    /// Width and height of the window with every panel closed, in points.
+    pub window_size: [f32; 2],
+    /// Layout the window opens in.
+    pub display_mode: DisplayMode,
+    /// Screen position of the window's top-left corner, once it was moved.
+    pub window_position: Option<[f32; 2]>,
+    /// Whether the window stays above other windows.
+    pub always_on_top: bool,
+    /// Colors used to show how far off the pitch is.
+    pub color_scheme: ColorScheme,
+    /// Light or dark interface.
+    pub theme: UiTheme,
+    /// Whether capture and analysis continue while the window is minimized.
+    pub listen_in_background: bool,
+    /// Whether closing the window hides it in the tray instead of quitting.
+    pub close_to_tray: bool,
+    /// System-wide shortcut that shows or hides the window, e.g.
+    /// "Ctrl+Shift+T"; blank for none.
+    pub toggle_shortcut: String,
+    /// What shows behind the reading in overlay mode.
+    pub overlay_background: OverlayBackground,
+    /// Color of the chroma-key background, as sRGB.
+    pub overlay_key_color: [u8; 3],
+    /// Whether clicks pass through the window to the one beneath in overlay
+    /// mode.
+    pub overlay_click_through: bool,
+}
+
+impl Default for TunerConfig {
+    fn default() -> Self {
+        Self {
+            a4_reference: DEFAULT_A4_FREQUENCY,
+            min_magnitude_threshold: 0.08,
+            min_confidence: 0.5,
+            min_tonality: DEFAULT_MIN_TONALITY,
+            stability_threshold: 3.0,
+            pitch_window_ms: 150,
+            cents_window_ms: 150,
+            smoothing_ms: 50,
+            sustain_fraction: 0.25,
+            quiet_hold_ms: 1500,
+            silence_hold_ms: 2000,
+            relock_grace_ms: 1500,
+            attack_skip_ms: 100,
+            in_tune_cents: 5.0,
+            close_cents: 20.0,
+            show_stability: true,
+            stability_target_cents: 3.0,
+            drone_note: 50,
+            drone_waveform: Waveform::Sine,
+            drone_volume: 0.5,
+            piano_mode: false,
+            piano_stretch: 3.0,
+            metronome_bpm: 100,
+            metronome_beats_per_bar: 4,
+            in_tune_flash: false,
+            in_tune_beep: false,
+            in_tune_dwell_ms: 750,
+            preferred_device: None,
+            follow_default_device: false,
+            buffer_frames: 512,
+            instrument_preset: None,
+            preset_transpose: BTreeMap::new(),
+            tuning_offset_cents: 0.0,
+            keyboard_octave: 3,
+            preset_keyboard_octaves: BTreeMap::new(),
+            show_spectrum: false,
+            show_diagnostics: false,
+            spectrum_log_scale: false,
+            identify_intervals: false,
+            show_beats: true,
+            fft_size: DEFAULT_FFT_SIZE,
+            hop_size: DEFAULT_HOP_SIZE,
+            window_kind: WindowKind::Hann,
+            precision: Precision::Standard,
+            low_range: LowRange::Off,
+            hum_rejection: HumRejection::Off,
+            high_pass_cutoff: 60.0,
+            custom_frequency_range: false,
+            min_frequency: CHROMATIC_RANGE.0,
+            max_frequency: CHROMATIC_RANGE.1,
+            crossover_frequency: DEFAULT_CROSSOVER_FREQUENCY,
+            note_naming: NoteNaming::Sharps,
+            deviation_display: DeviationDisplay::Cents,
+            needle_style: NeedleStyle::Bar,
+            needle_range: NeedleRange::Cents50,
+            needle_response: NeedleResponse::Medium,
+            history_seconds: 20,
+            history_scale: HistoryScale::Cents,
+            temperament: Temperament::Equal,
+            temperament_tonic: 0,
+            edo: 12,
+            key_filter: false,
+            key_tonic: 0,
+            key_mode: KeyMode::Major,
+            key_custom_notes: Vec::new(),
+            scala_file: None,
+            scala_reference: midi_to_frequency(60, DEFAULT_A4_FREQUENCY),
+            device_thresholds: BTreeMap::new(),
+            device_gains: BTreeMap::new(),
+            // A violin in first position, G3 to B5.
+            challenge_low_note: 55,
+            challenge_high_note: 83,
+            challenge_key: None,
+            challenge_high_scores: Vec::new(),
+            recordings_dir: None,
+            midi_enabled: false,
+            midi_port: None,
+            midi_bend_range: 2,
+            osc_enabled: false,
+            osc_target: "127.0.0.1:9000".to_string(),
+            websocket_enabled: false,
+            websocket_port: 8765,
+            log_capacity: 100_000,
+            export_format: ExportFormat::Csv,
+            midi_export_ppq: 480,
+            midi_export_bend: true,
+            midi_export_min_note_ms: 80,
+            window_size: [320.0, 180.0],
+            display_mode: DisplayMode::Standard,
+            window_position: None,
+            always_on_top: true,
+            color_scheme: ColorScheme::Standard,
+            theme: UiTheme::Dark,
+            listen_in_background: false,
+            close_to_tray: false,
+            toggle_shortcut: "Ctrl+Shift+T".to_string(),
+            overlay_background: OverlayBackground::ChromaKey,
+            overlay_key_color: [0, 255, 0],
+            overlay_click_through: false,
+        }
+    }
+}
+
+impl TunerConfig {
+    /// Where the configuration file lives on this platform.
+    pub fn config_path() -> Option<PathBuf> {
+        ProjectDirs::from("com", "cinbarker", "chroma-tuner")
+            .map(|dirs| dirs.config_dir().join(CONFIG_FILE_NAME))
+    }
+
+    /// A fresh timestamped file in the chosen recordings directory.
+    pub fn recording_path(&self, now: SystemTime) -> Option<PathBuf> {
+        self.timestamped_path("recording", "wav", now)
+    }
+
+    /// Where the detection log is exported unless a path is given, next to
+    /// the recordings.
+    pub fn log_export_path(&self, format: ExportFormat, now: SystemTime) -> Option<PathBuf> {
+        self.timestamped_path("detections", format.extension(), now)
+    }
+
+    /// How the detection log is turned into a MIDI file, bending notes over
+    /// the same range as MIDI output.
+    pub fn midi_export_options(&self) -> MidiExportOptions {
+        MidiExportOptions {
+            ppq: self.midi_export_ppq.max(1),
+            pitch_bend: self.midi_export_bend,
+            bend_range: self.midi_bend_range,
+            min_duration: Duration::from_millis(self.midi_export_min_note_ms),
+        }
+    }
+
+    fn timestamped_path(&self, prefix: &str, extension: &str, now: SystemTime) -> Option<PathBuf> {
+        let dir = self.recordings_dir.clone().or_else(|| {
+            ProjectDirs::from("com", "cinbarker", "chroma-tuner")
+                .map(|dirs| dirs.data_dir().join(RECORDINGS_DIR_NAME))
+        })?;
+        let timestamp = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+        Some(dir.join(format!("{}-{}.{}", prefix, timestamp.as_secs(), extension)))
+    }
+
+    /// Loads the saved configuration, or the defaults if there is none.
+    pub fn load() -> Self {
+        let Some(path) = Self::config_path() else {
+            return Self::default();
+        };
+
+        if !path.exists() {
+            return Self::default();
+        }
+
+        Self::load_from(&path).unwrap_or_else(|err| {
+            log::warn!("Failed to load config from {}: {}", path.display(), err);
+            Self::default()
+        })
+    }
+
+    /// The configured analysis size, falling back to the default if the file
+    /// holds a size the detector doesn't offer.
+    pub fn analysis_size(&self) -> usize {
+        if FFT_SIZES.contains(&self.fft_size) {
+            self.fft_size
+        } else {
+            DEFAULT_FFT_SIZE
+        }
+    }
+
+    /// How note names are spelled: as set, but with the key's accidentals
+    /// when snapping to a key.
+    pub fn spelling(&self) -> NoteNaming {
+        self.key()
+            .map_or(self.note_naming, |key| key.naming(self.note_naming))
+    }
+
+    /// The configured divisions of the octave, falling back to 12 if the
+    /// file holds a number the tuner doesn't offer.
+    pub fn octave_divisions(&self) -> u32 {
+        if EDO_RANGE.contains(&self.edo) {
+            self.edo
+        } else {
+            12
+        }
+    }
+
+    /// The key notes snap to, if the filter is on.
+    pub fn key(&self) -> Option<Key> {
+        self.key_filter.then(|| Key {
+            tonic: self.key_tonic % 12,
+            mode: self.key_mode,
+            custom: PitchClassSet::from_pitch_classes(self.key_custom_notes.iter().copied()),
+        })
+    }
+
+    /// The configured hop, falling back to the default if the file holds
+    /// one the tuner doesn't offer.
+    pub fn analysis_hop(&self) -> usize {
+        if HOP_SIZES.contains(&self.hop_size) {
+            self.hop_size
+        } else {
+            DEFAULT_HOP_SIZE
+        }
+    }
+
+    /// Loads a configuration from `path`.
+    pub fn load_from(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        Ok(toml::from_str(&contents)?)
+    }
+
+    /// Saves to the platform configuration file.
+    pub fn save(&self) -> Result<()> {
+        let path =
+            Self::config_path().ok_or_else(|| anyhow::anyhow!("No config directory available"))?;
+        self.save_to(&path)
+    }
+
+    /// Saves to `path`, creating its directory if needed.
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_round_trip() {
+        let config = TunerConfig {
+            a4_reference: 442.0,
+            min_magnitude_threshold: 0.2,
+            min_confidence: 0.65,
+            min_tonality: 0.55,
+            stability_threshold: 4.5,
+            pitch_window_ms: 250,
+            cents_window_ms: 100,
+            smoothing_ms: 80,
+            sustain_fraction: 0.4,
+            quiet_hold_ms: 800,
+            silence_hold_ms: 1200,
+            relock_grace_ms: 900,
+            attack_skip_ms: 80,
+            in_tune_cents: 3.0,
+            close_cents: 12.0,
+            show_stability: false,
+            stability_target_cents: 1.5,
+            drone_note: 45,
+            drone_waveform: Waveform::Organ,
+            drone_volume: 0.8,
+            piano_mode: true,
+            piano_stretch: 4.5,
+            metronome_bpm: 137,
+            metronome_beats_per_bar: 3,
+            in_tune_flash: true,
+            in_tune_beep: true,
+            in_tune_dwell_ms: 400,
+            preferred_device: Some("Scarlett 2i2 USB".to_string()),
+            follow_default_device: true,
+            buffer_frames: 256,
+            instrument_preset: Some("Guitar (Drop D)".to_string()),
+            preset_transpose: BTreeMap::from([
+                ("Guitar".to_string(), 2),
+                ("Bass (4-string)".to_string(), -1),
+            ]),
+            tuning_offset_cents: -7.5,
+            keyboard_octave: 2,
+            preset_keyboard_octaves: BTreeMap::from([("Cello".to_string(), 1)]),
+            show_spectrum: true,
+            show_diagnostics: true,
+            spectrum_log_scale: true,
+            identify_intervals: true,
+            show_beats: false,
+            fft_size: 16384,
+            hop_size: 512,
+            window_kind: WindowKind::BlackmanHarris,
+            precision: Precision::Maximum,
+            low_range: LowRange::Auto,
+            hum_rejection: HumRejection::Mains60,
+            high_pass_cutoff: 45.0,
+            custom_frequency_range: true,
+            min_frequency: 180.0,
+            max_frequency: 4000.0,
+            crossover_frequency: 200.0,
+            note_naming: NoteNaming::German,
+            deviation_display: DeviationDisplay::Both,
+            needle_style: NeedleStyle::Gauge,
+            needle_range: NeedleRange::Auto,
+            needle_response: NeedleResponse::Slow,
+            history_seconds: 30,
+            history_scale: HistoryScale::Hz,
+            temperament: Temperament::WerckmeisterIII,
+            temperament_tonic: 3,
+            edo: 24,
+            key_filter: true,
+            key_tonic: 10,
+            key_mode: KeyMode::Custom,
+            key_custom_notes: vec![0, 4, 7],
+            scala_file: Some(PathBuf::from("/home/user/scales/meanquar.scl")),
+            scala_reference: 293.66,
+            device_thresholds: BTreeMap::from([
+                ("MacBook Pro Microphone".to_string(), 0.021),
+                ("Scarlett 2i2 USB".to_string(), 0.004),
+            ]),
+            device_gains: BTreeMap::from([("Scarlett 2i2 USB".to_string(), 18.0)]),
+            challenge_low_note: 43,
+            challenge_high_note: 67,
+            challenge_key: Some(7),
+            challenge_high_scores: vec![870, 640],
+            recordings_dir: Some(PathBuf::from("/home/user/takes")),
+            midi_enabled: true,
+            midi_port: Some("IAC Driver Bus 1".to_string()),
+            midi_bend_range: 12,
+            osc_enabled: true,
+            osc_target: "192.168.1.20:57120".to_string(),
+            websocket_enabled: true,
+            websocket_port: 9090,
+            log_capacity: 5_000,
+            export_format: ExportFormat::Json,
+            midi_export_ppq: 960,
+            midi_export_bend: false,
+            midi_export_min_note_ms: 150,
+            window_size: [640.0, 360.0],
+            display_mode: DisplayMode::Performance,
+            window_position: Some([-1280.0, 24.0]),
+            always_on_top: false,
+            color_scheme: ColorScheme::HighContrast,
+            theme: UiTheme::System,
+            listen_in_background: true,
+            close_to_tray: true,
+            toggle_shortcut: "Alt+F9".to_string(),
+            overlay_background: OverlayBackground::Transparent,
+            overlay_key_color: [255, 0, 255],
+            overlay_click_through: true,
+        };
+
+        let serialized = toml::to_string_pretty(&config).unwrap();
+        let deserialized: TunerConfig = toml::from_str(&serialized).unwrap();
+        assert_eq!(config, deserialized);
+
+        let path = std::env::temp_dir()
+            .join(format!("chroma-tuner-config-test-{}", std::process::id()))
+            .join(CONFIG_FILE_NAME);
+        config.save_to(&path).unwrap();
+        assert_eq!(TunerConfig::load_from(&path).unwrap(), config);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_missing_fields_use_defaults() {
+        let config: TunerConfig = toml::from_str("a4_reference = 415.0").unwrap();
+        assert_eq!(config.a4_reference, 415.0);
+        assert_eq!(
+            config.pitch_window_ms,
+            TunerConfig::default().pitch_window_ms
+        );
+        assert_eq!(config.analysis_size(), DEFAULT_FFT_SIZE);
+
+        let config: TunerConfig = toml::from_str("fft_size = 1000").unwrap();
+        assert_eq!(config.analysis_size(), DEFAULT_FFT_SIZE);
+        let config: TunerConfig = toml::from_str("hop_size = 100").unwrap();
+        assert_eq!(config.analysis_hop(), DEFAULT_HOP_SIZE);
+        let config: TunerConfig = toml::from_str("edo = 0").unwrap();
+        assert_eq!(config.octave_divisions(), 12);
+    }
+
+    #[test]
+    fn test_recording_path_is_timestamped() {
+        let config = TunerConfig {
+            recordings_dir: Some(PathBuf::from("/home/user/takes")),
+            ..TunerConfig::default()
+        };
+        let now = UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+        assert_eq!(
+            config.recording_path(now),
+            Some(PathBuf::from("/home/user/takes/recording-1700000000.wav"))
+        );
+        assert_eq!(
+            config.log_export_path(ExportFormat::Json, now),
+            Some(PathBuf::from("/home/user/takes/detections-1700000000.json"))
+        );
+    }
+}
@@ -0,0 +1,431 @@
+//! Log of stable detections and its CSV/JSON/MIDI export
+//!
+//! Keeps the most recent stable readings in a capped buffer, with wall-clock
+//! timestamps, and writes them out as CSV or JSON so a session can be handed to
+//! a teacher or analysed elsewhere, or as a MIDI file to review in a DAW.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::io::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::midi_file::{write_midi, MidiExportOptions};
+use crate::pitch::{Note, NoteNaming};
+use crate::tracker::PitchReading;
+
+/// One stable reading in the log.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoggedDetection {
+    /// When the reading was shown.
+    pub timestamp: SystemTime,
+    /// The note shown, with the smoothed deviation as `cents_off`.
+    pub note: Note,
+    /// Magnitude of the detection behind the reading.
+    pub magnitude: f32,
+}
+
+/// The most recent stable readings, oldest first. Once `capacity` entries are
+/// held, each new one drops the oldest.
+#[derive(Debug)]
+pub struct DetectionLog {
+    entries: VecDeque<LoggedDetection>,
+    capacity: usize,
+    last_reading: Option<(f32, f32)>,
+}
+
+impl DetectionLog {
+    /// An empty log holding at most `capacity` entries.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::new(),
+            capacity: capacity.max(1),
+            last_reading: None,
+        }
+    }
+
+    /// Changes the cap, dropping the oldest entries if there are too many.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity.max(1);
+        let excess = self.entries.len().saturating_sub(self.capacity);
+        self.entries.drain(..excess);
+    }
+
+    /// Most entries the log holds.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Logs the reading shown at `now` if it holds a note the tracker has
+    /// updated since the last call; frames repeating it are skipped.
+    pub fn record(&mut self, reading: &PitchReading, now: SystemTime) {
+        let Some(note) = &reading.note else {
+            self.last_reading = None;
+            return;
+        };
+        let key = (note.frequency, reading.smoothed_cents);
+        if self.last_reading == Some(key) {
+            return;
+        }
+        self.last_reading = Some(key);
+
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(LoggedDetection {
+            timestamp: now,
+            note: Note {
+                cents_off: reading.smoothed_cents,
+                ..note.clone()
+            },
+            magnitude: reading.magnitude,
+        });
+    }
+
+    /// Number of entries held.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether nothing has been logged.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The entries, oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = &LoggedDetection> {
+        self.entries.iter()
+    }
+
+    /// Forgets every entry.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.last_reading = None;
+    }
+}
+
+/// File format of an exported log.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExportFormat {
+    /// One row per entry with a header line.
+    #[default]
+    Csv,
+    /// An array of objects, one per entry.
+    Json,
+    /// A standard MIDI file with a note per run of readings on the same note.
+    Midi,
+}
+
+impl ExportFormat {
+    /// Every format, in menu order.
+    pub const ALL: [ExportFormat; 3] = [ExportFormat::Csv, ExportFormat::Json, ExportFormat::Midi];
+
+    /// Name shown in the format menu.
+    pub fn label(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "CSV",
+            ExportFormat::Json => "JSON",
+            ExportFormat::Midi => "MIDI",
+        }
+    }
+
+    /// The format named `name` by its label or extension in any case, e.g.
+    /// "csv" or "MIDI".
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|format| {
+            name.eq_ignore_ascii_case(format.label())
+                || name.eq_ignore_ascii_case(format.extension())
+        })
+    }
+
+    /// File extension, without the dot.
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "json",
+            ExportFormat::Midi => "mid",
+        }
+    }
+}
+
+fn unix_seconds(timestamp: SystemTime) -> f64 {
+    timestamp
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
+
+/// Quotes a CSV field if it contains a separator, quote or line break.
+pub(crate) fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// A JSON string literal. Non-ASCII text such as ♯ and ♭ is written as UTF-8.
+pub(crate) fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// JSON has no NaN or infinity, so those become `null`.
+pub(crate) fn json_number(value: f32, decimals: usize) -> String {
+    if value.is_finite() {
+        format!("{:.*}", decimals, value)
+    } else {
+        "null".to_string()
+    }
+}
+
+/// Writes `entries` in `format`, spelling note names with `naming` and
+/// building a MIDI file as `midi` says.
+pub fn write_log<'a>(
+    entries: impl IntoIterator<Item = &'a LoggedDetection>,
+    format: ExportFormat,
+    naming: NoteNaming,
+    midi: &MidiExportOptions,
+    out: &mut impl Write,
+) -> std::io::Result<()> {
+    match format {
+        ExportFormat::Csv => {
+            writeln!(out, "timestamp,note,frequency_hz,cents,magnitude")?;
+            for entry in entries {
+                writeln!(
+                    out,
+                    "{:.3},{},{:.2},{:.1},{:.4}",
+                    unix_seconds(entry.timestamp),
+                    csv_field(&entry.note.display_name(naming)),
+                    entry.note.frequency,
+                    entry.note.cents_off,
+                    entry.magnitude
+                )?;
+            }
+        }
+        ExportFormat::Json => {
+            write!(out, "[")?;
+            for (index, entry) in entries.into_iter().enumerate() {
+                let separator = if index == 0 { "" } else { "," };
+                write!(
+                    out,
+                    "{}\n  {{\"timestamp\": {:.3}, \"note\": {}, \"frequency_hz\": {}, \"cents\": {}, \"magnitude\": {}}}",
+                    separator,
+                    unix_seconds(entry.timestamp),
+                    json_string(&entry.note.display_name(naming)),
+                    json_number(entry.note.frequency, 2),
+                    json_number(entry.note.cents_off, 1),
+                    json_number(entry.magnitude, 4)
+                )?;
+            }
+            writeln!(out, "\n]")?;
+        }
+        ExportFormat::Midi => {
+            let entries: Vec<LoggedDetection> = entries.into_iter().cloned().collect();
+            write_midi(&entries, midi, out)?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes `entries` to a file at `path`, creating its directory.
+pub fn export_log(
+    entries: &[LoggedDetection],
+    format: ExportFormat,
+    naming: NoteNaming,
+    midi: &MidiExportOptions,
+    path: &Path,
+) -> Result<()> {
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    let file = std::fs::File::create(path)
+        .with_context(|| format!("Failed to create {}", path.display()))?;
+    let mut out = std::io::BufWriter::new(file);
+    write_log(entries, format, naming, midi, &mut out)
+        .and_then(|()| out.flush())
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pitch::{frequency_to_note, DEFAULT_A4_FREQUENCY};
+    use std::time::Duration;
+
+    fn reading(frequency: f32, cents: f32) -> PitchReading {
+        PitchReading {
+            note: frequency_to_note(frequency, DEFAULT_A4_FREQUENCY),
+            smoothed_cents: cents,
+            magnitude: 0.5,
+            ..PitchReading::default()
+        }
+    }
+
+    fn at(seconds: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(1_700_000_000 + seconds)
+    }
+
+    #[test]
+    fn test_log_rolls_over_at_capacity() {
+        let mut log = DetectionLog::new(3);
+        for index in 0..5 {
+            log.record(&reading(440.0 + index as f32, index as f32), at(index));
+            // Repeated frames of the same reading are logged once.
+            log.record(&reading(440.0 + index as f32, index as f32), at(index));
+        }
+        assert_eq!(log.len(), 3);
+        let first: Vec<f32> = log.iter().map(|entry| entry.note.cents_off).collect();
+        assert_eq!(first, vec![2.0, 3.0, 4.0]);
+
+        // Silence is not logged, and the same reading after it counts again.
+        log.record(&PitchReading::default(), at(5));
+        log.record(&reading(444.0, 4.0), at(6));
+        assert_eq!(log.iter().last().unwrap().timestamp, at(6));
+
+        log.set_capacity(2);
+        assert_eq!(log.len(), 2);
+        assert_eq!(log.iter().next().unwrap().note.cents_off, 4.0);
+
+        log.clear();
+        assert!(log.is_empty());
+        assert_eq!(log.capacity(), 2);
+    }
+
+    #[test]
+    fn test_csv_export_escapes_and_keeps_accidentals() {
+        let mut log = DetectionLog::new(10);
+        log.record(&reading(466.16, -3.0), at(0));
+        log.record(&reading(277.18, 8.3), at(1));
+
+        let mut csv = Vec::new();
+        write_log(
+            log.iter(),
+            ExportFormat::Csv,
+            NoteNaming::Flats,
+            &MidiExportOptions::default(),
+            &mut csv,
+        )
+        .unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(
+            csv,
+            "timestamp,note,frequency_hz,cents,magnitude\n\
+             1700000000.000,B♭4,466.16,-3.0,0.5000\n\
+             1700000001.000,D♭4,277.18,8.3,0.5000\n"
+        );
+
+        assert_eq!(ExportFormat::from_name("csv"), Some(ExportFormat::Csv));
+        assert_eq!(ExportFormat::from_name("Mid"), Some(ExportFormat::Midi));
+        assert_eq!(ExportFormat::from_name("MIDI"), Some(ExportFormat::Midi));
+        assert_eq!(ExportFormat::from_name("xml"), None);
+
+        assert_eq!(csv_field("C♯4"), "C♯4");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"A\""), "\"say \"\"A\"\"\"");
+    }
+
+    #[test]
+    fn test_json_export_escapes_strings() {
+        let mut log = DetectionLog::new(10);
+        log.record(&reading(466.16, -3.0), at(0));
+        log.record(&reading(440.0, 0.0), at(1));
+
+        let mut json = Vec::new();
+        write_log(
+            log.iter(),
+            ExportFormat::Json,
+            NoteNaming::Flats,
+            &MidiExportOptions::default(),
+            &mut json,
+        )
+        .unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert_eq!(
+            json,
+            "[\n  \
+             {\"timestamp\": 1700000000.000, \"note\": \"B♭4\", \"frequency_hz\": 466.16, \"cents\": -3.0, \"magnitude\": 0.5000},\n  \
+             {\"timestamp\": 1700000001.000, \"note\": \"A4\", \"frequency_hz\": 440.00, \"cents\": 0.0, \"magnitude\": 0.5000}\n]\n"
+        );
+
+        let mut empty = Vec::new();
+        write_log(
+            &[],
+            ExportFormat::Json,
+            NoteNaming::Sharps,
+            &MidiExportOptions::default(),
+            &mut empty,
+        )
+        .unwrap();
+        assert_eq!(String::from_utf8(empty).unwrap(), "[\n]\n");
+
+        assert_eq!(json_string("C♯4"), "\"C♯4\"");
+        assert_eq!(json_string("a\"b\\c\nd\u{1}"), "\"a\\\"b\\\\c\\nd\\u0001\"");
+        assert_eq!(json_number(f32::NAN, 2), "null");
+    }
+
+    #[test]
+    fn test_export_writes_file() {
+        let mut log = DetectionLog::new(10);
+        log.record(&reading(440.0, 0.0), at(0));
+        let entries: Vec<LoggedDetection> = log.iter().cloned().collect();
+
+        let dir = std::env::temp_dir().join(format!("chroma-tuner-log-{}", std::process::id()));
+        let path = dir.join("session.json");
+        export_log(
+            &entries,
+            ExportFormat::Json,
+            NoteNaming::Sharps,
+            &MidiExportOptions::default(),
+            &path,
+        )
+        .unwrap();
+        let written = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+        assert!(written.contains("\"note\": \"A4\""));
+
+        let mut midi = Vec::new();
+        let options = MidiExportOptions::default();
+        write_log(
+            &entries,
+            ExportFormat::Midi,
+            NoteNaming::Sharps,
+            &options,
+            &mut midi,
+        )
+        .unwrap();
+        assert!(midi.starts_with(b"MThd"));
+
+        let blocker =
+            std::env::temp_dir().join(format!("chroma-tuner-log-blocker-{}", std::process::id()));
+        std::fs::write(&blocker, b"not a directory").unwrap();
+        let error = export_log(
+            &entries,
+            ExportFormat::Csv,
+            NoteNaming::Sharps,
+            &MidiExportOptions::default(),
+            &blocker.join("session.csv"),
+        )
+        .unwrap_err();
+        let _ = std::fs::remove_file(&blocker);
+        assert!(format!("{:#}", error).starts_with("Failed to create"));
+    }
+}
@@ -0,0 +1,109 @@
+//! System-wide shortcut to show or hide the window
+//!
+//! global-hotkey delivers presses on its own handler, even while another
+//! application is focused or fullscreen, which forwards them over a channel
+//! the app polls each frame, waking the window so that happens even while it
+//! is hidden.
+
+use std::sync::mpsc::{self, Receiver};
+
+use anyhow::{Context, Result};
+use eframe::egui;
+use global_hotkey::hotkey::HotKey;
+use global_hotkey::{GlobalHotKeyEvent, GlobalHotKeyManager, HotKeyState};
+
+/// The shortcut written as `text`, e.g. "Ctrl+Shift+T", or `None` if it is
+/// blank.
+pub fn parse_shortcut(text: &str) -> Result<Option<HotKey>> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(None);
+    }
+    let hotkey = text
+        .parse()
+        .with_context(|| format!("'{}' is not a key combination", text))?;
+    Ok(Some(hotkey))
+}
+
+/// The registered shortcut, held for as long as this is alive.
+pub struct GlobalShortcut {
+    manager: GlobalHotKeyManager,
+    registered: Option<HotKey>,
+    presses: Receiver<u32>,
+}
+
+impl GlobalShortcut {
+    /// Starts listening for shortcuts, with none registered yet. On macOS
+    /// this must run on the main thread, as the eframe app creator does.
+    pub fn new(ctx: &egui::Context) -> Result<Self> {
+        let manager = GlobalHotKeyManager::new()?;
+        let (sender, presses) = mpsc::channel();
+        let ctx = ctx.clone();
+        GlobalHotKeyEvent::set_event_handler(Some(move |event: GlobalHotKeyEvent| {
+            if event.state() == HotKeyState::Pressed {
+                let _ = sender.send(event.id());
+                ctx.request_repaint();
+            }
+        }));
+        Ok(Self {
+            manager,
+            registered: None,
+            presses,
+        })
+    }
+
+    /// Replaces the shortcut with the one written as `text`, or removes it if
+    /// `text` is blank. The old one stays if the new one can't be had, say
+    /// because another application holds it.
+    pub fn set(&mut self, text: &str) -> Result<()> {
+        let hotkey = parse_shortcut(text)?;
+        if hotkey == self.registered {
+            return Ok(());
+        }
+        if let Some(hotkey) = hotkey {
+            self.manager
+                .register(hotkey)
+                .context("Another application may already use it")?;
+        }
+        if let Some(old) = self.registered.take() {
+            if let Err(err) = self.manager.unregister(old) {
+                log::warn!("Failed to release the old shortcut: {}", err);
+            }
+        }
+        self.registered = hotkey;
+        Ok(())
+    }
+
+    /// Whether the shortcut was pressed since the last call.
+    pub fn try_recv(&self) -> bool {
+        let mut pressed = false;
+        while let Ok(id) = self.presses.try_recv() {
+            pressed |= self.registered.is_some_and(|hotkey| hotkey.id() == id);
+        }
+        pressed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use global_hotkey::hotkey::{Code, Modifiers};
+
+    #[test]
+    fn test_shortcuts_parse_from_text() {
+        assert_eq!(
+            parse_shortcut(" Ctrl+Shift+T ").unwrap(),
+            Some(HotKey::new(
+                Some(Modifiers::CONTROL | Modifiers::SHIFT),
+                Code::KeyT
+            ))
+        );
+        assert_eq!(
+            parse_shortcut("alt+F9").unwrap(),
+            Some(HotKey::new(Some(Modifiers::ALT), Code::F9))
+        );
+        assert_eq!(parse_shortcut("").unwrap(), None);
+        assert!(parse_shortcut("Ctrl+Shift+").is_err());
+        assert!(parse_shortcut("Ctrl+T+Shift").is_err());
+    }
+}
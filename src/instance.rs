@@ -0,0 +1,305 @@
+//! Single-instance guard
+//!
+//! The first launch listens on a local socket, whose address is its lock: a
+//! Unix domain socket in the runtime directory, or elsewhere a loopback port
+//! recorded in a file there. A later launch finds it taken, asks the first to
+//! raise its window and exits instead of opening the microphone a second
+//! time. Requests arrive on a listener thread, which forwards them over a
+//! channel the app polls each frame, waking the window so that happens even
+//! while it is hidden.
+
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::Duration;
+
+use directories::ProjectDirs;
+
+/// Sent by a later launch to ask the running one to come to the front.
+const RAISE_REQUEST: &[u8] = b"chroma-tuner raise\n";
+/// Sent back once the request is queued, so the later launch knows it was
+/// heard and not, say, a stale port now used by something else.
+const RAISE_REPLY: &[u8] = b"chroma-tuner ok\n";
+/// How long either side waits on the other.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(1);
+/// Longest message read, so a stray client can't make the listener buffer
+/// without end.
+const MAX_MESSAGE: u64 = 64;
+
+#[cfg(unix)]
+type Listener = std::os::unix::net::UnixListener;
+#[cfg(unix)]
+type Stream = std::os::unix::net::UnixStream;
+#[cfg(not(unix))]
+type Listener = std::net::TcpListener;
+#[cfg(not(unix))]
+type Stream = std::net::TcpStream;
+
+/// Where the lock lives: the per-user runtime directory, or the temporary
+/// directory where there is none.
+pub fn default_path() -> PathBuf {
+    let dir = ProjectDirs::from("com", "cinbarker", "chroma-tuner")
+        .and_then(|dirs| dirs.runtime_dir().map(Path::to_path_buf))
+        .unwrap_or_else(std::env::temp_dir);
+    let name = if cfg!(unix) {
+        "chroma-tuner.sock"
+    } else {
+        "chroma-tuner.port"
+    };
+    dir.join(name)
+}
+
+#[cfg(unix)]
+fn bind(path: &Path) -> io::Result<Listener> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    Listener::bind(path)
+}
+
+/// Records the port in a file of its own first, then links that to `path`,
+/// so a later launch never finds the lock without a port in it. Unlike a
+/// rename, the link fails if the lock is already there.
+#[cfg(not(unix))]
+fn bind(path: &Path) -> io::Result<Listener> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let listener = Listener::bind((std::net::Ipv4Addr::LOCALHOST, 0))?;
+    let port = listener.local_addr()?.port();
+    let pending = path.with_extension(format!("{}.tmp", std::process::id()));
+    fs::write(&pending, port.to_string())?;
+    let linked = fs::hard_link(&pending, path);
+    let _ = fs::remove_file(&pending);
+    linked?;
+    Ok(listener)
+}
+
+#[cfg(unix)]
+fn connect(path: &Path) -> io::Result<Stream> {
+    Stream::connect(path)
+}
+
+#[cfg(not(unix))]
+fn connect(path: &Path) -> io::Result<Stream> {
+    let port: u16 = fs::read_to_string(path)?
+        .trim()
+        .parse()
+        .map_err(|_| io::Error::from(io::ErrorKind::InvalidData))?;
+    Stream::connect_timeout(
+        &(std::net::Ipv4Addr::LOCALHOST, port).into(),
+        HANDSHAKE_TIMEOUT,
+    )
+}
+
+/// Whether `err` from [`raise`] means nothing is listening at the lock, left
+/// behind by a launch that crashed.
+fn is_stale(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::ConnectionRefused | io::ErrorKind::NotFound | io::ErrorKind::InvalidData
+    )
+}
+
+/// Reads one newline-terminated message of at most [`MAX_MESSAGE`] bytes.
+fn read_message(stream: &Stream) -> io::Result<Vec<u8>> {
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    let mut message = Vec::new();
+    BufReader::new(stream.take(MAX_MESSAGE)).read_until(b'\n', &mut message)?;
+    Ok(message)
+}
+
+/// Asks the instance holding the lock at `path` to raise its window, and
+/// waits for it to confirm.
+fn raise(path: &Path) -> io::Result<()> {
+    let mut stream = connect(path)?;
+    stream.set_write_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    stream.write_all(RAISE_REQUEST)?;
+    if read_message(&stream)? == RAISE_REPLY {
+        Ok(())
+    } else {
+        Err(io::ErrorKind::InvalidData.into())
+    }
+}
+
+/// Removes the lock file when the instance holding it goes away.
+#[derive(Debug)]
+struct LockFile(PathBuf);
+
+impl Drop for LockFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// The lock held by the one running instance, not yet listening.
+#[derive(Debug)]
+pub struct InstanceLock {
+    listener: Listener,
+    file: LockFile,
+}
+
+impl InstanceLock {
+    /// Takes the lock at `path`, or `None` if another instance holds it and
+    /// was asked to raise its window. A lock left by a crashed launch is
+    /// taken over.
+    pub fn acquire(path: &Path) -> io::Result<Option<Self>> {
+        let listener = match bind(path) {
+            Ok(listener) => listener,
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::AddrInUse | io::ErrorKind::AlreadyExists
+                ) =>
+            {
+                match raise(path) {
+                    Ok(()) => return Ok(None),
+                    Err(err) if is_stale(&err) => {
+                        log::info!("Taking over the lock of an instance that is gone");
+                        fs::remove_file(path)?;
+                        bind(path)?
+                    }
+                    Err(err) => return Err(err),
+                }
+            }
+            Err(err) => return Err(err),
+        };
+        Ok(Some(Self {
+            listener,
+            file: LockFile(path.to_path_buf()),
+        }))
+    }
+
+    /// Answers later launches from now on, calling `wake` after queueing
+    /// each request to raise the window.
+    pub fn listen(self, wake: impl Fn() + Send + 'static) -> RaiseRequests {
+        let (sender, requests) = mpsc::channel();
+        let listener = self.listener;
+        let spawned = std::thread::Builder::new()
+            .name("instance-listener".into())
+            .spawn(move || serve(listener, sender, wake));
+        if let Err(err) = spawned {
+            log::warn!("Failed to listen for other launches: {}", err);
+        }
+        RaiseRequests {
+            requests,
+            _file: self.file,
+        }
+    }
+}
+
+fn serve(listener: Listener, sender: Sender<()>, wake: impl Fn()) {
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                log::warn!("Failed to accept another launch: {}", err);
+                continue;
+            }
+        };
+        match read_message(&stream) {
+            Ok(message) if message == RAISE_REQUEST => {
+                if sender.send(()).is_err() {
+                    return;
+                }
+                wake();
+                if let Err(err) = stream.write_all(RAISE_REPLY) {
+                    log::debug!("Failed to answer another launch: {}", err);
+                }
+            }
+            Ok(_) => log::warn!("Ignoring an unexpected message from another launch"),
+            Err(err) => log::warn!("Failed to read from another launch: {}", err),
+        }
+    }
+}
+
+/// Requests from later launches to raise the window, holding the lock for as
+/// long as this is alive.
+#[derive(Debug)]
+pub struct RaiseRequests {
+    requests: Receiver<()>,
+    _file: LockFile,
+}
+
+impl RaiseRequests {
+    /// Whether another launch asked to raise the window since the last call.
+    pub fn try_recv(&self) -> bool {
+        let mut raised = false;
+        while self.requests.try_recv().is_ok() {
+            raised = true;
+        }
+        raised
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn lock_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "chroma-tuner-test-{}-{}.lock",
+            std::process::id(),
+            name
+        ));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_second_launch_raises_the_first() {
+        let path = lock_path("raise");
+        let wakes = Arc::new(AtomicUsize::new(0));
+        let requests = {
+            let wakes = wakes.clone();
+            InstanceLock::acquire(&path)
+                .unwrap()
+                .unwrap()
+                .listen(move || {
+                    wakes.fetch_add(1, Ordering::SeqCst);
+                })
+        };
+        assert!(!requests.try_recv());
+
+        let second = {
+            let path = path.clone();
+            std::thread::spawn(move || InstanceLock::acquire(&path).unwrap().is_none())
+        };
+        assert!(second.join().unwrap());
+        assert!(requests.try_recv());
+        assert!(!requests.try_recv());
+        assert_eq!(wakes.load(Ordering::SeqCst), 1);
+
+        // Anything else is answered with nothing and raises nothing.
+        let stream = connect(&path).unwrap();
+        (&stream).write_all(b"hello\n").unwrap();
+        assert_eq!(read_message(&stream).unwrap(), b"");
+        assert!(!requests.try_recv());
+
+        // Quitting frees the lock for the next launch.
+        drop(requests);
+        assert!(!path.exists());
+        assert!(InstanceLock::acquire(&path).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_lock_of_a_crashed_launch_is_taken_over() {
+        let path = lock_path("stale");
+        // Bound and closed without cleaning up, as after a crash.
+        drop(bind(&path).unwrap());
+        assert!(path.exists());
+
+        let lock = std::thread::spawn({
+            let path = path.clone();
+            move || InstanceLock::acquire(&path).unwrap()
+        })
+        .join()
+        .unwrap();
+        let requests = lock.unwrap().listen(|| {});
+        assert!(InstanceLock::acquire(&path).unwrap().is_none());
+        assert!(requests.try_recv());
+    }
+}
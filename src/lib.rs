@@ -0,0 +1,60 @@
+//! Chroma Tuner's pitch detection engine
+//!
+//! The DSP core — FFT/YIN pitch detection, note conversion, temperaments,
+//! instrument presets, Scala scales and the note tracker that smooths raw
+//! detections into a stable reading — builds without any GUI or audio hardware
+//! dependencies:
+//!
+//! ```no_run
+//! use chroma_tuner::pitch::{NoteNaming, WindowKind};
+//! use chroma_tuner::{frequency_to_note, PitchDetector};
+//!
+//! let samples = vec![0.0; 8192];
+//! let mut detector = PitchDetector::new(samples.len(), 44100.0, WindowKind::Hann);
+//! let estimate = detector.detect_pitch(&samples);
+//! if let Some(note) = estimate.and_then(|estimate| frequency_to_note(estimate.frequency, 440.0)) {
+//!     println!("{} {:+.1} cents", note.display_name(NoteNaming::Sharps), note.cents_off);
+//! }
+//! ```
+//!
+//! The `capture` feature adds cpal input devices and the reference tone, and
+//! `gui` adds the egui application along with MIDI, OSC and WebSocket output.
+//! Both are on by default.
+
+#![warn(missing_docs)]
+
+#[cfg(test)]
+mod alloc_counter;
+pub mod audio;
+pub mod config;
+pub mod detection_log;
+#[cfg(feature = "gui")]
+pub mod hotkey;
+pub mod instance;
+pub mod metronome;
+#[cfg(feature = "gui")]
+pub mod midi;
+pub mod midi_file;
+pub mod offline;
+#[cfg(feature = "gui")]
+pub mod osc;
+#[cfg(feature = "gui")]
+pub mod palette;
+pub mod pitch;
+pub mod presets;
+pub mod scala;
+pub mod self_test;
+pub mod stats;
+#[cfg(feature = "gui")]
+pub mod theme;
+pub mod tracker;
+#[cfg(feature = "gui")]
+pub mod tray;
+#[cfg(feature = "gui")]
+pub mod tuner;
+#[cfg(feature = "gui")]
+pub mod websocket;
+
+pub use audio::{AudioData, AudioSource};
+pub use pitch::{frequency_to_note, Note, PitchDetector, PitchEstimate};
+pub use tracker::{AnalysisSettings, PitchReading, PitchTracker, TrackerOutput};
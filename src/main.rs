@@ -1,18 +1,26 @@
 //! Application entry point for Chroma Tuner
 //!
-//! Sets up the eframe window with native styling, initializes audio capture,
-//! and creates the main TunerApp instance.
+//! Parses the command line, sets up the eframe window with native styling,
+//! initializes audio capture, and creates the main TunerApp instance. Everything
+//! else lives in the `chroma_tuner` library.
 
+use anyhow::Context;
 use eframe::egui;
-use std::sync::{Arc, Mutex};
 use egui::IconData;
+use std::ffi::OsString;
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
 
-mod audio;
-mod pitch;
-mod tuner;
-
-use audio::AudioCapture;
-use tuner::TunerApp;
+use chroma_tuner::audio::{self, AudioCapture, FileSource};
+use chroma_tuner::config::TunerConfig;
+use chroma_tuner::detection_log::ExportFormat;
+use chroma_tuner::hotkey::GlobalShortcut;
+use chroma_tuner::instance::{self, InstanceLock};
+use chroma_tuner::offline;
+use chroma_tuner::tracker::AnalysisSettings;
+use chroma_tuner::tray::Tray;
+use chroma_tuner::tuner::{self, TunerApp};
 
 fn load_app_icon() -> IconData {
     let icon_bytes = include_bytes!("../assets/icons/icon.png");
@@ -22,8 +30,8 @@ fn load_app_icon() -> IconData {
             let (width, height) = img.dimensions();
             IconData {
                 rgba: img.into_raw(),
-                width: width as u32,
-                height: height as u32,
+                width,
+                height,
             }
         }
         Err(_) => {
@@ -41,33 +49,221 @@ fn load_app_icon() -> IconData {
     }
 }
 
+enum Input {
+    Device(AudioCapture),
+    File(FileSource),
+}
+
+fn open_preferred_capture(
+    audio_data: Arc<Mutex<audio::AudioData>>,
+    config: &TunerConfig,
+) -> anyhow::Result<AudioCapture> {
+    let preferred = config
+        .preferred_device
+        .as_ref()
+        .filter(|_| !config.follow_default_device);
+    if let Some(name) = preferred {
+        match audio::find_input_device(name) {
+            Some(device) => match AudioCapture::new_with_device(
+                audio_data.clone(),
+                device,
+                config.buffer_frames,
+            ) {
+                Ok(capture) => return Ok(capture),
+                Err(err) => log::warn!("Failed to open preferred device '{}': {}", name, err),
+            },
+            None => log::info!("Preferred device '{}' not found, using default", name),
+        }
+    }
+
+    AudioCapture::new(audio_data, config.buffer_frames)
+}
+
+/// Opens the device named by `--device`, matched exactly or by a unique
+/// case-insensitive prefix.
+fn open_named_capture(
+    audio_data: Arc<Mutex<audio::AudioData>>,
+    query: &str,
+    buffer_frames: u32,
+) -> anyhow::Result<AudioCapture> {
+    let mut devices = audio::get_input_devices()?;
+    let names: Vec<String> = devices.iter().map(|(name, _)| name.clone()).collect();
+    let (name, device) = devices.swap_remove(audio::match_device_name(&names, query)?);
+    AudioCapture::new_with_device(audio_data, device, buffer_frames)
+        .with_context(|| format!("Failed to open '{}'", name))
+}
+
+/// Prints each input device with its default format, marking the default one.
+fn list_devices() -> anyhow::Result<()> {
+    let default_name = audio::get_default_input_device_name().ok();
+    for (name, device) in audio::get_input_devices()? {
+        let marker = if default_name.as_ref() == Some(&name) {
+            "*"
+        } else {
+            " "
+        };
+        match audio::capture_config(&device) {
+            Ok(config) => println!(
+                "{} {} ({} Hz, {} ch)",
+                marker,
+                name,
+                config.sample_rate().0,
+                config.channels()
+            ),
+            Err(err) => println!("{} {} (unavailable: {})", marker, name, err),
+        }
+    }
+    Ok(())
+}
+
+/// Returns the value following `flag`, e.g. `--scala <file.scl>` to load a
+/// Scala scale, `--input-file <file.wav>` to play a recording through the
+/// tuner, `--analyze <file.wav>` to print its pitch curve or `--device <name>`
+/// to pick the input device.
+fn argument(flag: &str) -> Option<OsString> {
+    let mut args = std::env::args_os().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == flag {
+            return args.next();
+        }
+    }
+    None
+}
+
+fn has_flag(flag: &str) -> bool {
+    std::env::args_os().skip(1).any(|arg| arg == flag)
+}
+
+/// Analyses a WAV file with the saved settings and prints its pitch curve,
+/// for `--analyze <file.wav> [--format csv|json|midi]`.
+fn analyze(path: &Path, format: Option<OsString>, config: &TunerConfig) -> anyhow::Result<()> {
+    let format = match format {
+        Some(name) => {
+            let name = name.to_string_lossy();
+            ExportFormat::from_name(&name)
+                .with_context(|| format!("Unknown format '{}'; use csv, json or midi", name))?
+        }
+        None => ExportFormat::Csv,
+    };
+    let curve = offline::analyze_file(path, &AnalysisSettings::from(config), |_| {})?;
+    let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+    offline::write_curve(
+        &curve,
+        format,
+        config.spelling(),
+        &config.midi_export_options(),
+        &mut out,
+    )?;
+    out.flush()?;
+    Ok(())
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     env_logger::init();
 
+    if has_flag("--list-devices") {
+        list_devices()?;
+        return Ok(());
+    }
+
+    let mut config = TunerConfig::load();
+    if let Some(path) = argument("--analyze") {
+        analyze(path.as_ref(), argument("--format"), &config)?;
+        return Ok(());
+    }
+    if let Some(path) = argument("--scala") {
+        config.scala_file = Some(path.into());
+    }
+
+    // A second copy would fight this one for the microphone, so bring the
+    // running one to the front instead.
+    let instance = match InstanceLock::acquire(&instance::default_path()) {
+        Ok(Some(lock)) => Some(lock),
+        Ok(None) => {
+            log::info!("Chroma Tuner is already running; raised its window");
+            return Ok(());
+        }
+        Err(err) => {
+            log::warn!("Failed to check for a running instance: {}", err);
+            None
+        }
+    };
+
+    let mut viewport = egui::ViewportBuilder::default()
+        .with_inner_size(tuner::initial_window_size(&config))
+        .with_min_inner_size(tuner::min_window_size(&config))
+        .with_resizable(true)
+        .with_decorations(true)
+        .with_title_shown(false)
+        .with_titlebar_buttons_shown(true)
+        .with_titlebar_shown(false)
+        .with_fullsize_content_view(true)
+        .with_transparent(true)
+        .with_icon(load_app_icon());
+    // `--no-always-on-top` applies to this launch only; the pin button
+    // changes the saved preference.
+    let always_on_top = config.always_on_top && !has_flag("--no-always-on-top");
+    if always_on_top {
+        viewport = viewport.with_always_on_top();
+    }
+    if let Some([x, y]) = config.window_position {
+        viewport = viewport.with_position(egui::pos2(x, y));
+    }
     let options = eframe::NativeOptions {
-        viewport: egui::ViewportBuilder::default()
-            .with_inner_size([320.0, 180.0])
-            .with_resizable(false)
-            .with_always_on_top()
-            .with_decorations(true)
-            .with_title_shown(false)
-            .with_titlebar_buttons_shown(true)
-            .with_titlebar_shown(false)
-            .with_fullsize_content_view(true)
-            .with_transparent(true)
-            .with_icon(load_app_icon()),
+        viewport,
         ..Default::default()
     };
 
     let audio_data = Arc::new(Mutex::new(audio::AudioData::new()));
-    let audio_capture = AudioCapture::new(audio_data.clone())?;
+    let mut device_error = None;
+    let input = if let Some(path) = argument("--input-file") {
+        FileSource::open(path.as_ref(), audio_data.clone(), 1.0).map(Input::File)
+    } else {
+        let requested = argument("--device").map(|query| {
+            open_named_capture(
+                audio_data.clone(),
+                &query.to_string_lossy(),
+                config.buffer_frames,
+            )
+        });
+        match requested {
+            Some(Ok(capture)) => {
+                config.follow_default_device = false;
+                Ok(Input::Device(capture))
+            }
+            requested => {
+                device_error = requested.and_then(Result::err);
+                open_preferred_capture(audio_data.clone(), &config).map(Input::Device)
+            }
+        }
+    };
 
     eframe::run_native(
         "Chroma Tuner",
         options,
-        Box::new(|_cc| {
-            let mut app = TunerApp::new(audio_data);
-            app.set_audio_capture(audio_capture);
+        Box::new(|cc| {
+            let mut app = TunerApp::new(audio_data, config);
+            app.set_launched_on_top(always_on_top);
+            if let Some(lock) = instance {
+                let ctx = cc.egui_ctx.clone();
+                app.set_raise_requests(lock.listen(move || ctx.request_repaint()));
+            }
+            match Tray::new(&cc.egui_ctx) {
+                Ok(tray) => app.set_tray(tray),
+                Err(err) => log::warn!("No tray icon: {:#}", err),
+            }
+            match GlobalShortcut::new(&cc.egui_ctx) {
+                Ok(shortcut) => app.set_global_shortcut(shortcut),
+                Err(err) => log::warn!("No global shortcut: {:#}", err),
+            }
+            match input {
+                Ok(Input::Device(audio_capture)) => app.set_audio_capture(audio_capture),
+                Ok(Input::File(file_source)) => app.set_file_source(file_source),
+                Err(err) => app.report_error(format!("Failed to open audio input: {:#}", err)),
+            }
+            if let Some(err) = device_error {
+                app.report_error(format!("{:#}; using the default input instead", err));
+            }
             Ok(Box::new(app))
         }),
     )?;
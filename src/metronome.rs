@@ -0,0 +1,277 @@
+//! Metronome clicks scheduled to the sample
+//!
+//! The clicks are placed by counting samples on the audio thread rather than
+//! by a timer on the UI thread, so their spacing doesn't depend on when the
+//! window gets to draw. The beat that sounded last is kept for the window's
+//! beat indicator.
+
+use crate::audio::MonoGenerator;
+
+/// Slowest tempo offered, in beats per minute.
+pub const MIN_BPM: u32 = 30;
+/// Fastest tempo offered, in beats per minute.
+pub const MAX_BPM: u32 = 300;
+/// Longest bar offered, in beats.
+pub const MAX_BEATS_PER_BAR: u32 = 12;
+
+/// How long each click sounds, and how fast it dies away within that.
+const CLICK_SECONDS: f32 = 0.04;
+const CLICK_DECAY_SECONDS: f32 = 0.008;
+const CLICK_FREQUENCY: f32 = 1000.0;
+const CLICK_AMPLITUDE: f32 = 0.3;
+/// The downbeat is higher and louder.
+const ACCENT_FREQUENCY: f32 = 1500.0;
+const ACCENT_AMPLITUDE: f32 = 0.5;
+
+/// Sample at which beat `beat` falls at `bpm`, counting from beat 0 at
+/// sample 0. Every beat is rounded from its exact time rather than adding up
+/// rounded intervals, so the clicks never drift by more than half a sample.
+pub fn beat_sample(beat: u64, bpm: f32, sample_rate: f32) -> u64 {
+    (beat as f64 * 60.0 * sample_rate as f64 / bpm as f64).round() as u64
+}
+
+/// A beat that has sounded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Beat {
+    /// Beats since the metronome started, from 0.
+    pub count: u64,
+    /// Beat within the bar, 0 being the accented downbeat.
+    pub in_bar: u32,
+}
+
+/// Generates the clicks, one sample at a time.
+pub struct ClickGenerator {
+    sample_rate: f32,
+    bpm: f32,
+    beats_per_bar: u32,
+    running: bool,
+    /// Samples since the last tempo change, or the start.
+    position: u64,
+    /// Beats since then; the next one is due at `next_click`.
+    beats: u64,
+    next_click: u64,
+    last_beat: Option<Beat>,
+    /// Samples into the click that is sounding, and whether it's accented.
+    click: Option<(u32, bool)>,
+}
+
+impl ClickGenerator {
+    /// A stopped metronome at 100 BPM in 4/4.
+    pub fn new(sample_rate: f32) -> Self {
+        Self {
+            sample_rate,
+            bpm: 100.0,
+            beats_per_bar: 4,
+            running: false,
+            position: 0,
+            beats: 0,
+            next_click: 0,
+            last_beat: None,
+            click: None,
+        }
+    }
+
+    /// Starts with a downbeat on the next sample.
+    pub fn start(&mut self) {
+        self.running = true;
+        self.position = 0;
+        self.beats = 0;
+        self.next_click = 0;
+        self.last_beat = None;
+    }
+
+    /// Stops counting; the click that is sounding dies away on its own.
+    pub fn stop(&mut self) {
+        self.running = false;
+    }
+
+    /// Whether the metronome is counting.
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// The beat that sounded last, if any since the start.
+    pub fn last_beat(&self) -> Option<Beat> {
+        self.last_beat
+    }
+
+    /// Changes the tempo, clamped to the offered range, and the bar length.
+    /// A new tempo counts on from the last beat, so the next one comes a
+    /// whole new interval after it.
+    pub fn set_tempo(&mut self, bpm: f32, beats_per_bar: u32) {
+        self.beats_per_bar = beats_per_bar.clamp(1, MAX_BEATS_PER_BAR);
+        let bpm = bpm.clamp(MIN_BPM as f32, MAX_BPM as f32);
+        if bpm == self.bpm {
+            return;
+        }
+        if self.beats > 0 {
+            let last_click = beat_sample(self.beats - 1, self.bpm, self.sample_rate);
+            self.position -= last_click;
+            self.beats = 1;
+        }
+        self.bpm = bpm;
+        self.next_click = beat_sample(self.beats, self.bpm, self.sample_rate);
+    }
+}
+
+impl MonoGenerator for ClickGenerator {
+    fn next_sample(&mut self) -> f32 {
+        if self.running {
+            if self.position >= self.next_click {
+                let count = self.last_beat.map_or(0, |beat| beat.count + 1);
+                let in_bar = (count % self.beats_per_bar as u64) as u32;
+                self.last_beat = Some(Beat { count, in_bar });
+                self.click = Some((0, in_bar == 0));
+                self.beats += 1;
+                self.next_click = beat_sample(self.beats, self.bpm, self.sample_rate);
+            }
+            self.position += 1;
+        }
+
+        let Some((elapsed, accent)) = &mut self.click else {
+            return 0.0;
+        };
+        let time = *elapsed as f32 / self.sample_rate;
+        let (frequency, amplitude) = if *accent {
+            (ACCENT_FREQUENCY, ACCENT_AMPLITUDE)
+        } else {
+            (CLICK_FREQUENCY, CLICK_AMPLITUDE)
+        };
+        let sample = (time * frequency * std::f32::consts::TAU).sin()
+            * amplitude
+            * (-time / CLICK_DECAY_SECONDS).exp();
+        *elapsed += 1;
+        if time >= CLICK_SECONDS {
+            self.click = None;
+        }
+        sample
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Samples at which a click started over `samples` samples.
+    fn click_starts(generator: &mut ClickGenerator, samples: usize) -> Vec<usize> {
+        let mut starts = Vec::new();
+        let mut seen = generator.last_beat();
+        for index in 0..samples {
+            generator.next_sample();
+            if generator.last_beat() != seen {
+                seen = generator.last_beat();
+                starts.push(index);
+            }
+        }
+        starts
+    }
+
+    #[test]
+    fn test_click_intervals_are_within_a_millisecond() {
+        for sample_rate in [44100.0, 48000.0] {
+            for bpm in [30.0, 97.0, 120.0, 137.0, 300.0] {
+                let mut generator = ClickGenerator::new(sample_rate);
+                generator.set_tempo(bpm, 4);
+                generator.start();
+                let starts = click_starts(&mut generator, 20 * sample_rate as usize);
+
+                let nominal = 60.0 / bpm;
+                assert_eq!(starts[0], 0);
+                assert!(starts.len() as f32 >= 20.0 / nominal);
+                for pair in starts.windows(2) {
+                    let interval = (pair[1] - pair[0]) as f32 / sample_rate;
+                    assert!(
+                        (interval - nominal).abs() < 0.001,
+                        "{} BPM at {} Hz: {} s apart",
+                        bpm,
+                        sample_rate,
+                        interval
+                    );
+                }
+                // And no drift over the run.
+                let last = starts.len() - 1;
+                let expected = last as f64 * 60.0 * sample_rate as f64 / bpm as f64;
+                assert!((starts[last] as f64 - expected).abs() <= 0.5);
+            }
+        }
+    }
+
+    #[test]
+    fn test_beat_samples_never_drift() {
+        for beat in [0, 1, 1000, 1_000_000] {
+            let exact = beat as f64 * 60.0 * 44100.0 / 137.0;
+            assert!((beat_sample(beat, 137.0, 44100.0) as f64 - exact).abs() <= 0.5);
+        }
+    }
+
+    #[test]
+    fn test_downbeat_is_accented() {
+        let sample_rate = 48000.0;
+        let mut generator = ClickGenerator::new(sample_rate);
+        generator.set_tempo(120.0, 3);
+        generator.start();
+        let beat_samples = 24000;
+        let peaks: Vec<f32> = (0..6)
+            .map(|_| {
+                (0..beat_samples)
+                    .map(|_| generator.next_sample().abs())
+                    .fold(0.0, f32::max)
+            })
+            .collect();
+        for (beat, peak) in peaks.iter().enumerate() {
+            let expected = if beat % 3 == 0 {
+                ACCENT_AMPLITUDE
+            } else {
+                CLICK_AMPLITUDE
+            };
+            assert!((peak - expected).abs() < 0.05, "beat {}: {}", beat, peak);
+        }
+        assert_eq!(
+            generator.last_beat(),
+            Some(Beat {
+                count: 5,
+                in_bar: 2
+            })
+        );
+    }
+
+    #[test]
+    fn test_tempo_change_counts_on_from_the_last_beat() {
+        let sample_rate = 48000.0;
+        let mut generator = ClickGenerator::new(sample_rate);
+        generator.set_tempo(60.0, 4);
+        generator.start();
+        let mut starts = click_starts(&mut generator, 60000);
+        assert_eq!(starts, [0, 48000]);
+
+        // A quarter of a second after the last beat, switch to 120: the next
+        // beat comes half a second after the last, a quarter of a second on.
+        generator.set_tempo(120.0, 4);
+        starts = click_starts(&mut generator, 40000);
+        assert_eq!(starts, [12000, 36000]);
+    }
+
+    #[test]
+    fn test_stopping_lets_the_click_die_away() {
+        let sample_rate = 48000.0;
+        let mut generator = ClickGenerator::new(sample_rate);
+        generator.start();
+        for _ in 0..100 {
+            generator.next_sample();
+        }
+        generator.stop();
+        let tail: Vec<f32> = (0..sample_rate as usize)
+            .map(|_| generator.next_sample())
+            .collect();
+        let click_samples = (CLICK_SECONDS * sample_rate) as usize;
+        assert!(tail[..100].iter().any(|sample| sample.abs() > 0.01));
+        assert!(tail[click_samples..].iter().all(|&sample| sample == 0.0));
+        assert_eq!(
+            generator.last_beat(),
+            Some(Beat {
+                count: 0,
+                in_bar: 0
+            })
+        );
+    }
+}
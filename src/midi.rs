@@ -0,0 +1,164 @@
+//! MIDI output of detected pitch
+//!
+//! Turns the tracker's stable note into Note On/Off and pitch-bend messages and
+//! sends them to a MIDI output port, so the tuner can drive a synthesizer as a
+//! monophonic pitch-to-MIDI converter.
+
+use anyhow::{anyhow, Result};
+use midir::{MidiOutput, MidiOutputConnection};
+
+use crate::midi_file::{bend_message, bend_value, BEND_CENTER, NOTE_OFF, NOTE_ON, VELOCITY};
+use crate::pitch::Note;
+
+const CLIENT_NAME: &str = "Chroma Tuner";
+
+/// Names of the MIDI output ports currently available.
+pub fn output_port_names() -> Result<Vec<String>> {
+    let output = MidiOutput::new(CLIENT_NAME)?;
+    Ok(output
+        .ports()
+        .iter()
+        .filter_map(|port| output.port_name(port).ok())
+        .collect())
+}
+
+/// Works out which messages follow from the note currently shown. The tracker
+/// only changes that note once the pitch is stable, so polling it never
+/// produces more than one Note On per note.
+#[derive(Debug, Default)]
+struct NoteState {
+    playing: Option<u8>,
+    bend: Option<u16>,
+}
+
+impl NoteState {
+    fn update(&mut self, note: Option<&Note>, bend_range: u8, messages: &mut Vec<[u8; 3]>) {
+        let next = note.and_then(|note| {
+            let cents_off = note.microtone_cents + note.cents_off;
+            Some((u8::try_from(note.midi).ok()?, cents_off))
+        });
+        let next = next.filter(|&(midi, _)| midi < 128);
+
+        if self.playing != next.map(|(midi, _)| midi) {
+            if let Some(previous) = self.playing.take() {
+                messages.push([NOTE_OFF, previous, 0]);
+            }
+        }
+
+        let Some((midi, cents_off)) = next else {
+            return;
+        };
+        let bend = bend_value(cents_off, bend_range);
+        if self.bend != Some(bend) {
+            messages.push(bend_message(bend));
+            self.bend = Some(bend);
+        }
+        if self.playing.is_none() {
+            messages.push([NOTE_ON, midi, VELOCITY]);
+            self.playing = Some(midi);
+        }
+    }
+}
+
+/// An open MIDI output that plays the detected note.
+pub struct MidiSender {
+    port_name: String,
+    connection: MidiOutputConnection,
+    state: NoteState,
+    messages: Vec<[u8; 3]>,
+}
+
+impl MidiSender {
+    /// Connects to the output port named `port_name`.
+    pub fn open(port_name: &str) -> Result<Self> {
+        let output = MidiOutput::new(CLIENT_NAME)?;
+        let port = output
+            .ports()
+            .into_iter()
+            .find(|port| output.port_name(port).is_ok_and(|name| name == port_name))
+            .ok_or_else(|| anyhow!("MIDI port '{}' not found", port_name))?;
+        let connection = output
+            .connect(&port, "chroma-tuner-out")
+            .map_err(|err| anyhow!("Failed to open MIDI port '{}': {}", port_name, err))?;
+        log::info!("Sending MIDI to '{}'", port_name);
+
+        Ok(Self {
+            port_name: port_name.to_string(),
+            connection,
+            state: NoteState::default(),
+            messages: Vec::new(),
+        })
+    }
+
+    /// Sends whatever has changed about the shown note since the last call.
+    pub fn send_note(&mut self, note: Option<&Note>, bend_range: u8) -> Result<()> {
+        self.state.update(note, bend_range, &mut self.messages);
+        for message in self.messages.drain(..) {
+            self.connection
+                .send(&message)
+                .map_err(|err| anyhow!("Failed to send MIDI to '{}': {}", self.port_name, err))?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for MidiSender {
+    fn drop(&mut self) {
+        if self.send_note(None, 1).is_ok() && self.state.bend.is_some() {
+            let _ = self.connection.send(&bend_message(BEND_CENTER));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pitch::{frequency_to_note, DEFAULT_A4_FREQUENCY};
+
+    fn note(frequency: f32) -> Note {
+        frequency_to_note(frequency, DEFAULT_A4_FREQUENCY).unwrap()
+    }
+
+    #[test]
+    fn test_note_state_sends_each_note_once() {
+        let mut state = NoteState::default();
+        let mut messages = Vec::new();
+        let a4 = note(440.0);
+
+        state.update(Some(&a4), 2, &mut messages);
+        assert_eq!(
+            messages,
+            vec![bend_message(BEND_CENTER), [NOTE_ON, 69, VELOCITY]]
+        );
+
+        // Holding the note sends nothing until the bend moves.
+        messages.clear();
+        for _ in 0..10 {
+            state.update(Some(&a4), 2, &mut messages);
+        }
+        assert!(messages.is_empty());
+        let sharp = Note {
+            cents_off: 25.0,
+            ..a4.clone()
+        };
+        state.update(Some(&sharp), 2, &mut messages);
+        assert_eq!(messages, vec![bend_message(BEND_CENTER + 1024)]);
+
+        // A new note stops the old one first.
+        messages.clear();
+        state.update(Some(&note(493.88)), 2, &mut messages);
+        assert_eq!(
+            messages,
+            vec![
+                [NOTE_OFF, 69, 0],
+                bend_message(BEND_CENTER),
+                [NOTE_ON, 71, VELOCITY]
+            ]
+        );
+
+        messages.clear();
+        state.update(None, 2, &mut messages);
+        state.update(None, 2, &mut messages);
+        assert_eq!(messages, vec![[NOTE_OFF, 71, 0]]);
+    }
+}
@@ -0,0 +1,333 @@
+//! Standard MIDI file export of the detection log
+//!
+//! Splits the log into notes, each a run of consecutive entries on the same
+//! MIDI note, and writes them as a type-0 file with one Note On/Off pair per
+//! note and, optionally, pitch bends that follow the logged deviation.
+
+use std::io::Write;
+use std::time::{Duration, SystemTime};
+
+use crate::detection_log::LoggedDetection;
+
+pub(crate) const NOTE_OFF: u8 = 0x80;
+pub(crate) const NOTE_ON: u8 = 0x90;
+pub(crate) const PITCH_BEND: u8 = 0xE0;
+pub(crate) const VELOCITY: u8 = 100;
+pub(crate) const BEND_CENTER: u16 = 8192;
+pub(crate) const MAX_BEND: u16 = 16383;
+/// Microseconds per quarter note, i.e. 120 BPM, so a tick is a fixed time.
+const TEMPO: u32 = 500_000;
+/// A pause in the log longer than this ends the note even if the same one
+/// follows.
+const MAX_GAP: Duration = Duration::from_millis(250);
+
+/// Ticks per quarter note offered for export.
+pub const PPQ_OPTIONS: [u16; 5] = [96, 192, 240, 480, 960];
+
+/// How the log is turned into a MIDI file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MidiExportOptions {
+    /// Ticks per quarter note.
+    pub ppq: u16,
+    /// Whether each note carries pitch bends following its deviation.
+    pub pitch_bend: bool,
+    /// Pitch-bend range of the receiving synth, in semitones either way.
+    pub bend_range: u8,
+    /// Notes held for less than this are left out as blips.
+    pub min_duration: Duration,
+}
+
+impl Default for MidiExportOptions {
+    fn default() -> Self {
+        Self {
+            ppq: 480,
+            pitch_bend: true,
+            bend_range: 2,
+            min_duration: Duration::from_millis(80),
+        }
+    }
+}
+
+/// Converts a deviation into a 14-bit pitch-bend value for a synth whose bend
+/// range is `range` semitones either way.
+pub(crate) fn bend_value(cents: f32, range: u8) -> u16 {
+    let offset = cents / (range.max(1) as f32 * 100.0) * BEND_CENTER as f32;
+    (BEND_CENTER as f32 + offset)
+        .round()
+        .clamp(0.0, MAX_BEND as f32) as u16
+}
+
+pub(crate) fn bend_message(bend: u16) -> [u8; 3] {
+    [PITCH_BEND, (bend & 0x7f) as u8, (bend >> 7) as u8]
+}
+
+/// Runs of consecutive entries on the same MIDI note with no pause longer
+/// than [`MAX_GAP`], held for at least `min_duration`. Notes outside the MIDI
+/// range are skipped.
+fn note_runs(entries: &[LoggedDetection], min_duration: Duration) -> Vec<&[LoggedDetection]> {
+    let elapsed = |from: SystemTime, to: SystemTime| to.duration_since(from).unwrap_or_default();
+    entries
+        .chunk_by(|a, b| a.note.midi == b.note.midi && elapsed(a.timestamp, b.timestamp) <= MAX_GAP)
+        .filter(|run| (0..128).contains(&run[0].note.midi))
+        .filter(|run| elapsed(run[0].timestamp, run[run.len() - 1].timestamp) >= min_duration)
+        .collect()
+}
+
+/// Appends `value` as a variable-length quantity.
+fn push_variable_length(bytes: &mut Vec<u8>, value: u32) {
+    let mut groups = vec![(value & 0x7f) as u8];
+    let mut rest = value >> 7;
+    while rest > 0 {
+        groups.push((rest & 0x7f) as u8 | 0x80);
+        rest >>= 7;
+    }
+    bytes.extend(groups.iter().rev());
+}
+
+/// Writes `entries` as a type-0 standard MIDI file, timed from the first
+/// entry at 120 BPM.
+pub fn write_midi(
+    entries: &[LoggedDetection],
+    options: &MidiExportOptions,
+    out: &mut impl Write,
+) -> std::io::Result<()> {
+    let start = entries.first().map(|entry| entry.timestamp);
+    let ticks_per_second = options.ppq as f64 * 1e6 / TEMPO as f64;
+    let tick = |timestamp: SystemTime| {
+        let seconds = start
+            .and_then(|start| timestamp.duration_since(start).ok())
+            .unwrap_or_default()
+            .as_secs_f64();
+        (seconds * ticks_per_second).round() as u32
+    };
+
+    // Set Tempo at tick 0.
+    let mut track = vec![0x00, 0xff, 0x51, 0x03];
+    track.extend_from_slice(&TEMPO.to_be_bytes()[1..]);
+    let mut last_tick = 0;
+    let mut event = |track: &mut Vec<u8>, at: u32, bytes: &[u8]| {
+        push_variable_length(track, at.saturating_sub(last_tick));
+        track.extend_from_slice(bytes);
+        last_tick = last_tick.max(at);
+    };
+
+    for run in note_runs(entries, options.min_duration) {
+        let midi = run[0].note.midi as u8;
+        let mut bend = None;
+        for (index, entry) in run.iter().enumerate() {
+            if options.pitch_bend {
+                let cents = entry.note.microtone_cents + entry.note.cents_off;
+                let value = bend_value(cents, options.bend_range);
+                if bend != Some(value) {
+                    event(&mut track, tick(entry.timestamp), &bend_message(value));
+                    bend = Some(value);
+                }
+            }
+            // The note's first bend goes out just before its Note On.
+            if index == 0 {
+                event(
+                    &mut track,
+                    tick(entry.timestamp),
+                    &[NOTE_ON, midi, VELOCITY],
+                );
+            }
+        }
+        event(
+            &mut track,
+            tick(run[run.len() - 1].timestamp),
+            &[NOTE_OFF, midi, 0],
+        );
+    }
+    // End of Track.
+    track.extend_from_slice(&[0x00, 0xff, 0x2f, 0x00]);
+
+    out.write_all(b"MThd")?;
+    out.write_all(&6u32.to_be_bytes())?;
+    out.write_all(&0u16.to_be_bytes())?;
+    out.write_all(&1u16.to_be_bytes())?;
+    out.write_all(&options.ppq.to_be_bytes())?;
+    out.write_all(b"MTrk")?;
+    out.write_all(&(track.len() as u32).to_be_bytes())?;
+    out.write_all(&track)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pitch::{midi_to_frequency, midi_to_note, Note, DEFAULT_A4_FREQUENCY};
+    use std::time::UNIX_EPOCH;
+
+    fn entry(ms: u64, midi: i32, cents: f32) -> LoggedDetection {
+        let frequency = midi_to_frequency(midi, DEFAULT_A4_FREQUENCY);
+        LoggedDetection {
+            timestamp: UNIX_EPOCH + Duration::from_secs(1_700_000_000) + Duration::from_millis(ms),
+            note: Note {
+                cents_off: cents,
+                ..midi_to_note(midi, frequency, DEFAULT_A4_FREQUENCY)
+            },
+            magnitude: 0.5,
+        }
+    }
+
+    /// Reads back the header's division and the track's events at their
+    /// absolute ticks.
+    fn read_midi(bytes: &[u8]) -> (u16, Vec<(u32, Vec<u8>)>) {
+        assert_eq!(&bytes[..8], b"MThd\0\0\0\x06");
+        assert_eq!(&bytes[8..12], &[0, 0, 0, 1], "type 0 with one track");
+        let ppq = u16::from_be_bytes([bytes[12], bytes[13]]);
+        assert_eq!(&bytes[14..18], b"MTrk");
+        let length = u32::from_be_bytes(bytes[18..22].try_into().unwrap()) as usize;
+        let track = &bytes[22..];
+        assert_eq!(track.len(), length);
+
+        let mut position = 0;
+        let read_variable_length = |position: &mut usize| {
+            let mut value = 0u32;
+            loop {
+                let byte = track[*position];
+                *position += 1;
+                value = value << 7 | (byte & 0x7f) as u32;
+                if byte & 0x80 == 0 {
+                    return value;
+                }
+            }
+        };
+        let mut tick = 0;
+        let mut events = Vec::new();
+        while position < track.len() {
+            tick += read_variable_length(&mut position);
+            let length = if track[position] == 0xff {
+                3 + track[position + 2] as usize
+            } else {
+                3
+            };
+            events.push((tick, track[position..position + length].to_vec()));
+            position += length;
+        }
+        (ppq, events)
+    }
+
+    fn on(midi: u8) -> Vec<u8> {
+        vec![NOTE_ON, midi, VELOCITY]
+    }
+
+    fn off(midi: u8) -> Vec<u8> {
+        vec![NOTE_OFF, midi, 0]
+    }
+
+    fn bend(cents: f32) -> Vec<u8> {
+        bend_message(bend_value(cents, 2)).to_vec()
+    }
+
+    #[test]
+    fn test_bend_value() {
+        assert_eq!(bend_value(0.0, 2), BEND_CENTER);
+        assert_eq!(bend_value(200.0, 2), MAX_BEND);
+        assert_eq!(bend_value(-200.0, 2), 0);
+        assert_eq!(bend_value(50.0, 2), BEND_CENTER + 2048);
+        assert_eq!(bend_value(-50.0, 12), BEND_CENTER - 341);
+        assert_eq!(bend_message(BEND_CENTER), [PITCH_BEND, 0x00, 0x40]);
+    }
+
+    #[test]
+    fn test_variable_length_quantities() {
+        for (value, expected) in [
+            (0, vec![0x00]),
+            (0x7f, vec![0x7f]),
+            (0x80, vec![0x81, 0x00]),
+            (0x3fff, vec![0xff, 0x7f]),
+            (0x0fff_ffff, vec![0xff, 0xff, 0xff, 0x7f]),
+        ] {
+            let mut bytes = Vec::new();
+            push_variable_length(&mut bytes, value);
+            assert_eq!(bytes, expected, "{:#x}", value);
+        }
+    }
+
+    #[test]
+    fn test_log_round_trips_as_notes_and_bends() {
+        let mut log = Vec::new();
+        // A4 10 cents sharp for 480 ms.
+        log.extend((0..=480).step_by(20).map(|ms| entry(ms, 69, 10.0)));
+        // A 20 ms blip of C5.
+        log.extend([entry(500, 72, 0.0), entry(520, 72, 0.0)]);
+        // E5 that comes up from 20 cents flat.
+        log.extend((540..=1000).step_by(20).map(|ms| {
+            let cents = if ms < 780 { -20.0 } else { 0.0 };
+            entry(ms, 76, cents)
+        }));
+        // A4 again after a second's pause.
+        log.extend((2000..=2200).step_by(20).map(|ms| entry(ms, 69, 10.0)));
+
+        let mut bytes = Vec::new();
+        write_midi(&log, &MidiExportOptions::default(), &mut bytes).unwrap();
+        let (ppq, events) = read_midi(&bytes);
+        assert_eq!(ppq, 480);
+
+        // 960 ticks a second at 480 PPQ and 120 BPM.
+        let tempo = vec![0xff, 0x51, 0x03, 0x07, 0xa1, 0x20];
+        let end = vec![0xff, 0x2f, 0x00];
+        assert_eq!(
+            events,
+            vec![
+                (0, tempo.clone()),
+                (0, bend(10.0)),
+                (0, on(69)),
+                (461, off(69)),
+                (518, bend(-20.0)),
+                (518, on(76)),
+                (749, bend(0.0)),
+                (960, off(76)),
+                (1920, bend(10.0)),
+                (1920, on(69)),
+                (2112, off(69)),
+                (2112, end.clone()),
+            ]
+        );
+
+        let options = MidiExportOptions {
+            ppq: 96,
+            pitch_bend: false,
+            ..MidiExportOptions::default()
+        };
+        let mut bytes = Vec::new();
+        write_midi(&log, &options, &mut bytes).unwrap();
+        let (ppq, events) = read_midi(&bytes);
+        assert_eq!(ppq, 96);
+        assert_eq!(
+            events,
+            vec![
+                (0, tempo),
+                (0, on(69)),
+                (92, off(69)),
+                (104, on(76)),
+                (192, off(76)),
+                (384, on(69)),
+                (422, off(69)),
+                (422, end),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_blips_shorter_than_the_minimum_are_dropped() {
+        let log = [
+            entry(0, 60, 0.0),
+            entry(50, 60, 0.0),
+            entry(60, 62, 0.0),
+            entry(200, 62, 0.0),
+            // Same note after a long pause: two short notes, not one long one.
+            entry(600, 62, 0.0),
+            entry(650, 62, 0.0),
+            entry(1000, 62, 0.0),
+        ];
+        let runs = note_runs(&log, Duration::from_millis(80));
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0], &log[2..4]);
+        assert_eq!(note_runs(&log, Duration::ZERO).len(), 4);
+
+        let mut bytes = Vec::new();
+        write_midi(&[], &MidiExportOptions::default(), &mut bytes).unwrap();
+        assert_eq!(read_midi(&bytes).1.len(), 2, "just the tempo and end");
+    }
+}
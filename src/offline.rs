@@ -0,0 +1,370 @@
+//! Offline pitch analysis of a recording
+//!
+//! Runs the detector and tracker over a whole WAV file at the live hop, as
+//! fast as the CPU allows, giving the pitch curve that the analysis view plots
+//! and `--analyze` prints.
+
+use anyhow::{Context, Result};
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use crate::audio::{extract_channel, read_wav, ChannelSelection};
+use crate::detection_log::{csv_field, json_number, json_string, ExportFormat, LoggedDetection};
+use crate::midi_file::{write_midi, MidiExportOptions};
+use crate::pitch::{Note, NoteNaming, OnsetDetector, PitchDetector};
+use crate::tracker::{AnalysisSettings, PitchTracker};
+
+/// Samples summarised by each pair of the waveform overview.
+pub const WAVEFORM_BUCKET: usize = 256;
+/// Progress is reported in steps of at least this fraction.
+const PROGRESS_STEP: f32 = 0.01;
+
+/// The analysis after one hop of a recording.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CurvePoint {
+    /// Seconds from the start of the recording to the end of the window.
+    pub time: f32,
+    /// Frequency detected in this window alone, if any.
+    pub frequency: Option<f32>,
+    /// Magnitude of that detection, 0 without one.
+    pub magnitude: f32,
+    /// The note the tracker shows after this window, with the smoothed
+    /// deviation as `cents_off`.
+    pub note: Option<Note>,
+}
+
+/// A recording's pitch over time, with an overview of its waveform.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PitchCurve {
+    /// Length of the recording in seconds.
+    pub duration: f32,
+    /// One point per hop, in time order.
+    pub points: Vec<CurvePoint>,
+    /// Lowest and highest sample of each [`WAVEFORM_BUCKET`] samples.
+    pub waveform: Vec<(f32, f32)>,
+    /// Seconds covered by each waveform pair.
+    pub bucket_duration: f32,
+}
+
+impl PitchCurve {
+    /// The latest point at or before `time`.
+    pub fn point_at(&self, time: f32) -> Option<&CurvePoint> {
+        let index = self.points.partition_point(|point| point.time <= time);
+        self.points.get(index.checked_sub(1)?)
+    }
+}
+
+/// Analyses mono `samples` as the live tuner would with `settings`, one
+/// window every hop, calling `progress` with the fraction done as it goes.
+pub fn analyze_samples(
+    samples: &[f32],
+    sample_rate: f32,
+    settings: &AnalysisSettings,
+    mut progress: impl FnMut(f32),
+) -> PitchCurve {
+    let size = settings.fft_size;
+    let hop = settings.hop_size.max(1);
+    let mut detector = PitchDetector::new(size, sample_rate, settings.window_kind);
+    settings.configure(&mut detector);
+    let mut onset_detector = OnsetDetector::new(sample_rate);
+    let mut tracker = PitchTracker::default();
+    let start = Instant::now();
+
+    let mut points = Vec::with_capacity(samples.len() / hop);
+    let mut reported = 0.0;
+    for end in (hop..=samples.len()).step_by(hop) {
+        let window = &samples[end.saturating_sub(size)..end];
+        let now = start + Duration::from_secs_f64(end as f64 / sample_rate as f64);
+        let note = tracker.reading().note;
+        detector.set_low_range(
+            settings
+                .low_range
+                .active(note.map(|note| note.target_frequency)),
+        );
+        if onset_detector.detect(window) {
+            tracker.onset(now, settings);
+        }
+        let detection = detector.detect_pitch(window);
+        match detection {
+            Some(estimate) => {
+                tracker.feed(estimate, now, settings);
+            }
+            None => tracker.timeout(now, settings),
+        }
+
+        let reading = tracker.reading();
+        points.push(CurvePoint {
+            time: end as f32 / sample_rate,
+            frequency: detection.map(|estimate| estimate.frequency),
+            magnitude: detection.map_or(0.0, |estimate| estimate.magnitude),
+            note: reading.note.map(|note| Note {
+                cents_off: reading.smoothed_cents,
+                ..note
+            }),
+        });
+
+        let done = end as f32 / samples.len() as f32;
+        if done - reported >= PROGRESS_STEP {
+            progress(done);
+            reported = done;
+        }
+    }
+    progress(1.0);
+
+    PitchCurve {
+        duration: samples.len() as f32 / sample_rate,
+        points,
+        waveform: samples
+            .chunks(WAVEFORM_BUCKET)
+            .map(|chunk| {
+                chunk.iter().fold((0.0f32, 0.0f32), |(low, high), &sample| {
+                    (low.min(sample), high.max(sample))
+                })
+            })
+            .collect(),
+        bucket_duration: WAVEFORM_BUCKET as f32 / sample_rate,
+    }
+}
+
+/// Reads a WAV file, mixes it to mono and analyses it as
+/// [`analyze_samples`] does.
+pub fn analyze_file(
+    path: &Path,
+    settings: &AnalysisSettings,
+    progress: impl FnMut(f32),
+) -> Result<PitchCurve> {
+    let (interleaved, spec) =
+        read_wav(path).with_context(|| format!("Failed to read WAV file {}", path.display()))?;
+    let mut samples = Vec::new();
+    extract_channel(
+        &interleaved,
+        spec.channels.max(1) as usize,
+        ChannelSelection::Mix,
+        &mut samples,
+    );
+    Ok(analyze_samples(
+        &samples,
+        spec.sample_rate as f32,
+        settings,
+        progress,
+    ))
+}
+
+/// Writes the curve in `format`, one row per hop with empty fields where
+/// nothing was detected, spelling note names with `naming`. As MIDI, the
+/// tracked notes are written as [`write_midi`] writes the detection log,
+/// timed from the first of them.
+pub fn write_curve(
+    curve: &PitchCurve,
+    format: ExportFormat,
+    naming: NoteNaming,
+    midi: &MidiExportOptions,
+    out: &mut impl Write,
+) -> std::io::Result<()> {
+    let name = |point: &CurvePoint| point.note.as_ref().map(|note| note.display_name(naming));
+    match format {
+        ExportFormat::Csv => {
+            writeln!(out, "time_s,frequency_hz,note,cents,magnitude")?;
+            for point in &curve.points {
+                writeln!(
+                    out,
+                    "{:.3},{},{},{},{:.4}",
+                    point.time,
+                    point
+                        .frequency
+                        .map_or(String::new(), |frequency| format!("{:.2}", frequency)),
+                    name(point).map_or(String::new(), |name| csv_field(&name)),
+                    point
+                        .note
+                        .as_ref()
+                        .map_or(String::new(), |note| format!("{:.1}", note.cents_off)),
+                    point.magnitude
+                )?;
+            }
+        }
+        ExportFormat::Json => {
+            write!(out, "[")?;
+            for (index, point) in curve.points.iter().enumerate() {
+                let separator = if index == 0 { "" } else { "," };
+                write!(
+                    out,
+                    "{}\n  {{\"time_s\": {:.3}, \"frequency_hz\": {}, \"note\": {}, \"cents\": {}, \"magnitude\": {}}}",
+                    separator,
+                    point.time,
+                    point
+                        .frequency
+                        .map_or("null".to_string(), |frequency| json_number(frequency, 2)),
+                    name(point).map_or("null".to_string(), |name| json_string(&name)),
+                    point
+                        .note
+                        .as_ref()
+                        .map_or("null".to_string(), |note| json_number(note.cents_off, 1)),
+                    json_number(point.magnitude, 4)
+                )?;
+            }
+            writeln!(out, "\n]")?;
+        }
+        ExportFormat::Midi => {
+            let entries: Vec<LoggedDetection> = curve
+                .points
+                .iter()
+                .filter_map(|point| {
+                    Some(LoggedDetection {
+                        timestamp: UNIX_EPOCH + Duration::from_secs_f32(point.time),
+                        note: point.note.clone()?,
+                        magnitude: point.magnitude,
+                    })
+                })
+                .collect();
+            write_midi(&entries, midi, out)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pitch::test_signals;
+
+    #[test]
+    fn test_curve_follows_a_melody() {
+        let sample_rate = 44100.0;
+        let samples = test_signals::melody(
+            &[(Some(440.0), 1.0), (None, 0.5), (Some(523.25), 1.0)],
+            sample_rate,
+        );
+        let settings = AnalysisSettings::default();
+        let mut progress = Vec::new();
+        let curve = analyze_samples(&samples, sample_rate, &settings, |done| progress.push(done));
+
+        assert_eq!(curve.points.len(), samples.len() / settings.hop_size);
+        assert!((curve.duration - 2.5).abs() < 1e-3);
+        assert_eq!(
+            curve.waveform.len(),
+            samples.len().div_ceil(WAVEFORM_BUCKET)
+        );
+        assert!(curve.waveform.iter().all(|&(low, high)| low <= high));
+        assert!(progress.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(progress.last(), Some(&1.0));
+        assert!(progress.len() <= 101, "{} progress reports", progress.len());
+
+        let name = |time: f32| {
+            let point = curve.point_at(time).unwrap();
+            assert!((time - point.time) < settings.hop_size as f32 / sample_rate);
+            point
+                .note
+                .as_ref()
+                .map(|note| (note.to_string(), note.cents_off))
+        };
+        for (time, expected) in [(0.8, "A4"), (2.3, "C5")] {
+            let (note, cents) = name(time).expect("no note");
+            assert_eq!(note, expected, "at {} s", time);
+            assert!(cents.abs() < 2.0, "{} {:+.1} cents", note, cents);
+        }
+        assert!(curve.point_at(1.45).unwrap().frequency.is_none());
+        assert!(curve.point_at(0.0).is_none());
+    }
+
+    #[test]
+    fn test_file_is_mixed_down_and_analysed() {
+        let path =
+            std::env::temp_dir().join(format!("chroma-tuner-offline-{}.wav", std::process::id()));
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 48000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for sample in test_signals::sawtooth(196.0, 48000.0, 48000) {
+            let sample = (sample * i16::MAX as f32) as i16;
+            writer.write_sample(sample).unwrap();
+            writer.write_sample(sample).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let curve = analyze_file(&path, &AnalysisSettings::default(), |_| {});
+        let _ = std::fs::remove_file(&path);
+        let curve = curve.unwrap();
+        assert!((curve.duration - 1.0).abs() < 1e-3);
+        let note = curve.points.last().unwrap().note.as_ref().unwrap();
+        assert_eq!(note.to_string(), "G3");
+
+        let error = analyze_file(
+            Path::new("/no/such.wav"),
+            &AnalysisSettings::default(),
+            |_| {},
+        )
+        .unwrap_err();
+        assert!(format!("{:#}", error).starts_with("Failed to read WAV file"));
+    }
+
+    #[test]
+    fn test_curve_is_written_with_empty_fields_for_silence() {
+        let a4 = crate::pitch::frequency_to_note(442.0, 440.0).unwrap();
+        let curve = PitchCurve {
+            duration: 0.05,
+            points: vec![
+                CurvePoint {
+                    time: 0.023,
+                    frequency: None,
+                    magnitude: 0.0,
+                    note: None,
+                },
+                CurvePoint {
+                    time: 0.046,
+                    frequency: Some(442.0),
+                    magnitude: 0.25,
+                    note: Some(a4),
+                },
+            ],
+            ..PitchCurve::default()
+        };
+        let options = MidiExportOptions::default();
+
+        let mut csv = Vec::new();
+        write_curve(
+            &curve,
+            ExportFormat::Csv,
+            NoteNaming::Sharps,
+            &options,
+            &mut csv,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "time_s,frequency_hz,note,cents,magnitude\n\
+             0.023,,,,0.0000\n\
+             0.046,442.00,A4,7.9,0.2500\n"
+        );
+
+        let mut json = Vec::new();
+        write_curve(
+            &curve,
+            ExportFormat::Json,
+            NoteNaming::Sharps,
+            &options,
+            &mut json,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(json).unwrap(),
+            "[\n  \
+             {\"time_s\": 0.023, \"frequency_hz\": null, \"note\": null, \"cents\": null, \"magnitude\": 0.0000},\n  \
+             {\"time_s\": 0.046, \"frequency_hz\": 442.00, \"note\": \"A4\", \"cents\": 7.9, \"magnitude\": 0.2500}\n]\n"
+        );
+
+        let mut midi = Vec::new();
+        write_curve(
+            &curve,
+            ExportFormat::Midi,
+            NoteNaming::Sharps,
+            &options,
+            &mut midi,
+        )
+        .unwrap();
+        assert!(midi.starts_with(b"MThd"));
+    }
+}
@@ -0,0 +1,216 @@
+//! OSC output of detected pitch
+//!
+//! Broadcasts stable readings as `/chroma/pitch` messages and note releases as
+//! `/chroma/silence` over UDP, for Max/MSP, Pure Data or SuperCollider. Packets
+//! are sent from a background thread so the network never stalls the UI.
+
+use anyhow::{anyhow, Context, Result};
+use rosc::{OscMessage, OscPacket, OscType};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::mpsc::{self, SyncSender};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::pitch::NoteNaming;
+use crate::tracker::PitchReading;
+
+/// Address of pitch messages.
+pub const PITCH_ADDRESS: &str = "/chroma/pitch";
+/// Address of the message sent when the note clears.
+pub const SILENCE_ADDRESS: &str = "/chroma/silence";
+/// Pitch messages are sent at most this often (about 30 per second).
+const MIN_PITCH_INTERVAL: Duration = Duration::from_millis(33);
+const QUEUE_MESSAGES: usize = 64;
+
+/// Picks which reading changes become messages: pitch at a throttled rate
+/// while a note is shown, and a single silence message when it clears.
+#[derive(Debug, Default)]
+struct OscThrottle {
+    last_pitch: Option<Instant>,
+}
+
+impl OscThrottle {
+    fn next_message(
+        &mut self,
+        reading: &PitchReading,
+        naming: NoteNaming,
+        now: Instant,
+    ) -> Option<OscMessage> {
+        let Some(note) = &reading.note else {
+            self.last_pitch.take()?;
+            return Some(OscMessage {
+                addr: SILENCE_ADDRESS.to_string(),
+                args: Vec::new(),
+            });
+        };
+
+        if self
+            .last_pitch
+            .is_some_and(|last| now.saturating_duration_since(last) < MIN_PITCH_INTERVAL)
+        {
+            return None;
+        }
+        self.last_pitch = Some(now);
+        Some(OscMessage {
+            addr: PITCH_ADDRESS.to_string(),
+            args: vec![
+                OscType::Float(note.frequency),
+                OscType::Float(reading.confidence),
+                OscType::String(note.display_name(naming)),
+                OscType::Float(note.cents_off),
+            ],
+        })
+    }
+}
+
+/// Sends readings to an OSC receiver over UDP.
+pub struct OscSender {
+    target: SocketAddr,
+    sender: Option<SyncSender<OscMessage>>,
+    handle: Option<JoinHandle<()>>,
+    throttle: OscThrottle,
+}
+
+impl OscSender {
+    /// Resolves `host:port` and starts the sending thread.
+    pub fn open(target: &str) -> Result<Self> {
+        let target = target
+            .trim()
+            .to_socket_addrs()
+            .with_context(|| format!("Invalid OSC address '{}'", target.trim()))?
+            .next()
+            .ok_or_else(|| anyhow!("OSC address '{}' did not resolve", target.trim()))?;
+        let bind = if target.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+        let socket = UdpSocket::bind(bind).context("Failed to open an OSC socket")?;
+
+        let (sender, receiver) = mpsc::sync_channel::<OscMessage>(QUEUE_MESSAGES);
+        let handle = std::thread::Builder::new()
+            .name("osc-sender".to_string())
+            .spawn(move || {
+                for message in receiver {
+                    let sent = rosc::encoder::encode(&OscPacket::Message(message))
+                        .map_err(|err| anyhow!("{}", err))
+                        .and_then(|packet| Ok(socket.send_to(&packet, target)?));
+                    if let Err(err) = sent {
+                        log::warn!("Failed to send OSC to {}: {}", target, err);
+                    }
+                }
+            })?;
+        log::info!("Sending OSC to {}", target);
+
+        Ok(Self {
+            target,
+            sender: Some(sender),
+            handle: Some(handle),
+            throttle: OscThrottle::default(),
+        })
+    }
+
+    /// The resolved address messages are sent to.
+    pub fn target(&self) -> SocketAddr {
+        self.target
+    }
+
+    /// Queues whatever the reading implies; a full queue drops the message.
+    pub fn publish(&mut self, reading: &PitchReading, naming: NoteNaming, now: Instant) {
+        let Some(message) = self.throttle.next_message(reading, naming, now) else {
+            return;
+        };
+        if let Some(sender) = &self.sender {
+            let _ = sender.try_send(message);
+        }
+    }
+}
+
+impl Drop for OscSender {
+    fn drop(&mut self) {
+        self.sender = None;
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pitch::{frequency_to_note, DEFAULT_A4_FREQUENCY};
+
+    fn a4_reading() -> PitchReading {
+        PitchReading {
+            note: frequency_to_note(442.0, DEFAULT_A4_FREQUENCY),
+            confidence: 0.9,
+            ..PitchReading::default()
+        }
+    }
+
+    #[test]
+    fn test_throttle_limits_rate_and_sends_silence_once() {
+        let start = Instant::now();
+        let mut throttle = OscThrottle::default();
+        let reading = a4_reading();
+
+        // One second of 120 Hz UI frames.
+        let pitch_messages = (0..120)
+            .filter_map(|frame| {
+                let now = start + Duration::from_micros(frame * 8333);
+                throttle.next_message(&reading, NoteNaming::Sharps, now)
+            })
+            .count();
+        assert!((28..=31).contains(&pitch_messages), "{}", pitch_messages);
+
+        let silent = PitchReading::default();
+        let later = start + Duration::from_secs(2);
+        let silence = throttle
+            .next_message(&silent, NoteNaming::Sharps, later)
+            .unwrap();
+        assert_eq!(silence.addr, SILENCE_ADDRESS);
+        assert!(throttle
+            .next_message(&silent, NoteNaming::Sharps, later + Duration::from_secs(1))
+            .is_none());
+
+        // The next note is sent straight away.
+        let message = throttle
+            .next_message(&reading, NoteNaming::Sharps, later + Duration::from_secs(2))
+            .unwrap();
+        assert_eq!(message.addr, PITCH_ADDRESS);
+    }
+
+    #[test]
+    fn test_sender_delivers_pitch_over_udp() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        let mut sender = OscSender::open(&receiver.local_addr().unwrap().to_string()).unwrap();
+        assert_eq!(sender.target(), receiver.local_addr().unwrap());
+
+        sender.publish(&a4_reading(), NoteNaming::Sharps, Instant::now());
+        let mut buffer = [0u8; 1024];
+        let (length, _) = receiver.recv_from(&mut buffer).unwrap();
+        let (_, packet) = rosc::decoder::decode_udp(&buffer[..length]).unwrap();
+
+        let OscPacket::Message(message) = packet else {
+            panic!("expected a message, got {:?}", packet);
+        };
+        assert_eq!(message.addr, PITCH_ADDRESS);
+        assert_eq!(message.args[0], OscType::Float(442.0));
+        assert_eq!(message.args[1], OscType::Float(0.9));
+        assert_eq!(message.args[2], OscType::String("A4".to_string()));
+        let OscType::Float(cents) = message.args[3] else {
+            panic!("cents should be a float");
+        };
+        assert!((cents - 7.85).abs() < 0.01, "{}", cents);
+    }
+
+    #[test]
+    fn test_invalid_address_is_an_error() {
+        let error = OscSender::open("localhost").err().unwrap();
+        assert_eq!(error.to_string(), "Invalid OSC address 'localhost'");
+        assert!(OscSender::open("127.0.0.1:notaport").is_err());
+    }
+}
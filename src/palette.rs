@@ -0,0 +1,199 @@
+//! Status colors for each color scheme
+//!
+//! Everything that tells the player something through color — how far off the
+//! pitch is, warnings, clipping and the status banner — comes from a
+//! [`Palette`], so a scheme can swap all of it at once. The neutral greys of
+//! the interface stay the same in every scheme.
+
+use eframe::egui::{Color32, Theme};
+
+use crate::config::ColorScheme;
+
+/// The colors that carry meaning in one color scheme.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Palette {
+    /// Within the in-tune tolerance.
+    pub in_tune: Color32,
+    /// Out of tune, but by less than the close threshold.
+    pub near: Color32,
+    /// Out of tune by more than that.
+    pub off: Color32,
+    /// Hints that need attention: a quiet signal, low confidence, a held reading.
+    pub warning: Color32,
+    /// Clipping and recording.
+    pub alert: Color32,
+    /// Background of error messages.
+    pub error_fill: Color32,
+    /// Background of other status messages.
+    pub success_fill: Color32,
+    /// Notes played flat on average, on the intonation keyboard.
+    pub flat: Color32,
+    /// Notes played sharp on average.
+    pub sharp: Color32,
+}
+
+impl Palette {
+    /// The palette of `scheme` on a `theme` background.
+    pub fn for_scheme(scheme: ColorScheme, theme: Theme) -> Self {
+        match scheme {
+            ColorScheme::Standard => Self {
+                in_tune: Color32::from_rgb(48, 209, 88),
+                near: Color32::from_rgb(255, 159, 10),
+                off: Color32::from_rgb(255, 69, 58),
+                warning: Color32::from_rgb(255, 159, 10),
+                alert: Color32::from_rgb(255, 69, 58),
+                error_fill: Color32::from_rgb(180, 40, 34),
+                success_fill: Color32::from_rgb(36, 128, 60),
+                flat: Color32::from_rgb(10, 132, 255),
+                sharp: Color32::from_rgb(255, 69, 58),
+            },
+            // Okabe-Ito sky blue, yellow and vermillion, which stay apart for
+            // red-green color blindness.
+            ColorScheme::ColorBlind => Self {
+                in_tune: Color32::from_rgb(86, 180, 233),
+                near: Color32::from_rgb(240, 228, 66),
+                off: Color32::from_rgb(213, 94, 0),
+                warning: Color32::from_rgb(240, 228, 66),
+                alert: Color32::from_rgb(213, 94, 0),
+                error_fill: Color32::from_rgb(153, 68, 0),
+                success_fill: Color32::from_rgb(0, 94, 148),
+                flat: Color32::from_rgb(0, 114, 178),
+                sharp: Color32::from_rgb(213, 94, 0),
+            },
+            // Brightness only; being in tune is shown by the needle's shape.
+            // The keyboard's tendencies need hue, so they take the
+            // color-blind pair, with the exact figures on hover.
+            ColorScheme::HighContrast if theme == Theme::Light => Self {
+                in_tune: Color32::BLACK,
+                near: Color32::from_rgb(70, 70, 70),
+                off: Color32::from_rgb(120, 120, 120),
+                warning: Color32::BLACK,
+                alert: Color32::BLACK,
+                error_fill: Color32::from_rgb(60, 60, 60),
+                success_fill: Color32::from_rgb(100, 100, 100),
+                flat: Color32::from_rgb(0, 114, 178),
+                sharp: Color32::from_rgb(213, 94, 0),
+            },
+            ColorScheme::HighContrast => Self {
+                in_tune: Color32::WHITE,
+                near: Color32::from_rgb(190, 190, 190),
+                off: Color32::from_rgb(130, 130, 130),
+                warning: Color32::WHITE,
+                alert: Color32::WHITE,
+                error_fill: Color32::from_rgb(90, 90, 90),
+                success_fill: Color32::from_rgb(60, 60, 60),
+                flat: Color32::from_rgb(86, 180, 233),
+                sharp: Color32::from_rgb(230, 159, 0),
+            },
+        }
+    }
+
+    /// Color for a deviation of `cents_off`: in tune below `in_tune_cents`,
+    /// close below `close_cents` and off beyond.
+    pub fn deviation(&self, cents_off: f32, in_tune_cents: f32, close_cents: f32) -> Color32 {
+        if cents_off.abs() < in_tune_cents {
+            self.in_tune
+        } else if cents_off.abs() < close_cents {
+            self.near
+        } else {
+            self.off
+        }
+    }
+
+    /// Tint for a note played `mean_cents` off on average: `neutral` when in
+    /// tune, shading into flat or sharp until fully so at `full_cents`.
+    pub fn tendency(&self, mean_cents: f32, full_cents: f32, neutral: Color32) -> Color32 {
+        let amount = (mean_cents.abs() / full_cents.max(f32::EPSILON)).clamp(0.0, 1.0);
+        let towards = if mean_cents < 0.0 {
+            self.flat
+        } else {
+            self.sharp
+        };
+        neutral.lerp_to_gamma(towards, amount)
+    }
+
+    /// Translucent fill for the in-tune band behind the history graph.
+    pub fn in_tune_band(&self) -> Color32 {
+        let [red, green, blue, _] = self.in_tune.to_array();
+        Color32::from_rgba_unmultiplied(red, green, blue, 40)
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::for_scheme(ColorScheme::default(), Theme::Dark)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deviation_bands() {
+        let palette = Palette::default();
+        assert_eq!(palette.deviation(-2.0, 5.0, 20.0), palette.in_tune);
+        assert_eq!(palette.deviation(4.99, 5.0, 20.0), palette.in_tune);
+        assert_eq!(palette.deviation(5.0, 5.0, 20.0), palette.near);
+        assert_eq!(palette.deviation(-19.0, 5.0, 20.0), palette.near);
+        assert_eq!(palette.deviation(20.0, 5.0, 20.0), palette.off);
+        assert_eq!(palette.deviation(-20.0, 5.0, 20.0), palette.off);
+    }
+
+    #[test]
+    fn test_deviation_bands_follow_the_thresholds() {
+        let palette = Palette::default();
+        // A piano technician's tolerance.
+        assert_eq!(palette.deviation(0.9, 1.0, 3.0), palette.in_tune);
+        assert_eq!(palette.deviation(-1.0, 1.0, 3.0), palette.near);
+        assert_eq!(palette.deviation(2.9, 1.0, 3.0), palette.near);
+        assert_eq!(palette.deviation(3.0, 1.0, 3.0), palette.off);
+        // A beginner's.
+        assert_eq!(palette.deviation(-9.5, 10.0, 30.0), palette.in_tune);
+        assert_eq!(palette.deviation(29.0, 10.0, 30.0), palette.near);
+        assert_eq!(palette.deviation(-30.0, 10.0, 30.0), palette.off);
+    }
+
+    #[test]
+    fn test_tendency_diverges_from_neutral() {
+        let palette = Palette::default();
+        let neutral = Color32::WHITE;
+        assert_eq!(palette.tendency(0.0, 20.0, neutral), neutral);
+        assert_eq!(palette.tendency(-20.0, 20.0, neutral), palette.flat);
+        assert_eq!(palette.tendency(35.0, 20.0, neutral), palette.sharp);
+        let slightly_flat = palette.tendency(-5.0, 20.0, neutral);
+        assert_ne!(slightly_flat, neutral);
+        assert!(slightly_flat.r() > palette.flat.r(), "{:?}", slightly_flat);
+        assert!(palette.tendency(5.0, 20.0, neutral).b() < neutral.b());
+    }
+
+    #[test]
+    fn test_schemes_keep_deviation_colors_apart() {
+        for (scheme, theme) in ColorScheme::ALL
+            .into_iter()
+            .flat_map(|scheme| [(scheme, Theme::Dark), (scheme, Theme::Light)])
+        {
+            let palette = Palette::for_scheme(scheme, theme);
+            let colors = [palette.in_tune, palette.near, palette.off];
+            for (index, color) in colors.iter().enumerate() {
+                assert!(
+                    !colors[index + 1..].contains(color),
+                    "{:?} repeats {:?}",
+                    scheme,
+                    color
+                );
+            }
+        }
+
+        for theme in [Theme::Dark, Theme::Light] {
+            let high_contrast = Palette::for_scheme(ColorScheme::HighContrast, theme);
+            for color in [high_contrast.in_tune, high_contrast.near, high_contrast.off] {
+                assert!(
+                    color.r() == color.g() && color.g() == color.b(),
+                    "{:?}",
+                    color
+                );
+            }
+        }
+    }
+}
@@ -1,163 +1,3692 @@
 //! Pitch detection and musical note conversion
 //!
-//! Implements FFT-based pitch detection using a Hann window for frequency analysis.
-//! Converts detected frequencies to musical notes with cent deviation calculations.
+//! Implements FFT-based pitch detection using a selectable analysis window and
+//! a harmonic product spectrum for frequency analysis, falling back to a YIN
+//! time-domain estimator for low notes where FFT bins are too coarse. A
+//! fundamental weaker than its overtones, or missing, is inferred from the
+//! harmonic series of the strongest peaks. In low-range mode the block is
+//! low-passed and decimated first, so the same FFT size resolves bass notes
+//! four times more finely. An optional high-pass and mains hum notch pre-filter
+//! can run first. Spots note onsets from jumps in level so the noisy attack can
+//! be skipped. Measures vibrato from a trace of detected frequencies and
+//! converts them to musical notes with cent deviation calculations. Several
+//! notes sounding together can be picked out of the spectrum, and the interval
+//! between two of them named.
 
+use realfft::num_complex::Complex;
 use realfft::{RealFftPlanner, RealToComplex};
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
 use std::sync::Arc;
 
+#[cfg(test)]
+// The scripted signals are only played through the app's tests.
+#[cfg_attr(not(feature = "gui"), allow(dead_code))]
+pub(crate) mod test_signals;
+
+const HPS_HARMONICS: usize = 5;
+const HPS_MIN_FUNDAMENTAL_RATIO: f32 = 0.1;
+/// How far from its target each string's peak is searched for when several
+/// strings sound at once.
+const MULTI_PITCH_SEARCH_CENTS: f32 = 80.0;
+/// A second harmonic weaker than this fraction of the fundamental is left out
+/// of a multi-pitch estimate.
+const MULTI_PITCH_HARMONIC_RATIO: f32 = 0.1;
+/// Partials a piano note is measured at to estimate its inharmonicity.
+pub const PIANO_PARTIALS: usize = 6;
+/// Stretch relative to the configured cents per octave, for each octave away
+/// from A4 and beyond: gentle in the middle of the keyboard and steeper
+/// towards both ends, after the Railsback curve of aurally tuned pianos.
+const STRETCH_SHAPE: [f32; 4] = [0.5, 1.5, 3.0, 6.0];
+/// Golden-section steps that narrow a peak from three bins to a few
+/// thousandths of one.
+const PEAK_REFINE_STEPS: usize = 14;
+/// Peaks weaker than this fraction of the strongest aren't taken for notes.
+const PEAK_MIN_RATIO: f32 = 0.1;
+/// A peak this close in cents to a multiple of a note's frequency is taken
+/// for one of its harmonics.
+const HARMONIC_TOLERANCE_CENTS: f32 = 30.0;
+/// Below this no spectral peak counts as sound.
+const MIN_PEAK_MAGNITUDE: f32 = 0.005;
+/// Strongest spectral peaks weighed when inferring a missing fundamental.
+const INFERENCE_PEAKS: usize = 6;
+/// Highest partial number a peak is tried as, or counted as, when inferring
+/// one; any frequency low enough is a near multiple of everything.
+const MAX_INFERRED_PARTIAL: usize = 8;
+/// Fewest peaks a harmonic series must explain for its fundamental to be
+/// inferred.
+const MIN_INFERRED_PARTIALS: usize = 3;
+/// A fundamental weaker than this fraction of its strongest partial is
+/// placed by the partials instead, whose skirts would pull it off.
+const WEAK_FUNDAMENTAL_RATIO: f32 = 0.5;
+const YIN_THRESHOLD: f32 = 0.15;
+const YIN_MIN_FREQUENCY: f32 = 30.0;
+const YIN_MAX_FREQUENCY: f32 = 2000.0;
+/// Below this the FFT bins are too coarse, and YIN takes over.
+const FFT_MIN_FREQUENCY: f32 = 80.0;
+const HIGH_PASS_Q: f64 = std::f64::consts::FRAC_1_SQRT_2;
+/// Factor the input is downsampled by in low-range mode.
+pub const DECIMATION: usize = 4;
+/// Cutoff of the anti-aliasing low-pass ahead of decimation.
+const ANTI_ALIAS_CUTOFF: f64 = 1000.0;
+/// Q of the two sections of a fourth-order Butterworth low-pass.
+const ANTI_ALIAS_Q: [f64; 2] = [0.5412, 1.3066];
+const LOW_RANGE_MIN_FREQUENCY: f32 = 25.0;
+const LOW_RANGE_MAX_FREQUENCY: f32 = 400.0;
+const HUM_NOTCH_Q: f64 = 8.0;
+const VIBRATO_SAMPLE_RATE: f32 = 100.0;
+const MIN_VIBRATO_RATE: f32 = 3.0;
+const MAX_VIBRATO_RATE: f32 = 10.0;
+/// Narrower oscillations are indistinguishable from detection jitter.
+const MIN_VIBRATO_WIDTH_CENTS: f32 = 10.0;
+/// How closely the cents trace must repeat itself one period later.
+const MIN_VIBRATO_CORRELATION: f32 = 0.5;
+/// Level of the newest audio compared for onsets.
+const ONSET_FRAME_SECONDS: f32 = 0.01;
+/// A frame this many times louder than the one before starts a note.
+const ONSET_RMS_RATIO: f32 = 2.0;
+/// Frames quieter than this are never an onset.
+const ONSET_MIN_RMS: f32 = 0.01;
+
+/// Lowest frequency the detector can find, with the low range on.
+pub const MIN_DETECTABLE_FREQUENCY: f32 = LOW_RANGE_MIN_FREQUENCY;
+/// Highest frequency the detector can find.
+pub const MAX_DETECTABLE_FREQUENCY: f32 = 4200.0;
+/// Frequencies searched when no instrument narrows them, in Hz: the piano's
+/// lowest A up to just above its top C.
+pub const CHROMATIC_RANGE: (f32, f32) = (27.0, MAX_DETECTABLE_FREQUENCY);
+
+/// FFT candidates below this are re-estimated in the time domain unless
+/// configured otherwise, in Hz.
+pub const DEFAULT_CROSSOVER_FREQUENCY: f32 = 160.0;
+/// The FFT can lock onto the second or third harmonic of a low note, so
+/// candidates up to this many times the crossover are checked too.
+const SUBHARMONIC_CHECKS: usize = 3;
+/// Estimates this close in cents to a whole multiple of each other are taken
+/// for the FFT having picked a harmonic.
+const HARMONIC_ERROR_CENTS: f32 = 50.0;
+/// Share of the spectrum's energy a note's harmonics must hold unless
+/// configured otherwise; fan noise and speech hold far less.
+pub const DEFAULT_MIN_TONALITY: f32 = 0.4;
+/// Harmonics counted towards tonality. Further up, stretched partials miss
+/// their multiples and noise fills the gaps between them.
+const TONALITY_HARMONICS: usize = 6;
+
+/// Analysis sizes the detector can be set to.
+pub const FFT_SIZES: [usize; 3] = [4096, 8192, 16384];
+/// Analysis size used unless configured otherwise.
+pub const DEFAULT_FFT_SIZE: usize = 8192;
+/// How many new samples can arrive between analyses.
+pub const HOP_SIZES: [usize; 4] = [256, 512, 1024, 2048];
+/// Hop used unless configured otherwise.
+pub const DEFAULT_HOP_SIZE: usize = 1024;
+
+/// How finely the FFT peak is located between bins. Higher settings zero-pad
+/// the windowed block before the transform, which costs a larger FFT.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Precision {
+    /// No padding; the peak is interpolated on log magnitudes.
+    #[default]
+    Standard,
+    /// 2× zero-padding.
+    High,
+    /// 4× zero-padding.
+    Maximum,
+}
+
+impl Precision {
+    /// Every setting, in menu order.
+    pub const ALL: [Precision; 3] = [Precision::Standard, Precision::High, Precision::Maximum];
+
+    /// Name shown in the settings panel.
+    pub fn label(self) -> &'static str {
+        match self {
+            Precision::Standard => "Standard",
+            Precision::High => "High (2× FFT)",
+            Precision::Maximum => "Maximum (4× FFT)",
+        }
+    }
+
+    /// How many times longer the FFT is than the analysis block.
+    pub fn zero_padding(self) -> usize {
+        match self {
+            Precision::Standard => 1,
+            Precision::High => 2,
+            Precision::Maximum => 4,
+        }
+    }
+}
+
+/// Window applied to each analysis block before the FFT.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WindowKind {
+    /// Good all-round choice.
+    #[default]
+    Hann,
+    /// Slightly narrower main lobe than Hann.
+    Hamming,
+    /// Lowest sidelobes, for separating close partials.
+    BlackmanHarris,
+}
+
+impl WindowKind {
+    /// Every window, in menu order.
+    pub const ALL: [WindowKind; 3] = [
+        WindowKind::Hann,
+        WindowKind::Hamming,
+        WindowKind::BlackmanHarris,
+    ];
+
+    /// Name shown in the settings panel.
+    pub fn label(self) -> &'static str {
+        match self {
+            WindowKind::Hann => "Hann",
+            WindowKind::Hamming => "Hamming",
+            WindowKind::BlackmanHarris => "Blackman-Harris",
+        }
+    }
+
+    /// Half-width of the main lobe in bins of an unpadded FFT.
+    fn main_lobe_bins(self) -> usize {
+        match self {
+            WindowKind::Hann | WindowKind::Hamming => 2,
+            WindowKind::BlackmanHarris => 4,
+        }
+    }
+
+    /// The window coefficients for a block of `size` samples.
+    pub fn coefficients(self, size: usize) -> Vec<f32> {
+        let cosine_terms: &[f32] = match self {
+            WindowKind::Hann => &[0.5, 0.5],
+            WindowKind::Hamming => &[0.54, 0.46],
+            WindowKind::BlackmanHarris => &[0.35875, 0.48829, 0.14128, 0.01168],
+        };
+
+        (0..size)
+            .map(|i| {
+                let x = 2.0 * PI * i as f32 / (size - 1) as f32;
+                cosine_terms
+                    .iter()
+                    .enumerate()
+                    .map(|(k, &a)| {
+                        let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+                        sign * a * (k as f32 * x).cos()
+                    })
+                    .sum()
+            })
+            .collect()
+    }
+}
+
+/// Mains frequency to notch out before detection.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HumRejection {
+    /// No notch.
+    #[default]
+    Off,
+    /// Notch 50 Hz and 100 Hz.
+    Mains50,
+    /// Notch 60 Hz and 120 Hz.
+    Mains60,
+}
+
+impl HumRejection {
+    /// Every option, in menu order.
+    pub const ALL: [HumRejection; 3] = [
+        HumRejection::Off,
+        HumRejection::Mains50,
+        HumRejection::Mains60,
+    ];
+
+    /// Name shown in the settings panel.
+    pub fn label(self) -> &'static str {
+        match self {
+            HumRejection::Off => "Off",
+            HumRejection::Mains50 => "50 Hz",
+            HumRejection::Mains60 => "60 Hz",
+        }
+    }
+
+    fn mains_frequency(self) -> Option<f64> {
+        match self {
+            HumRejection::Off => None,
+            HumRejection::Mains50 => Some(50.0),
+            HumRejection::Mains60 => Some(60.0),
+        }
+    }
+}
+
+/// Whether the detector works on a decimated signal for bass instruments.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LowRange {
+    /// Always the full range.
+    #[default]
+    Off,
+    /// Switch to low range while the note is below [`LOW_RANGE_AUTO_BELOW`].
+    Auto,
+    /// Always low range, 25–400 Hz.
+    On,
+}
+
+impl LowRange {
+    /// Every option, in menu order.
+    pub const ALL: [LowRange; 3] = [LowRange::Off, LowRange::Auto, LowRange::On];
+
+    /// Name shown in the settings panel.
+    pub fn label(self) -> &'static str {
+        match self {
+            LowRange::Off => "Full",
+            LowRange::Auto => "Auto",
+            LowRange::On => "Bass",
+        }
+    }
+
+    /// Whether to analyse in low range while `note_frequency` is tracked.
+    pub fn active(self, note_frequency: Option<f32>) -> bool {
+        match self {
+            LowRange::Off => false,
+            LowRange::Auto => note_frequency.is_some_and(|f| f < LOW_RANGE_AUTO_BELOW),
+            LowRange::On => true,
+        }
+    }
+}
+
+/// In [`LowRange::Auto`], notes below this many Hz switch to low range.
+pub const LOW_RANGE_AUTO_BELOW: f32 = 110.0;
+
+/// Second-order IIR section with RBJ cookbook coefficients, normalized so a0 = 1.
+#[derive(Clone, Copy, Debug)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+}
+
+impl Biquad {
+    fn new(b: [f64; 3], a: [f64; 3]) -> Self {
+        Self {
+            b: b.map(|b| b / a[0]),
+            a: [a[1] / a[0], a[2] / a[0]],
+        }
+    }
+
+    fn high_pass(cutoff: f64, q: f64, sample_rate: f64) -> Self {
+        let w0 = 2.0 * std::f64::consts::PI * cutoff / sample_rate;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * q);
+        Self::new(
+            [(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0],
+            [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        )
+    }
+
+    fn low_pass(cutoff: f64, q: f64, sample_rate: f64) -> Self {
+        let w0 = 2.0 * std::f64::consts::PI * cutoff / sample_rate;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * q);
+        Self::new(
+            [(1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0],
+            [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        )
+    }
+
+    fn notch(frequency: f64, q: f64, sample_rate: f64) -> Self {
+        let w0 = 2.0 * std::f64::consts::PI * frequency / sample_rate;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * q);
+        Self::new(
+            [1.0, -2.0 * cos, 1.0],
+            [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        )
+    }
+
+    /// Filters `samples` in place, starting from silence.
+    fn process(&self, samples: &mut [f32]) {
+        let (mut z1, mut z2) = (0.0f64, 0.0f64);
+        for sample in samples {
+            let x = *sample as f64;
+            let y = self.b[0] * x + z1;
+            z1 = self.b[1] * x - self.a[0] * y + z2;
+            z2 = self.b[2] * x - self.a[1] * y;
+            *sample = y as f32;
+        }
+    }
+}
+
+/// High-pass plus notches at the mains frequency and its first harmonic, run
+/// over each analysis block before pitch detection. Does nothing while hum
+/// rejection is off.
+#[derive(Clone, Debug)]
+pub struct PreFilter {
+    sample_rate: f32,
+    hum_rejection: HumRejection,
+    high_pass_cutoff: f32,
+    sections: Vec<Biquad>,
+}
+
+impl PreFilter {
+    /// A filter for `sample_rate` with the given hum rejection and high-pass cutoff.
+    pub fn new(sample_rate: f32, hum_rejection: HumRejection, high_pass_cutoff: f32) -> Self {
+        let mut sections = Vec::new();
+        if let Some(mains) = hum_rejection.mains_frequency() {
+            let sample_rate = sample_rate as f64;
+            if high_pass_cutoff > 0.0 {
+                sections.push(Biquad::high_pass(
+                    high_pass_cutoff as f64,
+                    HIGH_PASS_Q,
+                    sample_rate,
+                ));
+            }
+            for frequency in [mains, mains * 2.0] {
+                sections.push(Biquad::notch(frequency, HUM_NOTCH_Q, sample_rate));
+            }
+        }
+
+        Self {
+            sample_rate,
+            hum_rejection,
+            high_pass_cutoff,
+            sections,
+        }
+    }
+
+    /// Returns `samples` untouched when inactive, otherwise a filtered copy
+    /// written into `scratch`.
+    pub fn apply<'a>(&self, samples: &'a [f32], scratch: &'a mut Vec<f32>) -> &'a [f32] {
+        if self.sections.is_empty() {
+            return samples;
+        }
+
+        scratch.clear();
+        scratch.extend_from_slice(samples);
+        for section in &self.sections {
+            section.process(scratch);
+        }
+        scratch
+    }
+
+    fn matches(
+        &self,
+        sample_rate: f32,
+        hum_rejection: HumRejection,
+        high_pass_cutoff: f32,
+    ) -> bool {
+        self.sample_rate == sample_rate
+            && self.hum_rejection == hum_rejection
+            && self.high_pass_cutoff == high_pass_cutoff
+    }
+}
+
+/// A single pitch detection.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PitchEstimate {
+    /// Detected fundamental in Hz.
+    pub frequency: f32,
+    /// Level of the fundamental, on the FFT magnitude scale.
+    pub magnitude: f32,
+    /// How periodic the signal looks, from 0 (noise) to 1 (a clean tone).
+    pub confidence: f32,
+}
+
+/// Where the fundamental is expected to be, e.g. because the player has said
+/// which octave they are in; detection only searches this close to it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FrequencyPrior {
+    /// Expected fundamental in Hz.
+    pub center: f32,
+    /// How far either side of `center` is searched, in cents.
+    pub range_cents: f32,
+}
+
+impl FrequencyPrior {
+    /// Lowest and highest frequency searched, in Hz.
+    pub fn bounds(&self) -> (f32, f32) {
+        let ratio = 2.0f32.powf(self.range_cents / 1200.0);
+        (self.center / ratio, self.center * ratio)
+    }
+}
+
+/// Where the vertex of a parabola through three evenly spaced values lies
+/// relative to the middle one, within half a step either way. Flat values have
+/// no vertex and give 0.
+fn parabolic_offset(left: f32, center: f32, right: f32) -> f32 {
+    let denominator = left - 2.0 * center + right;
+    if denominator.abs() <= f32::EPSILON * center.abs().max(1.0) {
+        return 0.0;
+    }
+    (0.5 * (left - right) / denominator).clamp(-0.5, 0.5)
+}
+
+/// Geometric over arithmetic mean: close to 1 for a flat (noise-like)
+/// spectrum and close to 0 when energy sits in a few peaks.
+fn spectral_flatness(magnitudes: &[f32]) -> f32 {
+    let mean = magnitudes.iter().sum::<f32>() / magnitudes.len() as f32;
+    if mean <= 0.0 {
+        return 1.0;
+    }
+    let log_mean =
+        magnitudes.iter().map(|&m| (m + 1e-12).ln()).sum::<f32>() / magnitudes.len() as f32;
+    (log_mean.exp() / mean).clamp(0.0, 1.0)
+}
+
+/// FFT pitch detector with harmonic product spectrum refinement and a YIN
+/// fallback for low notes. All working buffers are allocated up front, so
+/// analysing a block doesn't allocate.
 pub struct PitchDetector {
     fft: Arc<dyn RealToComplex<f32>>,
     buffer_size: usize,
+    precision: Precision,
     sample_rate: f32,
+    window_kind: WindowKind,
     window: Vec<f32>,
+    magnitudes: Vec<f32>,
+    yin: YinDetector,
+    pre_filter: PreFilter,
+    filtered: Vec<f32>,
+    /// The last analysed block after windowing.
+    windowed: Vec<f32>,
+    /// FFT input, which the transform overwrites, so `windowed` is kept apart.
+    /// Longer than the block when zero-padding.
+    input: Vec<f32>,
+    spectrum: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+    /// Whether blocks are decimated before the FFT.
+    low_range: bool,
+    /// Window over a decimated block, which fills a quarter of the FFT.
+    low_range_window: Vec<f32>,
+    anti_alias: [Biquad; 2],
+    /// Lowest and highest fundamental searched for, in Hz.
+    min_frequency: f32,
+    max_frequency: f32,
+    /// FFT candidates below this are re-estimated with YIN.
+    crossover_frequency: f32,
+    /// Share of spectral energy a detection's harmonics need.
+    min_tonality: f32,
+    /// Narrows the search further while set.
+    prior: Option<FrequencyPrior>,
+}
+
+fn anti_alias_filter(sample_rate: f32) -> [Biquad; 2] {
+    ANTI_ALIAS_Q.map(|q| Biquad::low_pass(ANTI_ALIAS_CUTOFF, q, sample_rate as f64))
 }
 
 impl PitchDetector {
-    pub fn new(buffer_size: usize, sample_rate: f32) -> Self {
+    /// A detector that analyses blocks of `buffer_size` samples.
+    pub fn new(buffer_size: usize, sample_rate: f32, window_kind: WindowKind) -> Self {
         let mut planner = RealFftPlanner::<f32>::new();
         let fft = planner.plan_fft_forward(buffer_size);
 
-        let window = (0..buffer_size)
-            .map(|i| {
-                let x = i as f32 / (buffer_size - 1) as f32;
-                0.5 * (1.0 - (2.0 * std::f32::consts::PI * x).cos())
-            })
-            .collect();
-
         Self {
-            fft,
             buffer_size,
+            precision: Precision::Standard,
             sample_rate,
-            window,
+            window_kind,
+            window: window_kind.coefficients(buffer_size),
+            magnitudes: vec![0.0; buffer_size / 2 + 1],
+            yin: YinDetector::new(buffer_size, sample_rate),
+            pre_filter: PreFilter::new(sample_rate, HumRejection::Off, 0.0),
+            filtered: Vec::with_capacity(buffer_size),
+            windowed: Vec::with_capacity(buffer_size),
+            input: fft.make_input_vec(),
+            spectrum: fft.make_output_vec(),
+            scratch: fft.make_scratch_vec(),
+            fft,
+            low_range: false,
+            low_range_window: window_kind.coefficients(buffer_size / DECIMATION),
+            anti_alias: anti_alias_filter(sample_rate),
+            min_frequency: CHROMATIC_RANGE.0,
+            max_frequency: CHROMATIC_RANGE.1,
+            crossover_frequency: DEFAULT_CROSSOVER_FREQUENCY,
+            min_tonality: DEFAULT_MIN_TONALITY,
+            prior: None,
         }
     }
 
-    pub fn detect_pitch(&mut self, samples: &[f32]) -> Option<(f32, f32)> {
-        if samples.len() < self.buffer_size {
-            return None;
+    /// Adapts to a new input sample rate.
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        if sample_rate != self.sample_rate {
+            self.anti_alias = anti_alias_filter(sample_rate);
         }
+        self.sample_rate = sample_rate;
+        self.yin.set_sample_rate(sample_rate);
+        let filter = &self.pre_filter;
+        self.set_pre_filter(filter.hum_rejection, filter.high_pass_cutoff);
+    }
 
-        let mut input: Vec<f32> = samples
-            .iter()
-            .take(self.buffer_size)
-            .zip(self.window.iter())
-            .map(|(sample, window)| sample * window)
-            .collect();
+    /// Changes the hum rejection and high-pass cutoff, keeping the filter state
+    /// if nothing changed.
+    pub fn set_pre_filter(&mut self, hum_rejection: HumRejection, high_pass_cutoff: f32) {
+        if !self
+            .pre_filter
+            .matches(self.sample_rate, hum_rejection, high_pass_cutoff)
+        {
+            self.pre_filter = PreFilter::new(self.sample_rate, hum_rejection, high_pass_cutoff);
+        }
+    }
 
-        let mut spectrum = self.fft.make_output_vec();
+    /// Sample rate the detector expects, in Hz.
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
 
-        self.fft.process(&mut input, &mut spectrum).ok()?;
+    /// Analysis size in samples.
+    pub fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
 
-        let mut max_magnitude = 0.0;
-        let mut max_index = 0;
+    /// Window applied before the FFT.
+    pub fn window_kind(&self) -> WindowKind {
+        self.window_kind
+    }
 
-        let min_freq_bin = (80.0 * self.buffer_size as f32 / self.sample_rate) as usize;
-        let max_freq_bin = (2000.0 * self.buffer_size as f32 / self.sample_rate) as usize;
+    /// Changes how much the FFT is zero-padded, replanning it if that changed.
+    pub fn set_precision(&mut self, precision: Precision) {
+        if precision.zero_padding() != self.precision.zero_padding() {
+            let fft_size = self.buffer_size * precision.zero_padding();
+            let fft = RealFftPlanner::<f32>::new().plan_fft_forward(fft_size);
+            self.input = fft.make_input_vec();
+            self.spectrum = fft.make_output_vec();
+            self.scratch = fft.make_scratch_vec();
+            self.magnitudes = vec![0.0; fft_size / 2 + 1];
+            self.fft = fft;
+        }
+        self.precision = precision;
+    }
 
-        for (i, complex) in spectrum.iter().enumerate().skip(min_freq_bin) {
-            if i > max_freq_bin {
-                break;
-            }
+    /// How finely peaks are located between bins.
+    pub fn precision(&self) -> Precision {
+        self.precision
+    }
 
-            let magnitude = (complex.re * complex.re + complex.im * complex.im).sqrt();
-            if magnitude > max_magnitude {
-                max_magnitude = magnitude;
-                max_index = i;
-            }
-        }
+    /// Limits the fundamentals searched for to `min_frequency`–`max_frequency`
+    /// Hz, within what the detector can find at all, e.g. to keep a violin
+    /// tuner from locking onto rumble.
+    pub fn set_frequency_range(&mut self, min_frequency: f32, max_frequency: f32) {
+        self.min_frequency =
+            min_frequency.clamp(MIN_DETECTABLE_FREQUENCY, MAX_DETECTABLE_FREQUENCY);
+        self.max_frequency = max_frequency.clamp(self.min_frequency, MAX_DETECTABLE_FREQUENCY);
+    }
 
-        if max_magnitude < 0.005 {
-            return None;
+    /// Lowest and highest fundamental searched for, in Hz.
+    pub fn frequency_range(&self) -> (f32, f32) {
+        (self.min_frequency, self.max_frequency)
+    }
+
+    /// Only looks for fundamentals near `prior`, within the frequency range,
+    /// e.g. to hold a note the detector keeps reading an octave off in the
+    /// octave the player asserts; `None` searches the whole range again. The
+    /// spectrum handed out still covers the whole range.
+    pub fn set_frequency_prior(&mut self, prior: Option<FrequencyPrior>) {
+        self.prior = prior;
+    }
+
+    /// Where the fundamental is expected, if anywhere.
+    pub fn frequency_prior(&self) -> Option<FrequencyPrior> {
+        self.prior
+    }
+
+    /// Lowest and highest fundamental detections may report: the frequency
+    /// range narrowed to the prior, or the prior alone where they don't
+    /// overlap.
+    fn search_range(&self) -> (f32, f32) {
+        let Some(prior) = self.prior else {
+            return (self.min_frequency, self.max_frequency);
+        };
+        let (low, high) = prior.bounds();
+        let (low, high) = (
+            low.clamp(MIN_DETECTABLE_FREQUENCY, MAX_DETECTABLE_FREQUENCY),
+            high.clamp(MIN_DETECTABLE_FREQUENCY, MAX_DETECTABLE_FREQUENCY),
+        );
+        if high < self.min_frequency || low > self.max_frequency {
+            return (low, high);
         }
+        (low.max(self.min_frequency), high.min(self.max_frequency))
+    }
 
-        let frequency = max_index as f32 * self.sample_rate / self.buffer_size as f32;
+    /// Sets the frequency in Hz below which an FFT candidate is checked
+    /// against a time-domain estimate.
+    pub fn set_crossover_frequency(&mut self, crossover_frequency: f32) {
+        self.crossover_frequency = crossover_frequency;
+    }
+
+    /// Frequency in Hz below which FFT candidates are re-estimated.
+    pub fn crossover_frequency(&self) -> f32 {
+        self.crossover_frequency
+    }
+
+    /// Sets the share of spectral energy (0–1) a note's harmonics must hold
+    /// for it to be reported; 0 reports anything with a peak.
+    pub fn set_min_tonality(&mut self, min_tonality: f32) {
+        self.min_tonality = min_tonality.clamp(0.0, 1.0);
+    }
+
+    /// Share of spectral energy a detection's harmonics need.
+    pub fn min_tonality(&self) -> f32 {
+        self.min_tonality
+    }
+
+    /// Switches low-range mode: blocks are low-passed and downsampled 4× before
+    /// the FFT, which then spans the same time with bins a quarter as wide, and
+    /// only 25–400 Hz of the frequency range is searched.
+    pub fn set_low_range(&mut self, low_range: bool) {
+        self.low_range = low_range;
+    }
 
-        let refined_frequency = if max_index > 0 && max_index < spectrum.len() - 1 {
-            let left = (spectrum[max_index - 1].re * spectrum[max_index - 1].re
-                + spectrum[max_index - 1].im * spectrum[max_index - 1].im)
-                .sqrt();
-            let center = max_magnitude;
-            let right = (spectrum[max_index + 1].re * spectrum[max_index + 1].re
-                + spectrum[max_index + 1].im * spectrum[max_index + 1].im)
-                .sqrt();
+    /// Whether low-range mode is on.
+    pub fn low_range(&self) -> bool {
+        self.low_range
+    }
 
-            let offset = 0.5 * (left - right) / (left - 2.0 * center + right);
-            (max_index as f32 + offset) * self.sample_rate / self.buffer_size as f32
+    /// Sample rate of the signal the FFT sees.
+    fn analysis_rate(&self) -> f32 {
+        if self.low_range {
+            self.sample_rate / DECIMATION as f32
         } else {
-            frequency
+            self.sample_rate
+        }
+    }
+
+    /// Detects the pitch of the latest `buffer_size` samples, or `None` if
+    /// nothing pitched is present. The FFT runs first; a candidate in the low
+    /// register, or none at all, is re-estimated with YIN over the same
+    /// samples, which is far more precise there. Either is dropped if its
+    /// harmonics hold less than `min_tonality` of the spectrum's energy.
+    pub fn detect_pitch(&mut self, samples: &[f32]) -> Option<PitchEstimate> {
+        let mut filtered = std::mem::take(&mut self.filtered);
+        let samples = self.pre_filter.apply(samples, &mut filtered);
+
+        let fft_estimate = self.detect_pitch_fft(samples);
+        let detection = self.cross_check(samples, fft_estimate);
+
+        self.filtered = filtered;
+        detection.filter(|estimate| self.is_tonal(estimate.frequency))
+    }
+
+    /// Like `detect_pitch`, but also copies the magnitude spectrum between the
+    /// minimum and maximum search frequencies into `spectrum`, reusing its
+    /// allocation.
+    pub fn detect_pitch_with_spectrum(
+        &mut self,
+        samples: &[f32],
+        spectrum: &mut Vec<f32>,
+    ) -> Option<PitchEstimate> {
+        let mut filtered = std::mem::take(&mut self.filtered);
+        let samples = self.pre_filter.apply(samples, &mut filtered);
+
+        let fft_estimate = self.detect_pitch_fft(samples);
+
+        spectrum.clear();
+        if samples.len() >= self.buffer_size {
+            let (min_bin, max_bin) = self.search_bins();
+            spectrum.extend_from_slice(&self.magnitudes[min_bin..=max_bin]);
+        }
+
+        let detection = self.cross_check(samples, fft_estimate);
+        self.filtered = filtered;
+        detection.filter(|estimate| self.is_tonal(estimate.frequency))
+    }
+
+    /// Whether a detection at `frequency` passes the tonality gate.
+    fn is_tonal(&self, frequency: f32) -> bool {
+        self.min_tonality <= 0.0 || self.tonality(frequency) >= self.min_tonality
+    }
+
+    /// Share of the energy in the searched part of the spectrum from
+    /// `frequency` up that is held by its first `TONALITY_HARMONICS`
+    /// harmonics. Broadband sound such as typing, talking or air conditioning
+    /// can still put a peak above the magnitude gate, but spreads its energy
+    /// everywhere else too. Hum and rumble below the note are left out.
+    fn tonality(&self, frequency: f32) -> f32 {
+        let decimation = if self.low_range { DECIMATION } else { 1 };
+        let lobe = self.window_kind.main_lobe_bins() * self.precision.zero_padding() * decimation;
+        let bin_width = self.bin_width();
+        let (_, max_bin) = self.search_bins();
+        let fundamental_bin = (frequency / bin_width).round() as usize;
+        let min_bin = fundamental_bin.saturating_sub(lobe).clamp(1, max_bin);
+        let energy = |bins: std::ops::RangeInclusive<usize>| -> f32 {
+            self.magnitudes[bins].iter().map(|m| m * m).sum()
         };
+        let total = energy(min_bin..=max_bin);
+        if total <= 0.0 {
+            return 0.0;
+        }
 
-        Some((refined_frequency, max_magnitude))
+        let mut harmonic_energy = 0.0;
+        let mut last_counted = None;
+        for harmonic in 1..=TONALITY_HARMONICS {
+            let center = (frequency * harmonic as f32 / bin_width).round() as usize;
+            // Lobes of a low note's harmonics can overlap; count each bin once.
+            let first = center
+                .saturating_sub(lobe)
+                .max(min_bin)
+                .max(last_counted.map_or(0, |last| last + 1));
+            let last = (center + lobe).min(max_bin);
+            if first > last {
+                continue;
+            }
+            harmonic_energy += energy(first..=last);
+            last_counted = Some(last);
+        }
+        harmonic_energy / total
     }
-}
 
-#[derive(Debug, Clone)]
-pub struct Note {
-    pub name: String,
-    pub frequency: f32,
-    pub cents_off: f32,
-}
+    /// Settles between the FFT's estimate and a time-domain one when the FFT
+    /// found nothing, or something that is or could be a harmonic of a note
+    /// below the crossover. When YIN finds the FFT's estimate to be its second
+    /// or third harmonic, the FFT was fooled by the overtones and YIN is
+    /// taken; otherwise the below-crossover estimate is the more confident
+    /// one. Low-range mode already resolves the low register and keeps the
+    /// FFT's.
+    fn cross_check(
+        &mut self,
+        samples: &[f32],
+        fft_estimate: Option<PitchEstimate>,
+    ) -> Option<PitchEstimate> {
+        let checked_below = self.crossover_frequency * SUBHARMONIC_CHECKS as f32;
+        if self.low_range
+            || fft_estimate.is_some_and(|estimate| estimate.frequency >= checked_below)
+        {
+            return fft_estimate;
+        }
+        let Some(yin_estimate) = self.detect_low_register(samples) else {
+            return fft_estimate;
+        };
+        let Some(fft_estimate) = fft_estimate else {
+            return Some(yin_estimate);
+        };
 
-pub fn frequency_to_note(frequency: f32) -> Note {
-    let a4_freq = 440.0;
+        let ratio = fft_estimate.frequency / yin_estimate.frequency;
+        let harmonic_error = (2..=SUBHARMONIC_CHECKS).any(|harmonic| {
+            (1200.0 * (ratio / harmonic as f32).log2()).abs() < HARMONIC_ERROR_CENTS
+        });
+        if harmonic_error {
+            return Some(yin_estimate);
+        }
+        if fft_estimate.frequency >= self.crossover_frequency {
+            return Some(fft_estimate);
+        }
+        if yin_estimate.confidence >= fft_estimate.confidence {
+            Some(yin_estimate)
+        } else {
+            Some(fft_estimate)
+        }
+    }
 
-    let semitones_from_a4 = 12.0 * (frequency / a4_freq).log2();
-    let nearest_semitone = semitones_from_a4.round() as i32;
+    /// Measures several notes sounding at once, such as strummed open strings:
+    /// for each of `targets` (in Hz), the frequency and magnitude of the
+    /// strongest spectral peak within 80 cents of it, or `None` if there is no
+    /// peak there. Each peak is refined between bins, and averaged with its
+    /// second harmonic when that is present, which doubles the resolution for
+    /// low strings.
+    pub fn detect_multi_pitch(
+        &mut self,
+        samples: &[f32],
+        targets: &[f32],
+    ) -> Vec<Option<(f32, f32)>> {
+        let mut filtered = std::mem::take(&mut self.filtered);
+        let samples = self.pre_filter.apply(samples, &mut filtered);
+        let analysed = self.analyse_spectrum(samples);
+        self.filtered = filtered;
+        if !analysed {
+            return vec![None; targets.len()];
+        }
 
-    let cents_off = (semitones_from_a4 - nearest_semitone as f32) * 100.0;
+        targets
+            .iter()
+            .map(|&target| {
+                let (frequency, magnitude) = self.peak_near(target)?;
+                let estimate = match self.peak_near(2.0 * frequency) {
+                    Some((harmonic, harmonic_magnitude))
+                        if harmonic_magnitude >= magnitude * MULTI_PITCH_HARMONIC_RATIO =>
+                    {
+                        (frequency * magnitude + harmonic / 2.0 * harmonic_magnitude)
+                            / (magnitude + harmonic_magnitude)
+                    }
+                    _ => frequency,
+                };
+                Some((estimate, magnitude))
+            })
+            .collect()
+    }
 
-    let note_names = [
-        "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
-    ];
+    /// Measures the first `count` partials of a note whose fundamental is near
+    /// `fundamental`, indexed by partial number - 1, `None` where there is no
+    /// peak. On a stiff string such as a piano's the partials run
+    /// progressively sharp of whole multiples, so each is searched for where
+    /// the ones below it predict.
+    pub fn detect_partials(
+        &mut self,
+        samples: &[f32],
+        fundamental: f32,
+        count: usize,
+    ) -> Vec<Option<f32>> {
+        let mut partials = vec![None; count];
+        let mut filtered = std::mem::take(&mut self.filtered);
+        let samples = self.pre_filter.apply(samples, &mut filtered);
+        let analysed = self.analyse_spectrum(samples);
+        self.filtered = filtered;
+        if !analysed || count == 0 {
+            return partials;
+        }
 
-    let semitones_from_c4 = nearest_semitone + 9;
+        let Some((first, _)) = self
+            .peak_near(fundamental)
+            .filter(|&(_, magnitude)| magnitude >= MIN_PEAK_MAGNITUDE)
+        else {
+            return partials;
+        };
+        partials[0] = Some(first);
+        let nyquist = self.analysis_rate() / 2.0;
+        for number in 2..=count {
+            let n = number as f32;
+            let inharmonicity = estimate_inharmonicity(&partials[..number - 1]).unwrap_or(0.0);
+            let predicted =
+                n * first * ((1.0 + inharmonicity * n * n) / (1.0 + inharmonicity)).sqrt();
+            if predicted * 2f32.powf(MULTI_PITCH_SEARCH_CENTS / 1200.0) >= nyquist {
+                break;
+            }
+            partials[number - 1] = self
+                .peak_near(predicted)
+                .filter(|&(_, magnitude)| magnitude >= MIN_PEAK_MAGNITUDE)
+                .map(|(frequency, _)| frequency);
+        }
+        partials
+    }
 
-    let note_index = ((semitones_from_c4 % 12) + 12) % 12;
+    /// Picks up to `max_peaks` notes sounding together, such as a double stop:
+    /// the frequency and magnitude of the strongest spectral peaks, strongest
+    /// first, leaving out any peak that is a harmonic of another. Notes an
+    /// octave apart are indistinguishable from one note's harmonics and come
+    /// back as one.
+    pub fn detect_peaks(&mut self, samples: &[f32], max_peaks: usize) -> Vec<(f32, f32)> {
+        let mut filtered = std::mem::take(&mut self.filtered);
+        let samples = self.pre_filter.apply(samples, &mut filtered);
+        let analysed = self.analyse_spectrum(samples);
+        self.filtered = filtered;
+        if !analysed {
+            return Vec::new();
+        }
 
-    let octave = if semitones_from_c4 >= 0 {
-        4 + semitones_from_c4 / 12
-    } else {
-        4 + (semitones_from_c4 - 11) / 12
-    };
+        let (min_bin, max_bin) = self.search_bins();
+        let (min_bin, max_bin) = (min_bin.max(1), max_bin.min(self.magnitudes.len() - 2));
+        let strongest = self.magnitudes[min_bin..=max_bin]
+            .iter()
+            .copied()
+            .fold(0.0, f32::max);
+        if strongest < MIN_PEAK_MAGNITUDE {
+            return Vec::new();
+        }
 
-    let note_name = format!("{}{}", note_names[note_index as usize], octave);
+        let mut candidates: Vec<usize> = (min_bin..=max_bin)
+            .filter(|&bin| self.is_local_peak(bin, strongest * PEAK_MIN_RATIO))
+            .collect();
+        candidates.sort_by(|&a, &b| self.magnitudes[b].total_cmp(&self.magnitudes[a]));
 
-    Note {
-        name: note_name,
-        frequency,
-        cents_off,
+        let mut peaks: Vec<(f32, f32)> = Vec::new();
+        for bin in candidates {
+            let peak = self.refine_peak(bin);
+            if peaks.iter().any(|&(note, _)| is_harmonic(peak.0, note)) {
+                continue;
+            }
+            // A weaker fundamental under a note already taken means that
+            // note was really its harmonic.
+            if let Some(note) = peaks
+                .iter_mut()
+                .find(|(note, _)| is_harmonic(*note, peak.0))
+            {
+                *note = peak;
+                continue;
+            }
+            if peaks.len() < max_peaks {
+                peaks.push(peak);
+            }
+        }
+        peaks
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// The refined frequency and magnitude of the strongest local maximum
+    /// of the spectrum within [`MULTI_PITCH_SEARCH_CENTS`] of `frequency`.
+    fn peak_near(&self, frequency: f32) -> Option<(f32, f32)> {
+        let bin_width = self.bin_width();
+        let spread = 2f32.powf(MULTI_PITCH_SEARCH_CENTS / 1200.0);
+        let low = ((frequency / spread / bin_width).floor() as usize).max(1);
+        let high =
+            ((frequency * spread / bin_width).ceil() as usize).min(self.magnitudes.len() - 2);
+        let bin = (low..=high)
+            .filter(|&bin| {
+                self.magnitudes[bin] > self.magnitudes[bin - 1]
+                    && self.magnitudes[bin] >= self.magnitudes[bin + 1]
+            })
+            .max_by(|&a, &b| self.magnitudes[a].total_cmp(&self.magnitudes[b]))?;
+        Some(self.refine_peak(bin))
+    }
 
-    #[test]
-    fn test_note_mapping() {
-        let test_cases = [
-            (440.0, "A4"),
-            (493.88, "B4"),
-            (523.25, "C5"),
-            (392.0, "G4"),
-            (220.0, "A3"),
-            (880.0, "A5"),
-        ];
+    /// Frequency and magnitude of the peak around `bin`, between bins.
+    fn refine_peak(&self, bin: usize) -> (f32, f32) {
+        let bin_width = self.bin_width();
+        // Golden-section search of the windowed block's spectrum between the
+        // neighbouring bins; the FFT alone is too coarse for low strings.
+        let ratio = (5f32.sqrt() - 1.0) / 2.0;
+        let (mut left, mut right) = ((bin - 1) as f32 * bin_width, (bin + 1) as f32 * bin_width);
+        for _ in 0..PEAK_REFINE_STEPS {
+            let inner_left = right - ratio * (right - left);
+            let inner_right = left + ratio * (right - left);
+            if self.magnitude_at(inner_left) < self.magnitude_at(inner_right) {
+                left = inner_left;
+            } else {
+                right = inner_right;
+            }
+        }
+        let peak = (left + right) / 2.0;
+        (peak, self.magnitude_at(peak))
+    }
 
-        for (freq, expected) in test_cases.iter() {
-            let note = frequency_to_note(*freq);
-            println!(
-                "{:.2} Hz -> {} (expected {}), cents: {:.1}",
-                freq, note.name, expected, note.cents_off
+    /// Magnitude of the last windowed block at `frequency`, which needn't fall
+    /// on a bin; on the same scale as the FFT magnitudes.
+    fn magnitude_at(&self, frequency: f32) -> f32 {
+        let step = 2.0 * std::f64::consts::PI * frequency as f64 / self.analysis_rate() as f64;
+        let (sin, cos) = step.sin_cos();
+        let (mut phase_re, mut phase_im) = (1.0f64, 0.0f64);
+        let (mut sum_re, mut sum_im) = (0.0f64, 0.0f64);
+        for &sample in &self.windowed {
+            sum_re += sample as f64 * phase_re;
+            sum_im += sample as f64 * phase_im;
+            (phase_re, phase_im) = (
+                phase_re * cos + phase_im * sin,
+                phase_im * cos - phase_re * sin,
             );
+        }
+        sum_re.hypot(sum_im) as f32
+    }
 
-            assert!(
-                note.cents_off.abs() < 10.0,
-                "Frequency {} Hz should be close to perfect pitch, got {} cents off",
-                freq,
-                note.cents_off
-            );
+    /// Width of one FFT bin in Hz, which zero-padding narrows.
+    pub fn bin_width(&self) -> f32 {
+        self.analysis_rate() / self.input.len() as f32
+    }
+
+    /// Lowest frequency the detector searches, in Hz.
+    pub fn spectrum_min_frequency(&self) -> f32 {
+        self.search_bins().0 as f32 * self.bin_width()
+    }
+
+    /// The FFT bins searched: the frequency range, within what the FFT can
+    /// resolve in the current mode.
+    fn search_bins(&self) -> (usize, usize) {
+        self.bins_between(self.min_frequency, self.max_frequency)
+    }
+
+    /// The FFT bins from `min_frequency` to `max_frequency`, within what the
+    /// FFT can resolve in the current mode.
+    fn bins_between(&self, min_frequency: f32, max_frequency: f32) -> (usize, usize) {
+        let (floor, ceiling) = if self.low_range {
+            (LOW_RANGE_MIN_FREQUENCY, LOW_RANGE_MAX_FREQUENCY)
+        } else {
+            (FFT_MIN_FREQUENCY, MAX_DETECTABLE_FREQUENCY)
+        };
+        let min_frequency = min_frequency.max(floor);
+        let max_frequency = max_frequency.min(ceiling).max(min_frequency);
+        let last_bin = self.magnitudes.len() - 1;
+        let min_bin = ((min_frequency / self.bin_width()) as usize).min(last_bin);
+        let max_bin = ((max_frequency / self.bin_width()) as usize).clamp(min_bin, last_bin);
+        (min_bin, max_bin)
+    }
+
+    fn detect_low_register(&mut self, samples: &[f32]) -> Option<PitchEstimate> {
+        let (min_frequency, max_frequency) = self.search_range();
+        self.yin
+            .detect_pitch(samples)
+            .filter(|estimate| (min_frequency..=max_frequency).contains(&estimate.frequency))
+    }
+
+    /// Windows the first `buffer_size` samples and fills the magnitude
+    /// spectrum; false if there are too few samples. In low-range mode the
+    /// samples are decimated first and the FFT zero-padded, scaled so
+    /// magnitudes match the full-range ones. Padding for precision goes after
+    /// the block and leaves magnitudes unchanged.
+    fn analyse_spectrum(&mut self, samples: &[f32]) -> bool {
+        if samples.len() < self.buffer_size {
+            return false;
         }
+
+        self.windowed.clear();
+        if self.low_range {
+            self.windowed
+                .extend_from_slice(&samples[..self.buffer_size]);
+            for section in &self.anti_alias {
+                section.process(&mut self.windowed);
+            }
+            // Decimate in place; each output index is at or before its input.
+            for (index, window) in self.low_range_window.iter().enumerate() {
+                self.windowed[index] =
+                    self.windowed[index * DECIMATION] * window * DECIMATION as f32;
+            }
+            self.windowed[self.low_range_window.len()..].fill(0.0);
+        } else {
+            self.windowed.extend(
+                samples
+                    .iter()
+                    .take(self.buffer_size)
+                    .zip(self.window.iter())
+                    .map(|(sample, window)| sample * window),
+            );
+        }
+        let (block, padding) = self.input.split_at_mut(self.windowed.len());
+        block.copy_from_slice(&self.windowed);
+        padding.fill(0.0);
+
+        if self
+            .fft
+            .process_with_scratch(&mut self.input, &mut self.spectrum, &mut self.scratch)
+            .is_err()
+        {
+            return false;
+        }
+
+        for (magnitude, complex) in self.magnitudes.iter_mut().zip(self.spectrum.iter()) {
+            *magnitude = (complex.re * complex.re + complex.im * complex.im).sqrt();
+        }
+        true
+    }
+
+    fn detect_pitch_fft(&mut self, samples: &[f32]) -> Option<PitchEstimate> {
+        if !self.analyse_spectrum(samples) {
+            return None;
+        }
+        self.spectrum_peak()
+    }
+
+    /// The fundamental in the current magnitude spectrum, refined between bins.
+    fn spectrum_peak(&self) -> Option<PitchEstimate> {
+        let (min_frequency, max_frequency) = self.search_range();
+        let (min_freq_bin, max_freq_bin) = self.bins_between(min_frequency, max_frequency);
+
+        let mut max_magnitude = 0.0;
+        for &magnitude in &self.magnitudes[min_freq_bin..=max_freq_bin] {
+            if magnitude > max_magnitude {
+                max_magnitude = magnitude;
+            }
+        }
+
+        if max_magnitude < MIN_PEAK_MAGNITUDE {
+            return None;
+        }
+
+        let max_index = self.harmonic_product_peak(min_freq_bin, max_freq_bin, max_magnitude);
+        let magnitude = self.magnitudes[max_index];
+
+        let refined_frequency = self.interpolated_frequency(max_index);
+
+        // The top of the lobe outside the range means the energy belongs to a
+        // note outside it, such as rumble leaking up into a violin's range.
+        let tolerance = self.bin_width();
+        if refined_frequency < min_frequency - tolerance
+            || refined_frequency > max_frequency + tolerance
+        {
+            return None;
+        }
+
+        let (min_bin, max_bin) = self.search_bins();
+        let confidence = 1.0 - spectral_flatness(&self.magnitudes[min_bin..=max_bin]);
+
+        let estimate = PitchEstimate {
+            frequency: refined_frequency,
+            magnitude,
+            confidence,
+        };
+        Some(self.infer_fundamental(estimate, (min_freq_bin, max_freq_bin), min_frequency))
+    }
+
+    /// The frequency of the peak at `bin`, between bins.
+    fn interpolated_frequency(&self, bin: usize) -> f32 {
+        if bin == 0 || bin >= self.magnitudes.len() - 1 {
+            return bin as f32 * self.bin_width();
+        }
+        // A parabola fits the log of a window's main lobe far better than the
+        // lobe itself, which pulls linear estimates toward the bin centre.
+        let log_magnitude = |index: usize| (self.magnitudes[index] + 1e-12).ln();
+        let offset = parabolic_offset(
+            log_magnitude(bin - 1),
+            log_magnitude(bin),
+            log_magnitude(bin + 1),
+        );
+        (bin as f32 + offset) * self.bin_width()
+    }
+
+    /// Whether `bin` is a local maximum of the spectrum of at least `floor`.
+    fn is_local_peak(&self, bin: usize, floor: f32) -> bool {
+        self.magnitudes[bin] >= floor
+            && self.magnitudes[bin] > self.magnitudes[bin - 1]
+            && self.magnitudes[bin] >= self.magnitudes[bin + 1]
+    }
+
+    /// The frequencies and magnitudes of up to `N` of the strongest peaks
+    /// between `min_bin` and `max_bin`, strongest first, leaving out any
+    /// weaker than [`PEAK_MIN_RATIO`] of the strongest, and how many there
+    /// are. Doesn't allocate.
+    fn strongest_peaks<const N: usize>(
+        &self,
+        min_bin: usize,
+        max_bin: usize,
+    ) -> ([(f32, f32); N], usize) {
+        let (min_bin, max_bin) = (min_bin.max(1), max_bin.min(self.magnitudes.len() - 2));
+        let mut bins = [0usize; N];
+        let mut count = 0;
+        if min_bin > max_bin {
+            return ([(0.0, 0.0); N], 0);
+        }
+        let strongest = self.magnitudes[min_bin..=max_bin]
+            .iter()
+            .copied()
+            .fold(0.0, f32::max);
+        for bin in min_bin..=max_bin {
+            if !self.is_local_peak(bin, strongest * PEAK_MIN_RATIO) {
+                continue;
+            }
+            // Insert in order of magnitude, dropping the weakest when full.
+            let position = bins[..count]
+                .iter()
+                .position(|&other| self.magnitudes[bin] > self.magnitudes[other])
+                .unwrap_or(count);
+            if position == N {
+                continue;
+            }
+            count = (count + 1).min(N);
+            bins.copy_within(position..count - 1, position + 1);
+            bins[position] = bin;
+        }
+        let peaks = bins.map(|bin| (self.interpolated_frequency(bin), self.magnitudes[bin]));
+        (peaks, count)
+    }
+
+    /// The fundamental of the harmonic series the strongest peaks between
+    /// `bins` form, when `estimate` is one of its upper partials or a
+    /// fundamental much weaker than them: on low piano and bass notes the fundamental
+    /// is often weaker than the partials above it, or missing, and the peak
+    /// picker lands an octave or a twelfth high, or is pulled off by their
+    /// skirts. Each peak is tried as each of the first few partials, and the
+    /// highest fundamental that explains the most peaks wins if it explains
+    /// at least [`MIN_INFERRED_PARTIALS`] and more than `estimate` does,
+    /// however little energy there is at it. Its frequency comes from the
+    /// lowest strong partial, which strays least from a whole multiple on a
+    /// stiff string, and its confidence is scaled by the share of the peaks
+    /// it explains. Fundamentals below `min_frequency` aren't considered.
+    fn infer_fundamental(
+        &self,
+        estimate: PitchEstimate,
+        (min_bin, max_bin): (usize, usize),
+        min_frequency: f32,
+    ) -> PitchEstimate {
+        let (peaks, count) = self.strongest_peaks::<INFERENCE_PEAKS>(min_bin, max_bin);
+        let peaks = &peaks[..count];
+        let is_partial = |peak: f32, fundamental: f32| {
+            is_harmonic(peak, fundamental)
+                && (peak / fundamental).round() <= MAX_INFERRED_PARTIAL as f32
+        };
+        let explained = |fundamental: f32| {
+            peaks
+                .iter()
+                .filter(|&&(peak, _)| is_partial(peak, fundamental))
+                .count()
+        };
+
+        let mut best: Option<(usize, f32)> = None;
+        for &(peak, _) in peaks {
+            for partial in 1..=MAX_INFERRED_PARTIAL {
+                let fundamental = peak / partial as f32;
+                if fundamental < min_frequency {
+                    break;
+                }
+                let matched = explained(fundamental);
+                if best.is_none_or(|(most, highest)| {
+                    matched > most || (matched == most && fundamental > highest)
+                }) {
+                    best = Some((matched, fundamental));
+                }
+            }
+        }
+        let Some((matched, fundamental)) = best else {
+            return estimate;
+        };
+        let strongest = peaks.first().map_or(0.0, |&(_, magnitude)| magnitude);
+        let weak = estimate.magnitude < strongest * WEAK_FUNDAMENTAL_RATIO;
+        if matched < MIN_INFERRED_PARTIALS
+            || !is_partial(estimate.frequency, fundamental)
+            || (matched <= explained(estimate.frequency) && !weak)
+        {
+            return estimate;
+        }
+
+        let lowest = peaks
+            .iter()
+            .filter(|&&(peak, magnitude)| {
+                is_partial(peak, fundamental) && magnitude >= strongest * WEAK_FUNDAMENTAL_RATIO
+            })
+            .map(|&(peak, _)| peak)
+            .fold(f32::INFINITY, f32::min);
+        PitchEstimate {
+            frequency: lowest / (lowest / fundamental).round(),
+            confidence: estimate.confidence * matched as f32 / count as f32,
+            ..estimate
+        }
+    }
+
+    /// Picks the fundamental bin by summing log magnitudes at the first
+    /// `HPS_HARMONICS` multiples of each candidate (a harmonic product spectrum),
+    /// so a strong second harmonic doesn't pull the reading up an octave.
+    /// Candidates with almost no energy of their own are skipped, which keeps
+    /// pure tones from being assigned to the octave below.
+    fn harmonic_product_peak(&self, min_bin: usize, max_bin: usize, max_magnitude: f32) -> usize {
+        let mut best_bin = min_bin;
+        let mut best_score = f32::NEG_INFINITY;
+
+        for bin in min_bin..=max_bin {
+            if self.magnitudes[bin] < max_magnitude * HPS_MIN_FUNDAMENTAL_RATIO {
+                continue;
+            }
+
+            let score: f32 = (1..=HPS_HARMONICS)
+                .take_while(|&harmonic| bin * harmonic < self.magnitudes.len())
+                .map(|harmonic| (self.harmonic_magnitude(bin, harmonic) + 1e-9).ln())
+                .sum();
+
+            if score > best_score {
+                best_score = score;
+                best_bin = bin;
+            }
+        }
+
+        // Climb to the top of the lobe; a zero-padded low-range spectrum
+        // spreads it over several bins.
+        let mut peak_bin = best_bin;
+        loop {
+            let next = [peak_bin.saturating_sub(1), peak_bin + 1]
+                .into_iter()
+                .filter(|&neighbor| neighbor < self.magnitudes.len())
+                .max_by(|&a, &b| self.magnitudes[a].total_cmp(&self.magnitudes[b]))
+                .unwrap_or(peak_bin);
+            if self.magnitudes[next] <= self.magnitudes[peak_bin] {
+                break peak_bin;
+            }
+            peak_bin = next;
+        }
+    }
+
+    /// The strongest bin where `harmonic` of a fundamental in `bin` can land.
+    /// The fundamental's true position is anywhere within half a bin, so the
+    /// uncertainty grows with the harmonic number; this matters most for short
+    /// FFTs where a harmonic easily falls a couple of bins off `bin * harmonic`.
+    fn harmonic_magnitude(&self, bin: usize, harmonic: usize) -> f32 {
+        let center = bin * harmonic;
+        let spread = harmonic / 2;
+        let last = (center + spread).min(self.magnitudes.len() - 1);
+        self.magnitudes[center - spread..=last]
+            .iter()
+            .fold(0.0f32, |a, &b| a.max(b))
+    }
+}
+
+/// The inharmonicity coefficient B of a stiff string from its partials,
+/// indexed by partial number - 1, whose nth partial lies at n·f0·√(1 + B·n²).
+/// Squared, (fₙ/n)² = f0² + f0²·B·n², so a straight-line fit of (fₙ/n)²
+/// against n² gives f0² as its intercept and f0²·B as its slope. Needs two
+/// partials; measurement noise that would make B negative gives 0.
+pub fn estimate_inharmonicity(partials: &[Option<f32>]) -> Option<f32> {
+    let points: Vec<(f64, f64)> = partials
+        .iter()
+        .enumerate()
+        .filter_map(|(index, partial)| {
+            let n = (index + 1) as f64;
+            partial.map(|frequency| (n * n, (frequency as f64 / n).powi(2)))
+        })
+        .collect();
+    if points.len() < 2 {
+        return None;
+    }
+
+    let count = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / count;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / count;
+    let covariance: f64 = points
+        .iter()
+        .map(|(x, y)| (x - mean_x) * (y - mean_y))
+        .sum();
+    let variance: f64 = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+    let slope = covariance / variance;
+    let intercept = mean_y - slope * mean_x;
+    if intercept <= 0.0 {
+        return None;
+    }
+    Some((slope / intercept).max(0.0) as f32)
+}
+
+/// How far the stretched target of `midi` lies from equal temperament in
+/// cents, sharp above A4 and flat below. `cents_per_octave` scales
+/// [`STRETCH_SHAPE`], so the octave either side of A4 is stretched by half of
+/// it, the next by one and a half times it and so on outwards.
+pub fn stretch_cents(midi: i32, cents_per_octave: f32) -> f32 {
+    let octaves = (midi - 69) as f32 / 12.0;
+    let mut remaining = octaves.abs();
+    let mut cents = 0.0;
+    for (octave, &shape) in STRETCH_SHAPE.iter().enumerate() {
+        let span = if octave == STRETCH_SHAPE.len() - 1 {
+            remaining
+        } else {
+            remaining.min(1.0)
+        };
+        cents += span * shape * cents_per_octave;
+        remaining -= span;
+        if remaining <= 0.0 {
+            break;
+        }
+    }
+    cents.copysign(octaves)
+}
+
+/// Spots the start of a plucked or struck note from a jump in level between
+/// the newest 10 ms of audio and the 10 ms before the one before that, so an
+/// attack that straddles two frames still counts.
+#[derive(Debug)]
+pub struct OnsetDetector {
+    frame_len: usize,
+    /// Whether the level was jumping at the last call.
+    rising: bool,
+}
+
+impl OnsetDetector {
+    /// A detector for audio at `sample_rate`.
+    pub fn new(sample_rate: f32) -> Self {
+        Self {
+            frame_len: ((sample_rate * ONSET_FRAME_SECONDS) as usize).max(1),
+            rising: false,
+        }
+    }
+
+    /// Follows a change of input sample rate.
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.frame_len = ((sample_rate * ONSET_FRAME_SECONDS) as usize).max(1);
+    }
+
+    /// Whether a note started since the last call, judging by the end of
+    /// `samples`, the newest audio.
+    pub fn detect(&mut self, samples: &[f32]) -> bool {
+        let rms = |frame: &[f32]| {
+            (frame.iter().map(|sample| sample * sample).sum::<f32>() / frame.len() as f32).sqrt()
+        };
+        let Some(start) = samples.len().checked_sub(3 * self.frame_len) else {
+            return false;
+        };
+        let earlier = rms(&samples[start..start + self.frame_len]);
+        let newest = rms(&samples[samples.len() - self.frame_len..]);
+        let rising = newest >= ONSET_MIN_RMS && newest > earlier * ONSET_RMS_RATIO;
+        let onset = rising && !self.rising;
+        self.rising = rising;
+        onset
+    }
+}
+
+/// Time-domain YIN estimator (de Cheveigné & Kawahara, 2002).
+///
+/// Much more precise than FFT peak-picking for low notes, where adjacent semitones
+/// are only a couple of FFT bins apart. The returned magnitude is scaled to match
+/// the FFT peak of a sine with the same RMS level, so the same thresholds apply.
+pub struct YinDetector {
+    buffer_size: usize,
+    sample_rate: f32,
+    difference: Vec<f32>,
+}
+
+impl YinDetector {
+    /// A detector that analyses blocks of `buffer_size` samples.
+    pub fn new(buffer_size: usize, sample_rate: f32) -> Self {
+        Self {
+            buffer_size,
+            sample_rate,
+            difference: vec![0.0; buffer_size / 2],
+        }
+    }
+
+    /// Adapts to a new input sample rate.
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+    }
+
+    /// Detects the pitch of the latest `buffer_size` samples, or `None` if no
+    /// period stands out.
+    pub fn detect_pitch(&mut self, samples: &[f32]) -> Option<PitchEstimate> {
+        if samples.len() < self.buffer_size {
+            return None;
+        }
+
+        let samples = &samples[..self.buffer_size];
+        let rms = (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt();
+        if rms < 1e-6 {
+            return None;
+        }
+
+        let window = self.buffer_size / 2;
+        let min_tau = ((self.sample_rate / YIN_MAX_FREQUENCY) as usize).max(2);
+        let max_tau = ((self.sample_rate / YIN_MIN_FREQUENCY) as usize).min(window - 1);
+        if min_tau >= max_tau {
+            return None;
+        }
+
+        self.difference[0] = 1.0;
+        let mut running_sum = 0.0;
+        for tau in 1..=max_tau {
+            let diff: f32 = samples[..window]
+                .iter()
+                .zip(&samples[tau..tau + window])
+                .map(|(a, b)| (a - b) * (a - b))
+                .sum();
+            running_sum += diff;
+            self.difference[tau] = if running_sum > 0.0 {
+                diff * tau as f32 / running_sum
+            } else {
+                1.0
+            };
+        }
+
+        let mut tau = min_tau;
+        let best_tau = loop {
+            if tau >= max_tau {
+                return None;
+            }
+            if self.difference[tau] < YIN_THRESHOLD {
+                while tau + 1 < max_tau && self.difference[tau + 1] < self.difference[tau] {
+                    tau += 1;
+                }
+                break tau;
+            }
+            tau += 1;
+        };
+
+        let refined_tau = if best_tau > 1 && best_tau < max_tau {
+            let left = self.difference[best_tau - 1];
+            let center = self.difference[best_tau];
+            let right = self.difference[best_tau + 1];
+            best_tau as f32 + parabolic_offset(left, center, right)
+        } else {
+            best_tau as f32
+        };
+
+        let magnitude = rms * std::f32::consts::SQRT_2 * self.buffer_size as f32 / 4.0;
+
+        Some(PitchEstimate {
+            frequency: self.sample_rate / refined_tau,
+            magnitude,
+            confidence: (1.0 - self.difference[best_tau]).clamp(0.0, 1.0),
+        })
+    }
+}
+
+/// A regular pitch oscillation found by `detect_vibrato`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Vibrato {
+    /// Oscillations per second.
+    pub rate: f32,
+    /// Peak-to-peak extent in cents.
+    pub width_cents: f32,
+    /// The pitch the vibrato oscillates around.
+    pub center_frequency: f32,
+}
+
+/// Looks for a regular pitch oscillation in a trace of `(seconds, frequency)`
+/// detections. The trace is resampled onto a uniform grid, detrended so slow
+/// drift is ignored, and autocorrelated over lags of 3–10 Hz.
+pub fn detect_vibrato(trace: &[(f32, f32)]) -> Option<Vibrato> {
+    let (&(start, reference), &(end, _)) = (trace.first()?, trace.last()?);
+    let count = ((end - start) * VIBRATO_SAMPLE_RATE) as usize + 1;
+    let max_lag = (VIBRATO_SAMPLE_RATE / MIN_VIBRATO_RATE).ceil() as usize;
+    if count < 2 * max_lag {
+        return None;
+    }
+
+    // A median of three removes the odd single-detection glitch.
+    let trace: Vec<(f32, f32)> = (0..trace.len())
+        .map(|index| {
+            let (time, frequency) = trace[index];
+            let neighbours = &trace[index.saturating_sub(1)..(index + 2).min(trace.len())];
+            if neighbours.len() < 3 {
+                return (time, frequency);
+            }
+            let mut frequencies = [neighbours[0].1, neighbours[1].1, neighbours[2].1];
+            frequencies.sort_by(f32::total_cmp);
+            (time, frequencies[1])
+        })
+        .collect();
+
+    let mut cents = Vec::with_capacity(count);
+    let mut segment = 0;
+    for index in 0..count {
+        let time = start + index as f32 / VIBRATO_SAMPLE_RATE;
+        while segment + 2 < trace.len() && trace[segment + 1].0 < time {
+            segment += 1;
+        }
+        let (t0, f0) = trace[segment];
+        let (t1, f1) = trace[(segment + 1).min(trace.len() - 1)];
+        let fraction = if t1 > t0 {
+            ((time - t0) / (t1 - t0)).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        cents.push(1200.0 * ((f0 + (f1 - f0) * fraction) / reference).log2());
+    }
+
+    // Least-squares line through the trace; its midpoint is the centre pitch.
+    let n = count as f32;
+    let mean_x = (n - 1.0) / 2.0;
+    let mean = cents.iter().sum::<f32>() / n;
+    let covariance: f32 = cents
+        .iter()
+        .enumerate()
+        .map(|(index, &value)| (index as f32 - mean_x) * (value - mean))
+        .sum();
+    let variance_x = (0..count)
+        .map(|index| (index as f32 - mean_x).powi(2))
+        .sum::<f32>();
+    let slope = covariance / variance_x;
+    for (index, value) in cents.iter_mut().enumerate() {
+        *value -= mean + slope * (index as f32 - mean_x);
+    }
+
+    let energy = cents.iter().map(|value| value * value).sum::<f32>();
+    let width_cents = 2.0 * std::f32::consts::SQRT_2 * (energy / n).sqrt();
+    if width_cents < MIN_VIBRATO_WIDTH_CENTS {
+        return None;
+    }
+
+    let correlation = |lag: usize| {
+        let sum: f32 = cents.iter().zip(&cents[lag..]).map(|(a, b)| a * b).sum();
+        sum / energy * n / (count - lag) as f32
+    };
+    let min_lag = (VIBRATO_SAMPLE_RATE / MAX_VIBRATO_RATE) as usize;
+    let correlations: Vec<f32> = (min_lag - 1..=max_lag + 1).map(correlation).collect();
+    let (peak, best) = (1..correlations.len() - 1)
+        .filter(|&index| {
+            correlations[index] >= correlations[index - 1]
+                && correlations[index] >= correlations[index + 1]
+        })
+        .map(|index| (index, correlations[index]))
+        .max_by(|a, b| a.1.total_cmp(&b.1))?;
+    // Jitter between neighbouring detections can repeat too, but a real
+    // vibrato also changes smoothly from one grid step to the next.
+    if best < MIN_VIBRATO_CORRELATION || correlation(1) < MIN_VIBRATO_CORRELATION {
+        return None;
+    }
+
+    let (before, after) = (correlations[peak - 1], correlations[peak + 1]);
+    let curvature = before - 2.0 * best + after;
+    let offset = if curvature < 0.0 {
+        0.5 * (before - after) / curvature
+    } else {
+        0.0
+    };
+    let lag = (peak + min_lag - 1) as f32 + offset;
+
+    Some(Vibrato {
+        rate: VIBRATO_SAMPLE_RATE / lag,
+        width_cents,
+        center_frequency: reference * 2.0f32.powf(mean / 1200.0),
+    })
+}
+
+/// How note names are spelled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NoteNaming {
+    /// C, C#, D…
+    #[default]
+    Sharps,
+    /// C, D♭, D…
+    Flats,
+    /// Do, Re, Mi…
+    Solfege,
+    /// C, Cis, D… with H for B.
+    German,
+}
+
+impl NoteNaming {
+    /// Every option, in menu order.
+    pub const ALL: [NoteNaming; 4] = [
+        NoteNaming::Sharps,
+        NoteNaming::Flats,
+        NoteNaming::Solfege,
+        NoteNaming::German,
+    ];
+
+    /// Name shown in the settings panel.
+    pub fn label(self) -> &'static str {
+        match self {
+            NoteNaming::Sharps => "Sharps (C#)",
+            NoteNaming::Flats => "Flats (D♭)",
+            NoteNaming::Solfege => "Solfège (Do)",
+            NoteNaming::German => "German (H)",
+        }
+    }
+
+    /// Name of `pitch_class` (0 = C) without an octave.
+    pub fn pitch_class_name(self, pitch_class: u8) -> &'static str {
+        let names = match self {
+            NoteNaming::Sharps => [
+                "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+            ],
+            NoteNaming::Flats => [
+                "C", "D♭", "D", "E♭", "E", "F", "G♭", "G", "A♭", "A", "B♭", "B",
+            ],
+            NoteNaming::Solfege => [
+                "Do", "Do#", "Re", "Re#", "Mi", "Fa", "Fa#", "Sol", "Sol#", "La", "La#", "Si",
+            ],
+            NoteNaming::German => [
+                "C", "Cis", "D", "Dis", "E", "F", "Fis", "G", "Gis", "A", "B", "H",
+            ],
+        };
+        names[pitch_class as usize % 12]
+    }
+}
+
+/// Scale a key's notes are drawn from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeyMode {
+    /// Ionian.
+    #[default]
+    Major,
+    /// Natural minor, or Aeolian.
+    Minor,
+    /// Natural minor with a raised seventh.
+    HarmonicMinor,
+    /// Minor with a raised sixth.
+    Dorian,
+    /// Major with a lowered seventh.
+    Mixolydian,
+    /// Major without its fourth and seventh.
+    MajorPentatonic,
+    /// Natural minor without its second and sixth.
+    MinorPentatonic,
+    /// Pitch classes picked one by one.
+    Custom,
+}
+
+impl KeyMode {
+    /// Every mode, in menu order.
+    pub const ALL: [KeyMode; 8] = [
+        KeyMode::Major,
+        KeyMode::Minor,
+        KeyMode::HarmonicMinor,
+        KeyMode::Dorian,
+        KeyMode::Mixolydian,
+        KeyMode::MajorPentatonic,
+        KeyMode::MinorPentatonic,
+        KeyMode::Custom,
+    ];
+
+    /// Name shown in the settings panel.
+    pub fn label(self) -> &'static str {
+        match self {
+            KeyMode::Major => "major",
+            KeyMode::Minor => "minor",
+            KeyMode::HarmonicMinor => "harmonic minor",
+            KeyMode::Dorian => "Dorian",
+            KeyMode::Mixolydian => "Mixolydian",
+            KeyMode::MajorPentatonic => "major pentatonic",
+            KeyMode::MinorPentatonic => "minor pentatonic",
+            KeyMode::Custom => "custom",
+        }
+    }
+
+    /// Semitones above the tonic of each degree; empty for a custom set.
+    fn degrees(self) -> &'static [u8] {
+        match self {
+            KeyMode::Major => &[0, 2, 4, 5, 7, 9, 11],
+            KeyMode::Minor => &[0, 2, 3, 5, 7, 8, 10],
+            KeyMode::HarmonicMinor => &[0, 2, 3, 5, 7, 8, 11],
+            KeyMode::Dorian => &[0, 2, 3, 5, 7, 9, 10],
+            KeyMode::Mixolydian => &[0, 2, 4, 5, 7, 9, 10],
+            KeyMode::MajorPentatonic => &[0, 2, 4, 7, 9],
+            KeyMode::MinorPentatonic => &[0, 3, 5, 7, 10],
+            KeyMode::Custom => &[],
+        }
+    }
+
+    /// Semitones from the tonic up to the major key with the same signature.
+    fn relative_major(self) -> u8 {
+        match self {
+            KeyMode::Major | KeyMode::MajorPentatonic | KeyMode::Custom => 0,
+            KeyMode::Minor | KeyMode::HarmonicMinor | KeyMode::MinorPentatonic => 3,
+            KeyMode::Dorian => 10,
+            KeyMode::Mixolydian => 5,
+        }
+    }
+}
+
+/// A set of pitch classes, bit 0 being C.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PitchClassSet(u16);
+
+impl PitchClassSet {
+    /// The set holding each of `pitch_classes`, taken modulo 12.
+    pub fn from_pitch_classes(pitch_classes: impl IntoIterator<Item = u8>) -> Self {
+        Self(
+            pitch_classes
+                .into_iter()
+                .fold(0, |bits, pitch_class| bits | 1 << (pitch_class % 12)),
+        )
+    }
+
+    /// Whether `pitch_class` (0 = C) is in the set.
+    pub fn contains(self, pitch_class: u8) -> bool {
+        self.0 & 1 << (pitch_class % 12) != 0
+    }
+
+    /// Whether no pitch class is in the set.
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+/// A key whose notes readings are snapped to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Key {
+    /// Pitch class (0 = C) of the tonic; unused by a custom set.
+    pub tonic: u8,
+    /// Scale built on the tonic.
+    pub mode: KeyMode,
+    /// Pitch classes of a [`KeyMode::Custom`] key.
+    pub custom: PitchClassSet,
+}
+
+impl Key {
+    /// The pitch classes in the key.
+    pub fn pitch_classes(&self) -> PitchClassSet {
+        match self.mode {
+            KeyMode::Custom => self.custom,
+            mode => PitchClassSet::from_pitch_classes(
+                mode.degrees().iter().map(|degree| self.tonic % 12 + degree),
+            ),
+        }
+    }
+
+    /// `naming` with sharps or flats as the key signature spells them: E♭
+    /// major and D minor read flats and G major sharps, whichever the setting.
+    /// C major, a custom set and the other namings are left alone.
+    pub fn naming(&self, naming: NoteNaming) -> NoteNaming {
+        if self.mode == KeyMode::Custom {
+            return naming;
+        }
+        let major = (self.tonic + self.mode.relative_major()) % 12;
+        match (naming, major) {
+            (NoteNaming::Sharps, 1 | 3 | 5 | 8 | 10) => NoteNaming::Flats,
+            (NoteNaming::Flats, 2 | 4 | 6 | 7 | 9 | 11) => NoteNaming::Sharps,
+            _ => naming,
+        }
+    }
+
+    /// Name such as "E♭ major", spelled as the key is.
+    pub fn label(&self, naming: NoteNaming) -> String {
+        match self.mode {
+            KeyMode::Custom => "Custom".to_string(),
+            mode => format!(
+                "{} {}",
+                self.naming(naming).pitch_class_name(self.tonic),
+                mode.label()
+            ),
+        }
+    }
+}
+
+/// A frequency snapped to a note, with its deviation.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Note {
+    /// Semitone within the octave, 0 = C through 11 = B.
+    pub pitch_class: u8,
+    /// Scientific pitch octave, C4 being middle C.
+    pub octave: i32,
+    /// MIDI note number, 69 being A4.
+    pub midi: i32,
+    /// Exact frequency of the snapped note under the reference used to detect it.
+    pub target_frequency: f32,
+    /// The measured frequency.
+    pub frequency: f32,
+    /// Deviation of `frequency` from `target_frequency` in cents.
+    pub cents_off: f32,
+    /// Cents the target lies above the equal-tempered `midi` note, for a step
+    /// of an n-EDO that falls between semitones; 0 otherwise.
+    #[serde(default)]
+    pub microtone_cents: f32,
+}
+
+impl Note {
+    /// Parses a note name with octave such as "D#3", "Eb2" or "C-1", at
+    /// A4 = 440 Hz. Sharps may be written `#` or `♯` and flats `b` or `♭`.
+    pub fn parse(name: &str) -> Option<Note> {
+        let name = name.trim();
+        let mut chars = name.chars();
+        let pitch_class = match chars.next()?.to_ascii_uppercase() {
+            'C' => 0,
+            'D' => 2,
+            'E' => 4,
+            'F' => 5,
+            'G' => 7,
+            'A' => 9,
+            'B' => 11,
+            _ => return None,
+        };
+        let rest = chars.as_str();
+        let octave_start = rest.find(|c: char| c == '-' || c.is_ascii_digit())?;
+        let (accidentals, octave) = rest.split_at(octave_start);
+        let accidental: i32 = accidentals
+            .chars()
+            .map(|c| match c {
+                '#' | '♯' => Some(1),
+                'b' | '♭' => Some(-1),
+                _ => None,
+            })
+            .sum::<Option<i32>>()?;
+        let octave: i32 = octave.parse().ok()?;
+        let midi = (octave + 1) * 12 + pitch_class + accidental;
+        Some(midi_to_note(
+            midi,
+            midi_to_frequency(midi, DEFAULT_A4_FREQUENCY),
+            DEFAULT_A4_FREQUENCY,
+        ))
+    }
+
+    /// Note name with octave, e.g. "A4", and the step's offset from it
+    /// between semitones, e.g. "A4+50¢" for a quarter tone above.
+    pub fn display_name(&self, naming: NoteNaming) -> String {
+        let name = format!(
+            "{}{}",
+            naming.pitch_class_name(self.pitch_class),
+            self.octave
+        );
+        if self.microtone_cents.round() == 0.0 {
+            name
+        } else {
+            format!("{}{:+.0}¢", name, self.microtone_cents)
+        }
+    }
+}
+
+impl std::fmt::Display for Note {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.display_name(NoteNaming::Sharps))
+    }
+}
+
+/// Whether `frequency` is within [`HARMONIC_TOLERANCE_CENTS`] of a whole
+/// multiple of `fundamental`, counting the fundamental itself.
+fn is_harmonic(frequency: f32, fundamental: f32) -> bool {
+    let harmonic = (frequency / fundamental).round();
+    harmonic >= 1.0
+        && (1200.0 * (frequency / (harmonic * fundamental)).log2()).abs() < HARMONIC_TOLERANCE_CENTS
+}
+
+/// The interval between two notes sounding together.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Interval {
+    /// Size in semitones of the nearest named interval.
+    pub semitones: i32,
+    /// How much wider (positive) or narrower the notes are than that interval
+    /// in the temperament.
+    pub cents_off: f32,
+}
+
+impl Interval {
+    /// The interval from the lower to the higher of two notes. In just
+    /// intonation it is measured against the pure interval, in other
+    /// temperaments against the interval between the two notes' targets
+    /// there, which in equal temperament is a whole number of semitones.
+    pub fn between(first: &Note, second: &Note, temperament: Temperament, tonic: u8) -> Self {
+        let (lower, upper) = if first.frequency <= second.frequency {
+            (first, second)
+        } else {
+            (second, first)
+        };
+        let semitones = upper.midi - lower.midi;
+        let correction = match temperament {
+            Temperament::Just => Temperament::Just.offset_cents(semitones.rem_euclid(12) as u8, 0),
+            _ => {
+                temperament.offset_cents(upper.pitch_class, tonic)
+                    - temperament.offset_cents(lower.pitch_class, tonic)
+            }
+        };
+        let measured = 1200.0 * (upper.frequency / lower.frequency).log2();
+        Self {
+            semitones,
+            cents_off: measured - (semitones as f32 * 100.0 + correction),
+        }
+    }
+
+    /// Short name, e.g. "m3", "P5", "TT" or "M10" past the octave.
+    pub fn name(&self) -> String {
+        const QUALITIES: [(&str, i32); 12] = [
+            ("P", 1),
+            ("m", 2),
+            ("M", 2),
+            ("m", 3),
+            ("M", 3),
+            ("P", 4),
+            ("TT", 0),
+            ("P", 5),
+            ("m", 6),
+            ("M", 6),
+            ("m", 7),
+            ("M", 7),
+        ];
+        let octaves = self.semitones / 12;
+        match QUALITIES[self.semitones.rem_euclid(12) as usize] {
+            ("TT", _) => "TT".to_string(),
+            ("P", 1) if octaves > 0 => format!("P{}", 7 * octaves + 1),
+            (quality, number) => format!("{}{}", quality, number + 7 * octaves),
+        }
+    }
+}
+
+impl std::fmt::Display for Interval {
+    /// The name with how far off the interval is, e.g. "P5 +4¢ wide".
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let cents = self.cents_off.round();
+        if cents == 0.0 {
+            write!(f, "{} in tune", self.name())
+        } else if cents > 0.0 {
+            write!(f, "{} {:+.0}¢ wide", self.name(), cents)
+        } else {
+            write!(f, "{} {:+.0}¢ narrow", self.name(), cents)
+        }
+    }
+}
+
+/// Standard concert pitch for A4.
+pub const DEFAULT_A4_FREQUENCY: f32 = 440.0;
+
+/// Equal-tempered frequency of a MIDI note with A4 at `a4_freq`.
+pub fn midi_to_frequency(midi: i32, a4_freq: f32) -> f32 {
+    a4_freq * 2.0f32.powf((midi - 69) as f32 / 12.0)
+}
+
+/// Frequency of `midi` in `temperament` built on `tonic`, with A4 at
+/// `a4_freq` as the equal-tempered reference.
+pub fn tempered_frequency(midi: i32, a4_freq: f32, temperament: Temperament, tonic: u8) -> f32 {
+    let offset_cents = temperament.offset_cents(midi.rem_euclid(12) as u8, tonic);
+    midi_to_frequency(midi, a4_freq) * 2.0f32.powf(offset_cents / 1200.0)
+}
+
+/// Partials of each tone compared when looking for the beats between two.
+pub const MAX_BEAT_PARTIAL: u32 = 6;
+/// Partials closer than this, in cents, beat against each other rather than
+/// sounding as two pitches.
+const BEAT_MATCH_CENTS: f32 = 50.0;
+
+/// Beats per second between a tone `cents` off `target` Hz and the target
+/// itself, the difference of their frequencies.
+pub fn beat_frequency(target: f32, cents: f32) -> f32 {
+    (target * ((cents / 1200.0).exp2() - 1.0)).abs()
+}
+
+/// Beats per second heard between tones at `frequency` and `reference` Hz:
+/// the difference of the lowest pair of their partials, up to the
+/// [`MAX_BEAT_PARTIAL`]th, that nearly coincide, e.g. the third partial of
+/// the lower note of a fifth against the second of the upper. `None` if no
+/// pair does.
+pub fn partial_beat_frequency(frequency: f32, reference: f32) -> Option<f32> {
+    (1..=MAX_BEAT_PARTIAL)
+        .flat_map(|partial| (1..=MAX_BEAT_PARTIAL).map(move |other| (partial, other)))
+        .filter(|&(partial, other)| {
+            let ratio = partial as f32 * frequency / (other as f32 * reference);
+            (1200.0 * ratio.log2()).abs() < BEAT_MATCH_CENTS
+        })
+        .min_by_key(|&(partial, other)| partial * other)
+        .map(|(partial, other)| (partial as f32 * frequency - other as f32 * reference).abs())
+}
+
+/// The equal-tempered note `midi` with `frequency` measured against it,
+/// however far off that is.
+pub fn midi_to_note(midi: i32, frequency: f32, a4_freq: f32) -> Note {
+    let target_frequency = midi_to_frequency(midi, a4_freq);
+    Note {
+        pitch_class: midi.rem_euclid(12) as u8,
+        octave: midi.div_euclid(12) - 1,
+        midi,
+        target_frequency,
+        frequency,
+        cents_off: 1200.0 * (frequency / target_frequency).log2(),
+        microtone_cents: 0.0,
+    }
+}
+
+/// Tuning system notes are snapped to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Temperament {
+    /// Twelve-tone equal temperament.
+    #[default]
+    Equal,
+    /// Five-limit just intonation.
+    Just,
+    /// Quarter-comma meantone.
+    QuarterCommaMeantone,
+    /// Werckmeister III well temperament.
+    WerckmeisterIII,
+    /// Pythagorean tuning from pure fifths.
+    Pythagorean,
+}
+
+impl Temperament {
+    /// Every temperament, in menu order.
+    pub const ALL: [Temperament; 5] = [
+        Temperament::Equal,
+        Temperament::Just,
+        Temperament::QuarterCommaMeantone,
+        Temperament::WerckmeisterIII,
+        Temperament::Pythagorean,
+    ];
+
+    /// Name shown in the settings panel.
+    pub fn label(self) -> &'static str {
+        match self {
+            Temperament::Equal => "Equal",
+            Temperament::Just => "Just intonation",
+            Temperament::QuarterCommaMeantone => "1/4-comma meantone",
+            Temperament::WerckmeisterIII => "Werckmeister III",
+            Temperament::Pythagorean => "Pythagorean",
+        }
+    }
+
+    /// Deviation in cents from equal temperament of `pitch_class` when the
+    /// temperament is built on `tonic`.
+    pub fn offset_cents(self, pitch_class: u8, tonic: u8) -> f32 {
+        let offsets: [f32; 12] = match self {
+            Temperament::Equal => return 0.0,
+            Temperament::Just => [
+                0.0, 11.73, 3.91, 15.64, -13.69, -1.96, -9.78, 1.96, 13.69, -15.64, 17.6, -11.73,
+            ],
+            Temperament::QuarterCommaMeantone => [
+                0.0, -23.95, -6.84, 10.26, -13.69, 3.42, -20.53, -3.42, -27.37, -10.26, 6.84,
+                -17.11,
+            ],
+            Temperament::WerckmeisterIII => [
+                0.0, -9.78, -7.82, -5.87, -9.78, -1.96, -11.73, -3.91, -7.82, -11.73, -3.91, -7.82,
+            ],
+            Temperament::Pythagorean => [
+                0.0, -9.78, 3.91, -5.87, 7.82, -1.96, 11.73, 1.96, -7.82, 5.87, -3.91, 9.78,
+            ],
+        };
+        offsets[(pitch_class as usize + 12 - tonic as usize % 12) % 12]
+    }
+}
+
+/// Snaps `frequency` to the nearest equal-tempered note with A4 at `a4_freq`;
+/// `None` unless both are positive and finite.
+pub fn frequency_to_note(frequency: f32, a4_freq: f32) -> Option<Note> {
+    frequency_to_tempered_note(frequency, a4_freq, Temperament::Equal, 0)
+}
+
+/// The nearest whole semitone. Exactly halfway goes up on both sides of A4,
+/// where `round` would go away from it.
+fn nearest_semitone(semitones: f32) -> i32 {
+    (semitones + 0.5).floor() as i32
+}
+
+/// Snaps `frequency` to the nearest note of `temperament` built on `tonic`,
+/// with A4 at `a4_freq` as the equal-tempered reference; `None` unless both
+/// are positive and finite.
+pub fn frequency_to_tempered_note(
+    frequency: f32,
+    a4_freq: f32,
+    temperament: Temperament,
+    tonic: u8,
+) -> Option<Note> {
+    let valid = |frequency: f32| frequency.is_finite() && frequency > 0.0;
+    if !valid(frequency) || !valid(a4_freq) {
+        return None;
+    }
+
+    let semitones_from_a4 = 12.0 * (frequency / a4_freq).log2();
+    let offset =
+        |semitone: i32| temperament.offset_cents((semitone + 9).rem_euclid(12) as u8, tonic);
+
+    let mut nearest_semitone = nearest_semitone(semitones_from_a4);
+    if temperament != Temperament::Equal {
+        let distance = |semitone: i32| {
+            (semitones_from_a4 * 100.0 - semitone as f32 * 100.0 - offset(semitone)).abs()
+        };
+        // Highest first, so a tie goes up as it does in equal temperament.
+        nearest_semitone = (nearest_semitone - 1..=nearest_semitone + 1)
+            .rev()
+            .min_by(|&a, &b| distance(a).total_cmp(&distance(b)))
+            .unwrap_or(nearest_semitone);
+    }
+
+    let offset_cents = offset(nearest_semitone);
+    let cents_off = (semitones_from_a4 - nearest_semitone as f32) * 100.0 - offset_cents;
+
+    let semitones_from_c4 = nearest_semitone + 9;
+
+    let pitch_class = semitones_from_c4.rem_euclid(12) as u8;
+
+    let octave = 4 + semitones_from_c4.div_euclid(12);
+
+    let midi = nearest_semitone + 69;
+
+    Some(Note {
+        pitch_class,
+        octave,
+        midi,
+        target_frequency: tempered_frequency(midi, a4_freq, temperament, tonic),
+        frequency,
+        cents_off,
+        microtone_cents: 0.0,
+    })
+}
+
+/// Equal divisions of the octave the tuner offers, from 5 to 72.
+pub const EDO_RANGE: std::ops::RangeInclusive<u32> = 5..=72;
+
+/// Snaps `frequency` to the nearest step of `divisions` equal divisions of
+/// the octave counted from A4 at `a4_freq`, named after the nearest semitone
+/// with the step's offset from it; a step halfway between two semitones is
+/// named after the lower. Twelve divisions is [`frequency_to_note`]. `None`
+/// unless both frequencies are positive and finite and `divisions` is not 0.
+pub fn frequency_to_edo_note(frequency: f32, a4_freq: f32, divisions: u32) -> Option<Note> {
+    if divisions == 12 {
+        return frequency_to_note(frequency, a4_freq);
+    }
+    let valid = |frequency: f32| frequency.is_finite() && frequency > 0.0;
+    if !valid(frequency) || !valid(a4_freq) || divisions == 0 {
+        return None;
+    }
+
+    let step_cents = 1200.0 / divisions as f32;
+    let step = nearest_semitone(1200.0 * (frequency / a4_freq).log2() / step_cents);
+    let step_from_a4 = step as f32 * step_cents;
+    let semitones_from_a4 = (step_from_a4 / 100.0 - 0.5).ceil() as i32;
+    let target_frequency = a4_freq * 2.0f32.powf(step_from_a4 / 1200.0);
+    Some(Note {
+        target_frequency,
+        cents_off: 1200.0 * (frequency / target_frequency).log2(),
+        microtone_cents: step_from_a4 - semitones_from_a4 as f32 * 100.0,
+        ..midi_to_note(69 + semitones_from_a4, frequency, a4_freq)
+    })
+}
+
+/// Like [`frequency_to_tempered_note`], but against targets stretched as on
+/// a piano by [`stretch_cents`], so a well-tuned piano reads in tune across
+/// the keyboard. Snaps to the note whose stretched target is closest.
+pub fn frequency_to_stretched_note(
+    frequency: f32,
+    a4_freq: f32,
+    temperament: Temperament,
+    tonic: u8,
+    cents_per_octave: f32,
+) -> Option<Note> {
+    let stretch = |midi: i32| 2f32.powf(stretch_cents(midi, cents_per_octave) / 1200.0);
+    let unstretched = frequency_to_tempered_note(frequency, a4_freq, temperament, tonic)?;
+    let note = frequency_to_tempered_note(
+        frequency / stretch(unstretched.midi),
+        a4_freq,
+        temperament,
+        tonic,
+    )?;
+    let target_frequency = note.target_frequency * stretch(note.midi);
+    Some(Note {
+        target_frequency,
+        frequency,
+        cents_off: 1200.0 * (frequency / target_frequency).log2(),
+        ..note
+    })
+}
+
+/// Like [`frequency_to_tempered_note`], but snaps only to notes in
+/// `pitch_classes`: a frequency closer to a note outside them reads as the
+/// nearer in-key note on either side, however many cents off that is. An
+/// empty set snaps to every note.
+pub fn frequency_to_key_note(
+    frequency: f32,
+    a4_freq: f32,
+    temperament: Temperament,
+    tonic: u8,
+    pitch_classes: PitchClassSet,
+) -> Option<Note> {
+    let chromatic = frequency_to_tempered_note(frequency, a4_freq, temperament, tonic)?;
+    if pitch_classes.is_empty() || pitch_classes.contains(chromatic.pitch_class) {
+        return Some(chromatic);
+    }
+
+    let in_key = |midi: &i32| pitch_classes.contains(midi.rem_euclid(12) as u8);
+    let below = (chromatic.midi - 11..chromatic.midi).rev().find(in_key)?;
+    let above = (chromatic.midi + 1..=chromatic.midi + 11).find(in_key)?;
+    let cents_off =
+        |midi| 1200.0 * (frequency / tempered_frequency(midi, a4_freq, temperament, tonic)).log2();
+    let midi = if cents_off(above).abs() < cents_off(below).abs() {
+        above
+    } else {
+        below
+    };
+
+    let target_frequency = tempered_frequency(midi, a4_freq, temperament, tonic);
+    Some(Note {
+        target_frequency,
+        cents_off: cents_off(midi),
+        ..midi_to_note(midi, frequency, a4_freq)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alloc_counter::count_allocations;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_midi_to_note_measures_against_the_given_note() {
+        let note = midi_to_note(41, 87.31, DEFAULT_A4_FREQUENCY);
+        assert_eq!(note.to_string(), "F2");
+        assert!(note.cents_off.abs() < 0.1);
+
+        // A semitone below is a hundred cents flat, not G#4.
+        let note = midi_to_note(69, 415.3, DEFAULT_A4_FREQUENCY);
+        assert_eq!(note.to_string(), "A4");
+        assert!((note.cents_off + 100.0).abs() < 0.1);
+        assert_eq!(note.target_frequency, 440.0);
+
+        assert_eq!(
+            midi_to_note(0, 8.18, DEFAULT_A4_FREQUENCY).to_string(),
+            "C-1"
+        );
+    }
+
+    #[test]
+    fn test_note_mapping() {
+        let test_cases = [
+            (440.0, "A4"),
+            (493.88, "B4"),
+            (523.25, "C5"),
+            (392.0, "G4"),
+            (220.0, "A3"),
+            (880.0, "A5"),
+        ];
+
+        for (freq, expected) in test_cases.iter() {
+            let note = frequency_to_note(*freq, DEFAULT_A4_FREQUENCY).unwrap();
+            println!(
+                "{:.2} Hz -> {} (expected {}), cents: {:.1}",
+                freq, note, expected, note.cents_off
+            );
+
+            assert!(
+                note.cents_off.abs() < 10.0,
+                "Frequency {} Hz should be close to perfect pitch, got {} cents off",
+                freq,
+                note.cents_off
+            );
+        }
+    }
+
+    #[test]
+    fn test_note_mapping_with_reference() {
+        let test_cases = [
+            (415.0, 415.0, "A4", 0.0),
+            (440.0, 415.0, "A#4", 1.3),
+            (369.73, 415.0, "G4", 0.0),
+            (432.0, 432.0, "A4", 0.0),
+            (440.0, 432.0, "A4", 31.8),
+            (256.87, 432.0, "C4", 0.0),
+            (442.0, 442.0, "A4", 0.0),
+            (440.0, 442.0, "A4", -7.9),
+            (662.16, 442.0, "E5", 0.0),
+        ];
+
+        for (freq, reference, expected, expected_cents) in test_cases.iter() {
+            let note = frequency_to_note(*freq, *reference).unwrap();
+            assert_eq!(
+                note.to_string(),
+                *expected,
+                "{} Hz at A4 = {} Hz should be {}",
+                freq,
+                reference,
+                expected
+            );
+            assert!(
+                (note.cents_off - expected_cents).abs() < 0.5,
+                "{} Hz at A4 = {} Hz should be {} cents off, got {}",
+                freq,
+                reference,
+                expected_cents,
+                note.cents_off
+            );
+        }
+    }
+
+    #[test]
+    fn test_detects_a4_after_sample_rate_change() {
+        let sample_rate = 48000.0;
+        let buffer_size = 8192;
+        let samples = test_signals::sine(440.0, sample_rate, buffer_size);
+
+        let mut detector = PitchDetector::new(buffer_size, 44100.0, WindowKind::Hann);
+        detector.set_sample_rate(sample_rate);
+
+        let frequency = detector
+            .detect_pitch(&samples)
+            .expect("no pitch detected")
+            .frequency;
+        let note = frequency_to_note(frequency, DEFAULT_A4_FREQUENCY).unwrap();
+
+        assert_eq!(note.to_string(), "A4");
+        assert!(
+            note.cents_off.abs() < 3.0,
+            "expected A4 within 3 cents, got {} cents",
+            note.cents_off
+        );
+    }
+
+    fn cents_between(measured: f32, expected: f32) -> f32 {
+        1200.0 * (measured / expected).log2()
+    }
+
+    #[test]
+    fn test_detects_a4_in_noise() {
+        let sample_rate = 48000.0;
+        let buffer_size = DEFAULT_FFT_SIZE;
+        let mut detector = PitchDetector::new(buffer_size, sample_rate, WindowKind::Hann);
+        let clean = test_signals::sine(440.0, sample_rate, buffer_size);
+        let noisy = test_signals::sine_with_noise(440.0, sample_rate, buffer_size, 0.3);
+
+        let clean = detector.detect_pitch(&clean).expect("no pitch detected");
+        let noisy = detector.detect_pitch(&noisy).expect("no pitch in noise");
+        let cents = cents_between(noisy.frequency, 440.0);
+        assert!(cents.abs() < 3.0, "off by {} cents in noise", cents);
+        assert!(noisy.confidence < clean.confidence);
+    }
+
+    #[test]
+    fn test_estimate_inharmonicity_of_exact_partials() {
+        for b in [0.0, 1e-4, 4e-4, 2e-3] {
+            let partials: Vec<Option<f32>> = (1..=6)
+                .map(|n| {
+                    let n = n as f32;
+                    Some(n * 110.0 * (1.0 + b * n * n).sqrt())
+                })
+                .collect();
+            let estimate = estimate_inharmonicity(&partials).unwrap();
+            assert!(
+                (estimate - b).abs() < 1e-6,
+                "B {} estimated as {}",
+                b,
+                estimate
+            );
+        }
+
+        // Missing partials are skipped; one alone says nothing.
+        let b = 5e-4f32;
+        let partial = |n: f32| Some(n * 55.0 * (1.0 + b * n * n).sqrt());
+        let gappy = [partial(1.0), None, partial(3.0), None, partial(5.0)];
+        assert!((estimate_inharmonicity(&gappy).unwrap() - b).abs() < 1e-6);
+        assert_eq!(estimate_inharmonicity(&[partial(1.0), None]), None);
+    }
+
+    #[test]
+    fn test_detect_partials_of_a_stiff_string() {
+        let sample_rate = 48000.0;
+        let buffer_size = 16384;
+        let mut detector = PitchDetector::new(buffer_size, sample_rate, WindowKind::Hann);
+        for (f0, b) in [(110.0, 3e-4), (220.0, 1e-3), (261.63, 0.0)] {
+            let samples = test_signals::inharmonic(f0, b, PIANO_PARTIALS, sample_rate, buffer_size);
+            let fundamental = detector.detect_pitch(&samples).unwrap().frequency;
+            let partials = detector.detect_partials(&samples, fundamental, PIANO_PARTIALS);
+
+            for (index, partial) in partials.iter().enumerate() {
+                let n = (index + 1) as f32;
+                let expected = n * f0 * (1.0 + b * n * n).sqrt();
+                let measured = partial.expect("every partial sounds");
+                assert!(
+                    cents_between(measured, expected).abs() < 0.5,
+                    "partial {} of {} Hz at {} Hz, expected {}",
+                    n,
+                    f0,
+                    measured,
+                    expected
+                );
+            }
+            let estimate = estimate_inharmonicity(&partials).unwrap();
+            assert!(
+                (estimate - b).abs() < 5e-5,
+                "B {} of {} Hz estimated as {}",
+                b,
+                f0,
+                estimate
+            );
+        }
+
+        assert!(detector
+            .detect_partials(&vec![0.0; buffer_size], 110.0, PIANO_PARTIALS)
+            .iter()
+            .all(Option::is_none));
+    }
+
+    #[test]
+    fn test_stretch_curve() {
+        assert_eq!(stretch_cents(69, 3.0), 0.0);
+        // Gentle across the middle octave, steeper towards the ends, and
+        // mirrored below A4.
+        assert!((stretch_cents(81, 3.0) - 1.5).abs() < 1e-4);
+        assert!((stretch_cents(93, 3.0) - 6.0).abs() < 1e-4);
+        assert!((stretch_cents(57, 3.0) + 1.5).abs() < 1e-4);
+        assert!((stretch_cents(21, 3.0) + 33.0).abs() < 1e-4);
+        assert!((stretch_cents(75, 3.0) - 0.75).abs() < 1e-4);
+        assert_eq!(stretch_cents(108, 0.0), 0.0);
+        for midi in 21..108 {
+            assert!(stretch_cents(midi + 1, 3.0) > stretch_cents(midi, 3.0));
+        }
+    }
+
+    #[test]
+    fn test_stretched_notes_read_in_tune_on_a_stretched_piano() {
+        let stretched = |frequency| {
+            frequency_to_stretched_note(frequency, 440.0, Temperament::Equal, 0, 3.0).unwrap()
+        };
+        for midi in [21, 45, 69, 81, 93, 108] {
+            let target =
+                midi_to_frequency(midi, 440.0) * 2f32.powf(stretch_cents(midi, 3.0) / 1200.0);
+            let note = stretched(target);
+            assert_eq!(note.midi, midi);
+            assert!(
+                note.cents_off.abs() < 0.01,
+                "MIDI {}: {}",
+                midi,
+                note.cents_off
+            );
+            assert_eq!(note.frequency, target);
+        }
+
+        // 53 cents under equal-tempered A0 is nearer G#0, but only 20 under
+        // the stretched A0.
+        let note = stretched(27.5 * 2f32.powf(-53.0 / 1200.0));
+        assert_eq!(note.to_string(), "A0");
+        assert!((note.cents_off + 20.0).abs() < 0.01);
+
+        // Without stretch it is plain equal temperament.
+        let note = frequency_to_stretched_note(1760.0, 440.0, Temperament::Equal, 0, 0.0).unwrap();
+        assert_eq!((note.to_string().as_str(), note.cents_off), ("A6", 0.0));
+    }
+
+    #[test]
+    fn test_multi_pitch_finds_detuned_strings_in_a_strum() {
+        let sample_rate = 48000.0;
+        let buffer_size = DEFAULT_FFT_SIZE;
+        let targets = [82.41, 110.0, 146.83, 196.0, 246.94, 329.63];
+        let detune_cents = [0.0, 0.0, 25.0, 0.0, -30.0, 0.0];
+
+        let mut mix = vec![0.0; buffer_size];
+        for (&target, &cents) in targets.iter().zip(&detune_cents) {
+            let frequency = target * 2f32.powf(cents / 1200.0);
+            for (sample, tone) in
+                mix.iter_mut()
+                    .zip(test_signals::sine(frequency, sample_rate, buffer_size))
+            {
+                *sample += tone / 6.0;
+            }
+        }
+
+        let mut detector = PitchDetector::new(buffer_size, sample_rate, WindowKind::Hann);
+        let strings = detector.detect_multi_pitch(&mix, &targets);
+        for ((&target, &expected), string) in targets.iter().zip(&detune_cents).zip(&strings) {
+            let (frequency, _) = string.expect("every string sounds");
+            let cents = cents_between(frequency, target);
+            assert!(
+                (cents - expected).abs() < 3.0,
+                "{} Hz measured {:.1} cents off, expected {}",
+                target,
+                cents,
+                expected
+            );
+        }
+
+        // Strings that aren't sounding have no peak to report.
+        let silence = vec![0.0; buffer_size];
+        let strings = detector.detect_multi_pitch(&silence, &targets);
+        assert!(strings.iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn test_yin_detects_low_notes() {
+        let sample_rate = 44100.0;
+        let buffer_size = 8192;
+        let mut yin = YinDetector::new(buffer_size, sample_rate);
+
+        for &frequency in &[41.2, 55.0, 82.4] {
+            for &sawtooth in &[false, true] {
+                let samples = if sawtooth {
+                    test_signals::sawtooth(frequency, sample_rate, buffer_size)
+                } else {
+                    test_signals::sine(frequency, sample_rate, buffer_size)
+                };
+                let detected = yin
+                    .detect_pitch(&samples)
+                    .expect("no pitch detected")
+                    .frequency;
+                let error = cents_between(detected, frequency);
+                assert!(
+                    error.abs() < 2.0,
+                    "{} Hz {} detected as {} Hz ({} cents off)",
+                    frequency,
+                    if sawtooth { "sawtooth" } else { "sine" },
+                    detected,
+                    error
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_yin_rejects_silence() {
+        let mut yin = YinDetector::new(8192, 44100.0);
+        assert!(yin.detect_pitch(&vec![0.0; 8192]).is_none());
+    }
+
+    #[test]
+    fn test_pitch_detector_uses_yin_below_crossover() {
+        let sample_rate = 48000.0;
+        let buffer_size = 8192;
+        let mut detector = PitchDetector::new(buffer_size, sample_rate, WindowKind::Hann);
+
+        let samples = test_signals::sawtooth(41.2, sample_rate, buffer_size);
+        let frequency = detector
+            .detect_pitch(&samples)
+            .expect("no pitch detected")
+            .frequency;
+        let note = frequency_to_note(frequency, DEFAULT_A4_FREQUENCY).unwrap();
+
+        assert_eq!(note.to_string(), "E1");
+        assert!(
+            note.cents_off.abs() < 2.0,
+            "E1 off by {} cents",
+            note.cents_off
+        );
+    }
+
+    #[test]
+    fn test_strong_second_harmonic_does_not_cause_octave_error() {
+        let sample_rate = 44100.0;
+        let buffer_size = 8192;
+        let mut detector = PitchDetector::new(buffer_size, sample_rate, WindowKind::Hann);
+
+        for &(frequency, expected) in
+            &[(196.0, "G3"), (246.94, "B3"), (329.63, "E4"), (440.0, "A4")]
+        {
+            let samples: Vec<f32> = (0..buffer_size)
+                .map(|i| {
+                    let t = i as f32 / sample_rate;
+                    let omega = 2.0 * std::f32::consts::PI * frequency * t;
+                    0.2 * omega.sin() + 0.4 * (2.0 * omega).sin() + 0.1 * (3.0 * omega).sin()
+                })
+                .collect();
+
+            let detected = detector
+                .detect_pitch(&samples)
+                .expect("no pitch detected")
+                .frequency;
+            let note = frequency_to_note(detected, DEFAULT_A4_FREQUENCY).unwrap();
+            assert_eq!(
+                note.to_string(),
+                expected,
+                "{} Hz detected as {} Hz",
+                frequency,
+                detected
+            );
+        }
+    }
+
+    #[test]
+    fn test_detect_pitch_with_spectrum_fills_search_range() {
+        let sample_rate = 44100.0;
+        let buffer_size = 8192;
+        let mut detector = PitchDetector::new(buffer_size, sample_rate, WindowKind::Hann);
+        let samples = test_signals::sine(440.0, sample_rate, buffer_size);
+
+        let mut spectrum = Vec::new();
+        let frequency = detector
+            .detect_pitch_with_spectrum(&samples, &mut spectrum)
+            .expect("no pitch detected")
+            .frequency;
+        assert!((frequency - 440.0).abs() < 1.0);
+
+        let peak_index = spectrum
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .map(|(index, _)| index)
+            .unwrap();
+        let peak_frequency =
+            detector.spectrum_min_frequency() + peak_index as f32 * detector.bin_width();
+        assert!((peak_frequency - 440.0).abs() < detector.bin_width());
+
+        let capacity = spectrum.capacity();
+        detector.detect_pitch_with_spectrum(&samples, &mut spectrum);
+        assert_eq!(spectrum.capacity(), capacity);
+    }
+
+    #[test]
+    fn test_window_coherent_gain() {
+        let expected = [
+            (WindowKind::Hann, 0.5),
+            (WindowKind::Hamming, 0.54),
+            (WindowKind::BlackmanHarris, 0.35875),
+        ];
+
+        for size in FFT_SIZES {
+            for (kind, gain) in expected {
+                let window = kind.coefficients(size);
+                let coherent_gain = window.iter().sum::<f32>() / size as f32;
+                assert!(
+                    (coherent_gain - gain).abs() < 1e-3,
+                    "{:?} at {}: coherent gain {}",
+                    kind,
+                    size,
+                    coherent_gain
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_detects_a4_at_every_size_and_window() {
+        let sample_rate = 48000.0;
+
+        for size in FFT_SIZES {
+            for kind in WindowKind::ALL {
+                let mut detector = PitchDetector::new(size, sample_rate, kind);
+                let samples = test_signals::sawtooth(440.0, sample_rate, size);
+                let frequency = detector.detect_pitch(&samples).unwrap().frequency;
+                assert!(
+                    cents_between(frequency, 440.0).abs() < 5.0,
+                    "{:?} at {} detected {} Hz",
+                    kind,
+                    size,
+                    frequency
+                );
+            }
+        }
+    }
+
+    fn steady_state_gain(filter: &PreFilter, frequency: f32, sample_rate: f32) -> f32 {
+        let samples = test_signals::sine(frequency, sample_rate, sample_rate as usize);
+        let mut scratch = Vec::new();
+        let filtered = filter.apply(&samples, &mut scratch);
+        let rms = |samples: &[f32]| {
+            (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+        };
+        let settled = samples.len() / 2;
+        rms(&filtered[settled..]) / rms(&samples[settled..])
+    }
+
+    #[test]
+    fn test_pre_filter_response() {
+        let sample_rate = 48000.0;
+
+        let off = PreFilter::new(sample_rate, HumRejection::Off, 60.0);
+        assert_eq!(steady_state_gain(&off, 100.0, sample_rate), 1.0);
+
+        let filter = PreFilter::new(sample_rate, HumRejection::Mains50, 60.0);
+        for hum in [50.0, 100.0] {
+            let gain = steady_state_gain(&filter, hum, sample_rate);
+            assert!(gain < 0.01, "{} Hz only attenuated to {}", hum, gain);
+        }
+        for note in [196.0, 440.0, 1318.5] {
+            let gain = steady_state_gain(&filter, note, sample_rate);
+            assert!((gain - 1.0).abs() < 0.05, "{} Hz passed at {}", note, gain);
+        }
+        assert!(steady_state_gain(&filter, 20.0, sample_rate) < 0.15);
+    }
+
+    #[test]
+    fn test_hum_rejection_recovers_a4_under_heavy_hum() {
+        let sample_rate = 48000.0;
+        let buffer_size = DEFAULT_FFT_SIZE;
+
+        for (hum_rejection, mains) in [(HumRejection::Mains50, 50.0), (HumRejection::Mains60, 60.0)]
+        {
+            let note = test_signals::sawtooth(440.0, sample_rate, buffer_size);
+            let fundamental = test_signals::sine(mains, sample_rate, buffer_size);
+            let harmonic = test_signals::sine(mains * 2.0, sample_rate, buffer_size);
+            let samples: Vec<f32> = (0..buffer_size)
+                .map(|i| 0.1 * note[i] + 0.6 * fundamental[i] + 1.6 * harmonic[i])
+                .collect();
+
+            let mut detector = PitchDetector::new(buffer_size, sample_rate, WindowKind::Hann);
+            let unfiltered = detector
+                .detect_pitch(&samples)
+                .and_then(|estimate| frequency_to_note(estimate.frequency, DEFAULT_A4_FREQUENCY));
+            assert_ne!(
+                unfiltered.map(|note| note.to_string()).as_deref(),
+                Some("A4"),
+                "hum at {} Hz should mask the note without filtering",
+                mains
+            );
+
+            detector.set_pre_filter(hum_rejection, 60.0);
+            let estimate = detector.detect_pitch(&samples);
+            let frequency = estimate.expect("no pitch detected").frequency;
+            assert!(
+                cents_between(frequency, 440.0).abs() < 5.0,
+                "{:?} detected {} Hz",
+                hum_rejection,
+                frequency
+            );
+        }
+    }
+
+    #[test]
+    fn test_confidence_separates_tones_from_noise() {
+        let sample_rate = 48000.0;
+        let buffer_size = DEFAULT_FFT_SIZE;
+        let mut detector = PitchDetector::new(buffer_size, sample_rate, WindowKind::Hann);
+
+        // Deterministic white noise whose biggest bin is about as strong as a
+        // quiet sine's peak.
+        let noise: Vec<f32> = test_signals::noise(buffer_size, 1)
+            .iter()
+            .map(|sample| sample * 0.6)
+            .collect();
+        let sine: Vec<f32> = test_signals::sine(440.0, sample_rate, buffer_size)
+            .iter()
+            .map(|sample| sample * 0.04)
+            .collect();
+        let sawtooth = test_signals::sawtooth(196.0, sample_rate, buffer_size);
+
+        // Confidence is measured on what gets past the tonality gate.
+        detector.set_min_tonality(0.0);
+        let noise = detector
+            .detect_pitch(&noise)
+            .expect("noise still has a biggest bin");
+        let sine = detector.detect_pitch(&sine).expect("no pitch detected");
+        let sawtooth = detector.detect_pitch(&sawtooth).expect("no pitch detected");
+        assert!(
+            (0.5..2.0).contains(&(sine.magnitude / noise.magnitude)),
+            "sine {} vs noise {}",
+            sine.magnitude,
+            noise.magnitude
+        );
+
+        assert!(
+            noise.confidence < 0.3,
+            "noise confidence {}",
+            noise.confidence
+        );
+        assert!(sine.confidence > 0.8, "sine confidence {}", sine.confidence);
+        assert!(
+            sawtooth.confidence > 0.8,
+            "sawtooth confidence {}",
+            sawtooth.confidence
+        );
+    }
+
+    /// Runs a sine whose pitch follows `cents_at(t)` around `center` through
+    /// the detector hop by hop, returning the `(seconds, frequency)` trace.
+    fn detect_modulated_sine(
+        center: f32,
+        seconds: f32,
+        cents_at: impl Fn(f32) -> f32,
+    ) -> Vec<(f32, f32)> {
+        let sample_rate = 48000.0;
+        let size = DEFAULT_FFT_SIZE;
+        let hop = 1024;
+        let mut detector = PitchDetector::new(size, sample_rate, WindowKind::Hann);
+
+        let mut phase = 0.0f32;
+        let samples: Vec<f32> = (0..(seconds * sample_rate) as usize)
+            .map(|index| {
+                let t = index as f32 / sample_rate;
+                phase += 2.0 * PI * center * 2.0f32.powf(cents_at(t) / 1200.0) / sample_rate;
+                0.5 * phase.sin()
+            })
+            .collect();
+
+        (size..=samples.len())
+            .step_by(hop)
+            .filter_map(|end| {
+                let estimate = detector.detect_pitch(&samples[end - size..end])?;
+                let t = (end - size / 2) as f32 / sample_rate;
+                Some((t, estimate.frequency))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_detects_vibrato_in_frequency_modulated_sine() {
+        let trace = detect_modulated_sine(440.0, 2.5, |t| 32.0 * (2.0 * PI * 5.8 * t).sin());
+        let vibrato = detect_vibrato(&trace).expect("vibrato should be detected");
+        assert!((vibrato.rate - 5.8).abs() < 0.3, "{:?}", vibrato);
+        // The analysis window averages part of each cycle away.
+        assert!(
+            (30.0..=70.0).contains(&vibrato.width_cents),
+            "{:?}",
+            vibrato
+        );
+        assert!(
+            (vibrato.center_frequency - 440.0).abs() < 2.0,
+            "{:?}",
+            vibrato
+        );
+
+        let steady = detect_modulated_sine(440.0, 2.5, |_| 0.0);
+        assert_eq!(detect_vibrato(&steady), None);
+
+        let glide = detect_modulated_sine(440.0, 2.5, |t| 40.0 * t);
+        assert_eq!(detect_vibrato(&glide), None);
+
+        assert_eq!(detect_vibrato(&trace[..10]), None);
+    }
+
+    #[test]
+    fn test_note_naming_conventions() {
+        let note = frequency_to_note(466.16, DEFAULT_A4_FREQUENCY).unwrap();
+        assert_eq!(note.pitch_class, 10);
+        assert_eq!(note.octave, 4);
+
+        assert_eq!(note.display_name(NoteNaming::Sharps), "A#4");
+        assert_eq!(note.display_name(NoteNaming::Flats), "B♭4");
+        assert_eq!(note.display_name(NoteNaming::Solfege), "La#4");
+        assert_eq!(note.display_name(NoteNaming::German), "B4");
+
+        let b = frequency_to_note(493.88, DEFAULT_A4_FREQUENCY).unwrap();
+        assert_eq!(b.display_name(NoteNaming::German), "H4");
+        assert_eq!(b.display_name(NoteNaming::Flats), "B4");
+
+        let c = frequency_to_note(261.63, DEFAULT_A4_FREQUENCY).unwrap();
+        assert_eq!(c.display_name(NoteNaming::Solfege), "Do4");
+    }
+
+    #[test]
+    fn test_key_snaps_past_excluded_notes() {
+        let e_flat_major = Key {
+            tonic: 3,
+            mode: KeyMode::Major,
+            custom: PitchClassSet::default(),
+        };
+        let key = e_flat_major.pitch_classes();
+        let snap = |midi: i32, cents: f32| {
+            let frequency = midi_to_frequency(midi, 440.0) * 2f32.powf(cents / 1200.0);
+            let note = frequency_to_key_note(frequency, 440.0, Temperament::Equal, 0, key).unwrap();
+            (
+                note.display_name(e_flat_major.naming(NoteNaming::Sharps)),
+                note.cents_off,
+            )
+        };
+
+        // A♭4 and B♭4 with the excluded A4 between them: the boundary moves
+        // from A♭4 + 50 cents to A4 itself.
+        for (midi, cents, name, expected_cents) in [
+            (68, 40.0, "A♭4", 40.0),
+            (68, 60.0, "A♭4", 60.0),
+            (69, -5.0, "A♭4", 95.0),
+            (69, 5.0, "B♭4", -95.0),
+            (70, -60.0, "B♭4", -60.0),
+            // G4 and A♭4 are a semitone apart and both in the key.
+            (67, 60.0, "A♭4", -40.0),
+        ] {
+            let (snapped, cents_off) = snap(midi, cents);
+            assert_eq!(snapped, name, "MIDI {} {:+} cents", midi, cents);
+            assert!(
+                (cents_off - expected_cents).abs() < 0.1,
+                "MIDI {} {:+} cents read {} {:+.1}",
+                midi,
+                cents,
+                snapped,
+                cents_off
+            );
+        }
+        let note = frequency_to_key_note(445.0, 440.0, Temperament::Just, 3, key).unwrap();
+        assert_eq!(note.midi, 70);
+        assert_eq!(
+            note.target_frequency,
+            tempered_frequency(70, 440.0, Temperament::Just, 3)
+        );
+
+        // In-key notes and an empty set read as chromatic mode does.
+        let chromatic = frequency_to_note(445.0, 440.0).unwrap();
+        let empty = PitchClassSet::default();
+        assert_eq!(
+            frequency_to_key_note(445.0, 440.0, Temperament::Equal, 0, empty).unwrap(),
+            chromatic
+        );
+        let custom = PitchClassSet::from_pitch_classes([9, 21]);
+        assert_eq!(custom, PitchClassSet::from_pitch_classes([9]));
+        assert_eq!(
+            frequency_to_key_note(445.0, 440.0, Temperament::Equal, 0, custom).unwrap(),
+            chromatic
+        );
+        // With a single pitch class, the next one up is an octave away.
+        let a = frequency_to_key_note(600.0, 440.0, Temperament::Equal, 0, custom).unwrap();
+        assert_eq!(a.midi, 69);
+    }
+
+    #[test]
+    fn test_edo_steps_between_semitones() {
+        // One 24-EDO step above A4, a hair under 440 · 2^(1/24).
+        let quarter_sharp_a4 = 452.89;
+        let note = frequency_to_edo_note(quarter_sharp_a4, 440.0, 24).unwrap();
+        assert_eq!(note.display_name(NoteNaming::Sharps), "A4+50¢");
+        assert_eq!(note.midi, 69);
+        assert!(note.cents_off.abs() < 0.1, "{}", note.cents_off);
+        assert!((note.target_frequency - 452.893).abs() < 0.01);
+
+        let note = frequency_to_edo_note(quarter_sharp_a4, 440.0, 12).unwrap();
+        assert_eq!(note.to_string(), "A4");
+        assert!((note.cents_off - 50.0).abs() < 0.1, "{}", note.cents_off);
+
+        // Whole semitones are still plain notes, and every step is named
+        // after the nearest one.
+        let b_flat = frequency_to_edo_note(466.16, 440.0, 24).unwrap();
+        assert_eq!(b_flat.display_name(NoteNaming::Flats), "B♭4");
+        let name = |cents: f32, divisions| {
+            frequency_to_edo_note(440.0 * 2f32.powf(cents / 1200.0), 440.0, divisions)
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(name(-50.0, 24), "G#4+50¢");
+        assert_eq!(name(63.0, 19), "A#4-37¢");
+        assert_eq!(name(126.0, 19), "A#4+26¢");
+        assert_eq!(name(-39.0, 31), "A4-39¢");
+        assert_eq!(name(1200.0, 31), "A5");
+
+        assert_eq!(
+            frequency_to_edo_note(445.0, 442.0, 12),
+            frequency_to_note(445.0, 442.0)
+        );
+        assert!(frequency_to_edo_note(440.0, 440.0, 0).is_none());
+    }
+
+    #[test]
+    fn test_key_spells_accidentals_as_its_signature_does() {
+        let key = |tonic, mode| Key {
+            tonic,
+            mode,
+            custom: PitchClassSet::default(),
+        };
+        let sharps = NoteNaming::Sharps;
+        assert_eq!(key(3, KeyMode::Major).naming(sharps), NoteNaming::Flats);
+        assert_eq!(key(2, KeyMode::Minor).naming(sharps), NoteNaming::Flats);
+        assert_eq!(key(0, KeyMode::Dorian).naming(sharps), NoteNaming::Flats);
+        assert_eq!(key(7, KeyMode::Major).naming(NoteNaming::Flats), sharps);
+        assert_eq!(
+            key(0, KeyMode::Major).naming(NoteNaming::Flats),
+            NoteNaming::Flats
+        );
+        assert_eq!(
+            key(3, KeyMode::Major).naming(NoteNaming::German),
+            NoteNaming::German
+        );
+        assert_eq!(key(3, KeyMode::Custom).naming(sharps), sharps);
+
+        assert_eq!(key(3, KeyMode::Major).label(sharps), "E♭ major");
+        assert_eq!(key(1, KeyMode::Minor).label(sharps), "C# minor");
+        assert_eq!(
+            key(10, KeyMode::MajorPentatonic).pitch_classes(),
+            PitchClassSet::from_pitch_classes([10, 0, 2, 5, 7])
+        );
+    }
+
+    #[test]
+    fn test_midi_numbers_and_negative_octaves() {
+        let a4 = frequency_to_note(440.0, DEFAULT_A4_FREQUENCY).unwrap();
+        assert_eq!((a4.midi, a4.pitch_class, a4.octave), (69, 9, 4));
+
+        let c4 = frequency_to_note(261.63, DEFAULT_A4_FREQUENCY).unwrap();
+        assert_eq!((c4.midi, c4.pitch_class, c4.octave), (60, 0, 4));
+
+        let c_minus_1 =
+            frequency_to_note(midi_to_frequency(0, DEFAULT_A4_FREQUENCY), 440.0).unwrap();
+        assert_eq!((c_minus_1.midi, c_minus_1.octave), (0, -1));
+        assert_eq!(c_minus_1.to_string(), "C-1");
+
+        let g_minus_2 =
+            frequency_to_note(midi_to_frequency(-5, DEFAULT_A4_FREQUENCY), 440.0).unwrap();
+        assert_eq!(
+            (g_minus_2.midi, g_minus_2.pitch_class, g_minus_2.octave),
+            (-5, 7, -2)
+        );
+        assert_eq!(g_minus_2.to_string(), "G-2");
+    }
+
+    #[test]
+    fn test_parse_note_names() {
+        let parsed = |name| Note::parse(name).map(|note| (note.midi, note.to_string()));
+        assert_eq!(parsed("A4"), Some((69, "A4".to_string())));
+        assert_eq!(parsed("D#3"), Some((51, "D#3".to_string())));
+        assert_eq!(parsed(" eb2 "), Some((39, "D#2".to_string())));
+        assert_eq!(parsed("B♭1"), Some((34, "A#1".to_string())));
+        assert_eq!(parsed("C-1"), Some((0, "C-1".to_string())));
+        // Accidentals can cross the octave boundary.
+        assert_eq!(parsed("Cb4"), Some((59, "B3".to_string())));
+        assert_eq!(parsed("B#3"), Some((60, "C4".to_string())));
+        for invalid in ["", "H2", "A", "A#", "Ax4", "4A", "A4.5", "E 2"] {
+            assert_eq!(parsed(invalid), None, "{:?}", invalid);
+        }
+
+        let a4 = Note::parse("A4").unwrap();
+        assert_eq!(a4.target_frequency, 440.0);
+        assert_eq!(a4.cents_off, 0.0);
+    }
+
+    proptest! {
+        #[test]
+        fn test_notes_round_trip_across_the_midi_range(
+            midi in 0i32..=127,
+            cents in -49.99f64..49.99,
+            a4 in 400.0f64..480.0,
+        ) {
+            let frequency = a4 * 2f64.powf((midi - 69) as f64 / 12.0 + cents / 1200.0);
+            let note = frequency_to_note(frequency as f32, a4 as f32).unwrap();
+            prop_assert_eq!(note.midi, midi);
+            prop_assert_eq!(note.pitch_class as i32, midi % 12);
+            // MIDI 0 is C-1 and 60 is C4.
+            prop_assert_eq!(note.octave, midi / 12 - 1);
+            prop_assert!(
+                (note.cents_off as f64 - cents).abs() < 0.01,
+                "{} cents read as {}",
+                cents,
+                note.cents_off
+            );
+        }
+
+        #[test]
+        fn test_note_names_parse_back(midi in -12i32..=127) {
+            let note = midi_to_note(midi, 0.0, DEFAULT_A4_FREQUENCY);
+            for naming in [NoteNaming::Sharps, NoteNaming::Flats] {
+                let parsed = Note::parse(&note.display_name(naming)).unwrap();
+                prop_assert_eq!(parsed.midi, midi);
+            }
+        }
+
+        #[test]
+        fn test_consecutive_notes_step_through_octaves(midi in -24i32..140) {
+            let note = |midi| {
+                frequency_to_note(midi_to_frequency(midi, DEFAULT_A4_FREQUENCY), 440.0).unwrap()
+            };
+            let (lower, upper) = (note(midi), note(midi + 1));
+            prop_assert_eq!(upper.midi, lower.midi + 1);
+            if lower.pitch_class == 11 {
+                prop_assert_eq!((upper.pitch_class, upper.octave), (0, lower.octave + 1));
+            } else {
+                prop_assert_eq!(
+                    (upper.pitch_class, upper.octave),
+                    (lower.pitch_class + 1, lower.octave)
+                );
+            }
+        }
+
+        #[test]
+        fn test_any_frequency_is_within_half_a_semitone(frequency in 1.0f32..30000.0) {
+            let note = frequency_to_note(frequency, DEFAULT_A4_FREQUENCY).unwrap();
+            prop_assert!(note.cents_off.abs() <= 50.001, "{} cents", note.cents_off);
+            let back = note.target_frequency * 2.0f32.powf(note.cents_off / 1200.0);
+            prop_assert!((back / frequency - 1.0).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_octave_changes_between_b3_and_c4() {
+        let b3 = midi_to_frequency(59, DEFAULT_A4_FREQUENCY);
+        let notes: Vec<String> = [-49.0, 0.0, 49.0, 51.0, 100.0, 149.0]
+            .iter()
+            .map(|cents| b3 * 2.0f32.powf(cents / 1200.0))
+            .map(|frequency| frequency_to_note(frequency, 440.0).unwrap().to_string())
+            .collect();
+        assert_eq!(notes, ["B3", "B3", "B3", "C4", "C4", "C4"]);
+    }
+
+    #[test]
+    fn test_halfway_between_notes_snaps_up() {
+        assert_eq!(nearest_semitone(0.5), 1);
+        assert_eq!(nearest_semitone(-0.5), 0);
+        assert_eq!(nearest_semitone(-12.5), -12);
+        assert_eq!(nearest_semitone(-12.49), -12);
+        assert_eq!(nearest_semitone(-12.51), -13);
+
+        // A quarter tone above A4 and above A3 both read as the note above.
+        for a in [440.0, 220.0] {
+            let quarter_tone_up = a * 2.0f32.powf(50.0 / 1200.0);
+            let note = frequency_to_note(quarter_tone_up, 440.0).unwrap();
+            assert_eq!(note.pitch_class, 10, "{} Hz", quarter_tone_up);
+            assert!((note.cents_off + 50.0).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn test_invalid_frequencies_have_no_note() {
+        for frequency in [0.0, -440.0, f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            assert!(
+                frequency_to_note(frequency, 440.0).is_none(),
+                "{}",
+                frequency
+            );
+            assert!(
+                frequency_to_note(440.0, frequency).is_none(),
+                "A4 = {}",
+                frequency
+            );
+        }
+        assert!(frequency_to_tempered_note(f32::NAN, 440.0, Temperament::Just, 0).is_none());
+    }
+
+    #[test]
+    fn test_target_frequency_round_trips() {
+        for a4 in [415.0, DEFAULT_A4_FREQUENCY, 442.0] {
+            for frequency in [41.2, 98.5, 261.0, 446.0, 1046.5] {
+                let note = frequency_to_note(frequency, a4).unwrap();
+                assert!((note.target_frequency - midi_to_frequency(note.midi, a4)).abs() < 1e-3);
+
+                let target = frequency_to_note(note.target_frequency, a4).unwrap();
+                assert_eq!(target.midi, note.midi);
+                assert!(target.cents_off.abs() < 0.01, "{} cents", target.cents_off);
+            }
+        }
+    }
+
+    #[test]
+    fn test_temperament_offsets() {
+        let c = 0;
+        let e = 4;
+        let g = 7;
+
+        assert!((Temperament::Just.offset_cents(e, c) - -13.7).abs() < 0.05);
+        assert!((Temperament::Just.offset_cents(g, c) - 1.96).abs() < 0.01);
+        assert!((Temperament::Pythagorean.offset_cents(e, c) - 7.82).abs() < 0.01);
+        assert!((Temperament::QuarterCommaMeantone.offset_cents(e, c) - -13.69).abs() < 0.01);
+
+        // The offsets follow the tonic: in D major, F# is the just major third.
+        assert_eq!(
+            Temperament::Just.offset_cents(6, 2),
+            Temperament::Just.offset_cents(e, c)
+        );
+
+        for pitch_class in 0..12 {
+            assert_eq!(Temperament::Equal.offset_cents(pitch_class, 3), 0.0);
+        }
+    }
+
+    #[test]
+    fn test_tempered_note_targets() {
+        let just_e4 = midi_to_frequency(64, DEFAULT_A4_FREQUENCY) * 2.0f32.powf(-13.69 / 1200.0);
+        let note = frequency_to_tempered_note(just_e4, DEFAULT_A4_FREQUENCY, Temperament::Just, 0)
+            .unwrap();
+        assert_eq!(note.to_string(), "E4");
+        assert!(note.cents_off.abs() < 0.05);
+        assert!((note.target_frequency - just_e4).abs() < 0.01);
+
+        let equal = frequency_to_note(just_e4, DEFAULT_A4_FREQUENCY).unwrap();
+        assert!((equal.cents_off - -13.69).abs() < 0.05);
+
+        for frequency in [55.0, 261.63, 311.0, 440.0, 987.77] {
+            let tempered =
+                frequency_to_tempered_note(frequency, 442.0, Temperament::Equal, 5).unwrap();
+            let plain = frequency_to_note(frequency, 442.0).unwrap();
+            assert_eq!(tempered.cents_off.to_bits(), plain.cents_off.to_bits());
+            assert_eq!(
+                tempered.target_frequency.to_bits(),
+                plain.target_frequency.to_bits()
+            );
+        }
+    }
+
+    #[test]
+    fn test_tempered_frequency_reads_in_tune() {
+        for temperament in Temperament::ALL {
+            for tonic in [0, 2, 7] {
+                for midi in 36..=84 {
+                    let frequency = tempered_frequency(midi, 442.0, temperament, tonic);
+                    let note =
+                        frequency_to_tempered_note(frequency, 442.0, temperament, tonic).unwrap();
+                    assert_eq!(note.midi, midi, "{:?} on {}", temperament, tonic);
+                    assert!(note.cents_off.abs() < 0.01, "{:?} {}", temperament, midi);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_beat_frequency_is_the_difference_from_the_target() {
+        let cents = |frequency: f32, target: f32| 1200.0 * (frequency / target).log2();
+        assert!((beat_frequency(440.0, cents(441.8, 440.0)) - 1.8).abs() < 1e-3);
+        assert!((beat_frequency(440.0, cents(438.2, 440.0)) - 1.8).abs() < 1e-3);
+        assert_eq!(beat_frequency(440.0, 0.0), 0.0);
+        // The same cents beat faster the higher the note.
+        assert!((beat_frequency(880.0, 10.0) / beat_frequency(440.0, 10.0) - 2.0).abs() < 1e-4);
+
+        // Measured against a just D major scale at A4 = 415 Hz, the target
+        // is the tempered note, not the equal-tempered one.
+        let target = tempered_frequency(54, 415.0, Temperament::Just, 2);
+        let played = target + 0.9;
+        let note = frequency_to_tempered_note(played, 415.0, Temperament::Just, 2).unwrap();
+        assert_eq!(note.midi, 54);
+        assert!((beat_frequency(note.target_frequency, note.cents_off) - 0.9).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_partial_beats_against_a_drone() {
+        let d3 = midi_to_frequency(50, 440.0);
+        // Unison and octaves beat at the difference of the coinciding partials.
+        assert!((partial_beat_frequency(d3 + 1.5, d3).unwrap() - 1.5).abs() < 1e-3);
+        assert!((partial_beat_frequency(2.0 * d3 + 1.5, d3).unwrap() - 1.5).abs() < 1e-3);
+        assert!((partial_beat_frequency(d3 - 0.7, 2.0 * d3).unwrap() - 1.4).abs() < 1e-3);
+        // A pure fifth doesn't beat; an equal-tempered one, two cents narrow,
+        // beats about once every two seconds at A3.
+        assert!(partial_beat_frequency(1.5 * d3, d3).unwrap() < 1e-3);
+        let a3 = midi_to_frequency(57, 440.0);
+        let beats = partial_beat_frequency(a3, d3).unwrap();
+        assert!((beats - (3.0 * d3 - 2.0 * a3)).abs() < 1e-3, "{}", beats);
+        assert!((beats - 0.5).abs() < 0.01, "{}", beats);
+        // A tritone's partials don't coincide below the sixth.
+        assert_eq!(
+            partial_beat_frequency(midi_to_frequency(56, 440.0), d3),
+            None
+        );
+    }
+
+    #[test]
+    fn test_detect_peaks_separates_a_double_stop() {
+        let sample_rate = 48000.0;
+        let buffer_size = DEFAULT_FFT_SIZE;
+        let mut detector = PitchDetector::new(buffer_size, sample_rate, WindowKind::Hann);
+
+        // One bright note is one peak, however strong its harmonics.
+        let a3 = test_signals::sawtooth(220.0, sample_rate, buffer_size);
+        let peaks = detector.detect_peaks(&a3, 3);
+        assert_eq!(peaks.len(), 1, "{:?}", peaks);
+        assert!(cents_between(peaks[0].0, 220.0).abs() < 2.0);
+
+        // A3 with an E4 4 cents above a pure fifth.
+        let e4 = 330.0 * 2f32.powf(4.0 / 1200.0);
+        let mix: Vec<f32> = a3
+            .iter()
+            .zip(test_signals::sawtooth(e4, sample_rate, buffer_size))
+            .map(|(a, e)| (a + e) / 2.0)
+            .collect();
+        let mut peaks = detector.detect_peaks(&mix, 3);
+        assert_eq!(peaks.len(), 2, "{:?}", peaks);
+        peaks.sort_by(|a, b| a.0.total_cmp(&b.0));
+        assert!(cents_between(peaks[0].0, 220.0).abs() < 2.0);
+        assert!(cents_between(peaks[1].0, e4).abs() < 2.0);
+
+        let notes: Vec<Note> = peaks
+            .iter()
+            .map(|&(frequency, _)| frequency_to_note(frequency, DEFAULT_A4_FREQUENCY).unwrap())
+            .collect();
+        let just = Interval::between(&notes[1], &notes[0], Temperament::Just, 0);
+        assert_eq!(just.semitones, 7);
+        assert!((just.cents_off - 4.0).abs() < 1.0, "{:?}", just);
+        assert_eq!(just.to_string(), "P5 +4¢ wide");
+        let equal = Interval::between(&notes[0], &notes[1], Temperament::Equal, 0);
+        assert!((equal.cents_off - 5.96).abs() < 1.0, "{:?}", equal);
+
+        assert!(detector.detect_peaks(&vec![0.0; buffer_size], 3).is_empty());
+    }
+
+    #[test]
+    fn test_interval_names() {
+        let named = |semitones, cents_off| Interval {
+            semitones,
+            cents_off,
+        };
+        assert_eq!(named(0, 0.2).to_string(), "P1 in tune");
+        assert_eq!(named(3, -14.0).to_string(), "m3 -14¢ narrow");
+        assert_eq!(named(4, 0.0).name(), "M3");
+        assert_eq!(named(6, 0.0).name(), "TT");
+        assert_eq!(named(12, 0.0).name(), "P8");
+        assert_eq!(named(16, 0.0).name(), "M10");
+        assert_eq!(named(19, 0.0).name(), "P12");
+        assert_eq!(named(24, 0.0).name(), "P15");
+
+        // Meantone thirds are pure, its fifths a quarter comma narrow.
+        let note = |frequency| frequency_to_note(frequency, DEFAULT_A4_FREQUENCY).unwrap();
+        let (c4, e4, g4) = (note(261.63), note(329.63), note(392.0));
+        let third = Interval::between(&c4, &e4, Temperament::QuarterCommaMeantone, 0);
+        assert!((third.cents_off - 13.69).abs() < 0.1, "{:?}", third);
+        let fifth = Interval::between(&c4, &g4, Temperament::QuarterCommaMeantone, 0);
+        assert!((fifth.cents_off - 3.42).abs() < 0.1, "{:?}", fifth);
+    }
+
+    #[test]
+    fn test_onset_detector_fires_once_per_attack() {
+        let sample_rate = 48000.0;
+        let mut onsets = OnsetDetector::new(sample_rate);
+        let quiet = test_signals::sine(220.0, sample_rate, 24000);
+        let signal: Vec<f32> = quiet
+            .iter()
+            .enumerate()
+            .map(|(index, sample)| if index < 12000 { sample * 0.1 } else { *sample })
+            .collect();
+
+        // Walk through in 5 ms hops, as the worker polls.
+        let fired: Vec<usize> = (2048..=signal.len())
+            .step_by(240)
+            .filter(|&end| onsets.detect(&signal[..end]))
+            .collect();
+        assert_eq!(fired.len(), 1, "{:?}", fired);
+        assert!((12000..12000 + 960).contains(&fired[0]), "{:?}", fired);
+    }
+
+    #[test]
+    fn test_low_range_resolves_sub_bass_notes() {
+        let sample_rate = 48000.0;
+        let buffer_size = 8192;
+        let mut detector = PitchDetector::new(buffer_size, sample_rate, WindowKind::Hann);
+        detector.set_low_range(true);
+        assert!((detector.bin_width() - sample_rate / 4.0 / buffer_size as f32).abs() < 1e-6);
+
+        for (name, frequency) in [("B0", 30.87), ("E1", 41.2)] {
+            let samples = test_signals::sine(frequency, sample_rate, buffer_size);
+            let estimate = detector
+                .detect_pitch(&samples)
+                .unwrap_or_else(|| panic!("no pitch detected for {}", name));
+            let note = frequency_to_note(estimate.frequency, DEFAULT_A4_FREQUENCY).unwrap();
+            assert_eq!(note.to_string(), name);
+            let cents = cents_between(estimate.frequency, frequency);
+            assert!(cents.abs() < 3.0, "{} off by {} cents", name, cents);
+        }
+
+        // A note above the low range isn't aliased down into it.
+        let samples = test_signals::sine(3000.0, sample_rate, buffer_size);
+        assert!(detector.detect_pitch(&samples).is_none());
+    }
+
+    #[test]
+    fn test_hybrid_detection_is_accurate_in_every_register() {
+        for sample_rate in [44100.0, 48000.0] {
+            let mut detector = PitchDetector::new(DEFAULT_FFT_SIZE, sample_rate, WindowKind::Hann);
+            for frequency in [41.0, 98.0, 196.0, 440.0] {
+                for (kind, samples) in [
+                    (
+                        "sine",
+                        test_signals::sine(frequency, sample_rate, DEFAULT_FFT_SIZE),
+                    ),
+                    (
+                        "sawtooth",
+                        test_signals::sawtooth(frequency, sample_rate, DEFAULT_FFT_SIZE),
+                    ),
+                ] {
+                    let estimate = detector.detect_pitch(&samples).unwrap_or_else(|| {
+                        panic!(
+                            "no pitch for a {} Hz {} at {}",
+                            frequency, kind, sample_rate
+                        )
+                    });
+                    let cents = cents_between(estimate.frequency, frequency);
+                    assert!(
+                        cents.abs() < 3.0,
+                        "{} Hz {} at {} Hz off by {} cents",
+                        frequency,
+                        kind,
+                        sample_rate,
+                        cents
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_fft_harmonic_errors_are_caught_in_the_time_domain() {
+        // A weak fundamental under strong second and third harmonics, like a
+        // low string through a small speaker.
+        let sample_rate = 48000.0;
+        let fundamental = 98.0;
+        let samples: Vec<f32> = (0..DEFAULT_FFT_SIZE)
+            .map(|index| {
+                let t = index as f32 / sample_rate;
+                [(1.0, 0.02), (2.0, 0.5), (3.0, 0.4)]
+                    .iter()
+                    .map(|(harmonic, amplitude)| {
+                        amplitude * (std::f32::consts::TAU * harmonic * fundamental * t).sin()
+                    })
+                    .sum()
+            })
+            .collect();
+        let mut detector = PitchDetector::new(DEFAULT_FFT_SIZE, sample_rate, WindowKind::Hann);
+        let estimate = detector.detect_pitch(&samples).expect("no pitch detected");
+        assert!(cents_between(estimate.frequency, fundamental).abs() < 3.0);
+
+        // With the crossover below it the FFT's reading stands.
+        detector.set_crossover_frequency(30.0);
+        let estimate = detector.detect_pitch(&samples).expect("no pitch detected");
+        assert!(
+            estimate.frequency > 1.5 * fundamental,
+            "{}",
+            estimate.frequency
+        );
+    }
+
+    #[test]
+    fn test_frequency_range_gates_detection() {
+        let sample_rate = 44100.0;
+        let buffer_size = DEFAULT_FFT_SIZE;
+        let mut detector = PitchDetector::new(buffer_size, sample_rate, WindowKind::Hann);
+        assert_eq!(detector.frequency_range(), CHROMATIC_RANGE);
+
+        // G7, above a piccolo's top note, is found across the chromatic range
+        // but not in a bass's.
+        let g7 = test_signals::sine(3136.0, sample_rate, buffer_size);
+        let estimate = detector
+            .detect_pitch(&g7)
+            .expect("no pitch detected for G7");
+        assert!(cents_between(estimate.frequency, 3136.0).abs() < 3.0);
+        detector.set_frequency_range(25.0, 500.0);
+        assert!(detector.detect_pitch(&g7).is_none());
+
+        // Rumble below a violin's range is ignored there, by YIN too.
+        let rumble = test_signals::sine(60.0, sample_rate, buffer_size);
+        detector.set_frequency_range(CHROMATIC_RANGE.0, CHROMATIC_RANGE.1);
+        assert!(detector.detect_pitch(&rumble).is_some());
+        detector.set_frequency_range(147.0, 4000.0);
+        assert!(detector.detect_pitch(&rumble).is_none());
+
+        // The range stays within what the detector can find at all.
+        detector.set_frequency_range(1.0, 20000.0);
+        assert_eq!(
+            detector.frequency_range(),
+            (MIN_DETECTABLE_FREQUENCY, MAX_DETECTABLE_FREQUENCY)
+        );
+        detector.set_frequency_range(600.0, 300.0);
+        assert_eq!(detector.frequency_range(), (600.0, 600.0));
+    }
+
+    #[test]
+    fn test_weak_fundamental_is_inferred_from_its_partials() {
+        let sample_rate = 48000.0;
+        let buffer_size = DEFAULT_FFT_SIZE;
+        // Fundamental 20 dB below partials 2–5, as on low piano strings.
+        let amplitudes = [0.03, 0.3, 0.3, 0.3, 0.3];
+
+        for (name, fundamental) in [("A0", 27.5), ("A1", 55.0), ("E2", 82.41)] {
+            let samples: Vec<f32> = (0..buffer_size)
+                .map(|index| {
+                    let t = index as f32 / sample_rate;
+                    amplitudes
+                        .iter()
+                        .enumerate()
+                        .map(|(partial, amplitude)| {
+                            let frequency = fundamental * (partial + 1) as f32;
+                            amplitude * (std::f32::consts::TAU * frequency * t).sin()
+                        })
+                        .sum()
+                })
+                .collect();
+            for low_range in [false, true] {
+                let mut detector = PitchDetector::new(buffer_size, sample_rate, WindowKind::Hann);
+                detector.set_low_range(low_range);
+                let estimate = detector
+                    .detect_pitch(&samples)
+                    .unwrap_or_else(|| panic!("{} (low range {}): no pitch", name, low_range));
+                let note = frequency_to_note(estimate.frequency, DEFAULT_A4_FREQUENCY).unwrap();
+                assert_eq!(
+                    note.to_string(),
+                    name,
+                    "low range {}: {} Hz",
+                    low_range,
+                    estimate.frequency
+                );
+                assert!(note.cents_off.abs() < 10.0, "{}: {}", name, note.cents_off);
+                assert!(
+                    estimate.confidence > 0.9,
+                    "{}: {}",
+                    name,
+                    estimate.confidence
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_frequency_prior_settles_an_octave_error() {
+        let sample_rate = 48000.0;
+        let buffer_size = DEFAULT_FFT_SIZE;
+        let mut detector = PitchDetector::new(buffer_size, sample_rate, WindowKind::Hann);
+
+        // E2 with a barely-there fundamental and strong even harmonics, as
+        // through a distorted amp, reads as E3.
+        let e2 = 82.41;
+        let amplitudes = [0.01, 0.5, 0.05, 0.4, 0.05, 0.3];
+        let samples: Vec<f32> = (0..buffer_size)
+            .map(|index| {
+                let t = index as f32 / sample_rate;
+                amplitudes
+                    .iter()
+                    .enumerate()
+                    .map(|(harmonic, amplitude)| {
+                        amplitude * (std::f32::consts::TAU * e2 * (harmonic + 1) as f32 * t).sin()
+                    })
+                    .sum()
+            })
+            .collect();
+        let estimate = detector.detect_pitch(&samples).expect("no pitch");
+        assert!(
+            cents_between(estimate.frequency, 2.0 * e2).abs() < 5.0,
+            "{}",
+            estimate.frequency
+        );
+
+        // Asserting the octave below finds E2.
+        detector.set_frequency_prior(Some(FrequencyPrior {
+            center: e2,
+            range_cents: 600.0,
+        }));
+        let estimate = detector.detect_pitch(&samples).expect("no pitch");
+        assert!(
+            cents_between(estimate.frequency, e2).abs() < 5.0,
+            "{}",
+            estimate.frequency
+        );
+
+        // The spectrum still covers the whole range, and clearing the prior
+        // goes back to E3.
+        let mut spectrum = Vec::new();
+        detector.detect_pitch_with_spectrum(&samples, &mut spectrum);
+        assert_eq!(spectrum.len(), {
+            let (min_bin, max_bin) = detector.search_bins();
+            max_bin - min_bin + 1
+        });
+        detector.set_frequency_prior(None);
+        let estimate = detector.detect_pitch(&samples).expect("no pitch");
+        assert!(cents_between(estimate.frequency, 2.0 * e2).abs() < 5.0);
+
+        assert_eq!(
+            FrequencyPrior {
+                center: 440.0,
+                range_cents: 1200.0,
+            }
+            .bounds(),
+            (220.0, 880.0)
+        );
+    }
+
+    #[test]
+    fn test_flat_topped_peak_refines_to_a_finite_frequency() {
+        let mut detector = PitchDetector::new(8192, 48000.0, WindowKind::Hann);
+        let bin = (440.0 / detector.bin_width()) as usize;
+        // Three equal bins, with harmonics only where the middle one's fall,
+        // so the middle bin is picked and the parabola through them is flat.
+        detector.magnitudes.fill(0.0);
+        detector.magnitudes[bin - 1..=bin + 1].fill(1.0);
+        for harmonic in 2..=HPS_HARMONICS {
+            detector.magnitudes[bin * harmonic] = 0.5;
+        }
+
+        let estimate = detector.spectrum_peak().expect("no peak");
+        assert_eq!(estimate.frequency, bin as f32 * detector.bin_width());
+
+        assert_eq!(parabolic_offset(1.0, 1.0, 1.0), 0.0);
+        assert_eq!(parabolic_offset(0.5, 1.0, 0.5), 0.0);
+        assert!((parabolic_offset(0.0, 1.0, 0.5) - 1.0 / 6.0).abs() < 1e-6);
+        // A lopsided triple without a peak in the middle stays within its bin.
+        assert_eq!(parabolic_offset(0.0, 1.0, 3.0), -0.5);
+    }
+
+    #[test]
+    fn test_steady_state_detection_does_not_allocate() {
+        let sample_rate = 48000.0;
+        let buffer_size = DEFAULT_FFT_SIZE;
+        let high = test_signals::sawtooth(440.0, sample_rate, buffer_size);
+        let low = test_signals::sawtooth(41.2, sample_rate, buffer_size);
+        let mut detector = PitchDetector::new(buffer_size, sample_rate, WindowKind::Hann);
+        detector.set_pre_filter(HumRejection::Mains50, 40.0);
+        let mut spectrum = Vec::new();
+
+        for low_range in [false, true] {
+            detector.set_low_range(low_range);
+            let mut analyse = || {
+                for samples in [&high, &low] {
+                    detector.detect_pitch(samples);
+                    detector.detect_pitch_with_spectrum(samples, &mut spectrum);
+                }
+            };
+            // The first pass sizes the filter and spectrum buffers.
+            analyse();
+            let (_, allocations) = count_allocations(analyse);
+            assert_eq!(allocations, 0, "low range {}", low_range);
+        }
+    }
+
+    #[test]
+    fn test_sines_between_bins_are_placed_within_a_cent() {
+        let sample_rate = 48000.0;
+        let buffer_size = DEFAULT_FFT_SIZE;
+        for precision in Precision::ALL {
+            let mut detector = PitchDetector::new(buffer_size, sample_rate, WindowKind::Hann);
+            detector.set_precision(precision);
+            let padded_bin = sample_rate / (buffer_size * precision.zero_padding()) as f32;
+            assert!((detector.bin_width() - padded_bin).abs() < 1e-6);
+            let mut max_error = 0.0f32;
+            // 437–443 Hz crosses a whole bin of the unpadded FFT.
+            for step in 0..=12 {
+                let frequency = 437.0 + 0.5 * step as f32;
+                let samples = test_signals::sine(frequency, sample_rate, buffer_size);
+                let estimate = detector.detect_pitch(&samples).expect("no pitch");
+                max_error = max_error.max(cents_between(estimate.frequency, frequency).abs());
+            }
+            assert!(max_error < 1.0, "{:?}: {:.2} cents", precision, max_error);
+        }
+    }
+
+    #[test]
+    fn test_loud_pink_noise_is_rejected_and_a_quiet_sine_is_not() {
+        let sample_rate = 48000.0;
+        let buffer_size = DEFAULT_FFT_SIZE;
+        let mut detector = PitchDetector::new(buffer_size, sample_rate, WindowKind::Hann);
+
+        let noise = test_signals::pink_noise(buffer_size * 6, 3);
+        for block in noise.chunks(buffer_size) {
+            assert_eq!(detector.detect_pitch(block), None);
+        }
+
+        // −30 dBFS.
+        let quiet: Vec<f32> = test_signals::sine(440.0, sample_rate, buffer_size)
+            .iter()
+            .map(|sample| sample * 2.0 * 10.0f32.powf(-30.0 / 20.0))
+            .collect();
+        let estimate = detector.detect_pitch(&quiet).expect("quiet sine rejected");
+        assert!(cents_between(estimate.frequency, 440.0).abs() < 1.0);
+
+        // Without the gate the noise has peaks enough to be given a pitch.
+        detector.set_min_tonality(0.0);
+        assert!(noise
+            .chunks(buffer_size)
+            .any(|block| detector.detect_pitch(block).is_some()));
     }
 }
@@ -0,0 +1,118 @@
+//! Synthetic signals for tests and benchmarks
+//!
+//! Deterministic sines, sawtooths and white noise at half full scale. The
+//! benchmarks include this file by path, as the library only builds it for
+//! its own tests.
+
+use std::f32::consts::PI;
+
+/// Position within the cycle, 0 to 1, of sample `index`.
+fn phase(frequency: f32, sample_rate: f32, index: usize) -> f32 {
+    (frequency * index as f32 / sample_rate).fract()
+}
+
+/// `len` samples of a sine at half full scale.
+pub fn sine(frequency: f32, sample_rate: f32, len: usize) -> Vec<f32> {
+    (0..len)
+        .map(|index| (2.0 * PI * phase(frequency, sample_rate, index)).sin() * 0.5)
+        .collect()
+}
+
+/// `len` samples of a sawtooth at half full scale, rich in harmonics like a
+/// plucked or bowed string.
+pub fn sawtooth(frequency: f32, sample_rate: f32, len: usize) -> Vec<f32> {
+    (0..len)
+        .map(|index| (2.0 * phase(frequency, sample_rate, index) - 1.0) * 0.5)
+        .collect()
+}
+
+/// `len` samples of a stiff string like a piano's: `partials` partials
+/// falling off as 1/n, the nth at n·f0·√(1 + B·n²) for inharmonicity
+/// `inharmonicity` (B), together at half full scale.
+pub fn inharmonic(
+    f0: f32,
+    inharmonicity: f32,
+    partials: usize,
+    sample_rate: f32,
+    len: usize,
+) -> Vec<f32> {
+    let mut samples = vec![0.0; len];
+    let norm: f32 = (1..=partials).map(|n| 1.0 / n as f32).sum();
+    for n in 1..=partials {
+        let n = n as f32;
+        let frequency = n * f0 * (1.0 + inharmonicity * n * n).sqrt();
+        for (index, sample) in samples.iter_mut().enumerate() {
+            *sample += (2.0 * PI * phase(frequency, sample_rate, index)).sin() * 0.5 / n / norm;
+        }
+    }
+    samples
+}
+
+/// `len` samples of white noise between -1 and 1, the same for the same `seed`.
+pub fn noise(len: usize, seed: u32) -> Vec<f32> {
+    let mut state = seed;
+    (0..len)
+        .map(|_| {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (state >> 8) as f32 / (1 << 24) as f32 * 2.0 - 1.0
+        })
+        .collect()
+}
+
+/// `len` samples of pink noise, falling 3 dB per octave like fans, air
+/// conditioning and room rumble, peaking around half full scale. Paul Kellet's
+/// filter over `noise`.
+pub fn pink_noise(len: usize, seed: u32) -> Vec<f32> {
+    let mut state = [0.0f32; 7];
+    noise(len, seed)
+        .into_iter()
+        .map(|white| {
+            state[0] = 0.99886 * state[0] + white * 0.0555179;
+            state[1] = 0.99332 * state[1] + white * 0.0750759;
+            state[2] = 0.96900 * state[2] + white * 0.153852;
+            state[3] = 0.86650 * state[3] + white * 0.3104856;
+            state[4] = 0.55000 * state[4] + white * 0.5329522;
+            state[5] = -0.7616 * state[5] - white * 0.0168980;
+            let pink = state.iter().sum::<f32>() + white * 0.5362;
+            state[6] = white * 0.115926;
+            pink * 0.11
+        })
+        .collect()
+}
+
+/// A sine with white noise mixed in at `noise_level` times full scale.
+pub fn sine_with_noise(frequency: f32, sample_rate: f32, len: usize, noise_level: f32) -> Vec<f32> {
+    sine(frequency, sample_rate, len)
+        .iter()
+        .zip(noise(len, 1))
+        .map(|(sample, noise)| sample + noise * noise_level)
+        .collect()
+}
+
+/// A sawtooth gliding from `start` to `end` Hz at an even rate in cents.
+pub fn sweep(start: f32, end: f32, sample_rate: f32, len: usize) -> Vec<f32> {
+    let octaves = (end / start).log2();
+    let mut phase = 0.0f32;
+    (0..len)
+        .map(|index| {
+            let frequency = start * 2.0f32.powf(octaves * index as f32 / len as f32);
+            phase = (phase + frequency / sample_rate).fract();
+            (2.0 * phase - 1.0) * 0.5
+        })
+        .collect()
+}
+
+/// Sawtooth notes played one after another, each given as a frequency (or
+/// `None` for a rest) and a length in seconds.
+pub fn melody(notes: &[(Option<f32>, f32)], sample_rate: f32) -> Vec<f32> {
+    notes
+        .iter()
+        .flat_map(|&(frequency, seconds)| {
+            let len = (seconds * sample_rate) as usize;
+            match frequency {
+                Some(frequency) => sawtooth(frequency, sample_rate, len),
+                None => vec![0.0; len],
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,573 @@
+//! Instrument presets for string-by-string tuning
+//!
+//! Defines built-in tunings for common string instruments and matches detected
+//! frequencies to the closest open string instead of the nearest chromatic note.
+//! A preset can be transposed for a capo or a lowered tuning, and users can
+//! add their own tunings in a `presets.toml` next to the configuration.
+
+use anyhow::{anyhow, bail, Context, Result};
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::pitch::{
+    frequency_to_note, midi_to_frequency, midi_to_note, Note, NoteNaming, CHROMATIC_RANGE,
+    DEFAULT_A4_FREQUENCY, MAX_DETECTABLE_FREQUENCY, MIN_DETECTABLE_FREQUENCY,
+};
+
+const USER_PRESETS_FILE_NAME: &str = "presets.toml";
+
+/// Lowest transposition offered, in semitones.
+pub const MIN_TRANSPOSE: i32 = -5;
+/// Highest transposition offered, in semitones.
+pub const MAX_TRANSPOSE: i32 = 7;
+
+/// A named instrument tuning.
+#[derive(Clone, Debug)]
+pub struct InstrumentPreset {
+    /// Name shown in the preset menu.
+    pub name: String,
+    /// Target notes of the strings at A4 = 440 Hz, ordered from the
+    /// highest-numbered string down to string 1.
+    pub strings: Vec<Note>,
+    /// The strings before any transposition, in the same order.
+    pub open_strings: Vec<Note>,
+    /// Semitones the targets are shifted from the open strings.
+    pub transpose: i32,
+    /// Cents the targets are shifted on top of `transpose`.
+    pub offset_cents: f32,
+}
+
+/// The open string a frequency was matched to.
+#[derive(Clone, Debug)]
+pub struct StringMatch {
+    /// String number, 1 being the highest string.
+    pub string_number: usize,
+    /// The string's note, with the deviation of the matched frequency.
+    pub note: Note,
+}
+
+/// One string of a user preset, as written in the presets file.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum StringTuning {
+    /// A note name such as "D#3".
+    Note(String),
+    /// A frequency in Hz at A4 = 440 Hz.
+    Frequency(f32),
+}
+
+/// A tuning from the user's presets file, as written there.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct UserPreset {
+    /// Name shown in the preset menu.
+    pub name: String,
+    /// Open strings, lowest first.
+    pub strings: Vec<StringTuning>,
+}
+
+/// Layout of the presets file: a `[[preset]]` table per tuning. Entries are
+/// kept as raw values so that one malformed entry doesn't hide the rest.
+#[derive(Default, Deserialize)]
+struct UserPresetsFile {
+    #[serde(default)]
+    preset: Vec<toml::Value>,
+}
+
+#[derive(Serialize)]
+struct UserPresetsFileRef<'a> {
+    preset: &'a [UserPreset],
+}
+
+impl InstrumentPreset {
+    /// A preset with open strings at the given MIDI notes, lowest string first.
+    pub fn from_midi(name: &str, midi_notes: &[i32]) -> Self {
+        let strings = midi_notes
+            .iter()
+            .map(|&midi| {
+                let mut note = frequency_to_note(
+                    midi_to_frequency(midi, DEFAULT_A4_FREQUENCY),
+                    DEFAULT_A4_FREQUENCY,
+                )
+                .expect("MIDI notes have a positive frequency");
+                note.cents_off = 0.0;
+                note
+            })
+            .collect();
+        Self::from_notes(name, strings)
+    }
+
+    fn from_notes(name: &str, strings: Vec<Note>) -> Self {
+        Self {
+            name: name.to_string(),
+            open_strings: strings.clone(),
+            strings,
+            transpose: 0,
+            offset_cents: 0.0,
+        }
+    }
+
+    /// The same instrument with every string shifted `semitones` from its
+    /// open note, for a capo or a retuned instrument, and then `offset_cents`
+    /// further.
+    pub fn transposed(&self, semitones: i32, offset_cents: f32) -> Self {
+        let shift = 2.0f32.powf((semitones as f32 * 100.0 + offset_cents) / 1200.0);
+        let strings = self
+            .open_strings
+            .iter()
+            .map(|open| {
+                let mut note = midi_to_note(open.midi + semitones, 0.0, DEFAULT_A4_FREQUENCY);
+                note.target_frequency = open.target_frequency * shift;
+                note.frequency = note.target_frequency;
+                note.cents_off = 0.0;
+                note
+            })
+            .collect();
+
+        Self {
+            name: self.name.clone(),
+            strings,
+            open_strings: self.open_strings.clone(),
+            transpose: semitones,
+            offset_cents,
+        }
+    }
+
+    /// The tunings offered in the preset menu.
+    pub fn built_in() -> Vec<Self> {
+        vec![
+            Self::from_midi("Guitar", &[40, 45, 50, 55, 59, 64]),
+            Self::from_midi("Guitar (Drop D)", &[38, 45, 50, 55, 59, 64]),
+            Self::from_midi("Bass (4-string)", &[28, 33, 38, 43]),
+            Self::from_midi("Bass (5-string)", &[23, 28, 33, 38, 43]),
+            Self::from_midi("Ukulele", &[67, 60, 64, 69]),
+            Self::from_midi("Violin", &[55, 62, 69, 76]),
+            Self::from_midi("Viola", &[48, 55, 62, 69]),
+            Self::from_midi("Cello", &[36, 43, 50, 57]),
+        ]
+    }
+
+    /// Fundamentals worth searching for on this instrument, in Hz: from a
+    /// fourth below the lowest string, which leaves room for dropped tunings,
+    /// up to the sixth harmonic of the highest, which covers the top of the
+    /// fingerboard.
+    pub fn frequency_range(&self) -> (f32, f32) {
+        if self.strings.is_empty() {
+            return CHROMATIC_RANGE;
+        }
+        let targets = self.strings.iter().map(|string| string.target_frequency);
+        let lowest = targets.clone().fold(f32::INFINITY, f32::min);
+        let highest = targets.fold(0.0, f32::max);
+        (
+            (lowest * 2f32.powf(-5.0 / 12.0)).max(MIN_DETECTABLE_FREQUENCY),
+            (highest * 6.0).min(MAX_DETECTABLE_FREQUENCY),
+        )
+    }
+
+    /// Octave of the lowest string, where a keyboard of the instrument's
+    /// notes starts.
+    pub fn lowest_octave(&self) -> Option<i32> {
+        self.strings.iter().map(|string| string.octave).min()
+    }
+
+    /// The open note of `string_number`, counted from 1.
+    pub fn string(&self, string_number: usize) -> Option<&Note> {
+        let index = self.strings.len().checked_sub(string_number)?;
+        self.strings.get(index)
+    }
+
+    /// The target of `string_number`, followed by its open note and the
+    /// transposition when there is one, e.g. "F#2 (E2 +2)".
+    pub fn string_label(&self, string_number: usize, naming: NoteNaming) -> Option<String> {
+        let note = self.string(string_number)?;
+        if self.transpose == 0 {
+            return Some(note.display_name(naming));
+        }
+        let open = &self.open_strings[self.strings.len() - string_number];
+        Some(format!(
+            "{} ({} {:+})",
+            note.display_name(naming),
+            open.display_name(naming),
+            self.transpose
+        ))
+    }
+
+    /// Finds the open string closest to `frequency` and returns it with the
+    /// deviation measured in cents from that string's exact target.
+    pub fn closest_string(&self, frequency: f32, a4_reference: f32) -> Option<StringMatch> {
+        let scale = a4_reference / DEFAULT_A4_FREQUENCY;
+
+        self.strings
+            .iter()
+            .enumerate()
+            .map(|(index, string)| {
+                let cents = 1200.0 * (frequency / (string.target_frequency * scale)).log2();
+                (index, string, cents)
+            })
+            .min_by(|a, b| a.2.abs().total_cmp(&b.2.abs()))
+            .map(|(index, string, cents_off)| StringMatch {
+                string_number: self.strings.len() - index,
+                note: Note {
+                    pitch_class: string.pitch_class,
+                    octave: string.octave,
+                    midi: string.midi,
+                    target_frequency: string.target_frequency * scale,
+                    frequency,
+                    cents_off,
+                    microtone_cents: 0.0,
+                },
+            })
+    }
+}
+
+impl UserPreset {
+    /// The preset this entry describes, or why it can't be tuned to: an
+    /// unknown note name, or a string outside the range the detector hears.
+    pub fn to_preset(&self) -> Result<InstrumentPreset> {
+        if self.name.trim().is_empty() {
+            bail!("preset has no name");
+        }
+        if self.strings.is_empty() {
+            bail!("preset '{}' has no strings", self.name);
+        }
+
+        let strings = self
+            .strings
+            .iter()
+            .enumerate()
+            .map(|(index, string)| {
+                string
+                    .to_note()
+                    .with_context(|| format!("preset '{}', string {}", self.name, index + 1))
+            })
+            .collect::<Result<Vec<Note>>>()?;
+
+        Ok(InstrumentPreset::from_notes(&self.name, strings))
+    }
+}
+
+impl StringTuning {
+    /// The open note of the string, with its exact frequency as the target.
+    pub fn to_note(&self) -> Result<Note> {
+        let note = match self {
+            StringTuning::Note(name) => {
+                Note::parse(name).ok_or_else(|| anyhow!("unknown note '{}'", name))?
+            }
+            StringTuning::Frequency(frequency) => {
+                let mut note = frequency_to_note(*frequency, DEFAULT_A4_FREQUENCY)
+                    .ok_or_else(|| anyhow!("invalid frequency {} Hz", frequency))?;
+                note.target_frequency = *frequency;
+                note.frequency = *frequency;
+                note.cents_off = 0.0;
+                note
+            }
+        };
+        if !(MIN_DETECTABLE_FREQUENCY..=MAX_DETECTABLE_FREQUENCY).contains(&note.target_frequency) {
+            bail!(
+                "{:.1} Hz is outside the detectable {:.0}-{:.0} Hz",
+                note.target_frequency,
+                MIN_DETECTABLE_FREQUENCY,
+                MAX_DETECTABLE_FREQUENCY
+            );
+        }
+        Ok(note)
+    }
+}
+
+/// Where the user's presets file lives on this platform.
+pub fn user_presets_path() -> Option<PathBuf> {
+    ProjectDirs::from("com", "cinbarker", "chroma-tuner")
+        .map(|dirs| dirs.config_dir().join(USER_PRESETS_FILE_NAME))
+}
+
+/// Parses a presets file into the entries that could be read, and a message
+/// for each that couldn't. Only a file that isn't TOML at all is an error.
+pub fn parse_user_presets(contents: &str) -> Result<(Vec<UserPreset>, Vec<String>)> {
+    let file: UserPresetsFile = toml::from_str(contents)?;
+    let mut presets = Vec::new();
+    let mut errors = Vec::new();
+    for (index, value) in file.preset.into_iter().enumerate() {
+        let label = match value.get("name").and_then(|name| name.as_str()) {
+            Some(name) => format!("preset '{}'", name),
+            None => format!("preset {}", index + 1),
+        };
+        match value.try_into::<UserPreset>() {
+            Ok(preset) => presets.push(preset),
+            Err(err) => errors.push(format!("{}: {}", label, err.message())),
+        }
+    }
+    Ok((presets, errors))
+}
+
+/// Reads the presets file at `path`; a missing file holds no presets.
+pub fn load_user_presets(path: &Path) -> Result<(Vec<UserPreset>, Vec<String>)> {
+    if !path.exists() {
+        return Ok((Vec::new(), Vec::new()));
+    }
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    parse_user_presets(&contents)
+        .with_context(|| format!("Invalid presets file {}", path.display()))
+}
+
+/// The presets file holding `presets`.
+pub fn user_presets_to_toml(presets: &[UserPreset]) -> Result<String> {
+    Ok(toml::to_string_pretty(&UserPresetsFileRef {
+        preset: presets,
+    })?)
+}
+
+/// Writes `presets` to `path`, creating its directory if needed.
+pub fn save_user_presets(path: &Path, presets: &[UserPreset]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, user_presets_to_toml(presets)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preset(name: &str) -> InstrumentPreset {
+        InstrumentPreset::built_in()
+            .into_iter()
+            .find(|preset| preset.name == name)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_closest_guitar_string() {
+        let guitar = preset("Guitar");
+
+        let matched = guitar.closest_string(140.0, DEFAULT_A4_FREQUENCY).unwrap();
+        assert_eq!(matched.note.to_string(), "D3");
+        assert_eq!(matched.string_number, 4);
+        assert!((matched.note.cents_off - -82.5).abs() < 0.5);
+
+        let matched = guitar.closest_string(82.41, DEFAULT_A4_FREQUENCY).unwrap();
+        assert_eq!(matched.note.to_string(), "E2");
+        assert_eq!(matched.string_number, 6);
+        assert!(matched.note.cents_off.abs() < 0.5);
+    }
+
+    #[test]
+    fn test_transposed_targets_wrap_octaves() {
+        let guitar = preset("Guitar");
+
+        let capo = guitar.transposed(2, 0.0);
+        let names: Vec<String> = capo.strings.iter().map(|note| note.to_string()).collect();
+        assert_eq!(names, ["F#2", "B2", "E3", "A3", "C#4", "F#4"]);
+        assert!(
+            (capo.strings[0].target_frequency / guitar.strings[0].target_frequency
+                - 2.0f32.powf(2.0 / 12.0))
+            .abs()
+                < 1e-4
+        );
+
+        let lowered = guitar.transposed(-5, 0.0);
+        assert_eq!(lowered.strings[0].to_string(), "B1");
+        assert_eq!(lowered.strings[3].to_string(), "D3");
+        assert_eq!(lowered.strings[4].to_string(), "F#3");
+
+        // Transposing again starts from the open strings, not the last shift.
+        let back = capo.transposed(-1, 0.0);
+        assert_eq!(back.strings[0].to_string(), "D#2");
+        assert_eq!(back.transposed(0, 0.0).strings[0].to_string(), "E2");
+    }
+
+    #[test]
+    fn test_transposed_preset_matches_shifted_strings() {
+        let guitar = preset("Guitar").transposed(-1, -10.0);
+
+        // Half a step down and ten cents flat of it.
+        let low_e_flat = 82.41 * 2.0f32.powf(-110.0 / 1200.0);
+        let matched = guitar
+            .closest_string(low_e_flat, DEFAULT_A4_FREQUENCY)
+            .unwrap();
+        assert_eq!(matched.string_number, 6);
+        assert_eq!(matched.note.to_string(), "D#2");
+        assert!(matched.note.cents_off.abs() < 0.5);
+
+        let matched = guitar.closest_string(77.78, DEFAULT_A4_FREQUENCY).unwrap();
+        assert!((matched.note.cents_off - 10.0).abs() < 0.5);
+    }
+
+    #[test]
+    fn test_frequency_range_follows_the_strings() {
+        // A 5-string bass's low B fits, with rumble-free room below it.
+        let (low, high) = preset("Bass (5-string)").frequency_range();
+        assert_eq!(low, MIN_DETECTABLE_FREQUENCY);
+        assert!((500.0..600.0).contains(&high), "{}", high);
+        let (low, high) = preset("Bass (4-string)").frequency_range();
+        assert!(low < 30.87 && low > MIN_DETECTABLE_FREQUENCY, "{}", low);
+        assert!(high < 600.0);
+
+        let (low, high) = preset("Violin").frequency_range();
+        assert!((140.0..196.0).contains(&low), "{}", low);
+        assert!(
+            (3900.0..=MAX_DETECTABLE_FREQUENCY).contains(&high),
+            "{}",
+            high
+        );
+
+        // A capo takes the range up with the strings.
+        let guitar = preset("Guitar");
+        let (low, _) = guitar.frequency_range();
+        let (capo_low, _) = guitar.transposed(5, 0.0).frequency_range();
+        assert!((1200.0 * (capo_low / low).log2() - 500.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_string_label_shows_the_open_note() {
+        let guitar = preset("Guitar");
+        assert_eq!(guitar.string_label(6, NoteNaming::Sharps).unwrap(), "E2");
+        let capo = guitar.transposed(2, 0.0);
+        assert_eq!(
+            capo.string_label(6, NoteNaming::Sharps).unwrap(),
+            "F#2 (E2 +2)"
+        );
+        assert_eq!(
+            guitar
+                .transposed(-1, 0.0)
+                .string_label(1, NoteNaming::Flats)
+                .unwrap(),
+            "E♭4 (E4 -1)"
+        );
+        assert!(capo.string_label(7, NoteNaming::Sharps).is_none());
+    }
+
+    #[test]
+    fn test_parse_user_presets() {
+        let (presets, errors) = parse_user_presets(
+            r#"
+            [[preset]]
+            name = "DADGAD"
+            strings = ["D2", "A2", "D3", "G3", "A3", "D4"]
+
+            [[preset]]
+            name = "Open G"
+            strings = ["D2", "G2", "D3", "G3", "B3", "D4"]
+
+            [[preset]]
+            name = "Baritone-ish"
+            strings = [61.74, "E2"]
+            "#,
+        )
+        .unwrap();
+        assert!(errors.is_empty());
+        assert_eq!(presets.len(), 3);
+
+        let dadgad = presets[0].to_preset().unwrap();
+        assert_eq!(dadgad.name, "DADGAD");
+        assert_eq!(dadgad.string(6).unwrap().to_string(), "D2");
+        assert_eq!(dadgad.string(1).unwrap().to_string(), "D4");
+
+        let custom = presets[2].to_preset().unwrap();
+        let low = custom.string(2).unwrap();
+        assert_eq!(low.to_string(), "B1");
+        assert_eq!(low.target_frequency, 61.74);
+        let matched = custom.closest_string(61.74, DEFAULT_A4_FREQUENCY).unwrap();
+        assert_eq!(matched.string_number, 2);
+        assert!(matched.note.cents_off.abs() < 0.01);
+    }
+
+    #[test]
+    fn test_bad_user_presets_are_reported_one_by_one() {
+        let (presets, errors) = parse_user_presets(
+            r#"
+            [[preset]]
+            name = "Good"
+            strings = ["E2", "A2"]
+
+            [[preset]]
+            name = "No strings"
+
+            [[preset]]
+            strings = ["E2"]
+
+            [[preset]]
+            name = "Typo"
+            strings = ["E2", "H2"]
+
+            [[preset]]
+            name = "Too low"
+            strings = [12.0]
+
+            [[preset]]
+            name = "Too high"
+            strings = ["D8"]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(errors.len(), 2);
+        assert!(
+            errors[0].starts_with("preset 'No strings': "),
+            "{}",
+            errors[0]
+        );
+        assert!(errors[1].starts_with("preset 3: "), "{}", errors[1]);
+
+        let names: Vec<&str> = presets.iter().map(|preset| preset.name.as_str()).collect();
+        assert_eq!(names, ["Good", "Typo", "Too low", "Too high"]);
+        assert!(presets[0].to_preset().is_ok());
+        let message = |preset: &UserPreset| format!("{:#}", preset.to_preset().unwrap_err());
+        assert_eq!(
+            message(&presets[1]),
+            "preset 'Typo', string 2: unknown note 'H2'"
+        );
+        assert!(message(&presets[2]).contains("outside the detectable"));
+        assert!(message(&presets[3]).starts_with("preset 'Too high', string 1: 4698.6 Hz"));
+
+        assert!(parse_user_presets("[[preset]\nname =").is_err());
+        assert!(parse_user_presets("").unwrap().0.is_empty());
+    }
+
+    #[test]
+    fn test_user_presets_round_trip() {
+        let presets = vec![
+            UserPreset {
+                name: "7-string".to_string(),
+                strings: ["B1", "E2", "A2", "D3", "G3", "B3", "E4"]
+                    .map(|name| StringTuning::Note(name.to_string()))
+                    .to_vec(),
+            },
+            UserPreset {
+                name: "Mixed".to_string(),
+                strings: vec![
+                    StringTuning::Frequency(73.5),
+                    StringTuning::Note("A♭2".to_string()),
+                ],
+            },
+        ];
+        let contents = user_presets_to_toml(&presets).unwrap();
+        assert_eq!(
+            parse_user_presets(&contents).unwrap(),
+            (presets.clone(), vec![])
+        );
+
+        let path = std::env::temp_dir()
+            .join(format!("chroma-tuner-presets-{}", std::process::id()))
+            .join("presets.toml");
+        save_user_presets(&path, &presets).unwrap();
+        assert_eq!(load_user_presets(&path).unwrap().0, presets);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        assert!(load_user_presets(&path).unwrap().0.is_empty());
+    }
+
+    #[test]
+    fn test_closest_string_respects_reference_and_reentrant_order() {
+        let ukulele = preset("Ukulele");
+
+        let matched = ukulele.closest_string(392.0, DEFAULT_A4_FREQUENCY).unwrap();
+        assert_eq!(matched.note.to_string(), "G4");
+        assert_eq!(matched.string_number, 4);
+
+        let matched = ukulele.closest_string(442.0, 442.0).unwrap();
+        assert_eq!(matched.note.to_string(), "A4");
+        assert_eq!(matched.string_number, 1);
+        assert!(matched.note.cents_off.abs() < 0.01);
+    }
+}
@@ -0,0 +1,265 @@
+//! Scala (.scl) scale files
+//!
+//! Parses the Scala scale format used by microtonal tuning software and maps
+//! detected frequencies to the nearest degree of the loaded scale.
+
+use anyhow::{anyhow, bail, Context, Result};
+use std::path::Path;
+
+/// A scale loaded from a Scala file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScalaScale {
+    /// The description line of the file.
+    pub description: String,
+    /// Degrees 1..=N in cents above the reference; the last one is the period
+    /// (usually the octave). Degree 0 is the implicit 1/1.
+    pub degrees: Vec<f32>,
+}
+
+/// The scale degree a frequency was matched to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScaleMatch {
+    /// Degree within the period, 0 being the reference pitch.
+    pub degree: usize,
+    /// How many periods above (or below) the reference the match lies.
+    pub period: i32,
+    /// Frequency of the matched degree in Hz.
+    pub target_frequency: f32,
+    /// Deviation from `target_frequency` in cents.
+    pub cents_off: f32,
+}
+
+impl ScalaScale {
+    /// Reads and parses a `.scl` file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::parse(&contents).with_context(|| format!("Invalid scale file {}", path.display()))
+    }
+
+    /// Parses the contents of a `.scl` file.
+    pub fn parse(contents: &str) -> Result<Self> {
+        let mut lines = contents
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.trim()))
+            .filter(|(_, line)| !line.starts_with('!'));
+
+        let (_, description) = lines.next().ok_or_else(|| anyhow!("missing description"))?;
+
+        let (line_number, count) = lines.next().ok_or_else(|| anyhow!("missing note count"))?;
+        let count: usize = count
+            .split_whitespace()
+            .next()
+            .and_then(|count| count.parse().ok())
+            .ok_or_else(|| anyhow!("line {}: invalid note count '{}'", line_number, count))?;
+
+        let mut degrees = Vec::with_capacity(count);
+        for (line_number, line) in lines {
+            if degrees.len() == count {
+                break;
+            }
+            let Some(value) = line.split_whitespace().next() else {
+                continue;
+            };
+            let cents = parse_pitch(value)
+                .ok_or_else(|| anyhow!("line {}: invalid pitch value '{}'", line_number, value))?;
+            degrees.push(cents);
+        }
+
+        if degrees.len() != count {
+            bail!("expected {} pitches but found {}", count, degrees.len());
+        }
+
+        match degrees.last() {
+            Some(&period) if period > 0.0 => {}
+            Some(_) => bail!("the last pitch must be above 1/1 to define the period"),
+            None => bail!("scale has no pitches"),
+        }
+
+        Ok(Self {
+            description: description.to_string(),
+            degrees,
+        })
+    }
+
+    /// Size of the repeating interval in cents, usually 1200.
+    pub fn period(&self) -> f32 {
+        self.degrees.last().copied().unwrap_or(1200.0)
+    }
+
+    /// Number of degrees per period.
+    pub fn degree_count(&self) -> usize {
+        self.degrees.len()
+    }
+
+    fn degree_cents(&self, degree: usize) -> f32 {
+        match degree {
+            0 => 0.0,
+            degree => self.degrees[degree - 1],
+        }
+    }
+
+    /// Finds the scale degree closest to `frequency`, with degree 0 sounding at
+    /// `reference`.
+    pub fn nearest_degree(&self, frequency: f32, reference: f32) -> ScaleMatch {
+        let cents = 1200.0 * (frequency / reference).log2();
+        let period = self.period();
+        let base_period = (cents / period).floor() as i32;
+
+        let mut best = (0, base_period, f32::INFINITY);
+        for candidate_period in base_period - 1..=base_period + 1 {
+            for degree in 0..self.degree_count() {
+                let target = candidate_period as f32 * period + self.degree_cents(degree);
+                let deviation = cents - target;
+                if deviation.abs() < best.2.abs() {
+                    best = (degree, candidate_period, deviation);
+                }
+            }
+        }
+
+        let (degree, period_index, cents_off) = best;
+        let target_cents = period_index as f32 * period + self.degree_cents(degree);
+
+        ScaleMatch {
+            degree,
+            period: period_index,
+            target_frequency: reference * 2.0f32.powf(target_cents / 1200.0),
+            cents_off,
+        }
+    }
+}
+
+/// Scala pitches are cents when they contain a period and ratios otherwise,
+/// where a bare integer `n` means `n/1`.
+fn parse_pitch(value: &str) -> Option<f32> {
+    if value.contains('.') {
+        return value.parse::<f32>().ok().filter(|cents| cents.is_finite());
+    }
+
+    let (numerator, denominator) = value.split_once('/').unwrap_or((value, "1"));
+    let numerator: u64 = numerator.parse().ok()?;
+    let denominator: u64 = denominator.parse().ok()?;
+    if numerator == 0 || denominator == 0 {
+        return None;
+    }
+
+    Some((1200.0 * (numerator as f64 / denominator as f64).log2()) as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MEANTONE: &str = "! meanquar.scl
+!
+1/4-comma meantone scale. Pietro Aaron's temp. (1523). 6/5 beats twice 3/2
+ 12
+!
+ 76.04900
+ 193.15686
+ 310.26471
+ 5/4
+ 503.42157
+ 579.47057
+ 696.57843
+ 25/16
+ 889.73529
+ 1006.84314
+ 1082.89214
+ 2/1
+";
+
+    const PYTHAGOREAN_DIATONIC: &str = "! pyth_7.scl
+!
+Pythagorean diatonic scale
+ 7
+!
+ 9/8
+ 81/64
+ 4/3
+ 3/2
+ 27/16
+ 243/128
+ 2
+";
+
+    const NEGATIVE_CENTS: &str = "! flat_leading.scl
+!
+Tritave scale with a degree written below the tonic
+3
+!
+-31.5   a comment after the value
+ 701.955
+ 3/1
+";
+
+    #[test]
+    fn test_parse_cents_and_ratios() {
+        let scale = ScalaScale::parse(MEANTONE).unwrap();
+        assert_eq!(
+            scale.description,
+            "1/4-comma meantone scale. Pietro Aaron's temp. (1523). 6/5 beats twice 3/2"
+        );
+        assert_eq!(scale.degree_count(), 12);
+        assert!((scale.degrees[0] - 76.049).abs() < 1e-3);
+        assert!((scale.degrees[3] - 386.3137).abs() < 1e-3);
+        assert!((scale.period() - 1200.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_parse_ratio_only_file() {
+        let scale = ScalaScale::parse(PYTHAGOREAN_DIATONIC).unwrap();
+        assert_eq!(scale.degree_count(), 7);
+        assert!((scale.degrees[0] - 203.91).abs() < 0.01);
+        assert!((scale.degrees[1] - 407.82).abs() < 0.01);
+        assert!((scale.period() - 1200.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_parse_negative_cents_and_non_octave_period() {
+        let scale = ScalaScale::parse(NEGATIVE_CENTS).unwrap();
+        assert_eq!(scale.degrees[0], -31.5);
+        assert!((scale.period() - 1901.955).abs() < 0.01);
+
+        let matched = scale.nearest_degree(261.63 * 2.0f32.powf(-30.0 / 1200.0), 261.63);
+        assert_eq!((matched.degree, matched.period), (1, 0));
+        assert!((matched.cents_off - 1.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_malformed_files_are_errors() {
+        assert!(ScalaScale::parse("").is_err());
+        assert!(ScalaScale::parse("desc\nmany\n").is_err());
+
+        let error = ScalaScale::parse("desc\n2\n100.0\nfoo\n").unwrap_err();
+        assert_eq!(error.to_string(), "line 4: invalid pitch value 'foo'");
+
+        let error = ScalaScale::parse("desc\n3\n100.0\n2/1\n").unwrap_err();
+        assert_eq!(error.to_string(), "expected 3 pitches but found 2");
+
+        assert!(ScalaScale::parse("desc\n1\n3/0\n").is_err());
+        assert!(ScalaScale::parse("desc\n1\n-1200.0\n").is_err());
+    }
+
+    #[test]
+    fn test_nearest_degree_wraps_periods() {
+        let scale = ScalaScale::parse(PYTHAGOREAN_DIATONIC).unwrap();
+        let reference = 261.63;
+
+        let fifth = scale.nearest_degree(reference * 1.5, reference);
+        assert_eq!((fifth.degree, fifth.period), (4, 0));
+        assert!(fifth.cents_off.abs() < 0.01);
+
+        let high_third = scale.nearest_degree(reference * 81.0 / 64.0 * 2.0 * 1.003, reference);
+        assert_eq!((high_third.degree, high_third.period), (2, 1));
+        assert!((high_third.cents_off - 5.19).abs() < 0.05);
+
+        let below = scale.nearest_degree(reference * 243.0 / 256.0, reference);
+        assert_eq!((below.degree, below.period), (6, -1));
+        assert!((below.target_frequency - reference * 243.0 / 256.0).abs() < 0.01);
+
+        let octave = scale.nearest_degree(reference * 1.99, reference);
+        assert_eq!((octave.degree, octave.period), (0, 1));
+    }
+}
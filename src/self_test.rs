@@ -0,0 +1,262 @@
+//! Accuracy self-test over the whole analysis chain
+//!
+//! Plays synthetic tones at known offsets from every note between E1 and E7
+//! into an `AudioData` at a typical device rate, so they go through the
+//! resampler, the ring buffer, the detector and the tracker exactly as live
+//! input does, and reports how far each reading lands from the truth.
+
+use std::time::{Duration, Instant};
+
+use crate::audio::AudioData;
+use crate::pitch::{
+    midi_to_frequency, Note, OnsetDetector, PitchDetector, Temperament, DEFAULT_A4_FREQUENCY,
+};
+use crate::tracker::{AnalysisSettings, PitchTracker};
+
+/// Lowest note tested, E1.
+pub const SELF_TEST_LOW_MIDI: i32 = 28;
+/// Highest note tested, E7.
+pub const SELF_TEST_HIGH_MIDI: i32 = 100;
+/// Cents each note is detuned by in turn.
+pub const SELF_TEST_OFFSETS: [f32; 5] = [-30.0, -10.0, 0.0, 10.0, 30.0];
+/// Readings further than this from the tone played are flagged.
+pub const SELF_TEST_TOLERANCE_CENTS: f32 = 2.0;
+/// Sample rate the tones are fed in at, so they pass through the resampler.
+const INPUT_RATE: u32 = 44100;
+/// Frames per simulated capture callback.
+const CALLBACK_FRAMES: usize = 441;
+/// How long each tone plays before its reading is taken.
+const TONE_DURATION: Duration = Duration::from_millis(600);
+
+/// How one tone read.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ToneResult {
+    /// MIDI note the tone is detuned from.
+    pub midi: i32,
+    /// Cents the tone is detuned by.
+    pub offset_cents: f32,
+    /// The note shown at the end of the tone, with the smoothed deviation as
+    /// `cents_off`, if any.
+    pub reading: Option<Note>,
+}
+
+impl ToneResult {
+    /// Cents the reading is off from the tone played, or `None` if nothing
+    /// or the wrong note was read.
+    pub fn error_cents(&self) -> Option<f32> {
+        let note = self
+            .reading
+            .as_ref()
+            .filter(|note| note.midi == self.midi)?;
+        Some(note.cents_off - self.offset_cents)
+    }
+
+    /// Whether the tone was missed, read as the wrong note or read further
+    /// off than [`SELF_TEST_TOLERANCE_CENTS`].
+    pub fn flagged(&self) -> bool {
+        self.error_cents()
+            .is_none_or(|error| error.abs() > SELF_TEST_TOLERANCE_CENTS)
+    }
+}
+
+/// The results for one octave, C to B.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OctaveSummary {
+    /// Scientific pitch octave.
+    pub octave: i32,
+    /// Largest error of the tones read as the right note, in cents.
+    pub worst_cents: Option<f32>,
+    /// Tones missed or read as the wrong note.
+    pub failures: usize,
+}
+
+/// Every tone of a self-test run.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SelfTestReport {
+    /// One result per note and offset, lowest note first.
+    pub tones: Vec<ToneResult>,
+}
+
+impl SelfTestReport {
+    /// Worst error and failures of each octave, lowest first.
+    pub fn octaves(&self) -> Vec<OctaveSummary> {
+        let mut octaves: Vec<OctaveSummary> = Vec::new();
+        for tone in &self.tones {
+            let octave = tone.midi.div_euclid(12) - 1;
+            if octaves.last().is_none_or(|last| last.octave != octave) {
+                octaves.push(OctaveSummary {
+                    octave,
+                    worst_cents: None,
+                    failures: 0,
+                });
+            }
+            let summary = octaves.last_mut().expect("pushed above");
+            match tone.error_cents() {
+                Some(error) => {
+                    let worst = summary.worst_cents.unwrap_or(0.0);
+                    summary.worst_cents = Some(worst.max(error.abs()));
+                }
+                None => summary.failures += 1,
+            }
+        }
+        octaves
+    }
+
+    /// The tones that failed or missed the tolerance.
+    pub fn flagged(&self) -> impl Iterator<Item = &ToneResult> {
+        self.tones.iter().filter(|tone| tone.flagged())
+    }
+}
+
+impl AnalysisSettings {
+    /// These detection settings measuring against plain equal-tempered notes
+    /// at A4 = 440 Hz over the full range, so every self-test tone has one
+    /// right answer.
+    pub fn for_self_test(&self) -> AnalysisSettings {
+        let defaults = AnalysisSettings::default();
+        AnalysisSettings {
+            a4_reference: DEFAULT_A4_FREQUENCY,
+            target_note: None,
+            preset: None,
+            capture_spectrum: false,
+            capture_strum: false,
+            capture_peaks: false,
+            frequency_range: defaults.frequency_range,
+            frequency_prior: None,
+            temperament: Temperament::Equal,
+            edo: 12,
+            key: None,
+            piano_stretch: None,
+            scale: None,
+            ..self.clone()
+        }
+    }
+}
+
+/// Plays every note from [`SELF_TEST_LOW_MIDI`] to [`SELF_TEST_HIGH_MIDI`] at
+/// each of the [`SELF_TEST_OFFSETS`] through the analysis chain with
+/// `settings`, calling `progress` with the fraction done after each tone.
+pub fn run_self_test(settings: &AnalysisSettings, mut progress: impl FnMut(f32)) -> SelfTestReport {
+    let settings = settings.for_self_test();
+    let total = (SELF_TEST_HIGH_MIDI - SELF_TEST_LOW_MIDI + 1) as usize * SELF_TEST_OFFSETS.len();
+    let mut tones = Vec::with_capacity(total);
+    for midi in SELF_TEST_LOW_MIDI..=SELF_TEST_HIGH_MIDI {
+        for offset_cents in SELF_TEST_OFFSETS {
+            let frequency =
+                midi_to_frequency(midi, DEFAULT_A4_FREQUENCY) * 2.0f32.powf(offset_cents / 1200.0);
+            tones.push(ToneResult {
+                midi,
+                offset_cents,
+                reading: read_tone(frequency, &settings),
+            });
+            progress(tones.len() as f32 / total as f32);
+        }
+    }
+    SelfTestReport { tones }
+}
+
+/// Feeds a sine at `frequency` through a fresh `AudioData`, detector and
+/// tracker in capture-sized blocks, on a simulated clock, and returns the
+/// note shown at the end.
+fn read_tone(frequency: f32, settings: &AnalysisSettings) -> Option<Note> {
+    let mut audio_data = AudioData::new();
+    audio_data.set_buffer_size(settings.fft_size);
+    audio_data.configure_input(INPUT_RATE as f32, 1, None);
+    let sample_rate = audio_data.sample_rate;
+    let mut detector = PitchDetector::new(settings.fft_size, sample_rate, settings.window_kind);
+    settings.configure(&mut detector);
+    let mut onset_detector = OnsetDetector::new(sample_rate);
+    let mut tracker = PitchTracker::default();
+    let mut window = vec![0.0; settings.fft_size];
+    let mut last_read = 0;
+    let start = Instant::now();
+
+    let len = (TONE_DURATION.as_secs_f32() * INPUT_RATE as f32) as usize;
+    let step = frequency as f64 / INPUT_RATE as f64;
+    let tone: Vec<f32> = (0..len)
+        .map(|index| ((std::f64::consts::TAU * (step * index as f64).fract()).sin() * 0.5) as f32)
+        .collect();
+    for block in tone.chunks(CALLBACK_FRAMES) {
+        audio_data.push_input(block);
+        if !audio_data.has_new_data(last_read, settings.hop_size) {
+            continue;
+        }
+        last_read = audio_data.total_samples_written();
+        let count = audio_data.get_samples(&mut window);
+        let samples = &window[..count];
+        let now = start + Duration::from_secs_f64(last_read as f64 / sample_rate as f64);
+
+        let note = tracker.reading().note;
+        detector.set_low_range(
+            settings
+                .low_range
+                .active(note.map(|note| note.target_frequency)),
+        );
+        if onset_detector.detect(samples) {
+            tracker.onset(now, settings);
+        }
+        match detector.detect_pitch(samples) {
+            Some(estimate) => {
+                tracker.feed(estimate, now, settings);
+            }
+            None => tracker.timeout(now, settings),
+        }
+    }
+
+    let reading = tracker.reading();
+    reading.note.map(|note| Note {
+        cents_off: reading.smoothed_cents,
+        ..note
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tone(midi: i32, offset_cents: f32, read: Option<(i32, f32)>) -> ToneResult {
+        ToneResult {
+            midi,
+            offset_cents,
+            reading: read.map(|(midi, cents_off)| Note {
+                cents_off,
+                ..crate::pitch::midi_to_note(midi, 440.0, 440.0)
+            }),
+        }
+    }
+
+    #[test]
+    fn test_report_summarises_each_octave() {
+        let report = SelfTestReport {
+            tones: vec![
+                tone(28, 10.0, Some((28, 10.5))),
+                tone(29, -10.0, Some((29, -12.5))),
+                tone(47, 0.0, None),
+                tone(48, 30.0, Some((49, -70.0))),
+                tone(48, 0.0, Some((48, -0.25))),
+            ],
+        };
+        assert_eq!(
+            report.octaves(),
+            [
+                OctaveSummary {
+                    octave: 1,
+                    worst_cents: Some(2.5),
+                    failures: 0,
+                },
+                OctaveSummary {
+                    octave: 2,
+                    worst_cents: None,
+                    failures: 1,
+                },
+                OctaveSummary {
+                    octave: 3,
+                    worst_cents: Some(0.25),
+                    failures: 1,
+                },
+            ]
+        );
+        let flagged: Vec<i32> = report.flagged().map(|tone| tone.midi).collect();
+        assert_eq!(flagged, [29, 47, 48]);
+    }
+}
@@ -0,0 +1,323 @@
+//! Session statistics for practice
+//!
+//! Accumulates, over a practice session, how long a stable note was shown, how
+//! much of that time it was in tune, the average deviation overall and for
+//! each note, and how steadily notes were held. Silence doesn't count towards
+//! any of the totals.
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use crate::pitch::{Note, NoteNaming};
+use crate::tracker::PitchReading;
+
+/// Frames further apart than this don't add time, e.g. after the app stalled.
+const MAX_FRAME_GAP: Duration = Duration::from_millis(250);
+
+/// A note regardless of how far off it was played, ordered from low to high.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NoteKey {
+    /// Scientific pitch octave.
+    pub octave: i32,
+    /// Semitone within the octave, 0 = C.
+    pub pitch_class: u8,
+}
+
+impl NoteKey {
+    /// The key of MIDI note `midi`.
+    pub fn from_midi(midi: i32) -> Self {
+        Self {
+            octave: midi.div_euclid(12) - 1,
+            pitch_class: midi.rem_euclid(12) as u8,
+        }
+    }
+
+    /// Note name with octave, e.g. "D4".
+    pub fn display_name(self, naming: NoteNaming) -> String {
+        format!(
+            "{}{}",
+            naming.pitch_class_name(self.pitch_class),
+            self.octave
+        )
+    }
+}
+
+impl From<&Note> for NoteKey {
+    fn from(note: &Note) -> Self {
+        Self {
+            octave: note.octave,
+            pitch_class: note.pitch_class,
+        }
+    }
+}
+
+/// Accuracy of one note over the session.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct NoteStats {
+    /// Number of distinct readings of the note.
+    pub count: u64,
+    /// Running mean of the signed deviation in cents; negative is flat.
+    pub mean_cents: f32,
+    /// How long the note was shown.
+    pub time: Duration,
+}
+
+/// Running totals for a practice session, fed with every reading the tuner
+/// shows.
+#[derive(Debug, Default)]
+pub struct SessionStats {
+    last_frame: Option<Instant>,
+    last_reading: Option<(f32, f32)>,
+    tracked_time: Duration,
+    in_tune_time: Duration,
+    count: u64,
+    mean_abs_cents: f32,
+    notes: BTreeMap<NoteKey, NoteStats>,
+    deviation_count: u64,
+    mean_deviation: f32,
+    best_deviation: Option<f32>,
+}
+
+impl SessionStats {
+    /// Adds the reading shown at `now`. The time since the previous frame is
+    /// credited to the note on display, and a reading the tracker hasn't
+    /// changed since the last frame isn't counted twice.
+    pub fn record(&mut self, reading: &PitchReading, now: Instant, in_tune_cents: f32) {
+        let Some(note) = &reading.note else {
+            self.last_frame = None;
+            self.last_reading = None;
+            return;
+        };
+        let cents = reading.smoothed_cents;
+        let key = NoteKey::from(note);
+
+        let elapsed = self
+            .last_frame
+            .map(|last| now.saturating_duration_since(last))
+            .filter(|&elapsed| elapsed <= MAX_FRAME_GAP)
+            .unwrap_or_default();
+        self.last_frame = Some(now);
+        self.tracked_time += elapsed;
+        if cents.abs() < in_tune_cents {
+            self.in_tune_time += elapsed;
+        }
+        let note_stats = self.notes.entry(key).or_default();
+        note_stats.time += elapsed;
+
+        if self.last_reading == Some((note.frequency, cents)) {
+            return;
+        }
+        self.last_reading = Some((note.frequency, cents));
+
+        note_stats.count += 1;
+        note_stats.mean_cents += (cents - note_stats.mean_cents) / note_stats.count as f32;
+        self.count += 1;
+        self.mean_abs_cents += (cents.abs() - self.mean_abs_cents) / self.count as f32;
+
+        if let Some(deviation) = reading.cents_deviation {
+            self.deviation_count += 1;
+            self.mean_deviation += (deviation - self.mean_deviation) / self.deviation_count as f32;
+            self.best_deviation = Some(
+                self.best_deviation
+                    .map_or(deviation, |best| best.min(deviation)),
+            );
+        }
+    }
+
+    /// Starts a new session.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Total time a note was shown.
+    pub fn tracked_time(&self) -> Duration {
+        self.tracked_time
+    }
+
+    /// Time the note shown was within the in-tune tolerance.
+    pub fn in_tune_time(&self) -> Duration {
+        self.in_tune_time
+    }
+
+    /// Share of the tracked time spent in tune, once anything was tracked.
+    pub fn in_tune_fraction(&self) -> Option<f32> {
+        (!self.tracked_time.is_zero())
+            .then(|| self.in_tune_time.as_secs_f32() / self.tracked_time.as_secs_f32())
+    }
+
+    /// Average absolute deviation in cents over every reading.
+    pub fn mean_abs_cents(&self) -> Option<f32> {
+        (self.count > 0).then_some(self.mean_abs_cents)
+    }
+
+    /// Average stability over every reading, as the standard deviation of
+    /// cents over a second.
+    pub fn mean_deviation(&self) -> Option<f32> {
+        (self.deviation_count > 0).then_some(self.mean_deviation)
+    }
+
+    /// Steadiest any note was held, as the standard deviation of cents over
+    /// a second.
+    pub fn best_deviation(&self) -> Option<f32> {
+        self.best_deviation
+    }
+
+    /// Accuracy of one note, if it was played.
+    pub fn note(&self, key: NoteKey) -> Option<&NoteStats> {
+        self.notes.get(&key)
+    }
+
+    /// Per-note accuracy, from the lowest note up.
+    pub fn notes(&self) -> impl Iterator<Item = (NoteKey, &NoteStats)> {
+        self.notes.iter().map(|(&key, stats)| (key, stats))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pitch::{frequency_to_note, midi_to_frequency, DEFAULT_A4_FREQUENCY};
+
+    const FRAME: Duration = Duration::from_millis(20);
+
+    fn reading(midi: i32, cents: f32) -> PitchReading {
+        let frequency = midi_to_frequency(midi, DEFAULT_A4_FREQUENCY) * 2.0f32.powf(cents / 1200.0);
+        PitchReading {
+            note: frequency_to_note(frequency, DEFAULT_A4_FREQUENCY),
+            smoothed_cents: cents,
+            confidence: 0.9,
+            ..PitchReading::default()
+        }
+    }
+
+    /// Feeds each reading for one frame, in order, and returns when it ended.
+    fn play(stats: &mut SessionStats, start: Instant, readings: &[PitchReading]) -> Instant {
+        let mut now = start;
+        for reading in readings {
+            stats.record(reading, now, 5.0);
+            now += FRAME;
+        }
+        now
+    }
+
+    #[test]
+    fn test_time_in_tune_ignores_silence() {
+        let mut stats = SessionStats::default();
+        assert_eq!(stats.in_tune_fraction(), None);
+        assert_eq!(stats.mean_abs_cents(), None);
+
+        let start = Instant::now();
+        // One second in tune, a long silence, then one second 12 cents sharp.
+        let mut readings: Vec<PitchReading> = (0..50)
+            .map(|frame| reading(69, if frame % 5 == 4 { 1.0 } else { -2.0 }))
+            .collect();
+        readings.extend((0..200).map(|_| PitchReading::default()));
+        readings.extend((0..50).map(|frame| reading(69, 12.0 + frame as f32 * 0.01)));
+        play(&mut stats, start, &readings);
+
+        // The first frame of each phrase adds no time.
+        assert_eq!(stats.tracked_time(), FRAME * 98);
+        assert_eq!(stats.in_tune_time(), FRAME * 49);
+        assert!((stats.in_tune_fraction().unwrap() - 0.5).abs() < 1e-6);
+
+        // Readings that repeat unchanged count once: 20 changes of the first
+        // phrase and 50 of the second.
+        let (_, a4) = stats.notes().next().unwrap();
+        assert_eq!(a4.count, 70);
+        let expected_abs =
+            (20.0 * 1.5 + (0..50).map(|i| 12.0 + i as f32 * 0.01).sum::<f32>()) / 70.0;
+        assert!((stats.mean_abs_cents().unwrap() - expected_abs).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_per_note_breakdown_finds_flat_note() {
+        let mut stats = SessionStats::default();
+        let d4 = 62;
+        let a4 = 69;
+        let readings: Vec<PitchReading> = (0..300)
+            .map(|frame| {
+                let wobble = (frame % 7) as f32 * 0.5 - 1.5;
+                if frame % 100 < 50 {
+                    reading(d4, -8.0 + wobble)
+                } else {
+                    reading(a4, 1.0 + wobble)
+                }
+            })
+            .collect();
+        play(&mut stats, Instant::now(), &readings);
+
+        let notes: Vec<(String, NoteStats)> = stats
+            .notes()
+            .map(|(key, stats)| (key.display_name(NoteNaming::Sharps), *stats))
+            .collect();
+        assert_eq!(notes.len(), 2);
+        let (d4_name, d4_stats) = &notes[0];
+        let (a4_name, a4_stats) = &notes[1];
+        assert_eq!(d4_name, "D4");
+        assert_eq!(a4_name, "A4");
+        assert_eq!(d4_stats.count, 150);
+        assert!(
+            (d4_stats.mean_cents + 8.0).abs() < 0.1,
+            "{}",
+            d4_stats.mean_cents
+        );
+        assert!(
+            (a4_stats.mean_cents - 1.0).abs() < 0.1,
+            "{}",
+            a4_stats.mean_cents
+        );
+        // A switch between notes doesn't break the timing.
+        assert_eq!(d4_stats.time + a4_stats.time, FRAME * 299);
+
+        assert_eq!(
+            NoteKey::from_midi(d4).display_name(NoteNaming::Sharps),
+            "D4"
+        );
+        assert_eq!(stats.note(NoteKey::from_midi(a4)).unwrap().count, 150);
+        assert!(stats.note(NoteKey::from_midi(a4 + 12)).is_none());
+    }
+
+    #[test]
+    fn test_reset_starts_a_new_session() {
+        let mut stats = SessionStats::default();
+        play(
+            &mut stats,
+            Instant::now(),
+            &[reading(69, 0.0), reading(69, 0.0), reading(62, -8.0)],
+        );
+        assert_eq!(stats.notes().count(), 2);
+        assert_eq!(stats.mean_abs_cents(), Some(4.0));
+
+        stats.reset();
+        assert_eq!(stats.tracked_time(), Duration::ZERO);
+        assert_eq!(stats.notes().count(), 0);
+        assert_eq!(stats.mean_abs_cents(), None);
+    }
+
+    #[test]
+    fn test_stability_keeps_the_best_and_average() {
+        let mut stats = SessionStats::default();
+        assert_eq!(stats.best_deviation(), None);
+        let steadied = |cents: f32, deviation: Option<f32>| PitchReading {
+            cents_deviation: deviation,
+            ..reading(69, cents)
+        };
+        play(
+            &mut stats,
+            Instant::now(),
+            &[
+                steadied(0.0, None),
+                steadied(1.0, Some(4.0)),
+                steadied(2.0, Some(1.5)),
+                steadied(2.0, Some(1.5)),
+                PitchReading::default(),
+                steadied(-1.0, Some(2.5)),
+            ],
+        );
+        assert_eq!(stats.best_deviation(), Some(1.5));
+        assert!((stats.mean_deviation().unwrap() - 8.0 / 3.0).abs() < 1e-5);
+
+        stats.reset();
+        assert_eq!(stats.mean_deviation(), None);
+    }
+}
@@ -0,0 +1,130 @@
+//! Light and dark themes
+//!
+//! The neutral colors of the interface — panel and widget fills, text and the
+//! greys of secondary labels and tick marks — for each egui theme, and the
+//! widget styling derived from them. Colors that carry meaning come from the
+//! [`Palette`](crate::palette::Palette) instead.
+
+use eframe::egui::{self, Color32};
+
+/// Neutral interface colors of one theme.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Theme {
+    /// Background of the window and its panels; slightly translucent.
+    pub panel_fill: Color32,
+    /// Widgets, the needle track and other recessed areas.
+    pub track: Color32,
+    /// Widgets under the pointer, and selections.
+    pub hover: Color32,
+    /// Background of plots.
+    pub plot_fill: Color32,
+    /// Primary text, such as the note name.
+    pub text: Color32,
+    /// Labels and hints.
+    pub secondary_text: Color32,
+    /// Tick marks and inactive icons.
+    pub faint: Color32,
+    /// Lit indicator dots.
+    pub lit: Color32,
+}
+
+impl Theme {
+    /// Light text on near-black, the tuner's original look.
+    pub const DARK: Theme = Theme {
+        panel_fill: Color32::from_rgba_premultiplied(31, 31, 31, 240),
+        track: Color32::from_rgb(59, 59, 59),
+        hover: Color32::from_rgb(75, 75, 75),
+        plot_fill: Color32::from_rgb(38, 38, 38),
+        text: Color32::WHITE,
+        secondary_text: Color32::from_rgb(142, 142, 147),
+        faint: Color32::from_rgb(99, 99, 102),
+        lit: Color32::from_rgb(199, 199, 204),
+    };
+
+    /// Dark text on off-white.
+    pub const LIGHT: Theme = Theme {
+        // Premultiplied channels may not exceed the alpha, or the fill would
+        // brighten whatever is behind the window instead of covering it.
+        panel_fill: Color32::from_rgba_premultiplied(228, 228, 232, 240),
+        track: Color32::from_rgb(214, 214, 219),
+        hover: Color32::from_rgb(198, 198, 204),
+        plot_fill: Color32::from_rgb(236, 236, 240),
+        text: Color32::from_rgb(28, 28, 30),
+        secondary_text: Color32::from_rgb(108, 108, 112),
+        faint: Color32::from_rgb(160, 160, 165),
+        lit: Color32::from_rgb(72, 72, 74),
+    };
+
+    /// The colors of `theme`.
+    pub fn of(theme: egui::Theme) -> Self {
+        match theme {
+            egui::Theme::Dark => Self::DARK,
+            egui::Theme::Light => Self::LIGHT,
+        }
+    }
+
+    /// `color` with its alpha replaced, for overlays.
+    pub fn translucent(color: Color32, alpha: u8) -> Color32 {
+        let [red, green, blue, _] = color.to_array();
+        Color32::from_rgba_unmultiplied(red, green, blue, alpha)
+    }
+
+    /// Styles widgets, popups and text to match the theme.
+    pub fn apply(&self, style: &mut egui::Style) {
+        let visuals = &mut style.visuals;
+        for widget in [
+            &mut visuals.widgets.inactive,
+            &mut visuals.widgets.active,
+            &mut visuals.widgets.open,
+        ] {
+            widget.bg_fill = self.track;
+            widget.weak_bg_fill = self.track;
+            widget.fg_stroke.color = self.text;
+        }
+        visuals.widgets.hovered.bg_fill = self.hover;
+        visuals.widgets.hovered.weak_bg_fill = self.hover;
+        visuals.widgets.hovered.fg_stroke.color = self.text;
+
+        visuals.window_fill = self.track;
+        visuals.panel_fill = self.track;
+        visuals.extreme_bg_color = self.track;
+
+        visuals.selection.bg_fill = self.hover;
+        visuals.selection.stroke.color = self.text;
+
+        visuals.override_text_color = Some(self.text);
+        visuals.popup_shadow = egui::epaint::Shadow::NONE;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn luminance(color: Color32) -> f32 {
+        let [red, green, blue, _] = color.to_array();
+        0.2126 * red as f32 + 0.7152 * green as f32 + 0.0722 * blue as f32
+    }
+
+    #[test]
+    fn test_text_contrasts_with_panel_in_both_themes() {
+        for theme in [Theme::DARK, Theme::LIGHT] {
+            let panel = luminance(theme.panel_fill);
+            assert!((luminance(theme.text) - panel).abs() > 150.0, "{:?}", theme);
+            assert!(
+                (luminance(theme.secondary_text) - panel).abs() > 60.0,
+                "{:?}",
+                theme
+            );
+        }
+    }
+
+    #[test]
+    fn test_apply_styles_text_and_widgets() {
+        let mut style = egui::Style::default();
+        Theme::LIGHT.apply(&mut style);
+        assert_eq!(style.visuals.override_text_color, Some(Theme::LIGHT.text));
+        assert_eq!(style.visuals.widgets.inactive.bg_fill, Theme::LIGHT.track);
+        assert_eq!(style.visuals.widgets.hovered.bg_fill, Theme::LIGHT.hover);
+    }
+}
@@ -0,0 +1,1486 @@
+//! Note tracking and smoothing
+//!
+//! Turns a stream of raw pitch estimates into a stable note with a smoothed
+//! cents deviation: gates on magnitude and confidence, requires detections to
+//! agree over a time window, skips the attack of each new note, applies note
+//! hysteresis, measures vibrato and maps pitches to instrument strings, Scala
+//! degrees or tempered notes.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::config::TunerConfig;
+use crate::pitch::{
+    detect_vibrato, frequency_to_edo_note, frequency_to_key_note, frequency_to_note,
+    frequency_to_stretched_note, frequency_to_tempered_note, midi_to_note, FrequencyPrior,
+    HumRejection, Key, LowRange, Note, PitchDetector, PitchEstimate, Precision, Temperament,
+    Vibrato, WindowKind, CHROMATIC_RANGE,
+};
+use crate::presets::InstrumentPreset;
+use crate::scala::{ScalaScale, ScaleMatch};
+
+/// Everything the tracker and detector need to know about how to analyse.
+#[derive(Clone, Debug)]
+pub struct AnalysisSettings {
+    /// Frequency of A4 in Hz.
+    pub a4_reference: f32,
+    /// Detections quieter than this cannot acquire a note.
+    pub min_magnitude_threshold: f32,
+    /// Detections less periodic than this (0–1) are ignored.
+    pub min_confidence: f32,
+    /// Share of spectral energy a note's harmonics need to be detected.
+    pub min_tonality: f32,
+    /// Largest frequency spread in Hz the pitch window may hold.
+    pub stability_threshold: f32,
+    /// How long detections must agree before a pitch is accepted.
+    pub pitch_window: Duration,
+    /// How much cents history is averaged for the needle.
+    pub cents_window: Duration,
+    /// Time constant of the needle's exponential smoothing.
+    pub smoothing_time: Duration,
+    /// Fraction of the magnitude gate a locked note keeps updating down to.
+    pub sustain_fraction: f32,
+    /// How long a note is held while detections fall below the gate.
+    pub quiet_hold: Duration,
+    /// How long a note is held once detections stop entirely.
+    pub silence_hold: Duration,
+    /// How long after a dropout the same note resumes at once.
+    pub relock_grace: Duration,
+    /// How long detections are ignored after an onset; zero disables onset
+    /// handling.
+    pub attack_skip: Duration,
+    /// Measure against this MIDI note however far off the pitch is, instead
+    /// of snapping; takes precedence over the preset and scale.
+    pub target_note: Option<i32>,
+    /// Snap to the closest string of this instrument instead of any note.
+    pub preset: Option<Arc<InstrumentPreset>>,
+    /// Whether the analyzer keeps a copy of the spectrum for display.
+    pub capture_spectrum: bool,
+    /// Whether the analyzer also measures every string of the preset at once.
+    pub capture_strum: bool,
+    /// Whether the analyzer also picks out several notes sounding together.
+    pub capture_peaks: bool,
+    /// Analysis size in samples, one of [`FFT_SIZES`](crate::pitch::FFT_SIZES).
+    pub fft_size: usize,
+    /// New samples between analyses, one of
+    /// [`HOP_SIZES`](crate::pitch::HOP_SIZES).
+    pub hop_size: usize,
+    /// Window applied before the FFT.
+    pub window_kind: WindowKind,
+    /// How finely the FFT peak is located.
+    pub precision: Precision,
+    /// Whether bass notes are analysed on a decimated signal.
+    pub low_range: LowRange,
+    /// Mains hum notch applied before detection.
+    pub hum_rejection: HumRejection,
+    /// High-pass cutoff in Hz used with hum rejection; 0 disables it.
+    pub high_pass_cutoff: f32,
+    /// Lowest and highest fundamental searched for, in Hz.
+    pub frequency_range: (f32, f32),
+    /// Narrows the search to around this, e.g. the octave the player says
+    /// they are in.
+    pub frequency_prior: Option<FrequencyPrior>,
+    /// Detections below this are re-estimated in the time domain, in Hz.
+    pub crossover_frequency: f32,
+    /// Temperament notes are tuned to when no preset or scale is active.
+    pub temperament: Temperament,
+    /// Pitch class (0 = C) the temperament is built on.
+    pub temperament_tonic: u8,
+    /// Equal divisions of the octave notes snap to when no preset, scale or
+    /// piano stretch is active; 12 uses the temperament and key.
+    pub edo: u32,
+    /// Key whose notes readings snap to when no preset, scale or piano
+    /// stretch is active.
+    pub key: Option<Key>,
+    /// Cents per octave notes are stretched by as on a piano, when no preset
+    /// or scale is active; `None` outside piano mode.
+    pub piano_stretch: Option<f32>,
+    /// Snap to the degrees of this Scala scale instead of notes.
+    pub scale: Option<Arc<ScalaScale>>,
+    /// Frequency of degree 0 of the scale.
+    pub scale_reference: f32,
+}
+
+impl From<&TunerConfig> for AnalysisSettings {
+    fn from(config: &TunerConfig) -> Self {
+        Self {
+            a4_reference: config.a4_reference,
+            min_magnitude_threshold: config.min_magnitude_threshold,
+            min_confidence: config.min_confidence,
+            min_tonality: config.min_tonality,
+            stability_threshold: config.stability_threshold,
+            pitch_window: Duration::from_millis(config.pitch_window_ms),
+            cents_window: Duration::from_millis(config.cents_window_ms),
+            smoothing_time: Duration::from_millis(config.smoothing_ms),
+            sustain_fraction: config.sustain_fraction,
+            quiet_hold: Duration::from_millis(config.quiet_hold_ms),
+            silence_hold: Duration::from_millis(config.silence_hold_ms),
+            relock_grace: Duration::from_millis(config.relock_grace_ms),
+            attack_skip: Duration::from_millis(config.attack_skip_ms),
+            target_note: None,
+            preset: None,
+            capture_spectrum: config.show_spectrum,
+            capture_strum: false,
+            capture_peaks: config.identify_intervals,
+            fft_size: config.analysis_size(),
+            hop_size: config.analysis_hop(),
+            window_kind: config.window_kind,
+            precision: config.precision,
+            low_range: config.low_range,
+            hum_rejection: config.hum_rejection,
+            high_pass_cutoff: config.high_pass_cutoff,
+            frequency_range: if config.custom_frequency_range {
+                (config.min_frequency, config.max_frequency)
+            } else {
+                CHROMATIC_RANGE
+            },
+            frequency_prior: None,
+            crossover_frequency: config.crossover_frequency,
+            temperament: config.temperament,
+            temperament_tonic: config.temperament_tonic,
+            edo: config.octave_divisions(),
+            key: config.key(),
+            piano_stretch: config.piano_mode.then_some(config.piano_stretch),
+            scale: None,
+            scale_reference: config.scala_reference,
+        }
+    }
+}
+
+impl Default for AnalysisSettings {
+    fn default() -> Self {
+        Self::from(&TunerConfig::default())
+    }
+}
+
+impl AnalysisSettings {
+    /// Applies the detector's share of the settings, all but its size,
+    /// window, sample rate and bass range.
+    pub fn configure(&self, detector: &mut PitchDetector) {
+        detector.set_pre_filter(self.hum_rejection, self.high_pass_cutoff);
+        let (min_frequency, max_frequency) = self.frequency_range;
+        detector.set_frequency_range(min_frequency, max_frequency);
+        detector.set_frequency_prior(self.frequency_prior);
+        detector.set_crossover_frequency(self.crossover_frequency);
+        detector.set_precision(self.precision);
+        detector.set_min_tonality(self.min_tonality);
+    }
+}
+
+/// What the tracker currently shows.
+#[derive(Clone, Debug, Default)]
+pub struct PitchReading {
+    /// The locked note with its smoothed deviation, if any.
+    pub note: Option<Note>,
+    /// Smoothed deviation from the target in cents.
+    pub smoothed_cents: f32,
+    /// String of the active preset the note belongs to.
+    pub string_number: Option<usize>,
+    /// Degree of the active Scala scale the note belongs to.
+    pub scale_match: Option<ScaleMatch>,
+    /// Confidence of the latest detection, 0 when nothing was detected.
+    pub confidence: f32,
+    /// Magnitude of the latest detection, 0 when nothing was detected.
+    pub magnitude: f32,
+    /// Rate and width of a regular vibrato, when one is present.
+    pub vibrato: Option<Vibrato>,
+    /// Deviation of the latest detection alone from the note's target,
+    /// before any windowing or smoothing.
+    pub raw_cents: Option<f32>,
+    /// Detections fed to the tracker so far, for measuring how often they
+    /// arrive.
+    pub detections: u64,
+    /// Standard deviation of the raw cents over the last
+    /// [`STABILITY_WINDOW`], showing how steadily the note is held.
+    pub cents_deviation: Option<f32>,
+}
+
+/// Fewest detections a window must hold before its spread is trusted.
+const MIN_WINDOW_DETECTIONS: usize = 3;
+const MAX_CENTS_SPREAD: f32 = 20.0;
+/// A locked note is kept until the pitch strays this far from its target...
+const NOTE_HYSTERESIS_CENTS: f32 = 60.0;
+/// ...or a neighbouring note has been detected continuously for this long.
+const NOTE_SWITCH_TIME: Duration = Duration::from_millis(200);
+/// How much frequency history is searched for vibrato.
+const VIBRATO_WINDOW: Duration = Duration::from_secs(2);
+/// How much raw deviation the stability of a held note is measured over.
+pub const STABILITY_WINDOW: Duration = Duration::from_secs(1);
+
+/// What feeding a single detection did to the tracked note.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrackerOutput {
+    /// Below the magnitude or confidence gate, or not a usable frequency; ignored.
+    Gated,
+    /// Accepted, but the window doesn't agree on a pitch yet.
+    Settling,
+    /// Made during the attack of a new note; ignored.
+    Attack,
+    /// The cents window spread too far, so the note was cleared.
+    Cleared,
+    /// The current note was updated.
+    Tracking,
+}
+
+/// Turns raw detections into a stable note and a smoothed cents value using
+/// time-based windows, so behaviour doesn't depend on how often audio arrives.
+#[derive(Debug, Default)]
+pub struct PitchTracker {
+    /// Frequency and magnitude of each recent detection.
+    detections: VecDeque<(Instant, (f32, f32))>,
+    cents: VecDeque<(Instant, f32)>,
+    smoothed_cents: f32,
+    last_smoothed: Option<Instant>,
+    current_note: Option<Note>,
+    current_string: Option<usize>,
+    current_scale_match: Option<ScaleMatch>,
+    last_update: Option<Instant>,
+    /// Target frequency of a different note the pitch has been snapping to,
+    /// and when that started.
+    pending_note: Option<(f32, Instant)>,
+    confidence: f32,
+    magnitude: f32,
+    /// Every detection that passed the gates, for vibrato analysis.
+    vibrato_trace: VecDeque<(Instant, f32)>,
+    vibrato: Option<Vibrato>,
+    /// Set at an onset: detections are ignored until then, while the new
+    /// note's attack rings, and the first deviation afterwards starts afresh
+    /// instead of gliding from the last note's.
+    attack_until: Option<Instant>,
+    raw_cents: Option<f32>,
+    /// Raw deviation of each detection tracked on the current note, for
+    /// measuring how steadily it is held.
+    stability: VecDeque<(Instant, f32)>,
+    /// Every detection fed, kept across resets.
+    detection_count: u64,
+    /// The note that last dropped out, so it can resume if heard again soon.
+    last_lock: Option<LastLock>,
+    /// Set when a note resumes: its windows are trusted with fewer
+    /// detections until they have filled again.
+    resumed: bool,
+}
+
+/// A locked note that dropped out, with its string or scale degree.
+#[derive(Clone, Debug)]
+struct LastLock {
+    /// The note, its deviation being the smoothed one at the time.
+    note: Note,
+    string_number: Option<usize>,
+    scale_match: Option<ScaleMatch>,
+    dropped_at: Instant,
+}
+
+fn expire<T>(history: &mut VecDeque<(Instant, T)>, now: Instant, window: Duration) {
+    while let Some((time, _)) = history.front() {
+        if now.saturating_duration_since(*time) <= window {
+            break;
+        }
+        history.pop_front();
+    }
+}
+
+fn cents_between(frequency: f32, reference: f32) -> f32 {
+    1200.0 * (frequency / reference).log2()
+}
+
+/// Whether two target frequencies are the same note.
+pub(crate) fn same_target(a: f32, b: f32) -> bool {
+    cents_between(a, b).abs() < 1.0
+}
+
+fn spread(values: impl Iterator<Item = f32>) -> f32 {
+    let (min, max) = values.fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), value| {
+        (min.min(value), max.max(value))
+    });
+    max - min
+}
+
+/// Population standard deviation of `values`, or `None` if there are none.
+pub(crate) fn standard_deviation(values: impl Iterator<Item = f32> + Clone) -> Option<f32> {
+    let count = values.clone().count();
+    if count == 0 {
+        return None;
+    }
+    let mean = values.clone().sum::<f32>() / count as f32;
+    let variance = values.map(|value| (value - mean).powi(2)).sum::<f32>() / count as f32;
+    Some(variance.sqrt())
+}
+
+impl PitchTracker {
+    /// Forgets the note and all history.
+    pub fn reset(&mut self) {
+        self.detections.clear();
+        self.cents.clear();
+        self.smoothed_cents = 0.0;
+        self.last_smoothed = None;
+        self.current_note = None;
+        self.current_string = None;
+        self.current_scale_match = None;
+        self.pending_note = None;
+        self.confidence = 0.0;
+        self.magnitude = 0.0;
+        self.vibrato_trace.clear();
+        self.vibrato = None;
+        self.attack_until = None;
+        self.raw_cents = None;
+        self.stability.clear();
+        self.last_lock = None;
+        self.resumed = false;
+    }
+
+    /// Remembers the locked note as it drops out at `now`.
+    fn lose_note(&mut self, now: Instant) {
+        if let Some(note) = self.current_note.take() {
+            self.last_lock = Some(LastLock {
+                note,
+                string_number: self.current_string,
+                scale_match: self.current_scale_match,
+                dropped_at: now,
+            });
+        }
+    }
+
+    /// Resets after a dropout, remembering the note that was locked.
+    fn drop_note(&mut self, now: Instant) {
+        self.lose_note(now);
+        let last_lock = self.last_lock.take();
+        self.reset();
+        self.last_lock = last_lock;
+    }
+
+    /// Brings back the note that dropped out if `frequency` is on it and
+    /// it's within the relock grace, with its old deviation as the needle's
+    /// starting point, instead of waiting for fresh windows to agree.
+    fn resume(&mut self, frequency: f32, now: Instant, settings: &AnalysisSettings) {
+        let Some(lock) = self.last_lock.take() else {
+            return;
+        };
+        if now.saturating_duration_since(lock.dropped_at) > settings.relock_grace
+            || cents_between(frequency, lock.note.target_frequency).abs() >= 50.0
+        {
+            return;
+        }
+        self.detections.clear();
+        self.cents.clear();
+        self.stability.clear();
+        self.smoothed_cents = lock.note.cents_off;
+        self.last_smoothed = None;
+        self.current_note = Some(lock.note);
+        self.current_string = lock.string_number;
+        self.current_scale_match = lock.scale_match;
+        self.last_update = Some(now);
+        self.resumed = true;
+    }
+
+    /// Fewest detections a window must hold before it is trusted.
+    fn min_window_detections(&self) -> usize {
+        if self.resumed {
+            1
+        } else {
+            MIN_WINDOW_DETECTIONS
+        }
+    }
+
+    /// Called when a new note starts at `now`: forgets the detections and
+    /// deviations of the last one and ignores detections for the attack skip,
+    /// so the noisy attack can't seed the windows. The note on display stays
+    /// until the new one settles.
+    pub fn onset(&mut self, now: Instant, settings: &AnalysisSettings) {
+        if settings.attack_skip.is_zero() {
+            return;
+        }
+        self.detections.clear();
+        self.cents.clear();
+        self.last_smoothed = None;
+        self.pending_note = None;
+        self.vibrato_trace.clear();
+        self.vibrato = None;
+        self.stability.clear();
+        self.attack_until = Some(now + settings.attack_skip);
+    }
+
+    /// The note, deviation and confidence to show right now.
+    pub fn reading(&self) -> PitchReading {
+        PitchReading {
+            note: self.current_note.clone(),
+            smoothed_cents: self.smoothed_cents,
+            string_number: self.current_string,
+            scale_match: self.current_scale_match,
+            confidence: self.confidence,
+            magnitude: self.magnitude,
+            vibrato: self.vibrato,
+            raw_cents: self.raw_cents,
+            detections: self.detection_count,
+            cents_deviation: self.cents_deviation(),
+        }
+    }
+
+    /// Spread of the recent raw deviations, once there are enough of them.
+    fn cents_deviation(&self) -> Option<f32> {
+        if self.current_note.is_none() || self.stability.len() < MIN_WINDOW_DETECTIONS {
+            return None;
+        }
+        standard_deviation(self.stability.iter().map(|&(_, cents)| cents))
+    }
+
+    fn held_for(&self, now: Instant, hold: Duration) -> bool {
+        match self.last_update {
+            Some(last_update) => now.saturating_duration_since(last_update) <= hold,
+            None => false,
+        }
+    }
+
+    /// Called when the detector found no pitch at all; drops the note once
+    /// it has gone unconfirmed for longer than the silence hold.
+    pub fn timeout(&mut self, now: Instant, settings: &AnalysisSettings) {
+        self.confidence = 0.0;
+        self.magnitude = 0.0;
+        if !self.held_for(now, settings.silence_hold) {
+            self.drop_note(now);
+        }
+    }
+
+    /// Feeds one detection made at `now` and reports what it did. A frequency
+    /// that isn't positive and finite is ignored.
+    pub fn feed(
+        &mut self,
+        estimate: PitchEstimate,
+        now: Instant,
+        settings: &AnalysisSettings,
+    ) -> TrackerOutput {
+        let PitchEstimate {
+            frequency,
+            magnitude,
+            confidence,
+        } = estimate;
+        self.detection_count += 1;
+        if !(frequency.is_finite() && frequency > 0.0) {
+            return TrackerOutput::Gated;
+        }
+        if self.attack_until.is_some_and(|until| now < until) {
+            self.confidence = confidence;
+            self.magnitude = magnitude;
+            return TrackerOutput::Attack;
+        }
+        if magnitude < self.magnitude_gate(settings) || confidence < settings.min_confidence {
+            if !self.held_for(now, settings.quiet_hold) {
+                self.drop_note(now);
+            }
+            self.confidence = confidence;
+            self.magnitude = magnitude;
+            return TrackerOutput::Gated;
+        }
+        self.confidence = confidence;
+        self.magnitude = magnitude;
+
+        self.vibrato_trace.push_back((now, frequency));
+        expire(&mut self.vibrato_trace, now, VIBRATO_WINDOW);
+        self.vibrato = self.measure_vibrato();
+
+        if self.current_note.is_none() {
+            self.resume(frequency, now, settings);
+        }
+        let Some(median_freq) = self.push_detection(frequency, magnitude, now, settings) else {
+            return TrackerOutput::Settling;
+        };
+
+        let mut string_number = None;
+        let mut scale_match = None;
+        let mut note = if let Some(midi) = settings.target_note {
+            midi_to_note(midi, median_freq, settings.a4_reference)
+        } else if let Some(preset) = &settings.preset {
+            match preset.closest_string(median_freq, settings.a4_reference) {
+                Some(matched) => {
+                    string_number = Some(matched.string_number);
+                    matched.note
+                }
+                None => return TrackerOutput::Settling,
+            }
+        } else if let Some(scale) = &settings.scale {
+            let matched = scale.nearest_degree(median_freq, settings.scale_reference);
+            scale_match = Some(matched);
+            match frequency_to_note(median_freq, settings.a4_reference) {
+                Some(note) => Note {
+                    target_frequency: matched.target_frequency,
+                    cents_off: matched.cents_off,
+                    ..note
+                },
+                None => return TrackerOutput::Settling,
+            }
+        } else if let Some(cents_per_octave) = settings.piano_stretch {
+            match frequency_to_stretched_note(
+                median_freq,
+                settings.a4_reference,
+                settings.temperament,
+                settings.temperament_tonic,
+                cents_per_octave,
+            ) {
+                Some(note) => note,
+                None => return TrackerOutput::Settling,
+            }
+        } else if settings.edo != 12 {
+            match frequency_to_edo_note(median_freq, settings.a4_reference, settings.edo) {
+                Some(note) => note,
+                None => return TrackerOutput::Settling,
+            }
+        } else if let Some(key) = settings.key {
+            match frequency_to_key_note(
+                median_freq,
+                settings.a4_reference,
+                settings.temperament,
+                settings.temperament_tonic,
+                key.pitch_classes(),
+            ) {
+                Some(note) => note,
+                None => return TrackerOutput::Settling,
+            }
+        } else {
+            match frequency_to_tempered_note(
+                median_freq,
+                settings.a4_reference,
+                settings.temperament,
+                settings.temperament_tonic,
+            ) {
+                Some(note) => note,
+                None => return TrackerOutput::Settling,
+            }
+        };
+
+        if let Some(locked) = &self.current_note {
+            if same_target(locked.target_frequency, note.target_frequency) {
+                self.pending_note = None;
+            } else {
+                let since = match self.pending_note {
+                    Some((target, since)) if same_target(target, note.target_frequency) => since,
+                    _ => now,
+                };
+                self.pending_note = Some((note.target_frequency, since));
+
+                let locked_cents = cents_between(median_freq, locked.target_frequency);
+                if locked_cents.abs() <= NOTE_HYSTERESIS_CENTS
+                    && now.saturating_duration_since(since) < NOTE_SWITCH_TIME
+                {
+                    note = Note {
+                        cents_off: locked_cents,
+                        ..locked.clone()
+                    };
+                    string_number = self.current_string;
+                    scale_match = self.current_scale_match.map(|matched| ScaleMatch {
+                        cents_off: locked_cents,
+                        ..matched
+                    });
+                } else {
+                    // Switch immediately and restart the cents window, which
+                    // holds deviations from the old note.
+                    self.pending_note = None;
+                    self.cents.clear();
+                    self.stability.clear();
+                    self.last_smoothed = None;
+                    self.smoothed_cents = note.cents_off;
+                    self.current_note = Some(note.clone());
+                    self.current_string = string_number;
+                    self.current_scale_match = scale_match;
+                    self.last_update = Some(now);
+                }
+            }
+        }
+
+        match self.push_cents(note.cents_off, now, settings) {
+            Some(smoothed_cents) => {
+                let raw_cents = cents_between(frequency, note.target_frequency);
+                self.raw_cents = Some(raw_cents);
+                self.stability.push_back((now, raw_cents));
+                expire(&mut self.stability, now, STABILITY_WINDOW);
+                self.current_note = Some(Note {
+                    cents_off: smoothed_cents,
+                    ..note
+                });
+                self.current_string = string_number;
+                self.current_scale_match = scale_match;
+                self.last_update = Some(now);
+                TrackerOutput::Tracking
+            }
+            None if self.cents.is_empty() => {
+                self.lose_note(now);
+                TrackerOutput::Cleared
+            }
+            None => TrackerOutput::Settling,
+        }
+    }
+
+    /// The magnitude a detection needs: the full gate to acquire a note, and
+    /// the lower sustain level while one is locked.
+    fn magnitude_gate(&self, settings: &AnalysisSettings) -> f32 {
+        if self.current_note.is_some() {
+            settings.min_magnitude_threshold * settings.sustain_fraction
+        } else {
+            settings.min_magnitude_threshold
+        }
+    }
+
+    fn measure_vibrato(&self) -> Option<Vibrato> {
+        let &(start, _) = self.vibrato_trace.front()?;
+        let trace: Vec<(f32, f32)> = self
+            .vibrato_trace
+            .iter()
+            .map(|&(time, frequency)| (time.duration_since(start).as_secs_f32(), frequency))
+            .collect();
+        detect_vibrato(&trace)
+    }
+
+    /// Records a detection and returns the median frequency once the detections
+    /// within the pitch window agree in frequency and level. During a regular
+    /// vibrato the pitch swings by design, so its centre is used instead.
+    fn push_detection(
+        &mut self,
+        frequency: f32,
+        magnitude: f32,
+        now: Instant,
+        settings: &AnalysisSettings,
+    ) -> Option<f32> {
+        self.detections.push_back((now, (frequency, magnitude)));
+        expire(&mut self.detections, now, settings.pitch_window);
+
+        if self.detections.len() < self.min_window_detections() {
+            return None;
+        }
+
+        let frequency_spread = spread(self.detections.iter().map(|&(_, (frequency, _))| frequency));
+        let avg_magnitude = self
+            .detections
+            .iter()
+            .map(|&(_, (_, magnitude))| magnitude)
+            .sum::<f32>()
+            / self.detections.len() as f32;
+        let magnitude_stable = self
+            .detections
+            .iter()
+            .all(|&(_, (_, m))| (m - avg_magnitude).abs() < avg_magnitude * 0.5);
+
+        // Acquiring needs a margin over the gate; a locked note rides its
+        // decay down to the sustain gate.
+        let magnitude_floor = match self.current_note {
+            Some(_) => self.magnitude_gate(settings),
+            None => settings.min_magnitude_threshold * 2.0,
+        };
+        if !magnitude_stable || avg_magnitude <= magnitude_floor {
+            return None;
+        }
+        if let Some(vibrato) = self.vibrato {
+            return Some(vibrato.center_frequency);
+        }
+        if frequency_spread >= settings.stability_threshold {
+            return None;
+        }
+
+        let mut sorted: Vec<f32> = self.detections.iter().map(|&(_, (f, _))| f).collect();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        Some(sorted[sorted.len() / 2])
+    }
+
+    /// Records the cents deviation of a stable pitch and returns the smoothed
+    /// value, or `None` (clearing the cents history) if the window disagrees.
+    fn push_cents(&mut self, cents: f32, now: Instant, settings: &AnalysisSettings) -> Option<f32> {
+        self.cents.push_back((now, cents));
+        expire(&mut self.cents, now, settings.cents_window);
+
+        if self.cents.len() < self.min_window_detections() {
+            return None;
+        }
+        if self.cents.len() >= MIN_WINDOW_DETECTIONS {
+            self.resumed = false;
+        }
+
+        if self.vibrato.is_none()
+            && spread(self.cents.iter().map(|&(_, cents)| cents)) >= MAX_CENTS_SPREAD
+        {
+            self.cents.clear();
+            self.stability.clear();
+            self.smoothed_cents = 0.0;
+            self.last_smoothed = None;
+            return None;
+        }
+
+        let target =
+            self.cents.iter().map(|&(_, cents)| cents).sum::<f32>() / self.cents.len() as f32;
+        if self.attack_until.take().is_some() {
+            self.smoothed_cents = target;
+            self.last_smoothed = Some(now);
+            return Some(self.smoothed_cents);
+        }
+        if !self.smoothed_cents.is_finite() {
+            // The average would never recover, so start again from here.
+            self.smoothed_cents = target;
+        }
+        let since = self.last_smoothed.unwrap_or(self.cents[0].0);
+        let dt = now.saturating_duration_since(since).as_secs_f32();
+        let tau = settings.smoothing_time.as_secs_f32();
+        let alpha = if tau > 0.0 {
+            1.0 - (-dt / tau).exp()
+        } else {
+            1.0
+        };
+
+        self.smoothed_cents += alpha * (target - self.smoothed_cents);
+        self.last_smoothed = Some(now);
+        Some(self.smoothed_cents)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pitch::{
+        midi_to_frequency, KeyMode, OnsetDetector, PitchClassSet, DEFAULT_A4_FREQUENCY,
+    };
+
+    #[test]
+    fn test_tracker_accepts_pitch_once_window_agrees() {
+        let settings = AnalysisSettings::default();
+        let start = Instant::now();
+        let mut tracker = PitchTracker::default();
+
+        let at = |ms| start + Duration::from_millis(ms);
+        assert_eq!(tracker.push_detection(440.0, 1.0, at(0), &settings), None);
+        assert_eq!(tracker.push_detection(441.0, 1.0, at(10), &settings), None);
+        assert_eq!(
+            tracker.push_detection(440.5, 1.0, at(20), &settings),
+            Some(440.5)
+        );
+
+        // A jump makes the window disagree until the old detections expire.
+        let window = settings.pitch_window.as_millis() as u64;
+        for ms in (30..=300).step_by(10) {
+            let accepted = tracker.push_detection(494.0, 1.0, at(ms), &settings);
+            if ms <= 20 + window {
+                assert_eq!(accepted, None, "accepted a mixed window at {} ms", ms);
+            } else {
+                assert_eq!(
+                    accepted,
+                    Some(494.0),
+                    "rejected a settled window at {} ms",
+                    ms
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_tracker_smoothing_is_independent_of_detection_rate() {
+        let settings = AnalysisSettings {
+            cents_window: Duration::from_millis(500),
+            smoothing_time: Duration::from_millis(100),
+            ..AnalysisSettings::default()
+        };
+        let start = Instant::now();
+
+        let smoothed_after = |interval_ms: u64| {
+            let mut tracker = PitchTracker::default();
+            let mut smoothed = None;
+            for step in 0..=(200 / interval_ms) {
+                let now = start + Duration::from_millis(step * interval_ms);
+                smoothed = tracker.push_cents(10.0, now, &settings);
+            }
+            smoothed.unwrap()
+        };
+
+        // After 200 ms at a 100 ms time constant the needle covers 1 - e^-2 of the way.
+        let expected = 10.0 * (1.0 - (-2.0f32).exp());
+        for interval in [5, 10, 20, 40] {
+            let smoothed = smoothed_after(interval);
+            assert!(
+                (smoothed - expected).abs() < 1e-3,
+                "{} ms updates gave {} cents, expected {}",
+                interval,
+                smoothed,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_tracker_clears_on_cents_spread() {
+        let settings = AnalysisSettings::default();
+        let start = Instant::now();
+        let mut tracker = PitchTracker::default();
+
+        for (ms, cents) in [(0, 3.0), (10, 4.0), (20, 3.5)] {
+            tracker.push_cents(cents, start + Duration::from_millis(ms), &settings);
+        }
+        assert!(tracker.smoothed_cents > 0.0);
+
+        let jumped = tracker.push_cents(30.0, start + Duration::from_millis(30), &settings);
+        assert_eq!(jumped, None);
+        assert_eq!(tracker.smoothed_cents, 0.0);
+    }
+
+    #[test]
+    fn test_smoothing_recovers_from_a_non_finite_value() {
+        let settings = AnalysisSettings::default();
+        let start = Instant::now();
+        let mut tracker = PitchTracker {
+            smoothed_cents: f32::NAN,
+            ..PitchTracker::default()
+        };
+
+        for (ms, cents) in [(0, 3.0), (10, 4.0), (20, 3.5)] {
+            tracker.push_cents(cents, start + Duration::from_millis(ms), &settings);
+        }
+        assert!((tracker.smoothed_cents - 3.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_non_finite_detections_are_ignored() {
+        let settings = AnalysisSettings::default();
+        let start = Instant::now();
+        let mut tracker = PitchTracker::default();
+
+        for step in 0..40u64 {
+            let now = start + Duration::from_millis(step * 10);
+            let frequency = match step % 4 {
+                1 => f32::NAN,
+                3 => f32::INFINITY,
+                _ => 440.0,
+            };
+            let output = tracker.feed(estimate(frequency, 1.0), now, &settings);
+            if !frequency.is_finite() {
+                assert_eq!(output, TrackerOutput::Gated);
+            }
+        }
+
+        let reading = tracker.reading();
+        let note = reading.note.expect("A4 was lost");
+        assert_eq!(note.to_string(), "A4");
+        assert!(reading.smoothed_cents.is_finite());
+    }
+
+    fn estimate(frequency: f32, magnitude: f32) -> PitchEstimate {
+        PitchEstimate {
+            frequency,
+            magnitude,
+            confidence: 1.0,
+        }
+    }
+
+    /// Feeds `frequency(ms)` every 10 ms over `range` and returns each output.
+    fn feed_script(
+        tracker: &mut PitchTracker,
+        settings: &AnalysisSettings,
+        start: Instant,
+        range: std::ops::Range<u64>,
+        frequency: impl Fn(u64) -> f32,
+    ) -> Vec<(u64, TrackerOutput)> {
+        range
+            .step_by(10)
+            .map(|ms| {
+                let now = start + Duration::from_millis(ms);
+                (
+                    ms,
+                    tracker.feed(estimate(frequency(ms), 1.0), now, settings),
+                )
+            })
+            .collect()
+    }
+
+    fn note_name(tracker: &PitchTracker) -> Option<String> {
+        tracker.current_note.as_ref().map(|note| note.to_string())
+    }
+
+    #[test]
+    fn test_vibrato_keeps_note_locked() {
+        let settings = AnalysisSettings::default();
+        let sample_rate = 48000.0;
+        let hop = 1024;
+        let size = settings.fft_size;
+        let mut detector = PitchDetector::new(size, sample_rate, settings.window_kind);
+
+        // A4 with a 5.8 Hz, ±32 cent vibrato: far wider than the stability
+        // threshold, which would otherwise keep clearing the note.
+        let mut phase = 0.0f32;
+        let samples: Vec<f32> = (0..3 * sample_rate as usize)
+            .map(|index| {
+                let t = index as f32 / sample_rate;
+                let cents = 32.0 * (2.0 * std::f32::consts::PI * 5.8 * t).sin();
+                phase +=
+                    2.0 * std::f32::consts::PI * 440.0 * 2.0f32.powf(cents / 1200.0) / sample_rate;
+                0.5 * phase.sin()
+            })
+            .collect();
+
+        let start = Instant::now();
+        let mut tracker = PitchTracker::default();
+        for end in (size..=samples.len()).step_by(hop) {
+            let now = start + Duration::from_secs_f32(end as f32 / sample_rate);
+            let estimate = detector.detect_pitch(&samples[end - size..end]).unwrap();
+            let output = tracker.feed(estimate, now, &settings);
+            if end as f32 >= 2.0 * sample_rate {
+                assert_eq!(output, TrackerOutput::Tracking);
+                assert_eq!(note_name(&tracker).as_deref(), Some("A4"));
+                assert!(
+                    tracker.smoothed_cents.abs() < 5.0,
+                    "{}",
+                    tracker.smoothed_cents
+                );
+            }
+        }
+
+        let vibrato = tracker
+            .reading()
+            .vibrato
+            .expect("vibrato should be reported");
+        assert!((vibrato.rate - 5.8).abs() < 0.3, "{:?}", vibrato);
+        assert!(vibrato.width_cents > 20.0, "{:?}", vibrato);
+    }
+
+    #[test]
+    fn test_tracker_script_stable_tone() {
+        let settings = AnalysisSettings::default();
+        let start = Instant::now();
+        let mut tracker = PitchTracker::default();
+        let sharp_a4 = 440.0 * 2.0f32.powf(10.0 / 1200.0);
+
+        let outputs = feed_script(&mut tracker, &settings, start, 0..600, |_| sharp_a4);
+
+        // Three detections settle the pitch, three more the cents window.
+        let first_note = outputs
+            .iter()
+            .find(|(_, output)| *output == TrackerOutput::Tracking)
+            .map(|&(ms, _)| ms);
+        assert_eq!(first_note, Some(40));
+        assert!(outputs[4..]
+            .iter()
+            .all(|(_, output)| *output == TrackerOutput::Tracking));
+
+        assert_eq!(note_name(&tracker).as_deref(), Some("A4"));
+        let cents = tracker.reading().note.unwrap().cents_off;
+        assert!((cents - 10.0).abs() < 0.1, "converged to {} cents", cents);
+    }
+
+    #[test]
+    fn test_target_note_holds_however_far_off() {
+        let settings = AnalysisSettings {
+            target_note: Some(69),
+            ..AnalysisSettings::default()
+        };
+        let start = Instant::now();
+        let mut tracker = PitchTracker::default();
+        // G#4, which chromatic mode would snap to.
+        let g_sharp4 = midi_to_frequency(68, DEFAULT_A4_FREQUENCY);
+
+        feed_script(&mut tracker, &settings, start, 0..600, |_| g_sharp4);
+
+        assert_eq!(note_name(&tracker).as_deref(), Some("A4"));
+        let cents = tracker.reading().note.unwrap().cents_off;
+        assert!((cents + 100.0).abs() < 0.1, "converged to {} cents", cents);
+    }
+
+    #[test]
+    fn test_quarter_tone_is_in_tune_in_24_edo() {
+        let start = Instant::now();
+        let quarter_sharp_a4 = 452.89;
+        for (edo, expected_cents) in [(24, 0.0), (12, 50.0)] {
+            let settings = AnalysisSettings {
+                edo,
+                ..AnalysisSettings::default()
+            };
+            let mut tracker = PitchTracker::default();
+            feed_script(&mut tracker, &settings, start, 0..600, |_| quarter_sharp_a4);
+
+            let note = tracker.reading().note.unwrap();
+            assert_eq!(note.microtone_cents, 50.0 - expected_cents);
+            assert!(
+                (note.cents_off - expected_cents).abs() < 0.1,
+                "{}-EDO converged to {} cents",
+                edo,
+                note.cents_off
+            );
+        }
+    }
+
+    #[test]
+    fn test_key_reads_an_excluded_note_as_its_neighbour() {
+        let settings = AnalysisSettings {
+            key: Some(Key {
+                tonic: 3,
+                mode: KeyMode::Major,
+                custom: PitchClassSet::default(),
+            }),
+            ..AnalysisSettings::default()
+        };
+        let start = Instant::now();
+        let mut tracker = PitchTracker::default();
+        // A4 10 cents flat is not in E♭ major, so it is a very sharp A♭4.
+        let flat_a4 = 440.0 * 2.0f32.powf(-10.0 / 1200.0);
+
+        feed_script(&mut tracker, &settings, start, 0..600, |_| flat_a4);
+
+        assert_eq!(note_name(&tracker).as_deref(), Some("G#4"));
+        let cents = tracker.reading().note.unwrap().cents_off;
+        assert!((cents - 90.0).abs() < 0.1, "converged to {} cents", cents);
+    }
+
+    #[test]
+    fn test_tracker_script_wobbling_tone_never_settles() {
+        let settings = AnalysisSettings::default();
+        let start = Instant::now();
+        let mut tracker = PitchTracker::default();
+
+        let wobble = |ms: u64| [437.0, 443.0][(ms / 10) as usize % 2];
+        let outputs = feed_script(&mut tracker, &settings, start, 0..1000, wobble);
+
+        assert!(outputs
+            .iter()
+            .all(|(_, output)| *output == TrackerOutput::Settling));
+        assert_eq!(tracker.reading().note, None);
+    }
+
+    #[test]
+    fn test_tracker_script_silence_clears_after_hold() {
+        let settings = AnalysisSettings::default();
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut tracker = PitchTracker::default();
+
+        feed_script(&mut tracker, &settings, start, 0..200, |_| 329.63);
+        assert_eq!(note_name(&tracker).as_deref(), Some("E4"));
+
+        // No detection at all: held for the silence hold, then cleared.
+        let silence_hold = settings.silence_hold.as_millis() as u64;
+        tracker.timeout(at(190 + silence_hold - 50), &settings);
+        assert_eq!(note_name(&tracker).as_deref(), Some("E4"));
+        tracker.timeout(at(190 + silence_hold + 50), &settings);
+        assert_eq!(tracker.reading().note, None);
+        assert_eq!(tracker.smoothed_cents, 0.0);
+
+        // Below even the sustain gate: held for the quiet hold.
+        let resumed = 190 + silence_hold + 1000;
+        feed_script(&mut tracker, &settings, at(resumed), 0..200, |_| 329.63);
+        assert_eq!(note_name(&tracker).as_deref(), Some("E4"));
+        let quiet_hold = settings.quiet_hold.as_millis() as u64;
+        let quiet = settings.min_magnitude_threshold * settings.sustain_fraction / 2.0;
+        let gated = tracker.feed(
+            estimate(329.63, quiet),
+            at(resumed + 190 + quiet_hold - 50),
+            &settings,
+        );
+        assert_eq!(gated, TrackerOutput::Gated);
+        assert_eq!(note_name(&tracker).as_deref(), Some("E4"));
+        tracker.feed(
+            estimate(329.63, quiet),
+            at(resumed + 190 + quiet_hold + 50),
+            &settings,
+        );
+        assert_eq!(tracker.reading().note, None);
+    }
+
+    /// Magnitude of a plucked note decaying from `peak` with the given time
+    /// constant.
+    fn decay(peak: f32, time_constant_ms: f32) -> impl Fn(u64) -> f32 {
+        move |ms| peak * (-(ms as f32) / time_constant_ms).exp()
+    }
+
+    #[test]
+    fn test_tracker_sustains_decaying_note() {
+        let settings = AnalysisSettings::default();
+        let start = Instant::now();
+        let mut tracker = PitchTracker::default();
+        let gate = settings.min_magnitude_threshold;
+        let envelope = decay(gate * 8.0, 600.0);
+
+        // The note locks while loud, then keeps tracking as it decays below the
+        // acquire gate, until it sinks under the sustain gate.
+        let mut last_tracking = None;
+        for ms in (0..4000).step_by(10) {
+            let magnitude = envelope(ms);
+            let output = tracker.feed(
+                estimate(110.0, magnitude),
+                start + Duration::from_millis(ms),
+                &settings,
+            );
+            if output == TrackerOutput::Tracking {
+                last_tracking = Some((ms, magnitude));
+            }
+        }
+        let (last_ms, last_magnitude) = last_tracking.expect("decaying note never locked");
+        assert!(last_magnitude < gate * 0.3, "stopped at {}", last_magnitude);
+        assert!(last_magnitude >= gate * settings.sustain_fraction);
+
+        // Once below the sustain gate the note is held, then blanked.
+        let quiet_hold = settings.quiet_hold.as_millis() as u64;
+        assert!(last_ms + quiet_hold < 4000);
+        assert_eq!(tracker.reading().note, None);
+    }
+
+    #[test]
+    fn test_tracker_holds_decayed_note_for_quiet_hold() {
+        let settings = AnalysisSettings::default();
+        let start = Instant::now();
+        let mut tracker = PitchTracker::default();
+        let gate = settings.min_magnitude_threshold;
+        let envelope = decay(gate * 8.0, 300.0);
+        let below_sustain = (0..)
+            .step_by(10)
+            .find(|&ms| envelope(ms) < gate * settings.sustain_fraction)
+            .unwrap();
+
+        let names: Vec<_> = (0..below_sustain + 3000)
+            .step_by(10)
+            .map(|ms| {
+                tracker.feed(
+                    estimate(82.41, envelope(ms)),
+                    start + Duration::from_millis(ms),
+                    &settings,
+                );
+                (ms, note_name(&tracker))
+            })
+            .collect();
+
+        let quiet_hold = settings.quiet_hold.as_millis() as u64;
+        let shown = |ms: u64| names.iter().find(|&&(at, _)| at == ms).unwrap().1.clone();
+        assert_eq!(shown(below_sustain - 10).as_deref(), Some("E2"));
+        assert_eq!(
+            shown(below_sustain + quiet_hold - 100).as_deref(),
+            Some("E2")
+        );
+        assert_eq!(shown(below_sustain + quiet_hold + 100), None);
+    }
+
+    #[test]
+    fn test_tracker_needs_full_gate_to_acquire() {
+        let settings = AnalysisSettings::default();
+        let start = Instant::now();
+        let mut tracker = PitchTracker::default();
+
+        // Above the sustain gate but below the acquire gate: never locks.
+        let magnitude = settings.min_magnitude_threshold * 0.6;
+        for ms in (0..500).step_by(10) {
+            let output = tracker.feed(
+                estimate(440.0, magnitude),
+                start + Duration::from_millis(ms),
+                &settings,
+            );
+            assert_eq!(output, TrackerOutput::Gated);
+        }
+        assert_eq!(tracker.reading().note, None);
+    }
+
+    #[test]
+    fn test_tracker_gates_low_confidence() {
+        let settings = AnalysisSettings::default();
+        let start = Instant::now();
+        let mut tracker = PitchTracker::default();
+
+        let noisy = PitchEstimate {
+            confidence: settings.min_confidence / 2.0,
+            ..estimate(440.0, 1.0)
+        };
+        for ms in (0..200).step_by(10) {
+            let output = tracker.feed(noisy, start + Duration::from_millis(ms), &settings);
+            assert_eq!(output, TrackerOutput::Gated);
+        }
+        assert_eq!(tracker.reading().note, None);
+        assert_eq!(tracker.reading().confidence, noisy.confidence);
+    }
+
+    #[test]
+    fn test_reading_counts_detections_and_keeps_the_raw_deviation() {
+        let settings = AnalysisSettings::default();
+        let start = Instant::now();
+        let mut tracker = PitchTracker::default();
+        let quiet = settings.min_magnitude_threshold / 2.0;
+
+        for ms in (0..50).step_by(10) {
+            tracker.feed(
+                estimate(440.0, quiet),
+                start + Duration::from_millis(ms),
+                &settings,
+            );
+        }
+        assert_eq!(tracker.reading().detections, 5);
+        assert_eq!(tracker.reading().raw_cents, None);
+
+        // Alternating detections either side of A4 stay within them once
+        // windowed and smoothed, while the raw deviation is the latest one's.
+        for (index, ms) in (50..400).step_by(10).enumerate() {
+            let frequency = if index % 2 == 0 { 439.0 } else { 441.0 };
+            tracker.feed(
+                estimate(frequency, 1.0),
+                start + Duration::from_millis(ms),
+                &settings,
+            );
+        }
+        let reading = tracker.reading();
+        assert_eq!(reading.detections, 40);
+        let raw = reading.raw_cents.expect("no raw deviation");
+        assert!(
+            (raw - 1200.0 * (439.0f32 / 440.0).log2()).abs() < 0.01,
+            "{}",
+            raw
+        );
+        assert!(reading.note.unwrap().cents_off.abs() < 4.0);
+
+        // Resetting forgets the deviation but keeps counting.
+        tracker.timeout(start + Duration::from_secs(10), &settings);
+        assert_eq!(tracker.reading().raw_cents, None);
+        assert_eq!(tracker.reading().detections, 40);
+    }
+
+    #[test]
+    fn test_standard_deviation_of_scripted_sequences() {
+        assert_eq!(standard_deviation(std::iter::empty()), None);
+        assert_eq!(standard_deviation([4.0; 10].into_iter()), Some(0.0));
+        let spread = [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0];
+        assert_eq!(standard_deviation(spread.into_iter()), Some(2.0));
+        let alternating = (0..20).map(|index| if index % 2 == 0 { -3.0 } else { 3.0 });
+        assert_eq!(standard_deviation(alternating), Some(3.0));
+    }
+
+    #[test]
+    fn test_cents_deviation_covers_the_last_second() {
+        let settings = AnalysisSettings::default();
+        let start = Instant::now();
+        let mut tracker = PitchTracker::default();
+        let detuned = |cents: f32| 440.0 * 2.0f32.powf(cents / 1200.0);
+        let deviation = |tracker: &PitchTracker| tracker.reading().cents_deviation.unwrap();
+
+        assert_eq!(tracker.reading().cents_deviation, None);
+        feed_script(&mut tracker, &settings, start, 0..1000, |_| 440.0);
+        assert!(deviation(&tracker) < 0.01);
+
+        // Wavering ±3 cents: once it fills the window, that's the deviation.
+        feed_script(&mut tracker, &settings, start, 1000..2500, |ms| {
+            detuned(if ms % 20 == 0 { -3.0 } else { 3.0 })
+        });
+        assert!(
+            (deviation(&tracker) - 3.0).abs() < 0.05,
+            "{}",
+            deviation(&tracker)
+        );
+
+        // Half a second steady again is still part of the window...
+        feed_script(&mut tracker, &settings, start, 2500..3000, |_| 440.0);
+        let partial = deviation(&tracker);
+        assert!(partial > 1.0 && partial < 3.0, "{}", partial);
+
+        // ...but the wavering is forgotten a second later.
+        feed_script(&mut tracker, &settings, start, 3000..3600, |_| 440.0);
+        assert!(deviation(&tracker) < 0.01);
+
+        tracker.timeout(start + Duration::from_secs(10), &settings);
+        assert_eq!(tracker.reading().cents_deviation, None);
+    }
+
+    #[test]
+    fn test_tracker_script_sudden_note_change() {
+        let settings = AnalysisSettings::default();
+        let start = Instant::now();
+        let mut tracker = PitchTracker::default();
+        let change_at = 500;
+
+        let script = |ms: u64| if ms < change_at { 329.63 } else { 440.0 };
+        let names: Vec<_> = (0..1000)
+            .step_by(10)
+            .map(|ms| {
+                feed_script(&mut tracker, &settings, start, ms..ms + 1, script);
+                (ms, note_name(&tracker))
+            })
+            .collect();
+
+        // The old note holds while the window is mixed, then switches straight over.
+        let first_a4 = names
+            .iter()
+            .find(|(_, name)| name.as_deref() == Some("A4"))
+            .map(|&(ms, _)| ms)
+            .expect("never switched to A4");
+        let window = settings.pitch_window.as_millis() as u64;
+        assert!(
+            first_a4 >= change_at + window && first_a4 <= change_at + window + 50,
+            "switched at {} ms",
+            first_a4
+        );
+        assert!(names
+            .iter()
+            .filter(|&&(ms, _)| ms >= 50 && ms < first_a4)
+            .all(|(_, name)| name.as_deref() == Some("E4")));
+
+        let cents = tracker.reading().note.unwrap().cents_off;
+        assert!(cents.abs() < 0.5, "A4 settled at {} cents", cents);
+    }
+
+    #[test]
+    fn test_tracker_hysteresis_changes_name_once_across_boundary() {
+        let settings = AnalysisSettings::default();
+        let start = Instant::now();
+        let mut tracker = PitchTracker::default();
+        let e2 = midi_to_frequency(40, DEFAULT_A4_FREQUENCY);
+
+        // Sweep from 20 to 90 cents above E2 over three seconds with a few
+        // cents of deterministic jitter, so the median straddles the midpoint.
+        let mut seed = 12345u32;
+        let mut names = Vec::new();
+        let mut max_cents: f32 = 0.0;
+        for ms in (0..3000u64).step_by(10) {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            let jitter = (seed >> 16) as f32 / 65536.0 * 8.0 - 4.0;
+            let cents = 20.0 + 70.0 * ms as f32 / 3000.0 + jitter;
+            let frequency = e2 * 2.0f32.powf(cents / 1200.0);
+
+            tracker.feed(
+                estimate(frequency, 1.0),
+                start + Duration::from_millis(ms),
+                &settings,
+            );
+            if let Some(note) = &tracker.current_note {
+                if note.to_string() == "E2" {
+                    max_cents = max_cents.max(note.cents_off);
+                }
+                if names.last() != Some(&note.to_string()) {
+                    names.push(note.to_string());
+                }
+            }
+        }
+
+        assert_eq!(names, ["E2", "F2"]);
+        assert!(max_cents > 50.0, "E2 was never held past 50 cents");
+    }
+
+    /// Plucks A4 10 cents sharp with a noisy attack while a flat A4 is still
+    /// ringing, runs it through the detector, onset detector and tracker as
+    /// the app does, and returns how long after the pluck the reading moved to
+    /// the new pitch, what it first read, and what it settled on.
+    fn repluck(settings: &AnalysisSettings) -> (u64, f32, f32) {
+        let sample_rate = 48000.0;
+        let hop = 480;
+        let size = settings.fft_size;
+        let mut detector = PitchDetector::new(size, sample_rate, settings.window_kind);
+        let (min_frequency, max_frequency) = settings.frequency_range;
+        detector.set_frequency_range(min_frequency, max_frequency);
+        let mut onsets = OnsetDetector::new(sample_rate);
+        let tau = std::f32::consts::TAU;
+        let flat_a4 = 440.0 * 2.0f32.powf(-20.0 / 1200.0);
+        let sharp_a4 = 440.0 * 2.0f32.powf(10.0 / 1200.0);
+
+        let pluck = sample_rate as usize;
+        let mut seed = 12345u32;
+        let samples: Vec<f32> = (0..2 * pluck)
+            .map(|index| {
+                let t = index as f32 / sample_rate;
+                let Some(since) = index.checked_sub(pluck) else {
+                    return 0.3 * (-t / 0.8).exp() * (tau * flat_a4 * t).sin();
+                };
+                let t = since as f32 / sample_rate;
+                seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+                let noise = (seed >> 8) as f32 / (1 << 24) as f32 * 2.0 - 1.0;
+                let burst = 0.3 * (1.0 - t / 0.04).max(0.0);
+                0.3 * (tau * sharp_a4 * t).sin() + burst * noise
+            })
+            .collect();
+
+        let start = Instant::now();
+        let mut tracker = PitchTracker::default();
+        let mut moved = None;
+        for end in (size..=samples.len()).step_by(hop) {
+            let now = start + Duration::from_secs_f32(end as f32 / sample_rate);
+            let block = &samples[end - size..end];
+            if onsets.detect(block) {
+                tracker.onset(now, settings);
+            }
+            match detector.detect_pitch(block) {
+                Some(estimate) => {
+                    tracker.feed(estimate, now, settings);
+                }
+                None => tracker.timeout(now, settings),
+            }
+            let cents = tracker.reading().note.map_or(0.0, |note| note.cents_off);
+            if end > pluck && cents > 0.0 && moved.is_none() {
+                moved = Some(((end - pluck) as u64 * 1000 / sample_rate as u64, cents));
+            }
+        }
+        let (lock_ms, first) = moved.expect("the reading never moved to the new pitch");
+        (lock_ms, first, tracker.reading().note.unwrap().cents_off)
+    }
+
+    #[test]
+    fn test_attack_skip_locks_sooner_and_closer() {
+        for fft_size in [4096, 8192] {
+            let settings = AnalysisSettings {
+                fft_size,
+                // Searched over a guitar's range, as with its preset selected.
+                frequency_range: InstrumentPreset::from_midi("Guitar", &[40, 45, 50, 55, 59, 64])
+                    .frequency_range(),
+                ..AnalysisSettings::default()
+            };
+            let (skipped_ms, skipped_first, settled) = repluck(&settings);
+            let (unskipped_ms, unskipped_first, _) = repluck(&AnalysisSettings {
+                attack_skip: Duration::ZERO,
+                ..settings
+            });
+
+            // Without the skip the old note's detections hold the reading
+            // until they leave the pitch window.
+            assert!(
+                skipped_ms + 50 <= unskipped_ms,
+                "{} ms with the skip, {} ms without",
+                skipped_ms,
+                unskipped_ms
+            );
+            assert!(
+                (skipped_first - settled).abs() < 1.0,
+                "first read {} cents, settled on {}",
+                skipped_first,
+                settled
+            );
+            assert!((unskipped_first - settled).abs() < 1.0);
+        }
+    }
+
+    #[test]
+    fn test_dropped_note_resumes_at_once_within_the_grace() {
+        let sharp_a4 = 440.0 * 2.0f32.powf(6.0 / 1200.0);
+        let at = |start: Instant, ms: u64| start + Duration::from_millis(ms);
+        // Locks onto `frequency`, loses it to 300 ms of silence, then plays
+        // `again` and returns how long that took to track and the deviation
+        // it started from.
+        let relock = |relock_grace: Duration, again: f32| {
+            let settings = AnalysisSettings {
+                silence_hold: Duration::from_millis(100),
+                relock_grace,
+                ..AnalysisSettings::default()
+            };
+            let start = Instant::now();
+            let mut tracker = PitchTracker::default();
+            for ms in (0..600).step_by(20) {
+                tracker.feed(estimate(sharp_a4, 1.0), at(start, ms), &settings);
+            }
+            assert!(tracker.reading().note.is_some());
+            for ms in (620..=900).step_by(20) {
+                tracker.timeout(at(start, ms), &settings);
+            }
+            assert!(tracker.reading().note.is_none());
+
+            (1000..2000)
+                .step_by(20)
+                .find(|&ms| {
+                    tracker.feed(estimate(again, 1.0), at(start, ms), &settings)
+                        == TrackerOutput::Tracking
+                })
+                .map(|ms| (ms - 1000, tracker.reading().smoothed_cents))
+                .expect("never relocked")
+        };
+
+        let (resumed_after, resumed_cents) = relock(Duration::from_millis(1500), sharp_a4);
+        assert_eq!(resumed_after, 0);
+        assert!((resumed_cents - 6.0).abs() < 0.5, "{}", resumed_cents);
+
+        // Without the grace, or once it has run out, the windows fill afresh.
+        let (fresh_after, _) = relock(Duration::ZERO, sharp_a4);
+        assert!(fresh_after >= 40, "{} ms", fresh_after);
+        let (expired_after, _) = relock(Duration::from_millis(100), sharp_a4);
+        assert_eq!(expired_after, fresh_after);
+
+        // A different note doesn't pick up the old one's deviation.
+        let (other_after, _) = relock(Duration::from_millis(1500), 493.88);
+        assert_eq!(other_after, fresh_after);
+    }
+}
@@ -0,0 +1,207 @@
+//! Tray / menu bar icon with quick controls
+//!
+//! Clicking the icon shows or hides the window; its menu picks the input
+//! device, enters or leaves overlay mode and quits. tray-icon delivers clicks
+//! and menu picks on its own handlers, which forward them as [`TrayCommand`]s
+//! over a channel the app polls each frame, waking the window so that happens
+//! even while it is hidden.
+
+use std::sync::mpsc::{self, Receiver};
+
+use anyhow::Result;
+use eframe::egui;
+use image::RgbaImage;
+use tray_icon::menu::{CheckMenuItem, Menu, MenuEvent, MenuItem, PredefinedMenuItem, Submenu};
+use tray_icon::{Icon, MouseButton, MouseButtonState, TrayIcon, TrayIconBuilder, TrayIconEvent};
+
+use crate::audio::device_label;
+
+const ICON_PNG: &[u8] = include_bytes!("../assets/icons/icon.png");
+const ICON_SIZE: u32 = 32;
+const TOGGLE_ID: &str = "toggle";
+const OVERLAY_ID: &str = "overlay";
+const QUIT_ID: &str = "quit";
+const DEVICE_ID_PREFIX: &str = "device:";
+/// Pixels of the app icon darker than this are background, not the fork.
+const TEMPLATE_THRESHOLD: u8 = 64;
+
+/// What the user asked for from the tray.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TrayCommand {
+    /// Show the window if it is hidden, hide it otherwise.
+    ToggleWindow,
+    /// Enter or leave overlay mode, whose window may not take clicks.
+    ToggleOverlay,
+    /// Switch to the input device of this name.
+    SelectDevice(String),
+    /// Close the window for good, even when closing normally goes to the tray.
+    Quit,
+}
+
+/// The command behind a menu item's id.
+fn command_for(menu_id: &str) -> Option<TrayCommand> {
+    match menu_id {
+        TOGGLE_ID => Some(TrayCommand::ToggleWindow),
+        OVERLAY_ID => Some(TrayCommand::ToggleOverlay),
+        QUIT_ID => Some(TrayCommand::Quit),
+        _ => menu_id
+            .strip_prefix(DEVICE_ID_PREFIX)
+            .map(|name| TrayCommand::SelectDevice(name.to_string())),
+    }
+}
+
+/// A monochrome silhouette of the tuning fork in `icon`, for the macOS menu
+/// bar: black, with the fork's brightness as its alpha so the dark background
+/// drops out.
+fn template_icon(icon: &RgbaImage) -> RgbaImage {
+    let mut template = icon.clone();
+    for pixel in template.pixels_mut() {
+        let [red, green, blue, alpha] = pixel.0;
+        let brightness = red.min(green).min(blue).saturating_sub(TEMPLATE_THRESHOLD);
+        let coverage = brightness as f32 / (255 - TEMPLATE_THRESHOLD) as f32;
+        pixel.0 = [0, 0, 0, (alpha as f32 * coverage).round() as u8];
+    }
+    template
+}
+
+fn tray_icon_image() -> Result<Icon> {
+    let icon = image::load_from_memory(ICON_PNG)?.to_rgba8();
+    let icon = if cfg!(target_os = "macos") {
+        template_icon(&icon)
+    } else {
+        icon
+    };
+    let icon = image::imageops::resize(
+        &icon,
+        ICON_SIZE,
+        ICON_SIZE,
+        image::imageops::FilterType::Lanczos3,
+    );
+    Ok(Icon::from_rgba(icon.into_raw(), ICON_SIZE, ICON_SIZE)?)
+}
+
+/// The tray icon and its menu, alive for as long as this is.
+pub struct Tray {
+    _icon: TrayIcon,
+    device_menu: Submenu,
+    /// Device names and the current device as the menu lists them.
+    shown_devices: (Vec<String>, String),
+    commands: Receiver<TrayCommand>,
+}
+
+impl Tray {
+    /// Adds the icon to the tray. On macOS this must run on the main thread
+    /// once the event loop is running, as the eframe app creator does.
+    pub fn new(ctx: &egui::Context) -> Result<Self> {
+        let device_menu = Submenu::new("Input device", true);
+        let menu = Menu::new();
+        menu.append(&MenuItem::with_id(TOGGLE_ID, "Show/Hide", true, None))?;
+        menu.append(&MenuItem::with_id(OVERLAY_ID, "Overlay mode", true, None))?;
+        menu.append(&device_menu)?;
+        menu.append(&PredefinedMenuItem::separator())?;
+        menu.append(&MenuItem::with_id(QUIT_ID, "Quit", true, None))?;
+
+        let image = tray_icon_image()?;
+        #[cfg(target_os = "macos")]
+        let builder = TrayIconBuilder::new().with_icon_templated(image);
+        #[cfg(not(target_os = "macos"))]
+        let builder = TrayIconBuilder::new().with_icon(image);
+        let icon = builder
+            .with_tooltip("Chroma Tuner")
+            .with_menu(Box::new(menu))
+            .with_menu_on_left_click(false)
+            .build()?;
+
+        let (sender, commands) = mpsc::channel();
+        {
+            let sender = sender.clone();
+            let ctx = ctx.clone();
+            TrayIconEvent::set_event_handler(Some(move |event| {
+                if let TrayIconEvent::Click {
+                    button: MouseButton::Left,
+                    button_state: MouseButtonState::Up,
+                    ..
+                } = event
+                {
+                    let _ = sender.send(TrayCommand::ToggleWindow);
+                    ctx.request_repaint();
+                }
+            }));
+        }
+        {
+            let ctx = ctx.clone();
+            MenuEvent::set_event_handler(Some(move |event: MenuEvent| {
+                if let Some(command) = command_for(event.id().as_ref()) {
+                    let _ = sender.send(command);
+                    ctx.request_repaint();
+                }
+            }));
+        }
+
+        Ok(Self {
+            _icon: icon,
+            device_menu,
+            shown_devices: (Vec::new(), String::new()),
+            commands,
+        })
+    }
+
+    /// The next command from the tray, if any.
+    pub fn try_recv(&self) -> Option<TrayCommand> {
+        self.commands.try_recv().ok()
+    }
+
+    /// Lists `devices` in the device menu with `current` checked, rebuilding
+    /// it only if either changed since the last call.
+    pub fn show_devices(&mut self, devices: &[String], current: &str) {
+        if self.shown_devices.0 == devices && self.shown_devices.1 == current {
+            return;
+        }
+        while self.device_menu.remove_at(0).is_some() {}
+        for device in devices {
+            let item = CheckMenuItem::with_id(
+                format!("{}{}", DEVICE_ID_PREFIX, device),
+                device_label(device),
+                true,
+                device == current,
+                None,
+            );
+            if let Err(err) = self.device_menu.append(&item) {
+                log::warn!("Failed to list '{}' in the tray menu: {}", device, err);
+            }
+        }
+        self.shown_devices = (devices.to_vec(), current.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_menu_ids_map_to_commands() {
+        assert_eq!(command_for(TOGGLE_ID), Some(TrayCommand::ToggleWindow));
+        assert_eq!(command_for(OVERLAY_ID), Some(TrayCommand::ToggleOverlay));
+        assert_eq!(command_for(QUIT_ID), Some(TrayCommand::Quit));
+        assert_eq!(
+            command_for("device:MacBook Pro Microphone: Built-in"),
+            Some(TrayCommand::SelectDevice(
+                "MacBook Pro Microphone: Built-in".to_string()
+            ))
+        );
+        assert_eq!(command_for("something else"), None);
+    }
+
+    #[test]
+    fn test_template_icon_keeps_only_the_fork() {
+        let icon = image::load_from_memory(ICON_PNG).unwrap().to_rgba8();
+        let template = template_icon(&icon);
+        let (width, height) = template.dimensions();
+        assert!(template.pixels().all(|pixel| pixel.0[..3] == [0, 0, 0]));
+        // The transparent corner, the blue background beside the stem, and the
+        // stem itself.
+        assert_eq!(template.get_pixel(0, 0).0[3], 0);
+        assert_eq!(template.get_pixel(width / 4, height * 3 / 5).0[3], 0);
+        assert!(template.get_pixel(width / 2, height * 3 / 5).0[3] > 200);
+    }
+}
@@ -1,420 +1,9155 @@
 //! Main tuner application and GUI
 //!
-//! Contains the primary TunerApp struct with pitch detection logic, signal filtering,
-//! and the complete user interface including the tuning display and device selector.
+//! Contains the primary TunerApp struct, the background PitchWorker that runs pitch
+//! detection and signal filtering off the UI thread, and the complete user interface
+//! including the tuning display and device selector.
 
 use eframe::egui;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
 use std::sync::{Arc, Mutex};
-use std::time::Instant;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime};
 
-use crate::audio::{get_default_input_device_name, get_input_devices, AudioCapture, AudioData};
-use crate::pitch::{frequency_to_note, Note, PitchDetector};
+use crate::audio::{
+    db_to_gain, device_label, device_list_changed, find_input_device,
+    get_default_input_device_name, scan_input_devices, AudioCapture, AudioData, AudioSource,
+    ChannelSelection, DeviceMonitor, FileSource, InputLevel, MetronomePlayer, Recorder, TonePlayer,
+    Waveform, BUFFER_FRAME_OPTIONS, MAX_INPUT_GAIN_DB, MIN_INPUT_GAIN_DB,
+};
+use crate::config::{
+    ColorScheme, DeviationDisplay, DisplayMode, HistoryScale, NeedleRange, NeedleResponse,
+    NeedleStyle, OverlayBackground, TunerConfig, UiTheme,
+};
+use crate::detection_log::{export_log, DetectionLog, ExportFormat, LoggedDetection};
+use crate::hotkey::{parse_shortcut, GlobalShortcut};
+use crate::instance::RaiseRequests;
+use crate::metronome::{Beat, MAX_BEATS_PER_BAR, MAX_BPM, MIN_BPM};
+use crate::midi::{self, MidiSender};
+use crate::midi_file::PPQ_OPTIONS;
+use crate::offline::{self, PitchCurve};
+use crate::osc::OscSender;
+use crate::palette::Palette;
+use crate::pitch::{
+    beat_frequency, estimate_inharmonicity, frequency_to_tempered_note, midi_to_frequency,
+    midi_to_note, partial_beat_frequency, tempered_frequency, FrequencyPrior, HumRejection,
+    Interval, Key, KeyMode, LowRange, Note, NoteNaming, OnsetDetector, PitchClassSet,
+    PitchDetector, Precision, Temperament, WindowKind, CHROMATIC_RANGE, DECIMATION,
+    DEFAULT_A4_FREQUENCY, EDO_RANGE, FFT_SIZES, HOP_SIZES, MAX_DETECTABLE_FREQUENCY,
+    MIN_DETECTABLE_FREQUENCY, PIANO_PARTIALS,
+};
+use crate::presets::{
+    load_user_presets, save_user_presets, user_presets_path, InstrumentPreset, StringTuning,
+    UserPreset, MAX_TRANSPOSE, MIN_TRANSPOSE,
+};
+use crate::scala::ScalaScale;
+use crate::self_test::{run_self_test, SelfTestReport, SELF_TEST_TOLERANCE_CENTS};
+use crate::stats::{NoteKey, SessionStats};
+use crate::theme::Theme;
+use crate::tracker::{same_target, AnalysisSettings, PitchReading, PitchTracker};
+use crate::tray::{Tray, TrayCommand};
+use crate::websocket::WebSocketServer;
 
-pub struct TunerApp {
-    audio_data: Arc<Mutex<AudioData>>,
-    pitch_detector: PitchDetector,
-    current_note: Option<Note>,
-    last_update: Instant,
-    frequency_history: Vec<f32>,
-    magnitude_history: Vec<f32>,
-    max_history: usize,
-    stability_threshold: f32,
-    min_magnitude_threshold: f32,
-    available_devices: Vec<(String, cpal::Device)>,
-    current_device_name: String,
-    audio_capture: Option<AudioCapture>,
-    smoothed_cents: f32,
-    cents_history: Vec<f32>,
-    max_cents_history: usize,
-    last_device_refresh: std::time::Instant,
-    device_refresh_interval: std::time::Duration,
+/// Default size of the window with every panel closed, which is also the
+/// smallest it can be resized to.
+pub const WINDOW_SIZE: egui::Vec2 = egui::vec2(320.0, 180.0);
+/// The mini strip, just big enough for the note and a deviation bar.
+const MINI_WINDOW_SIZE: egui::Vec2 = egui::vec2(200.0, 60.0);
+/// Performance mode opens this large; it can be maximized from there.
+const PERFORMANCE_WINDOW_SIZE: egui::Vec2 = egui::vec2(960.0, 600.0);
+/// Room the tuner display is laid out in at the default window size; larger
+/// windows scale it up in proportion.
+const DISPLAY_BASE_SIZE: egui::Vec2 = egui::vec2(320.0, 130.0);
+/// How long a requested window size may take to arrive before the window's
+/// actual size is trusted again.
+const RESIZE_SETTLE_TIME: Duration = Duration::from_secs(1);
+const SETTINGS_PANEL_HEIGHT: f32 = 240.0;
+const HISTORY_PANEL_HEIGHT: f32 = 110.0;
+const STATS_PANEL_HEIGHT: f32 = 196.0;
+const STRUM_PANEL_HEIGHT: f32 = 90.0;
+/// The strum bars reach their ends at this many cents.
+const STRUM_CENTS_RANGE: f32 = 50.0;
+/// Most notes picked out at once when identifying intervals.
+const MAX_INTERVAL_NOTES: usize = 3;
+/// The history keeps at most this much pitch, and never more than
+/// `MAX_HISTORY_POINTS` readings, whatever the graph is showing.
+const MAX_HISTORY_SPAN: Duration = Duration::from_secs(30);
+const MAX_HISTORY_POINTS: usize = 8192;
+/// Readings further apart than this are drawn as a dropout.
+const HISTORY_GAP: Duration = Duration::from_millis(250);
+const HISTORY_CENTS_RANGE: f32 = 50.0;
+/// Holding a reading also shows how far the note wavered over this long.
+const HOLD_RANGE_WINDOW: Duration = Duration::from_secs(2);
+/// Octaves the intonation keyboard spans.
+const KEYBOARD_OCTAVES: i32 = 3;
+/// Octaves the intonation keyboard can start at.
+const KEYBOARD_START_OCTAVES: std::ops::RangeInclusive<i32> = 0..=6;
+const KEYBOARD_HEIGHT: f32 = 40.0;
+/// Shifting the octave searches this far either side of the asserted note...
+const OCTAVE_SHIFT_RANGE_CENTS: f32 = 600.0;
+/// ...and lets go once the note has settled there and then moves this far.
+const OCTAVE_SHIFT_RELEASE_CENTS: f32 = 300.0;
+/// The sparkline under the needle shows this much of the note's cents...
+const SPARKLINE_SPAN: Duration = Duration::from_secs(4);
+/// ...reaching its edges at this many cents either side.
+const SPARKLINE_CENTS_RANGE: f32 = 10.0;
+/// Beats slower than this many per second are too slow to hear...
+const MIN_BEAT_HZ: f32 = 0.2;
+/// ...and faster than this blur into roughness that can't be counted.
+const MAX_BEAT_HZ: f32 = 20.0;
+/// Guided tuning ticks a string off once it has stayed in tune this long.
+const GUIDED_HOLD_TIME: Duration = Duration::from_secs(1);
+/// How long a ticked-off string shows its check mark before the next one.
+const GUIDED_CHECK_TIME: Duration = Duration::from_millis(800);
+/// Targets in one round of the challenge.
+const CHALLENGE_ROUND_NOTES: usize = 10;
+/// How close in cents a challenge target must be played.
+const CHALLENGE_TOLERANCE_CENTS: f32 = 15.0;
+/// How long a challenge target must be held within the tolerance.
+const CHALLENGE_HOLD_TIME: Duration = Duration::from_secs(1);
+/// How long each challenge target has before it counts as missed.
+const CHALLENGE_TIME_LIMIT: Duration = Duration::from_secs(10);
+/// Most points one target can score, half for speed and half for accuracy.
+const CHALLENGE_MAX_POINTS: u32 = 100;
+/// How long a finished target's result shows before the next one.
+const CHALLENGE_RESULT_TIME: Duration = Duration::from_millis(800);
+/// Challenge scores kept in the config.
+const MAX_HIGH_SCORES: usize = 5;
+const WORKER_POLL_INTERVAL: Duration = Duration::from_millis(5);
+/// Redraw interval while a note is detected or the input is loud enough to
+/// move the level meter visibly.
+const ACTIVE_REPAINT_INTERVAL: Duration = Duration::from_millis(16);
+/// Redraw interval otherwise, which keeps an idle tuner from using a core just
+/// to draw the same frame. The worker wakes the window as soon as a note starts.
+const IDLE_REPAINT_INTERVAL: Duration = Duration::from_millis(150);
+/// Input above -40 dBFS RMS redraws at the active rate; a quiet room's noise
+/// floor doesn't.
+const ACTIVE_RMS_THRESHOLD: f32 = 0.01;
+const SPECTRUM_COLUMN_WIDTH: f32 = 2.0;
+const SPECTRUM_FLOOR_DB: f32 = 60.0;
+const LEVEL_FLOOR_DB: f32 = 60.0;
+// The FFT magnitude gate sits far below any real microphone noise floor, so the
+// hint uses -60 dBFS RMS as the practical "too quiet to detect" level.
+const QUIET_RMS_THRESHOLD: f32 = 0.001;
+const QUIET_HINT_DELAY: Duration = Duration::from_secs(2);
+const CLIP_WINDOW: Duration = Duration::from_secs(1);
+const CLIP_REPEAT_COUNT: usize = 3;
+const CLIP_HOLD: Duration = Duration::from_secs(2);
+const DEVICE_REFRESH_INTERVAL: Duration = Duration::from_secs(2);
+/// Following the system default rebuilds the stream at most this often, so a
+/// Bluetooth headset flapping between connected and not doesn't thrash it.
+const DEFAULT_SWITCH_COOLDOWN: Duration = Duration::from_secs(3);
+const STREAM_STALL_TIMEOUT: Duration = Duration::from_secs(3);
+const CALIBRATION_DURATION: Duration = Duration::from_secs(2);
+/// The calibrated gate sits this far above the loudest ambient detection.
+const CALIBRATION_MARGIN: f32 = 1.5;
+/// The status banner's fill is saturated in every theme.
+const BANNER_TEXT: egui::Color32 = egui::Color32::WHITE;
+/// The needle stops moving once this close to where it is going.
+const NEEDLE_SETTLED_CENTS: f32 = 0.05;
+/// Longest frame step the needle takes, so it doesn't jump after a stall.
+const MAX_NEEDLE_STEP: f32 = 0.1;
+/// Auto zoom narrows the needle's scale to this many cents once the note
+/// has stayed inside it for `NEEDLE_ZOOM_DELAY`.
+const NEEDLE_ZOOM_CENTS: f32 = 15.0;
+const NEEDLE_ZOOM_DELAY: Duration = Duration::from_secs(1);
+/// Time constant of the scale easing to a new range.
+const NEEDLE_ZOOM_TIME: f32 = 0.15;
+/// The gauge pivots about a point this far below the top of its arc, out of
+/// sight like a VU meter's, so that a shallow arc fits where the bar goes.
+const GAUGE_RADIUS: f32 = 160.0;
+/// Angle either side of upright at which the gauge reaches the ends of its
+/// scale.
+const GAUGE_HALF_ANGLE: f32 = std::f32::consts::PI / 6.0;
+/// After the in-tune cue, it waits at least this long before firing again,
+/// even if the note wobbles out of tune and back.
+const IN_TUNE_CUE_COOLDOWN: Duration = Duration::from_secs(3);
+/// How long the in-tune flash takes to fade, and how strongly it starts.
+const IN_TUNE_FLASH_TIME: Duration = Duration::from_millis(300);
+const IN_TUNE_FLASH_ALPHA: f32 = 90.0;
+/// How long the metronome's beat dot takes to fade after each click.
+const METRONOME_PULSE_TIME: Duration = Duration::from_millis(150);
+/// How long detections are counted for each detections-per-second figure.
+const DETECTION_RATE_WINDOW: Duration = Duration::from_secs(1);
+/// How often the diagnostics overlay refreshes while nothing else moves.
+const DIAGNOSTICS_REFRESH: Duration = Duration::from_millis(250);
+/// Deviation at which the tune up / down arrow is drawn at full size.
+const DIRECTION_FULL_CENTS: f32 = 50.0;
+/// The in-tune beep: short, soft and high enough to hear over the instrument.
+const IN_TUNE_BEEP_FREQUENCY: f32 = 1760.0;
+const IN_TUNE_BEEP_DURATION: Duration = Duration::from_millis(120);
+/// The close threshold stays at least this far above the in-tune tolerance.
+const MIN_CLOSE_BAND_CENTS: f32 = 1.0;
+const MIN_MAGNITUDE_THRESHOLD: f32 = 0.01;
+/// The shortest stretch of a recording the analysis view zooms in to.
+const MIN_ANALYSIS_SPAN: f32 = 0.05;
+const MAX_MAGNITUDE_THRESHOLD: f32 = 100.0;
+
+#[derive(Debug, Default)]
+pub(crate) struct SpectrumSnapshot {
+    pub magnitudes: Vec<f32>,
+    pub min_frequency: f32,
+    pub bin_width: f32,
+    pub peak_frequency: Option<f32>,
 }
 
-impl TunerApp {
-    pub fn new(audio_data: Arc<Mutex<AudioData>>) -> Self {
-        let buffer_size = 8192;
+impl SpectrumSnapshot {
+    pub fn copy_from(&mut self, other: &SpectrumSnapshot) {
+        self.magnitudes.clear();
+        self.magnitudes.extend_from_slice(&other.magnitudes);
+        self.min_frequency = other.min_frequency;
+        self.bin_width = other.bin_width;
+        self.peak_frequency = other.peak_frequency;
+    }
 
-        let sample_rate = if let Ok(audio_data) = audio_data.lock() {
-            audio_data.sample_rate
-        } else {
-            44100.0
-        };
+    pub fn max_frequency(&self) -> f32 {
+        self.min_frequency + self.magnitudes.len().saturating_sub(1) as f32 * self.bin_width
+    }
+}
 
-        let available_devices = get_input_devices().unwrap_or_default();
-        let current_device_name =
-            get_default_input_device_name().unwrap_or_else(|_| "Default".to_string());
+struct PitchAnalyzer {
+    pitch_detector: PitchDetector,
+    onset_detector: OnsetDetector,
+    sample_buffer: Vec<f32>,
+    spectrum: SpectrumSnapshot,
+    /// Deviation in cents of each preset string, lowest first, when strumming.
+    strum: Vec<Option<f32>>,
+    /// Frequency and magnitude of each note sounding, strongest first, when
+    /// identifying intervals.
+    peaks: Vec<(f32, f32)>,
+    /// Partials of the latest detection, from the fundamental up, in piano
+    /// mode.
+    partials: Vec<Option<f32>>,
+    /// `AudioData::total_samples_written` when the window was last read.
+    last_read: u64,
+    tracker: PitchTracker,
+}
 
+impl PitchAnalyzer {
+    fn new(buffer_size: usize, sample_rate: f32, window_kind: WindowKind) -> Self {
         Self {
-            audio_data,
-            pitch_detector: PitchDetector::new(buffer_size, sample_rate),
-            current_note: None,
-            last_update: Instant::now(),
-            frequency_history: Vec::new(),
-            magnitude_history: Vec::new(),
-            max_history: 8,
-            stability_threshold: 3.0,
-            min_magnitude_threshold: 0.08,
-            available_devices,
-            current_device_name,
-            audio_capture: None,
-            smoothed_cents: 0.0,
-            cents_history: Vec::new(),
-            max_cents_history: 8,
-            last_device_refresh: std::time::Instant::now(),
-            device_refresh_interval: std::time::Duration::from_secs(2),
+            pitch_detector: PitchDetector::new(buffer_size, sample_rate, window_kind),
+            onset_detector: OnsetDetector::new(sample_rate),
+            sample_buffer: vec![0.0; buffer_size],
+            spectrum: SpectrumSnapshot::default(),
+            strum: Vec::new(),
+            peaks: Vec::new(),
+            partials: Vec::new(),
+            last_read: 0,
+            tracker: PitchTracker::default(),
         }
     }
 
-    pub fn set_audio_capture(&mut self, audio_capture: AudioCapture) {
-        self.audio_capture = Some(audio_capture);
+    fn reset(&mut self) {
+        self.tracker.reset();
     }
 
-    pub fn switch_device(&mut self, device_name: String, device: cpal::Device) {
-        self.current_device_name = device_name;
-        if let Ok(new_capture) = AudioCapture::new_with_device(self.audio_data.clone(), device) {
-            self.audio_capture = Some(new_capture);
-            self.frequency_history.clear();
-            self.magnitude_history.clear();
-            self.cents_history.clear();
-            self.current_note = None;
-            self.smoothed_cents = 0.0;
-        }
+    fn reading(&self) -> PitchReading {
+        self.tracker.reading()
     }
 
-    fn refresh_audio_devices(&mut self) {
-        if self.last_device_refresh.elapsed() >= self.device_refresh_interval {
-            if let Ok(devices) = get_input_devices() {
-                if devices.len() != self.available_devices.len() || 
-                   !devices.iter().all(|(name, _)| self.available_devices.iter().any(|(existing_name, _)| existing_name == name)) {
-                    println!("Audio device list changed - refreshing");
-                    self.available_devices = devices;
-                    
-                    if !self.available_devices.iter().any(|(name, _)| name == &self.current_device_name) {
-                        if let Ok(default_name) = get_default_input_device_name() {
-                            self.current_device_name = default_name;
-                            println!("Current device no longer available, switched to default");
-                        }
-                    }
-                }
+    /// Analyses the newest block if there is one, returning its detected
+    /// magnitude (0 without a detection) and input RMS.
+    fn update(
+        &mut self,
+        audio_data: &Mutex<AudioData>,
+        settings: &AnalysisSettings,
+    ) -> Option<(f32, f32)> {
+        if settings.fft_size != self.pitch_detector.buffer_size()
+            || settings.window_kind != self.pitch_detector.window_kind()
+        {
+            self.pitch_detector = PitchDetector::new(
+                settings.fft_size,
+                self.pitch_detector.sample_rate(),
+                settings.window_kind,
+            );
+            self.sample_buffer = vec![0.0; settings.fft_size];
+            self.reset();
+        }
+
+        let (count, sample_rate, rms) = match audio_data.lock() {
+            Ok(audio_data) if audio_data.has_new_data(self.last_read, settings.hop_size) => {
+                self.last_read = audio_data.total_samples_written();
+                (
+                    audio_data.get_samples(&mut self.sample_buffer),
+                    audio_data.sample_rate,
+                    audio_data.level.rms,
+                )
+            }
+            _ => return None,
+        };
+        self.pitch_detector.set_sample_rate(sample_rate);
+        settings.configure(&mut self.pitch_detector);
+        let note = self.tracker.reading().note;
+        self.pitch_detector.set_low_range(
+            settings
+                .low_range
+                .active(note.map(|note| note.target_frequency)),
+        );
+        self.onset_detector.set_sample_rate(sample_rate);
+
+        let samples = &self.sample_buffer[..count];
+        let now = Instant::now();
+        if self.onset_detector.detect(samples) {
+            self.tracker.onset(now, settings);
+        }
+        let detection = if settings.capture_spectrum {
+            let detection = self
+                .pitch_detector
+                .detect_pitch_with_spectrum(samples, &mut self.spectrum.magnitudes);
+            self.spectrum.min_frequency = self.pitch_detector.spectrum_min_frequency();
+            self.spectrum.bin_width = self.pitch_detector.bin_width();
+            self.spectrum.peak_frequency = detection.map(|estimate| estimate.frequency);
+            detection
+        } else {
+            self.pitch_detector.detect_pitch(samples)
+        };
+
+        if let Some(preset) = settings.preset.as_ref().filter(|_| settings.capture_strum) {
+            let scale = settings.a4_reference / DEFAULT_A4_FREQUENCY;
+            let targets: Vec<f32> = preset
+                .strings
+                .iter()
+                .map(|string| string.target_frequency * scale)
+                .collect();
+            let strings = self.pitch_detector.detect_multi_pitch(samples, &targets);
+            self.strum.clear();
+            self.strum
+                .extend(strings.iter().zip(&targets).map(|(string, target)| {
+                    string
+                        .filter(|(_, magnitude)| *magnitude >= settings.min_magnitude_threshold)
+                        .map(|(frequency, _)| 1200.0 * (frequency / target).log2())
+                }));
+        }
+
+        if settings.capture_peaks {
+            self.peaks = self
+                .pitch_detector
+                .detect_peaks(samples, MAX_INTERVAL_NOTES);
+            self.peaks
+                .retain(|(_, magnitude)| *magnitude >= settings.min_magnitude_threshold);
+        }
+
+        if let Some(estimate) = detection.filter(|_| settings.piano_stretch.is_some()) {
+            self.partials =
+                self.pitch_detector
+                    .detect_partials(samples, estimate.frequency, PIANO_PARTIALS);
+        }
+
+        match detection {
+            Some(estimate) => {
+                self.tracker.feed(estimate, now, settings);
             }
-            self.last_device_refresh = std::time::Instant::now();
+            None => self.tracker.timeout(now, settings),
         }
+
+        Some((detection.map_or(0.0, |estimate| estimate.magnitude), rms))
     }
+}
 
-    fn update_pitch_detection(&mut self) {
-        if let Ok(mut audio_data) = self.audio_data.try_lock() {
-            if audio_data.has_new_data() {
-                let samples = audio_data.get_samples();
+/// Loudest ambient input seen while calibrating the magnitude gate.
+#[derive(Clone, Copy, Debug)]
+struct NoiseCalibration {
+    started: Instant,
+    peak_magnitude: f32,
+    peak_rms: f32,
+}
 
-                if let Some((frequency, magnitude)) = self.pitch_detector.detect_pitch(&samples) {
-                    if magnitude < self.min_magnitude_threshold {
-                        if self.last_update.elapsed().as_millis() > 400 {
-                            self.current_note = None;
-                            self.frequency_history.clear();
-                            self.magnitude_history.clear();
-                            self.cents_history.clear();
-                            self.smoothed_cents = 0.0;
-                        }
-                        return;
-                    }
+impl NoiseCalibration {
+    fn new(now: Instant) -> Self {
+        Self {
+            started: now,
+            peak_magnitude: 0.0,
+            peak_rms: 0.0,
+        }
+    }
 
-                    self.frequency_history.push(frequency);
-                    self.magnitude_history.push(magnitude);
+    fn observe(&mut self, magnitude: f32, rms: f32) {
+        self.peak_magnitude = self.peak_magnitude.max(magnitude);
+        self.peak_rms = self.peak_rms.max(rms);
+    }
 
-                    if self.frequency_history.len() > self.max_history {
-                        self.frequency_history.remove(0);
-                        self.magnitude_history.remove(0);
-                    }
+    fn finished(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.started) >= CALIBRATION_DURATION
+    }
 
-                    if self.frequency_history.len() >= self.max_history {
-                        let max_freq = self
-                            .frequency_history
-                            .iter()
-                            .fold(f32::NEG_INFINITY, |a, &b| a.max(b));
-                        let min_freq = self
-                            .frequency_history
-                            .iter()
-                            .fold(f32::INFINITY, |a, &b| a.min(b));
+    fn threshold(&self) -> f32 {
+        (self.peak_magnitude * CALIBRATION_MARGIN)
+            .clamp(MIN_MAGNITUDE_THRESHOLD, MAX_MAGNITUDE_THRESHOLD)
+    }
+}
 
-                        let avg_magnitude = self.magnitude_history.iter().sum::<f32>()
-                            / self.magnitude_history.len() as f32;
-                        let magnitude_stable = self
-                            .magnitude_history
-                            .iter()
-                            .all(|&m| (m - avg_magnitude).abs() < avg_magnitude * 0.5);
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ToneTarget {
+    A4,
+    String(usize),
+}
 
-                        if (max_freq - min_freq) < self.stability_threshold
-                            && magnitude_stable
-                            && avg_magnitude > self.min_magnitude_threshold * 2.0
-                        {
-                            let mut sorted_freq = self.frequency_history.clone();
-                            sorted_freq.sort_by(|a, b| a.partial_cmp(b).unwrap());
-                            let median_freq = sorted_freq[sorted_freq.len() / 2];
+pub(crate) struct PitchWorker {
+    settings: Arc<Mutex<AnalysisSettings>>,
+    reading: Arc<Mutex<PitchReading>>,
+    spectrum: Arc<Mutex<SpectrumSnapshot>>,
+    strum: Arc<Mutex<Vec<Option<f32>>>>,
+    peaks: Arc<Mutex<Vec<(f32, f32)>>>,
+    partials: Arc<Mutex<Vec<Option<f32>>>>,
+    running: Arc<AtomicBool>,
+    muted: Arc<AtomicBool>,
+    calibration: Arc<Mutex<Option<NoiseCalibration>>>,
+    /// Woken when a note starts, so an idle window redraws without waiting.
+    repaint: Arc<Mutex<Option<egui::Context>>>,
+    handle: Option<JoinHandle<()>>,
+}
 
-                            let note = frequency_to_note(median_freq);
+impl PitchWorker {
+    pub fn start(audio_data: Arc<Mutex<AudioData>>, settings: AnalysisSettings) -> Self {
+        let (fft_size, window_kind) = (settings.fft_size, settings.window_kind);
+        let settings = Arc::new(Mutex::new(settings));
+        let reading = Arc::new(Mutex::new(PitchReading::default()));
+        let spectrum = Arc::new(Mutex::new(SpectrumSnapshot::default()));
+        let strum = Arc::new(Mutex::new(Vec::new()));
+        let peaks = Arc::new(Mutex::new(Vec::new()));
+        let partials = Arc::new(Mutex::new(Vec::new()));
+        let running = Arc::new(AtomicBool::new(true));
+        let muted = Arc::new(AtomicBool::new(false));
+        let calibration = Arc::new(Mutex::new(None::<NoiseCalibration>));
+        let repaint = Arc::new(Mutex::new(None::<egui::Context>));
 
-                            self.cents_history.push(note.cents_off);
-                            if self.cents_history.len() > self.max_cents_history {
-                                self.cents_history.remove(0);
-                            }
+        let sample_rate = audio_data
+            .lock()
+            .map(|audio_data| audio_data.sample_rate)
+            .unwrap_or(44100.0);
+        let mut analyzer = PitchAnalyzer::new(fft_size, sample_rate, window_kind);
 
-                            if self.cents_history.len() >= self.max_cents_history {
-                                let cents_max = self
-                                    .cents_history
-                                    .iter()
-                                    .fold(f32::NEG_INFINITY, |a, &b| a.max(b));
-                                let cents_min = self
-                                    .cents_history
-                                    .iter()
-                                    .fold(f32::INFINITY, |a, &b| a.min(b));
-
-                                if (cents_max - cents_min) < 20.0 {
-                                    let target_cents = self.cents_history.iter().sum::<f32>()
-                                        / self.cents_history.len() as f32;
-                                    self.smoothed_cents =
-                                        self.smoothed_cents * 0.8 + target_cents * 0.2;
-
-                                    let mut smoothed_note = note.clone();
-                                    smoothed_note.cents_off = self.smoothed_cents;
-
-                                    self.current_note = Some(smoothed_note);
-                                    self.last_update = Instant::now();
-                                } else {
-                                    self.current_note = None;
-                                    self.cents_history.clear();
-                                    self.smoothed_cents = 0.0;
+        let handle = {
+            let settings = settings.clone();
+            let reading = reading.clone();
+            let spectrum = spectrum.clone();
+            let strum = strum.clone();
+            let peaks = peaks.clone();
+            let partials = partials.clone();
+            let running = running.clone();
+            let muted = muted.clone();
+            let calibration = calibration.clone();
+            let repaint = repaint.clone();
+            std::thread::Builder::new()
+                .name("pitch-worker".to_string())
+                .spawn(move || {
+                    let mut had_note = false;
+                    while running.load(Ordering::Relaxed) {
+                        let current_settings = match settings.lock() {
+                            Ok(settings) => settings.clone(),
+                            Err(_) => break,
+                        };
+
+                        if muted.load(Ordering::Relaxed) {
+                            analyzer.reset();
+                        } else if let Some((magnitude, rms)) =
+                            analyzer.update(&audio_data, &current_settings)
+                        {
+                            if let Ok(mut calibration) = calibration.lock() {
+                                if let Some(calibration) = calibration.as_mut() {
+                                    calibration.observe(magnitude, rms);
                                 }
                             }
                         }
+
+                        let next = analyzer.reading();
+                        let note_started = next.note.is_some() && !had_note;
+                        had_note = next.note.is_some();
+                        if let Ok(mut reading) = reading.lock() {
+                            *reading = next;
+                        }
+                        if note_started {
+                            if let Some(ctx) = repaint.lock().ok().and_then(|ctx| ctx.clone()) {
+                                ctx.request_repaint();
+                            }
+                        }
+
+                        if current_settings.capture_spectrum {
+                            if let Ok(mut spectrum) = spectrum.lock() {
+                                spectrum.copy_from(&analyzer.spectrum);
+                            }
+                        }
+                        if current_settings.capture_strum {
+                            if let Ok(mut strum) = strum.lock() {
+                                strum.clone_from(&analyzer.strum);
+                            }
+                        }
+                        if current_settings.capture_peaks {
+                            if let Ok(mut peaks) = peaks.lock() {
+                                peaks.clone_from(&analyzer.peaks);
+                            }
+                        }
+                        if current_settings.piano_stretch.is_some() {
+                            if let Ok(mut partials) = partials.lock() {
+                                partials.clone_from(&analyzer.partials);
+                            }
+                        }
+
+                        std::thread::sleep(WORKER_POLL_INTERVAL);
                     }
-                } else if self.last_update.elapsed().as_millis() > 500 {
-                    self.current_note = None;
-                    self.frequency_history.clear();
-                    self.magnitude_history.clear();
-                    self.cents_history.clear();
-                    self.smoothed_cents = 0.0;
-                }
+                })
+                .ok()
+        };
+
+        Self {
+            settings,
+            reading,
+            spectrum,
+            strum,
+            peaks,
+            partials,
+            running,
+            muted,
+            calibration,
+            repaint,
+            handle,
+        }
+    }
+
+    /// Repaints `ctx` whenever a note starts.
+    pub fn wake_on_note(&self, ctx: &egui::Context) {
+        if let Ok(mut repaint) = self.repaint.lock() {
+            if repaint.is_none() {
+                *repaint = Some(ctx.clone());
             }
         }
     }
 
-    fn draw_tuner_display(&self, ui: &mut egui::Ui) {
-        let available_size = ui.available_size();
-        let center = available_size / 2.0;
+    pub fn start_calibration(&self, now: Instant) {
+        if let Ok(mut calibration) = self.calibration.lock() {
+            *calibration = Some(NoiseCalibration::new(now));
+        }
+    }
 
-        ui.scope_builder(
-            egui::UiBuilder::new().max_rect(egui::Rect::from_center_size(
-                egui::pos2(center.x, center.y - 30.0),
-                egui::vec2(200.0, 40.0),
-            )),
-            |ui| {
-                ui.vertical_centered(|ui| {
-                    if let Some(note) = &self.current_note {
-                        ui.label(
-                            egui::RichText::new(&note.name)
-                                .size(36.0)
-                                .color(egui::Color32::WHITE)
-                                .strong(),
-                        );
-                        ui.label(
-                            egui::RichText::new(format!("{:.1} Hz", note.frequency))
-                                .size(12.0)
-                                .color(egui::Color32::from_rgb(142, 142, 147)),
-                        );
-                    } else {
-                        ui.label(
-                            egui::RichText::new("♪ Play a note...")
-                                .size(18.0)
-                                .color(egui::Color32::from_rgb(142, 142, 147)),
-                        );
-                        ui.add_space(16.0);
-                    }
-                });
-            },
-        );
+    pub fn calibrating(&self) -> bool {
+        self.calibration
+            .lock()
+            .is_ok_and(|calibration| calibration.is_some())
+    }
 
-        let cents_off = if let Some(note) = &self.current_note {
-            note.cents_off
+    /// Hands back the calibration once it has run for its full duration.
+    fn take_finished_calibration(&self, now: Instant) -> Option<NoiseCalibration> {
+        let mut calibration = self.calibration.lock().ok()?;
+        if calibration.as_ref()?.finished(now) {
+            calibration.take()
         } else {
-            0.0
+            None
+        }
+    }
+
+    /// Pauses analysis, e.g. while the reference tone could feed back from the
+    /// speakers into the microphone or the window is minimized.
+    pub fn set_muted(&self, muted: bool) {
+        self.muted.store(muted, Ordering::Relaxed);
+    }
+
+    pub fn copy_spectrum(&self, out: &mut SpectrumSnapshot) {
+        if let Ok(spectrum) = self.spectrum.lock() {
+            out.copy_from(&spectrum);
+        }
+    }
+
+    pub fn copy_strum(&self, out: &mut Vec<Option<f32>>) {
+        if let Ok(strum) = self.strum.lock() {
+            out.clone_from(&strum);
+        }
+    }
+
+    pub fn copy_peaks(&self, out: &mut Vec<(f32, f32)>) {
+        if let Ok(peaks) = self.peaks.lock() {
+            out.clone_from(&peaks);
+        }
+    }
+
+    pub fn copy_partials(&self, out: &mut Vec<Option<f32>>) {
+        if let Ok(partials) = self.partials.lock() {
+            out.clone_from(&partials);
+        }
+    }
+
+    pub fn reading(&self) -> PitchReading {
+        self.reading
+            .lock()
+            .map(|reading| reading.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn set_settings(&self, settings: AnalysisSettings) {
+        if let Ok(mut current) = self.settings.lock() {
+            *current = settings;
+        }
+    }
+
+    pub fn stop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for PitchWorker {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Detections per second, counted over the last [`DETECTION_RATE_WINDOW`].
+#[derive(Debug, Default)]
+struct DetectionRate {
+    /// Detection count when the current count started, and when that was.
+    start: Option<(u64, Instant)>,
+    per_second: Option<f32>,
+}
+
+impl DetectionRate {
+    /// Follows the tracker's running count of detections.
+    fn update(&mut self, detections: u64, now: Instant) {
+        let Some((start_count, since)) = self.start else {
+            self.start = Some((detections, now));
+            return;
         };
-        self.draw_tuner_needle(ui, cents_off, center);
+        // A restarted worker counts from zero again.
+        if detections < start_count {
+            self.start = Some((detections, now));
+            return;
+        }
+        let elapsed = now.saturating_duration_since(since);
+        if elapsed >= DETECTION_RATE_WINDOW {
+            self.per_second = Some((detections - start_count) as f32 / elapsed.as_secs_f32());
+            self.start = Some((detections, now));
+        }
+    }
+}
 
-        ui.scope_builder(
-            egui::UiBuilder::new().max_rect(egui::Rect::from_center_size(
-                egui::pos2(center.x, center.y + 38.0),
-                egui::vec2(120.0, 20.0),
-            )),
-            |ui| {
-                ui.vertical_centered(|ui| {
-                    if let Some(note) = &self.current_note {
-                        let sign = if note.cents_off > 0.0 { "+" } else { "" };
-                        let cents_text =
-                            egui::RichText::new(format!("{}{:.0} cents", sign, note.cents_off))
-                                .size(11.0)
-                                .color(if note.cents_off.abs() < 5.0 {
-                                    egui::Color32::from_rgb(48, 209, 88)
-                                } else if note.cents_off.abs() < 20.0 {
-                                    egui::Color32::from_rgb(255, 159, 10)
-                                } else {
-                                    egui::Color32::from_rgb(255, 69, 58)
-                                });
-                        ui.label(cents_text);
-                    } else {
-                        ui.label(egui::RichText::new("").size(11.0));
-                    }
-                });
-            },
-        );
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct HistoryPoint {
+    at: Instant,
+    frequency: f32,
+    cents: f32,
+    target_frequency: f32,
+}
+
+#[derive(Default)]
+struct PitchHistory {
+    points: VecDeque<HistoryPoint>,
+}
+
+impl PitchHistory {
+    fn record(&mut self, reading: &PitchReading, now: Instant) {
+        if let Some(note) = &reading.note {
+            if self.points.len() == MAX_HISTORY_POINTS {
+                self.points.pop_front();
+            }
+            self.points.push_back(HistoryPoint {
+                at: now,
+                frequency: note.frequency,
+                cents: reading.smoothed_cents,
+                target_frequency: note.target_frequency,
+            });
+        }
+        while self
+            .points
+            .front()
+            .is_some_and(|point| now.duration_since(point.at) > MAX_HISTORY_SPAN)
+        {
+            self.points.pop_front();
+        }
     }
 
-    fn draw_tuner_needle(&self, ui: &mut egui::Ui, cents_off: f32, center: egui::Vec2) {
-        let painter = ui.painter();
-        let needle_area = egui::Rect::from_center_size(
-            egui::pos2(center.x, center.y + 5.0),
-            egui::vec2(220.0, 20.0),
-        );
+    /// Lowest and highest cents recorded for `target_frequency` since `since`.
+    fn cents_range(&self, since: Instant, target_frequency: f32) -> Option<(f32, f32)> {
+        self.points
+            .iter()
+            .filter(|point| {
+                point.at >= since && same_target(point.target_frequency, target_frequency)
+            })
+            .fold(None, |range, point| match range {
+                None => Some((point.cents, point.cents)),
+                Some((low, high)) => Some((low.min(point.cents), high.max(point.cents))),
+            })
+    }
 
-        painter.rect_filled(needle_area, 10.0, egui::Color32::from_rgb(59, 59, 59));
+    /// Index ranges of the points since `since` that form one unbroken line,
+    /// split wherever detection dropped out or the note changed.
+    fn segments(&self, since: Instant) -> Vec<std::ops::Range<usize>> {
+        let first = self.points.partition_point(|point| point.at < since);
+        let mut segments = Vec::new();
+        let mut start = first;
+        for index in first + 1..self.points.len() {
+            let (previous, point) = (&self.points[index - 1], &self.points[index]);
+            if point.at.duration_since(previous.at) > HISTORY_GAP
+                || !same_target(point.target_frequency, previous.target_frequency)
+            {
+                segments.push(start..index);
+                start = index;
+            }
+        }
+        if start < self.points.len() {
+            segments.push(start..self.points.len());
+        }
+        segments
+    }
+}
 
-        let center_x = needle_area.center().x;
-        painter.line_segment(
-            [
-                egui::pos2(center_x, needle_area.top() + 3.0),
-                egui::pos2(center_x, needle_area.bottom() - 3.0),
-            ],
-            egui::Stroke::new(1.5, egui::Color32::from_rgb(99, 99, 102)),
-        );
+#[derive(Default)]
+struct LevelMeter {
+    level: InputLevel,
+    last_clip_count: u64,
+    clip_times: VecDeque<Instant>,
+    clipping_until: Option<Instant>,
+    quiet_since: Option<Instant>,
+}
+
+impl LevelMeter {
+    fn update(&mut self, level: InputLevel, now: Instant) {
+        let new_clips = level.clip_count.saturating_sub(self.last_clip_count) as usize;
+        for _ in 0..new_clips.min(CLIP_REPEAT_COUNT) {
+            self.clip_times.push_back(now);
+        }
+        while self.clip_times.len() > CLIP_REPEAT_COUNT {
+            self.clip_times.pop_front();
+        }
+        while self
+            .clip_times
+            .front()
+            .is_some_and(|&time| now.duration_since(time) > CLIP_WINDOW)
+        {
+            self.clip_times.pop_front();
+        }
+        if self.clip_times.len() >= CLIP_REPEAT_COUNT {
+            self.clipping_until = Some(now + CLIP_HOLD);
+        }
+
+        if level.rms < QUIET_RMS_THRESHOLD {
+            self.quiet_since.get_or_insert(now);
+        } else {
+            self.quiet_since = None;
+        }
+
+        self.level = level;
+        self.last_clip_count = level.clip_count;
+    }
+
+    /// Loud enough that the meter moves noticeably from frame to frame.
+    fn active(&self) -> bool {
+        self.level.rms >= ACTIVE_RMS_THRESHOLD
+    }
+
+    fn too_quiet(&self, now: Instant) -> bool {
+        self.quiet_since
+            .is_some_and(|since| now.duration_since(since) >= QUIET_HINT_DELAY)
+    }
+
+    fn clipping(&self, now: Instant) -> bool {
+        self.clipping_until.is_some_and(|until| now < until)
+    }
+}
+
+/// Where the needle is drawn, following the reading like a critically damped
+/// spring: it glides to each new value without overshooting, at the same pace
+/// whatever the frame rate.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct NeedleMotion {
+    cents: f32,
+    velocity: f32,
+}
+
+impl NeedleMotion {
+    /// Moves `dt` seconds towards `target`, getting within a couple of percent
+    /// of it in `response_time`.
+    fn update(&mut self, target: f32, response_time: Duration, dt: f32) {
+        let omega = 6.0 / response_time.as_secs_f32().max(f32::EPSILON);
+        let dt = dt.clamp(0.0, MAX_NEEDLE_STEP);
+        // The exact solution for a constant target, so step size doesn't matter.
+        let offset = self.cents - target;
+        let impulse = (self.velocity + omega * offset) * dt;
+        let decay = (-omega * dt).exp();
+        self.velocity = (self.velocity - omega * impulse) * decay;
+        self.cents = target + (offset + impulse) * decay;
+        if self.settled_at(target) {
+            *self = Self {
+                cents: target,
+                velocity: 0.0,
+            };
+        }
+    }
+
+    fn settled_at(&self, target: f32) -> bool {
+        (self.cents - target).abs() < NEEDLE_SETTLED_CENTS
+            && self.velocity.abs() < NEEDLE_SETTLED_CENTS
+    }
+}
 
-        let max_cents = 50.0;
-        let normalized_cents = (cents_off / max_cents).clamp(-1.0, 1.0);
-        let needle_x = center_x + normalized_cents * (needle_area.width() / 2.0 - 10.0);
+/// The span of the needle's scale, easing between ranges so that the needle
+/// doesn't jump when it changes.
+#[derive(Debug)]
+struct NeedleScale {
+    /// Cents at either end of the scale as drawn.
+    range: f32,
+    /// When the note last came within the zoomed range.
+    near_since: Option<Instant>,
+}
+
+impl NeedleScale {
+    fn new(setting: NeedleRange) -> Self {
+        Self {
+            range: setting.cents(),
+            near_since: None,
+        }
+    }
 
-        let needle_color = if cents_off.abs() < 5.0 {
-            egui::Color32::from_rgb(48, 209, 88)
-        } else if cents_off.abs() < 20.0 {
-            egui::Color32::from_rgb(255, 159, 10)
+    /// Moves `dt` seconds towards the range `setting` calls for with the note
+    /// `cents` off, or no note; true while the range is still changing.
+    fn update(&mut self, setting: NeedleRange, cents: Option<f32>, now: Instant, dt: f32) -> bool {
+        if cents.is_some_and(|cents| cents.abs() < NEEDLE_ZOOM_CENTS) {
+            self.near_since.get_or_insert(now);
+        } else {
+            self.near_since = None;
+        }
+        let zoomed = setting == NeedleRange::Auto
+            && self
+                .near_since
+                .is_some_and(|since| now.duration_since(since) >= NEEDLE_ZOOM_DELAY);
+        let target = if zoomed {
+            NEEDLE_ZOOM_CENTS
         } else {
-            egui::Color32::from_rgb(255, 69, 58)
+            setting.cents()
         };
 
-        if cents_off != 0.0 || self.current_note.is_some() {
-            painter.circle_filled(
-                egui::pos2(needle_x, needle_area.center().y),
-                6.0,
-                needle_color,
-            );
+        let dt = dt.clamp(0.0, MAX_NEEDLE_STEP);
+        self.range = target + (self.range - target) * (-dt / NEEDLE_ZOOM_TIME).exp();
+        if (self.range - target).abs() < NEEDLE_SETTLED_CENTS {
+            self.range = target;
         }
+        self.range != target
+    }
+}
 
-        for i in [-4i32, -2, 2, 4] {
-            let mark_cents = i as f32 * 12.5;
-            let mark_x = center_x + (mark_cents / max_cents) * (needle_area.width() / 2.0 - 10.0);
-            painter.line_segment(
-                [
-                    egui::pos2(mark_x, needle_area.center().y - 3.0),
-                    egui::pos2(mark_x, needle_area.center().y + 3.0),
-                ],
-                egui::Stroke::new(1.0, egui::Color32::from_rgb(99, 99, 102)),
-            );
+/// Decides when to tell the player a note has settled in tune: once it has
+/// stayed within the tolerance for the dwell time, and then not again until
+/// it has left it or moved to another note.
+#[derive(Debug, Default)]
+struct InTuneCue {
+    /// Target frequency of the note in tune, and since when.
+    in_tune: Option<(f32, Instant)>,
+    /// Whether the cue has fired for this stretch in tune.
+    fired: bool,
+    last_fired: Option<Instant>,
+}
+
+impl InTuneCue {
+    /// Follows the note's target frequency and cents off, or no note; true
+    /// when the cue should fire.
+    fn update(
+        &mut self,
+        note: Option<(f32, f32)>,
+        in_tune_cents: f32,
+        dwell: Duration,
+        now: Instant,
+    ) -> bool {
+        let target = note
+            .filter(|&(_, cents)| cents.abs() < in_tune_cents)
+            .map(|(target, _)| target);
+        let since = match (target, self.in_tune) {
+            (Some(target), Some((in_tune_target, since))) if target == in_tune_target => since,
+            (Some(target), _) => {
+                self.in_tune = Some((target, now));
+                self.fired = false;
+                now
+            }
+            (None, _) => {
+                self.in_tune = None;
+                self.fired = false;
+                return false;
+            }
+        };
+
+        let cooled_down = self
+            .last_fired
+            .is_none_or(|last| now.duration_since(last) >= IN_TUNE_CUE_COOLDOWN);
+        if self.fired || !cooled_down || now.duration_since(since) < dwell {
+            return false;
         }
+        self.fired = true;
+        self.last_fired = Some(now);
+        true
     }
 }
 
-impl eframe::App for TunerApp {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        self.refresh_audio_devices();
-        self.update_pitch_detection();
+/// Sent by the offline analysis thread.
+enum AnalysisMessage {
+    /// Fraction of the file analysed so far.
+    Progress(f32),
+    Done(anyhow::Result<PitchCurve>),
+}
 
-        ctx.request_repaint();
+/// Sent by the self-test thread.
+enum SelfTestMessage {
+    /// Fraction of the tones played so far.
+    Progress(f32),
+    Done(SelfTestReport),
+}
 
-        egui::CentralPanel::default()
-            .frame(egui::Frame {
-                fill: egui::Color32::from_rgba_premultiplied(31, 31, 31, 240),
-                corner_radius: 8.0.into(),
-                shadow: eframe::epaint::Shadow::NONE,
-                outer_margin: egui::Margin::ZERO,
-                inner_margin: egui::Margin::symmetric(0, 16),
-                ..Default::default()
-            })
-            .show(ctx, |ui| {
-                ui.add_space(8.0);
+/// The self-test window, running or showing its results.
+struct SelfTestView {
+    /// Messages from the run, until it finishes.
+    receiver: Option<Receiver<SelfTestMessage>>,
+    progress: f32,
+    report: Option<SelfTestReport>,
+}
 
-                ui.with_layout(egui::Layout::top_down(egui::Align::Center), |ui| {
-                    ui.add_space(10.0);
-                    self.draw_tuner_display(ui);
-                    ui.add_space(8.0);
+/// The offline analysis window: a WAV file analysed on a background thread,
+/// then plotted with a playhead to scrub through it.
+#[derive(Default)]
+struct AnalysisView {
+    path_input: String,
+    /// Messages from the analysis running, if any.
+    receiver: Option<Receiver<AnalysisMessage>>,
+    progress: f32,
+    curve: Option<PitchCurve>,
+    /// Seconds at the left and right edges of the plot.
+    view: (f32, f32),
+    /// Seconds from the start of the recording to the playhead.
+    playhead: f32,
+}
 
-                    ui.add_space(4.0);
-                    ui.vertical_centered(|ui| {
-                        ui.add_space(20.0);
+impl AnalysisView {
+    /// Shows a finished analysis whole, with the playhead at the start.
+    fn show(&mut self, curve: PitchCurve) {
+        self.view = (0.0, curve.duration.max(MIN_ANALYSIS_SPAN));
+        self.playhead = 0.0;
+        self.curve = Some(curve);
+    }
 
-                        let available_rect = ui.available_rect_before_wrap();
-                        let combo_rect = egui::Rect::from_center_size(
-                            available_rect.center(),
-                            egui::vec2(200.0, 25.0),
-                        );
+    fn duration(&self) -> f32 {
+        self.curve
+            .as_ref()
+            .map_or(0.0, |curve| curve.duration)
+            .max(MIN_ANALYSIS_SPAN)
+    }
 
-                        ui.scope_builder(egui::UiBuilder::new().max_rect(combo_rect), |ui| {
-                            let mut style = (*ui.ctx().style()).clone();
-
-                            style.visuals.widgets.inactive.bg_fill =
-                                egui::Color32::from_rgb(59, 59, 59);
-                            style.visuals.widgets.inactive.weak_bg_fill =
-                                egui::Color32::from_rgb(59, 59, 59);
-                            style.visuals.widgets.inactive.fg_stroke.color = egui::Color32::WHITE;
-
-                            style.visuals.widgets.hovered.bg_fill =
-                                egui::Color32::from_rgb(75, 75, 75);
-                            style.visuals.widgets.hovered.weak_bg_fill =
-                                egui::Color32::from_rgb(75, 75, 75);
-                            style.visuals.widgets.hovered.fg_stroke.color = egui::Color32::WHITE;
-
-                            style.visuals.widgets.active.bg_fill =
-                                egui::Color32::from_rgb(59, 59, 59);
-                            style.visuals.widgets.active.weak_bg_fill =
-                                egui::Color32::from_rgb(59, 59, 59);
-                            style.visuals.widgets.active.fg_stroke.color = egui::Color32::WHITE;
-
-                            style.visuals.widgets.open.bg_fill =
-                                egui::Color32::from_rgb(59, 59, 59);
-                            style.visuals.widgets.open.weak_bg_fill =
-                                egui::Color32::from_rgb(59, 59, 59);
-                            style.visuals.widgets.open.fg_stroke.color = egui::Color32::WHITE;
-
-                            style.visuals.window_fill = egui::Color32::from_rgb(59, 59, 59);
-                            style.visuals.panel_fill = egui::Color32::from_rgb(59, 59, 59);
-                            style.visuals.extreme_bg_color = egui::Color32::from_rgb(59, 59, 59);
-
-                            style.visuals.selection.bg_fill = egui::Color32::from_rgb(75, 75, 75);
-                            style.visuals.selection.stroke.color = egui::Color32::WHITE;
-
-                            style.visuals.override_text_color = Some(egui::Color32::WHITE);
-
-                            style.visuals.popup_shadow = eframe::epaint::Shadow::NONE;
-                            ui.ctx().set_style(style);
-
-                            egui::ComboBox::from_id_salt("device_selector")
-                                .selected_text(&self.current_device_name)
-                                .width(200.0)
-                                .height(25.0)
+    /// Scales the span shown by `factor`, below 1 zooming in, keeping the
+    /// time at `anchor` where it is on screen.
+    fn zoom(&mut self, factor: f32, anchor: f32) {
+        let (start, end) = self.view;
+        let span = ((end - start) * factor).clamp(MIN_ANALYSIS_SPAN, self.duration());
+        let fraction = ((anchor - start) / (end - start)).clamp(0.0, 1.0);
+        self.set_view(anchor - fraction * span, span);
+    }
+
+    /// Moves the span shown by `seconds`, stopping at either end.
+    fn pan(&mut self, seconds: f32) {
+        let (start, end) = self.view;
+        self.set_view(start + seconds, end - start);
+    }
+
+    fn set_view(&mut self, start: f32, span: f32) {
+        let start = start.clamp(0.0, (self.duration() - span).max(0.0));
+        self.view = (start, start + span);
+    }
+}
+
+/// Cents of the tick marks inside either half of a needle scale reaching
+/// `range`: every 1, 2, 5 or 10 cents, the finest that leaves at most five.
+fn needle_ticks(range: f32) -> impl Iterator<Item = f32> {
+    let step = [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .find(|step| range / step <= 5.0)
+        .unwrap_or(10.0);
+    (1..)
+        .map(move |tick| tick as f32 * step)
+        .take_while(move |&cents| cents < range - step / 2.0)
+}
+
+/// Angle of the gauge needle from upright, clockwise, for `cents` off on a
+/// scale reaching `range`.
+fn gauge_angle(cents: f32, range: f32) -> f32 {
+    (cents / range).clamp(-1.0, 1.0) * GAUGE_HALF_ANGLE
+}
+
+/// A stream counts as stalled once neither the capture start nor the last
+/// delivered block is within the timeout.
+fn stream_stalled(last_push: Option<Instant>, capture_started: Instant, now: Instant) -> bool {
+    let last_activity =
+        last_push.map_or(capture_started, |last_push| last_push.max(capture_started));
+    now.duration_since(last_activity) >= STREAM_STALL_TIMEOUT
+}
+
+/// The standard window size the user last left it at, but never smaller than
+/// the default.
+fn saved_window_size(config: &TunerConfig) -> egui::Vec2 {
+    let [width, height] = config.window_size;
+    egui::vec2(width, height).max(WINDOW_SIZE)
+}
+
+/// Window size to open with in the configured display mode.
+pub fn initial_window_size(config: &TunerConfig) -> egui::Vec2 {
+    match config.display_mode {
+        DisplayMode::Standard => saved_window_size(config),
+        DisplayMode::Mini => MINI_WINDOW_SIZE,
+        DisplayMode::Performance => PERFORMANCE_WINDOW_SIZE,
+    }
+}
+
+/// Smallest the window may be resized to in the configured display mode.
+pub fn min_window_size(config: &TunerConfig) -> egui::Vec2 {
+    match config.display_mode {
+        DisplayMode::Mini => MINI_WINDOW_SIZE,
+        DisplayMode::Standard | DisplayMode::Performance => WINDOW_SIZE,
+    }
+}
+
+/// Beats per second heard from `reading` against a `drone` at that many Hz
+/// if one sounds and shares partials with the note, otherwise against the
+/// note's exact target, following the smoothed deviation. `None` while no
+/// note shows or the rate is too slow or too fast to count.
+fn audible_beats(reading: &PitchReading, drone: Option<f32>) -> Option<f32> {
+    let note = reading.note.as_ref()?;
+    let frequency = note.target_frequency * (reading.smoothed_cents / 1200.0).exp2();
+    let beats = drone
+        .and_then(|drone| partial_beat_frequency(frequency, drone))
+        .unwrap_or_else(|| beat_frequency(note.target_frequency, reading.smoothed_cents));
+    (MIN_BEAT_HZ..=MAX_BEAT_HZ)
+        .contains(&beats)
+        .then_some(beats)
+}
+
+/// Whether keys of `pitch_class` (0 = C) are black on a piano.
+fn is_black_key(pitch_class: i32) -> bool {
+    matches!(pitch_class, 1 | 3 | 6 | 8 | 10)
+}
+
+/// Every key of a piano keyboard of `octaves` octaves from C of
+/// `first_octave`, filling `rect`: the MIDI note and outline of each, white
+/// keys first so the black ones draw over them.
+fn keyboard_layout(first_octave: i32, octaves: i32, rect: egui::Rect) -> Vec<(i32, egui::Rect)> {
+    let first_midi = (first_octave + 1) * 12;
+    let midis = first_midi..first_midi + octaves * 12;
+    let white_width = rect.width() / (octaves * 7) as f32;
+    let mut keys = Vec::with_capacity(midis.len());
+    let mut white_index = 0;
+    for midi in midis.clone() {
+        if is_black_key(midi.rem_euclid(12)) {
+            continue;
+        }
+        let left = rect.left() + white_index as f32 * white_width;
+        keys.push((
+            midi,
+            egui::Rect::from_x_y_ranges(left..=left + white_width, rect.y_range()),
+        ));
+        white_index += 1;
+    }
+    white_index = 0;
+    for midi in midis {
+        if !is_black_key(midi.rem_euclid(12)) {
+            white_index += 1;
+            continue;
+        }
+        // Centred on the line between the white keys either side.
+        let center = rect.left() + white_index as f32 * white_width;
+        let half = white_width * 0.3;
+        keys.push((
+            midi,
+            egui::Rect::from_x_y_ranges(
+                center - half..=center + half,
+                rect.top()..=rect.top() + rect.height() * 0.6,
+            ),
+        ));
+    }
+    keys
+}
+
+/// How much the tuner display is scaled up to fill `available`, keeping its
+/// proportions; it never shrinks below the compact default.
+fn display_scale(available: egui::Vec2) -> f32 {
+    let scale = (available.x / DISPLAY_BASE_SIZE.x).min(available.y / DISPLAY_BASE_SIZE.y);
+    if scale.is_finite() {
+        scale.max(1.0)
+    } else {
+        1.0
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct StatusMessage {
+    pub text: String,
+    /// Errors are shown in red, anything else in green.
+    pub error: bool,
+}
+
+/// The octave the player has said they are in, overriding the detector.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct OctaveShift {
+    /// Where detection searches.
+    prior: FrequencyPrior,
+    /// Whether a note has been read near the prior yet; until then the
+    /// reading may still be in the octave being corrected.
+    settled: bool,
+}
+
+/// What is kept alongside the reading while the display is held.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Hold {
+    /// How far the held note wavered just before it was held.
+    cents_range: Option<(f32, f32)>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum StringStatus {
+    /// Not played yet.
+    Untuned,
+    /// Played, but not held in tune yet.
+    Off,
+    /// Held in tune for [`GUIDED_HOLD_TIME`].
+    InTune,
+}
+
+/// Which way the note has to go, shown as an arrow beside the note name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TuneDirection {
+    /// Within the in-tune tolerance.
+    InTune,
+    /// Flat: tighten the string.
+    Up,
+    /// Sharp: loosen the string.
+    Down,
+}
+
+impl TuneDirection {
+    /// The direction for a deviation of `cents_off`, however large, e.g. the
+    /// whole way to a target note.
+    fn of(cents_off: f32, in_tune_cents: f32) -> Self {
+        if cents_off.abs() < in_tune_cents {
+            Self::InTune
+        } else if cents_off > 0.0 {
+            Self::Down
+        } else {
+            Self::Up
+        }
+    }
+
+    /// How boldly the arrow is drawn, from 0 just outside the in-tune
+    /// tolerance to 1 at [`DIRECTION_FULL_CENTS`] or more.
+    fn strength(cents_off: f32, in_tune_cents: f32) -> f32 {
+        let span = (DIRECTION_FULL_CENTS - in_tune_cents).max(1.0);
+        ((cents_off.abs() - in_tune_cents) / span).clamp(0.0, 1.0)
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::InTune => "in tune",
+            Self::Up => "tune up",
+            Self::Down => "tune down",
+        }
+    }
+}
+
+/// The built-in presets followed by the user's, and why any of the user's
+/// were left out.
+fn merge_presets(user_presets: &[UserPreset]) -> (Vec<Arc<InstrumentPreset>>, Vec<String>) {
+    let mut presets: Vec<Arc<InstrumentPreset>> = InstrumentPreset::built_in()
+        .into_iter()
+        .map(Arc::new)
+        .collect();
+    let mut errors = Vec::new();
+    for user_preset in user_presets {
+        match user_preset.to_preset() {
+            Ok(_) if presets.iter().any(|preset| preset.name == user_preset.name) => {
+                errors.push(format!(
+                    "preset '{}': another preset has the same name",
+                    user_preset.name
+                ));
+            }
+            Ok(preset) => presets.push(Arc::new(preset)),
+            Err(err) => errors.push(format!("{:#}", err)),
+        }
+    }
+    (presets, errors)
+}
+
+/// `preset` shifted by its saved transposition and the tuning offset.
+fn transpose_preset(preset: &InstrumentPreset, config: &TunerConfig) -> Arc<InstrumentPreset> {
+    let semitones = config
+        .preset_transpose
+        .get(&preset.name)
+        .copied()
+        .unwrap_or(0);
+    Arc::new(preset.transposed(semitones, config.tuning_offset_cents))
+}
+
+/// Walks through the strings of a preset from the lowest up, ticking each off
+/// once it stays in tune. Whichever string is played becomes the one being
+/// tuned, so they can be done in any order.
+struct GuidedTuning {
+    preset: Arc<InstrumentPreset>,
+    /// Indexed by string number - 1.
+    status: Vec<StringStatus>,
+    /// The string being tuned, 1 being the highest.
+    current: usize,
+    in_tune_since: Option<Instant>,
+    /// The string ticked off last and when, for its check mark.
+    completed: Option<(usize, Instant)>,
+    /// A ticked-off string that is still ringing, and so shouldn't take the
+    /// guide back from the next one.
+    ringing: Option<usize>,
+}
+
+impl GuidedTuning {
+    fn new(preset: Arc<InstrumentPreset>) -> Self {
+        let strings = preset.strings.len();
+        Self {
+            preset,
+            status: vec![StringStatus::Untuned; strings],
+            current: strings,
+            in_tune_since: None,
+            completed: None,
+            ringing: None,
+        }
+    }
+
+    fn restart(&mut self) {
+        *self = Self::new(self.preset.clone());
+    }
+
+    fn finished(&self) -> bool {
+        self.status
+            .iter()
+            .all(|&status| status == StringStatus::InTune)
+    }
+
+    fn status(&self, string_number: usize) -> StringStatus {
+        self.status[string_number - 1]
+    }
+
+    /// Follows the string being played, if any, and how far off it is.
+    fn update(&mut self, played: Option<(usize, f32)>, in_tune_cents: f32, now: Instant) {
+        let Some((string_number, cents_off)) =
+            played.filter(|&(string_number, _)| (1..=self.status.len()).contains(&string_number))
+        else {
+            self.in_tune_since = None;
+            self.ringing = None;
+            return;
+        };
+        if self.ringing == Some(string_number) {
+            return;
+        }
+        self.ringing = None;
+        if string_number != self.current {
+            self.current = string_number;
+            self.in_tune_since = None;
+        }
+
+        if cents_off.abs() >= in_tune_cents {
+            self.in_tune_since = None;
+            self.status[string_number - 1] = StringStatus::Off;
+            return;
+        }
+        let since = *self.in_tune_since.get_or_insert(now);
+        if now.duration_since(since) >= GUIDED_HOLD_TIME {
+            self.status[string_number - 1] = StringStatus::InTune;
+            self.completed = Some((string_number, now));
+            self.ringing = Some(string_number);
+            self.in_tune_since = None;
+            self.current = self.next_string(string_number);
+        }
+    }
+
+    /// The next string up from `string_number` that isn't in tune, wrapping
+    /// around to the lowest; `string_number` itself once all are.
+    fn next_string(&self, string_number: usize) -> usize {
+        let strings = self.status.len();
+        (1..strings)
+            .map(|step| (string_number + strings - 1 - step) % strings + 1)
+            .find(|&next| self.status(next) != StringStatus::InTune)
+            .unwrap_or(string_number)
+    }
+
+    /// The string whose check mark is showing at `now`.
+    fn just_completed(&self, now: Instant) -> Option<usize> {
+        self.completed
+            .filter(|&(_, at)| now.duration_since(at) < GUIDED_CHECK_TIME)
+            .map(|(string_number, _)| string_number)
+    }
+}
+
+/// How one challenge target went.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct ChallengeResult {
+    /// MIDI note that was asked for.
+    target: i32,
+    /// 0 when its time ran out.
+    points: u32,
+}
+
+/// Hit-the-note practice game: shows a random target note, which scores
+/// points for speed and accuracy once it has been held within
+/// `CHALLENGE_TOLERANCE_CENTS` for `CHALLENGE_HOLD_TIME`, and then the next
+/// one appears, for `CHALLENGE_ROUND_NOTES` targets.
+struct ChallengeMode {
+    /// Lowest and highest MIDI notes targets are drawn from.
+    range: (i32, i32),
+    /// Tonic pitch class of the major key targets are limited to, if any.
+    key: Option<u8>,
+    /// Xorshift state for picking targets.
+    rng: u32,
+    target: i32,
+    /// When the current target appeared.
+    shown_at: Instant,
+    /// When the target started being held in tune, and the summed distance
+    /// in cents and number of readings since.
+    held: Option<(Instant, f32, u32)>,
+    results: Vec<ChallengeResult>,
+    streak: usize,
+    best_streak: usize,
+    /// The target finished last and when, for its mark.
+    last: Option<(ChallengeResult, Instant)>,
+    /// Whether the finished round beat every earlier high score.
+    new_best: bool,
+}
+
+impl ChallengeMode {
+    fn new(range: (i32, i32), key: Option<u8>, seed: u32, now: Instant) -> Self {
+        let mut challenge = Self {
+            range: (range.0.min(range.1), range.0.max(range.1)),
+            key,
+            rng: seed.max(1),
+            target: i32::MIN,
+            shown_at: now,
+            held: None,
+            results: Vec::with_capacity(CHALLENGE_ROUND_NOTES),
+            streak: 0,
+            best_streak: 0,
+            last: None,
+            new_best: false,
+        };
+        challenge.next_target(now);
+        challenge
+    }
+
+    /// Starts another round over the same notes.
+    fn restart(&mut self, now: Instant) {
+        *self = Self::new(self.range, self.key, self.rng, now);
+    }
+
+    /// The notes in range, limited to the key when there is one; a range
+    /// holding none of the key's notes falls back to all of them.
+    fn candidates(&self) -> Vec<i32> {
+        let notes = self.range.0..=self.range.1;
+        let scale = self.key.map(|tonic| {
+            Key {
+                tonic,
+                mode: KeyMode::Major,
+                custom: PitchClassSet::default(),
+            }
+            .pitch_classes()
+        });
+        let in_key: Vec<i32> = notes
+            .clone()
+            .filter(|&midi| scale.is_none_or(|scale| scale.contains(midi.rem_euclid(12) as u8)))
+            .collect();
+        if in_key.is_empty() {
+            notes.collect()
+        } else {
+            in_key
+        }
+    }
+
+    fn random(&mut self) -> u32 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        self.rng
+    }
+
+    /// Shows a new target, never the last one again when there is a choice.
+    fn next_target(&mut self, now: Instant) {
+        let mut candidates = self.candidates();
+        if candidates.len() > 1 {
+            candidates.retain(|&midi| midi != self.target);
+        }
+        self.target = candidates[self.random() as usize % candidates.len()];
+        self.shown_at = now;
+        self.held = None;
+    }
+
+    fn finished(&self) -> bool {
+        self.results.len() >= CHALLENGE_ROUND_NOTES
+    }
+
+    fn score(&self) -> u32 {
+        self.results.iter().map(|result| result.points).sum()
+    }
+
+    fn hits(&self) -> usize {
+        self.results
+            .iter()
+            .filter(|result| result.points > 0)
+            .count()
+    }
+
+    /// Time left at `now` to play the current target.
+    fn time_left(&self, now: Instant) -> Duration {
+        CHALLENGE_TIME_LIMIT.saturating_sub(now.saturating_duration_since(self.shown_at))
+    }
+
+    /// Follows the note being played, if any, as a MIDI note and its
+    /// deviation in cents. Returns the target's result once it has been held
+    /// long enough or its time has run out.
+    fn update(&mut self, played: Option<(i32, f32)>, now: Instant) -> Option<ChallengeResult> {
+        if self.finished() {
+            return None;
+        }
+        let elapsed = now.saturating_duration_since(self.shown_at);
+        if elapsed >= CHALLENGE_TIME_LIMIT {
+            return Some(self.finish_target(0, now));
+        }
+        let Some((_, cents)) = played.filter(|&(midi, cents)| {
+            midi == self.target && cents.abs() <= CHALLENGE_TOLERANCE_CENTS
+        }) else {
+            self.held = None;
+            return None;
+        };
+
+        let (since, total_cents, readings) = self.held.get_or_insert((now, 0.0, 0));
+        *total_cents += cents.abs();
+        *readings += 1;
+        if now.saturating_duration_since(*since) < CHALLENGE_HOLD_TIME {
+            return None;
+        }
+        let points = challenge_points(elapsed, *total_cents / *readings as f32);
+        Some(self.finish_target(points, now))
+    }
+
+    fn finish_target(&mut self, points: u32, now: Instant) -> ChallengeResult {
+        let result = ChallengeResult {
+            target: self.target,
+            points,
+        };
+        self.results.push(result);
+        if points > 0 {
+            self.streak += 1;
+            self.best_streak = self.best_streak.max(self.streak);
+        } else {
+            self.streak = 0;
+        }
+        self.last = Some((result, now));
+        if !self.finished() {
+            self.next_target(now);
+        }
+        result
+    }
+
+    /// The target whose result is showing at `now`.
+    fn just_finished(&self, now: Instant) -> Option<ChallengeResult> {
+        self.last
+            .filter(|&(_, at)| now.saturating_duration_since(at) < CHALLENGE_RESULT_TIME)
+            .map(|(result, _)| result)
+    }
+}
+
+/// Points for a challenge target held in tune `elapsed` after it appeared,
+/// having been `mean_cents` off on average: half for how soon it was found,
+/// half for how close it was held. A hit scores at least 1.
+fn challenge_points(elapsed: Duration, mean_cents: f32) -> u32 {
+    let searching = elapsed.saturating_sub(CHALLENGE_HOLD_TIME).as_secs_f32();
+    let allowed = (CHALLENGE_TIME_LIMIT - CHALLENGE_HOLD_TIME).as_secs_f32();
+    let speed = 1.0 - (searching / allowed).clamp(0.0, 1.0);
+    let accuracy = 1.0 - (mean_cents / CHALLENGE_TOLERANCE_CENTS).clamp(0.0, 1.0);
+    let half = CHALLENGE_MAX_POINTS as f32 / 2.0;
+    ((half * (speed + accuracy)).round() as u32).max(1)
+}
+
+/// Adds a round's `score` to `high_scores`, best first, and tells whether
+/// it beat all of them.
+fn record_high_score(high_scores: &mut Vec<u32>, score: u32) -> bool {
+    let best = high_scores.first().is_none_or(|&best| score > best);
+    high_scores.push(score);
+    high_scores.sort_unstable_by(|a, b| b.cmp(a));
+    high_scores.truncate(MAX_HIGH_SCORES);
+    best
+}
+
+/// A drag value over piano MIDI notes that shows and accepts note names.
+fn note_drag_value(midi: &mut i32, naming: NoteNaming) -> egui::DragValue<'_> {
+    egui::DragValue::new(midi)
+        .range(21..=108)
+        .speed(0.1)
+        .custom_formatter(move |midi, _| {
+            midi_to_note(midi as i32, 0.0, DEFAULT_A4_FREQUENCY).display_name(naming)
+        })
+        .custom_parser(|text| Note::parse(text).map(|note| note.midi as f64))
+}
+
+/// The tuner application: owns the audio input, the pitch worker and the UI.
+pub struct TunerApp {
+    audio_data: Arc<Mutex<AudioData>>,
+    pitch_worker: PitchWorker,
+    /// The reading on display, which stops following the worker while held.
+    reading: PitchReading,
+    needle: NeedleMotion,
+    needle_scale: NeedleScale,
+    in_tune_cue: InTuneCue,
+    /// MIDI note the tuner is locked to, or chromatic if none.
+    target_note: Option<i32>,
+    /// Octave the target note picker is showing.
+    picker_octave: i32,
+    /// Octave correction while the detector reads the wrong octave.
+    octave_shift: Option<OctaveShift>,
+    /// When the in-tune flash started, while it is fading.
+    in_tune_flash: Option<Instant>,
+    hold: Option<Hold>,
+    guided: Option<GuidedTuning>,
+    challenge: Option<ChallengeMode>,
+    /// Status colors of the configured color scheme.
+    palette: Palette,
+    /// Neutral colors of the theme on screen, which can follow the system.
+    theme: Theme,
+    /// The theme setting last handed to egui.
+    applied_theme: Option<UiTheme>,
+    spectrum: SpectrumSnapshot,
+    /// Shows every string of the preset at once, for tuning from a strum.
+    show_strum: bool,
+    strum: Vec<Option<f32>>,
+    /// Notes sounding together, strongest first, when identifying intervals.
+    peaks: Vec<(f32, f32)>,
+    /// Partials of the note, from the fundamental up, in piano mode.
+    partials: Vec<Option<f32>>,
+    available_devices: Vec<String>,
+    /// The system default input as of the last device scan.
+    system_default: Option<String>,
+    last_default_switch: Option<Instant>,
+    /// Window size last asked for, and when, so the size on its way isn't
+    /// mistaken for one the user chose.
+    pending_resize: Option<(egui::Vec2, Instant)>,
+    /// The window level last requested. egui doesn't report the level back,
+    /// so this is what was asked for rather than what the platform did.
+    always_on_top: bool,
+    device_monitor: DeviceMonitor,
+    current_device_name: String,
+    input: Option<Box<dyn AudioSource>>,
+    config: TunerConfig,
+    config_dirty: bool,
+    show_settings: bool,
+    presets: Vec<Arc<InstrumentPreset>>,
+    active_preset: Option<Arc<InstrumentPreset>>,
+    /// The user's presets file, if there is a config directory.
+    user_presets_path: Option<PathBuf>,
+    /// The user's presets as last read or saved, including any that are
+    /// invalid and so missing from `presets`.
+    user_presets: Vec<UserPreset>,
+    /// Presets being edited, while the editor is open.
+    preset_editor: Option<Vec<UserPreset>>,
+    /// The offline analysis window, while it is open.
+    analysis: Option<AnalysisView>,
+    /// The self-test window, while it is open.
+    self_test: Option<SelfTestView>,
+    tone_player: Option<TonePlayer>,
+    /// Output of the drone, separate from the reference tone so that
+    /// detection keeps running while it plays.
+    drone_player: Option<TonePlayer>,
+    /// Click output, opened the first time the metronome starts.
+    metronome: Option<MetronomePlayer>,
+    /// The latest metronome beat and when the window first saw it.
+    metronome_beat: Option<(Beat, Instant)>,
+    tone_target: ToneTarget,
+    level_meter: LevelMeter,
+    status: Option<StatusMessage>,
+    capture_started: Instant,
+    scale: Option<Arc<ScalaScale>>,
+    scala_path_input: String,
+    recorder: Option<Recorder>,
+    /// Capture is paused because the window is minimized or hidden.
+    suspended: bool,
+    tray: Option<Tray>,
+    /// Asks from later launches to bring the window to the front.
+    raise_requests: Option<RaiseRequests>,
+    global_shortcut: Option<GlobalShortcut>,
+    shortcut_input: String,
+    /// Hidden in the tray; only the tray icon or the shortcut brings it back.
+    window_hidden: bool,
+    /// Showing only the reading, for streaming; see [`Self::set_overlay`].
+    overlay: bool,
+    /// Quitting from the tray, which closes the window even if closing
+    /// normally hides it.
+    quitting: bool,
+    recordings_path_input: String,
+    detection_rate: DetectionRate,
+    history: PitchHistory,
+    show_history: bool,
+    stats: SessionStats,
+    show_stats: bool,
+    /// Stable readings of this session, capped at the configured log size.
+    detection_log: DetectionLog,
+    export_path_input: String,
+    /// Receives the outcome of an export running in the background.
+    export: Option<Receiver<anyhow::Result<(PathBuf, usize)>>>,
+    midi: Option<MidiSender>,
+    midi_ports: Vec<String>,
+    osc: Option<OscSender>,
+    websocket: Option<WebSocketServer>,
+    /// The port `websocket` was last opened on, to tell when a typed one
+    /// differs.
+    websocket_port: u16,
+    osc_target_input: String,
+}
+
+impl TunerApp {
+    /// Creates the app for `config`, analysing whatever is pushed into `audio_data`.
+    pub fn new(audio_data: Arc<Mutex<AudioData>>, config: TunerConfig) -> Self {
+        let current_device_name =
+            get_default_input_device_name().unwrap_or_else(|_| "Default".to_string());
+        let detection_log = DetectionLog::new(config.log_capacity);
+        let needle_scale = NeedleScale::new(config.needle_range);
+        let user_presets_path = user_presets_path();
+        let (user_presets, mut errors) = match &user_presets_path {
+            Some(path) => load_user_presets(path)
+                .unwrap_or_else(|err| (Vec::new(), vec![format!("{:#}", err)])),
+            None => (Vec::new(), Vec::new()),
+        };
+        let (presets, preset_errors) = merge_presets(&user_presets);
+        errors.extend(preset_errors);
+        let active_preset = config
+            .instrument_preset
+            .as_ref()
+            .and_then(|name| presets.iter().find(|preset| &preset.name == name))
+            .map(|preset| transpose_preset(preset, &config));
+
+        let scale = match &config.scala_file {
+            Some(path) => match ScalaScale::load(path) {
+                Ok(scale) => Some(Arc::new(scale)),
+                Err(err) => {
+                    errors.insert(0, format!("{:#}", err));
+                    None
+                }
+            },
+            None => None,
+        };
+        let status = (!errors.is_empty()).then(|| StatusMessage {
+            text: errors.join("; "),
+            error: true,
+        });
+        let scala_path_input = config
+            .scala_file
+            .as_ref()
+            .map(|path| path.display().to_string())
+            .unwrap_or_default();
+        let shortcut_input = config.toggle_shortcut.clone();
+        let recordings_path_input = config
+            .recordings_dir
+            .as_ref()
+            .map(|path| path.display().to_string())
+            .unwrap_or_default();
+
+        let mut settings = AnalysisSettings::from(&config);
+        settings.preset = active_preset.clone();
+        settings.scale = scale.clone();
+
+        if let Ok(mut audio_data) = audio_data.lock() {
+            audio_data.set_buffer_size(config.analysis_size());
+        }
+
+        let mut app = Self {
+            pitch_worker: PitchWorker::start(audio_data.clone(), settings),
+            audio_data,
+            reading: PitchReading::default(),
+            hold: None,
+            guided: None,
+            challenge: None,
+            palette: Palette::for_scheme(config.color_scheme, egui::Theme::Dark),
+            theme: Theme::DARK,
+            applied_theme: None,
+            spectrum: SpectrumSnapshot::default(),
+            show_strum: false,
+            strum: Vec::new(),
+            peaks: Vec::new(),
+            partials: Vec::new(),
+            available_devices: Vec::new(),
+            system_default: None,
+            last_default_switch: None,
+            pending_resize: None,
+            always_on_top: config.always_on_top,
+            device_monitor: DeviceMonitor::start(DEVICE_REFRESH_INTERVAL, scan_input_devices),
+            current_device_name,
+            input: None,
+            config,
+            config_dirty: false,
+            show_settings: false,
+            presets,
+            active_preset,
+            user_presets_path,
+            user_presets,
+            preset_editor: None,
+            analysis: None,
+            self_test: None,
+            tone_player: None,
+            drone_player: None,
+            metronome: None,
+            metronome_beat: None,
+            tone_target: ToneTarget::A4,
+            level_meter: LevelMeter::default(),
+            needle: NeedleMotion::default(),
+            needle_scale,
+            in_tune_cue: InTuneCue::default(),
+            target_note: None,
+            picker_octave: 4,
+            octave_shift: None,
+            in_tune_flash: None,
+            status,
+            capture_started: Instant::now(),
+            scale,
+            scala_path_input,
+            recorder: None,
+            suspended: false,
+            tray: None,
+            raise_requests: None,
+            global_shortcut: None,
+            overlay: false,
+            shortcut_input,
+            window_hidden: false,
+            quitting: false,
+            recordings_path_input,
+            detection_rate: DetectionRate::default(),
+            history: PitchHistory::default(),
+            show_history: false,
+            stats: SessionStats::default(),
+            show_stats: false,
+            detection_log,
+            export_path_input: String::new(),
+            export: None,
+            midi: None,
+            midi_ports: Vec::new(),
+            osc: None,
+            websocket: None,
+            websocket_port: 0,
+            osc_target_input: String::new(),
+        };
+        app.osc_target_input = app.config.osc_target.clone();
+        app.apply_midi();
+        app.apply_osc();
+        app.apply_websocket();
+        app
+    }
+
+    fn set_input(&mut self, mut input: Box<dyn AudioSource>) {
+        self.stop_recording();
+        self.current_device_name = input.name().to_string();
+        if self.suspended {
+            if let Err(err) = input.pause() {
+                log::warn!("Failed to pause '{}': {}", self.current_device_name, err);
+            }
+        }
+        self.input = Some(input);
+        self.capture_started = Instant::now();
+        self.apply_device_gain();
+    }
+
+    /// Software gain in dB set for the current device.
+    fn device_gain_db(&self) -> f32 {
+        self.config
+            .device_gains
+            .get(&self.current_device_name)
+            .copied()
+            .unwrap_or(0.0)
+    }
+
+    /// Has the input apply the current device's gain.
+    fn apply_device_gain(&mut self) {
+        let gain = db_to_gain(self.device_gain_db());
+        if let Ok(mut audio_data) = self.audio_data.lock() {
+            audio_data.gain = gain;
+        }
+    }
+
+    /// Pauses capture and analysis while the window is minimized or hidden in
+    /// the tray, unless the user wants to keep listening or a recording is
+    /// running.
+    fn suspend_when_hidden(&mut self, ctx: &egui::Context, now: Instant) {
+        let minimized = ctx.input(|input| input.viewport().minimized == Some(true));
+        self.set_suspended(
+            (minimized || self.window_hidden)
+                && !self.config.listen_in_background
+                && self.recorder.is_none(),
+            now,
+        );
+    }
+
+    fn set_suspended(&mut self, suspended: bool, now: Instant) {
+        if self.suspended == suspended {
+            return;
+        }
+        self.suspended = suspended;
+        if let Some(input) = &mut self.input {
+            let result = if suspended {
+                input.pause()
+            } else {
+                input.resume()
+            };
+            if let Err(err) = result {
+                log::warn!("Failed to pause or resume '{}': {}", input.name(), err);
+            }
+        }
+        // Paused streams deliver nothing; don't mistake that for a stall.
+        self.capture_started = now;
+        self.pitch_worker.set_muted(self.analysis_paused());
+        log::info!(
+            "{} capture while hidden",
+            if suspended { "Pausing" } else { "Resuming" }
+        );
+    }
+
+    /// Adds quick controls in the tray, and lets closing the window hide it
+    /// there if the user chose that.
+    pub fn set_tray(&mut self, tray: Tray) {
+        self.tray = Some(tray);
+    }
+
+    fn poll_tray(&mut self, ctx: &egui::Context) {
+        let Some(tray) = &mut self.tray else {
+            return;
+        };
+        tray.show_devices(&self.available_devices, &self.current_device_name);
+        while let Some(command) = self.tray.as_ref().and_then(Tray::try_recv) {
+            self.handle_tray_command(ctx, command);
+        }
+    }
+
+    /// Brings the window to the front when later launches ask to.
+    pub fn set_raise_requests(&mut self, requests: RaiseRequests) {
+        self.raise_requests = Some(requests);
+    }
+
+    fn poll_raise_requests(&mut self, ctx: &egui::Context) {
+        if self
+            .raise_requests
+            .as_ref()
+            .is_some_and(RaiseRequests::try_recv)
+        {
+            self.show_window(ctx);
+        }
+    }
+
+    /// Lets the saved system-wide shortcut show and hide the window,
+    /// reporting if it can't be registered.
+    pub fn set_global_shortcut(&mut self, shortcut: GlobalShortcut) {
+        self.global_shortcut = Some(shortcut);
+        let text = self.config.toggle_shortcut.clone();
+        self.set_toggle_shortcut(&text);
+    }
+
+    /// Changes the system-wide shortcut, keeping the old one if `text` isn't
+    /// a key combination or is taken.
+    fn set_toggle_shortcut(&mut self, text: &str) {
+        let text = text.trim();
+        let result = match &mut self.global_shortcut {
+            Some(shortcut) => shortcut.set(text),
+            None => parse_shortcut(text).map(|_| ()),
+        };
+        match result {
+            Ok(()) => {
+                if self.config.toggle_shortcut != text {
+                    self.config.toggle_shortcut = text.to_string();
+                    self.config_dirty = true;
+                }
+            }
+            Err(err) => self.report_error(format!(
+                "Can't use {} to show and hide the tuner: {:#}; choose another in settings",
+                text, err
+            )),
+        }
+    }
+
+    fn poll_global_shortcut(&mut self, ctx: &egui::Context) {
+        if self
+            .global_shortcut
+            .as_ref()
+            .is_some_and(GlobalShortcut::try_recv)
+        {
+            self.toggle_window(ctx);
+        }
+    }
+
+    /// Hides the window, or brings it back if it is hidden or minimized.
+    fn toggle_window(&mut self, ctx: &egui::Context) {
+        let minimized = ctx.input(|input| input.viewport().minimized == Some(true));
+        if self.window_hidden || minimized {
+            self.show_window(ctx);
+        } else {
+            self.set_window_hidden(ctx, true);
+        }
+    }
+
+    fn show_window(&mut self, ctx: &egui::Context) {
+        ctx.send_viewport_cmd(egui::ViewportCommand::Minimized(false));
+        self.set_window_hidden(ctx, false);
+    }
+
+    fn handle_tray_command(&mut self, ctx: &egui::Context, command: TrayCommand) {
+        match command {
+            TrayCommand::ToggleWindow => self.set_window_hidden(ctx, !self.window_hidden),
+            TrayCommand::ToggleOverlay => self.set_overlay(ctx, !self.overlay),
+            TrayCommand::SelectDevice(device_name) => {
+                if device_name != self.current_device_name {
+                    self.select_device(device_name);
+                }
+            }
+            TrayCommand::Quit => {
+                self.quitting = true;
+                ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+            }
+        }
+    }
+
+    fn set_window_hidden(&mut self, ctx: &egui::Context, hidden: bool) {
+        self.window_hidden = hidden;
+        ctx.send_viewport_cmd(egui::ViewportCommand::Visible(!hidden));
+        if !hidden {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+        }
+    }
+
+    /// Turns closing the window into hiding it, when the tray can bring it back.
+    fn close_to_tray(&mut self, ctx: &egui::Context) {
+        if self.config.close_to_tray
+            && self.tray.is_some()
+            && !self.quitting
+            && ctx.input(|input| input.viewport().close_requested())
+        {
+            ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+            self.set_window_hidden(ctx, true);
+        }
+    }
+
+    fn analysis_paused(&self) -> bool {
+        self.tone_playing() || self.suspended
+    }
+
+    /// Analyses `audio_capture`, applying its calibrated gate if there is one.
+    pub fn set_audio_capture(&mut self, audio_capture: AudioCapture) {
+        self.set_input(Box::new(audio_capture));
+        self.apply_device_threshold();
+    }
+
+    /// Analyses a WAV file instead of a capture device until another device is
+    /// picked.
+    pub fn set_file_source(&mut self, file_source: FileSource) {
+        self.set_input(Box::new(file_source));
+        self.restart_worker();
+    }
+
+    /// Switches to the magnitude gate calibrated for the current device, if any.
+    fn apply_device_threshold(&mut self) {
+        let Some(&threshold) = self.config.device_thresholds.get(&self.current_device_name) else {
+            return;
+        };
+        if threshold != self.config.min_magnitude_threshold {
+            self.config.min_magnitude_threshold = threshold;
+            self.apply_config();
+        }
+    }
+
+    fn poll_calibration(&mut self, now: Instant) {
+        if let Some(calibration) = self.pitch_worker.take_finished_calibration(now) {
+            self.apply_calibration(calibration);
+        }
+    }
+
+    fn apply_calibration(&mut self, calibration: NoiseCalibration) {
+        let threshold = calibration.threshold();
+        log::info!(
+            "Calibrated '{}': peak magnitude {:.4}, peak RMS {:.4}, gate {:.4}",
+            self.current_device_name,
+            calibration.peak_magnitude,
+            calibration.peak_rms,
+            threshold
+        );
+        self.config.min_magnitude_threshold = threshold;
+        self.config
+            .device_thresholds
+            .insert(self.current_device_name.clone(), threshold);
+        self.apply_config();
+    }
+
+    fn calibration_label(&self) -> Option<String> {
+        if self.pitch_worker.calibrating() {
+            Some("Listening to the room, stay quiet…".to_string())
+        } else if self
+            .config
+            .device_thresholds
+            .contains_key(&self.current_device_name)
+        {
+            Some(format!("Calibrated for {}", self.current_device_name))
+        } else {
+            None
+        }
+    }
+
+    fn toggle_recording(&mut self) {
+        if self.recorder.is_some() {
+            self.stop_recording();
+            return;
+        }
+
+        let Some(path) = self.config.recording_path(SystemTime::now()) else {
+            self.report_error("No data directory available for recordings".to_string());
+            return;
+        };
+        match Recorder::start(&path, &self.audio_data) {
+            Ok(recorder) => self.recorder = Some(recorder),
+            Err(err) => self.report_error(format!("Failed to start recording: {:#}", err)),
+        }
+    }
+
+    /// Finalizes the current recording, if any. Called before the input
+    /// changes so a file never mixes devices or sample rates.
+    fn stop_recording(&mut self) {
+        let Some(recorder) = self.recorder.take() else {
+            return;
+        };
+        let path = recorder.path().to_path_buf();
+        match recorder.stop(&self.audio_data) {
+            Ok(()) => log::info!("Saved recording to {}", path.display()),
+            Err(err) => self.report_error(format!("Recording stopped: {:#}", err)),
+        }
+    }
+
+    fn poll_recorder(&mut self) {
+        if self.recorder.as_ref().is_some_and(Recorder::failed) {
+            self.stop_recording();
+        }
+    }
+
+    /// Opens or closes the MIDI output to match the settings.
+    fn apply_midi(&mut self) {
+        self.midi = None;
+        if !self.config.midi_enabled {
+            return;
+        }
+        let Some(port) = self.config.midi_port.clone() else {
+            return;
+        };
+        match MidiSender::open(&port) {
+            Ok(sender) => self.midi = Some(sender),
+            Err(err) => self.report_error(format!("{:#}", err)),
+        }
+    }
+
+    fn send_midi(&mut self, reading: &PitchReading) {
+        let Some(sender) = &mut self.midi else {
+            return;
+        };
+        if let Err(err) = sender.send_note(reading.note.as_ref(), self.config.midi_bend_range) {
+            self.midi = None;
+            self.report_error(format!("{:#}", err));
+        }
+    }
+
+    fn apply_osc(&mut self) {
+        self.osc = None;
+        if !self.config.osc_enabled {
+            return;
+        }
+        match OscSender::open(&self.config.osc_target) {
+            Ok(sender) => self.osc = Some(sender),
+            Err(err) => self.report_error(format!("{:#}", err)),
+        }
+    }
+
+    fn apply_websocket(&mut self) {
+        self.websocket = None;
+        self.websocket_port = self.config.websocket_port;
+        if !self.config.websocket_enabled {
+            return;
+        }
+        match WebSocketServer::open(("0.0.0.0", self.config.websocket_port)) {
+            Ok(server) => self.websocket = Some(server),
+            Err(err) => self.report_error(format!(
+                "{:#} {}; choose another in settings",
+                err, self.config.websocket_port
+            )),
+        }
+    }
+
+    /// Reopens the server once the port has been dragged or typed to a new
+    /// one. `DragValue` writes typed digits as they come, so by the time
+    /// editing finishes the field no longer reports a change.
+    fn finish_websocket_port_edit(&mut self) {
+        if self.config.websocket_port != self.websocket_port {
+            self.config_dirty = true;
+            self.apply_websocket();
+        }
+    }
+
+    fn refresh_midi_ports(&mut self) {
+        match midi::output_port_names() {
+            Ok(ports) => self.midi_ports = ports,
+            Err(err) => log::warn!("Failed to list MIDI ports: {}", err),
+        }
+    }
+
+    fn switch_device(&mut self, device_name: String, device: cpal::Device, remember: bool) {
+        let buffer_frames = self.config.buffer_frames;
+        self.open_device(device_name, remember, |audio_data| {
+            AudioCapture::new_with_device(audio_data, device, buffer_frames)
+        });
+    }
+
+    /// Opens a capture stream and restarts analysis on success. Only explicit
+    /// user choices are remembered as the preferred device.
+    fn open_device(
+        &mut self,
+        device_name: String,
+        remember: bool,
+        open: impl FnOnce(Arc<Mutex<AudioData>>) -> anyhow::Result<AudioCapture>,
+    ) {
+        self.capture_started = Instant::now();
+
+        match open(self.audio_data.clone()) {
+            Ok(new_capture) => {
+                self.set_audio_capture(new_capture);
+                // The old stream may have pushed more before it was dropped.
+                if let Ok(mut audio_data) = self.audio_data.lock() {
+                    audio_data.clear();
+                }
+                self.status = None;
+                self.restart_worker();
+
+                let device_name = self.current_device_name.clone();
+                if remember && self.config.preferred_device.as_ref() != Some(&device_name) {
+                    self.config.preferred_device = Some(device_name);
+                    self.config_dirty = true;
+                    self.save_config();
+                }
+            }
+            Err(err) => self.report_error(format!("Failed to open '{}': {}", device_name, err)),
+        }
+    }
+
+    /// Reopens capture after the current device disappeared or stopped delivering
+    /// samples, preferring the saved device and falling back to the default.
+    fn recover_capture(&mut self) {
+        self.input = None;
+
+        let preferred = self
+            .config
+            .preferred_device
+            .clone()
+            .filter(|_| !self.config.follow_default_device)
+            .filter(|preferred| self.available_devices.contains(preferred))
+            .and_then(|preferred| Some((find_input_device(&preferred)?, preferred)));
+
+        let buffer_frames = self.config.buffer_frames;
+        match preferred {
+            Some((device, name)) => self.open_device(name, false, |audio_data| {
+                AudioCapture::new_with_device(audio_data, device, buffer_frames)
+            }),
+            None => self.open_device("default input".to_string(), false, |audio_data| {
+                AudioCapture::new(audio_data, buffer_frames)
+            }),
+        }
+    }
+
+    /// Reopens the current device, e.g. with a new buffer size. Files carry
+    /// on as they are.
+    fn reopen_capture(&mut self) {
+        if !self.input.as_ref().is_some_and(|input| input.is_live()) {
+            return;
+        }
+        let device_name = self.current_device_name.clone();
+        match find_input_device(&device_name) {
+            Some(device) => self.switch_device(device_name, device, false),
+            None => self.recover_capture(),
+        }
+    }
+
+    fn check_stream_watchdog(&mut self, now: Instant) {
+        if self.suspended {
+            return;
+        }
+        let last_push = self
+            .audio_data
+            .lock()
+            .ok()
+            .and_then(|audio_data| audio_data.last_push());
+
+        if stream_stalled(last_push, self.capture_started, now) {
+            log::warn!(
+                "No audio from '{}' for {:?}, reopening capture",
+                self.current_device_name,
+                STREAM_STALL_TIMEOUT
+            );
+            self.recover_capture();
+        }
+    }
+
+    /// Shows `text` in the error banner and logs it.
+    pub fn report_error(&mut self, text: String) {
+        log::error!("{}", text);
+        self.status = Some(StatusMessage { text, error: true });
+    }
+
+    fn report_success(&mut self, text: String) {
+        log::info!("{}", text);
+        self.status = Some(StatusMessage { text, error: false });
+    }
+
+    fn poll_stream_errors(&mut self) {
+        let error = self
+            .audio_data
+            .lock()
+            .ok()
+            .and_then(|mut audio_data| audio_data.take_stream_error());
+        if let Some(error) = error {
+            self.status = Some(StatusMessage {
+                text: error,
+                error: true,
+            });
+        }
+    }
+
+    fn draw_status_banner(&mut self, ctx: &egui::Context) {
+        let Some(status) = &self.status else {
+            return;
+        };
+
+        let mut dismissed = false;
+        egui::Area::new(egui::Id::new("status_banner"))
+            .anchor(egui::Align2::CENTER_TOP, egui::vec2(0.0, 28.0))
+            .show(ctx, |ui| {
+                let fill = if status.error {
+                    self.palette.error_fill
+                } else {
+                    self.palette.success_fill
+                };
+                egui::Frame::new()
+                    .fill(fill)
+                    .corner_radius(6.0)
+                    .inner_margin(egui::Margin::symmetric(8, 4))
+                    .show(ui, |ui| {
+                        ui.set_max_width(WINDOW_SIZE.x - 40.0);
+                        ui.horizontal(|ui| {
+                            ui.add(
+                                egui::Label::new(
+                                    egui::RichText::new(&status.text)
+                                        .size(11.0)
+                                        .color(BANNER_TEXT),
+                                )
+                                .wrap(),
+                            );
+                            dismissed = ui
+                                .add(
+                                    egui::Button::new(egui::RichText::new("✕").color(BANNER_TEXT))
+                                        .frame(false),
+                                )
+                                .on_hover_text("Dismiss")
+                                .clicked();
+                        });
+                    });
+            });
+
+        if dismissed {
+            self.status = None;
+        }
+    }
+
+    fn select_channel(&mut self, selection: ChannelSelection) {
+        if let Ok(mut audio_data) = self.audio_data.lock() {
+            audio_data.channel_selection = selection;
+        }
+        self.restart_worker();
+    }
+
+    fn restart_worker(&mut self) {
+        self.pitch_worker.stop();
+        self.pitch_worker = PitchWorker::start(self.audio_data.clone(), self.analysis_settings());
+        self.pitch_worker.set_muted(self.analysis_paused());
+        self.reading = PitchReading::default();
+    }
+
+    fn analysis_settings(&self) -> AnalysisSettings {
+        let mut settings = AnalysisSettings::from(&self.config);
+        settings.preset = self.active_preset.clone();
+        settings.scale = self.scale.clone();
+        settings.capture_strum = self.show_strum && self.active_preset.is_some();
+        settings.target_note = self.target_note;
+        settings.frequency_range = self.frequency_range();
+        settings.frequency_prior = self.octave_shift.map(|shift| shift.prior);
+        settings
+    }
+
+    /// Moves detection `octaves` up or down from the note on display, for
+    /// when the detector keeps reading the wrong octave, until the note
+    /// changes or goes silent.
+    fn shift_octave(&mut self, octaves: i32) {
+        let center = match (self.octave_shift, &self.reading.note) {
+            (Some(shift), _) => shift.prior.center,
+            (None, Some(note)) => note.target_frequency,
+            (None, None) => return,
+        };
+        self.octave_shift = Some(OctaveShift {
+            prior: FrequencyPrior {
+                center: center * 2.0f32.powi(octaves),
+                range_cents: OCTAVE_SHIFT_RANGE_CENTS,
+            },
+            settled: false,
+        });
+        self.pitch_worker.set_settings(self.analysis_settings());
+    }
+
+    /// Drops the octave shift once `reading` falls silent, or moves well
+    /// away from the shifted octave after settling in it.
+    fn follow_octave_shift(&mut self, reading: &PitchReading) {
+        let Some(shift) = &mut self.octave_shift else {
+            return;
+        };
+        let release = match &reading.note {
+            None => true,
+            Some(note) => {
+                let cents = 1200.0 * (note.frequency / shift.prior.center).log2();
+                let near = cents.abs() <= OCTAVE_SHIFT_RELEASE_CENTS;
+                let release = shift.settled && !near;
+                shift.settled |= near;
+                release
+            }
+        };
+        if release {
+            self.octave_shift = None;
+            self.pitch_worker.set_settings(self.analysis_settings());
+        }
+    }
+
+    /// Fundamentals searched for: the custom range if one is set, otherwise
+    /// the selected preset's, otherwise the whole chromatic range.
+    fn frequency_range(&self) -> (f32, f32) {
+        if self.config.custom_frequency_range {
+            return (self.config.min_frequency, self.config.max_frequency);
+        }
+        self.active_preset
+            .as_ref()
+            .map_or(CHROMATIC_RANGE, |preset| preset.frequency_range())
+    }
+
+    /// Locks the tuner to one note, however far off the pitch is, or goes
+    /// back to snapping to the nearest one.
+    fn set_target_note(&mut self, midi: Option<i32>) {
+        self.target_note = midi;
+        self.pitch_worker.set_settings(self.analysis_settings());
+    }
+
+    /// Clicking the note name opens a picker that locks the tuner to a note;
+    /// clicking it again while locked goes back to chromatic.
+    fn draw_target_note_picker(&mut self, heading: egui::Response) {
+        if self.target_note.is_some() {
+            if heading.on_hover_text("Back to chromatic (Esc)").clicked() {
+                self.set_target_note(None);
+            }
+            return;
+        }
+
+        let heading = heading.on_hover_text("Tune to a fixed note");
+        if heading.clicked() {
+            if let Some(note) = &self.reading.note {
+                self.picker_octave = note.octave;
+            }
+        }
+        let mut picked = None;
+        egui::Popup::menu(&heading)
+            .close_behavior(egui::PopupCloseBehavior::CloseOnClickOutside)
+            .show(|ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Octave");
+                    ui.add(egui::DragValue::new(&mut self.picker_octave).range(0..=8));
+                });
+                egui::Grid::new("target_note_grid").show(ui, |ui| {
+                    for pitch_class in 0..12 {
+                        let name = self.note_naming().pitch_class_name(pitch_class);
+                        if ui.button(name).clicked() {
+                            picked = Some((self.picker_octave + 1) * 12 + pitch_class as i32);
+                            ui.close();
+                        }
+                        if pitch_class % 6 == 5 {
+                            ui.end_row();
+                        }
+                    }
+                });
+            });
+        if picked.is_some() {
+            self.set_target_note(picked);
+        }
+    }
+
+    fn apply_config(&mut self) {
+        if let Ok(mut audio_data) = self.audio_data.lock() {
+            audio_data.set_buffer_size(self.config.analysis_size());
+        }
+        self.pitch_worker.set_settings(self.analysis_settings());
+        self.detection_log.set_capacity(self.config.log_capacity);
+        self.config_dirty = true;
+    }
+
+    /// Makes `preset` the active instrument, shifted by its saved
+    /// transposition and the tuning offset.
+    fn select_preset(&mut self, preset: Option<Arc<InstrumentPreset>>) {
+        let preset = preset.map(|preset| transpose_preset(&preset, &self.config));
+        self.config.instrument_preset = preset.as_ref().map(|preset| preset.name.clone());
+        if self.guided.is_some() {
+            self.guided = preset.clone().map(GuidedTuning::new);
+        }
+        self.active_preset = preset;
+        self.config_dirty = true;
+        if let ToneTarget::String(string_number) = self.tone_target {
+            if self.target_string(string_number).is_none() {
+                self.tone_target = ToneTarget::A4;
+            }
+        }
+        self.restart_worker();
+    }
+
+    /// Shifts the active preset for a capo or a retuned instrument, and
+    /// remembers it for that preset.
+    fn set_preset_transpose(&mut self, semitones: i32) {
+        let Some(preset) = self.active_preset.clone() else {
+            return;
+        };
+        let semitones = semitones.clamp(MIN_TRANSPOSE, MAX_TRANSPOSE);
+        if semitones == 0 {
+            self.config.preset_transpose.remove(&preset.name);
+        } else {
+            self.config
+                .preset_transpose
+                .insert(preset.name.clone(), semitones);
+        }
+        self.select_preset(Some(preset));
+    }
+
+    /// The octave the intonation keyboard starts at: as chosen for the
+    /// active preset, or its lowest string's, or as chosen for chromatic mode.
+    fn keyboard_octave(&self) -> i32 {
+        match &self.active_preset {
+            Some(preset) => self
+                .config
+                .preset_keyboard_octaves
+                .get(&preset.name)
+                .copied()
+                .or(preset.lowest_octave())
+                .unwrap_or(self.config.keyboard_octave),
+            None => self.config.keyboard_octave,
+        }
+    }
+
+    /// Moves the intonation keyboard to start at `octave`, remembered for the
+    /// active preset or for chromatic mode.
+    fn set_keyboard_octave(&mut self, octave: i32) {
+        let octave = octave.clamp(
+            *KEYBOARD_START_OCTAVES.start(),
+            *KEYBOARD_START_OCTAVES.end(),
+        );
+        match &self.active_preset {
+            Some(preset) if preset.lowest_octave() == Some(octave) => {
+                self.config.preset_keyboard_octaves.remove(&preset.name);
+            }
+            Some(preset) => {
+                self.config
+                    .preset_keyboard_octaves
+                    .insert(preset.name.clone(), octave);
+            }
+            None => self.config.keyboard_octave = octave,
+        }
+        self.config_dirty = true;
+    }
+
+    fn set_tuning_offset(&mut self, cents: f32) {
+        self.config.tuning_offset_cents = cents;
+        self.select_preset(self.active_preset.clone());
+    }
+
+    fn load_scale(&mut self) {
+        let path = std::path::PathBuf::from(self.scala_path_input.trim());
+        match ScalaScale::load(&path) {
+            Ok(scale) => {
+                log::info!(
+                    "Loaded scale '{}' from {}",
+                    scale.description,
+                    path.display()
+                );
+                self.scale = Some(Arc::new(scale));
+                self.config.scala_file = Some(path);
+                self.status = None;
+                self.apply_config();
+            }
+            Err(err) => self.report_error(format!("{:#}", err)),
+        }
+    }
+
+    fn clear_scale(&mut self) {
+        self.scale = None;
+        self.config.scala_file = None;
+        self.apply_config();
+    }
+
+    fn target_string(&self, string_number: usize) -> Option<&Note> {
+        self.active_preset.as_ref()?.string(string_number)
+    }
+
+    fn tone_label(&self, target: ToneTarget) -> String {
+        match target {
+            ToneTarget::A4 => "A4".to_string(),
+            ToneTarget::String(string_number) => match self.target_string(string_number) {
+                Some(note) => format!(
+                    "{} ({})",
+                    note.display_name(self.note_naming()),
+                    string_number
+                ),
+                None => "A4".to_string(),
+            },
+        }
+    }
+
+    fn tone_frequency(&self) -> f32 {
+        let a4_reference = self.config.a4_reference;
+        match self.tone_target {
+            ToneTarget::A4 => a4_reference,
+            ToneTarget::String(string_number) => self
+                .target_string(string_number)
+                .map_or(a4_reference, |note| {
+                    note.target_frequency * a4_reference / DEFAULT_A4_FREQUENCY
+                }),
+        }
+    }
+
+    fn tone_playing(&self) -> bool {
+        self.tone_player
+            .as_ref()
+            .is_some_and(|player| player.is_playing())
+    }
+
+    /// Flashes the window and beeps, as configured, once the note has
+    /// settled in tune.
+    fn cue_in_tune(&mut self, reading: &PitchReading, now: Instant) {
+        if !self.config.in_tune_flash && !self.config.in_tune_beep {
+            return;
+        }
+        let note = reading
+            .note
+            .as_ref()
+            .map(|note| (note.target_frequency, reading.smoothed_cents));
+        let dwell = Duration::from_millis(self.config.in_tune_dwell_ms);
+        if !self
+            .in_tune_cue
+            .update(note, self.config.in_tune_cents, dwell, now)
+        {
+            return;
+        }
+
+        if self.config.in_tune_flash {
+            self.in_tune_flash = Some(now);
+        }
+        if self.config.in_tune_beep {
+            if self.tone_player.is_none() {
+                match TonePlayer::new(IN_TUNE_BEEP_FREQUENCY) {
+                    Ok(player) => self.tone_player = Some(player),
+                    Err(err) => {
+                        self.report_error(format!(
+                            "Failed to open the in-tune beep output: {}",
+                            err
+                        ));
+                        return;
+                    }
+                }
+            }
+            if let Some(player) = &self.tone_player {
+                player.beep(IN_TUNE_BEEP_FREQUENCY, IN_TUNE_BEEP_DURATION);
+            }
+        }
+    }
+
+    /// Tints the whole window with the in-tune color, fading out.
+    fn draw_in_tune_flash(&mut self, ctx: &egui::Context, now: Instant) {
+        let Some(started) = self.in_tune_flash else {
+            return;
+        };
+        let progress = now.duration_since(started).as_secs_f32() / IN_TUNE_FLASH_TIME.as_secs_f32();
+        if progress >= 1.0 {
+            self.in_tune_flash = None;
+            return;
+        }
+
+        let alpha = (IN_TUNE_FLASH_ALPHA * (1.0 - progress)) as u8;
+        ctx.layer_painter(egui::LayerId::new(
+            egui::Order::Foreground,
+            egui::Id::new("in_tune_flash"),
+        ))
+        .rect_filled(
+            ctx.screen_rect(),
+            0.0,
+            Theme::translucent(self.palette.in_tune, alpha),
+        );
+        ctx.request_repaint();
+    }
+
+    fn toggle_tone(&mut self) {
+        let frequency = self.tone_frequency();
+
+        if self.tone_player.is_none() {
+            match TonePlayer::new(frequency) {
+                Ok(player) => self.tone_player = Some(player),
+                Err(err) => {
+                    self.report_error(format!("Failed to open reference tone output: {}", err));
+                    return;
+                }
+            }
+        }
+
+        if let Some(player) = &mut self.tone_player {
+            if player.is_playing() {
+                player.stop();
+            } else {
+                player.set_frequency(frequency);
+                player.play();
+            }
+        }
+
+        self.pitch_worker.set_muted(self.analysis_paused());
+    }
+
+    /// The drone note's frequency in the configured temperament, so that the
+    /// needle reads it as in tune.
+    fn drone_frequency(&self) -> f32 {
+        tempered_frequency(
+            self.config.drone_note,
+            self.config.a4_reference,
+            self.config.temperament,
+            self.config.temperament_tonic,
+        )
+    }
+
+    fn drone_playing(&self) -> bool {
+        self.drone_player
+            .as_ref()
+            .is_some_and(|player| player.is_playing())
+    }
+
+    fn toggle_drone(&mut self) {
+        if self.drone_player.is_none() {
+            match TonePlayer::new(self.drone_frequency()) {
+                Ok(player) => self.drone_player = Some(player),
+                Err(err) => {
+                    self.report_error(format!("Failed to open drone output: {}", err));
+                    return;
+                }
+            }
+        }
+
+        let frequency = self.drone_frequency();
+        if let Some(player) = &mut self.drone_player {
+            if player.is_playing() {
+                player.stop();
+            } else {
+                player.set_frequency(frequency);
+                player.set_waveform(self.config.drone_waveform);
+                player.set_volume(self.config.drone_volume);
+                player.play();
+            }
+        }
+    }
+
+    fn metronome_running(&self) -> bool {
+        self.metronome
+            .as_ref()
+            .is_some_and(|metronome| metronome.is_running())
+    }
+
+    fn toggle_metronome(&mut self) {
+        if self.metronome.is_none() {
+            match MetronomePlayer::new() {
+                Ok(metronome) => self.metronome = Some(metronome),
+                Err(err) => {
+                    self.report_error(format!("Failed to open metronome output: {}", err));
+                    return;
+                }
+            }
+        }
+
+        if let Some(metronome) = &self.metronome {
+            if metronome.is_running() {
+                metronome.stop();
+            } else {
+                self.metronome_beat = None;
+                metronome.start(
+                    self.config.metronome_bpm,
+                    self.config.metronome_beats_per_bar,
+                );
+            }
+        }
+    }
+
+    /// Notes when a new metronome beat has sounded, for the beat indicator.
+    fn follow_metronome(&mut self, now: Instant) {
+        let Some(metronome) = self
+            .metronome
+            .as_ref()
+            .filter(|metronome| metronome.is_running())
+        else {
+            return;
+        };
+        metronome.set_tempo(
+            self.config.metronome_bpm,
+            self.config.metronome_beats_per_bar,
+        );
+        let Some(beat) = metronome.last_beat() else {
+            return;
+        };
+        if self.metronome_beat.is_none_or(|(seen, _)| seen != beat) {
+            self.metronome_beat = Some((beat, now));
+        }
+    }
+
+    /// A dot that lights up on each beat while the metronome runs, in the
+    /// in-tune color on the downbeat.
+    fn draw_metronome_beat(&self, painter: &egui::Painter, center: egui::Pos2, scale: f32) {
+        if !self.metronome_running() {
+            return;
+        }
+        let radius = 5.0 * scale;
+        painter.circle_stroke(
+            center,
+            radius,
+            egui::Stroke::new(1.0, self.theme.secondary_text),
+        );
+        let Some((beat, seen)) = self.metronome_beat else {
+            return;
+        };
+        let progress = seen.elapsed().as_secs_f32() / METRONOME_PULSE_TIME.as_secs_f32();
+        if progress >= 1.0 {
+            return;
+        }
+        let color = if beat.in_bar == 0 {
+            self.palette.in_tune
+        } else {
+            self.theme.text
+        };
+        painter.circle_filled(
+            center,
+            radius,
+            Theme::translucent(color, (255.0 * (1.0 - progress)) as u8),
+        );
+    }
+
+    /// Play button, tempo and bar length of the metronome on one settings row.
+    fn draw_metronome_settings(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut changed = false;
+        ui.label("Metronome");
+        ui.horizontal(|ui| {
+            let running = self.metronome_running();
+            if ui
+                .selectable_label(running, if running { "■ Stop" } else { "▶ Play" })
+                .on_hover_text("Click in time, accenting the first beat of each bar")
+                .clicked()
+            {
+                self.toggle_metronome();
+            }
+            changed |= ui
+                .add(
+                    egui::DragValue::new(&mut self.config.metronome_bpm)
+                        .range(MIN_BPM..=MAX_BPM)
+                        .suffix(" BPM"),
+                )
+                .changed();
+            changed |= ui
+                .add(
+                    egui::DragValue::new(&mut self.config.metronome_beats_per_bar)
+                        .range(1..=MAX_BEATS_PER_BAR)
+                        .suffix(" / bar"),
+                )
+                .on_hover_text("Beats per bar")
+                .changed();
+        });
+        ui.end_row();
+        changed
+    }
+
+    /// Play button, note and octave of the drone on one settings row, and its
+    /// waveform and volume on the next.
+    fn draw_drone_settings(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut changed = false;
+        let mut pitch_class = self.config.drone_note.rem_euclid(12) as u8;
+        let mut octave = self.config.drone_note.div_euclid(12) - 1;
+        let naming = self.note_naming();
+
+        ui.label("Drone");
+        ui.horizontal(|ui| {
+            let playing = self.drone_playing();
+            if ui
+                .selectable_label(playing, if playing { "■ Stop" } else { "▶ Play" })
+                .on_hover_text("Sustain the note to play against")
+                .clicked()
+            {
+                self.toggle_drone();
+            }
+            egui::ComboBox::from_id_salt("drone_note_selector")
+                .selected_text(naming.pitch_class_name(pitch_class))
+                .width(45.0)
+                .show_ui(ui, |ui| {
+                    for class in 0..12 {
+                        changed |= ui
+                            .selectable_value(
+                                &mut pitch_class,
+                                class,
+                                naming.pitch_class_name(class),
+                            )
+                            .changed();
+                    }
+                });
+            changed |= ui
+                .add(egui::DragValue::new(&mut octave).range(1..=6))
+                .on_hover_text("Octave")
+                .changed();
+        });
+        ui.end_row();
+
+        ui.label("Drone sound");
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_salt("drone_waveform_selector")
+                .selected_text(self.config.drone_waveform.label())
+                .width(60.0)
+                .show_ui(ui, |ui| {
+                    for waveform in Waveform::ALL {
+                        changed |= ui
+                            .selectable_value(
+                                &mut self.config.drone_waveform,
+                                waveform,
+                                waveform.label(),
+                            )
+                            .changed();
+                    }
+                });
+            changed |= ui
+                .add(egui::Slider::new(&mut self.config.drone_volume, 0.0..=1.0).show_value(false))
+                .on_hover_text("Drone volume")
+                .changed();
+        });
+        ui.end_row();
+
+        self.config.drone_note = (octave + 1) * 12 + pitch_class as i32;
+        changed
+    }
+
+    /// Whether to snap to a key, its tonic and mode, and the notes of a
+    /// custom key on a row of their own.
+    fn draw_key_settings(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut changed = false;
+        let naming = self.config.note_naming;
+        let spelled = |tonic: u8, mode: KeyMode| {
+            Key {
+                tonic,
+                mode,
+                custom: PitchClassSet::default(),
+            }
+            .naming(naming)
+            .pitch_class_name(tonic)
+        };
+
+        ui.label("Key");
+        ui.horizontal(|ui| {
+            changed |= ui
+                .checkbox(&mut self.config.key_filter, "Snap to")
+                .on_hover_text(
+                    "Name notes outside the key as the nearest note in it, however far off",
+                )
+                .changed();
+            ui.add_enabled_ui(self.config.key_filter, |ui| {
+                let mode = self.config.key_mode;
+                ui.add_enabled_ui(mode != KeyMode::Custom, |ui| {
+                    egui::ComboBox::from_id_salt("key_tonic_selector")
+                        .selected_text(spelled(self.config.key_tonic, mode))
+                        .width(50.0)
+                        .show_ui(ui, |ui| {
+                            for tonic in 0..12 {
+                                changed |= ui
+                                    .selectable_value(
+                                        &mut self.config.key_tonic,
+                                        tonic,
+                                        spelled(tonic, mode),
+                                    )
+                                    .changed();
+                            }
+                        });
+                });
+                egui::ComboBox::from_id_salt("key_mode_selector")
+                    .selected_text(self.config.key_mode.label())
+                    .show_ui(ui, |ui| {
+                        for mode in KeyMode::ALL {
+                            changed |= ui
+                                .selectable_value(&mut self.config.key_mode, mode, mode.label())
+                                .changed();
+                        }
+                    });
+            });
+        });
+        ui.end_row();
+
+        if self.config.key_filter && self.config.key_mode == KeyMode::Custom {
+            ui.label("");
+            ui.horizontal(|ui| {
+                ui.spacing_mut().item_spacing.x = 2.0;
+                for pitch_class in 0..12 {
+                    let notes = &mut self.config.key_custom_notes;
+                    let selected = notes.contains(&pitch_class);
+                    if ui
+                        .selectable_label(selected, naming.pitch_class_name(pitch_class))
+                        .clicked()
+                    {
+                        if selected {
+                            notes.retain(|&note| note != pitch_class);
+                        } else {
+                            notes.push(pitch_class);
+                            notes.sort_unstable();
+                        }
+                        changed = true;
+                    }
+                }
+            });
+            ui.end_row();
+        }
+        changed
+    }
+
+    fn draw_tone_controls(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            let color = if self.tone_playing() {
+                self.theme.text
+            } else {
+                self.theme.secondary_text
+            };
+            let tone_button = ui.add(
+                egui::Button::new(egui::RichText::new("♪").size(14.0).color(color)).frame(false),
+            );
+            if tone_button
+                .on_hover_text("Play reference tone (detection pauses while it plays)")
+                .clicked()
+            {
+                self.toggle_tone();
+            }
+
+            let mut targets = vec![ToneTarget::A4];
+            if let Some(preset) = &self.active_preset {
+                targets.extend((1..=preset.strings.len()).rev().map(ToneTarget::String));
+            }
+
+            egui::ComboBox::from_id_salt("tone_selector")
+                .selected_text(egui::RichText::new(self.tone_label(self.tone_target)).size(11.0))
+                .width(70.0)
+                .show_ui(ui, |ui| {
+                    for target in targets {
+                        let label = self.tone_label(target);
+                        ui.selectable_value(&mut self.tone_target, target, label);
+                    }
+                });
+        });
+    }
+
+    fn draw_record_control(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            let (color, hover) = match &self.recorder {
+                Some(recorder) => (
+                    self.palette.alert,
+                    format!("Stop recording to {}", recorder.path().display()),
+                ),
+                None => (
+                    self.theme.secondary_text,
+                    "Record the input to a WAV file".to_string(),
+                ),
+            };
+            let record_button = ui.add(
+                egui::Button::new(egui::RichText::new("●").size(12.0).color(color)).frame(false),
+            );
+            if let Some(recorder) = &self.recorder {
+                let seconds = recorder.elapsed().as_secs();
+                ui.label(
+                    egui::RichText::new(format!("{}:{:02}", seconds / 60, seconds % 60))
+                        .size(11.0)
+                        .color(color),
+                );
+            }
+            if record_button.on_hover_text(hover).clicked() {
+                self.toggle_recording();
+            }
+        });
+    }
+
+    /// Starts guided tuning of the active preset's strings, or stops it.
+    fn set_guided(&mut self, guided: bool) {
+        self.guided = self
+            .active_preset
+            .clone()
+            .filter(|_| guided)
+            .map(GuidedTuning::new);
+        if guided {
+            self.challenge = None;
+        }
+    }
+
+    /// Starts a round of the hit-the-note challenge, or stops it.
+    fn set_challenge(&mut self, challenge: bool) {
+        self.challenge = challenge.then(|| {
+            let seed = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(1, |elapsed| elapsed.subsec_nanos());
+            ChallengeMode::new(
+                (
+                    self.config.challenge_low_note,
+                    self.config.challenge_high_note,
+                ),
+                self.config.challenge_key,
+                seed,
+                Instant::now(),
+            )
+        });
+        if challenge {
+            self.guided = None;
+        }
+    }
+
+    fn set_strum(&mut self, ctx: &egui::Context, show: bool) {
+        self.show_strum = show;
+        self.strum.clear();
+        self.pitch_worker.set_settings(self.analysis_settings());
+        self.resize_window(ctx);
+    }
+
+    fn draw_preset_selector(&mut self, ui: &mut egui::Ui) {
+        let selected_name = self
+            .active_preset
+            .as_ref()
+            .map_or("Chromatic", |preset| preset.name.as_str())
+            .to_string();
+
+        let mut selected = None;
+        egui::ComboBox::from_id_salt("preset_selector")
+            .selected_text(egui::RichText::new(selected_name).size(11.0))
+            .width(70.0)
+            .show_ui(ui, |ui| {
+                let mut guided = self.guided.is_some();
+                if ui
+                    .add_enabled(
+                        self.active_preset.is_some(),
+                        egui::Checkbox::new(&mut guided, "Guided"),
+                    )
+                    .on_hover_text("Walk through the strings one by one")
+                    .on_disabled_hover_text("Pick an instrument first")
+                    .changed()
+                {
+                    self.set_guided(guided);
+                }
+                let mut strum = self.show_strum;
+                if ui
+                    .add_enabled(
+                        self.active_preset.is_some(),
+                        egui::Checkbox::new(&mut strum, "Strum"),
+                    )
+                    .on_hover_text("Show every string at once while strumming them open")
+                    .on_disabled_hover_text("Pick an instrument first")
+                    .changed()
+                {
+                    self.set_strum(ui.ctx(), strum);
+                }
+                let mut challenge = self.challenge.is_some();
+                if ui
+                    .checkbox(&mut challenge, "Challenge")
+                    .on_hover_text("A game: play each note shown as fast and as in tune as you can")
+                    .changed()
+                {
+                    self.set_challenge(challenge);
+                }
+                self.draw_transpose_controls(ui);
+                if ui
+                    .button("Edit presets…")
+                    .on_hover_text("Add your own tunings")
+                    .clicked()
+                {
+                    self.preset_editor = Some(self.user_presets.clone());
+                }
+                ui.separator();
+                if ui
+                    .selectable_label(self.active_preset.is_none(), "Chromatic")
+                    .clicked()
+                {
+                    selected = Some(None);
+                }
+                for preset in &self.presets {
+                    let is_active = self
+                        .active_preset
+                        .as_ref()
+                        .is_some_and(|active| active.name == preset.name);
+                    if ui.selectable_label(is_active, &preset.name).clicked() {
+                        selected = Some(Some(preset.clone()));
+                    }
+                }
+            });
+
+        if let Some(preset) = selected {
+            if preset.is_none() && self.show_strum {
+                self.set_strum(ui.ctx(), false);
+            }
+            self.select_preset(preset);
+        }
+    }
+
+    /// Writes the user's presets file and offers its presets in the menu,
+    /// keeping the active preset if it still exists.
+    fn save_presets_file(&mut self, presets: Vec<UserPreset>) {
+        let Some(path) = self.user_presets_path.clone() else {
+            self.report_error("No config directory available for presets".to_string());
+            return;
+        };
+        if let Err(err) = save_user_presets(&path, &presets) {
+            self.report_error(format!("Failed to save presets: {:#}", err));
+            return;
+        }
+
+        let (merged, errors) = merge_presets(&presets);
+        self.user_presets = presets;
+        self.presets = merged;
+        if errors.is_empty() {
+            self.report_success(format!("Saved presets to {}", path.display()));
+        } else {
+            self.report_error(errors.join("; "));
+        }
+        let active = self.config.instrument_preset.as_ref().and_then(|name| {
+            self.presets
+                .iter()
+                .find(|preset| &preset.name == name)
+                .cloned()
+        });
+        self.select_preset(active);
+    }
+
+    /// Window for adding, removing and retuning the user's presets, written
+    /// back to the presets file on save.
+    fn draw_preset_editor(&mut self, ctx: &egui::Context) {
+        let Some(mut presets) = self.preset_editor.take() else {
+            return;
+        };
+        let naming = self.note_naming();
+        let mut open = true;
+        let mut save = false;
+        let mut cancel = false;
+
+        egui::Window::new("Instrument presets")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                egui::ScrollArea::vertical()
+                    .max_height(360.0)
+                    .show(ui, |ui| {
+                        let mut remove = None;
+                        for (index, preset) in presets.iter_mut().enumerate() {
+                            ui.push_id(index, |ui| {
+                                ui.horizontal(|ui| {
+                                    ui.add(
+                                        egui::TextEdit::singleline(&mut preset.name)
+                                            .desired_width(140.0)
+                                            .hint_text("Name"),
+                                    );
+                                    if ui.button("Remove").clicked() {
+                                        remove = Some(index);
+                                    }
+                                });
+                                self.draw_preset_strings(ui, preset, naming);
+                                if let Err(err) = preset.to_preset() {
+                                    ui.label(
+                                        egui::RichText::new(format!("{:#}", err))
+                                            .size(11.0)
+                                            .color(self.palette.warning),
+                                    );
+                                }
+                            });
+                            ui.separator();
+                        }
+                        if let Some(index) = remove {
+                            presets.remove(index);
+                        }
+                    });
+
+                ui.horizontal(|ui| {
+                    if ui.button("+ Preset").clicked() {
+                        presets.push(UserPreset {
+                            name: format!("Tuning {}", presets.len() + 1),
+                            strings: ["E2", "A2", "D3", "G3", "B3", "E4"]
+                                .map(|name| StringTuning::Note(name.to_string()))
+                                .to_vec(),
+                        });
+                    }
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        save = ui.button("Save").clicked();
+                        cancel = ui.button("Cancel").clicked();
+                    });
+                });
+            });
+
+        if save {
+            self.save_presets_file(presets);
+        } else if open && !cancel {
+            self.preset_editor = Some(presets);
+        }
+    }
+
+    /// Analyses the WAV file typed in the analysis window on a background
+    /// thread, with the current analysis settings.
+    fn start_analysis(&mut self, view: &mut AnalysisView) {
+        let path = PathBuf::from(view.path_input.trim());
+        let settings = AnalysisSettings::from(&self.config);
+        let (sender, receiver) = mpsc::channel();
+        let spawned = std::thread::Builder::new()
+            .name("offline-analysis".to_string())
+            .spawn(move || {
+                let result = offline::analyze_file(&path, &settings, |fraction| {
+                    let _ = sender.send(AnalysisMessage::Progress(fraction));
+                });
+                let _ = sender.send(AnalysisMessage::Done(result));
+            });
+        match spawned {
+            Ok(_) => {
+                view.receiver = Some(receiver);
+                view.progress = 0.0;
+            }
+            Err(err) => self.report_error(format!("Failed to start the analysis: {}", err)),
+        }
+    }
+
+    fn poll_analysis(&mut self, view: &mut AnalysisView) {
+        let Some(receiver) = &view.receiver else {
+            return;
+        };
+        let result = loop {
+            match receiver.try_recv() {
+                Ok(AnalysisMessage::Progress(fraction)) => view.progress = fraction,
+                Ok(AnalysisMessage::Done(result)) => break result,
+                Err(TryRecvError::Empty) => return,
+                Err(TryRecvError::Disconnected) => {
+                    break Err(anyhow::anyhow!("the analysis thread stopped"))
+                }
+            }
+        };
+        view.receiver = None;
+        match result {
+            Ok(curve) => view.show(curve),
+            Err(err) => self.report_error(format!("Analysis failed: {:#}", err)),
+        }
+    }
+
+    fn draw_analysis(&mut self, ctx: &egui::Context) {
+        let Some(mut view) = self.analysis.take() else {
+            return;
+        };
+        self.poll_analysis(&mut view);
+        let mut open = true;
+        let mut analyse = false;
+
+        egui::Window::new("Analyse a recording")
+            .open(&mut open)
+            .collapsible(false)
+            .default_width(560.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    let response = ui.add(
+                        egui::TextEdit::singleline(&mut view.path_input)
+                            .hint_text("recording.wav")
+                            .desired_width(360.0),
+                    );
+                    let entered =
+                        response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                    let ready = view.receiver.is_none() && !view.path_input.trim().is_empty();
+                    let clicked = ui
+                        .add_enabled(ready, egui::Button::new("Analyse"))
+                        .clicked();
+                    analyse = ready && (clicked || entered);
+                });
+                if view.receiver.is_some() {
+                    ui.add(egui::ProgressBar::new(view.progress).show_percentage());
+                    ctx.request_repaint_after(Duration::from_millis(50));
+                }
+                if view.curve.is_some() {
+                    self.draw_analysis_readout(ui, &view);
+                    self.draw_pitch_curve(ui, &mut view);
+                }
+            });
+
+        if analyse {
+            self.start_analysis(&mut view);
+        }
+        if open {
+            self.analysis = Some(view);
+        }
+    }
+
+    /// Plays the self-test tones through the analysis chain on a background
+    /// thread, with the current detection settings.
+    fn start_self_test(&mut self) {
+        let settings = AnalysisSettings::from(&self.config);
+        let (sender, receiver) = mpsc::channel();
+        let spawned = std::thread::Builder::new()
+            .name("self-test".to_string())
+            .spawn(move || {
+                let report = run_self_test(&settings, |fraction| {
+                    let _ = sender.send(SelfTestMessage::Progress(fraction));
+                });
+                let _ = sender.send(SelfTestMessage::Done(report));
+            });
+        match spawned {
+            Ok(_) => {
+                self.self_test = Some(SelfTestView {
+                    receiver: Some(receiver),
+                    progress: 0.0,
+                    report: None,
+                })
+            }
+            Err(err) => self.report_error(format!("Failed to start the self test: {}", err)),
+        }
+    }
+
+    fn draw_self_test(&mut self, ctx: &egui::Context) {
+        let Some(mut view) = self.self_test.take() else {
+            return;
+        };
+        if let Some(receiver) = &view.receiver {
+            for message in receiver.try_iter() {
+                match message {
+                    SelfTestMessage::Progress(fraction) => view.progress = fraction,
+                    SelfTestMessage::Done(report) => view.report = Some(report),
+                }
+            }
+            if view.report.is_some() {
+                view.receiver = None;
+            } else {
+                ctx.request_repaint_after(Duration::from_millis(50));
+            }
+        }
+
+        let naming = self.note_naming();
+        let small = |text: String| egui::RichText::new(text).size(11.0);
+        let mut open = true;
+        egui::Window::new("Self test")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let Some(report) = &view.report else {
+                    ui.label("Playing test tones through the analysis…");
+                    ui.add(egui::ProgressBar::new(view.progress).show_percentage());
+                    return;
+                };
+
+                egui::Grid::new("self_test_octaves")
+                    .num_columns(3)
+                    .spacing([16.0, 2.0])
+                    .show(ui, |ui| {
+                        ui.label(small("Octave".to_string()).strong());
+                        ui.label(small("Worst error".to_string()).strong());
+                        ui.label(small("Missed".to_string()).strong());
+                        ui.end_row();
+                        for octave in report.octaves() {
+                            ui.label(small(octave.octave.to_string()));
+                            let worst = octave.worst_cents.unwrap_or(0.0);
+                            let color = if worst > SELF_TEST_TOLERANCE_CENTS {
+                                self.palette.warning
+                            } else {
+                                self.theme.text
+                            };
+                            ui.label(small(format!("{:.2}¢", worst)).color(color));
+                            let color = if octave.failures > 0 {
+                                self.palette.warning
+                            } else {
+                                self.theme.text
+                            };
+                            ui.label(small(octave.failures.to_string()).color(color));
+                            ui.end_row();
+                        }
+                    });
+
+                ui.separator();
+                let flagged: Vec<_> = report.flagged().collect();
+                if flagged.is_empty() {
+                    ui.label(
+                        small(format!(
+                            "All {} tones read within ±{}¢",
+                            report.tones.len(),
+                            SELF_TEST_TOLERANCE_CENTS
+                        ))
+                        .color(self.palette.in_tune),
+                    );
+                    return;
+                }
+                ui.label(
+                    small(format!(
+                        "{} of {} tones off by more than {}¢ or missed:",
+                        flagged.len(),
+                        report.tones.len(),
+                        SELF_TEST_TOLERANCE_CENTS
+                    ))
+                    .color(self.palette.warning),
+                );
+                egui::ScrollArea::vertical()
+                    .max_height(160.0)
+                    .show(ui, |ui| {
+                        for tone in flagged {
+                            let played = format!(
+                                "{} {:+.0}¢",
+                                midi_to_note(tone.midi, 0.0, DEFAULT_A4_FREQUENCY)
+                                    .display_name(naming),
+                                tone.offset_cents
+                            );
+                            let read = match &tone.reading {
+                                Some(note) => {
+                                    format!("{} {:+.1}¢", note.display_name(naming), note.cents_off)
+                                }
+                                None => "nothing".to_string(),
+                            };
+                            ui.label(small(format!("{} read as {}", played, read)));
+                        }
+                    });
+            });
+
+        if open {
+            self.self_test = Some(view);
+        }
+    }
+
+    /// The time at the playhead and the note tracked there.
+    fn draw_analysis_readout(&self, ui: &mut egui::Ui, view: &AnalysisView) {
+        let Some(curve) = &view.curve else {
+            return;
+        };
+        let reading = match curve
+            .point_at(view.playhead)
+            .and_then(|point| point.note.as_ref())
+        {
+            Some(note) => format!(
+                "{} {:+.1}¢  {:.2} Hz",
+                note.display_name(self.note_naming()),
+                note.cents_off,
+                note.frequency
+            ),
+            None => "No pitch".to_string(),
+        };
+        ui.horizontal(|ui| {
+            ui.label(egui::RichText::new(format!("{:.2} s", view.playhead)).monospace());
+            ui.label(egui::RichText::new(reading).strong());
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                ui.label(
+                    egui::RichText::new("Scroll to zoom, right-drag to pan")
+                        .size(11.0)
+                        .color(self.theme.secondary_text),
+                );
+            });
+        });
+    }
+
+    /// The pitch curve on a note grid above the waveform, zoomed with the
+    /// scroll wheel, panned by dragging with the secondary button or shift,
+    /// and scrubbed by clicking or dragging.
+    fn draw_pitch_curve(&self, ui: &mut egui::Ui, view: &mut AnalysisView) {
+        let size = egui::vec2(ui.available_width().max(200.0), 260.0);
+        let (rect, response) = ui.allocate_exact_size(size, egui::Sense::click_and_drag());
+        let left = rect.left() + 34.0;
+        let plot = egui::Rect::from_min_max(
+            egui::pos2(left, rect.top()),
+            egui::pos2(rect.right(), rect.bottom() - 60.0),
+        );
+        let wave =
+            egui::Rect::from_min_max(egui::pos2(left, rect.bottom() - 56.0), rect.right_bottom());
+
+        let seconds_per_point = (view.view.1 - view.view.0) / plot.width();
+        let time_at =
+            |view: &AnalysisView, x: f32| view.view.0 + (x - plot.left()) * seconds_per_point;
+        if let Some(pointer) = response.hover_pos() {
+            let scroll = ui.input(|i| i.smooth_scroll_delta);
+            if scroll.y != 0.0 {
+                view.zoom((-scroll.y / 200.0).exp(), time_at(view, pointer.x));
+            }
+            if scroll.x != 0.0 {
+                view.pan(-scroll.x * seconds_per_point);
+            }
+        }
+        let shift = ui.input(|i| i.modifiers.shift);
+        if response.dragged_by(egui::PointerButton::Secondary)
+            || (shift && response.dragged_by(egui::PointerButton::Primary))
+        {
+            view.pan(-response.drag_delta().x * seconds_per_point);
+        } else if response.clicked() || response.dragged_by(egui::PointerButton::Primary) {
+            if let Some(pointer) = response.interact_pointer_pos() {
+                view.playhead = time_at(view, pointer.x).clamp(0.0, view.duration());
+            }
+        }
+
+        let Some(curve) = &view.curve else {
+            return;
+        };
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(plot, 4.0, self.theme.plot_fill);
+        painter.rect_filled(wave, 4.0, self.theme.plot_fill);
+
+        let (start, end) = view.view;
+        let seconds_per_point = (end - start) / plot.width();
+        let to_x = |time: f32| plot.left() + (time - start) / (end - start) * plot.width();
+        let first = curve.points.partition_point(|point| point.time < start);
+        let last = curve.points.partition_point(|point| point.time <= end);
+        let visible = &curve.points[first.saturating_sub(1)..(last + 1).min(curve.points.len())];
+
+        let a4 = self.config.a4_reference;
+        let semitones = |frequency: f32| 69.0 + 12.0 * (frequency / a4).log2();
+        let (low, high) = visible
+            .iter()
+            .filter_map(|point| point.frequency)
+            .map(semitones)
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(low, high), value| {
+                (low.min(value), high.max(value))
+            });
+        let (low, high) = if low > high {
+            (57.0, 81.0)
+        } else {
+            ((low - 2.0).floor(), (high + 2.0).ceil())
+        };
+        let to_y = |value: f32| plot.bottom() - (value - low) / (high - low) * plot.height();
+
+        let naming = self.note_naming();
+        let label_every_note = high - low <= 24.0;
+        let font = egui::FontId::proportional(9.0);
+        for midi in low as i32..=high as i32 {
+            let pitch_class = midi.rem_euclid(12) as u8;
+            let y = to_y(midi as f32);
+            let color = if pitch_class == 0 {
+                self.theme.faint
+            } else {
+                self.theme.track
+            };
+            painter.hline(plot.x_range(), y, egui::Stroke::new(1.0, color));
+            if label_every_note || pitch_class == 0 {
+                painter.text(
+                    egui::pos2(rect.left(), y),
+                    egui::Align2::LEFT_CENTER,
+                    format!(
+                        "{}{}",
+                        naming.pitch_class_name(pitch_class),
+                        midi.div_euclid(12) - 1
+                    ),
+                    font.clone(),
+                    self.theme.secondary_text,
+                );
+            }
+        }
+
+        for point in visible {
+            if let Some(frequency) = point.frequency {
+                let position = egui::pos2(to_x(point.time), to_y(semitones(frequency)));
+                painter.circle_filled(position, 1.0, self.theme.faint);
+            }
+        }
+        let stroke = egui::Stroke::new(1.5, self.theme.text);
+        for run in visible.split(|point| point.note.is_none()) {
+            let line: Vec<egui::Pos2> = run
+                .iter()
+                .filter_map(|point| {
+                    let note = point.note.as_ref()?;
+                    let value = semitones(note.target_frequency) + note.cents_off / 100.0;
+                    Some(egui::pos2(to_x(point.time), to_y(value)))
+                })
+                .collect();
+            match line.as_slice() {
+                [] => {}
+                [single] => {
+                    painter.circle_filled(*single, 1.0, self.theme.text);
+                }
+                _ => {
+                    painter.add(egui::Shape::line(line, stroke));
+                }
+            }
+        }
+
+        let middle = wave.center().y;
+        let half_height = wave.height() / 2.0 - 2.0;
+        let wave_color = self.theme.secondary_text;
+        let mut x = wave.left();
+        while x < wave.right() {
+            let from =
+                ((start + (x - wave.left()) * seconds_per_point) / curve.bucket_duration) as usize;
+            let to = ((start + (x + 1.0 - wave.left()) * seconds_per_point) / curve.bucket_duration)
+                as usize;
+            let (low, high) = curve
+                .waveform
+                .get(from..(to + 1).min(curve.waveform.len()))
+                .unwrap_or_default()
+                .iter()
+                .fold((0.0f32, 0.0f32), |(low, high), &(min, max)| {
+                    (low.min(min), high.max(max))
+                });
+            if high > low {
+                painter.vline(
+                    x,
+                    middle - high.min(1.0) * half_height..=middle - low.max(-1.0) * half_height,
+                    egui::Stroke::new(1.0, wave_color),
+                );
+            }
+            x += 1.0;
+        }
+
+        let playhead = to_x(view.playhead);
+        if plot.x_range().contains(playhead) {
+            painter.vline(
+                playhead,
+                plot.top()..=wave.bottom(),
+                egui::Stroke::new(1.0, self.palette.in_tune),
+            );
+        }
+    }
+
+    /// One row per string of `preset`, highest-numbered first, each picked as
+    /// a note and octave or typed in Hz.
+    fn draw_preset_strings(&self, ui: &mut egui::Ui, preset: &mut UserPreset, naming: NoteNaming) {
+        let count = preset.strings.len();
+        let mut remove = None;
+        for (index, string) in preset.strings.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                ui.label(
+                    egui::RichText::new(format!("String {}", count - index))
+                        .color(self.theme.secondary_text),
+                );
+                match string {
+                    StringTuning::Note(name) => match Note::parse(name) {
+                        Some(note) => {
+                            let mut pitch_class = note.pitch_class;
+                            let mut octave = note.octave;
+                            egui::ComboBox::from_id_salt(("preset_string_note", index))
+                                .selected_text(naming.pitch_class_name(pitch_class))
+                                .width(45.0)
                                 .show_ui(ui, |ui| {
-                                    let devices = self.available_devices.clone();
-                                    for (device_name, device) in devices {
+                                    for class in 0..12 {
+                                        ui.selectable_value(
+                                            &mut pitch_class,
+                                            class,
+                                            naming.pitch_class_name(class),
+                                        );
+                                    }
+                                });
+                            ui.add(egui::DragValue::new(&mut octave).range(0..=7))
+                                .on_hover_text("Octave");
+                            if (pitch_class, octave) != (note.pitch_class, note.octave) {
+                                let midi = (octave + 1) * 12 + pitch_class as i32;
+                                *name = midi_to_note(midi, 0.0, DEFAULT_A4_FREQUENCY).to_string();
+                            }
+                        }
+                        None => {
+                            ui.add(egui::TextEdit::singleline(name).desired_width(60.0))
+                                .on_hover_text("A note name such as D#3");
+                        }
+                    },
+                    StringTuning::Frequency(frequency) => {
+                        ui.add(
+                            egui::DragValue::new(frequency)
+                                .range(1.0..=5000.0)
+                                .speed(0.1)
+                                .suffix(" Hz"),
+                        );
+                    }
+                }
+                if ui
+                    .small_button("✖")
+                    .on_hover_text("Remove string")
+                    .clicked()
+                {
+                    remove = Some(index);
+                }
+            });
+        }
+        if let Some(index) = remove {
+            preset.strings.remove(index);
+        }
+
+        ui.horizontal(|ui| {
+            // A new string goes a fourth above the highest, as on most
+            // fretted instruments.
+            let next = preset
+                .strings
+                .last()
+                .and_then(|string| string.to_note().ok())
+                .map_or(40, |note| note.midi + 5);
+            if ui.small_button("+ String").clicked() {
+                preset.strings.push(StringTuning::Note(
+                    midi_to_note(next, 0.0, DEFAULT_A4_FREQUENCY).to_string(),
+                ));
+            }
+            if ui
+                .small_button("+ Hz")
+                .on_hover_text("Add a string tuned to a frequency")
+                .clicked()
+            {
+                preset
+                    .strings
+                    .push(StringTuning::Frequency(midi_to_frequency(
+                        next,
+                        DEFAULT_A4_FREQUENCY,
+                    )));
+            }
+        });
+    }
+
+    /// Capo and fine offset of the preset, in its menu.
+    fn draw_transpose_controls(&mut self, ui: &mut egui::Ui) {
+        let mut semitones = self
+            .active_preset
+            .as_ref()
+            .map_or(0, |preset| preset.transpose);
+        let mut cents = self.config.tuning_offset_cents;
+        ui.add_enabled_ui(self.active_preset.is_some(), |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Capo / transpose");
+                if ui
+                    .add(
+                        egui::DragValue::new(&mut semitones)
+                            .range(MIN_TRANSPOSE..=MAX_TRANSPOSE)
+                            .custom_formatter(|value, _| format!("{:+}", value as i32)),
+                    )
+                    .on_hover_text(
+                        "Semitones every string is shifted, e.g. +2 for a capo on fret 2",
+                    )
+                    .changed()
+                {
+                    self.set_preset_transpose(semitones);
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Fine offset");
+                if ui
+                    .add(
+                        egui::DragValue::new(&mut cents)
+                            .range(-50.0..=50.0)
+                            .speed(0.1)
+                            .fixed_decimals(1)
+                            .suffix(" ¢"),
+                    )
+                    .on_hover_text(
+                        "Cents every string is shifted, for tuning slightly off on purpose",
+                    )
+                    .changed()
+                {
+                    self.set_tuning_offset(cents);
+                }
+            });
+        });
+    }
+
+    fn save_config(&mut self) {
+        if !self.config_dirty {
+            return;
+        }
+        match self.config.save() {
+            Ok(()) => self.config_dirty = false,
+            Err(err) => self.report_error(format!("Failed to save config: {}", err)),
+        }
+    }
+
+    fn toggle_settings(&mut self, ctx: &egui::Context) {
+        self.show_settings = !self.show_settings;
+        if self.show_settings {
+            self.refresh_midi_ports();
+        } else {
+            self.save_config();
+        }
+        self.resize_window(ctx);
+    }
+
+    fn toggle_history(&mut self, ctx: &egui::Context) {
+        self.show_history = !self.show_history;
+        self.resize_window(ctx);
+    }
+
+    fn toggle_stats(&mut self, ctx: &egui::Context) {
+        self.show_stats = !self.show_stats;
+        self.resize_window(ctx);
+    }
+
+    /// Writes the detection log on a background thread, to the path typed in
+    /// the stats panel or a timestamped file next to the recordings.
+    fn export_log(&mut self) {
+        let format = self.config.export_format;
+        let input = self.export_path_input.trim();
+        let path = if input.is_empty() {
+            self.config.log_export_path(format, SystemTime::now())
+        } else {
+            Some(PathBuf::from(input))
+        };
+        let Some(path) = path else {
+            self.report_error("No data directory available for the export".to_string());
+            return;
+        };
+
+        let entries: Vec<LoggedDetection> = self.detection_log.iter().cloned().collect();
+        let naming = self.note_naming();
+        let midi = self.config.midi_export_options();
+        let (sender, receiver) = mpsc::channel();
+        let spawned = std::thread::Builder::new()
+            .name("log-export".to_string())
+            .spawn(move || {
+                let result = export_log(&entries, format, naming, &midi, &path);
+                let _ = sender.send(result.map(|()| (path, entries.len())));
+            });
+        match spawned {
+            Ok(_) => self.export = Some(receiver),
+            Err(err) => self.report_error(format!("Failed to start the export: {}", err)),
+        }
+    }
+
+    fn poll_export(&mut self) {
+        let Some(receiver) = &self.export else {
+            return;
+        };
+        let result = match receiver.try_recv() {
+            Ok(result) => result,
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => Err(anyhow::anyhow!("the export thread stopped")),
+        };
+        self.export = None;
+        match result {
+            Ok((path, count)) => {
+                self.report_success(format!("Exported {} readings to {}", count, path.display()))
+            }
+            Err(err) => self.report_error(format!("Export failed: {:#}", err)),
+        }
+    }
+
+    /// Sizes the window for the display mode, growing it to make room for
+    /// whichever panels are open.
+    fn resize_window(&mut self, ctx: &egui::Context) {
+        let panels = egui::vec2(0.0, self.panels_height());
+        let size = initial_window_size(&self.config) + panels;
+        ctx.send_viewport_cmd(egui::ViewportCommand::MinInnerSize(
+            min_window_size(&self.config) + panels,
+        ));
+        ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(size));
+        self.pending_resize = Some((size, Instant::now()));
+    }
+
+    /// Remembers where the window was moved to.
+    fn track_window_position(&mut self, ctx: &egui::Context) {
+        let Some(outer) = ctx.input(|input| input.viewport().outer_rect) else {
+            return;
+        };
+        let position = [outer.min.x.round(), outer.min.y.round()];
+        if self.config.window_position != Some(position) {
+            self.config.window_position = Some(position);
+            self.config_dirty = true;
+        }
+    }
+
+    /// Lets the window be moved by dragging any part of `ui` not covered by
+    /// a widget, since without a titlebar there is often nothing else to
+    /// grab. Call before adding widgets so they take precedence.
+    fn drag_window_from_background(ui: &mut egui::Ui) {
+        // Selectable labels would take the drag for text selection instead.
+        ui.style_mut().interaction.selectable_labels = false;
+        let background = ui.interact(
+            ui.max_rect(),
+            ui.id().with("window_drag"),
+            egui::Sense::drag(),
+        );
+        if background.drag_started_by(egui::PointerButton::Primary) {
+            ui.ctx().send_viewport_cmd(egui::ViewportCommand::StartDrag);
+        }
+    }
+
+    /// Records the window level the viewport was created with, when the
+    /// command line overrode the saved preference.
+    pub fn set_launched_on_top(&mut self, always_on_top: bool) {
+        self.always_on_top = always_on_top;
+    }
+
+    fn toggle_always_on_top(&mut self, ctx: &egui::Context) {
+        self.always_on_top = !self.always_on_top;
+        self.config.always_on_top = self.always_on_top;
+        self.config_dirty = true;
+        let level = if self.always_on_top {
+            egui::WindowLevel::AlwaysOnTop
+        } else {
+            egui::WindowLevel::Normal
+        };
+        ctx.send_viewport_cmd(egui::ViewportCommand::WindowLevel(level));
+    }
+
+    /// Freezes the reading on display, or resumes following the worker.
+    fn toggle_hold(&mut self, now: Instant) {
+        if self.hold.take().is_some() {
+            self.reading = self.pitch_worker.reading();
+            return;
+        }
+        let since = now.checked_sub(HOLD_RANGE_WINDOW).unwrap_or(now);
+        let cents_range = self
+            .reading
+            .note
+            .as_ref()
+            .and_then(|note| self.history.cents_range(since, note.target_frequency));
+        self.hold = Some(Hold { cents_range });
+    }
+
+    /// Hands a changed theme setting to egui, styling both of its themes the
+    /// first time, then picks the colors of whichever theme is showing.
+    fn apply_theme(&mut self, ctx: &egui::Context) {
+        if self.applied_theme != Some(self.config.theme) {
+            if self.applied_theme.is_none() {
+                ctx.style_mut_of(egui::Theme::Dark, |style| Theme::DARK.apply(style));
+                ctx.style_mut_of(egui::Theme::Light, |style| Theme::LIGHT.apply(style));
+            }
+            ctx.set_theme(match self.config.theme {
+                UiTheme::System => egui::ThemePreference::System,
+                UiTheme::Dark => egui::ThemePreference::Dark,
+                UiTheme::Light => egui::ThemePreference::Light,
+            });
+            self.applied_theme = Some(self.config.theme);
+        }
+        let theme = ctx.theme();
+        self.theme = Theme::of(theme);
+        self.palette = Palette::for_scheme(self.config.color_scheme, theme);
+    }
+
+    /// How soon to draw the next frame; the history graph keeps scrolling and
+    /// the meter keeps moving as long as `note_active` or the input is loud.
+    /// Moves the needle `dt` seconds towards the reading, or back to the center
+    /// once the note has cleared; true while it is still on its way.
+    fn move_needle(&mut self, dt: f32) -> bool {
+        let target = match self.reading.note {
+            Some(_) => self.reading.smoothed_cents,
+            None => 0.0,
+        };
+        let response_time = self.config.needle_response.response_time();
+        self.needle.update(target, response_time, dt);
+        !self.needle.settled_at(target)
+    }
+
+    /// Eases the needle's scale to the configured range, or in and out with
+    /// auto zoom; true while it is still changing.
+    fn zoom_needle(&mut self, dt: f32, now: Instant) -> bool {
+        let cents = self
+            .reading
+            .note
+            .as_ref()
+            .map(|_| self.reading.smoothed_cents);
+        self.needle_scale
+            .update(self.config.needle_range, cents, now, dt)
+    }
+
+    fn repaint_interval(&self, note_active: bool) -> Duration {
+        if note_active || self.level_meter.active() {
+            ACTIVE_REPAINT_INTERVAL
+        } else {
+            IDLE_REPAINT_INTERVAL
+        }
+    }
+
+    /// Enters or leaves overlay mode: only the reading, on the overlay
+    /// background, without window decorations and, if chosen and the tray
+    /// can bring the controls back, letting clicks through.
+    fn set_overlay(&mut self, ctx: &egui::Context, overlay: bool) {
+        self.overlay = overlay;
+        ctx.send_viewport_cmd(egui::ViewportCommand::Decorations(!overlay));
+        let click_through = overlay && self.config.overlay_click_through && self.tray.is_some();
+        ctx.send_viewport_cmd(egui::ViewportCommand::MousePassthrough(click_through));
+    }
+
+    /// Fill behind the reading in overlay mode.
+    fn overlay_fill(&self) -> egui::Color32 {
+        match self.config.overlay_background {
+            OverlayBackground::ChromaKey => {
+                let [red, green, blue] = self.config.overlay_key_color;
+                egui::Color32::from_rgb(red, green, blue)
+            }
+            OverlayBackground::Transparent => egui::Color32::TRANSPARENT,
+        }
+    }
+
+    /// The reading alone in the current layout, for [`Self::set_overlay`].
+    fn draw_overlay(&mut self, ctx: &egui::Context) {
+        egui::CentralPanel::default()
+            .frame(egui::Frame {
+                fill: self.overlay_fill(),
+                inner_margin: egui::Margin::same(8),
+                ..Default::default()
+            })
+            .show(ctx, |ui| {
+                Self::drag_window_from_background(ui);
+                match self.config.display_mode {
+                    DisplayMode::Mini => self.draw_mini_display(ui),
+                    DisplayMode::Performance => self.draw_performance_display(ui),
+                    DisplayMode::Standard => {
+                        ui.vertical_centered(|ui| {
+                            ui.add_space(18.0);
+                            self.draw_tuner_display(ui);
+                        });
+                    }
+                }
+            });
+    }
+
+    fn set_display_mode(&mut self, ctx: &egui::Context, mode: DisplayMode) {
+        if self.config.display_mode == mode {
+            return;
+        }
+        self.config.display_mode = mode;
+        self.config_dirty = true;
+        self.resize_window(ctx);
+    }
+
+    /// Panels only show in the standard layout.
+    fn panels_height(&self) -> f32 {
+        let mut height = 0.0;
+        if self.config.display_mode != DisplayMode::Standard {
+            return height;
+        }
+        if self.show_history {
+            height += HISTORY_PANEL_HEIGHT;
+        }
+        if self.show_stats {
+            height += STATS_PANEL_HEIGHT;
+        }
+        if self.show_strum {
+            height += STRUM_PANEL_HEIGHT;
+        }
+        if self.show_settings {
+            height += SETTINGS_PANEL_HEIGHT;
+        }
+        height
+    }
+
+    /// Remembers the size the user dragged the window to, minus any open
+    /// panels, once a size the app asked for has arrived.
+    fn track_window_size(&mut self, ctx: &egui::Context, now: Instant) {
+        if self.config.display_mode != DisplayMode::Standard {
+            return;
+        }
+        let Some(inner) = ctx.input(|input| input.viewport().inner_rect) else {
+            return;
+        };
+        if let Some((requested, since)) = self.pending_resize {
+            let arrived = (inner.size() - requested).length() < 1.0;
+            if !arrived && now.saturating_duration_since(since) < RESIZE_SETTLE_TIME {
+                return;
+            }
+            self.pending_resize = None;
+        }
+
+        let size = (inner.size() - egui::vec2(0.0, self.panels_height())).max(WINDOW_SIZE);
+        let saved = saved_window_size(&self.config);
+        if (size - saved).length() >= 1.0 {
+            self.config.window_size = [size.x.round(), size.y.round()];
+            self.config_dirty = true;
+        }
+    }
+
+    fn draw_settings_panel(&mut self, ui: &mut egui::Ui) {
+        egui::ScrollArea::vertical().show(ui, |ui| self.draw_settings(ui));
+    }
+
+    fn draw_settings(&mut self, ui: &mut egui::Ui) {
+        let mut changed = false;
+
+        egui::Grid::new("settings_grid")
+            .num_columns(2)
+            .spacing([12.0, 8.0])
+            .show(ui, |ui| {
+                ui.label("Magnitude gate");
+                ui.horizontal(|ui| {
+                    let gate_changed = ui
+                        .add(
+                            egui::Slider::new(
+                                &mut self.config.min_magnitude_threshold,
+                                MIN_MAGNITUDE_THRESHOLD..=MAX_MAGNITUDE_THRESHOLD,
+                            )
+                            .logarithmic(true),
+                        )
+                        .on_hover_text("Minimum signal strength before a note is detected")
+                        .changed();
+                    if gate_changed {
+                        // A hand-tuned gate replaces this device's calibration.
+                        self.config
+                            .device_thresholds
+                            .remove(&self.current_device_name);
+                        changed = true;
+                    }
+
+                    let calibrating = self.pitch_worker.calibrating();
+                    if ui
+                        .add_enabled(!calibrating, egui::Button::new("Calibrate"))
+                        .on_hover_text("Measure the room noise for a couple of seconds")
+                        .clicked()
+                    {
+                        self.pitch_worker.start_calibration(Instant::now());
+                    }
+                });
+                ui.end_row();
+
+                if let Some(label) = self.calibration_label() {
+                    ui.label("");
+                    ui.label(
+                        egui::RichText::new(label)
+                            .size(11.0)
+                            .color(self.theme.secondary_text),
+                    );
+                    ui.end_row();
+                }
+
+                ui.label("Input gain");
+                ui.horizontal(|ui| {
+                    let mut gain_db = self.device_gain_db();
+                    if ui
+                        .add(
+                            egui::Slider::new(&mut gain_db, MIN_INPUT_GAIN_DB..=MAX_INPUT_GAIN_DB)
+                                .max_decimals(1)
+                                .suffix(" dB"),
+                        )
+                        .on_hover_text(
+                            "Boost for quiet inputs such as a passive piezo pickup, \
+                             remembered for this device",
+                        )
+                        .changed()
+                    {
+                        if gain_db == 0.0 {
+                            self.config.device_gains.remove(&self.current_device_name);
+                        } else {
+                            self.config
+                                .device_gains
+                                .insert(self.current_device_name.clone(), gain_db);
+                        }
+                        self.apply_device_gain();
+                        changed = true;
+                    }
+                    if gain_db > 0.0 && self.level_meter.clipping(Instant::now()) {
+                        ui.label(
+                            egui::RichText::new("Clipping, lower the gain")
+                                .size(11.0)
+                                .color(self.palette.warning),
+                        );
+                    }
+                });
+                ui.end_row();
+
+                ui.label("Confidence");
+                changed |= ui
+                    .add(egui::Slider::new(
+                        &mut self.config.min_confidence,
+                        0.0..=0.95,
+                    ))
+                    .on_hover_text("How tonal a sound must be before it is tracked")
+                    .changed();
+                ui.end_row();
+
+                ui.label("Tonality");
+                changed |= ui
+                    .add(egui::Slider::new(&mut self.config.min_tonality, 0.0..=0.9))
+                    .on_hover_text(
+                        "Share of the sound's energy that must belong to the note, so fans, \
+                         typing and speech aren't read as one; 0 turns the check off",
+                    )
+                    .changed();
+                ui.end_row();
+
+                ui.label("Stability (Hz)");
+                changed |= ui
+                    .add(egui::Slider::new(
+                        &mut self.config.stability_threshold,
+                        0.5..=10.0,
+                    ))
+                    .on_hover_text("Maximum frequency spread before a reading is accepted")
+                    .changed();
+                ui.end_row();
+
+                ui.label("Pitch window");
+                changed |= ui
+                    .add(
+                        egui::Slider::new(&mut self.config.pitch_window_ms, 50..=1000)
+                            .suffix(" ms"),
+                    )
+                    .on_hover_text("How long detections must agree before a pitch is shown")
+                    .changed();
+                ui.end_row();
+
+                ui.label("Cents window");
+                changed |= ui
+                    .add(
+                        egui::Slider::new(&mut self.config.cents_window_ms, 50..=1000)
+                            .suffix(" ms"),
+                    )
+                    .on_hover_text("How much recent history is averaged for the needle")
+                    .changed();
+                ui.end_row();
+
+                ui.label("Smoothing");
+                changed |= ui
+                    .add(egui::Slider::new(&mut self.config.smoothing_ms, 0..=500).suffix(" ms"))
+                    .on_hover_text("Needle time constant; longer is calmer but slower")
+                    .changed();
+                ui.end_row();
+
+                ui.label("Sustain gate");
+                changed |= ui
+                    .add(
+                        egui::Slider::new(&mut self.config.sustain_fraction, 0.1..=1.0)
+                            .fixed_decimals(2)
+                            .suffix("× gate"),
+                    )
+                    .on_hover_text("A locked note keeps updating down to this part of the gate")
+                    .changed();
+                ui.end_row();
+
+                ui.label("Quiet hold");
+                changed |= ui
+                    .add(
+                        egui::Slider::new(&mut self.config.quiet_hold_ms, 100..=4000).suffix(" ms"),
+                    )
+                    .on_hover_text("How long a note stays up once it falls below the sustain gate")
+                    .changed();
+                ui.end_row();
+
+                ui.label("Silence hold");
+                changed |= ui
+                    .add(
+                        egui::Slider::new(&mut self.config.silence_hold_ms, 100..=4000)
+                            .suffix(" ms"),
+                    )
+                    .on_hover_text("How long a note stays up once no pitch is detected at all")
+                    .changed();
+                ui.end_row();
+
+                ui.label("Relock grace");
+                changed |= ui
+                    .add(
+                        egui::Slider::new(&mut self.config.relock_grace_ms, 0..=4000)
+                            .suffix(" ms"),
+                    )
+                    .on_hover_text(
+                        "How soon after a note drops out it comes straight back, needle and \
+                         all, if you play it again; 0 turns this off",
+                    )
+                    .changed();
+                ui.end_row();
+
+                ui.label("Attack skip");
+                changed |= ui
+                    .add(egui::Slider::new(&mut self.config.attack_skip_ms, 0..=300).suffix(" ms"))
+                    .on_hover_text("Ignore the noisy start of each plucked note; 0 turns this off")
+                    .changed();
+                ui.end_row();
+
+                ui.label("Analysis size");
+                egui::ComboBox::from_id_salt("fft_size_selector")
+                    .selected_text(self.config.analysis_size().to_string())
+                    .show_ui(ui, |ui| {
+                        for size in FFT_SIZES {
+                            changed |= ui
+                                .selectable_value(&mut self.config.fft_size, size, size.to_string())
+                                .changed();
+                        }
+                    })
+                    .response
+                    .on_hover_text(
+                        "Shorter windows respond faster, longer ones resolve low notes better",
+                    );
+                ui.end_row();
+
+                ui.label("Input buffer");
+                let buffer_label = |frames: u32| match frames {
+                    0 => "Device default".to_string(),
+                    frames => format!("{} frames", frames),
+                };
+                let mut buffer_changed = false;
+                egui::ComboBox::from_id_salt("buffer_frames_selector")
+                    .selected_text(buffer_label(self.config.buffer_frames))
+                    .show_ui(ui, |ui| {
+                        for frames in BUFFER_FRAME_OPTIONS {
+                            buffer_changed |= ui
+                                .selectable_value(
+                                    &mut self.config.buffer_frames,
+                                    frames,
+                                    buffer_label(frames),
+                                )
+                                .changed();
+                        }
+                    })
+                    .response
+                    .on_hover_text(
+                        "Frames per audio callback; smaller ones cut latency, larger ones \
+                         survive a busy CPU",
+                    );
+                if buffer_changed {
+                    changed = true;
+                    self.reopen_capture();
+                }
+                ui.end_row();
+
+                ui.label("Update every");
+                egui::ComboBox::from_id_salt("hop_size_selector")
+                    .selected_text(format!("{} samples", self.config.analysis_hop()))
+                    .show_ui(ui, |ui| {
+                        for hop in HOP_SIZES {
+                            changed |= ui
+                                .selectable_value(
+                                    &mut self.config.hop_size,
+                                    hop,
+                                    format!("{} samples", hop),
+                                )
+                                .changed();
+                        }
+                    })
+                    .response
+                    .on_hover_text("Fewer samples between analyses lock onto notes sooner");
+                ui.end_row();
+
+                ui.label("Window");
+                egui::ComboBox::from_id_salt("window_selector")
+                    .selected_text(self.config.window_kind.label())
+                    .show_ui(ui, |ui| {
+                        for kind in WindowKind::ALL {
+                            changed |= ui
+                                .selectable_value(&mut self.config.window_kind, kind, kind.label())
+                                .changed();
+                        }
+                    });
+                ui.end_row();
+
+                ui.label("Precision");
+                egui::ComboBox::from_id_salt("precision_selector")
+                    .selected_text(self.config.precision.label())
+                    .show_ui(ui, |ui| {
+                        for precision in Precision::ALL {
+                            changed |= ui
+                                .selectable_value(
+                                    &mut self.config.precision,
+                                    precision,
+                                    precision.label(),
+                                )
+                                .changed();
+                        }
+                    })
+                    .response
+                    .on_hover_text(
+                        "Zero-pads the FFT to place peaks between bins more accurately, \
+                         at the cost of more CPU",
+                    );
+                ui.end_row();
+
+                ui.label("Time domain below");
+                changed |= ui
+                    .add(
+                        egui::Slider::new(&mut self.config.crossover_frequency, 80.0..=400.0)
+                            .max_decimals(0)
+                            .suffix(" Hz"),
+                    )
+                    .on_hover_text(
+                        "Notes below this are measured with YIN, which is more precise than \
+                         the FFT there and catches it reading a harmonic",
+                    )
+                    .changed();
+                ui.end_row();
+
+                ui.label("Range");
+                egui::ComboBox::from_id_salt("low_range_selector")
+                    .selected_text(self.config.low_range.label())
+                    .show_ui(ui, |ui| {
+                        for range in LowRange::ALL {
+                            changed |= ui
+                                .selectable_value(&mut self.config.low_range, range, range.label())
+                                .changed();
+                        }
+                    })
+                    .response
+                    .on_hover_text(
+                        "Bass downsamples the input to resolve notes from 25 to 400 Hz finely; \
+                         Auto switches to it while a note below A2 is held",
+                    );
+                ui.end_row();
+
+                ui.label("Frequencies");
+                ui.horizontal(|ui| {
+                    let custom = ui
+                        .checkbox(&mut self.config.custom_frequency_range, "Custom")
+                        .on_hover_text(
+                            "Search your own range instead of the one the preset implies",
+                        )
+                        .changed();
+                    if custom && self.config.custom_frequency_range {
+                        // Start from the range that was in use.
+                        (self.config.min_frequency, self.config.max_frequency) =
+                            self.frequency_range();
+                    }
+                    changed |= custom;
+
+                    let (mut min_frequency, mut max_frequency) = self.frequency_range();
+                    ui.add_enabled_ui(self.config.custom_frequency_range, |ui| {
+                        let min_changed = ui
+                            .add(
+                                egui::Slider::new(
+                                    &mut min_frequency,
+                                    MIN_DETECTABLE_FREQUENCY..=MAX_DETECTABLE_FREQUENCY,
+                                )
+                                .logarithmic(true)
+                                .max_decimals(0)
+                                .suffix(" Hz"),
+                            )
+                            .on_hover_text("Lowest note searched for")
+                            .changed();
+                        let max_changed = ui
+                            .add(
+                                egui::Slider::new(
+                                    &mut max_frequency,
+                                    MIN_DETECTABLE_FREQUENCY..=MAX_DETECTABLE_FREQUENCY,
+                                )
+                                .logarithmic(true)
+                                .max_decimals(0)
+                                .suffix(" Hz"),
+                            )
+                            .on_hover_text("Highest note searched for")
+                            .changed();
+                        if min_changed || max_changed {
+                            self.config.min_frequency = min_frequency.min(max_frequency);
+                            self.config.max_frequency = max_frequency.max(min_frequency);
+                            changed = true;
+                        }
+                    });
+                });
+                ui.end_row();
+
+                ui.label("Hum rejection");
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_id_salt("hum_rejection_selector")
+                        .selected_text(self.config.hum_rejection.label())
+                        .width(60.0)
+                        .show_ui(ui, |ui| {
+                            for hum in HumRejection::ALL {
+                                changed |= ui
+                                    .selectable_value(
+                                        &mut self.config.hum_rejection,
+                                        hum,
+                                        hum.label(),
+                                    )
+                                    .changed();
+                            }
+                        })
+                        .response
+                        .on_hover_text("Filter out mains hum and its first harmonic");
+                    ui.add_enabled_ui(self.config.hum_rejection != HumRejection::Off, |ui| {
+                        changed |= ui
+                            .add(
+                                egui::DragValue::new(&mut self.config.high_pass_cutoff)
+                                    .range(0.0..=120.0)
+                                    .speed(1.0)
+                                    .prefix("Low cut ")
+                                    .suffix(" Hz"),
+                            )
+                            .on_hover_text(
+                                "High-pass cutoff applied with hum rejection; 0 turns it off",
+                            )
+                            .changed();
+                    });
+                });
+                ui.end_row();
+
+                ui.label("Note names");
+                egui::ComboBox::from_id_salt("note_naming_selector")
+                    .selected_text(self.config.note_naming.label())
+                    .show_ui(ui, |ui| {
+                        for naming in NoteNaming::ALL {
+                            changed |= ui
+                                .selectable_value(
+                                    &mut self.config.note_naming,
+                                    naming,
+                                    naming.label(),
+                                )
+                                .changed();
+                        }
+                    });
+                ui.end_row();
+
+                ui.label("Layout");
+                let mut display_mode = self.config.display_mode;
+                egui::ComboBox::from_id_salt("display_mode_selector")
+                    .selected_text(display_mode.label())
+                    .show_ui(ui, |ui| {
+                        for mode in DisplayMode::ALL {
+                            ui.selectable_value(&mut display_mode, mode, mode.label());
+                        }
+                    })
+                    .response
+                    .on_hover_text("Press M to cycle through the layouts");
+                if display_mode != self.config.display_mode {
+                    let ctx = ui.ctx().clone();
+                    self.set_display_mode(&ctx, display_mode);
+                }
+                ui.end_row();
+
+                ui.label("Theme");
+                egui::ComboBox::from_id_salt("theme_selector")
+                    .selected_text(self.config.theme.label())
+                    .show_ui(ui, |ui| {
+                        for theme in UiTheme::ALL {
+                            changed |= ui
+                                .selectable_value(&mut self.config.theme, theme, theme.label())
+                                .changed();
+                        }
+                    });
+                ui.end_row();
+
+                ui.label("Colors");
+                egui::ComboBox::from_id_salt("color_scheme_selector")
+                    .selected_text(self.config.color_scheme.label())
+                    .show_ui(ui, |ui| {
+                        for scheme in ColorScheme::ALL {
+                            changed |= ui
+                                .selectable_value(
+                                    &mut self.config.color_scheme,
+                                    scheme,
+                                    scheme.label(),
+                                )
+                                .changed();
+                        }
+                    });
+                ui.end_row();
+
+                ui.label("Overlay");
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_id_salt("overlay_background_selector")
+                        .selected_text(self.config.overlay_background.label())
+                        .show_ui(ui, |ui| {
+                            for background in OverlayBackground::ALL {
+                                changed |= ui
+                                    .selectable_value(
+                                        &mut self.config.overlay_background,
+                                        background,
+                                        background.label(),
+                                    )
+                                    .changed();
+                            }
+                        })
+                        .response
+                        .on_hover_text("What shows behind the note and needle when streaming");
+                    if self.config.overlay_background == OverlayBackground::ChromaKey {
+                        changed |= ui
+                            .color_edit_button_srgb(&mut self.config.overlay_key_color)
+                            .on_hover_text("Key this color out in your streaming software")
+                            .changed();
+                    }
+                    changed |= ui
+                        .add_enabled(
+                            self.tray.is_some(),
+                            egui::Checkbox::new(
+                                &mut self.config.overlay_click_through,
+                                "Click-through",
+                            ),
+                        )
+                        .on_hover_text("Clicks go to the window beneath; leave from the tray menu")
+                        .on_disabled_hover_text("Needs the tray icon to leave overlay mode")
+                        .changed();
+                    if ui
+                        .button("Start")
+                        .on_hover_text("Press O or use the tray menu to leave")
+                        .clicked()
+                    {
+                        let ctx = ui.ctx().clone();
+                        self.set_overlay(&ctx, true);
+                    }
+                });
+                ui.end_row();
+
+                ui.label("Deviation");
+                egui::ComboBox::from_id_salt("deviation_display_selector")
+                    .selected_text(self.config.deviation_display.label())
+                    .show_ui(ui, |ui| {
+                        for display in DeviationDisplay::ALL {
+                            changed |= ui
+                                .selectable_value(
+                                    &mut self.config.deviation_display,
+                                    display,
+                                    display.label(),
+                                )
+                                .changed();
+                        }
+                    });
+                ui.end_row();
+
+                ui.label("Needle");
+                egui::ComboBox::from_id_salt("needle_style_selector")
+                    .selected_text(self.config.needle_style.label())
+                    .show_ui(ui, |ui| {
+                        for style in NeedleStyle::ALL {
+                            changed |= ui
+                                .selectable_value(
+                                    &mut self.config.needle_style,
+                                    style,
+                                    style.label(),
+                                )
+                                .changed();
+                        }
+                    });
+                ui.end_row();
+
+                ui.label("Needle range");
+                egui::ComboBox::from_id_salt("needle_range_selector")
+                    .selected_text(self.config.needle_range.label())
+                    .show_ui(ui, |ui| {
+                        for range in NeedleRange::ALL {
+                            changed |= ui
+                                .selectable_value(
+                                    &mut self.config.needle_range,
+                                    range,
+                                    range.label(),
+                                )
+                                .changed();
+                        }
+                    })
+                    .response
+                    .on_hover_text(
+                        "Auto zoom narrows the scale to ±15 ¢ once the note stays close",
+                    );
+                ui.end_row();
+
+                ui.label("Needle speed");
+                egui::ComboBox::from_id_salt("needle_response_selector")
+                    .selected_text(self.config.needle_response.label())
+                    .show_ui(ui, |ui| {
+                        for response in NeedleResponse::ALL {
+                            changed |= ui
+                                .selectable_value(
+                                    &mut self.config.needle_response,
+                                    response,
+                                    response.label(),
+                                )
+                                .changed();
+                        }
+                    })
+                    .response
+                    .on_hover_text("How quickly the needle follows the reading");
+                ui.end_row();
+
+                ui.label("Temperament");
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_id_salt("temperament_selector")
+                        .selected_text(self.config.temperament.label())
+                        .show_ui(ui, |ui| {
+                            for temperament in Temperament::ALL {
+                                changed |= ui
+                                    .selectable_value(
+                                        &mut self.config.temperament,
+                                        temperament,
+                                        temperament.label(),
+                                    )
+                                    .changed();
+                            }
+                        });
+
+                    let naming = self.note_naming();
+                    ui.add_enabled_ui(self.config.temperament != Temperament::Equal, |ui| {
+                        egui::ComboBox::from_id_salt("temperament_tonic_selector")
+                            .selected_text(naming.pitch_class_name(self.config.temperament_tonic))
+                            .width(50.0)
+                            .show_ui(ui, |ui| {
+                                for tonic in 0..12 {
+                                    changed |= ui
+                                        .selectable_value(
+                                            &mut self.config.temperament_tonic,
+                                            tonic,
+                                            naming.pitch_class_name(tonic),
+                                        )
+                                        .changed();
+                                }
+                            })
+                            .response
+                            .on_hover_text("Tonic the temperament is built on");
+                    });
+                });
+                ui.end_row();
+
+                ui.label("Divisions");
+                changed |= ui
+                    .add(
+                        egui::DragValue::new(&mut self.config.edo)
+                            .range(EDO_RANGE)
+                            .suffix("-EDO"),
+                    )
+                    .on_hover_text(
+                        "Equal steps per octave, e.g. 24 for quarter tones; \
+                         the temperament and key apply at 12",
+                    )
+                    .changed();
+                ui.end_row();
+
+                changed |= self.draw_key_settings(ui);
+
+                ui.label("Piano");
+                ui.horizontal(|ui| {
+                    changed |= ui
+                        .checkbox(&mut self.config.piano_mode, "Stretch tuning")
+                        .on_hover_text(
+                            "Measure against a piano's stretched tuning and show the partials",
+                        )
+                        .changed();
+                    changed |= ui
+                        .add_enabled(
+                            self.config.piano_mode,
+                            egui::DragValue::new(&mut self.config.piano_stretch)
+                                .range(0.0..=10.0)
+                                .speed(0.1)
+                                .fixed_decimals(1)
+                                .suffix(" ¢/octave"),
+                        )
+                        .on_hover_text(
+                            "Scales the stretch: half this per octave next to A4, more towards the ends",
+                        )
+                        .changed();
+                });
+                ui.end_row();
+
+                changed |= self.draw_drone_settings(ui);
+                changed |= self.draw_metronome_settings(ui);
+
+                ui.label("In tune (±¢)");
+                if ui
+                    .add(egui::Slider::new(
+                        &mut self.config.in_tune_cents,
+                        1.0..=15.0,
+                    ))
+                    .on_hover_text("In tune below this many cents")
+                    .changed()
+                {
+                    self.config.close_cents = self
+                        .config
+                        .close_cents
+                        .max(self.config.in_tune_cents + MIN_CLOSE_BAND_CENTS);
+                    changed = true;
+                }
+                ui.end_row();
+
+                ui.label("Close (±¢)");
+                changed |= ui
+                    .add(egui::Slider::new(
+                        &mut self.config.close_cents,
+                        self.config.in_tune_cents + MIN_CLOSE_BAND_CENTS..=50.0,
+                    ))
+                    .on_hover_text("Close rather than off below this many cents")
+                    .changed();
+                ui.end_row();
+
+                ui.label("In-tune cue");
+                ui.horizontal(|ui| {
+                    changed |= ui
+                        .checkbox(&mut self.config.in_tune_flash, "Flash")
+                        .on_hover_text("Flash the window green once the note settles in tune")
+                        .changed();
+                    changed |= ui
+                        .checkbox(&mut self.config.in_tune_beep, "Beep")
+                        .on_hover_text("Beep once the note settles in tune")
+                        .changed();
+                    changed |= ui
+                        .add_enabled(
+                            self.config.in_tune_flash || self.config.in_tune_beep,
+                            egui::Slider::new(&mut self.config.in_tune_dwell_ms, 200..=2000)
+                                .suffix(" ms"),
+                        )
+                        .on_hover_text("How long the note has to stay in tune first")
+                        .changed();
+                });
+                ui.end_row();
+
+                ui.label("Stability (σ¢)");
+                ui.horizontal(|ui| {
+                    changed |= ui
+                        .checkbox(&mut self.config.show_stability, "Show")
+                        .on_hover_text(
+                            "Show how steadily the note is held, with a sparkline of recent cents",
+                        )
+                        .changed();
+                    changed |= ui
+                        .add_enabled(
+                            self.config.show_stability,
+                            egui::Slider::new(&mut self.config.stability_target_cents, 0.5..=10.0),
+                        )
+                        .on_hover_text("Held steady below this standard deviation")
+                        .changed();
+                });
+                ui.end_row();
+
+                ui.label("History");
+                ui.horizontal(|ui| {
+                    changed |= ui
+                        .add(
+                            egui::Slider::new(&mut self.config.history_seconds, 10..=30)
+                                .suffix(" s"),
+                        )
+                        .on_hover_text("How much pitch the history graph shows")
+                        .changed();
+                    egui::ComboBox::from_id_salt("history_scale_selector")
+                        .selected_text(self.config.history_scale.label())
+                        .width(50.0)
+                        .show_ui(ui, |ui| {
+                            for scale in HistoryScale::ALL {
+                                changed |= ui
+                                    .selectable_value(
+                                        &mut self.config.history_scale,
+                                        scale,
+                                        scale.label(),
+                                    )
+                                    .changed();
+                            }
+                        });
+                });
+                ui.end_row();
+
+                ui.label("Challenge");
+                ui.horizontal(|ui| {
+                    let naming = self.note_naming();
+                    let mut challenge_changed = ui
+                        .add(note_drag_value(&mut self.config.challenge_low_note, naming))
+                        .on_hover_text("Lowest note the challenge asks for")
+                        .changed();
+                    ui.label("–");
+                    challenge_changed |= ui
+                        .add(note_drag_value(&mut self.config.challenge_high_note, naming))
+                        .on_hover_text("Highest note the challenge asks for")
+                        .changed();
+                    let key_label = |key: Option<u8>| match key {
+                        Some(tonic) => format!("{} major", naming.pitch_class_name(tonic)),
+                        None => "Any key".to_string(),
+                    };
+                    egui::ComboBox::from_id_salt("challenge_key_selector")
+                        .selected_text(key_label(self.config.challenge_key))
+                        .width(70.0)
+                        .show_ui(ui, |ui| {
+                            for key in std::iter::once(None).chain((0..12).map(Some)) {
+                                challenge_changed |= ui
+                                    .selectable_value(
+                                        &mut self.config.challenge_key,
+                                        key,
+                                        key_label(key),
+                                    )
+                                    .changed();
+                            }
+                        });
+                    if let Some(preset) = &self.active_preset {
+                        if ui
+                            .button("Instrument")
+                            .on_hover_text(
+                                "From the lowest open string up to a fifth above the highest, \
+                                 e.g. first position on a violin",
+                            )
+                            .clicked()
+                        {
+                            let strings = preset.strings.iter().map(|note| note.midi);
+                            if let (Some(low), Some(high)) =
+                                (strings.clone().min(), strings.max())
+                            {
+                                self.config.challenge_low_note = low;
+                                self.config.challenge_high_note = high + 7;
+                                challenge_changed = true;
+                            }
+                        }
+                    }
+                    if challenge_changed {
+                        if self.challenge.is_some() {
+                            self.set_challenge(true);
+                        }
+                        changed = true;
+                    }
+                });
+                ui.end_row();
+
+                ui.label("MIDI out");
+                ui.horizontal(|ui| {
+                    let mut midi_changed = ui
+                        .checkbox(&mut self.config.midi_enabled, "")
+                        .on_hover_text("Send the detected note as MIDI")
+                        .changed();
+                    if midi_changed && self.config.midi_port.is_none() {
+                        self.config.midi_port = self.midi_ports.first().cloned();
+                    }
+                    ui.add_enabled_ui(self.config.midi_enabled, |ui| {
+                        let selected = self
+                            .config
+                            .midi_port
+                            .clone()
+                            .unwrap_or_else(|| "No port".to_string());
+                        egui::ComboBox::from_id_salt("midi_port_selector")
+                            .selected_text(selected)
+                            .width(150.0)
+                            .show_ui(ui, |ui| {
+                                for port in &self.midi_ports {
+                                    midi_changed |= ui
+                                        .selectable_value(
+                                            &mut self.config.midi_port,
+                                            Some(port.clone()),
+                                            port,
+                                        )
+                                        .changed();
+                                }
+                            });
+                    });
+                    if midi_changed {
+                        self.config_dirty = true;
+                        self.apply_midi();
+                    }
+                });
+                ui.end_row();
+
+                ui.label("Bend range");
+                self.config_dirty |= ui
+                    .add_enabled(
+                        self.config.midi_enabled,
+                        egui::DragValue::new(&mut self.config.midi_bend_range)
+                            .range(1..=24)
+                            .prefix("±")
+                            .suffix(" semitones"),
+                    )
+                    .on_hover_text("Must match the pitch-bend range of the receiving synth")
+                    .changed();
+                ui.end_row();
+
+                ui.label("OSC out");
+                ui.horizontal(|ui| {
+                    let mut osc_changed = ui
+                        .checkbox(&mut self.config.osc_enabled, "")
+                        .on_hover_text("Broadcast /chroma/pitch and /chroma/silence over UDP")
+                        .changed();
+                    let hover = match &self.osc {
+                        Some(osc) => format!("Sending to {}", osc.target()),
+                        None => "host:port to send OSC to".to_string(),
+                    };
+                    let response = ui
+                        .add_enabled(
+                            self.config.osc_enabled,
+                            egui::TextEdit::singleline(&mut self.osc_target_input)
+                                .hint_text("127.0.0.1:9000")
+                                .desired_width(150.0),
+                        )
+                        .on_hover_text(hover);
+                    if response.lost_focus() && self.osc_target_input != self.config.osc_target {
+                        self.config.osc_target = self.osc_target_input.trim().to_string();
+                        osc_changed = true;
+                    }
+                    if osc_changed {
+                        self.config_dirty = true;
+                        self.apply_osc();
+                    }
+                });
+                ui.end_row();
+
+                ui.label("WebSocket");
+                ui.horizontal(|ui| {
+                    let websocket_changed = ui
+                        .checkbox(&mut self.config.websocket_enabled, "")
+                        .on_hover_text("Serve live readings as JSON for browser displays")
+                        .changed();
+                    let hover = match &self.websocket {
+                        Some(server) => format!(
+                            "Serving ws://{} to {} clients",
+                            server.address(),
+                            server.client_count()
+                        ),
+                        None => "Port to serve on".to_string(),
+                    };
+                    let response = ui
+                        .add_enabled(
+                            self.config.websocket_enabled,
+                            egui::DragValue::new(&mut self.config.websocket_port)
+                                .range(1024..=65535)
+                                .prefix("port "),
+                        )
+                        .on_hover_text(hover);
+                    if websocket_changed {
+                        self.config_dirty = true;
+                        self.apply_websocket();
+                    } else if response.drag_stopped() || response.lost_focus() {
+                        self.finish_websocket_port_edit();
+                    }
+                });
+                ui.end_row();
+
+                ui.label("Log size");
+                changed |= ui
+                    .add(
+                        egui::DragValue::new(&mut self.config.log_capacity)
+                            .range(1_000..=1_000_000)
+                            .speed(100)
+                            .suffix(" readings"),
+                    )
+                    .on_hover_text("Stable readings kept for export; the oldest are dropped")
+                    .changed();
+                ui.end_row();
+            });
+
+        ui.add_space(4.0);
+        changed |= ui
+            .checkbox(
+                &mut self.config.listen_in_background,
+                "Keep listening when minimized",
+            )
+            .on_hover_text("Otherwise the microphone is released until the window is shown again")
+            .changed();
+        changed |= ui
+            .add_enabled(
+                self.tray.is_some(),
+                egui::Checkbox::new(&mut self.config.close_to_tray, "Close to tray"),
+            )
+            .on_hover_text("Closing the window hides it; quit from the tray menu")
+            .on_disabled_hover_text("No tray is available on this desktop")
+            .changed();
+        ui.horizontal(|ui| {
+            ui.label("Show/hide shortcut");
+            let response = ui
+                .add(
+                    egui::TextEdit::singleline(&mut self.shortcut_input)
+                        .hint_text("none")
+                        .desired_width(110.0),
+                )
+                .on_hover_text("Works from any application, e.g. Ctrl+Shift+T; blank for none");
+            if response.lost_focus() && self.shortcut_input.trim() != self.config.toggle_shortcut {
+                let text = self.shortcut_input.clone();
+                self.set_toggle_shortcut(&text);
+            }
+        });
+        changed |= ui
+            .checkbox(&mut self.config.show_spectrum, "Spectrum behind needle")
+            .changed();
+        ui.add_enabled_ui(self.config.show_spectrum, |ui| {
+            changed |= ui
+                .checkbox(&mut self.config.spectrum_log_scale, "Log frequency axis")
+                .changed();
+        });
+        changed |= ui
+            .checkbox(&mut self.config.show_diagnostics, "Diagnostics")
+            .on_hover_text("Show what the audio input and detector are doing (D)")
+            .changed();
+        changed |= ui
+            .checkbox(&mut self.config.show_beats, "Beat rate")
+            .on_hover_text(
+                "How often the note beats against the target, or the drone while it plays",
+            )
+            .changed();
+        changed |= ui
+            .checkbox(&mut self.config.identify_intervals, "Identify intervals")
+            .on_hover_text("Name the interval when two or three notes sound together")
+            .changed();
+
+        ui.add_space(4.0);
+        ui.horizontal(|ui| {
+            ui.label("Scala scale");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.scala_path_input)
+                    .hint_text("path/to/scale.scl")
+                    .desired_width(110.0),
+            );
+            if ui.button("Load").clicked() {
+                self.load_scale();
+            }
+            if self.scale.is_some() && ui.button("✕").on_hover_text("Clear scale").clicked() {
+                self.clear_scale();
+            }
+        });
+        if let Some(scale) = &self.scale {
+            ui.label(
+                egui::RichText::new(format!(
+                    "{} ({} degrees)",
+                    scale.description,
+                    scale.degree_count()
+                ))
+                .size(11.0)
+                .color(self.theme.secondary_text),
+            );
+            ui.horizontal(|ui| {
+                ui.label("Degree 0 at");
+                changed |= ui
+                    .add(
+                        egui::DragValue::new(&mut self.config.scala_reference)
+                            .range(20.0..=2000.0)
+                            .speed(0.1)
+                            .fixed_decimals(2)
+                            .suffix(" Hz"),
+                    )
+                    .changed();
+            });
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Recordings");
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut self.recordings_path_input)
+                    .hint_text("data folder")
+                    .desired_width(150.0),
+            );
+            if response.changed() {
+                let input = self.recordings_path_input.trim();
+                self.config.recordings_dir =
+                    (!input.is_empty()).then(|| std::path::PathBuf::from(input));
+                self.config_dirty = true;
+            }
+        });
+        if ui
+            .button("Analyse a recording…")
+            .on_hover_text("Plot the pitch of a WAV file with the current settings")
+            .clicked()
+        {
+            self.analysis.get_or_insert_with(AnalysisView::default);
+        }
+        if ui
+            .add_enabled(self.self_test.is_none(), egui::Button::new("Self test"))
+            .on_hover_text("Check detection accuracy from E1 to E7 with generated tones")
+            .clicked()
+        {
+            self.start_self_test();
+        }
+
+        ui.add_space(8.0);
+        if ui.button("Reset to defaults").clicked() {
+            self.config = TunerConfig::default();
+            self.osc_target_input = self.config.osc_target.clone();
+            self.shortcut_input = self.config.toggle_shortcut.clone();
+            let text = self.shortcut_input.clone();
+            self.set_toggle_shortcut(&text);
+            self.apply_midi();
+            self.apply_osc();
+            self.apply_websocket();
+            changed = true;
+        }
+
+        if changed {
+            self.apply_config();
+        }
+    }
+
+    fn refresh_audio_devices(&mut self) {
+        let Some(scan) = self.device_monitor.try_recv() else {
+            return;
+        };
+
+        let first_scan = self.available_devices.is_empty();
+        let devices_changed = device_list_changed(&self.available_devices, &scan.devices);
+        self.available_devices = scan.devices;
+        self.system_default = scan.default;
+        let live = self.input.as_ref().is_none_or(|input| input.is_live());
+        if first_scan || !live || !devices_changed {
+            return;
+        }
+
+        log::info!("Audio device list changed - refreshing");
+        if !self.available_devices.contains(&self.current_device_name) {
+            log::warn!(
+                "Device '{}' is no longer available, reopening capture",
+                self.current_device_name
+            );
+            self.recover_capture();
+        } else if !self.config.follow_default_device {
+            self.switch_to_preferred_device();
+        }
+    }
+
+    /// The system default to move capture to, if following it and it changed.
+    /// Returns `None` within the cooldown of the previous switch; the change
+    /// is picked up once the cooldown is over, unless it has flapped back.
+    fn default_switch_due(&mut self, now: Instant) -> Option<String> {
+        if !self.config.follow_default_device
+            || !self.input.as_ref().is_none_or(|input| input.is_live())
+        {
+            return None;
+        }
+        let default = self.system_default.as_ref()?;
+        if *default == self.current_device_name || !self.available_devices.contains(default) {
+            return None;
+        }
+        let cooling_down = self
+            .last_default_switch
+            .is_some_and(|last| now.saturating_duration_since(last) < DEFAULT_SWITCH_COOLDOWN);
+        if cooling_down {
+            return None;
+        }
+
+        self.last_default_switch = Some(now);
+        Some(default.clone())
+    }
+
+    fn follow_system_default(&mut self, now: Instant) {
+        let Some(default) = self.default_switch_due(now) else {
+            return;
+        };
+        match find_input_device(&default) {
+            Some(device) => {
+                log::info!("System default input changed to '{}'", default);
+                self.switch_device(default, device, false);
+            }
+            None => log::warn!("Default input '{}' disappeared before opening", default),
+        }
+    }
+
+    fn set_follow_default(&mut self, follow: bool) {
+        if self.config.follow_default_device != follow {
+            self.config.follow_default_device = follow;
+            self.config_dirty = true;
+        }
+    }
+
+    /// Opens a device the user picked, which stops following the default.
+    fn select_device(&mut self, device_name: String) {
+        self.set_follow_default(false);
+        match find_input_device(&device_name) {
+            Some(device) => self.switch_device(device_name, device, true),
+            None => self.report_error(format!("'{}' is no longer available", device_name)),
+        }
+    }
+
+    fn switch_to_preferred_device(&mut self) {
+        let Some(preferred) = self.config.preferred_device.clone() else {
+            return;
+        };
+        if preferred == self.current_device_name || !self.available_devices.contains(&preferred) {
+            return;
+        }
+
+        if let Some(device) = find_input_device(&preferred) {
+            log::info!("Preferred device '{}' is available again", preferred);
+            self.switch_device(preferred, device, true);
+        }
+    }
+
+    fn draw_channel_selector(&mut self, ui: &mut egui::Ui) {
+        let (channels, selection) = match self.audio_data.lock() {
+            Ok(audio_data) => (audio_data.channels, audio_data.channel_selection),
+            Err(_) => return,
+        };
+
+        let label = |selection: ChannelSelection| match selection {
+            ChannelSelection::Mix => "Mix".to_string(),
+            ChannelSelection::Channel(index) => format!("Ch {}", index + 1),
+        };
+
+        let mut selected = selection;
+        egui::ComboBox::from_id_salt("channel_selector")
+            .selected_text(label(selection))
+            .width(55.0)
+            .height(25.0)
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut selected, ChannelSelection::Mix, "Mix");
+                for index in 0..channels {
+                    let option = ChannelSelection::Channel(index);
+                    ui.selectable_value(&mut selected, option, label(option));
+                }
+            });
+
+        if selected != selection {
+            self.select_channel(selected);
+        }
+    }
+
+    /// Draws the note, needle and deviation, scaled up from their default
+    /// layout to fill the room left in `ui`.
+    fn draw_tuner_display(&mut self, ui: &mut egui::Ui) {
+        let area = ui.available_rect_before_wrap();
+        let scale = display_scale(area.size());
+        // Centred in the room left over once the scaled layout fits.
+        let spare = area.height() - DISPLAY_BASE_SIZE.y * scale;
+        let center = egui::pos2(area.center().x, area.top() + 31.0 * scale + spare / 2.0);
+
+        let mut heading = None;
+        let mut direction_cents = None;
+        ui.scope_builder(
+            egui::UiBuilder::new().max_rect(egui::Rect::from_center_size(
+                center - egui::vec2(0.0, 30.0 * scale),
+                egui::vec2(280.0, 40.0) * scale,
+            )),
+            |ui| {
+                ui.vertical_centered(|ui| {
+                    if self.challenge.is_some() {
+                        self.draw_challenge_heading(ui, scale);
+                    } else if self.guided.is_some() {
+                        self.draw_guided_heading(ui, scale);
+                    } else if let Some((notes, interval)) = self.interval_reading() {
+                        let names: Vec<String> = notes
+                            .iter()
+                            .map(|note| note.display_name(self.note_naming()))
+                            .collect();
+                        ui.label(
+                            egui::RichText::new(names.join(" · "))
+                                .size(30.0 * scale)
+                                .color(self.theme.text)
+                                .strong(),
+                        );
+                        ui.label(
+                            egui::RichText::new(interval.to_string())
+                                .size(12.0 * scale)
+                                .color(self.deviation_color(interval.cents_off)),
+                        );
+                    } else if let Some(note) = &self.reading.note {
+                        direction_cents = Some(note.cents_off);
+                        heading = Some(
+                            ui.add(
+                                egui::Label::new(
+                                    egui::RichText::new(self.note_label(note))
+                                        .size(36.0 * scale)
+                                        .color(self.theme.text)
+                                        .strong(),
+                                )
+                                .sense(egui::Sense::click()),
+                            ),
+                        );
+                        let display = self.config.deviation_display;
+                        let frequencies = if display.shows_hz() {
+                            format!(
+                                "target {:.1} · {:.1} Hz",
+                                note.target_frequency, note.frequency
+                            )
+                        } else {
+                            format!("{:.1} Hz", note.frequency)
+                        };
+                        let detail = match self.reading.string_number {
+                            Some(string_number) => {
+                                let arrow = if self.in_tune(note.cents_off) {
+                                    ""
+                                } else if note.cents_off > 0.0 {
+                                    "↓ "
+                                } else {
+                                    "↑ "
+                                };
+                                format!("{}string {} · {}", arrow, string_number, frequencies)
+                            }
+                            None if self.target_note.is_some() => {
+                                match self.measured_note(note.frequency) {
+                                    Some(measured) => format!(
+                                        "playing {} · {}",
+                                        measured.display_name(self.note_naming()),
+                                        frequencies
+                                    ),
+                                    None => frequencies,
+                                }
+                            }
+                            None => match self.reading.scale_match {
+                                Some(matched) if matched.period != 0 => {
+                                    format!("period {:+} · {}", matched.period, frequencies)
+                                }
+                                _ => frequencies,
+                            },
+                        };
+                        ui.label(
+                            egui::RichText::new(detail)
+                                .size(12.0 * scale)
+                                .color(self.theme.secondary_text),
+                        );
+                    } else if self.tone_playing() {
+                        ui.label(
+                            egui::RichText::new(format!(
+                                "♪ {} · {:.1} Hz",
+                                self.tone_label(self.tone_target),
+                                self.tone_frequency()
+                            ))
+                            .size(18.0 * scale)
+                            .color(self.theme.secondary_text),
+                        );
+                        ui.add_space(16.0 * scale);
+                    } else if self.level_meter.too_quiet(Instant::now()) {
+                        ui.label(
+                            egui::RichText::new("Signal too low")
+                                .size(18.0 * scale)
+                                .color(self.palette.warning),
+                        );
+                        ui.label(
+                            egui::RichText::new("Check the input device and gain")
+                                .size(12.0 * scale)
+                                .color(self.theme.secondary_text),
+                        );
+                    } else if let Some(midi) = self.target_note {
+                        let target = midi_to_note(midi, 0.0, self.config.a4_reference);
+                        heading = Some(
+                            ui.add(
+                                egui::Label::new(
+                                    egui::RichText::new(target.display_name(self.note_naming()))
+                                        .size(36.0 * scale)
+                                        .color(self.theme.secondary_text)
+                                        .strong(),
+                                )
+                                .sense(egui::Sense::click()),
+                            ),
+                        );
+                        ui.label(
+                            egui::RichText::new("♪ Play a note to tune to it")
+                                .size(12.0 * scale)
+                                .color(self.theme.secondary_text),
+                        );
+                    } else {
+                        heading = Some(
+                            ui.add(
+                                egui::Label::new(
+                                    egui::RichText::new("♪ Play a note...")
+                                        .size(18.0 * scale)
+                                        .color(self.theme.secondary_text),
+                                )
+                                .sense(egui::Sense::click()),
+                            ),
+                        );
+                        ui.add_space(16.0 * scale);
+                    }
+                });
+            },
+        );
+        if let Some(heading) = heading {
+            self.draw_target_note_picker(heading);
+        }
+
+        self.draw_tuner_needle(ui, self.needle.cents, center, scale);
+        self.draw_confidence(ui.painter(), center + egui::vec2(124.0, 5.0) * scale, scale);
+        self.draw_metronome_beat(
+            ui.painter(),
+            center + egui::vec2(-130.0, -30.0) * scale,
+            scale,
+        );
+        if let Some(cents_off) = direction_cents {
+            self.draw_tune_direction(
+                ui.painter(),
+                center + egui::vec2(105.0, -32.0) * scale,
+                cents_off,
+                scale,
+            );
+            self.draw_octave_shift(ui, center + egui::vec2(70.0, -35.0) * scale, scale);
+        }
+        self.draw_hold_button(
+            ui,
+            egui::Rect::from_center_size(
+                center + egui::vec2(-130.0, 5.0) * scale,
+                egui::vec2(34.0, 16.0) * scale,
+            ),
+            scale,
+        );
+        if self.config.show_stability {
+            self.draw_stability(ui.painter(), center + egui::vec2(0.0, 74.0) * scale, scale);
+        }
+
+        ui.scope_builder(
+            egui::UiBuilder::new().max_rect(egui::Rect::from_center_size(
+                center + egui::vec2(0.0, 45.0 * scale),
+                egui::vec2(200.0, 34.0) * scale,
+            )),
+            |ui| {
+                ui.vertical_centered(|ui| {
+                    if let Some(note) = &self.reading.note {
+                        let display = self.config.deviation_display;
+                        let mut parts = Vec::new();
+                        if display.shows_cents() {
+                            let sign = if note.cents_off > 0.0 { "+" } else { "" };
+                            parts.push(format!("{}{:.0} cents", sign, note.cents_off));
+                        }
+                        if display.shows_hz() {
+                            parts.push(format!(
+                                "Δ {:+.1} Hz",
+                                note.frequency - note.target_frequency
+                            ));
+                        }
+                        let drone = self.drone_playing().then(|| self.drone_frequency());
+                        if let Some(beats) =
+                            audible_beats(&self.reading, drone).filter(|_| self.config.show_beats)
+                        {
+                            parts.push(format!("beats {:.1} Hz", beats));
+                        }
+                        ui.label(
+                            egui::RichText::new(parts.join(" · "))
+                                .size(11.0 * scale)
+                                .color(self.deviation_color(note.cents_off)),
+                        );
+                        if let Some((low, high)) = self.hold.and_then(|hold| hold.cents_range) {
+                            ui.label(
+                                egui::RichText::new(format!("range {:+.0} to {:+.0} ¢", low, high))
+                                    .size(10.0 * scale)
+                                    .color(self.theme.secondary_text),
+                            );
+                        } else if let Some(vibrato) = self.reading.vibrato {
+                            ui.label(
+                                egui::RichText::new(format!(
+                                    "vibrato {:.1} Hz, ±{:.0} ¢",
+                                    vibrato.rate,
+                                    vibrato.width_cents / 2.0
+                                ))
+                                .size(10.0 * scale)
+                                .color(self.theme.secondary_text),
+                            );
+                        } else if let Some(label) = self.partials_label() {
+                            ui.label(
+                                egui::RichText::new(label)
+                                    .size(10.0 * scale)
+                                    .color(self.theme.secondary_text),
+                            )
+                            .on_hover_text("Inharmonicity of the string and its partials");
+                        }
+                    } else {
+                        ui.label(egui::RichText::new("").size(11.0 * scale));
+                    }
+                });
+            },
+        );
+    }
+
+    /// The string to tune next and the list of strings with their marks, or
+    /// the all-done message with a way to start over.
+    fn draw_guided_heading(&mut self, ui: &mut egui::Ui, scale: f32) {
+        let Some(guided) = &self.guided else {
+            return;
+        };
+        let naming = self.note_naming();
+        if guided.finished() {
+            let mut restart = false;
+            ui.horizontal(|ui| {
+                ui.label(
+                    egui::RichText::new("✔ All strings in tune")
+                        .size(20.0 * scale)
+                        .color(self.palette.in_tune),
+                );
+                restart = ui
+                    .add(
+                        egui::Button::new(
+                            egui::RichText::new("↺")
+                                .size(16.0 * scale)
+                                .color(self.theme.secondary_text),
+                        )
+                        .frame(false),
+                    )
+                    .on_hover_text("Tune again")
+                    .clicked();
+            });
+            if restart {
+                if let Some(guided) = &mut self.guided {
+                    guided.restart();
+                }
+            }
+            return;
+        }
+
+        let (heading_string, heading_color, mark) = match guided.just_completed(Instant::now()) {
+            Some(string_number) => (string_number, self.palette.in_tune, "✔ "),
+            None => (guided.current, self.theme.text, ""),
+        };
+        if let Some(label) = guided.preset.string_label(heading_string, naming) {
+            ui.label(
+                egui::RichText::new(format!("{}String {}: {}", mark, heading_string, label))
+                    .size(24.0 * scale)
+                    .color(heading_color)
+                    .strong(),
+            );
+        }
+
+        let mut strings = egui::text::LayoutJob::default();
+        for string_number in (1..=guided.status.len()).rev() {
+            let Some(note) = guided.preset.string(string_number) else {
+                continue;
+            };
+            let (mark, color) = match guided.status(string_number) {
+                StringStatus::InTune => ("✔", self.palette.in_tune),
+                StringStatus::Off => ("✖", self.palette.off),
+                StringStatus::Untuned => ("·", self.theme.secondary_text),
+            };
+            let name_color = if string_number == guided.current {
+                self.theme.text
+            } else {
+                self.theme.secondary_text
+            };
+            let font = egui::FontId::proportional(12.0 * scale);
+            strings.append(
+                mark,
+                6.0 * scale,
+                egui::TextFormat::simple(font.clone(), color),
+            );
+            strings.append(
+                &note.display_name(naming),
+                2.0 * scale,
+                egui::TextFormat::simple(font, name_color),
+            );
+        }
+        ui.label(strings);
+    }
+
+    /// The target to play with the round's progress, or the summary of a
+    /// finished round with a way to play again.
+    fn draw_challenge_heading(&mut self, ui: &mut egui::Ui, scale: f32) {
+        let Some(challenge) = &self.challenge else {
+            return;
+        };
+        let now = Instant::now();
+        let naming = self.note_naming();
+        let a4_reference = self.config.a4_reference;
+        let name = |midi: i32| midi_to_note(midi, 0.0, a4_reference).display_name(naming);
+
+        if challenge.finished() {
+            let mut restart = false;
+            ui.horizontal(|ui| {
+                ui.label(
+                    egui::RichText::new(format!("Round over: {} points", challenge.score()))
+                        .size(20.0 * scale)
+                        .color(self.palette.in_tune),
+                );
+                restart = ui
+                    .add(
+                        egui::Button::new(
+                            egui::RichText::new("↺")
+                                .size(16.0 * scale)
+                                .color(self.theme.secondary_text),
+                        )
+                        .frame(false),
+                    )
+                    .on_hover_text("Play again")
+                    .clicked();
+            });
+            let high_score = if challenge.new_best {
+                "new high score!".to_string()
+            } else {
+                let best = self.config.challenge_high_scores.first().copied();
+                format!("high score {}", best.unwrap_or(0))
+            };
+            ui.label(
+                egui::RichText::new(format!(
+                    "{}/{} hit · best streak {} · {}",
+                    challenge.hits(),
+                    CHALLENGE_ROUND_NOTES,
+                    challenge.best_streak,
+                    high_score
+                ))
+                .size(12.0 * scale)
+                .color(self.theme.secondary_text),
+            );
+            if restart {
+                if let Some(challenge) = &mut self.challenge {
+                    challenge.restart(now);
+                }
+            }
+            return;
+        }
+
+        let (heading, color) = match challenge.just_finished(now) {
+            Some(result) if result.points > 0 => (
+                format!("✔ {} +{}", name(result.target), result.points),
+                self.palette.in_tune,
+            ),
+            Some(result) => (
+                format!("✖ {} missed", name(result.target)),
+                self.palette.off,
+            ),
+            None if challenge.held.is_some() => (
+                format!("Play {}", name(challenge.target)),
+                self.palette.in_tune,
+            ),
+            None => (format!("Play {}", name(challenge.target)), self.theme.text),
+        };
+        ui.label(
+            egui::RichText::new(heading)
+                .size(24.0 * scale)
+                .color(color)
+                .strong(),
+        );
+        ui.label(
+            egui::RichText::new(format!(
+                "{}/{} · {} s · streak {} · {} points",
+                challenge.results.len() + 1,
+                CHALLENGE_ROUND_NOTES,
+                challenge.time_left(now).as_secs_f32().ceil(),
+                challenge.streak,
+                challenge.score()
+            ))
+            .size(12.0 * scale)
+            .color(self.theme.secondary_text),
+        );
+    }
+
+    /// The inharmonicity coefficient and partials measured in piano mode,
+    /// e.g. "B 3.2e-4 · 110 220 331 441 Hz"; `None` until two partials are
+    /// found.
+    fn partials_label(&self) -> Option<String> {
+        if !self.config.piano_mode {
+            return None;
+        }
+        let inharmonicity = estimate_inharmonicity(&self.partials)?;
+        let partials: Vec<String> = self
+            .partials
+            .iter()
+            .map(|partial| {
+                partial.map_or("–".to_string(), |frequency| format!("{:.0}", frequency))
+            })
+            .collect();
+        Some(format!(
+            "B {:.1e} · {} Hz",
+            inharmonicity,
+            partials.join(" ")
+        ))
+    }
+
+    /// The note's name, or its scale degree when tuning to a Scala scale.
+    /// The notes sounding together, lowest first, with the interval between
+    /// the strongest two; `None` unless at least two are.
+    fn interval_reading(&self) -> Option<(Vec<Note>, Interval)> {
+        if !self.config.identify_intervals {
+            return None;
+        }
+        let (temperament, tonic) = (self.config.temperament, self.config.temperament_tonic);
+        let mut notes: Vec<Note> = self
+            .peaks
+            .iter()
+            .filter_map(|&(frequency, _)| {
+                frequency_to_tempered_note(frequency, self.config.a4_reference, temperament, tonic)
+            })
+            .collect();
+        if notes.len() < 2 {
+            return None;
+        }
+        let interval = Interval::between(&notes[0], &notes[1], temperament, tonic);
+        notes.sort_by(|a, b| a.frequency.total_cmp(&b.frequency));
+        Some((notes, interval))
+    }
+
+    fn note_label(&self, note: &Note) -> String {
+        match self.reading.scale_match {
+            Some(matched) => format!("°{}", matched.degree),
+            None => note.display_name(self.note_naming()),
+        }
+    }
+
+    /// How note names are spelled: as set, but with the key's accidentals
+    /// when snapping to a key.
+    fn note_naming(&self) -> NoteNaming {
+        self.config.spelling()
+    }
+
+    /// The note `frequency` is nearest to, as chromatic mode would name it.
+    fn measured_note(&self, frequency: f32) -> Option<Note> {
+        frequency_to_tempered_note(
+            frequency,
+            self.config.a4_reference,
+            self.config.temperament,
+            self.config.temperament_tonic,
+        )
+    }
+
+    /// Small button that switches to the next layout, for the modes that
+    /// have no settings button.
+    fn draw_mode_button(&mut self, ui: &mut egui::Ui, rect: egui::Rect) {
+        let next = self.config.display_mode.next();
+        let button = ui.put(
+            rect,
+            egui::Button::new(
+                egui::RichText::new("⛶")
+                    .size(rect.height() * 0.7)
+                    .color(self.theme.secondary_text),
+            )
+            .frame(false),
+        );
+        if button
+            .on_hover_text(format!("{} layout (M)", next.label()))
+            .clicked()
+        {
+            let ctx = ui.ctx().clone();
+            self.set_display_mode(&ctx, next);
+        }
+    }
+
+    /// The mini strip: the note on the left and a thin deviation bar.
+    fn draw_mini_display(&mut self, ui: &mut egui::Ui) {
+        let rect = ui.max_rect();
+        let button_size = 14.0;
+        self.draw_mode_button(
+            ui,
+            egui::Rect::from_min_size(
+                egui::pos2(rect.right() - button_size, rect.top()),
+                egui::vec2(button_size, button_size),
+            ),
+        );
+
+        let painter = ui.painter();
+        let (name, color) = match &self.reading.note {
+            Some(note) => (self.note_label(note), self.theme.text),
+            None => ("–".to_string(), self.theme.secondary_text),
+        };
+        let name_rect = painter.text(
+            egui::pos2(rect.left(), rect.center().y),
+            egui::Align2::LEFT_CENTER,
+            name,
+            egui::FontId::proportional(rect.height() * 0.7),
+            color,
+        );
+
+        let bar = egui::Rect::from_min_max(
+            egui::pos2(
+                name_rect.right().max(rect.left() + 50.0) + 8.0,
+                rect.center().y - 2.0,
+            ),
+            egui::pos2(rect.right() - button_size - 4.0, rect.center().y + 2.0),
+        );
+        painter.rect_filled(bar, 2.0, self.theme.track);
+        painter.line_segment(
+            [
+                egui::pos2(bar.center().x, bar.top() - 3.0),
+                egui::pos2(bar.center().x, bar.bottom() + 3.0),
+            ],
+            egui::Stroke::new(1.0, self.theme.faint),
+        );
+        let cents = self.needle.cents;
+        if cents != 0.0 || self.reading.note.is_some() {
+            let offset = (cents / self.needle_scale.range).clamp(-1.0, 1.0) * bar.width() / 2.0;
+            self.draw_needle_dot(
+                painter,
+                egui::pos2(bar.center().x + offset, bar.center().y),
+                4.0,
+                cents,
+                self.needle_scale.range,
+            );
+        }
+    }
+
+    /// The performance layout: a huge note name over the needle and the
+    /// deviation, all sized from the window.
+    fn draw_performance_display(&mut self, ui: &mut egui::Ui) {
+        let rect = ui.max_rect();
+        let button_size = 20.0;
+        self.draw_mode_button(
+            ui,
+            egui::Rect::from_min_size(
+                egui::pos2(rect.right() - button_size - 8.0, rect.top()),
+                egui::vec2(button_size, button_size),
+            ),
+        );
+
+        let (name, color) = match &self.reading.note {
+            Some(note) => (self.note_label(note), self.theme.text),
+            None => ("♪".to_string(), self.theme.secondary_text),
+        };
+        ui.painter().text(
+            egui::pos2(rect.center().x, rect.top() + rect.height() * 0.38),
+            egui::Align2::CENTER_CENTER,
+            name,
+            egui::FontId::proportional((rect.height() * 0.45).min(rect.width() * 0.3)),
+            color,
+        );
+
+        if self.hold.is_some() {
+            ui.painter().text(
+                rect.left_top(),
+                egui::Align2::LEFT_TOP,
+                "HOLD",
+                egui::FontId::proportional(rect.height() * 0.05),
+                self.palette.warning,
+            );
+        }
+
+        let scale = (rect.width() * 0.8 / 220.0).min(rect.height() * 0.12 / 20.0);
+        let needle_center = egui::pos2(rect.center().x, rect.top() + rect.height() * 0.72);
+        self.draw_tuner_needle(
+            ui,
+            self.needle.cents,
+            needle_center - egui::vec2(0.0, 5.0 * scale),
+            scale,
+        );
+
+        if let Some(note) = &self.reading.note {
+            ui.painter().text(
+                egui::pos2(rect.center().x, rect.top() + rect.height() * 0.88),
+                egui::Align2::CENTER_CENTER,
+                format!("{:+.0} cents", note.cents_off),
+                egui::FontId::proportional(rect.height() * 0.07),
+                self.deviation_color(note.cents_off),
+            );
+        }
+    }
+
+    /// The octave of the note on display between ▲/▼ buttons that tell the
+    /// detector it has the octave wrong; the octave is highlighted while
+    /// corrected.
+    fn draw_octave_shift(&mut self, ui: &mut egui::Ui, center: egui::Pos2, scale: f32) {
+        let Some(note) = &self.reading.note else {
+            return;
+        };
+        let color = if self.octave_shift.is_some() {
+            self.palette.warning
+        } else {
+            self.theme.secondary_text
+        };
+        ui.painter().text(
+            center,
+            egui::Align2::CENTER_CENTER,
+            note.octave.to_string(),
+            egui::FontId::proportional(11.0 * scale),
+            color,
+        );
+        let size = egui::vec2(14.0, 10.0) * scale;
+        for (octaves, arrow, offset, hover) in [
+            (
+                1,
+                "▲",
+                -11.0,
+                "Detected an octave too low: search higher (Page Up)",
+            ),
+            (
+                -1,
+                "▼",
+                11.0,
+                "Detected an octave too high: search lower (Page Down)",
+            ),
+        ] {
+            let button = ui.put(
+                egui::Rect::from_center_size(center + egui::vec2(0.0, offset * scale), size),
+                egui::Button::new(
+                    egui::RichText::new(arrow)
+                        .size(8.0 * scale)
+                        .color(self.theme.faint),
+                )
+                .frame(false),
+            );
+            if button.on_hover_text(hover).clicked() {
+                self.shift_octave(octaves);
+            }
+        }
+    }
+
+    /// Pauses the display on the current reading; shows a "HOLD" badge while
+    /// it is paused.
+    fn draw_hold_button(&mut self, ui: &mut egui::Ui, rect: egui::Rect, scale: f32) {
+        let (text, color, hover) = match self.hold {
+            Some(_) => ("HOLD", self.palette.warning, "Resume live readings (Space)"),
+            None => ("⏸", self.theme.faint, "Hold the current reading (Space)"),
+        };
+        let button = ui.put(
+            rect,
+            egui::Button::new(
+                egui::RichText::new(text)
+                    .size(9.0 * scale)
+                    .color(color)
+                    .strong(),
+            )
+            .frame(false),
+        );
+        if button.on_hover_text(hover).clicked() {
+            self.toggle_hold(Instant::now());
+        }
+    }
+
+    /// Three dots filling up with detection confidence; lit dots turn orange
+    /// while the confidence is too low for the tuner to lock.
+    fn draw_confidence(&self, painter: &egui::Painter, left_center: egui::Pos2, scale: f32) {
+        let confidence = self.reading.confidence;
+        let lit_color = if confidence < self.config.min_confidence {
+            self.palette.warning
+        } else {
+            self.theme.lit
+        };
+
+        for dot in 0..3 {
+            let lit = confidence > (dot + 1) as f32 / 4.0;
+            painter.circle_filled(
+                left_center + egui::vec2(dot as f32 * 7.0 * scale, 0.0),
+                2.0 * scale,
+                if lit { lit_color } else { self.theme.track },
+            );
+        }
+    }
+
+    /// A sparkline of the note's recent cents centred on `center`, with the
+    /// standard deviation over the last second beside it, green once it is
+    /// below the stability target.
+    fn draw_stability(&self, painter: &egui::Painter, center: egui::Pos2, scale: f32) {
+        let Some(note) = &self.reading.note else {
+            return;
+        };
+        let plot = egui::Rect::from_center_size(center, egui::vec2(100.0, 12.0) * scale);
+        painter.hline(
+            plot.x_range(),
+            plot.center().y,
+            egui::Stroke::new(1.0, self.theme.faint),
+        );
+
+        let now = Instant::now();
+        let since = now.checked_sub(SPARKLINE_SPAN).unwrap_or(now);
+        let points = &self.history.points;
+        let to_pos = |point: &HistoryPoint| {
+            let age = now.saturating_duration_since(point.at).as_secs_f32();
+            let fraction = (point.cents / SPARKLINE_CENTS_RANGE).clamp(-1.0, 1.0);
+            egui::pos2(
+                plot.right() - age / SPARKLINE_SPAN.as_secs_f32() * plot.width(),
+                plot.center().y - fraction * plot.height() / 2.0,
+            )
+        };
+        for segment in self.history.segments(since) {
+            if !same_target(
+                points[segment.start].target_frequency,
+                note.target_frequency,
+            ) {
+                continue;
+            }
+            let line: Vec<egui::Pos2> = segment.map(|index| to_pos(&points[index])).collect();
+            painter.add(egui::Shape::line(
+                line,
+                egui::Stroke::new(1.0 * scale, self.theme.secondary_text),
+            ));
+        }
+
+        if let Some(deviation) = self.reading.cents_deviation {
+            let color = if deviation < self.config.stability_target_cents {
+                self.palette.in_tune
+            } else {
+                self.theme.secondary_text
+            };
+            painter.text(
+                plot.right_center() + egui::vec2(6.0 * scale, 0.0),
+                egui::Align2::LEFT_CENTER,
+                format!("σ {:.1}¢", deviation),
+                egui::FontId::proportional(10.0 * scale),
+                color,
+            );
+        }
+    }
+
+    fn deviation_color(&self, cents_off: f32) -> egui::Color32 {
+        self.palette.deviation(
+            cents_off,
+            self.config.in_tune_cents,
+            self.config.close_cents,
+        )
+    }
+
+    fn in_tune(&self, cents_off: f32) -> bool {
+        cents_off.abs() < self.config.in_tune_cents
+    }
+
+    /// What the input and detector are doing, one line per figure, for the
+    /// diagnostics overlay.
+    fn diagnostics_lines(&self, now: Instant) -> Vec<String> {
+        let (sample_rate, input_rate, channels, buffer_frames, block_len, last_push) =
+            match self.audio_data.lock() {
+                Ok(audio_data) => (
+                    audio_data.sample_rate,
+                    audio_data.input_sample_rate,
+                    audio_data.channels,
+                    audio_data.buffer_frames,
+                    audio_data.block_len,
+                    audio_data.last_push(),
+                ),
+                Err(_) => return Vec::new(),
+            };
+        let fft_size = self.config.analysis_size();
+        let low_range = self
+            .config
+            .low_range
+            .active(self.reading.note.as_ref().map(|note| note.target_frequency));
+        let analysis_rate = if low_range {
+            sample_rate / DECIMATION as f32
+        } else {
+            sample_rate
+        };
+        let bin_width = analysis_rate / fft_size as f32;
+        let bin_cents = |frequency: f32| 1200.0 * ((frequency + bin_width) / frequency).log2();
+
+        let mut lines = vec![
+            format!("device   {}", self.current_device_name),
+            if input_rate == sample_rate {
+                format!("input    {:.0} Hz · {} ch", input_rate, channels)
+            } else {
+                format!(
+                    "input    {:.0} Hz → {:.0} Hz · {} ch",
+                    input_rate, sample_rate, channels
+                )
+            },
+            format!(
+                "buffer   {} · {} samples/callback",
+                match buffer_frames {
+                    Some(frames) => format!("{} frames", frames),
+                    None => "default".to_string(),
+                },
+                block_len
+            ),
+            format!(
+                "window   {} samples{} · {:.2} Hz/bin",
+                fft_size,
+                if low_range { " ÷4" } else { "" },
+                bin_width
+            ),
+            format!(
+                "bin      {:.1} ¢ @110 · {:.1} ¢ @440",
+                bin_cents(110.0),
+                bin_cents(440.0)
+            ),
+        ];
+        lines.push(match self.detection_rate.per_second {
+            Some(rate) => format!("rate     {:.0} detections/s", rate),
+            None => "rate     –".to_string(),
+        });
+        lines.push(match last_push {
+            Some(at) => format!(
+                "callback {} ms ago",
+                now.saturating_duration_since(at).as_millis()
+            ),
+            None => "callback none yet".to_string(),
+        });
+        lines.push(match (&self.reading.note, self.reading.raw_cents) {
+            (Some(note), Some(raw)) => {
+                format!("cents    raw {:+.1} · smoothed {:+.1}", raw, note.cents_off)
+            }
+            _ => "cents    –".to_string(),
+        });
+        lines
+    }
+
+    /// Small monospace diagnostics in the bottom-left corner, over whatever
+    /// is drawn there.
+    fn draw_diagnostics(&self, ctx: &egui::Context, now: Instant) {
+        let lines = self.diagnostics_lines(now);
+        egui::Area::new(egui::Id::new("diagnostics"))
+            .anchor(egui::Align2::LEFT_BOTTOM, egui::vec2(6.0, -6.0))
+            .order(egui::Order::Foreground)
+            .interactable(false)
+            .show(ctx, |ui| {
+                egui::Frame {
+                    fill: Theme::translucent(self.theme.panel_fill, 220),
+                    corner_radius: 4.0.into(),
+                    inner_margin: egui::Margin::same(6),
+                    ..Default::default()
+                }
+                .show(ui, |ui| {
+                    ui.spacing_mut().item_spacing.y = 0.0;
+                    for line in lines {
+                        ui.label(
+                            egui::RichText::new(line)
+                                .monospace()
+                                .size(10.0)
+                                .color(self.theme.secondary_text),
+                        );
+                    }
+                });
+            });
+    }
+
+    /// An arrow beside the note name pointing the way to tune, bigger and
+    /// bolder the further off the note is, or a check mark when in tune. The
+    /// shape tells the direction, so it reads in every color scheme.
+    fn draw_tune_direction(
+        &self,
+        painter: &egui::Painter,
+        center: egui::Pos2,
+        cents_off: f32,
+        scale: f32,
+    ) {
+        let direction = TuneDirection::of(cents_off, self.config.in_tune_cents);
+        let caption = |color| {
+            painter.text(
+                center + egui::vec2(0.0, 19.0 * scale),
+                egui::Align2::CENTER_CENTER,
+                direction.label(),
+                egui::FontId::proportional(9.0 * scale),
+                color,
+            );
+        };
+        let tip = match direction {
+            TuneDirection::InTune => {
+                painter.text(
+                    center,
+                    egui::Align2::CENTER_CENTER,
+                    "✔",
+                    egui::FontId::proportional(22.0 * scale),
+                    self.palette.in_tune,
+                );
+                caption(self.palette.in_tune);
+                return;
+            }
+            TuneDirection::Up => -1.0,
+            TuneDirection::Down => 1.0,
+        };
+
+        let strength = TuneDirection::strength(cents_off, self.config.in_tune_cents);
+        let size = (7.0 + 5.0 * strength) * scale;
+        let color = Theme::translucent(
+            self.deviation_color(cents_off),
+            (255.0 * (0.45 + 0.55 * strength)) as u8,
+        );
+        painter.add(egui::Shape::convex_polygon(
+            vec![
+                center + egui::vec2(0.0, tip * size),
+                center + egui::vec2(-size, -tip * size),
+                center + egui::vec2(size, -tip * size),
+            ],
+            color,
+            egui::Stroke::NONE,
+        ));
+        caption(color);
+    }
+
+    /// The needle dot, which turns into a ring when in tune so that it
+    /// doesn't rely on color alone, and into an arrow pointing off the end of
+    /// a scale reaching `range` cents once the deviation is past it.
+    fn draw_needle_dot(
+        &self,
+        painter: &egui::Painter,
+        center: egui::Pos2,
+        radius: f32,
+        cents_off: f32,
+        range: f32,
+    ) {
+        let color = self.deviation_color(cents_off);
+        if cents_off.abs() > range {
+            let direction = cents_off.signum();
+            painter.add(egui::Shape::convex_polygon(
+                vec![
+                    center + egui::vec2(direction * radius, 0.0),
+                    center + egui::vec2(-direction * radius, -radius),
+                    center + egui::vec2(-direction * radius, radius),
+                ],
+                color,
+                egui::Stroke::NONE,
+            ));
+        } else if self.in_tune(cents_off) {
+            painter.circle_stroke(center, radius, egui::Stroke::new(radius / 3.0, color));
+        } else {
+            painter.circle_filled(center, radius, color);
+        }
+    }
+
+    fn draw_level_meter(&self, painter: &egui::Painter, rect: egui::Rect) {
+        let now = Instant::now();
+        let level = self.level_meter.level;
+        let to_fraction = |value: f32| {
+            if value <= 0.0 {
+                return 0.0;
+            }
+            ((20.0 * value.log10() + LEVEL_FLOOR_DB) / LEVEL_FLOOR_DB).clamp(0.0, 1.0)
+        };
+
+        let clip_center = egui::pos2(rect.left() + 2.0, rect.center().y);
+        let clip_color = if self.level_meter.clipping(now) {
+            self.palette.alert
+        } else {
+            self.theme.track
+        };
+        painter.circle_filled(clip_center, 2.5, clip_color);
+
+        let bar = egui::Rect::from_min_max(egui::pos2(rect.left() + 8.0, rect.top()), rect.max);
+        painter.rect_filled(bar, 1.5, self.theme.track);
+
+        let peak_db = 20.0 * level.peak.max(1e-6).log10();
+        let color = if peak_db > -3.0 {
+            self.palette.off
+        } else if peak_db > -12.0 {
+            self.palette.near
+        } else {
+            self.palette.in_tune
+        };
+
+        let rms_width = bar.width() * to_fraction(level.rms);
+        painter.rect_filled(
+            egui::Rect::from_min_size(bar.min, egui::vec2(rms_width, bar.height())),
+            1.5,
+            color,
+        );
+
+        let peak_x = bar.left() + bar.width() * to_fraction(level.peak);
+        painter.line_segment(
+            [
+                egui::pos2(peak_x, bar.top()),
+                egui::pos2(peak_x, bar.bottom()),
+            ],
+            egui::Stroke::new(1.0, color),
+        );
+    }
+
+    /// One bar per string, lowest first, reaching up when the string is sharp
+    /// and down when flat; strings not heard show a dot on the midline.
+    fn draw_strum(&self, ui: &mut egui::Ui) {
+        let (rect, _) = ui.allocate_exact_size(ui.available_size(), egui::Sense::hover());
+        let Some(preset) = &self.active_preset else {
+            return;
+        };
+        let painter = ui.painter_at(rect);
+        let plot =
+            egui::Rect::from_min_max(rect.min, egui::pos2(rect.right(), rect.bottom() - 16.0));
+        painter.rect_filled(plot, 4.0, self.theme.plot_fill);
+
+        let half_height = plot.height() / 2.0 - 4.0;
+        let band =
+            self.config.in_tune_cents.min(STRUM_CENTS_RANGE) / STRUM_CENTS_RANGE * half_height;
+        painter.rect_filled(
+            egui::Rect::from_center_size(plot.center(), egui::vec2(plot.width(), 2.0 * band)),
+            0.0,
+            self.palette.in_tune_band(),
+        );
+
+        let column_width = plot.width() / preset.strings.len().max(1) as f32;
+        for (index, string) in preset.strings.iter().enumerate() {
+            let x = plot.left() + (index as f32 + 0.5) * column_width;
+            let midline = egui::pos2(x, plot.center().y);
+            match self.strum.get(index).copied().flatten() {
+                Some(cents) => {
+                    let length = cents.clamp(-STRUM_CENTS_RANGE, STRUM_CENTS_RANGE)
+                        / STRUM_CENTS_RANGE
+                        * half_height;
+                    let color = self.deviation_color(cents);
+                    painter.rect_filled(
+                        egui::Rect::from_two_pos(
+                            egui::pos2(x - 6.0, midline.y - length.min(-1.0)),
+                            egui::pos2(x + 6.0, midline.y - length.max(1.0)),
+                        ),
+                        2.0,
+                        color,
+                    );
+                }
+                None => {
+                    painter.circle_filled(midline, 2.5, self.theme.faint);
+                }
+            }
+            painter.text(
+                egui::pos2(x, rect.bottom()),
+                egui::Align2::CENTER_BOTTOM,
+                self.note_naming().pitch_class_name(string.pitch_class),
+                egui::FontId::proportional(11.0),
+                self.theme.secondary_text,
+            );
+        }
+    }
+
+    fn draw_history(&self, ui: &mut egui::Ui) {
+        let (rect, _) = ui.allocate_exact_size(ui.available_size(), egui::Sense::hover());
+        let painter = ui.painter_at(rect);
+        let label_color = self.theme.secondary_text;
+        let band_color = self.palette.in_tune_band();
+        let plot = egui::Rect::from_min_max(egui::pos2(rect.left() + 34.0, rect.top()), rect.max);
+        painter.rect_filled(plot, 4.0, self.theme.plot_fill);
+
+        let now = Instant::now();
+        let span = Duration::from_secs(self.config.history_seconds);
+        let segments = self.history.segments(now.checked_sub(span).unwrap_or(now));
+        let points = &self.history.points;
+        let scale = self.config.history_scale;
+        let tolerance = self.config.in_tune_cents;
+        let band = |target: f32, cents: f32| target * 2.0f32.powf(cents / 1200.0);
+
+        let (low, high) = match scale {
+            HistoryScale::Cents => (-HISTORY_CENTS_RANGE, HISTORY_CENTS_RANGE),
+            HistoryScale::Hz => {
+                let (low, high) = segments.iter().flat_map(|range| range.clone()).fold(
+                    (f32::INFINITY, f32::NEG_INFINITY),
+                    |(low, high), index| {
+                        let point = &points[index];
+                        (
+                            low.min(point.frequency)
+                                .min(band(point.target_frequency, -tolerance)),
+                            high.max(point.frequency)
+                                .max(band(point.target_frequency, tolerance)),
+                        )
+                    },
+                );
+                if low > high {
+                    painter.text(
+                        plot.center(),
+                        egui::Align2::CENTER_CENTER,
+                        "No pitch yet",
+                        egui::FontId::proportional(11.0),
+                        label_color,
+                    );
+                    return;
+                }
+                let padding = ((high - low) * 0.1).max(2.0);
+                (low - padding, high + padding)
+            }
+        };
+
+        let to_x = |at: Instant| {
+            let age = now.duration_since(at).as_secs_f32() / span.as_secs_f32();
+            plot.right() - age * plot.width()
+        };
+        let to_y = |value: f32| {
+            let fraction = ((value - low) / (high - low)).clamp(0.0, 1.0);
+            plot.bottom() - fraction * plot.height()
+        };
+
+        let (top_label, bottom_label) = match scale {
+            HistoryScale::Cents => {
+                painter.rect_filled(
+                    egui::Rect::from_x_y_ranges(plot.x_range(), to_y(tolerance)..=to_y(-tolerance)),
+                    0.0,
+                    band_color,
+                );
+                painter.hline(
+                    plot.x_range(),
+                    to_y(0.0),
+                    egui::Stroke::new(1.0, self.theme.faint),
+                );
+                (format!("+{:.0}¢", high), format!("{:.0}¢", low))
+            }
+            HistoryScale::Hz => {
+                for range in &segments {
+                    for index in range.start + 1..range.end {
+                        let (previous, point) = (&points[index - 1], &points[index]);
+                        painter.rect_filled(
+                            egui::Rect::from_x_y_ranges(
+                                to_x(previous.at)..=to_x(point.at),
+                                to_y(band(point.target_frequency, tolerance))
+                                    ..=to_y(band(point.target_frequency, -tolerance)),
+                            ),
+                            0.0,
+                            band_color,
+                        );
+                    }
+                }
+                (format!("{:.0} Hz", high), format!("{:.0} Hz", low))
+            }
+        };
+
+        let font = egui::FontId::proportional(9.0);
+        painter.text(
+            egui::pos2(rect.left(), plot.top()),
+            egui::Align2::LEFT_TOP,
+            top_label,
+            font.clone(),
+            label_color,
+        );
+        painter.text(
+            egui::pos2(rect.left(), plot.bottom()),
+            egui::Align2::LEFT_BOTTOM,
+            bottom_label,
+            font,
+            label_color,
+        );
+
+        let stroke = egui::Stroke::new(1.5, self.theme.text);
+        for range in segments {
+            let line: Vec<egui::Pos2> = range
+                .map(|index| {
+                    let point = &points[index];
+                    let value = match scale {
+                        HistoryScale::Cents => point.cents,
+                        HistoryScale::Hz => point.frequency,
+                    };
+                    egui::pos2(to_x(point.at), to_y(value))
+                })
+                .collect();
+            match line.as_slice() {
+                [single] => painter.circle_filled(*single, 1.0, self.theme.text),
+                _ => painter.add(egui::Shape::line(line, stroke)),
+            };
+        }
+    }
+
+    fn draw_stats(&mut self, ui: &mut egui::Ui) {
+        let label_color = self.theme.secondary_text;
+        let small = |text: String| egui::RichText::new(text).size(11.0);
+
+        ui.horizontal(|ui| {
+            let tracked = self.stats.tracked_time().as_secs();
+            let summary = match (self.stats.in_tune_fraction(), self.stats.mean_abs_cents()) {
+                (Some(fraction), Some(mean_abs)) => format!(
+                    "In tune {:.0}% of {}:{:02} · avg ±{:.1}¢",
+                    fraction * 100.0,
+                    tracked / 60,
+                    tracked % 60,
+                    mean_abs
+                ),
+                _ => "No notes yet".to_string(),
+            };
+            let summary = match (self.stats.best_deviation(), self.stats.mean_deviation()) {
+                (Some(best), Some(mean)) => {
+                    format!("{} · σ best {:.1}¢, avg {:.1}¢", summary, best, mean)
+                }
+                _ => summary,
+            };
+            ui.label(small(summary).color(self.theme.text));
+
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                if ui
+                    .small_button("Reset")
+                    .on_hover_text("Start a new session, clearing the log")
+                    .clicked()
+                {
+                    self.stats.reset();
+                    self.detection_log.clear();
+                }
+            });
+        });
+
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut self.export_path_input)
+                    .hint_text("data folder")
+                    .desired_width(130.0),
+            )
+            .on_hover_text("File to export the log to");
+            let format = self.config.export_format;
+            egui::ComboBox::from_id_salt("export_format")
+                .selected_text(format.label())
+                .width(50.0)
+                .show_ui(ui, |ui| {
+                    for option in ExportFormat::ALL {
+                        if ui
+                            .selectable_value(
+                                &mut self.config.export_format,
+                                option,
+                                option.label(),
+                            )
+                            .changed()
+                        {
+                            self.config_dirty = true;
+                        }
+                    }
+                });
+            let exporting = self.export.is_some();
+            if ui
+                .add_enabled(
+                    !exporting && !self.detection_log.is_empty(),
+                    egui::Button::new("Export…").small(),
+                )
+                .on_hover_text(format!(
+                    "Export {} logged readings",
+                    self.detection_log.len()
+                ))
+                .clicked()
+            {
+                self.export_log();
+            }
+        });
+        if self.config.export_format == ExportFormat::Midi {
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_id_salt("midi_export_ppq")
+                    .selected_text(format!("{} PPQ", self.config.midi_export_ppq))
+                    .width(70.0)
+                    .show_ui(ui, |ui| {
+                        for ppq in PPQ_OPTIONS {
+                            if ui
+                                .selectable_value(
+                                    &mut self.config.midi_export_ppq,
+                                    ppq,
+                                    format!("{} PPQ", ppq),
+                                )
+                                .changed()
+                            {
+                                self.config_dirty = true;
+                            }
+                        }
+                    })
+                    .response
+                    .on_hover_text("Ticks per quarter note, at 120 BPM");
+                if ui
+                    .checkbox(&mut self.config.midi_export_bend, "Pitch bend")
+                    .on_hover_text(format!(
+                        "Bend each note to the measured deviation, over ±{} semitones",
+                        self.config.midi_bend_range
+                    ))
+                    .changed()
+                {
+                    self.config_dirty = true;
+                }
+            });
+        }
+
+        let naming = self.note_naming();
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            egui::CollapsingHeader::new(small("Intonation keyboard".to_string()))
+                .default_open(true)
+                .show(ui, |ui| self.draw_intonation_keyboard(ui));
+            egui::Grid::new("stats_notes")
+                .num_columns(4)
+                .spacing(egui::vec2(16.0, 2.0))
+                .show(ui, |ui| {
+                    for heading in ["Note", "Readings", "Mean", "Time"] {
+                        ui.label(small(heading.to_string()).color(label_color));
+                    }
+                    ui.end_row();
+
+                    for (key, note) in self.stats.notes() {
+                        ui.label(small(key.display_name(naming)));
+                        ui.label(small(note.count.to_string()));
+                        ui.label(
+                            small(format!("{:+.1}¢", note.mean_cents))
+                                .color(self.deviation_color(note.mean_cents)),
+                        );
+                        ui.label(small(format!("{:.1} s", note.time.as_secs_f32())));
+                        ui.end_row();
+                    }
+                });
+        });
+    }
+
+    /// A piano keyboard over the notes of the instrument with each key tinted
+    /// by the session's average deviation on it, blue for flat and red for
+    /// sharp, and grey where nothing was played; hovering a key gives the
+    /// figures.
+    fn draw_intonation_keyboard(&mut self, ui: &mut egui::Ui) {
+        let first_octave = self.keyboard_octave();
+        let naming = self.note_naming();
+        ui.horizontal(|ui| {
+            if ui
+                .small_button("◀")
+                .on_hover_text("An octave lower")
+                .clicked()
+            {
+                self.set_keyboard_octave(first_octave - 1);
+            }
+            ui.label(
+                egui::RichText::new(format!(
+                    "C{}–B{}",
+                    first_octave,
+                    first_octave + KEYBOARD_OCTAVES - 1
+                ))
+                .size(11.0)
+                .color(self.theme.secondary_text),
+            );
+            if ui
+                .small_button("▶")
+                .on_hover_text("An octave higher")
+                .clicked()
+            {
+                self.set_keyboard_octave(first_octave + 1);
+            }
+        });
+
+        let (rect, response) = ui.allocate_exact_size(
+            egui::vec2(ui.available_width(), KEYBOARD_HEIGHT),
+            egui::Sense::hover(),
+        );
+        let painter = ui.painter_at(rect);
+        let keys = keyboard_layout(first_octave, KEYBOARD_OCTAVES, rect);
+        let outline = egui::Stroke::new(1.0, self.theme.faint);
+        for &(midi, key_rect) in &keys {
+            let black = is_black_key(midi.rem_euclid(12));
+            let (neutral, unplayed) = if black {
+                (egui::Color32::from_gray(20), egui::Color32::from_gray(90))
+            } else {
+                (egui::Color32::from_gray(245), egui::Color32::from_gray(170))
+            };
+            let fill = match self.stats.note(NoteKey::from_midi(midi)) {
+                Some(note) => {
+                    self.palette
+                        .tendency(note.mean_cents, self.config.close_cents, neutral)
+                }
+                None => unplayed,
+            };
+            painter.rect_filled(key_rect, 1.0, fill);
+            painter.rect_stroke(key_rect, 1.0, outline, egui::StrokeKind::Inside);
+        }
+
+        let hovered = response.hover_pos().and_then(|pos| {
+            keys.iter()
+                .rev()
+                .find(|(_, key_rect)| key_rect.contains(pos))
+        });
+        if let Some(&(midi, _)) = hovered {
+            let key = NoteKey::from_midi(midi);
+            let text = match self.stats.note(key) {
+                Some(note) => format!(
+                    "{}: {:+.1}¢ on average over {} readings, {:.1} s",
+                    key.display_name(naming),
+                    note.mean_cents,
+                    note.count,
+                    note.time.as_secs_f32()
+                ),
+                None => format!("{}: not played", key.display_name(naming)),
+            };
+            response.on_hover_text_at_pointer(text);
+        }
+    }
+
+    fn draw_spectrum(&self, painter: &egui::Painter, area: egui::Rect) {
+        let spectrum = &self.spectrum;
+        let max_magnitude = spectrum.magnitudes.iter().fold(0.0f32, |a, &b| a.max(b));
+        if max_magnitude <= 0.0 || spectrum.bin_width <= 0.0 {
+            return;
+        }
+
+        let min_frequency = spectrum.min_frequency.max(1.0);
+        let max_frequency = spectrum.max_frequency();
+        let inner = area.shrink2(egui::vec2(10.0, 3.0));
+        let columns = (inner.width() / SPECTRUM_COLUMN_WIDTH) as usize;
+        let last_bin = spectrum.magnitudes.len() - 1;
+        let bar_color = Theme::translucent(self.theme.secondary_text, 60);
+
+        for column in 0..columns {
+            let start = self.spectrum_axis_frequency(
+                column as f32 / columns as f32,
+                min_frequency,
+                max_frequency,
+            );
+            let end = self.spectrum_axis_frequency(
+                (column + 1) as f32 / columns as f32,
+                min_frequency,
+                max_frequency,
+            );
+
+            let first =
+                (((start - spectrum.min_frequency) / spectrum.bin_width) as usize).min(last_bin);
+            let last = (((end - spectrum.min_frequency) / spectrum.bin_width).ceil() as usize)
+                .clamp(first, last_bin);
+            let magnitude = spectrum.magnitudes[first..=last]
+                .iter()
+                .fold(0.0f32, |a, &b| a.max(b));
+
+            let level = ((20.0 * (magnitude / max_magnitude).log10() + SPECTRUM_FLOOR_DB)
+                / SPECTRUM_FLOOR_DB)
+                .clamp(0.0, 1.0);
+            if level <= 0.0 {
+                continue;
+            }
+
+            let x = inner.left() + (column as f32 + 0.5) * SPECTRUM_COLUMN_WIDTH;
+            painter.line_segment(
+                [
+                    egui::pos2(x, inner.bottom()),
+                    egui::pos2(x, inner.bottom() - level * inner.height()),
+                ],
+                egui::Stroke::new(SPECTRUM_COLUMN_WIDTH, bar_color),
+            );
+        }
+
+        if let Some(peak) = spectrum.peak_frequency {
+            if (min_frequency..=max_frequency).contains(&peak) {
+                let x = inner.left()
+                    + self.spectrum_axis_position(peak, min_frequency, max_frequency)
+                        * inner.width();
+                painter.line_segment(
+                    [egui::pos2(x, inner.top()), egui::pos2(x, inner.bottom())],
+                    egui::Stroke::new(1.5, Theme::translucent(self.theme.text, 110)),
+                );
+            }
+        }
+    }
+
+    fn spectrum_axis_frequency(
+        &self,
+        position: f32,
+        min_frequency: f32,
+        max_frequency: f32,
+    ) -> f32 {
+        if self.config.spectrum_log_scale {
+            min_frequency * (max_frequency / min_frequency).powf(position)
+        } else {
+            min_frequency + (max_frequency - min_frequency) * position
+        }
+    }
+
+    fn spectrum_axis_position(
+        &self,
+        frequency: f32,
+        min_frequency: f32,
+        max_frequency: f32,
+    ) -> f32 {
+        if self.config.spectrum_log_scale {
+            (frequency / min_frequency).ln() / (max_frequency / min_frequency).ln()
+        } else {
+            (frequency - min_frequency) / (max_frequency - min_frequency)
+        }
+    }
+
+    fn draw_tuner_needle(&self, ui: &mut egui::Ui, cents_off: f32, center: egui::Pos2, scale: f32) {
+        match self.config.needle_style {
+            NeedleStyle::Bar => self.draw_bar_needle(ui.painter(), cents_off, center, scale),
+            NeedleStyle::Gauge => self.draw_gauge_needle(ui.painter(), cents_off, center, scale),
+        }
+    }
+
+    fn draw_bar_needle(
+        &self,
+        painter: &egui::Painter,
+        cents_off: f32,
+        center: egui::Pos2,
+        scale: f32,
+    ) {
+        let needle_area = egui::Rect::from_center_size(
+            center + egui::vec2(0.0, 5.0 * scale),
+            egui::vec2(220.0, 20.0) * scale,
+        );
+
+        painter.rect_filled(needle_area, 10.0 * scale, self.theme.track);
+
+        if self.config.show_spectrum {
+            self.draw_spectrum(painter, needle_area);
+        }
+
+        let center_x = needle_area.center().x;
+        painter.line_segment(
+            [
+                egui::pos2(center_x, needle_area.top() + 3.0 * scale),
+                egui::pos2(center_x, needle_area.bottom() - 3.0 * scale),
+            ],
+            egui::Stroke::new(1.5 * scale, self.theme.faint),
+        );
+
+        let range = self.needle_scale.range;
+        let normalized_cents = (cents_off / range).clamp(-1.0, 1.0);
+        let half_travel = needle_area.width() / 2.0 - 10.0 * scale;
+        let needle_x = center_x + normalized_cents * half_travel;
+
+        if cents_off != 0.0 || self.reading.note.is_some() {
+            self.draw_needle_dot(
+                painter,
+                egui::pos2(needle_x, needle_area.center().y),
+                6.0 * scale,
+                cents_off,
+                range,
+            );
+        }
+
+        for mark_cents in needle_ticks(range) {
+            for side in [-1.0, 1.0] {
+                let mark_x = center_x + side * (mark_cents / range) * half_travel;
+                painter.line_segment(
+                    [
+                        egui::pos2(mark_x, needle_area.center().y - 3.0 * scale),
+                        egui::pos2(mark_x, needle_area.center().y + 3.0 * scale),
+                    ],
+                    egui::Stroke::new(scale, self.theme.faint),
+                );
+            }
+        }
+    }
+
+    /// A needle swinging across an arc ticked every 10 cents, taking the
+    /// bar's place between the note and the deviation.
+    fn draw_gauge_needle(
+        &self,
+        painter: &egui::Painter,
+        cents_off: f32,
+        center: egui::Pos2,
+        scale: f32,
+    ) {
+        let radius = GAUGE_RADIUS * scale;
+        let pivot = center + egui::vec2(0.0, GAUGE_RADIUS - 8.0) * scale;
+        let point =
+            |angle: f32, distance: f32| pivot + egui::vec2(angle.sin(), -angle.cos()) * distance;
+
+        if self.config.show_spectrum {
+            let corner = point(GAUGE_HALF_ANGLE, radius);
+            let area = egui::Rect::from_min_max(
+                egui::pos2(pivot.x - (corner.x - pivot.x), pivot.y - radius),
+                egui::pos2(corner.x, corner.y + 8.0 * scale),
+            );
+            self.draw_spectrum(painter, area);
+        }
+
+        const ARC_SEGMENTS: usize = 48;
+        let arc = (0..=ARC_SEGMENTS)
+            .map(|segment| {
+                let angle = (segment as f32 / ARC_SEGMENTS as f32 * 2.0 - 1.0) * GAUGE_HALF_ANGLE;
+                point(angle, radius)
+            })
+            .collect();
+        painter.add(egui::Shape::line(
+            arc,
+            egui::Stroke::new(1.5 * scale, self.theme.faint),
+        ));
+
+        let range = self.needle_scale.range;
+        let long_ticks = [-range, 0.0, range].map(|cents| (cents, 7.0));
+        let short_ticks = needle_ticks(range).flat_map(|cents| [(-cents, 4.0), (cents, 4.0)]);
+        for (cents, length) in long_ticks.into_iter().chain(short_ticks) {
+            let angle = gauge_angle(cents, range);
+            painter.line_segment(
+                [point(angle, radius), point(angle, radius - length * scale)],
+                egui::Stroke::new(scale, self.theme.faint),
+            );
+        }
+        let mut numerals = vec![-range, 0.0, range];
+        if (range / 2.0).fract() == 0.0 {
+            numerals.extend([-range / 2.0, range / 2.0]);
+        }
+        for numeral in numerals {
+            painter.text(
+                point(gauge_angle(numeral, range), radius - 14.0 * scale),
+                egui::Align2::CENTER_CENTER,
+                format!("{:.0}", numeral.abs()),
+                egui::FontId::proportional(8.0 * scale),
+                self.theme.secondary_text,
+            );
+        }
+
+        let active = cents_off != 0.0 || self.reading.note.is_some();
+        let color = if active {
+            self.deviation_color(cents_off)
+        } else {
+            self.theme.faint
+        };
+        let angle = gauge_angle(cents_off, range);
+        let tip = point(angle, radius + 2.0 * scale);
+        painter.line_segment(
+            [point(angle, radius - 24.0 * scale), tip],
+            egui::Stroke::new(2.0 * scale, color),
+        );
+        if active {
+            self.draw_needle_dot(painter, tip, 3.5 * scale, cents_off, range);
+        }
+    }
+}
+
+impl TunerApp {
+    /// Runs one frame: polls the workers and device monitor, then draws the
+    /// window. Nothing here replaces the context's style; that only happens
+    /// when the theme setting changes.
+    fn show(&mut self, ctx: &egui::Context) {
+        self.close_to_tray(ctx);
+        self.poll_tray(ctx);
+        self.poll_raise_requests(ctx);
+        self.poll_global_shortcut(ctx);
+        self.suspend_when_hidden(ctx, Instant::now());
+        self.refresh_audio_devices();
+        self.follow_system_default(Instant::now());
+        self.poll_stream_errors();
+        self.check_stream_watchdog(Instant::now());
+        self.poll_calibration(Instant::now());
+        self.poll_recorder();
+        // Holding only freezes the display; everything else stays live.
+        let reading = self.pitch_worker.reading();
+        self.detection_rate
+            .update(reading.detections, Instant::now());
+        self.history.record(&reading, Instant::now());
+        self.follow_octave_shift(&reading);
+        self.stats
+            .record(&reading, Instant::now(), self.config.in_tune_cents);
+        self.detection_log.record(&reading, SystemTime::now());
+        self.poll_export();
+        self.send_midi(&reading);
+        if let Some(guided) = &mut self.guided {
+            let played = reading
+                .note
+                .as_ref()
+                .and(reading.string_number)
+                .map(|string_number| (string_number, reading.smoothed_cents));
+            guided.update(played, self.config.in_tune_cents, Instant::now());
+        }
+        if let Some(challenge) = &mut self.challenge {
+            let played = reading
+                .note
+                .as_ref()
+                .map(|note| (note.midi, reading.smoothed_cents));
+            let result = challenge.update(played, Instant::now());
+            if result.is_some() && challenge.finished() {
+                challenge.new_best =
+                    record_high_score(&mut self.config.challenge_high_scores, challenge.score());
+                self.save_config();
+            }
+        }
+        let naming = self.note_naming();
+        if let Some(osc) = &mut self.osc {
+            osc.publish(&reading, naming, Instant::now());
+        }
+        if let Some(websocket) = &mut self.websocket {
+            websocket.publish(&reading, naming, self.config.in_tune_cents, Instant::now());
+        }
+        self.cue_in_tune(&reading, Instant::now());
+        let note_active = reading.note.is_some();
+        if self.hold.is_none() {
+            self.reading = reading;
+        }
+        let dt = ctx.input(|input| input.stable_dt);
+        let needle_moving = self.move_needle(dt);
+        let needle_zooming = self.zoom_needle(dt, Instant::now());
+        if self.config.show_spectrum {
+            self.pitch_worker.copy_spectrum(&mut self.spectrum);
+        }
+        if self.show_strum {
+            self.pitch_worker.copy_strum(&mut self.strum);
+        }
+        if self.config.identify_intervals && self.hold.is_none() {
+            self.pitch_worker.copy_peaks(&mut self.peaks);
+        }
+        if self.config.piano_mode && self.hold.is_none() {
+            self.pitch_worker.copy_partials(&mut self.partials);
+        }
+        if let Ok(audio_data) = self.audio_data.lock() {
+            self.level_meter.update(audio_data.level, Instant::now());
+        }
+        if let Some(player) = self
+            .tone_player
+            .as_ref()
+            .filter(|player| player.is_playing())
+        {
+            player.set_frequency(self.tone_frequency());
+        }
+        if let Some(player) = self
+            .drone_player
+            .as_ref()
+            .filter(|player| player.is_playing())
+        {
+            player.set_frequency(self.drone_frequency());
+            player.set_waveform(self.config.drone_waveform);
+            player.set_volume(self.config.drone_volume);
+        }
+        self.follow_metronome(Instant::now());
+
+        self.pitch_worker.wake_on_note(ctx);
+        ctx.request_repaint_after(self.repaint_interval(
+            note_active || needle_moving || needle_zooming || self.metronome_running(),
+        ));
+        if self.config.show_diagnostics {
+            ctx.request_repaint_after(DIAGNOSTICS_REFRESH);
+        }
+        self.apply_theme(ctx);
+        if !ctx.wants_keyboard_input()
+            && ctx.input_mut(|input| input.consume_key(egui::Modifiers::NONE, egui::Key::D))
+        {
+            self.config.show_diagnostics = !self.config.show_diagnostics;
+            self.config_dirty = true;
+        }
+        if !ctx.wants_keyboard_input()
+            && ctx.input_mut(|input| input.consume_key(egui::Modifiers::NONE, egui::Key::M))
+        {
+            self.set_display_mode(ctx, self.config.display_mode.next());
+        }
+        if !ctx.wants_keyboard_input()
+            && ctx.input_mut(|input| input.consume_key(egui::Modifiers::NONE, egui::Key::Space))
+        {
+            self.toggle_hold(Instant::now());
+        }
+        if !ctx.wants_keyboard_input()
+            && ctx.input_mut(|input| input.consume_key(egui::Modifiers::NONE, egui::Key::PageUp))
+        {
+            self.shift_octave(1);
+        }
+        if !ctx.wants_keyboard_input()
+            && ctx.input_mut(|input| input.consume_key(egui::Modifiers::NONE, egui::Key::PageDown))
+        {
+            self.shift_octave(-1);
+        }
+        if self.target_note.is_some()
+            && ctx.input_mut(|input| input.consume_key(egui::Modifiers::NONE, egui::Key::Escape))
+        {
+            self.set_target_note(None);
+        }
+        if !ctx.wants_keyboard_input()
+            && ctx.input_mut(|input| input.consume_key(egui::Modifiers::NONE, egui::Key::O))
+        {
+            self.set_overlay(ctx, !self.overlay);
+        }
+        self.track_window_size(ctx, Instant::now());
+        self.track_window_position(ctx);
+        if self.overlay {
+            self.draw_overlay(ctx);
+            return;
+        }
+
+        let standard = self.config.display_mode == DisplayMode::Standard;
+        if standard && self.show_settings {
+            egui::TopBottomPanel::bottom("settings_panel")
+                .exact_height(SETTINGS_PANEL_HEIGHT)
+                .show_separator_line(false)
+                .frame(egui::Frame {
+                    fill: self.theme.panel_fill,
+                    inner_margin: egui::Margin::symmetric(16, 8),
+                    ..Default::default()
+                })
+                .show(ctx, |ui| self.draw_settings_panel(ui));
+        }
+
+        if standard && self.show_history {
+            egui::TopBottomPanel::bottom("history_panel")
+                .exact_height(HISTORY_PANEL_HEIGHT)
+                .show_separator_line(false)
+                .frame(egui::Frame {
+                    fill: self.theme.panel_fill,
+                    inner_margin: egui::Margin::symmetric(12, 6),
+                    ..Default::default()
+                })
+                .show(ctx, |ui| self.draw_history(ui));
+        }
+
+        if standard && self.show_stats {
+            egui::TopBottomPanel::bottom("stats_panel")
+                .exact_height(STATS_PANEL_HEIGHT)
+                .show_separator_line(false)
+                .frame(egui::Frame {
+                    fill: self.theme.panel_fill,
+                    inner_margin: egui::Margin::symmetric(12, 6),
+                    ..Default::default()
+                })
+                .show(ctx, |ui| self.draw_stats(ui));
+        }
+
+        if standard && self.show_strum {
+            egui::TopBottomPanel::bottom("strum_panel")
+                .exact_height(STRUM_PANEL_HEIGHT)
+                .show_separator_line(false)
+                .frame(egui::Frame {
+                    fill: self.theme.panel_fill,
+                    inner_margin: egui::Margin::symmetric(12, 6),
+                    ..Default::default()
+                })
+                .show(ctx, |ui| self.draw_strum(ui));
+        }
+
+        if self.config.display_mode != DisplayMode::Mini {
+            self.draw_status_banner(ctx);
+        }
+        if self.config.show_diagnostics {
+            self.draw_diagnostics(ctx, Instant::now());
+        }
+
+        if !standard {
+            let mini = self.config.display_mode == DisplayMode::Mini;
+            egui::CentralPanel::default()
+                .frame(egui::Frame {
+                    fill: self.theme.panel_fill,
+                    corner_radius: 8.0.into(),
+                    inner_margin: if mini {
+                        egui::Margin::symmetric(8, 6)
+                    } else {
+                        egui::Margin::same(16)
+                    },
+                    ..Default::default()
+                })
+                .show(ctx, |ui| {
+                    Self::drag_window_from_background(ui);
+                    if mini {
+                        self.draw_mini_display(ui);
+                    } else {
+                        self.draw_performance_display(ui);
+                    }
+                });
+            return;
+        }
+
+        egui::CentralPanel::default()
+            .frame(egui::Frame {
+                fill: self.theme.panel_fill,
+                corner_radius: 8.0.into(),
+                shadow: eframe::epaint::Shadow::NONE,
+                outer_margin: egui::Margin::ZERO,
+                inner_margin: egui::Margin::symmetric(0, 16),
+                ..Default::default()
+            })
+            .show(ctx, |ui| {
+                Self::drag_window_from_background(ui);
+                let panel_rect = ui.max_rect();
+                let settings_button_rect = egui::Rect::from_min_size(
+                    egui::pos2(panel_rect.right() - 26.0, panel_rect.top() - 8.0),
+                    egui::vec2(20.0, 20.0),
+                );
+                let settings_button = ui.put(
+                    settings_button_rect,
+                    egui::Button::new(
+                        egui::RichText::new("⚙")
+                            .size(14.0)
+                            .color(self.theme.secondary_text),
+                    )
+                    .frame(false),
+                );
+                if settings_button.on_hover_text("Settings").clicked() {
+                    self.toggle_settings(ctx);
+                }
+
+                let history_button_rect = egui::Rect::from_min_size(
+                    egui::pos2(panel_rect.right() - 46.0, panel_rect.top() - 8.0),
+                    egui::vec2(20.0, 20.0),
+                );
+                let history_color = if self.show_history {
+                    self.theme.text
+                } else {
+                    self.theme.secondary_text
+                };
+                let history_button = ui.put(
+                    history_button_rect,
+                    egui::Button::new(egui::RichText::new("📈").size(13.0).color(history_color))
+                        .frame(false),
+                );
+                if history_button.on_hover_text("Pitch history").clicked() {
+                    self.toggle_history(ctx);
+                }
+
+                let stats_button_rect = egui::Rect::from_min_size(
+                    egui::pos2(panel_rect.right() - 66.0, panel_rect.top() - 8.0),
+                    egui::vec2(20.0, 20.0),
+                );
+                let stats_color = if self.show_stats {
+                    self.theme.text
+                } else {
+                    self.theme.secondary_text
+                };
+                let stats_button = ui.put(
+                    stats_button_rect,
+                    egui::Button::new(egui::RichText::new("📊").size(13.0).color(stats_color))
+                        .frame(false),
+                );
+                if stats_button.on_hover_text("Session statistics").clicked() {
+                    self.toggle_stats(ctx);
+                }
+
+                let pin_button_rect = egui::Rect::from_min_size(
+                    egui::pos2(panel_rect.right() - 86.0, panel_rect.top() - 8.0),
+                    egui::vec2(20.0, 20.0),
+                );
+                let (pin_color, pin_hover) = if self.always_on_top {
+                    (self.theme.text, "Stop keeping the window on top")
+                } else {
+                    (self.theme.faint, "Keep the window above other windows")
+                };
+                let pin_button = ui.put(
+                    pin_button_rect,
+                    egui::Button::new(egui::RichText::new("📌").size(12.0).color(pin_color))
+                        .frame(false),
+                );
+                if pin_button.on_hover_text(pin_hover).clicked() {
+                    self.toggle_always_on_top(ctx);
+                }
+
+                let preset_rect = egui::Rect::from_min_size(
+                    egui::pos2(panel_rect.right() - 166.0, panel_rect.top() - 8.0),
+                    egui::vec2(80.0, 20.0),
+                );
+                ui.scope_builder(egui::UiBuilder::new().max_rect(preset_rect), |ui| {
+                    self.draw_preset_selector(ui);
+                });
+
+                let tone_rect = egui::Rect::from_min_size(
+                    egui::pos2(panel_rect.left() + 8.0, panel_rect.top() - 8.0),
+                    egui::vec2(98.0, 20.0),
+                );
+                ui.scope_builder(egui::UiBuilder::new().max_rect(tone_rect), |ui| {
+                    self.draw_tone_controls(ui);
+                });
+
+                let record_rect = egui::Rect::from_min_size(
+                    egui::pos2(panel_rect.left() + 106.0, panel_rect.top() - 8.0),
+                    egui::vec2(48.0, 20.0),
+                );
+                ui.scope_builder(egui::UiBuilder::new().max_rect(record_rect), |ui| {
+                    self.draw_record_control(ui);
+                });
+
+                ui.add_space(8.0);
+
+                ui.with_layout(egui::Layout::top_down(egui::Align::Center), |ui| {
+                    ui.add_space(10.0);
+                    self.draw_tuner_display(ui);
+                    ui.add_space(8.0);
+
+                    ui.add_space(4.0);
+                    ui.vertical_centered(|ui| {
+                        ui.add_space(20.0);
+
+                        let available_rect = ui.available_rect_before_wrap();
+                        let controls_rect = egui::Rect::from_center_size(
+                            available_rect.center(),
+                            egui::vec2(290.0, 25.0),
+                        );
+
+                        let meter_rect = egui::Rect::from_center_size(
+                            egui::pos2(controls_rect.center().x, controls_rect.bottom() + 6.0),
+                            egui::vec2(290.0, 3.0),
+                        );
+                        self.draw_level_meter(ui.painter(), meter_rect);
+
+                        ui.scope_builder(egui::UiBuilder::new().max_rect(controls_rect), |ui| {
+                            ui.horizontal(|ui| {
+                                let device_combo = egui::ComboBox::from_id_salt("device_selector")
+                                    .selected_text(device_label(&self.current_device_name))
+                                    .width(130.0)
+                                    .height(25.0)
+                                    .show_ui(ui, |ui| {
+                                        let mut follow = self.config.follow_default_device;
                                         if ui
-                                            .selectable_value(
-                                                &mut self.current_device_name,
-                                                device_name.clone(),
-                                                &device_name,
+                                            .checkbox(&mut follow, "Follow system default")
+                                            .on_hover_text(
+                                                "Switch to the default input whenever it changes",
                                             )
-                                            .clicked()
+                                            .changed()
+                                        {
+                                            self.set_follow_default(follow);
+                                        }
+                                        ui.separator();
+                                        let mut clicked = None;
+                                        for (index, device_name) in
+                                            self.available_devices.iter().enumerate()
                                         {
-                                            self.switch_device(device_name, device);
+                                            let is_current =
+                                                *device_name == self.current_device_name;
+                                            if ui
+                                                .selectable_label(
+                                                    is_current,
+                                                    device_label(device_name),
+                                                )
+                                                .clicked()
+                                                && !is_current
+                                            {
+                                                clicked = Some(index);
+                                            }
                                         }
+                                        if let Some(index) = clicked {
+                                            let device_name = self.available_devices[index].clone();
+                                            self.select_device(device_name);
+                                        }
+                                    });
+
+                                let preferred = self
+                                    .config
+                                    .preferred_device
+                                    .as_ref()
+                                    .filter(|_| !self.config.follow_default_device);
+                                if let Some(preferred) = preferred {
+                                    if preferred != &self.current_device_name {
+                                        device_combo.response.on_hover_text(format!(
+                                            "Preferred device '{}' is not connected",
+                                            preferred
+                                        ));
                                     }
-                                });
+                                }
+
+                                self.draw_channel_selector(ui);
+
+                                let a4_response = ui
+                                    .add(
+                                        egui::DragValue::new(&mut self.config.a4_reference)
+                                            .range(400.0..=480.0)
+                                            .speed(0.1)
+                                            .fixed_decimals(1)
+                                            .prefix("A4 ")
+                                            .suffix(" Hz"),
+                                    )
+                                    .on_hover_text("Reference pitch for A4");
+                                if a4_response.changed() {
+                                    self.apply_config();
+                                }
+                            });
                         });
                     });
                 });
             });
+
+        self.draw_preset_editor(ctx);
+        self.draw_analysis(ctx);
+        self.draw_self_test(ctx);
+        self.draw_in_tune_flash(ctx, Instant::now());
+    }
+}
+
+impl eframe::App for TunerApp {
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        self.device_monitor.stop();
+        self.stop_recording();
+        self.save_config();
+    }
+
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.show(ctx);
+    }
+
+    fn clear_color(&self, _visuals: &egui::Visuals) -> [f32; 4] {
+        if self.overlay {
+            return self.overlay_fill().to_normalized_gamma_f32();
+        }
+        // eframe's default, showing only at the rounded corners.
+        egui::Color32::from_rgba_unmultiplied(12, 12, 12, 180).to_normalized_gamma_f32()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pitch::{frequency_to_note, test_signals};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_worker_converges_on_synthetic_tone() {
+        let sample_rate = 48000.0;
+        let audio_data = Arc::new(Mutex::new(AudioData::new()));
+        if let Ok(mut audio_data) = audio_data.lock() {
+            audio_data.sample_rate = sample_rate;
+        }
+
+        let mut worker = PitchWorker::start(audio_data.clone(), AnalysisSettings::default());
+
+        let chunk_size = 1024;
+        let mut phase = 0.0f32;
+        let mut reading = PitchReading::default();
+        let deadline = Instant::now() + Duration::from_secs(5);
+
+        while Instant::now() < deadline {
+            let chunk: Vec<f32> = (0..chunk_size)
+                .map(|_| {
+                    phase += 2.0 * std::f32::consts::PI * 329.63 / sample_rate;
+                    phase.sin() * 0.5
+                })
+                .collect();
+            if let Ok(mut audio_data) = audio_data.lock() {
+                audio_data.push_samples(&chunk);
+            }
+
+            std::thread::sleep(Duration::from_millis(10));
+
+            reading = worker.reading();
+            if reading.note.is_some() {
+                break;
+            }
+        }
+
+        worker.stop();
+
+        let note = reading.note.expect("worker never locked onto the tone");
+        assert_eq!(note.to_string(), "E4");
+        assert!(
+            note.cents_off.abs() < 5.0,
+            "expected E4 within 5 cents, got {} cents",
+            note.cents_off
+        );
+    }
+
+    /// Writes a Karplus-Strong plucked string as a stereo float WAV file.
+    fn write_plucked_string(path: &std::path::Path, frequency: f32, sample_rate: u32) {
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let mut writer = hound::WavWriter::create(path, spec).unwrap();
+
+        let period = (sample_rate as f32 / frequency - 0.5).round() as usize;
+        let mut seed = 7u32;
+        let mut delay: VecDeque<f32> = (0..period)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (seed >> 8) as f32 / (1 << 24) as f32 - 0.5
+            })
+            .collect();
+
+        for _ in 0..sample_rate * 2 {
+            let sample = delay.pop_front().unwrap();
+            let next = *delay.front().unwrap();
+            delay.push_back(0.498 * (sample + next));
+            writer.write_sample(sample).unwrap();
+            writer.write_sample(sample * 0.8).unwrap();
+        }
+        writer.finalize().unwrap();
+    }
+
+    #[test]
+    fn test_file_source_end_to_end_detects_plucked_string() {
+        let path = std::env::temp_dir().join(format!(
+            "chroma-tuner-plucked-a2-{}.wav",
+            std::process::id()
+        ));
+        write_plucked_string(&path, 110.0, 48000);
+
+        let audio_data = Arc::new(Mutex::new(AudioData::new()));
+        let mut source = FileSource::open(&path, audio_data.clone(), 4.0).unwrap();
+        let mut worker = PitchWorker::start(audio_data.clone(), AnalysisSettings::default());
+
+        let deadline = Instant::now() + Duration::from_secs(3);
+        let mut reading = PitchReading::default();
+        while Instant::now() < deadline && reading.note.is_none() {
+            std::thread::sleep(Duration::from_millis(10));
+            reading = worker.reading();
+        }
+        worker.stop();
+        source.stop();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(audio_data.lock().unwrap().sample_rate, 48000.0);
+        let note = reading.note.expect("never locked onto the plucked string");
+        assert_eq!(note.to_string(), "A2");
+        assert!(note.cents_off.abs() < 10.0, "{} cents", note.cents_off);
+    }
+
+    /// Runs `app` frame by frame for `duration`, listing each note it shows.
+    fn notes_shown(app: &mut TunerApp, duration: Duration) -> Vec<String> {
+        let ctx = egui::Context::default();
+        let deadline = Instant::now() + duration;
+        let mut shown: Vec<String> = Vec::new();
+        while Instant::now() < deadline {
+            let _ = ctx.run(egui::RawInput::default(), |ctx| app.show(ctx));
+            if let Some(note) = &app.reading.note {
+                let name = note.to_string();
+                if shown.last() != Some(&name) {
+                    shown.push(name);
+                }
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        shown
+    }
+
+    #[test]
+    fn test_scripted_notes_are_shown_in_order() {
+        let sample_rate = 48000.0;
+        let audio_data = Arc::new(Mutex::new(AudioData::new()));
+        let mut app = TunerApp::new(audio_data.clone(), TunerConfig::default());
+        let script = test_signals::melody(
+            &[
+                (Some(82.41), 1.0),
+                (None, 0.4),
+                (Some(110.0), 1.0),
+                (None, 0.4),
+                (Some(146.83), 1.0),
+                (None, 0.4),
+            ],
+            sample_rate,
+        );
+        app.set_file_source(FileSource::from_samples(
+            "Script",
+            script,
+            sample_rate,
+            audio_data,
+            2.0,
+        ));
+
+        let shown = notes_shown(&mut app, Duration::from_millis(2300));
+        assert_eq!(shown, ["E2", "A2", "D3"]);
+    }
+
+    #[test]
+    fn test_sweep_is_tracked_upwards() {
+        let sample_rate = 48000.0;
+        let audio_data = Arc::new(Mutex::new(AudioData::new()));
+        // E2 up to G#2 slowly enough that the deviation stays steady.
+        let sweep = test_signals::sweep(82.41, 103.83, sample_rate, 4 * 48000);
+        let mut source =
+            FileSource::from_samples("Sweep", sweep, sample_rate, audio_data.clone(), 1.0);
+        let mut worker = PitchWorker::start(audio_data, AnalysisSettings::default());
+
+        let deadline = Instant::now() + Duration::from_millis(4100);
+        let mut notes: Vec<i32> = Vec::new();
+        while Instant::now() < deadline {
+            if let Some(note) = worker.reading().note {
+                if notes.last() != Some(&note.midi) {
+                    notes.push(note.midi);
+                }
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        worker.stop();
+        source.stop();
+
+        assert!(
+            notes.windows(2).all(|pair| pair[0] < pair[1]),
+            "{:?}",
+            notes
+        );
+        assert!(notes.len() >= 4, "{:?}", notes);
+    }
+
+    #[test]
+    fn test_hop_sets_detections_per_second() {
+        let sample_rate = crate::audio::ANALYSIS_SAMPLE_RATE as f32;
+        let signal = test_signals::sine(220.0, sample_rate, sample_rate as usize);
+        for hop in HOP_SIZES {
+            let audio_data = Mutex::new(AudioData::new());
+            let settings = AnalysisSettings {
+                hop_size: hop,
+                ..AnalysisSettings::default()
+            };
+            let mut analyzer =
+                PitchAnalyzer::new(settings.fft_size, sample_rate, settings.window_kind);
+            let mut detections = 0;
+            for block in signal.chunks(128) {
+                audio_data.lock().unwrap().push_samples(block);
+                if analyzer.update(&audio_data, &settings).is_some() {
+                    detections += 1;
+                }
+            }
+            let note = analyzer.reading().note.map(|note| note.to_string());
+            assert_eq!(note.as_deref(), Some("A3"), "hop {}", hop);
+
+            // Half a window has to fill before the first analysis.
+            let expected = (sample_rate - (settings.fft_size / 2) as f32) / hop as f32;
+            assert!(
+                (detections as f32 - expected).abs() <= 1.5,
+                "hop {}: {} detections, expected {}",
+                hop,
+                detections,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_saved_config_is_applied_on_new() {
+        let path = std::env::temp_dir()
+            .join(format!("chroma-tuner-app-test-{}", std::process::id()))
+            .join("config.toml");
+        let config = TunerConfig {
+            a4_reference: 442.0,
+            smoothing_ms: 120,
+            pitch_window_ms: 300,
+            ..TunerConfig::default()
+        };
+        config.save_to(&path).unwrap();
+
+        let loaded = TunerConfig::load_from(&path).unwrap();
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+
+        let app = TunerApp::new(Arc::new(Mutex::new(AudioData::new())), loaded);
+        assert_eq!(app.config, config);
+
+        let settings = app.pitch_worker.settings.lock().unwrap().clone();
+        assert_eq!(settings.a4_reference, 442.0);
+        assert_eq!(settings.smoothing_time, Duration::from_millis(120));
+        assert_eq!(settings.pitch_window, Duration::from_millis(300));
+    }
+
+    #[test]
+    fn test_level_meter_hints_after_sustained_quiet_and_repeated_clips() {
+        let start = Instant::now();
+        let mut meter = LevelMeter::default();
+        let quiet = InputLevel {
+            rms: QUIET_RMS_THRESHOLD / 2.0,
+            peak: QUIET_RMS_THRESHOLD,
+            clip_count: 0,
+        };
+
+        meter.update(quiet, start);
+        assert!(!meter.too_quiet(start + Duration::from_millis(500)));
+        meter.update(quiet, start + QUIET_HINT_DELAY);
+        assert!(meter.too_quiet(start + QUIET_HINT_DELAY));
+
+        let loud = InputLevel {
+            rms: 0.3,
+            peak: 1.0,
+            clip_count: 1,
+        };
+        let now = start + QUIET_HINT_DELAY;
+        meter.update(loud, now);
+        assert!(!meter.too_quiet(now));
+        assert!(!meter.clipping(now), "a single clip is not flagged");
+
+        let later = now + CLIP_WINDOW * 2;
+        meter.update(
+            InputLevel {
+                clip_count: 2,
+                ..loud
+            },
+            later,
+        );
+        assert!(!meter.clipping(later), "stale clips fall out of the window");
+
+        for (offset, clip_count) in [(100, 3), (200, 4)] {
+            meter.update(
+                InputLevel { clip_count, ..loud },
+                later + Duration::from_millis(offset),
+            );
+        }
+        let clipped_at = later + Duration::from_millis(200);
+        assert!(meter.clipping(clipped_at));
+        assert!(!meter.clipping(clipped_at + CLIP_HOLD));
+    }
+
+    #[test]
+    fn test_noise_calibration_sets_gate_above_ambient_peak() {
+        let start = Instant::now();
+        let mut calibration = NoiseCalibration::new(start);
+        for (magnitude, rms) in [(0.02, 0.004), (0.05, 0.003), (0.0, 0.002)] {
+            calibration.observe(magnitude, rms);
+        }
+
+        assert!(!calibration.finished(start + CALIBRATION_DURATION / 2));
+        assert!(calibration.finished(start + CALIBRATION_DURATION));
+        assert_eq!(calibration.peak_rms, 0.004);
+        assert!((calibration.threshold() - 0.05 * CALIBRATION_MARGIN).abs() < 1e-6);
+
+        let silent = NoiseCalibration::new(start);
+        assert_eq!(silent.threshold(), MIN_MAGNITUDE_THRESHOLD);
+    }
+
+    #[test]
+    fn test_worker_calibrates_against_ambient_input() {
+        let sample_rate = 48000.0;
+        let audio_data = Arc::new(Mutex::new(AudioData::new()));
+        audio_data.lock().unwrap().sample_rate = sample_rate;
+        let mut worker = PitchWorker::start(audio_data.clone(), AnalysisSettings::default());
+
+        // Start as if the calibration began long enough ago to be finished on
+        // the next poll, then feed a faint hum for a moment.
+        worker.start_calibration(Instant::now() - CALIBRATION_DURATION);
+        assert!(worker.calibrating());
+
+        let mut phase = 0.0f32;
+        for _ in 0..20 {
+            let chunk: Vec<f32> = (0..1024)
+                .map(|_| {
+                    phase += 2.0 * std::f32::consts::PI * 220.0 / sample_rate;
+                    phase.sin() * 0.001
+                })
+                .collect();
+            audio_data.lock().unwrap().push_samples(&chunk);
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let calibration = worker
+            .take_finished_calibration(Instant::now())
+            .expect("calibration should be finished");
+        worker.stop();
+
+        assert!(!worker.calibrating());
+        assert!(calibration.peak_magnitude > 0.0);
+        assert!(calibration.peak_rms > 0.0005);
+        assert!(calibration.threshold() > calibration.peak_magnitude);
+    }
+
+    #[test]
+    fn test_calibrated_threshold_follows_device() {
+        let mut app = TunerApp::new(
+            Arc::new(Mutex::new(AudioData::new())),
+            TunerConfig::default(),
+        );
+        app.current_device_name = "MacBook Pro Microphone".to_string();
+
+        let mut calibration = NoiseCalibration::new(Instant::now());
+        calibration.observe(0.1, 0.01);
+        app.apply_calibration(calibration);
+        let calibrated = 0.1 * CALIBRATION_MARGIN;
+        assert_eq!(
+            app.config.device_thresholds.get("MacBook Pro Microphone"),
+            Some(&calibrated)
+        );
+        assert_eq!(
+            app.calibration_label().as_deref(),
+            Some("Calibrated for MacBook Pro Microphone")
+        );
+
+        app.current_device_name = "Scarlett 2i2".to_string();
+        app.config.min_magnitude_threshold = 0.5;
+        app.apply_device_threshold();
+        assert_eq!(app.config.min_magnitude_threshold, 0.5);
+        assert_eq!(app.calibration_label(), None);
+
+        app.current_device_name = "MacBook Pro Microphone".to_string();
+        app.apply_device_threshold();
+        assert_eq!(app.config.min_magnitude_threshold, calibrated);
+        let settings = app.pitch_worker.settings.lock().unwrap().clone();
+        assert_eq!(settings.min_magnitude_threshold, calibrated);
+    }
+
+    #[test]
+    fn test_input_gain_follows_device() {
+        let audio_data = Arc::new(Mutex::new(AudioData::new()));
+        let mut config = TunerConfig::default();
+        config.device_gains.insert("Piezo DI".to_string(), 20.0);
+        let mut app = TunerApp::new(audio_data.clone(), config);
+
+        app.current_device_name = "Piezo DI".to_string();
+        app.apply_device_gain();
+        assert!((audio_data.lock().unwrap().gain - 10.0).abs() < 1e-5);
+
+        app.current_device_name = "MacBook Pro Microphone".to_string();
+        app.apply_device_gain();
+        assert_eq!(audio_data.lock().unwrap().gain, 1.0);
+    }
+
+    #[test]
+    fn test_pitch_history_is_bounded_and_split_at_dropouts() {
+        let start = Instant::now();
+        let a4 = frequency_to_note(440.0, DEFAULT_A4_FREQUENCY);
+        let b4 = frequency_to_note(493.9, DEFAULT_A4_FREQUENCY);
+        let reading = |note: &Option<Note>| PitchReading {
+            note: note.clone(),
+            ..PitchReading::default()
+        };
+        let mut history = PitchHistory::default();
+
+        // 0–1 s on A4, a 500 ms dropout, then B4 from 1.5 s to 2 s.
+        for ms in (0..2000).step_by(10) {
+            let note = match ms {
+                0..1000 => &a4,
+                1000..1500 => &None,
+                _ => &b4,
+            };
+            history.record(&reading(note), start + Duration::from_millis(ms));
+        }
+        assert_eq!(history.points.len(), 150);
+        assert_eq!(history.segments(start), vec![0..100, 100..150]);
+        assert_eq!(
+            history.segments(start + Duration::from_millis(1700)),
+            vec![120..150]
+        );
+
+        // A note change without a dropout still breaks the line.
+        history.record(&reading(&a4), start + Duration::from_millis(2010));
+        assert_eq!(
+            history.segments(start + Duration::from_millis(1700)),
+            vec![120..150, 150..151]
+        );
+
+        let mut now = start + Duration::from_millis(2010);
+        for _ in 0..2 * MAX_HISTORY_POINTS {
+            now += Duration::from_millis(1);
+            history.record(&reading(&a4), now);
+        }
+        assert_eq!(history.points.len(), MAX_HISTORY_POINTS);
+
+        history.record(&reading(&None), now + MAX_HISTORY_SPAN * 2);
+        assert!(history.points.is_empty());
+    }
+
+    #[test]
+    fn test_recording_toggle_finalizes_and_reports_errors() {
+        let dir = std::env::temp_dir().join(format!("chroma-tuner-takes-{}", std::process::id()));
+        let audio_data = Arc::new(Mutex::new(AudioData::new()));
+        let mut app = TunerApp::new(
+            audio_data.clone(),
+            TunerConfig {
+                recordings_dir: Some(dir.clone()),
+                ..TunerConfig::default()
+            },
+        );
+
+        app.toggle_recording();
+        let path = app.recorder.as_ref().unwrap().path().to_path_buf();
+        audio_data.lock().unwrap().push_samples(&[0.25; 512]);
+        app.poll_recorder();
+        app.toggle_recording();
+        assert!(app.recorder.is_none());
+        assert!(app.status.is_none());
+        let reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.len(), 512);
+        let _ = std::fs::remove_dir_all(&dir);
+
+        std::fs::write(&dir, b"not a directory").unwrap();
+        app.toggle_recording();
+        let _ = std::fs::remove_file(&dir);
+        assert!(app.recorder.is_none());
+        let status = app
+            .status
+            .expect("unwritable location should report an error");
+        assert!(
+            status.text.starts_with("Failed to start recording"),
+            "{}",
+            status.text
+        );
+    }
+
+    #[test]
+    fn test_following_default_is_throttled_and_manual_pick_stops_it() {
+        let audio_data = Arc::new(Mutex::new(AudioData::new()));
+        let mut app = TunerApp::new(audio_data, TunerConfig::default());
+        app.current_device_name = "MacBook Pro Microphone".to_string();
+        app.available_devices = vec![
+            "MacBook Pro Microphone".to_string(),
+            "AirPods Pro".to_string(),
+        ];
+        app.system_default = Some("AirPods Pro".to_string());
+
+        let start = Instant::now();
+        assert_eq!(app.default_switch_due(start), None, "off by default");
+
+        app.set_follow_default(true);
+        assert_eq!(
+            app.default_switch_due(start).as_deref(),
+            Some("AirPods Pro")
+        );
+        app.current_device_name = "AirPods Pro".to_string();
+
+        // The headset drops out and reconnects within the cooldown.
+        app.system_default = Some("MacBook Pro Microphone".to_string());
+        assert_eq!(app.default_switch_due(start + Duration::from_secs(1)), None);
+        app.system_default = Some("AirPods Pro".to_string());
+        assert_eq!(app.default_switch_due(start + Duration::from_secs(2)), None);
+
+        // A change that sticks is followed once the cooldown is over.
+        app.system_default = Some("MacBook Pro Microphone".to_string());
+        assert_eq!(app.default_switch_due(start + Duration::from_secs(2)), None);
+        assert_eq!(
+            app.default_switch_due(start + DEFAULT_SWITCH_COOLDOWN)
+                .as_deref(),
+            Some("MacBook Pro Microphone")
+        );
+
+        app.select_device("Scarlett 2i2".to_string());
+        assert!(!app.config.follow_default_device);
+        assert_eq!(
+            app.default_switch_due(start + Duration::from_secs(60)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_device_and_stream_errors_reach_status() {
+        let audio_data = Arc::new(Mutex::new(AudioData::new()));
+        let mut app = TunerApp::new(audio_data.clone(), TunerConfig::default());
+        app.current_device_name = "Built-in Microphone".to_string();
+
+        app.open_device("Scarlett 2i2".to_string(), true, |_| {
+            Err(anyhow::anyhow!("device busy"))
+        });
+        assert_eq!(
+            app.status.as_ref().map(|status| status.text.as_str()),
+            Some("Failed to open 'Scarlett 2i2': device busy")
+        );
+        assert_eq!(app.current_device_name, "Built-in Microphone");
+        assert_eq!(app.config.preferred_device, None);
+
+        app.status = None;
+        audio_data
+            .lock()
+            .unwrap()
+            .report_stream_error("Audio stream error: device disconnected".to_string());
+        app.poll_stream_errors();
+        assert_eq!(
+            app.status.as_ref().map(|status| status.text.as_str()),
+            Some("Audio stream error: device disconnected")
+        );
+        assert!(audio_data.lock().unwrap().take_stream_error().is_none());
+    }
+
+    #[test]
+    fn test_stream_stalled_after_timeout_without_samples() {
+        let started = Instant::now();
+        let shortly = started + STREAM_STALL_TIMEOUT / 2;
+        let later = started + STREAM_STALL_TIMEOUT;
+
+        assert!(!stream_stalled(None, started, shortly));
+        assert!(stream_stalled(None, started, later));
+        assert!(!stream_stalled(Some(shortly), started, later));
+        assert!(stream_stalled(
+            Some(shortly),
+            started,
+            shortly + STREAM_STALL_TIMEOUT
+        ));
+
+        let stale_push = started;
+        let reopened = started + STREAM_STALL_TIMEOUT * 2;
+        assert!(!stream_stalled(
+            Some(stale_push),
+            reopened,
+            reopened + STREAM_STALL_TIMEOUT / 2
+        ));
+    }
+
+    #[test]
+    fn test_malformed_scale_reports_error() {
+        let path = std::env::temp_dir().join(format!(
+            "chroma-tuner-scale-test-{}.scl",
+            std::process::id()
+        ));
+        std::fs::write(&path, "Broken scale\n2\n100.0\nthree halves\n").unwrap();
+
+        let mut app = TunerApp::new(
+            Arc::new(Mutex::new(AudioData::new())),
+            TunerConfig::default(),
+        );
+        app.scala_path_input = path.display().to_string();
+        app.load_scale();
+        let _ = std::fs::remove_file(&path);
+
+        let status = app.status.expect("malformed scale should report an error");
+        assert!(
+            status.text.contains("line 4: invalid pitch value 'three'"),
+            "{}",
+            status.text
+        );
+        assert!(app.scale.is_none());
+        assert_eq!(app.config.scala_file, None);
+    }
+
+    #[test]
+    fn test_display_scales_with_window_but_not_below_default() {
+        assert_eq!(display_scale(DISPLAY_BASE_SIZE), 1.0);
+        assert_eq!(display_scale(DISPLAY_BASE_SIZE * 2.0), 2.0);
+        // A wide, short window is limited by its height.
+        assert_eq!(display_scale(egui::vec2(1600.0, 195.0)), 1.5);
+        assert_eq!(display_scale(egui::vec2(100.0, 40.0)), 1.0);
+        assert_eq!(display_scale(egui::Vec2::ZERO), 1.0);
+
+        let mut config = TunerConfig::default();
+        assert_eq!(initial_window_size(&config), WINDOW_SIZE);
+        config.window_size = [1280.0, 720.0];
+        assert_eq!(initial_window_size(&config), egui::vec2(1280.0, 720.0));
+        config.window_size = [100.0, f32::NAN];
+        assert_eq!(initial_window_size(&config), WINDOW_SIZE);
+    }
+
+    #[test]
+    fn test_hold_keeps_reading_and_wavering_range() {
+        let mut app = TunerApp::new(
+            Arc::new(Mutex::new(AudioData::new())),
+            TunerConfig::default(),
+        );
+        let a4 = frequency_to_note(440.0, DEFAULT_A4_FREQUENCY).unwrap();
+        let reading = |cents: f32| PitchReading {
+            note: Some(a4.clone()),
+            smoothed_cents: cents,
+            ..PitchReading::default()
+        };
+
+        // An old outlier, then a note wavering between -4 and +3 cents.
+        let start = Instant::now();
+        app.history.record(&reading(-20.0), start);
+        for (frame, cents) in [-4.0, 1.0, 3.0, -2.0].into_iter().enumerate() {
+            let at = start + Duration::from_secs(3) + Duration::from_millis(100 * frame as u64);
+            app.history.record(&reading(cents), at);
+        }
+        app.reading = reading(-2.0);
+
+        let now = start + Duration::from_millis(3400);
+        app.toggle_hold(now);
+        assert_eq!(
+            app.hold,
+            Some(Hold {
+                cents_range: Some((-4.0, 3.0))
+            })
+        );
+        assert_eq!(app.reading.smoothed_cents, -2.0);
+
+        // Resuming shows the live reading straight away.
+        app.toggle_hold(now);
+        assert_eq!(app.hold, None);
+        assert!(app.reading.note.is_none());
+    }
+
+    #[test]
+    fn test_pin_toggle_persists_but_launch_override_does_not() {
+        let ctx = egui::Context::default();
+        let mut app = TunerApp::new(
+            Arc::new(Mutex::new(AudioData::new())),
+            TunerConfig::default(),
+        );
+        assert!(app.always_on_top);
+
+        app.set_launched_on_top(false);
+        assert!(!app.always_on_top);
+        assert!(app.config.always_on_top);
+        assert!(!app.config_dirty);
+
+        app.toggle_always_on_top(&ctx);
+        assert!(app.always_on_top);
+        app.toggle_always_on_top(&ctx);
+        assert!(!app.always_on_top);
+        assert!(!app.config.always_on_top);
+        assert!(app.config_dirty);
+    }
+
+    #[test]
+    fn test_display_mode_cycles_and_resizes_window() {
+        let ctx = egui::Context::default();
+        let mut app = TunerApp::new(
+            Arc::new(Mutex::new(AudioData::new())),
+            TunerConfig::default(),
+        );
+        app.show_stats = true;
+
+        app.set_display_mode(&ctx, app.config.display_mode.next());
+        assert_eq!(app.config.display_mode, DisplayMode::Mini);
+        assert!(app.config_dirty, "the mode is saved for the next launch");
+        // Panels don't show in the mini strip, so they don't grow it either.
+        assert_eq!(
+            app.pending_resize.map(|(size, _)| size),
+            Some(MINI_WINDOW_SIZE)
+        );
+        assert_eq!(min_window_size(&app.config), MINI_WINDOW_SIZE);
+
+        app.set_display_mode(&ctx, app.config.display_mode.next());
+        assert_eq!(app.config.display_mode, DisplayMode::Performance);
+        assert_eq!(
+            app.pending_resize.map(|(size, _)| size),
+            Some(PERFORMANCE_WINDOW_SIZE)
+        );
+
+        app.config.window_size = [400.0, 240.0];
+        app.set_display_mode(&ctx, app.config.display_mode.next());
+        assert_eq!(app.config.display_mode, DisplayMode::Standard);
+        assert_eq!(
+            app.pending_resize.map(|(size, _)| size),
+            Some(egui::vec2(400.0, 240.0 + STATS_PANEL_HEIGHT))
+        );
+    }
+
+    #[test]
+    fn test_gauge_angle_is_linear_in_cents() {
+        assert_eq!(gauge_angle(0.0, 50.0), 0.0);
+        assert_eq!(gauge_angle(50.0, 50.0), GAUGE_HALF_ANGLE);
+        assert_eq!(gauge_angle(-25.0, 50.0), -GAUGE_HALF_ANGLE / 2.0);
+        assert!((gauge_angle(10.0, 50.0) - GAUGE_HALF_ANGLE / 5.0).abs() < 1e-6);
+        assert_eq!(gauge_angle(-5.0, 10.0), -GAUGE_HALF_ANGLE / 2.0);
+        // Past the end of the scale the needle rests against its stop.
+        assert_eq!(gauge_angle(80.0, 50.0), GAUGE_HALF_ANGLE);
+        assert_eq!(gauge_angle(-200.0, 50.0), -GAUGE_HALF_ANGLE);
+    }
+
+    #[test]
+    fn test_in_tune_cue_fires_once_per_stretch_in_tune() {
+        let start = Instant::now();
+        let at = |millis: u64| start + Duration::from_millis(millis);
+        let dwell = Duration::from_millis(750);
+        let mut cue = InTuneCue::default();
+        let a3 = Some((220.0, 2.0));
+
+        assert!(!cue.update(a3, 5.0, dwell, at(0)));
+        assert!(!cue.update(a3, 5.0, dwell, at(700)));
+        assert!(cue.update(a3, 5.0, dwell, at(750)));
+        // Holding the note doesn't retrigger it.
+        assert!(!cue.update(a3, 5.0, dwell, at(800)));
+        assert!(!cue.update(a3, 5.0, dwell, at(6000)));
+
+        // Wobbling out and straight back waits out the cooldown.
+        assert!(!cue.update(Some((220.0, 8.0)), 5.0, dwell, at(6100)));
+        cue.last_fired = Some(at(6000));
+        assert!(!cue.update(a3, 5.0, dwell, at(6200)));
+        assert!(!cue.update(a3, 5.0, dwell, at(7000)));
+        assert!(cue.update(a3, 5.0, dwell, at(9000)));
+
+        // The next string in tune gets its own cue, and a quick pass through
+        // the tolerance doesn't count.
+        cue.last_fired = None;
+        let d3 = Some((146.8, -1.0));
+        assert!(!cue.update(d3, 5.0, dwell, at(10_000)));
+        assert!(!cue.update(None, 5.0, dwell, at(10_500)));
+        assert!(!cue.update(d3, 5.0, dwell, at(10_600)));
+        assert!(!cue.update(d3, 5.0, dwell, at(11_300)));
+        assert!(cue.update(d3, 5.0, dwell, at(11_350)));
+
+        // A tighter tolerance leaves the same note out of tune.
+        let mut strict = InTuneCue::default();
+        assert!(!strict.update(a3, 1.0, dwell, at(0)));
+        assert!(!strict.update(a3, 1.0, dwell, at(2000)));
+    }
+
+    #[test]
+    fn test_escape_returns_from_target_note_to_chromatic() {
+        let ctx = egui::Context::default();
+        let mut app = TunerApp::new(
+            Arc::new(Mutex::new(AudioData::new())),
+            TunerConfig::default(),
+        );
+        app.set_target_note(Some(41));
+        assert_eq!(app.analysis_settings().target_note, Some(41));
+        let _ = ctx.run(egui::RawInput::default(), |ctx| app.show(ctx));
+        assert_eq!(app.target_note, Some(41));
+
+        let escape = egui::RawInput {
+            events: vec![egui::Event::Key {
+                key: egui::Key::Escape,
+                physical_key: None,
+                pressed: true,
+                repeat: false,
+                modifiers: egui::Modifiers::NONE,
+            }],
+            ..Default::default()
+        };
+        let _ = ctx.run(escape, |ctx| app.show(ctx));
+        assert_eq!(app.target_note, None);
+        assert_eq!(app.analysis_settings().target_note, None);
+    }
+
+    #[test]
+    fn test_octave_shift_holds_until_the_note_moves_or_stops() {
+        let mut app = TunerApp::new(
+            Arc::new(Mutex::new(AudioData::new())),
+            TunerConfig::default(),
+        );
+        let reading = |frequency: Option<f32>| PitchReading {
+            note: frequency
+                .and_then(|frequency| frequency_to_note(frequency, DEFAULT_A4_FREQUENCY)),
+            ..PitchReading::default()
+        };
+        let (e2, e3, a2) = (82.41, 164.81, 110.0);
+        app.shift_octave(-1);
+        assert_eq!(app.octave_shift, None, "nothing to shift without a note");
+
+        // E3 on display is really E2.
+        app.reading = reading(Some(e3));
+        app.shift_octave(-1);
+        let prior = app.analysis_settings().frequency_prior.expect("no prior");
+        assert!((prior.center - e2).abs() < 0.01, "{}", prior.center);
+
+        // The old octave until the worker catches up, then E2, keep it...
+        app.follow_octave_shift(&reading(Some(e3)));
+        app.follow_octave_shift(&reading(Some(e2)));
+        app.follow_octave_shift(&reading(Some(e2 * 1.01)));
+        assert!(app.octave_shift.is_some());
+        // ...until the next string.
+        app.follow_octave_shift(&reading(Some(a2)));
+        assert_eq!(app.analysis_settings().frequency_prior, None);
+
+        // Silence lets go too, and shifting twice moves two octaves.
+        app.shift_octave(1);
+        app.shift_octave(1);
+        let prior = app.octave_shift.unwrap().prior;
+        assert!((prior.center - 4.0 * e3).abs() < 0.1, "{}", prior.center);
+        app.follow_octave_shift(&reading(None));
+        assert_eq!(app.octave_shift, None);
+    }
+
+    #[test]
+    fn test_beats_follow_the_smoothed_deviation_and_the_drone() {
+        let a3 = frequency_to_note(220.0, DEFAULT_A4_FREQUENCY);
+        let reading = |cents: f32| PitchReading {
+            note: a3.clone(),
+            smoothed_cents: cents,
+            ..PitchReading::default()
+        };
+        // 220 Hz raised 8 cents is about 1 Hz sharp.
+        let beats = audible_beats(&reading(8.0), None).unwrap();
+        assert!((beats - 220.0 * ((8.0f32 / 1200.0).exp2() - 1.0)).abs() < 1e-4);
+        assert!((beats - 1.0).abs() < 0.05, "{}", beats);
+        // Under a fifth of a beat a second, and past counting, is hidden.
+        assert_eq!(audible_beats(&reading(1.0), None), None);
+        let a6 = PitchReading {
+            note: frequency_to_note(1760.0, DEFAULT_A4_FREQUENCY),
+            smoothed_cents: -40.0,
+            ..PitchReading::default()
+        };
+        assert_eq!(audible_beats(&a6, None), None);
+        assert_eq!(audible_beats(&PitchReading::default(), None), None);
+
+        // In tune with equal temperament, A3 still beats against a D3 drone.
+        let d3 = midi_to_frequency(50, DEFAULT_A4_FREQUENCY);
+        let beats = audible_beats(&reading(0.0), Some(d3)).unwrap();
+        assert!((beats - (3.0 * d3 - 440.0)).abs() < 1e-3, "{}", beats);
+        // A drone that shares no partials leaves the target's beats.
+        assert_eq!(
+            audible_beats(&reading(8.0), Some(midi_to_frequency(51, 440.0))),
+            audible_beats(&reading(8.0), None)
+        );
+    }
+
+    #[test]
+    fn test_drone_is_in_tune_with_the_needle() {
+        let mut app = TunerApp::new(
+            Arc::new(Mutex::new(AudioData::new())),
+            TunerConfig {
+                a4_reference: 443.0,
+                drone_note: 50,
+                ..TunerConfig::default()
+            },
+        );
+        for temperament in Temperament::ALL {
+            app.config.temperament = temperament;
+            app.config.temperament_tonic = 7;
+            let note = app.measured_note(app.drone_frequency()).unwrap();
+            assert_eq!(note.to_string(), "D3");
+            assert!(note.cents_off.abs() < 0.01, "{:?}", temperament);
+        }
+        // A just D3 on a G tonic is a pure fifth above G2.
+        app.config.temperament = Temperament::Just;
+        let g2 = tempered_frequency(43, 443.0, Temperament::Just, 7);
+        assert!((app.drone_frequency() / g2 - 1.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_tune_direction_points_towards_the_target() {
+        assert_eq!(TuneDirection::of(4.9, 5.0), TuneDirection::InTune);
+        assert_eq!(TuneDirection::of(-4.9, 5.0), TuneDirection::InTune);
+        assert_eq!(TuneDirection::of(5.0, 5.0), TuneDirection::Down);
+        assert_eq!(TuneDirection::of(-12.0, 5.0), TuneDirection::Up);
+        // A target note far away still has a direction.
+        assert_eq!(TuneDirection::of(-700.0, 5.0), TuneDirection::Up);
+        assert_eq!(TuneDirection::of(1200.0, 1.0), TuneDirection::Down);
+
+        assert_eq!(TuneDirection::strength(5.0, 5.0), 0.0);
+        assert!((TuneDirection::strength(-27.5, 5.0) - 0.5).abs() < 1e-6);
+        assert_eq!(TuneDirection::strength(50.0, 5.0), 1.0);
+        assert_eq!(TuneDirection::strength(-700.0, 5.0), 1.0);
+        // Even a tolerance wider than the full-size deviation grows the arrow.
+        assert_eq!(TuneDirection::strength(60.0, 55.0), 1.0);
+    }
+
+    #[test]
+    fn test_detection_rate_counts_per_second() {
+        let start = Instant::now();
+        let mut rate = DetectionRate::default();
+        rate.update(100, start);
+        rate.update(120, start + Duration::from_millis(500));
+        assert_eq!(rate.per_second, None);
+        rate.update(143, start + Duration::from_secs(1));
+        assert_eq!(rate.per_second, Some(43.0));
+
+        // A restarted worker starts the count over rather than going negative.
+        rate.update(5, start + Duration::from_millis(1200));
+        rate.update(25, start + Duration::from_millis(2200));
+        assert_eq!(rate.per_second, Some(20.0));
+    }
+
+    #[test]
+    fn test_diagnostics_show_input_and_resolution() {
+        let audio_data = Arc::new(Mutex::new(AudioData::new()));
+        let mut app = TunerApp::new(audio_data.clone(), TunerConfig::default());
+        app.current_device_name = "Test input".to_string();
+        let now = Instant::now();
+        assert!(app
+            .diagnostics_lines(now)
+            .contains(&"callback none yet".to_string()));
+
+        audio_data.lock().unwrap().push_samples(&[0.0; 64]);
+        app.reading = PitchReading {
+            note: Some(Note {
+                cents_off: 2.0,
+                ..midi_to_note(69, 440.0, 440.0)
+            }),
+            raw_cents: Some(3.5),
+            ..PitchReading::default()
+        };
+        let lines = app
+            .diagnostics_lines(now + Duration::from_millis(40))
+            .join("\n");
+        assert!(lines.contains("device   Test input"), "{}", lines);
+        assert!(lines.contains("input    48000 Hz · 1 ch"), "{}", lines);
+        // 5.86 Hz bins are 89.8 cents wide at 110 Hz and 22.9 at 440.
+        assert!(lines.contains("8192 samples · 5.86 Hz/bin"), "{}", lines);
+        assert!(lines.contains("89.8 ¢ @110 · 22.9 ¢ @440"), "{}", lines);
+        assert!(lines.contains("raw +3.5 · smoothed +2.0"), "{}", lines);
+        assert!(lines.contains("callback "), "{}", lines);
+    }
+
+    #[test]
+    fn test_in_tune_flash_fades_out() {
+        let ctx = egui::Context::default();
+        let mut app = TunerApp::new(
+            Arc::new(Mutex::new(AudioData::new())),
+            TunerConfig {
+                in_tune_flash: true,
+                ..TunerConfig::default()
+            },
+        );
+        let start = Instant::now();
+        let reading = PitchReading {
+            note: frequency_to_note(440.5, DEFAULT_A4_FREQUENCY),
+            smoothed_cents: 2.0,
+            ..PitchReading::default()
+        };
+        app.cue_in_tune(&reading, start);
+        app.cue_in_tune(&reading, start + Duration::from_millis(750));
+        assert_eq!(app.in_tune_flash, Some(start + Duration::from_millis(750)));
+
+        let _ = ctx.run(egui::RawInput::default(), |ctx| {
+            app.draw_in_tune_flash(ctx, start + Duration::from_millis(900))
+        });
+        assert!(app.in_tune_flash.is_some());
+        let _ = ctx.run(egui::RawInput::default(), |ctx| {
+            app.draw_in_tune_flash(ctx, start + Duration::from_millis(1100))
+        });
+        assert_eq!(app.in_tune_flash, None);
+    }
+
+    #[test]
+    fn test_needle_ticks_suit_the_range() {
+        let ticks = |range: f32| needle_ticks(range).collect::<Vec<_>>();
+        assert_eq!(ticks(50.0), [10.0, 20.0, 30.0, 40.0]);
+        assert_eq!(ticks(25.0), [5.0, 10.0, 15.0, 20.0]);
+        assert_eq!(ticks(15.0), [5.0, 10.0]);
+        assert_eq!(ticks(10.0), [2.0, 4.0, 6.0, 8.0]);
+        // Mid-zoom, no tick crowds the end of the scale.
+        assert_eq!(ticks(32.0), [10.0, 20.0]);
+    }
+
+    #[test]
+    fn test_auto_zoom_eases_in_near_tune_and_out_when_off() {
+        let start = Instant::now();
+        let mut scale = NeedleScale::new(NeedleRange::Auto);
+        let frame = 1.0 / 60.0;
+        let at = |seconds: f32| start + Duration::from_secs_f32(seconds);
+
+        // Close to in tune, but not yet for a second.
+        for step in 0..50 {
+            assert!(!scale.update(NeedleRange::Auto, Some(8.0), at(step as f32 * frame), frame));
+        }
+        assert_eq!(scale.range, 50.0);
+
+        assert!(scale.update(NeedleRange::Auto, Some(8.0), at(1.0), frame));
+        assert!(scale.range < 50.0 && scale.range > 45.0, "{}", scale.range);
+        let mut time = 1.0;
+        while scale.update(NeedleRange::Auto, Some(8.0), at(time), frame) {
+            time += frame;
+        }
+        assert_eq!(scale.range, NEEDLE_ZOOM_CENTS);
+        assert!(time < 2.0);
+
+        // Drifting off zooms straight back out.
+        assert!(scale.update(NeedleRange::Auto, Some(-20.0), at(time), frame));
+        while scale.update(NeedleRange::Auto, Some(-20.0), at(time), frame) {}
+        assert_eq!(scale.range, 50.0);
+
+        // A fixed range never zooms.
+        let mut fixed = NeedleScale::new(NeedleRange::Cents25);
+        assert!(!fixed.update(NeedleRange::Cents25, Some(1.0), start, frame));
+        assert!(!fixed.update(NeedleRange::Cents25, Some(1.0), at(5.0), frame));
+        assert_eq!(fixed.range, 25.0);
+    }
+
+    #[test]
+    fn test_frames_leave_style_alone_until_theme_changes() {
+        let ctx = egui::Context::default();
+        let mut app = TunerApp::new(
+            Arc::new(Mutex::new(AudioData::new())),
+            TunerConfig::default(),
+        );
+        app.show_settings = true;
+        let _ = ctx.run(egui::RawInput::default(), |ctx| app.show(ctx));
+        let style = ctx.style();
+        assert_eq!(style.visuals.override_text_color, Some(Theme::DARK.text));
+
+        for _ in 0..3 {
+            let _ = ctx.run(egui::RawInput::default(), |ctx| app.show(ctx));
+        }
+        assert!(Arc::ptr_eq(&style, &ctx.style()));
+
+        app.config.theme = UiTheme::Light;
+        let _ = ctx.run(egui::RawInput::default(), |ctx| app.show(ctx));
+        assert_eq!(
+            ctx.style().visuals.override_text_color,
+            Some(Theme::LIGHT.text)
+        );
+    }
+
+    #[test]
+    fn test_repaints_slowly_only_when_idle() {
+        let mut app = TunerApp::new(
+            Arc::new(Mutex::new(AudioData::new())),
+            TunerConfig::default(),
+        );
+        assert_eq!(app.repaint_interval(false), IDLE_REPAINT_INTERVAL);
+        assert_eq!(app.repaint_interval(true), ACTIVE_REPAINT_INTERVAL);
+
+        let now = Instant::now();
+        let noise_floor = InputLevel {
+            rms: 0.002,
+            ..InputLevel::default()
+        };
+        app.level_meter.update(noise_floor, now);
+        assert_eq!(app.repaint_interval(false), IDLE_REPAINT_INTERVAL);
+
+        let playing = InputLevel {
+            rms: 0.2,
+            ..InputLevel::default()
+        };
+        app.level_meter.update(playing, now);
+        assert_eq!(app.repaint_interval(false), ACTIVE_REPAINT_INTERVAL);
+    }
+
+    #[test]
+    fn test_needle_glides_without_overshooting() {
+        let response = NeedleResponse::Medium.response_time();
+        let mut needle = NeedleMotion::default();
+        let mut previous = 0.0;
+        for _ in 0..15 {
+            needle.update(20.0, response, 1.0 / 60.0);
+            assert!(needle.cents > previous && needle.cents <= 20.0);
+            previous = needle.cents;
+        }
+        // A quarter second in it's nearly there, and soon after it stops.
+        assert!(needle.cents > 19.5, "{}", needle.cents);
+        for _ in 0..30 {
+            needle.update(20.0, response, 1.0 / 60.0);
+        }
+        assert_eq!(needle.cents, 20.0);
+        assert!(needle.settled_at(20.0));
+
+        // A slower setting lags behind.
+        let mut slow = NeedleMotion::default();
+        slow.update(20.0, NeedleResponse::Slow.response_time(), 0.1);
+        let mut fast = NeedleMotion::default();
+        fast.update(20.0, NeedleResponse::Fast.response_time(), 0.1);
+        assert!(slow.cents < fast.cents);
+    }
+
+    #[test]
+    fn test_needle_motion_is_frame_rate_independent() {
+        let response = NeedleResponse::Medium.response_time();
+        let (mut at_30, mut at_120) = (NeedleMotion::default(), NeedleMotion::default());
+        for target in [-30.0, 10.0] {
+            for _ in 0..6 {
+                at_30.update(target, response, 1.0 / 30.0);
+            }
+            for _ in 0..24 {
+                at_120.update(target, response, 1.0 / 120.0);
+            }
+            assert!((at_30.cents - at_120.cents).abs() < 0.01);
+            assert!((at_30.velocity - at_120.velocity).abs() < 0.1);
+        }
+    }
+
+    #[test]
+    fn test_needle_returns_to_center_when_the_note_clears() {
+        let mut app = TunerApp::new(
+            Arc::new(Mutex::new(AudioData::new())),
+            TunerConfig::default(),
+        );
+        app.reading = PitchReading {
+            note: frequency_to_note(446.0, DEFAULT_A4_FREQUENCY),
+            smoothed_cents: 23.0,
+            ..PitchReading::default()
+        };
+        while app.move_needle(1.0 / 60.0) {}
+        assert_eq!(app.needle.cents, 23.0);
+
+        app.reading = PitchReading::default();
+        assert!(app.move_needle(1.0 / 60.0));
+        assert!(app.needle.cents > 0.0 && app.needle.cents < 23.0);
+        while app.move_needle(1.0 / 60.0) {}
+        assert_eq!(app.needle.cents, 0.0);
+    }
+
+    struct PausableSource {
+        paused: Arc<AtomicBool>,
+    }
+
+    impl AudioSource for PausableSource {
+        fn name(&self) -> &str {
+            "Pausable"
+        }
+
+        fn pause(&mut self) -> anyhow::Result<()> {
+            self.paused.store(true, Ordering::Relaxed);
+            Ok(())
+        }
+
+        fn resume(&mut self) -> anyhow::Result<()> {
+            self.paused.store(false, Ordering::Relaxed);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_minimizing_pauses_capture_unless_listening_in_background() {
+        let mut app = TunerApp::new(
+            Arc::new(Mutex::new(AudioData::new())),
+            TunerConfig::default(),
+        );
+        let paused = Arc::new(AtomicBool::new(false));
+        app.set_input(Box::new(PausableSource {
+            paused: paused.clone(),
+        }));
+
+        let ctx = egui::Context::default();
+        let frame = |app: &mut TunerApp, minimized: bool, now: Instant| {
+            let mut input = egui::RawInput::default();
+            input
+                .viewports
+                .entry(egui::ViewportId::ROOT)
+                .or_default()
+                .minimized = Some(minimized);
+            let _ = ctx.run(input, |ctx| app.suspend_when_hidden(ctx, now));
+        };
+
+        let start = Instant::now();
+        frame(&mut app, true, start);
+        assert!(paused.load(Ordering::Relaxed));
+        assert!(app.pitch_worker.muted.load(Ordering::Relaxed));
+        // No samples arrive while paused, which isn't a stalled stream.
+        app.check_stream_watchdog(start + STREAM_STALL_TIMEOUT * 2);
+        assert!(app.input.is_some());
+
+        let replacement = Arc::new(AtomicBool::new(false));
+        app.set_input(Box::new(PausableSource {
+            paused: replacement.clone(),
+        }));
+        assert!(replacement.load(Ordering::Relaxed), "stays paused");
+
+        frame(&mut app, false, start);
+        assert!(!replacement.load(Ordering::Relaxed));
+        assert!(!app.pitch_worker.muted.load(Ordering::Relaxed));
+
+        app.config.listen_in_background = true;
+        frame(&mut app, true, start);
+        assert!(!replacement.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_shortcut_restores_a_minimized_window_and_hides_a_shown_one() {
+        let mut app = TunerApp::new(
+            Arc::new(Mutex::new(AudioData::new())),
+            TunerConfig::default(),
+        );
+        let ctx = egui::Context::default();
+        let mut input = egui::RawInput::default();
+        input
+            .viewports
+            .entry(egui::ViewportId::ROOT)
+            .or_default()
+            .minimized = Some(true);
+        let output = ctx.run(input, |ctx| app.toggle_window(ctx));
+        assert!(!app.window_hidden);
+        let commands = &output.viewport_output[&egui::ViewportId::ROOT].commands;
+        assert!(commands.contains(&egui::ViewportCommand::Minimized(false)));
+        assert!(commands.contains(&egui::ViewportCommand::Focus));
+
+        let _ = ctx.run(egui::RawInput::default(), |ctx| app.toggle_window(ctx));
+        assert!(app.window_hidden);
+        let _ = ctx.run(egui::RawInput::default(), |ctx| app.toggle_window(ctx));
+        assert!(!app.window_hidden);
+
+        // A combination that doesn't parse is reported and not saved.
+        app.set_toggle_shortcut("Ctrl+Shift+");
+        assert!(app.status.as_ref().is_some_and(|status| status.error));
+        assert_eq!(app.config.toggle_shortcut, "Ctrl+Shift+T");
+        app.set_toggle_shortcut(" Alt+F9 ");
+        assert_eq!(app.config.toggle_shortcut, "Alt+F9");
+        app.set_toggle_shortcut("");
+        assert_eq!(app.config.toggle_shortcut, "");
+    }
+
+    #[test]
+    fn test_typed_websocket_port_rebinds_the_server() {
+        let free_port = || {
+            std::net::TcpListener::bind(("0.0.0.0", 0))
+                .unwrap()
+                .local_addr()
+                .unwrap()
+                .port()
+        };
+        let mut app = TunerApp::new(
+            Arc::new(Mutex::new(AudioData::new())),
+            TunerConfig {
+                websocket_enabled: true,
+                websocket_port: free_port(),
+                ..TunerConfig::default()
+            },
+        );
+        let first = app.websocket.as_ref().unwrap().address().port();
+
+        // Typing writes each digit to the config while the field has focus,
+        // then focus leaves without a change of its own.
+        let typed = free_port();
+        app.config.websocket_port = typed;
+        app.finish_websocket_port_edit();
+        assert!(app.config_dirty);
+        assert_ne!(typed, first);
+        assert_eq!(app.websocket.as_ref().unwrap().address().port(), typed);
+
+        // Leaving the field again without an edit keeps the server as is.
+        app.config_dirty = false;
+        app.finish_websocket_port_edit();
+        assert!(!app.config_dirty);
+    }
+
+    #[test]
+    fn test_overlay_mode_drops_the_chrome_and_is_left_with_o() {
+        let mut app = TunerApp::new(
+            Arc::new(Mutex::new(AudioData::new())),
+            TunerConfig {
+                overlay_click_through: true,
+                ..TunerConfig::default()
+            },
+        );
+        let ctx = egui::Context::default();
+        let output = ctx.run(egui::RawInput::default(), |ctx| {
+            app.handle_tray_command(ctx, TrayCommand::ToggleOverlay);
+        });
+        assert!(app.overlay);
+        let commands = &output.viewport_output[&egui::ViewportId::ROOT].commands;
+        assert!(commands.contains(&egui::ViewportCommand::Decorations(false)));
+        // Without a tray nothing could bring the controls back, so clicks
+        // still land.
+        assert!(commands.contains(&egui::ViewportCommand::MousePassthrough(false)));
+        assert_eq!(
+            eframe::App::clear_color(&app, &egui::Visuals::dark()),
+            egui::Color32::GREEN.to_normalized_gamma_f32()
+        );
+        app.config.overlay_background = OverlayBackground::Transparent;
+        assert_eq!(
+            eframe::App::clear_color(&app, &egui::Visuals::dark()),
+            [0.0; 4]
+        );
+
+        let mut input = egui::RawInput::default();
+        input.events.push(egui::Event::Key {
+            key: egui::Key::O,
+            physical_key: None,
+            pressed: true,
+            repeat: false,
+            modifiers: egui::Modifiers::NONE,
+        });
+        let output = ctx.run(input, |ctx| app.show(ctx));
+        assert!(!app.overlay);
+        let commands = &output.viewport_output[&egui::ViewportId::ROOT].commands;
+        assert!(commands.contains(&egui::ViewportCommand::Decorations(true)));
+    }
+
+    #[test]
+    fn test_tray_toggle_hides_window_and_pauses_capture() {
+        let mut app = TunerApp::new(
+            Arc::new(Mutex::new(AudioData::new())),
+            TunerConfig::default(),
+        );
+        let paused = Arc::new(AtomicBool::new(false));
+        app.set_input(Box::new(PausableSource {
+            paused: paused.clone(),
+        }));
+        let ctx = egui::Context::default();
+        let now = Instant::now();
+
+        let output = ctx.run(egui::RawInput::default(), |ctx| {
+            app.handle_tray_command(ctx, TrayCommand::ToggleWindow);
+            app.suspend_when_hidden(ctx, now);
+        });
+        assert!(app.window_hidden);
+        assert!(paused.load(Ordering::Relaxed));
+        let commands = &output.viewport_output[&egui::ViewportId::ROOT].commands;
+        assert!(commands.contains(&egui::ViewportCommand::Visible(false)));
+
+        let _ = ctx.run(egui::RawInput::default(), |ctx| {
+            app.handle_tray_command(ctx, TrayCommand::ToggleWindow);
+            app.suspend_when_hidden(ctx, now);
+        });
+        assert!(!app.window_hidden);
+        assert!(!paused.load(Ordering::Relaxed));
+
+        // Without a tray there is nothing to bring the window back, so closing
+        // quits as usual.
+        app.config.close_to_tray = true;
+        let mut input = egui::RawInput::default();
+        input
+            .viewports
+            .entry(egui::ViewportId::ROOT)
+            .or_default()
+            .events
+            .push(egui::ViewportEvent::Close);
+        let _ = ctx.run(input, |ctx| app.close_to_tray(ctx));
+        assert!(!app.window_hidden);
+    }
+
+    /// Feeds `guided` what the tracker would report for `frequency` over
+    /// `duration`, every 50 ms, and returns the time after.
+    fn play(
+        guided: &mut GuidedTuning,
+        frequency: Option<f32>,
+        start: Instant,
+        duration: Duration,
+    ) -> Instant {
+        let step = Duration::from_millis(50);
+        let mut now = start;
+        while now < start + duration {
+            let played = frequency.and_then(|frequency| {
+                let matched = guided
+                    .preset
+                    .closest_string(frequency, DEFAULT_A4_FREQUENCY)?;
+                Some((matched.string_number, matched.note.cents_off))
+            });
+            guided.update(played, 5.0, now);
+            now += step;
+        }
+        now
+    }
+
+    #[test]
+    fn test_guided_tuning_advances_and_follows_played_string() {
+        let guitar = InstrumentPreset::built_in()
+            .into_iter()
+            .find(|preset| preset.name == "Guitar")
+            .unwrap();
+        let mut guided = GuidedTuning::new(Arc::new(guitar));
+        assert_eq!(guided.current, 6, "starts on the low E");
+
+        // A flat low E is marked off until it has been in tune for a second.
+        let mut now = play(
+            &mut guided,
+            Some(80.0),
+            Instant::now(),
+            Duration::from_secs(1),
+        );
+        assert_eq!(guided.status(6), StringStatus::Off);
+        now = play(&mut guided, Some(82.41), now, Duration::from_millis(900));
+        assert_eq!(guided.status(6), StringStatus::Off);
+        now = play(&mut guided, Some(82.41), now, Duration::from_millis(200));
+        assert_eq!(guided.status(6), StringStatus::InTune);
+        assert_eq!(guided.just_completed(now), Some(6));
+        assert_eq!(guided.current, 5, "moves on to A");
+
+        // The low E ringing on doesn't take the guide back.
+        now = play(&mut guided, Some(82.2), now, Duration::from_millis(900));
+        assert_eq!(guided.current, 5);
+        assert_eq!(guided.status(6), StringStatus::InTune);
+        assert_eq!(guided.just_completed(now), None);
+
+        // Plucking the G out of order makes it the string being tuned.
+        now = play(&mut guided, None, now, Duration::from_millis(200));
+        now = play(&mut guided, Some(196.0), now, Duration::from_millis(1100));
+        assert_eq!(guided.status(3), StringStatus::InTune);
+        assert_eq!(guided.status(5), StringStatus::Untuned);
+        assert_eq!(guided.current, 2, "continues upwards from the G");
+
+        for frequency in [246.94, 329.63, 110.0, 146.83] {
+            now = play(&mut guided, None, now, Duration::from_millis(200));
+            now = play(
+                &mut guided,
+                Some(frequency),
+                now,
+                Duration::from_millis(1100),
+            );
+        }
+        assert!(guided.finished());
+
+        guided.restart();
+        assert!(!guided.finished());
+        assert_eq!(guided.current, 6);
+        assert_eq!(guided.status(3), StringStatus::Untuned);
+    }
+
+    #[test]
+    fn test_challenge_scores_speed_and_accuracy() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut challenge = ChallengeMode::new((69, 69), None, 1, start);
+        assert_eq!(challenge.target, 69);
+
+        // The wrong note or A4 too far off don't count.
+        assert_eq!(challenge.update(Some((71, 0.0)), at(500)), None);
+        assert_eq!(challenge.update(Some((69, 20.0)), at(1000)), None);
+        // Found after 2 s and held 6 cents flat: 7/9 for speed, 3/5 for
+        // accuracy.
+        let mut result = None;
+        for ms in (2000..=3000).step_by(20) {
+            result = result.or(challenge.update(Some((69, -6.0)), at(ms)));
+        }
+        let hit = ChallengeResult {
+            target: 69,
+            points: 69,
+        };
+        assert_eq!(result, Some(hit));
+        assert_eq!(challenge.just_finished(at(3000)), Some(hit));
+        assert_eq!((challenge.streak, challenge.score()), (1, 69));
+
+        // Straying out of tolerance starts the hold over.
+        for ms in (3000..=3500).step_by(20) {
+            assert_eq!(challenge.update(Some((69, 0.0)), at(ms)), None);
+        }
+        assert_eq!(challenge.update(None, at(3600)), None);
+        for ms in (3700..4700).step_by(20) {
+            assert_eq!(challenge.update(Some((69, 0.0)), at(ms)), None);
+        }
+        assert!(challenge.update(Some((69, 0.0)), at(4700)).is_some());
+        assert_eq!(challenge.streak, 2);
+
+        // Running out of time is a miss and ends the streak.
+        assert_eq!(challenge.time_left(at(9700)), Duration::from_secs(5));
+        assert_eq!(
+            challenge.update(None, at(14700)),
+            Some(ChallengeResult {
+                target: 69,
+                points: 0
+            })
+        );
+        assert_eq!((challenge.streak, challenge.best_streak), (0, 2));
+        assert_eq!(challenge.hits(), 2);
+
+        assert_eq!(
+            challenge_points(CHALLENGE_HOLD_TIME, 0.0),
+            CHALLENGE_MAX_POINTS
+        );
+        assert_eq!(
+            challenge_points(CHALLENGE_TIME_LIMIT, CHALLENGE_TOLERANCE_CENTS),
+            1
+        );
+    }
+
+    #[test]
+    fn test_challenge_round_draws_targets_from_the_key() {
+        let mut now = Instant::now();
+        let mut challenge = ChallengeMode::new((60, 72), Some(0), 7, now);
+        let c_major = [60, 62, 64, 65, 67, 69, 71, 72];
+        assert_eq!(challenge.candidates(), c_major);
+
+        let mut last = None;
+        for _ in 0..CHALLENGE_ROUND_NOTES {
+            assert!(!challenge.finished());
+            let target = challenge.target;
+            assert!(c_major.contains(&target), "{} is not in C major", target);
+            assert_ne!(Some(target), last, "the same target twice in a row");
+            last = Some(target);
+
+            assert_eq!(challenge.update(Some((target, 2.0)), now), None);
+            now += CHALLENGE_HOLD_TIME;
+            assert!(challenge.update(Some((target, 2.0)), now).is_some());
+        }
+        assert!(challenge.finished());
+        assert_eq!((challenge.hits(), challenge.best_streak), (10, 10));
+        assert_eq!(challenge.update(Some((60, 0.0)), now), None);
+
+        challenge.restart(now);
+        assert!(!challenge.finished());
+        assert_eq!(challenge.score(), 0);
+
+        // A key with none of its notes in range falls back to all of them.
+        let chromatic = ChallengeMode::new((61, 61), Some(0), 1, now);
+        assert_eq!(chromatic.candidates(), [61]);
+    }
+
+    #[test]
+    fn test_high_scores_keep_the_best_first() {
+        let mut scores = Vec::new();
+        assert!(record_high_score(&mut scores, 500));
+        assert!(!record_high_score(&mut scores, 300));
+        assert!(record_high_score(&mut scores, 800));
+        for score in [100, 200, 400, 600] {
+            assert!(!record_high_score(&mut scores, score));
+        }
+        assert_eq!(scores, [800, 600, 500, 400, 300]);
+    }
+
+    #[test]
+    fn test_guided_tuning_follows_preset_changes() {
+        let mut app = TunerApp::new(
+            Arc::new(Mutex::new(AudioData::new())),
+            TunerConfig::default(),
+        );
+        app.set_guided(true);
+        assert!(app.guided.is_none(), "needs an instrument");
+
+        let violin = app
+            .presets
+            .iter()
+            .find(|preset| preset.name == "Violin")
+            .cloned();
+        app.select_preset(violin);
+        app.set_guided(true);
+        assert_eq!(app.guided.as_ref().map(|guided| guided.current), Some(4));
+
+        app.select_preset(None);
+        assert!(app.guided.is_none());
+    }
+
+    #[test]
+    fn test_preset_transpose_is_saved_per_preset() {
+        let mut app = TunerApp::new(
+            Arc::new(Mutex::new(AudioData::new())),
+            TunerConfig::default(),
+        );
+        let preset = |app: &TunerApp, name: &str| {
+            app.presets
+                .iter()
+                .find(|preset| preset.name == name)
+                .cloned()
+        };
+        app.select_preset(preset(&app, "Guitar"));
+        app.set_guided(true);
+        app.set_preset_transpose(2);
+        app.set_tuning_offset(-10.0);
+
+        let target = app.target_string(6).unwrap();
+        assert_eq!(target.to_string(), "F#2");
+        assert!((target.target_frequency - 92.50 * 2.0f32.powf(-10.0 / 1200.0)).abs() < 0.01);
+        let settings = app.analysis_settings();
+        let matched = settings
+            .preset
+            .unwrap()
+            .closest_string(92.50, DEFAULT_A4_FREQUENCY)
+            .unwrap();
+        assert_eq!(matched.string_number, 6);
+        assert!((matched.note.cents_off - 10.0).abs() < 0.1);
+        let guided = app.guided.as_ref().unwrap();
+        assert_eq!(guided.preset.transpose, 2);
+        assert_eq!(guided.current, 6);
+
+        // Other presets keep their own transposition; the fine offset is shared.
+        app.select_preset(preset(&app, "Violin"));
+        assert_eq!(app.target_string(4).unwrap().to_string(), "G3");
+        assert_eq!(app.active_preset.as_ref().unwrap().offset_cents, -10.0);
+        app.select_preset(preset(&app, "Guitar"));
+        assert_eq!(app.target_string(6).unwrap().to_string(), "F#2");
+        assert_eq!(app.config.preset_transpose.get("Guitar"), Some(&2));
+
+        app.set_preset_transpose(0);
+        assert!(app.config.preset_transpose.is_empty());
+        assert_eq!(app.target_string(6).unwrap().to_string(), "E2");
+
+        let reopened = TunerApp::new(
+            Arc::new(Mutex::new(AudioData::new())),
+            TunerConfig {
+                preset_transpose: BTreeMap::from([("Guitar".to_string(), -1)]),
+                ..app.config.clone()
+            },
+        );
+        assert_eq!(reopened.target_string(6).unwrap().to_string(), "D#2");
+    }
+
+    #[test]
+    fn test_saved_user_presets_join_the_menu() {
+        let mut app = TunerApp::new(
+            Arc::new(Mutex::new(AudioData::new())),
+            TunerConfig::default(),
+        );
+        let dir =
+            std::env::temp_dir().join(format!("chroma-tuner-user-presets-{}", std::process::id()));
+        let path = dir.join("presets.toml");
+        app.user_presets_path = Some(path.clone());
+        let user_preset = |name: &str, strings: &[&str]| UserPreset {
+            name: name.to_string(),
+            strings: strings
+                .iter()
+                .map(|name| StringTuning::Note(name.to_string()))
+                .collect(),
+        };
+
+        app.save_presets_file(vec![
+            user_preset("DADGAD", &["D2", "A2", "D3", "G3", "A3", "D4"]),
+            user_preset("Guitar", &["E2"]),
+            user_preset("Broken", &["E2", "X9"]),
+        ]);
+        let names: Vec<&str> = app
+            .presets
+            .iter()
+            .map(|preset| preset.name.as_str())
+            .collect();
+        assert_eq!(names.last(), Some(&"DADGAD"));
+        assert_eq!(names.iter().filter(|&&name| name == "Guitar").count(), 1);
+        let status = app.status.as_ref().unwrap();
+        assert!(status.error);
+        assert_eq!(
+            status.text,
+            "preset 'Guitar': another preset has the same name; \
+             preset 'Broken', string 2: unknown note 'X9'"
+        );
+        // The broken one stays in the file to be fixed.
+        assert_eq!(load_user_presets(&path).unwrap().0.len(), 3);
+
+        let dadgad = app.presets.last().cloned();
+        app.select_preset(dadgad);
+        assert_eq!(app.target_string(1).unwrap().to_string(), "D4");
+        app.save_presets_file(vec![user_preset(
+            "DADGAD",
+            &["D2", "A2", "D3", "G3", "A3", "E4"],
+        )]);
+        assert!(!app.status.as_ref().unwrap().error);
+        assert_eq!(app.target_string(1).unwrap().to_string(), "E4");
+
+        app.save_presets_file(Vec::new());
+        assert!(app.active_preset.is_none());
+        assert_eq!(app.config.instrument_preset, None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_strum_panel_measures_the_preset_strings() {
+        let mut app = TunerApp::new(
+            Arc::new(Mutex::new(AudioData::new())),
+            TunerConfig::default(),
+        );
+        let ctx = egui::Context::default();
+        let guitar = app
+            .presets
+            .iter()
+            .find(|preset| preset.name == "Guitar")
+            .cloned();
+        app.select_preset(guitar);
+        app.set_strum(&ctx, true);
+        assert!(app.analysis_settings().capture_strum);
+        assert_eq!(app.panels_height(), STRUM_PANEL_HEIGHT);
+        let _ = ctx.run(egui::RawInput::default(), |ctx| app.show(ctx));
+
+        app.select_preset(None);
+        assert!(
+            !app.analysis_settings().capture_strum,
+            "needs an instrument"
+        );
+    }
+
+    #[test]
+    fn test_piano_mode_shows_the_partials() {
+        let mut app = TunerApp::new(
+            Arc::new(Mutex::new(AudioData::new())),
+            TunerConfig::default(),
+        );
+        let b = 4e-4f32;
+        app.partials = (1..=4)
+            .map(|n| {
+                let n = n as f32;
+                Some(n * 110.0 * (1.0 + b * n * n).sqrt())
+            })
+            .collect();
+        app.partials.push(None);
+        assert!(app.partials_label().is_none(), "off by default");
+        assert_eq!(app.analysis_settings().piano_stretch, None);
+
+        app.config.piano_mode = true;
+        assert_eq!(
+            app.partials_label().unwrap(),
+            "B 4.0e-4 · 110 220 331 441 – Hz"
+        );
+        assert_eq!(app.analysis_settings().piano_stretch, Some(3.0));
+
+        app.partials.truncate(1);
+        assert!(app.partials_label().is_none());
+    }
+
+    #[test]
+    fn test_interval_reading_names_the_strongest_two_notes() {
+        let mut app = TunerApp::new(
+            Arc::new(Mutex::new(AudioData::new())),
+            TunerConfig::default(),
+        );
+        app.peaks = vec![(330.0, 2.0), (220.0, 1.0), (440.0 * 1.26, 0.5)];
+        assert!(app.interval_reading().is_none(), "off by default");
+
+        app.config.identify_intervals = true;
+        let (notes, interval) = app.interval_reading().unwrap();
+        let names: Vec<String> = notes.iter().map(|note| note.to_string()).collect();
+        assert_eq!(names, ["A3", "E4", "C#5"]);
+        assert_eq!(interval.to_string(), "P5 +2¢ wide");
+
+        app.config.temperament = Temperament::Just;
+        let (_, interval) = app.interval_reading().unwrap();
+        assert_eq!(interval.to_string(), "P5 in tune");
+
+        app.peaks.truncate(1);
+        assert!(app.interval_reading().is_none());
+    }
+
+    #[test]
+    fn test_analysis_view_zooms_and_pans_within_the_recording() {
+        let mut view = AnalysisView::default();
+        view.show(PitchCurve {
+            duration: 10.0,
+            ..PitchCurve::default()
+        });
+        assert_eq!(view.view, (0.0, 10.0));
+
+        view.zoom(0.5, 10.0);
+        assert_eq!(view.view, (5.0, 10.0), "the anchor stays put");
+        view.pan(3.0);
+        assert_eq!(view.view, (5.0, 10.0), "no panning past the end");
+        view.pan(-7.0);
+        assert_eq!(view.view, (0.0, 5.0));
+
+        view.zoom(0.0, 1.0);
+        assert!((view.view.1 - view.view.0 - MIN_ANALYSIS_SPAN).abs() < 1e-4);
+        view.zoom(1000.0, 0.0);
+        assert_eq!(view.view, (0.0, 10.0));
+    }
+
+    #[test]
+    fn test_keyboard_layout_places_black_keys_between_white() {
+        let rect = egui::Rect::from_min_size(egui::pos2(10.0, 0.0), egui::vec2(210.0, 40.0));
+        let keys = keyboard_layout(2, 3, rect);
+        assert_eq!(keys.len(), 36);
+        let (white, black) = keys.split_at(21);
+        assert!(white.iter().all(|(midi, _)| !is_black_key(midi % 12)));
+        assert!(black.iter().all(|(midi, _)| is_black_key(midi % 12)));
+        // C2 is first and the white keys tile the strip.
+        assert_eq!(white[0].0, 36);
+        assert_eq!(white[0].1.left(), 10.0);
+        assert_eq!(white[20].0, 71);
+        assert!((white[20].1.right() - 220.0).abs() < 1e-3);
+        // C#2 straddles C2 and D2, and is the key hit in their upper half.
+        let (c_sharp, c_sharp_rect) = black[0];
+        assert_eq!(c_sharp, 37);
+        assert!((c_sharp_rect.center().x - white[1].1.left()).abs() < 1e-3);
+        let hit = keys
+            .iter()
+            .rev()
+            .find(|(_, key)| key.contains(egui::pos2(20.0, 5.0)))
+            .unwrap();
+        assert_eq!(hit.0, 37);
+    }
+
+    #[test]
+    fn test_keyboard_octave_follows_the_preset() {
+        let mut app = TunerApp::new(
+            Arc::new(Mutex::new(AudioData::new())),
+            TunerConfig::default(),
+        );
+        assert_eq!(app.keyboard_octave(), 3);
+        app.set_keyboard_octave(99);
+        assert_eq!(app.keyboard_octave(), *KEYBOARD_START_OCTAVES.end());
+
+        let preset = |app: &TunerApp, name: &str| {
+            app.presets
+                .iter()
+                .find(|preset| preset.name == name)
+                .cloned()
+        };
+        app.select_preset(preset(&app, "Guitar"));
+        assert_eq!(app.keyboard_octave(), 2);
+        app.set_keyboard_octave(3);
+        assert_eq!(app.keyboard_octave(), 3);
+        assert_eq!(app.config.preset_keyboard_octaves.get("Guitar"), Some(&3));
+
+        // Chromatic mode keeps its own octave.
+        app.select_preset(None);
+        assert_eq!(app.keyboard_octave(), *KEYBOARD_START_OCTAVES.end());
+
+        // Moving back to the lowest string forgets the override.
+        app.select_preset(preset(&app, "Guitar"));
+        app.set_keyboard_octave(2);
+        assert!(app.config.preset_keyboard_octaves.is_empty());
     }
 }
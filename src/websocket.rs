@@ -0,0 +1,338 @@
+//! Live tuner state over WebSocket
+//!
+//! Serves a JSON message per reading to every browser that connects, for a
+//! remote display on a phone or a browser source in OBS; see
+//! `assets/web/display.html`. Each client has its own thread and a short
+//! queue: one that falls behind is dropped rather than holding up the others
+//! or the UI, and idle connections are pinged to keep them open.
+
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use tungstenite::{Message, WebSocket};
+
+use crate::detection_log::{json_number, json_string};
+use crate::pitch::NoteNaming;
+use crate::tracker::PitchReading;
+
+/// State messages are sent at most this often while a note shows (about 30
+/// per second).
+const MIN_STATE_INTERVAL: Duration = Duration::from_millis(33);
+/// Connections with nothing to send are pinged this often.
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(5);
+/// Messages waiting for one client before it counts as too slow.
+const CLIENT_QUEUE_MESSAGES: usize = 32;
+/// How long a handshake or a single write may take.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(2);
+/// How long a client thread waits for a message before checking on the
+/// client, so one that leaves is noticed promptly.
+const CLIENT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// How long each check waits for the client to have sent something.
+const CLIENT_READ_TIMEOUT: Duration = Duration::from_millis(5);
+
+/// The message for `reading` at `time`: the note, its frequency in Hz, the
+/// smoothed deviation in cents, whether that is within `in_tune_cents`, the
+/// detector's confidence and milliseconds since the Unix epoch. Everything
+/// but the last two is `null` or false while no note shows.
+pub fn state_json(
+    reading: &PitchReading,
+    naming: NoteNaming,
+    in_tune_cents: f32,
+    time: SystemTime,
+) -> String {
+    let timestamp = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    match &reading.note {
+        Some(note) => format!(
+            "{{\"note\":{},\"frequency\":{},\"cents\":{},\"in_tune\":{},\"confidence\":{},\"timestamp\":{}}}",
+            json_string(&note.display_name(naming)),
+            json_number(note.frequency, 2),
+            json_number(reading.smoothed_cents, 1),
+            reading.smoothed_cents.abs() < in_tune_cents,
+            json_number(reading.confidence, 2),
+            timestamp
+        ),
+        None => format!(
+            "{{\"note\":null,\"frequency\":null,\"cents\":null,\"in_tune\":false,\"confidence\":{},\"timestamp\":{}}}",
+            json_number(reading.confidence, 2),
+            timestamp
+        ),
+    }
+}
+
+/// The clients, and the last message so a new one starts from it.
+#[derive(Debug, Default)]
+struct Clients {
+    queues: Vec<SyncSender<Arc<str>>>,
+    latest: Option<Arc<str>>,
+}
+
+impl Clients {
+    /// Queues `message` for every client, dropping those whose queue is full.
+    fn broadcast(&mut self, message: Arc<str>) {
+        self.queues
+            .retain(|queue| match queue.try_send(message.clone()) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    log::info!("Dropping a WebSocket client that fell behind");
+                    false
+                }
+                Err(TrySendError::Disconnected(_)) => false,
+            });
+        self.latest = Some(message);
+    }
+
+    fn join(&mut self, queue: SyncSender<Arc<str>>) {
+        if let Some(latest) = &self.latest {
+            let _ = queue.try_send(latest.clone());
+        }
+        self.queues.push(queue);
+    }
+}
+
+/// Serves readings to WebSocket clients until dropped.
+pub struct WebSocketServer {
+    address: SocketAddr,
+    clients: Arc<Mutex<Clients>>,
+    stopping: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+    last_state: Option<Instant>,
+}
+
+impl WebSocketServer {
+    /// Listens on `address`, e.g. `("0.0.0.0", port)` to let other devices on
+    /// the network connect, and starts accepting clients.
+    pub fn open(address: impl ToSocketAddrs) -> Result<Self> {
+        let listener = TcpListener::bind(address).context("Failed to open the WebSocket port")?;
+        let address = listener.local_addr()?;
+        let clients = Arc::new(Mutex::new(Clients::default()));
+        let stopping = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let clients = clients.clone();
+            let stopping = stopping.clone();
+            std::thread::Builder::new()
+                .name("websocket-server".to_string())
+                .spawn(move || {
+                    for stream in listener.incoming() {
+                        if stopping.load(Ordering::Relaxed) {
+                            break;
+                        }
+                        let spawned = stream.and_then(|stream| serve_client(stream, &clients));
+                        if let Err(err) = spawned {
+                            log::warn!("Failed to accept a WebSocket client: {}", err);
+                        }
+                    }
+                })?
+        };
+        log::info!("Serving live state on ws://{}", address);
+
+        Ok(Self {
+            address,
+            clients,
+            stopping,
+            handle: Some(handle),
+            last_state: None,
+        })
+    }
+
+    /// The address being listened on.
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Clients connected right now.
+    pub fn client_count(&self) -> usize {
+        self.clients
+            .lock()
+            .map_or(0, |clients| clients.queues.len())
+    }
+
+    /// Sends `reading` to every client, at a throttled rate while a note
+    /// shows and once when it clears.
+    pub fn publish(
+        &mut self,
+        reading: &PitchReading,
+        naming: NoteNaming,
+        in_tune_cents: f32,
+        now: Instant,
+    ) {
+        if reading.note.is_some() {
+            if self
+                .last_state
+                .is_some_and(|last| now.saturating_duration_since(last) < MIN_STATE_INTERVAL)
+            {
+                return;
+            }
+            self.last_state = Some(now);
+        } else if self.last_state.take().is_none() {
+            return;
+        }
+        let message = state_json(reading, naming, in_tune_cents, SystemTime::now());
+        if let Ok(mut clients) = self.clients.lock() {
+            clients.broadcast(message.into());
+        }
+    }
+}
+
+impl Drop for WebSocketServer {
+    fn drop(&mut self) {
+        self.stopping.store(true, Ordering::Relaxed);
+        // Wake the accept loop so it sees it should stop.
+        let mut wake = self.address;
+        if wake.ip().is_unspecified() {
+            wake.set_ip(Ipv4Addr::LOCALHOST.into());
+        }
+        let _ = TcpStream::connect_timeout(&wake, CLIENT_TIMEOUT);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+        // Closing the queues ends the client threads.
+        if let Ok(mut clients) = self.clients.lock() {
+            clients.queues.clear();
+        }
+    }
+}
+
+/// Completes the handshake with a new client on a thread of its own, then
+/// forwards its queue until it closes, falls behind or the server stops.
+/// Between messages it reads what the client sent, which answers its pings
+/// and notices its close frame.
+fn serve_client(stream: TcpStream, clients: &Arc<Mutex<Clients>>) -> io::Result<()> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_nodelay(true)?;
+    let clients = clients.clone();
+    std::thread::Builder::new()
+        .name("websocket-client".to_string())
+        .spawn(move || {
+            let peer = stream.peer_addr().ok();
+            let mut socket = match tungstenite::accept(stream) {
+                Ok(socket) => socket,
+                Err(err) => {
+                    log::warn!("WebSocket handshake with {:?} failed: {}", peer, err);
+                    return;
+                }
+            };
+            let (queue, messages) = mpsc::sync_channel(CLIENT_QUEUE_MESSAGES);
+            if let Ok(mut clients) = clients.lock() {
+                clients.join(queue);
+            }
+            if let Err(err) = socket.get_ref().set_read_timeout(Some(CLIENT_READ_TIMEOUT)) {
+                log::warn!("WebSocket client {:?} can't be read: {}", peer, err);
+                return;
+            }
+            log::info!("WebSocket client {:?} connected", peer);
+            let mut last_sent = Instant::now();
+            loop {
+                let message = match messages.recv_timeout(CLIENT_POLL_INTERVAL) {
+                    Ok(text) => Some(Message::text(text.to_string())),
+                    Err(RecvTimeoutError::Timeout) => (last_sent.elapsed() >= KEEPALIVE_INTERVAL)
+                        .then(|| Message::Ping(Default::default())),
+                    Err(RecvTimeoutError::Disconnected) => break,
+                };
+                if let Some(message) = message {
+                    if let Err(err) = socket.send(message) {
+                        log::info!("WebSocket client {:?} left: {}", peer, err);
+                        return;
+                    }
+                    last_sent = Instant::now();
+                }
+                match read_incoming(&mut socket) {
+                    Ok(false) => {}
+                    Ok(true) => {
+                        log::info!("WebSocket client {:?} left", peer);
+                        return;
+                    }
+                    Err(err) => {
+                        log::info!("WebSocket client {:?} left: {}", peer, err);
+                        return;
+                    }
+                }
+            }
+            let _ = socket.close(None);
+            let _ = socket.flush();
+        })?;
+    Ok(())
+}
+
+/// Reads whatever the client has sent, answering its pings, and returns
+/// whether it closed the connection.
+fn read_incoming(socket: &mut WebSocket<TcpStream>) -> tungstenite::Result<bool> {
+    loop {
+        match socket.read() {
+            Ok(Message::Close(_)) => {
+                // Sends the reply tungstenite queued, finishing the close
+                // handshake.
+                let _ = socket.flush();
+                return Ok(true);
+            }
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(err))
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                return Ok(false)
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pitch::{frequency_to_note, DEFAULT_A4_FREQUENCY};
+
+    #[test]
+    fn test_state_json_describes_the_reading() {
+        let time = UNIX_EPOCH + Duration::from_millis(1_700_000_000_123);
+        let reading = PitchReading {
+            note: frequency_to_note(442.0, DEFAULT_A4_FREQUENCY),
+            smoothed_cents: 7.84,
+            confidence: 0.9,
+            ..PitchReading::default()
+        };
+        assert_eq!(
+            state_json(&reading, NoteNaming::Sharps, 5.0, time),
+            "{\"note\":\"A4\",\"frequency\":442.00,\"cents\":7.8,\"in_tune\":false,\
+             \"confidence\":0.90,\"timestamp\":1700000000123}"
+        );
+        assert!(state_json(&reading, NoteNaming::Sharps, 10.0, time).contains("\"in_tune\":true"));
+        assert_eq!(
+            state_json(&PitchReading::default(), NoteNaming::Sharps, 5.0, time),
+            "{\"note\":null,\"frequency\":null,\"cents\":null,\"in_tune\":false,\
+             \"confidence\":0.00,\"timestamp\":1700000000123}"
+        );
+    }
+
+    #[test]
+    fn test_slow_clients_are_dropped_and_new_ones_start_from_the_latest() {
+        let mut clients = Clients::default();
+        let (slow, _stalled) = mpsc::sync_channel(CLIENT_QUEUE_MESSAGES);
+        let (fast, messages) = mpsc::sync_channel(CLIENT_QUEUE_MESSAGES);
+        clients.join(slow);
+        clients.join(fast);
+        for index in 0..=CLIENT_QUEUE_MESSAGES {
+            clients.broadcast(index.to_string().into());
+            assert_eq!(&*messages.recv().unwrap(), index.to_string());
+        }
+        assert_eq!(clients.queues.len(), 1);
+
+        let (late, late_messages) = mpsc::sync_channel(CLIENT_QUEUE_MESSAGES);
+        clients.join(late);
+        assert_eq!(
+            &*late_messages.try_recv().unwrap(),
+            CLIENT_QUEUE_MESSAGES.to_string()
+        );
+    }
+}
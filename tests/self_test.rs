@@ -0,0 +1,29 @@
+//! Runs the in-app accuracy self-test over the default analysis settings.
+
+use chroma_tuner::self_test::{run_self_test, SELF_TEST_TOLERANCE_CENTS};
+use chroma_tuner::AnalysisSettings;
+
+#[test]
+fn test_every_note_from_e1_to_e7_reads_within_tolerance() {
+    let report = run_self_test(&AnalysisSettings::default(), |_| {});
+    let flagged: Vec<String> = report
+        .flagged()
+        .map(|tone| {
+            format!(
+                "MIDI {} {:+}¢ read as {:?}",
+                tone.midi,
+                tone.offset_cents,
+                tone.reading
+                    .as_ref()
+                    .map(|note| (note.to_string(), note.cents_off))
+            )
+        })
+        .collect();
+    assert!(
+        flagged.is_empty(),
+        "{} tones off by more than {}¢:\n{}",
+        flagged.len(),
+        SELF_TEST_TOLERANCE_CENTS,
+        flagged.join("\n")
+    );
+}
@@ -0,0 +1,110 @@
+//! Connects WebSocket clients to the live state server and checks readings
+//! of a synthetic tone reach each of them.
+
+#![cfg(feature = "gui")]
+
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+use chroma_tuner::pitch::{NoteNaming, DEFAULT_A4_FREQUENCY};
+use chroma_tuner::websocket::WebSocketServer;
+use chroma_tuner::{
+    frequency_to_note, AnalysisSettings, PitchDetector, PitchReading, PitchTracker,
+};
+use tungstenite::{Message, WebSocket};
+
+fn connect(server: &WebSocketServer) -> WebSocket<TcpStream> {
+    let address = server.address();
+    let stream = TcpStream::connect(address).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let (socket, _) = tungstenite::client(format!("ws://{}/", address), stream).unwrap();
+    socket
+}
+
+/// Reads text messages until one contains `needle`, or panics on a timeout.
+fn read_until(socket: &mut WebSocket<TcpStream>, needle: &str) -> String {
+    loop {
+        match socket.read().unwrap() {
+            Message::Text(text) if text.contains(needle) => return text.to_string(),
+            _ => {}
+        }
+    }
+}
+
+#[test]
+fn test_clients_receive_readings_of_a_synthetic_tone() {
+    let mut server = WebSocketServer::open(("127.0.0.1", 0)).unwrap();
+    let mut clients = [connect(&server), connect(&server)];
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while server.client_count() < clients.len() {
+        assert!(Instant::now() < deadline, "clients never joined");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    let settings = AnalysisSettings::default();
+    let sample_rate = 48000.0;
+    let mut detector = PitchDetector::new(settings.fft_size, sample_rate, settings.window_kind);
+    settings.configure(&mut detector);
+    let mut tracker = PitchTracker::default();
+    let start = Instant::now();
+    for frame in 0..40 {
+        let offset = frame * settings.hop_size;
+        let samples: Vec<f32> = (offset..offset + settings.fft_size)
+            .map(|index| (std::f32::consts::TAU * 440.0 * index as f32 / sample_rate).sin() * 0.5)
+            .collect();
+        let now = start + Duration::from_secs_f32(offset as f32 / sample_rate);
+        if let Some(estimate) = detector.detect_pitch(&samples) {
+            tracker.feed(estimate, now, &settings);
+        }
+        server.publish(&tracker.reading(), NoteNaming::Sharps, 5.0, now);
+    }
+
+    for client in &mut clients {
+        let message = read_until(client, "\"note\":\"A4\"");
+        assert!(message.contains("\"in_tune\":true"), "{}", message);
+    }
+
+    // The note clearing is sent once, as a message without one.
+    tracker.reset();
+    server.publish(&tracker.reading(), NoteNaming::Sharps, 5.0, Instant::now());
+    for client in &mut clients {
+        read_until(client, "\"note\":null");
+    }
+
+    // A client that closes is answered and dropped without waiting for a
+    // send to fail.
+    let [mut leaving, client] = clients;
+    leaving.close(None).unwrap();
+    loop {
+        match leaving.read() {
+            Ok(_) => continue,
+            Err(tungstenite::Error::ConnectionClosed) => break,
+            Err(err) => panic!("close was not answered: {}", err),
+        }
+    }
+    let reading = PitchReading {
+        note: frequency_to_note(440.0, DEFAULT_A4_FREQUENCY),
+        ..PitchReading::default()
+    };
+    let deadline = Instant::now() + Duration::from_secs(1);
+    while server.client_count() > 1 {
+        assert!(Instant::now() < deadline, "the closed client was kept");
+        server.publish(&reading, NoteNaming::Sharps, 5.0, Instant::now());
+        std::thread::sleep(Duration::from_millis(40));
+    }
+    let mut clients = [client];
+
+    // Stopping the server closes the connections.
+    drop(server);
+    for client in &mut clients {
+        loop {
+            match client.read() {
+                Ok(Message::Close(_)) => {}
+                Ok(_) => continue,
+                Err(_) => break,
+            }
+        }
+    }
+}